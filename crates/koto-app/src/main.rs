@@ -1,6 +1,8 @@
 //! Koto DAW - Main application entry point
 
 use anyhow::Result;
+use koto_audio_engine::DevicePreferences;
+use koto_project::AppSettings;
 use koto_ui::KotoApp;
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
@@ -14,6 +16,15 @@ fn main() -> Result<()> {
 
     info!("Starting Koto DAW");
 
+    let settings = AppSettings::default_path()
+        .map(|path| AppSettings::load_or_default(&path))
+        .unwrap_or_default();
+    let preferences = DevicePreferences {
+        output_device: settings.audio.output_device.clone(),
+        sample_rate: settings.audio.sample_rate,
+        buffer_size: settings.audio.buffer_size,
+    };
+
     // Create native options
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -30,7 +41,7 @@ fn main() -> Result<()> {
         native_options,
         Box::new(|cc| {
             // Apply custom fonts/style here if needed
-            Ok(Box::new(KotoApp::new(cc)))
+            Ok(Box::new(KotoApp::new(cc, preferences)))
         }),
     )
     .map_err(|e| anyhow::anyhow!("Failed to run eframe: {}", e))?;
@@ -1,10 +1,89 @@
 //! Audio callback handler for real-time processing
 
-use crate::{AudioCommand, AudioEvent, TransportState};
-use koto_core::SampleRate;
-use parking_lot::Mutex;
+use crate::{
+    mix_inputs, AudioCommand, AudioEvent, ChannelMeterChunk, TakeInput, TransportState,
+    CHANNEL_METERS_PER_EVENT,
+};
+use koto_core::{
+    clamp_playback_rate, interleaved_peaks, interleaved_rms, sanitize_samples, stereo_correlation,
+    AudioBuffer, AudioProcessor, BrickwallLimiter, ChannelCount, ChannelMap, DenormalGuard,
+    MidiChannel, MidiEvent, MidiMessage, NoteNumber, NoteTracker, ProcessContext, SamplePosition,
+    SampleRate, SilenceFlags, SmoothedValue, SmoothingMode, Velocity,
+};
+use koto_mixer::MeterReading;
 use rtrb::{Consumer, Producer};
-use std::sync::Arc;
+
+/// Ramp time for master volume changes
+const MASTER_VOLUME_RAMP_MS: f32 = 20.0;
+
+/// Length of the snippet played for each scrub position
+const SCRUB_SNIPPET_MS: f64 = 40.0;
+
+/// Gain applied to scrub snippets
+const SCRUB_GAIN: f32 = 0.5;
+
+/// Most MIDI events the callback holds for the instruments between drains
+const MIDI_OUTPUT_CAPACITY: usize = 2048;
+
+/// Most mixer channels whose meters are sent to the UI
+const MAX_METERED_CHANNELS: usize = 256;
+
+/// Sources summed by the mixing stage: metronome, count-in and scrub
+/// snippets, then monitored input
+const SOURCE_PLAYBACK: usize = 0;
+const SOURCE_MONITOR: usize = 1;
+const SOURCE_COUNT: usize = 2;
+
+/// Progress of a count-in, fixed when recording is requested
+#[derive(Debug, Clone, Copy)]
+struct CountIn {
+    /// Frames played so far
+    elapsed: usize,
+    /// Total length in frames
+    total: usize,
+    samples_per_beat: f64,
+    beats: u32,
+    /// Next beat whose tick hasn't been sent
+    next_beat: u32,
+}
+
+/// MIDI events for the instruments, in a buffer allocated up front
+///
+/// Events pushed while it is full are dropped, so the audio thread never
+/// allocates.
+struct MidiOutput {
+    events: Box<[MidiEvent]>,
+    len: usize,
+}
+
+impl MidiOutput {
+    fn new() -> Self {
+        let empty = MidiEvent::new(
+            0,
+            MidiMessage::NoteOff {
+                channel: MidiChannel(0),
+                note: NoteNumber(0),
+                velocity: Velocity::OFF,
+            },
+        );
+        Self {
+            events: vec![empty; MIDI_OUTPUT_CAPACITY].into_boxed_slice(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: MidiEvent) {
+        if let Some(slot) = self.events.get_mut(self.len) {
+            *slot = event;
+            self.len += 1;
+        }
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = MidiEvent> + '_ {
+        let len = std::mem::take(&mut self.len);
+        self.events[..len].iter().copied()
+    }
+}
 
 /// Audio callback processor
 pub struct AudioCallback {
@@ -16,16 +95,47 @@ pub struct AudioCallback {
     transport: TransportState,
     /// Sample rate
     sample_rate: SampleRate,
-    /// Master volume (0.0 to 1.0)
-    master_volume: f32,
+    /// Master volume (0.0 to 1.0), smoothed to avoid zipper noise
+    master_volume: SmoothedValue,
     /// Metronome enabled
     metronome_enabled: bool,
+    /// Master output limiter
+    limiter: BrickwallLimiter,
+    /// Limiter enabled
+    limiter_enabled: bool,
     /// Frame counter for meter updates
     meter_frame_counter: usize,
     /// Frames between meter updates
     meter_update_interval: usize,
-    /// Recording buffer (shared with file writer thread)
-    recording_buffer: Option<Arc<Mutex<Vec<f32>>>>,
+    /// Latest mixer channel meters, sent with each meter update
+    channel_meters: Vec<MeterReading>,
+    /// Take being recorded, drained by the file writer thread
+    recording: Option<TakeInput>,
+    /// Number of channels in the input stream
+    input_channels: ChannelCount,
+    /// Routing from input channels to the stereo recording buffer
+    input_map: ChannelMap,
+    /// Replace NaN/Inf/denormal output samples with silence (on in debug builds)
+    sanitize_output: bool,
+    /// Notes currently sounding on instruments
+    note_tracker: NoteTracker,
+    /// MIDI generated by the callback, waiting to be sent to instruments
+    midi_output: MidiOutput,
+    /// Input device latency in samples
+    input_latency: usize,
+    /// Output device latency in samples
+    output_latency: usize,
+    /// Count-in in progress, if any
+    count_in: Option<CountIn>,
+    /// Scrub snippet length in frames
+    scrub_snippet_frames: usize,
+    /// Frames of the current scrub snippet already played
+    scrub_snippet_offset: usize,
+    /// Largest block processed at once; longer device buffers are split
+    max_frames: usize,
+    /// One stereo buffer per source, mixed into `mix_bus`
+    sources: Vec<AudioBuffer>,
+    mix_bus: AudioBuffer,
 }
 
 impl AudioCallback {
@@ -38,34 +148,199 @@ impl AudioCallback {
     ) -> Self {
         // Calculate meter update interval (~30 Hz)
         let meter_update_interval = (sample_rate.0 as usize / 30).max(buffer_size);
+        let max_frames = buffer_size.max(1);
+
+        // Allocate processor state before the stream starts
+        let mut limiter = BrickwallLimiter::new(sample_rate, ChannelCount::STEREO);
+        limiter.prepare(sample_rate, buffer_size);
 
         Self {
             command_rx,
             event_tx,
             transport: TransportState::new(),
             sample_rate,
-            master_volume: 1.0,
+            master_volume: SmoothedValue::new(
+                1.0,
+                sample_rate,
+                MASTER_VOLUME_RAMP_MS,
+                SmoothingMode::Linear,
+            ),
             metronome_enabled: false,
+            limiter,
+            limiter_enabled: false,
             meter_frame_counter: 0,
             meter_update_interval,
-            recording_buffer: None,
+            channel_meters: Vec::with_capacity(MAX_METERED_CHANNELS),
+            recording: None,
+            input_channels: ChannelCount::STEREO,
+            input_map: ChannelMap::identity(ChannelCount::STEREO),
+            sanitize_output: cfg!(debug_assertions),
+            note_tracker: NoteTracker::new(),
+            midi_output: MidiOutput::new(),
+            input_latency: 0,
+            output_latency: 0,
+            count_in: None,
+            scrub_snippet_frames: (sample_rate.as_f64() * SCRUB_SNIPPET_MS / 1000.0) as usize,
+            scrub_snippet_offset: usize::MAX,
+            max_frames,
+            sources: vec![AudioBuffer::new(ChannelCount::STEREO, max_frames); SOURCE_COUNT],
+            mix_bus: AudioBuffer::new(ChannelCount::STEREO, max_frames),
         }
     }
 
+    /// Store the mixer's channel meters for the next meter update
+    ///
+    /// Keeps at most [`MAX_METERED_CHANNELS`] and never allocates, so the
+    /// mixer can call it from the audio thread after each block.
+    pub fn set_channel_meters(&mut self, meters: &[MeterReading]) {
+        let len = meters.len().min(MAX_METERED_CHANNELS);
+        self.channel_meters.clear();
+        self.channel_meters.extend_from_slice(&meters[..len]);
+    }
+
+    /// Set the channel count of the input stream
+    ///
+    /// Recorded input is always stored as stereo; mono inputs are duplicated
+    /// into both channels. Builds the channel map, so call it while setting
+    /// up the stream rather than from the audio thread.
+    pub fn set_input_channels(&mut self, channels: ChannelCount) {
+        self.input_channels = channels;
+        self.input_map = ChannelMap::for_channels(channels, ChannelCount::STEREO);
+    }
+
+    /// Enable or disable output sanitization
+    ///
+    /// When enabled, NaN, infinite and denormal samples in the final output
+    /// are replaced with silence before reaching the device.
+    pub fn set_sanitize_output(&mut self, enabled: bool) {
+        self.sanitize_output = enabled;
+    }
+
+    /// Set the input device latency in samples, as reported by the device
+    pub fn set_input_latency(&mut self, samples: usize) {
+        self.input_latency = samples;
+    }
+
+    /// Set the output device latency in samples, as reported by the device
+    pub fn set_output_latency(&mut self, samples: usize) {
+        self.output_latency = samples;
+    }
+
+    /// Device latencies in samples, as `(input, output)`
+    pub fn device_latency(&self) -> (usize, usize) {
+        (self.input_latency, self.output_latency)
+    }
+
+    /// Context for processors run on a block of `frames`, with the
+    /// transport state and device latencies
+    pub fn process_context<'a>(
+        &self,
+        frames: usize,
+        midi_events: &'a [MidiEvent],
+        midi_out: &'a mut Vec<MidiEvent>,
+    ) -> ProcessContext<'a> {
+        ProcessContext {
+            sample_rate: self.sample_rate,
+            tempo: self.transport.tempo,
+            time_signature: self.transport.time_signature,
+            playhead: self.transport.playhead,
+            frames,
+            midi_events,
+            midi_out,
+            is_playing: self.transport.is_playing,
+            is_recording: self.transport.is_recording,
+            input_latency: self.input_latency,
+            output_latency: self.output_latency,
+        }
+    }
+
+    /// Record a MIDI message sent to an instrument
+    ///
+    /// Keeps track of sounding notes so they can be released on Stop and Seek.
+    pub fn track_midi(&mut self, message: &MidiMessage) {
+        self.note_tracker.process(message);
+    }
+
+    /// Take MIDI waiting for the instruments: messages from
+    /// [`AudioCommand::SendMidi`] and note-offs after Stop and Seek
+    pub fn drain_midi_output(&mut self) -> impl Iterator<Item = MidiEvent> + '_ {
+        self.midi_output.drain()
+    }
+
+    /// Track a message and queue it for the instruments
+    fn send_midi(&mut self, message: MidiMessage) {
+        self.track_midi(&message);
+        self.midi_output.push(MidiEvent::new(0, message));
+    }
+
+    /// Queue note-offs for every sounding note and forget them
+    fn release_notes(&mut self) {
+        let output = &mut self.midi_output;
+        self.note_tracker
+            .for_each_note_off(|message| output.push(MidiEvent::new(0, message)));
+        self.note_tracker.reset();
+    }
+
     /// Process commands from UI thread (non-blocking)
     fn process_commands(&mut self) {
         while let Ok(command) = self.command_rx.pop() {
             match command {
                 AudioCommand::Play => {
-                    self.transport.is_playing = true;
+                    self.transport.play();
+                    self.send_transport_state();
+                }
+                AudioCommand::PlayWithPreRoll => {
+                    if !self.transport.is_playing {
+                        self.release_notes();
+                    }
+                    self.transport.play_with_preroll(self.sample_rate);
                     self.send_transport_state();
                 }
+                AudioCommand::SetPreRoll(pre_roll) => {
+                    self.transport.pre_roll = pre_roll;
+                }
+                AudioCommand::Scrub(position) => {
+                    let was_playing = self.transport.is_playing;
+                    self.transport.scrub_to(position);
+                    self.scrub_snippet_offset = 0;
+                    if was_playing {
+                        self.release_notes();
+                        self.send_transport_state();
+                    }
+                }
+                AudioCommand::EndScrub => {
+                    self.transport.end_scrub();
+                    self.scrub_snippet_offset = usize::MAX;
+                    if self.transport.is_playing {
+                        self.send_transport_state();
+                    }
+                }
                 AudioCommand::Stop => {
+                    self.transport.end_scrub();
+                    // A second stop returns to the start
+                    let position = if self.transport.is_playing {
+                        self.transport
+                            .stop_behavior
+                            .stop_position(self.transport.playhead, self.transport.play_start)
+                    } else {
+                        SamplePosition::ZERO
+                    };
+                    self.transport.is_playing = false;
+                    self.transport.seek(position);
+                    self.cancel_count_in();
+                    self.release_notes();
+                    self.send_transport_state();
+                }
+                AudioCommand::Pause => {
+                    self.transport.end_scrub();
                     self.transport.is_playing = false;
+                    self.cancel_count_in();
+                    self.release_notes();
                     self.send_transport_state();
                 }
                 AudioCommand::Seek(position) => {
-                    self.transport.playhead = position;
+                    self.transport.seek(position);
+                    self.release_notes();
                 }
                 AudioCommand::SetTempo(tempo) => {
                     self.transport.tempo = tempo;
@@ -73,24 +348,167 @@ impl AudioCallback {
                 AudioCommand::SetTimeSignature(time_sig) => {
                     self.transport.time_signature = time_sig;
                 }
-                AudioCommand::StartRecording => {
-                    self.transport.is_recording = true;
-                    self.recording_buffer = Some(Arc::new(Mutex::new(Vec::with_capacity(
-                        self.sample_rate.0 as usize * 60 * 2, // 1 minute stereo
-                    ))));
+                AudioCommand::StartRecording(_) if self.transport.record_safe => {}
+                AudioCommand::StartRecording(take) => {
+                    self.recording = Some(take);
+                    if self.transport.count_in_bars > 0 {
+                        self.count_in = Some(CountIn {
+                            elapsed: 0,
+                            total: self.transport.count_in_frames(self.sample_rate),
+                            samples_per_beat: self
+                                .transport
+                                .tempo
+                                .samples_per_beat(self.sample_rate),
+                            beats: self.transport.count_in_beats(),
+                            next_beat: 0,
+                        });
+                        self.transport.is_counting_in = true;
+                    } else {
+                        self.transport.is_recording = true;
+                    }
                     self.send_transport_state();
                 }
                 AudioCommand::StopRecording => {
+                    self.cancel_count_in();
                     self.transport.is_recording = false;
-                    self.recording_buffer = None;
+                    self.recording = None;
                     self.send_transport_state();
                 }
                 AudioCommand::SetMasterVolume(volume) => {
-                    self.master_volume = volume.clamp(0.0, 1.0);
+                    self.master_volume.set_target(volume.clamp(0.0, 1.0));
                 }
                 AudioCommand::SetMetronomeEnabled(enabled) => {
                     self.metronome_enabled = enabled;
                 }
+                AudioCommand::SetLoopEnabled(enabled) => {
+                    self.transport.loop_enabled = enabled;
+                }
+                AudioCommand::SetLoopRange(range) => {
+                    if !range.is_empty() {
+                        self.transport.loop_start = range.start;
+                        self.transport.loop_end = range.end;
+                    }
+                }
+                AudioCommand::SetStopBehavior(behavior) => {
+                    self.transport.stop_behavior = behavior;
+                }
+                AudioCommand::SetPlaybackRate(rate) => {
+                    self.transport.playback_rate = clamp_playback_rate(rate);
+                }
+                AudioCommand::SetSeekPolicy(policy) => {
+                    self.transport.seek_policy = policy;
+                }
+                AudioCommand::SetRecordSafe(record_safe) => {
+                    self.transport.record_safe = record_safe;
+                }
+                AudioCommand::SetMonitorMode(mode) => {
+                    self.transport.input_monitoring = mode;
+                }
+                AudioCommand::SetTrackMute(track, mute) => {
+                    if !self.transport.tracks.set_mute(track, mute) {
+                        let _ = self.event_tx.push(AudioEvent::TrackStateOverflow(track));
+                    }
+                }
+                AudioCommand::SetTrackSolo(track, solo) => {
+                    if !self.transport.tracks.set_solo(track, solo) {
+                        let _ = self.event_tx.push(AudioEvent::TrackStateOverflow(track));
+                    }
+                }
+                AudioCommand::SetCountIn(bars) => {
+                    self.transport.count_in_bars = bars;
+                }
+                AudioCommand::SendMidi(message) => self.send_midi(message),
+                AudioCommand::SetLimiterEnabled(enabled) => {
+                    if enabled && !self.limiter_enabled {
+                        self.limiter.reset();
+                    }
+                    self.limiter_enabled = enabled;
+                }
+            }
+        }
+    }
+
+    /// Abandon a count-in that hasn't reached the downbeat
+    fn cancel_count_in(&mut self) {
+        if self.count_in.take().is_some() {
+            self.transport.is_counting_in = false;
+            self.recording = None;
+        }
+    }
+
+    /// Play the count-in metronome into the start of `output`
+    ///
+    /// Returns the number of frames the count-in used. When it finishes
+    /// within the block, recording starts on the following frame.
+    fn run_count_in(&mut self, output: &mut [f32]) -> usize {
+        let Some(mut count_in) = self.count_in else {
+            return 0;
+        };
+        let frames = (output.len() / 2).min(count_in.total - count_in.elapsed);
+        let end = count_in.elapsed + frames;
+
+        while count_in.next_beat < count_in.beats
+            && ((count_in.next_beat as f64 * count_in.samples_per_beat).round() as usize) < end
+        {
+            let _ = self.event_tx.push(AudioEvent::CountInTick {
+                beats_remaining: count_in.beats - count_in.next_beat,
+            });
+            count_in.next_beat += 1;
+        }
+
+        // The count-in runs in real time, independent of the playback rate
+        self.generate_metronome(&mut output[..frames * 2], count_in.elapsed as f64, 1.0);
+        count_in.elapsed = end;
+
+        if count_in.elapsed >= count_in.total {
+            self.count_in = None;
+            self.transport.is_counting_in = false;
+            self.transport.is_recording = true;
+            self.send_transport_state();
+        } else {
+            self.count_in = Some(count_in);
+        }
+        frames
+    }
+
+    /// Play a short windowed snippet around the scrub position
+    fn render_scrub(&mut self, output: &mut [f32]) {
+        let length = self.scrub_snippet_frames;
+        let remaining = length.saturating_sub(self.scrub_snippet_offset);
+        let frames = (output.len() / 2).min(remaining);
+        if frames == 0 {
+            return;
+        }
+        let block = &mut output[..frames * 2];
+
+        // Until the graph renders audio, the metronome is the only source
+        if self.metronome_enabled {
+            let start = self.transport.playhead.0 as f64 - (length / 2) as f64;
+            self.generate_metronome(block, start + self.scrub_snippet_offset as f64, 1.0);
+        }
+
+        // Hann window so snippets start and end silently
+        for (i, frame) in block.chunks_exact_mut(2).enumerate() {
+            let phase = (self.scrub_snippet_offset + i) as f64 / length as f64;
+            let window = (0.5 - 0.5 * (std::f64::consts::TAU * phase).cos()) as f32;
+            let gain = window * SCRUB_GAIN;
+            frame[0] *= gain;
+            frame[1] *= gain;
+        }
+        self.scrub_snippet_offset += frames;
+    }
+
+    /// Mix input into the stereo output using the input channel map
+    fn monitor_input(&self, input: &[f32], output: &mut [f32]) {
+        let input_channels = self.input_channels.as_usize().max(1);
+        for (out, frame) in output
+            .chunks_exact_mut(2)
+            .zip(input.chunks_exact(input_channels))
+        {
+            for (channel, sample) in out.iter_mut().enumerate() {
+                if let Some(source) = self.input_map.source(channel) {
+                    *sample += frame.get(source).copied().unwrap_or(0.0);
+                }
             }
         }
     }
@@ -107,41 +525,126 @@ impl AudioCallback {
     ///
     /// This is called from the audio thread and must be real-time safe
     pub fn process(&mut self, output: &mut [f32], input: Option<&[f32]>) {
+        let _denormal_guard = DenormalGuard::new();
+
+        // The limiter and source buffers were sized for `max_frames`
+        let block = self.max_frames * 2;
+        let input_block = self.max_frames * self.input_channels.as_usize().max(1);
+        for (i, output) in output.chunks_mut(block).enumerate() {
+            let input = input.map(|data| {
+                let start = (i * input_block).min(data.len());
+                &data[start..(start + input_block).min(data.len())]
+            });
+            self.process_block(output, input);
+        }
+    }
+
+    /// Process up to `max_frames` frames
+    fn process_block(&mut self, output: &mut [f32], input: Option<&[f32]>) {
         // Process any pending commands (non-blocking)
         self.process_commands();
 
         let channels = 2; // Stereo
         let frames = output.len() / channels;
 
-        // Clear output buffer
-        output.fill(0.0);
+        // Sources that render nothing this block stay silent and aren't mixed
+        let mut silence = SilenceFlags::all(SOURCE_COUNT);
+        let mut sources = std::mem::take(&mut self.sources);
+        let playback = &mut sources[SOURCE_PLAYBACK].samples_mut()[..output.len()];
+        if self.count_in.is_some()
+            || self.transport.is_scrubbing
+            || (self.transport.is_playing && self.metronome_enabled)
+        {
+            playback.fill(0.0);
+            silence.set(SOURCE_PLAYBACK, false);
+        }
+
+        // The playhead holds still and input is ignored during the count-in
+        let count_in_frames = self.run_count_in(playback);
 
         // If recording, capture input
         if self.transport.is_recording {
-            if let (Some(input_data), Some(buffer)) = (input, &self.recording_buffer) {
-                if let Some(mut guard) = buffer.try_lock() {
-                    guard.extend_from_slice(input_data);
+            let input = input.map(|data| {
+                let skip = count_in_frames * self.input_channels.as_usize();
+                &data[skip.min(data.len())..]
+            });
+            if let (Some(input_data), Some(take)) = (input, &mut self.recording) {
+                let channels = self.input_channels.as_usize().max(1);
+                let dropped_frames = take.write(input_data, channels, &self.input_map);
+                if dropped_frames > 0 {
+                    let _ = self
+                        .event_tx
+                        .push(AudioEvent::RecordingOverflow { dropped_frames });
                 }
             }
         }
 
+        if self.transport.is_scrubbing {
+            self.render_scrub(playback);
+        }
+
         // If playing, generate audio
         if self.transport.is_playing {
             // TODO: Process audio graph here
             // For now, generate silence
 
-            // Generate metronome click if enabled
-            if self.metronome_enabled {
-                self.generate_metronome(output, frames);
+            // Process in segments that end at the loop end, so the playhead
+            // wraps exactly at the boundary
+            let mut offset = count_in_frames;
+            while offset < frames {
+                let remaining = frames - offset;
+                let segment = self
+                    .transport
+                    .frames_until_wrap()
+                    .map_or(remaining, |until| until.min(remaining));
+                let start = self.transport.exact_playhead();
+
+                // Generate metronome click if enabled
+                if self.metronome_enabled {
+                    let block = &mut playback[offset * channels..(offset + segment) * channels];
+                    self.generate_metronome(block, start, self.transport.playback_rate);
+                }
+
+                // Advance playhead
+                self.transport.advance(segment);
+                offset += segment;
             }
+        }
 
-            // Advance playhead
-            self.transport.playhead.advance(frames);
+        // Pass input through to the output when monitoring
+        if let Some(input_data) = input.filter(|_| self.transport.is_monitoring()) {
+            let monitor = &mut sources[SOURCE_MONITOR].samples_mut()[..output.len()];
+            monitor.fill(0.0);
+            self.monitor_input(input_data, monitor);
+            silence.set(SOURCE_MONITOR, false);
         }
 
+        mix_inputs(&mut self.mix_bus, &sources, silence);
+        self.sources = sources;
+        output.copy_from_slice(&self.mix_bus.samples()[..output.len()]);
+
         // Apply master volume
-        for sample in output.iter_mut() {
-            *sample *= self.master_volume;
+        if self.master_volume.is_smoothing() {
+            for frame in output.chunks_exact_mut(channels) {
+                let gain = self.master_volume.next();
+                for sample in frame {
+                    *sample *= gain;
+                }
+            }
+        } else {
+            let gain = self.master_volume.current();
+            for sample in output.iter_mut() {
+                *sample *= gain;
+            }
+        }
+
+        // Sanitize before the limiter so NaNs can't poison its state
+        if self.sanitize_output {
+            sanitize_samples(output);
+        }
+
+        if self.limiter_enabled {
+            self.limiter.process_interleaved(output);
         }
 
         // Calculate and send meter levels
@@ -160,12 +663,15 @@ impl AudioCallback {
     }
 
     /// Generate metronome click
-    fn generate_metronome(&mut self, output: &mut [f32], frames: usize) {
+    ///
+    /// `start` is the timeline position of the first frame, which moves
+    /// `rate` samples per frame.
+    fn generate_metronome(&self, output: &mut [f32], start: f64, rate: f64) {
         let samples_per_beat = self.transport.tempo.samples_per_beat(self.sample_rate);
-        let playhead = self.transport.playhead.0 as f64;
+        let frames = output.len() / 2;
 
         for frame in 0..frames {
-            let sample_pos = playhead + frame as f64;
+            let sample_pos = start + frame as f64 * rate;
             let beat_pos = sample_pos / samples_per_beat;
             let beat_phase = beat_pos.fract();
 
@@ -194,31 +700,33 @@ impl AudioCallback {
 
     /// Calculate and send meter levels
     fn send_meter_update(&mut self, output: &[f32]) {
-        let mut peak_left = 0.0f32;
-        let mut peak_right = 0.0f32;
-        let mut sum_left = 0.0f64;
-        let mut sum_right = 0.0f64;
-
-        let frames = output.len() / 2;
-        for frame in 0..frames {
-            let left = output[frame * 2].abs();
-            let right = output[frame * 2 + 1].abs();
-
-            peak_left = peak_left.max(left);
-            peak_right = peak_right.max(right);
-            sum_left += (left * left) as f64;
-            sum_right += (right * right) as f64;
-        }
-
-        let rms_left = (sum_left / frames as f64).sqrt() as f32;
-        let rms_right = (sum_right / frames as f64).sqrt() as f32;
+        let mut peaks = [0.0f32; 2];
+        let mut rms = [0.0f32; 2];
+        interleaved_peaks(output, 2, &mut peaks);
+        interleaved_rms(output, 2, &mut rms);
 
         let _ = self.event_tx.push(AudioEvent::MeterUpdate {
-            peak_left,
-            peak_right,
-            rms_left,
-            rms_right,
+            peak_left: peaks[0],
+            peak_right: peaks[1],
+            rms_left: rms[0],
+            rms_right: rms[1],
+            gain_reduction_db: if self.limiter_enabled {
+                self.limiter.gain_reduction_db()
+            } else {
+                0.0
+            },
+            correlation: stereo_correlation(output),
         });
+
+        let total = self.channel_meters.len();
+        for (i, chunk) in self
+            .channel_meters
+            .chunks(CHANNEL_METERS_PER_EVENT)
+            .enumerate()
+        {
+            let chunk = ChannelMeterChunk::new(i * CHANNEL_METERS_PER_EVENT, total, chunk);
+            let _ = self.event_tx.push(AudioEvent::ChannelMeters(chunk));
+        }
     }
 
     /// Get the current transport state
@@ -231,3 +739,255 @@ impl AudioCallback {
         self.sample_rate
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{recording_take, take_with_frames};
+    use koto_core::{ControlNumber, MonitorMode, Tempo};
+    use rtrb::RingBuffer;
+
+    fn callback() -> (AudioCallback, Producer<AudioCommand>, Consumer<AudioEvent>) {
+        let (command_tx, command_rx) = RingBuffer::new(64);
+        let (event_tx, event_rx) = RingBuffer::new(256);
+        let callback = AudioCallback::new(command_rx, event_tx, SampleRate::CD_QUALITY, 512);
+        (callback, command_tx, event_rx)
+    }
+
+    fn count_in_ticks(events: &mut Consumer<AudioEvent>) -> Vec<u32> {
+        let mut ticks = Vec::new();
+        while let Ok(event) = events.pop() {
+            if let AudioEvent::CountInTick { beats_remaining } = event {
+                ticks.push(beats_remaining);
+            }
+        }
+        ticks
+    }
+
+    #[test]
+    fn test_count_in_then_records_on_downbeat() {
+        let (mut callback, mut commands, mut events) = callback();
+        // 1 bar of 4/4 at 120 BPM, 44.1 kHz: 4 beats of 22050 frames
+        commands
+            .push(AudioCommand::SetTempo(Tempo::new(120.0)))
+            .unwrap();
+        commands.push(AudioCommand::SetCountIn(1)).unwrap();
+        commands.push(AudioCommand::Play).unwrap();
+        let (input, mut take) = recording_take(SampleRate::CD_QUALITY);
+        commands.push(AudioCommand::StartRecording(input)).unwrap();
+
+        let mut output = vec![0.0; 1024];
+        let input = vec![0.5; 1024];
+        let mut frames = 0;
+        while !callback.transport().is_recording {
+            assert!(callback.transport().playhead == SamplePosition::ZERO);
+            callback.process(&mut output, Some(&input));
+            frames += 512;
+        }
+        assert_eq!(count_in_ticks(&mut events), vec![4, 3, 2, 1]);
+
+        // Recording started mid-block; only frames after the downbeat count
+        let recorded_frames = frames - 88_200;
+        assert_eq!(callback.transport().playhead.0 as usize, recorded_frames);
+        let mut recorded = Vec::new();
+        take.drain_into(&mut recorded);
+        assert_eq!(recorded.len(), recorded_frames * 2);
+    }
+
+    #[test]
+    fn test_record_safe_ignores_start_recording() {
+        let (mut callback, mut commands, mut events) = callback();
+        commands.push(AudioCommand::SetRecordSafe(true)).unwrap();
+        let (input, take) = recording_take(SampleRate::CD_QUALITY);
+        commands.push(AudioCommand::StartRecording(input)).unwrap();
+        let mut output = vec![0.0; 1024];
+        callback.process(&mut output, None);
+
+        assert!(!callback.transport().is_recording);
+        assert!(take.is_finished());
+        while let Ok(event) = events.pop() {
+            assert!(!matches!(event, AudioEvent::TransportStateChanged { .. }));
+        }
+    }
+
+    #[test]
+    fn test_stop_cancels_count_in() {
+        let (mut callback, mut commands, mut events) = callback();
+        commands.push(AudioCommand::SetCountIn(2)).unwrap();
+        let (input, take) = recording_take(SampleRate::CD_QUALITY);
+        commands.push(AudioCommand::StartRecording(input)).unwrap();
+        let mut output = vec![0.0; 1024];
+        callback.process(&mut output, None);
+        assert!(callback.transport().is_counting_in);
+
+        commands.push(AudioCommand::Stop).unwrap();
+        callback.process(&mut output, None);
+        assert!(!callback.transport().is_counting_in);
+        assert!(!callback.transport().is_recording);
+        assert!(take.is_finished());
+        assert_eq!(count_in_ticks(&mut events), vec![8]);
+    }
+
+    #[test]
+    fn test_process_context_carries_device_latency() {
+        let (mut callback, mut commands, _events) = callback();
+        callback.set_input_latency(512);
+        callback.set_output_latency(1024);
+        commands.push(AudioCommand::Play).unwrap();
+        callback.process(&mut [0.0; 1024], None);
+
+        let mut midi_out = Vec::new();
+        let context = callback.process_context(512, &[], &mut midi_out);
+        assert_eq!((context.input_latency, context.output_latency), (512, 1024));
+        assert!(context.is_playing);
+        assert_eq!(context.playhead, SamplePosition(512));
+    }
+
+    #[test]
+    fn test_mono_input_records_into_its_own_take() {
+        let (mut callback, mut commands, mut events) = callback();
+        callback.set_input_channels(ChannelCount::MONO);
+        let (input, mut first) = take_with_frames(600);
+        commands.push(AudioCommand::StartRecording(input)).unwrap();
+
+        let mut output = vec![0.0; 1024];
+        callback.process(&mut output, Some(&[0.25; 512]));
+        // The writer fell behind: what doesn't fit is dropped and reported
+        callback.process(&mut output, Some(&[0.25; 512]));
+        let mut overflows = Vec::new();
+        while let Ok(event) = events.pop() {
+            if let AudioEvent::RecordingOverflow { dropped_frames } = event {
+                overflows.push(dropped_frames);
+            }
+        }
+        assert_eq!(overflows, [424]);
+
+        // A new take leaves the first one's samples alone
+        let (input, mut second) = take_with_frames(600);
+        commands.push(AudioCommand::StartRecording(input)).unwrap();
+        callback.process(&mut output, Some(&[0.5; 512]));
+        assert!(first.is_finished());
+        let mut recorded = Vec::new();
+        assert_eq!(first.drain_into(&mut recorded), 1200);
+        assert!(recorded.iter().all(|&s| s == 0.25));
+        recorded.clear();
+        assert_eq!(second.drain_into(&mut recorded), 1024);
+        assert!(recorded.iter().all(|&s| s == 0.5));
+        assert!(!second.is_finished());
+    }
+
+    #[test]
+    fn test_monitored_input_is_mixed_in_prepared_blocks() {
+        let (mut callback, mut commands, _events) = callback();
+        commands
+            .push(AudioCommand::SetMonitorMode(MonitorMode::Always))
+            .unwrap();
+        // Longer than the 512 frames the callback was prepared for
+        let input: Vec<f32> = (0..3000).map(|i| i as f32 / 3000.0).collect();
+        let mut output = vec![1.0; 3000];
+        callback.process(&mut output, Some(&input));
+        assert_eq!(output, input);
+
+        // Nothing rendered or monitored: every source is skipped
+        commands
+            .push(AudioCommand::SetMonitorMode(MonitorMode::Off))
+            .unwrap();
+        callback.process(&mut output, Some(&input));
+        assert!(output.iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_stop_and_seek_release_sounding_notes() {
+        let (mut callback, mut commands, _events) = callback();
+        let note = |note| MidiMessage::NoteOn {
+            channel: MidiChannel(0),
+            note: NoteNumber(note),
+            velocity: Velocity(100),
+        };
+        commands.push(AudioCommand::Play).unwrap();
+        commands.push(AudioCommand::SendMidi(note(60))).unwrap();
+        commands.push(AudioCommand::SendMidi(note(64))).unwrap();
+        let mut output = vec![0.0; 1024];
+        callback.process(&mut output, None);
+        assert_eq!(callback.drain_midi_output().count(), 2);
+
+        commands.push(AudioCommand::Stop).unwrap();
+        callback.process(&mut output, None);
+        let released: Vec<_> = callback
+            .drain_midi_output()
+            .map(|event| event.message)
+            .collect();
+        let off = |note| MidiMessage::NoteOff {
+            channel: MidiChannel(0),
+            note: NoteNumber(note),
+            velocity: Velocity::OFF,
+        };
+        assert_eq!(released, [off(60), off(64)]);
+
+        // Released notes aren't released again
+        commands.push(AudioCommand::SendMidi(note(67))).unwrap();
+        commands
+            .push(AudioCommand::Seek(SamplePosition(1000)))
+            .unwrap();
+        commands
+            .push(AudioCommand::Seek(SamplePosition(2000)))
+            .unwrap();
+        callback.process(&mut output, None);
+        let messages: Vec<_> = callback
+            .drain_midi_output()
+            .map(|event| event.message)
+            .collect();
+        assert_eq!(messages, [note(67), off(67)]);
+    }
+
+    #[test]
+    fn test_midi_output_never_grows() {
+        let (mut callback, mut commands, _events) = callback();
+        let mut output = vec![0.0; 1024];
+        for _ in 0..MIDI_OUTPUT_CAPACITY / 32 + 1 {
+            for _ in 0..32 {
+                let message = MidiMessage::ControlChange {
+                    channel: MidiChannel(0),
+                    control: ControlNumber(1),
+                    value: 0,
+                };
+                commands.push(AudioCommand::SendMidi(message)).unwrap();
+            }
+            callback.process(&mut output, None);
+        }
+        assert_eq!(callback.drain_midi_output().count(), MIDI_OUTPUT_CAPACITY);
+        assert_eq!(callback.drain_midi_output().count(), 0);
+    }
+
+    #[test]
+    fn test_channel_meters_are_sent_in_chunks() {
+        let (mut callback, _commands, mut events) = callback();
+        let meters: Vec<MeterReading> = (0..20)
+            .map(|i| MeterReading {
+                channel: koto_mixer::ChannelId(i),
+                peak: [i as f32 / 20.0; 2],
+                ..MeterReading::default()
+            })
+            .collect();
+        callback.set_channel_meters(&meters);
+
+        // ~30 Hz at 44.1 kHz: the third block of 512 frames sends
+        let mut output = vec![0.0; 1024];
+        for _ in 0..3 {
+            callback.process(&mut output, None);
+        }
+        let mut chunks = Vec::new();
+        while let Ok(event) = events.pop() {
+            if let AudioEvent::ChannelMeters(chunk) = event {
+                chunks.push(chunk);
+            }
+        }
+        let sizes: Vec<_> = chunks
+            .iter()
+            .map(|c| (c.first, c.total, c.readings().len()))
+            .collect();
+        assert_eq!(sizes, [(0, 20, 8), (8, 20, 8), (16, 20, 4)]);
+        let received: Vec<_> = chunks.iter().flat_map(|c| c.readings()).copied().collect();
+        assert_eq!(received, meters);
+    }
+}
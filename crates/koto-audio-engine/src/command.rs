@@ -1,31 +1,77 @@
 //! Commands and events for audio engine communication
 
-use koto_core::{SamplePosition, Tempo, TimeSignature};
+use crate::{TakeInput, TrackStates};
+use koto_core::{
+    timeline_samples, LoopWrap, MidiMessage, MonitorMode, PreRoll, SamplePosition, SampleRange,
+    SampleRate, SeekPolicy, StopBehavior, Tempo, TimeConverter, TimeSignature, TrackId,
+};
+use koto_mixer::MeterReading;
+
+/// Most mixer channel meters carried by one [`AudioEvent::ChannelMeters`]
+pub const CHANNEL_METERS_PER_EVENT: usize = 8;
 
 /// Commands sent from UI thread to audio thread
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum AudioCommand {
     /// Start playback
     Play,
-    /// Stop playback
+    /// Start playback the configured pre-roll before the playhead
+    PlayWithPreRoll,
+    /// Set the pre-roll used by `PlayWithPreRoll`
+    SetPreRoll(Option<PreRoll>),
+    /// Stop playback, moving the playhead according to the stop behavior
     Stop,
+    /// Stop playback without moving the playhead
+    Pause,
     /// Seek to a specific position
     Seek(SamplePosition),
     /// Set tempo
     SetTempo(Tempo),
     /// Set time signature
     SetTimeSignature(TimeSignature),
-    /// Start recording
-    StartRecording,
+    /// Start recording into a new take, from [`recording_take`](crate::recording_take)
+    StartRecording(TakeInput),
     /// Stop recording
     StopRecording,
     /// Set master volume (0.0 to 1.0)
     SetMasterVolume(f32),
     /// Enable/disable metronome
     SetMetronomeEnabled(bool),
+    /// Enable/disable the brickwall limiter on the master output
+    SetLimiterEnabled(bool),
+    /// Enable/disable looping
+    SetLoopEnabled(bool),
+    /// Set the loop region (ignored unless it has a positive length)
+    SetLoopRange(SampleRange),
+    /// Set where the playhead goes on stop
+    SetStopBehavior(StopBehavior),
+    /// Refuse `StartRecording` while set
+    SetRecordSafe(bool),
+    /// Set when input is passed through to the output
+    SetMonitorMode(MonitorMode),
+    /// Mute or unmute a track
+    SetTrackMute(TrackId, bool),
+    /// Solo or unsolo a track
+    SetTrackSolo(TrackId, bool),
+    /// Set the number of count-in bars before recording (0 disables)
+    SetCountIn(u8),
+    /// Set the playback rate (clamped to 0.25-4.0)
+    SetPlaybackRate(f64),
+    /// Set how seeks outside the loop behave while playing
+    SetSeekPolicy(SeekPolicy),
+    /// Scrub to a position, pausing normal playback
+    Scrub(SamplePosition),
+    /// Release the scrub, resuming playback if it was playing before
+    EndScrub,
+    /// Send a MIDI message to the instruments, e.g. from a MIDI input or
+    /// the on-screen keyboard; notes still sounding are released on Stop
+    /// and Seek
+    SendMidi(MidiMessage),
 }
 
 /// Events sent from audio thread to UI thread
+// `ChannelMeters` stays inline: boxing it would allocate on the audio thread
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone)]
 pub enum AudioEvent {
     /// Playhead position update
@@ -36,20 +82,67 @@ pub enum AudioEvent {
         peak_right: f32,
         rms_left: f32,
         rms_right: f32,
+        /// Master limiter gain reduction in dB (0.0 when inactive)
+        gain_reduction_db: f32,
+        /// Phase correlation between left and right (-1.0 to 1.0)
+        correlation: f32,
     },
+    /// Mixer channel meters, sent alongside `MeterUpdate`; more channels
+    /// than fit in one chunk are split over several events
+    ChannelMeters(ChannelMeterChunk),
     /// Transport state changed
     TransportStateChanged {
         is_playing: bool,
         is_recording: bool,
     },
+    /// A count-in beat started; recording begins after `beats_remaining`
+    /// more beats
+    CountInTick { beats_remaining: u32 },
+    /// The track state table is full; the mute/solo change was dropped
+    TrackStateOverflow(TrackId),
+    /// The take's writer fell behind and input frames were dropped
+    RecordingOverflow { dropped_frames: usize },
     /// Audio device error
     DeviceError(String),
     /// Buffer underrun occurred
     BufferUnderrun,
 }
 
+/// Meters of up to [`CHANNEL_METERS_PER_EVENT`] mixer channels
+///
+/// Fixed size, so sending it from the audio thread doesn't allocate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelMeterChunk {
+    /// Index of the first channel in the chunk
+    pub first: usize,
+    /// Channels metered in the whole update
+    pub total: usize,
+    len: usize,
+    meters: [MeterReading; CHANNEL_METERS_PER_EVENT],
+}
+
+impl ChannelMeterChunk {
+    /// Chunk the meters starting at channel `first`, keeping the first
+    /// [`CHANNEL_METERS_PER_EVENT`]
+    pub fn new(first: usize, total: usize, readings: &[MeterReading]) -> Self {
+        let len = readings.len().min(CHANNEL_METERS_PER_EVENT);
+        let mut meters = [MeterReading::default(); CHANNEL_METERS_PER_EVENT];
+        meters[..len].copy_from_slice(&readings[..len]);
+        Self {
+            first,
+            total,
+            len,
+            meters,
+        }
+    }
+
+    pub fn readings(&self) -> &[MeterReading] {
+        &self.meters[..self.len]
+    }
+}
+
 /// Transport state
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy)]
 pub struct TransportState {
     pub is_playing: bool,
     pub is_recording: bool,
@@ -59,14 +152,240 @@ pub struct TransportState {
     pub loop_enabled: bool,
     pub loop_start: SamplePosition,
     pub loop_end: SamplePosition,
+    /// Bars of metronome played before recording starts
+    pub count_in_bars: u8,
+    /// Recording has been requested and the count-in is playing
+    pub is_counting_in: bool,
+    /// When live input is heard on the output
+    pub input_monitoring: MonitorMode,
+    /// Refuse to start recording
+    pub record_safe: bool,
+    /// What stop does with the playhead
+    pub stop_behavior: StopBehavior,
+    /// Position the last play started from
+    pub play_start: SamplePosition,
+    /// Context played before the playhead on `PlayWithPreRoll`
+    pub pre_roll: Option<PreRoll>,
+    /// Timeline samples played per output frame (0.25 to 4.0)
+    pub playback_rate: f64,
+    /// Sub-sample playhead position carried between blocks
+    pub playhead_fraction: f64,
+    /// How seeks outside the loop behave while playing
+    pub seek_policy: SeekPolicy,
+    /// Whether the playhead wraps at the loop end
+    pub following_loop: bool,
+    /// Mute/solo state of tracks
+    pub tracks: TrackStates,
+    /// The playhead is being dragged and normal playback is suspended
+    pub is_scrubbing: bool,
+    /// Playback was running when the scrub started
+    pub resume_after_scrub: bool,
 }
 
 impl TransportState {
     pub fn new() -> Self {
         Self {
+            is_playing: false,
+            is_recording: false,
+            playhead: SamplePosition::ZERO,
             tempo: Tempo::DEFAULT,
             time_signature: TimeSignature::COMMON_TIME,
-            ..Default::default()
+            loop_enabled: false,
+            loop_start: SamplePosition::ZERO,
+            loop_end: SamplePosition::ZERO,
+            count_in_bars: 0,
+            is_counting_in: false,
+            input_monitoring: MonitorMode::default(),
+            record_safe: false,
+            stop_behavior: StopBehavior::default(),
+            play_start: SamplePosition::ZERO,
+            pre_roll: None,
+            playback_rate: 1.0,
+            playhead_fraction: 0.0,
+            seek_policy: SeekPolicy::default(),
+            following_loop: true,
+            tracks: TrackStates::new(),
+            is_scrubbing: false,
+            resume_after_scrub: false,
+        }
+    }
+
+    /// Start playing, remembering where playback started
+    pub fn play(&mut self) {
+        if !self.is_playing {
+            self.play_start = self.playhead;
+        }
+        self.is_playing = true;
+    }
+
+    /// Start playing the pre-roll before the playhead
+    ///
+    /// The play start stays at the original playhead, so stop can return
+    /// there.
+    pub fn play_with_preroll(&mut self, sample_rate: SampleRate) {
+        if self.is_playing {
+            return;
+        }
+        let original = self.playhead;
+        if let Some(pre_roll) = self.pre_roll {
+            let converter = TimeConverter::new(sample_rate, self.tempo, self.time_signature);
+            self.seek(pre_roll.start_position(original, &converter));
+        }
+        self.play();
+        self.play_start = original;
+    }
+
+    /// Move the playhead for a scrub, entering the scrubbing state if needed
+    pub fn scrub_to(&mut self, position: SamplePosition) {
+        if !self.is_scrubbing {
+            self.is_scrubbing = true;
+            self.resume_after_scrub = self.is_playing;
+            self.is_playing = false;
         }
+        self.seek(position);
+    }
+
+    /// Leave the scrubbing state, resuming playback from the scrub position
+    /// if it was playing before
+    pub fn end_scrub(&mut self) {
+        if self.is_scrubbing {
+            self.is_scrubbing = false;
+            self.is_playing = self.resume_after_scrub;
+            self.resume_after_scrub = false;
+        }
+    }
+
+    /// Move the playhead, dropping any sub-sample remainder
+    ///
+    /// While playing with looping enabled, a target outside the loop is
+    /// handled according to the seek policy.
+    pub fn seek(&mut self, position: SamplePosition) {
+        let (position, following) = if self.is_playing && self.loop_enabled {
+            self.seek_policy.resolve(position, self.loop_range())
+        } else {
+            (position, true)
+        };
+        self.playhead = position;
+        self.playhead_fraction = 0.0;
+        self.following_loop = following;
+    }
+
+    /// Playhead position including the sub-sample part
+    pub fn exact_playhead(&self) -> f64 {
+        self.playhead.0 as f64 + self.playhead_fraction
+    }
+
+    /// Whether input should currently be heard; recording or counting in
+    /// counts as armed
+    pub fn is_monitoring(&self) -> bool {
+        self.input_monitoring
+            .is_monitoring(self.is_recording || self.is_counting_in)
+    }
+
+    /// Number of beats in the count-in
+    pub fn count_in_beats(&self) -> u32 {
+        self.count_in_bars as u32 * self.time_signature.numerator as u32
+    }
+
+    /// Length of the count-in in frames at the current tempo
+    pub fn count_in_frames(&self, sample_rate: SampleRate) -> usize {
+        (self.count_in_beats() as f64 * self.tempo.samples_per_beat(sample_rate)).round() as usize
+    }
+
+    pub fn loop_range(&self) -> SampleRange {
+        SampleRange::new(self.loop_start, self.loop_end)
+    }
+
+    /// Output frames until the playhead reaches the loop end, if it will
+    /// wrap there
+    pub fn frames_until_wrap(&self) -> Option<usize> {
+        let range = self.loop_range();
+        let following = self.loop_enabled && self.following_loop;
+        if !following || range.is_empty() || self.playhead >= range.end {
+            return None;
+        }
+        let samples = (range.end.0 - self.playhead.0) as usize;
+        if self.playback_rate == 1.0 {
+            return Some(samples);
+        }
+        let remaining = samples as f64 - self.playhead_fraction;
+        Some(((remaining / self.playback_rate).ceil() as usize).max(1))
+    }
+
+    /// Move the playhead forward by `frames` output frames at the playback
+    /// rate, wrapping at the loop end when looping
+    pub fn advance(&mut self, frames: usize) -> Option<LoopWrap> {
+        let samples = timeline_samples(frames, self.playback_rate, &mut self.playhead_fraction);
+        if !self.loop_enabled {
+            self.playhead.advance(samples);
+            return None;
+        }
+        if !self.following_loop {
+            self.playhead.advance(samples);
+            self.following_loop = self.loop_range().contains(self.playhead);
+            return None;
+        }
+        let (playhead, wrap) = self.loop_range().advance_looped(self.playhead, samples);
+        self.playhead = playhead;
+        wrap
+    }
+}
+
+impl Default for TransportState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seek_policies_at_loop_end() {
+        let mut transport = TransportState::new();
+        transport.loop_enabled = true;
+        transport.loop_start = SamplePosition(1000);
+        transport.loop_end = SamplePosition(2000);
+        transport.is_playing = true;
+
+        transport.seek(SamplePosition(2000));
+        assert_eq!(transport.frames_until_wrap(), None);
+        transport.advance(64);
+        assert_eq!(transport.playhead, SamplePosition(2064));
+
+        transport.seek_policy = SeekPolicy::ClampToLoop;
+        transport.seek(SamplePosition(2000));
+        assert_eq!(transport.playhead, SamplePosition(1000));
+        assert_eq!(transport.frames_until_wrap(), Some(1000));
+    }
+
+    #[test]
+    fn test_scrub_while_playing_resumes_on_release() {
+        let mut transport = TransportState::new();
+        transport.is_playing = true;
+        transport.scrub_to(SamplePosition(1000));
+        assert!(transport.is_scrubbing);
+        assert!(!transport.is_playing);
+
+        transport.scrub_to(SamplePosition(800));
+        transport.end_scrub();
+        assert!(!transport.is_scrubbing);
+        assert!(transport.is_playing);
+        assert_eq!(transport.playhead, SamplePosition(800));
+    }
+
+    #[test]
+    fn test_scrub_while_stopped_stays_stopped() {
+        let mut transport = TransportState::new();
+        transport.scrub_to(SamplePosition(1000));
+        assert!(transport.is_scrubbing);
+        transport.end_scrub();
+        assert!(!transport.is_playing);
+        assert_eq!(transport.playhead, SamplePosition(1000));
+
+        // Releasing again is a no-op
+        transport.end_scrub();
+        assert!(!transport.is_playing);
     }
 }
@@ -3,6 +3,7 @@
 use cpal::traits::{DeviceTrait, HostTrait};
 use koto_core::{ChannelCount, KotoError, KotoResult, SampleRate};
 use thiserror::Error;
+use tracing::warn;
 
 /// Audio device error
 #[derive(Error, Debug)]
@@ -21,10 +22,129 @@ pub enum DeviceError {
 
 impl From<DeviceError> for KotoError {
     fn from(err: DeviceError) -> Self {
-        KotoError::AudioDevice(err.to_string())
+        match err {
+            DeviceError::NoOutputDevice => KotoError::DeviceNotFound {
+                name: "default output".to_string(),
+            },
+            DeviceError::NoInputDevice => KotoError::DeviceNotFound {
+                name: "default input".to_string(),
+            },
+            other => KotoError::AudioBackend(Box::new(other)),
+        }
+    }
+}
+
+/// Describe a stream configuration for error messages
+pub(crate) fn describe_config(config: &cpal::StreamConfig) -> String {
+    format!("{} Hz, {} ch", config.sample_rate.0, config.channels)
+}
+
+/// Describe a device's supported configurations for error messages
+pub(crate) fn describe_supported(configs: &[cpal::SupportedStreamConfigRange]) -> String {
+    if configs.is_empty() {
+        return "none".to_string();
+    }
+    configs
+        .iter()
+        .map(|c| {
+            format!(
+                "{}-{} Hz, {} ch",
+                c.min_sample_rate().0,
+                c.max_sample_rate().0,
+                c.channels()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Convert a failure to read a device's default configuration
+pub(crate) fn default_config_error(err: cpal::DefaultStreamConfigError) -> KotoError {
+    match err {
+        cpal::DefaultStreamConfigError::DeviceNotAvailable => KotoError::DeviceDisconnected,
+        cpal::DefaultStreamConfigError::StreamTypeNotSupported => KotoError::UnsupportedConfig {
+            requested: "default stream".to_string(),
+            supported: "none".to_string(),
+        },
+        other => KotoError::AudioBackend(Box::new(other)),
     }
 }
 
+/// Convert a stream build failure; `supported` is only consulted when the
+/// requested configuration was rejected
+pub(crate) fn build_stream_error(
+    err: cpal::BuildStreamError,
+    requested: &cpal::StreamConfig,
+    supported: impl FnOnce() -> String,
+) -> KotoError {
+    match err {
+        cpal::BuildStreamError::DeviceNotAvailable => KotoError::DeviceDisconnected,
+        cpal::BuildStreamError::StreamConfigNotSupported => KotoError::UnsupportedConfig {
+            requested: describe_config(requested),
+            supported: supported(),
+        },
+        other => KotoError::StreamBuildFailed(Box::new(other)),
+    }
+}
+
+/// Convert a failure to start a stream
+pub(crate) fn play_stream_error(err: cpal::PlayStreamError) -> KotoError {
+    match err {
+        cpal::PlayStreamError::DeviceNotAvailable => KotoError::DeviceDisconnected,
+        other => KotoError::StreamPlayFailed(Box::new(other)),
+    }
+}
+
+/// Device and stream format the engine tries before the defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DevicePreferences {
+    /// Output device name, as listed by [`AudioDeviceManager::output_devices`]
+    pub output_device: Option<String>,
+    pub sample_rate: Option<SampleRate>,
+    /// Frames per buffer
+    pub buffer_size: Option<u32>,
+}
+
+/// Buffer size used when none is preferred or the preferred one isn't
+/// supported
+pub(crate) const DEFAULT_BUFFER_SIZE: usize = 512;
+
+/// Stream configuration and buffer size for `preferences`, keeping the
+/// device's `default` for whatever it doesn't support
+pub(crate) fn preferred_config(
+    default: &cpal::SupportedStreamConfig,
+    supported: &[cpal::SupportedStreamConfigRange],
+    preferences: &DevicePreferences,
+) -> (cpal::StreamConfig, usize) {
+    let mut config = default.config();
+    if let Some(rate) = preferences.sample_rate {
+        let rate = cpal::SampleRate(rate.0);
+        let available = supported.iter().any(|range| {
+            range.channels() == default.channels()
+                && range.sample_format() == default.sample_format()
+                && (range.min_sample_rate()..=range.max_sample_rate()).contains(&rate)
+        });
+        if available {
+            config.sample_rate = rate;
+        } else {
+            warn!("Preferred sample rate {} Hz not supported", rate.0);
+        }
+    }
+
+    let mut buffer_size = DEFAULT_BUFFER_SIZE;
+    config.buffer_size = cpal::BufferSize::Default;
+    if let Some(frames) = preferences.buffer_size {
+        match default.buffer_size() {
+            cpal::SupportedBufferSize::Range { min, max } if (*min..=*max).contains(&frames) => {
+                config.buffer_size = cpal::BufferSize::Fixed(frames);
+                buffer_size = frames as usize;
+            }
+            _ => warn!("Preferred buffer size {frames} not supported"),
+        }
+    }
+    (config, buffer_size)
+}
+
 /// Information about an audio device
 #[derive(Debug, Clone)]
 pub struct AudioDeviceInfo {
@@ -57,6 +177,17 @@ impl AudioDeviceManager {
         self.host.default_input_device()
     }
 
+    /// Find an output device by name
+    pub fn output_device_by_name(&self, name: &str) -> KotoResult<cpal::Device> {
+        self.host
+            .output_devices()
+            .map_err(|e| KotoError::AudioBackend(Box::new(e)))?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| KotoError::DeviceNotFound {
+                name: name.to_string(),
+            })
+    }
+
     /// List all available output devices
     pub fn output_devices(&self) -> Vec<AudioDeviceInfo> {
         self.host
@@ -136,3 +267,90 @@ impl Default for AudioDeviceManager {
         Self::new().expect("Failed to create audio device manager")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_cpal_errors_map_to_structured_variants() {
+        let config = cpal::StreamConfig {
+            channels: 2,
+            sample_rate: cpal::SampleRate(96_000),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        let err = build_stream_error(
+            cpal::BuildStreamError::DeviceNotAvailable,
+            &config,
+            || unreachable!(),
+        );
+        assert!(matches!(err, KotoError::DeviceDisconnected));
+        assert!(err.is_device_lost());
+
+        let err = build_stream_error(
+            cpal::BuildStreamError::StreamConfigNotSupported,
+            &config,
+            || "44100-48000 Hz, 2 ch".to_string(),
+        );
+        match err {
+            KotoError::UnsupportedConfig {
+                requested,
+                supported,
+            } => {
+                assert_eq!(requested, "96000 Hz, 2 ch");
+                assert_eq!(supported, "44100-48000 Hz, 2 ch");
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+
+        let backend = cpal::BackendSpecificError {
+            description: "xrun".to_string(),
+        };
+        let err = build_stream_error(
+            cpal::BuildStreamError::BackendSpecific { err: backend },
+            &config,
+            String::new,
+        );
+        assert!(matches!(err, KotoError::StreamBuildFailed(_)));
+        assert!(err.source().unwrap().to_string().contains("xrun"));
+
+        let err: KotoError = DeviceError::NoOutputDevice.into();
+        assert!(matches!(err, KotoError::DeviceNotFound { .. }));
+    }
+
+    #[test]
+    fn test_preferred_config_falls_back_to_default() {
+        let buffer = cpal::SupportedBufferSize::Range { min: 64, max: 2048 };
+        let format = cpal::SampleFormat::F32;
+        let default = cpal::SupportedStreamConfig::new(2, cpal::SampleRate(48_000), buffer, format);
+        let supported = [cpal::SupportedStreamConfigRange::new(
+            2,
+            cpal::SampleRate(44_100),
+            cpal::SampleRate(96_000),
+            buffer,
+            format,
+        )];
+
+        let preferences = DevicePreferences {
+            output_device: None,
+            sample_rate: Some(SampleRate(96_000)),
+            buffer_size: Some(128),
+        };
+        let (config, frames) = preferred_config(&default, &supported, &preferences);
+        assert_eq!(config.sample_rate, cpal::SampleRate(96_000));
+        assert_eq!(config.buffer_size, cpal::BufferSize::Fixed(128));
+        assert_eq!(frames, 128);
+
+        let preferences = DevicePreferences {
+            output_device: None,
+            sample_rate: Some(SampleRate(192_000)),
+            buffer_size: Some(8192),
+        };
+        let (config, frames) = preferred_config(&default, &supported, &preferences);
+        assert_eq!(config.sample_rate, cpal::SampleRate(48_000));
+        assert_eq!(config.buffer_size, cpal::BufferSize::Default);
+        assert_eq!(frames, DEFAULT_BUFFER_SIZE);
+    }
+}
@@ -1,13 +1,23 @@
 //! Main audio engine
 
-use crate::{AudioCallback, AudioCommand, AudioDeviceManager, AudioEvent};
+use crate::device::{
+    build_stream_error, default_config_error, describe_supported, play_stream_error,
+    preferred_config,
+};
+use crate::{
+    recording_take, AudioCallback, AudioCommand, AudioDeviceManager, AudioEvent, DeviceError,
+    DevicePreferences, RecordingTake,
+};
 use cpal::traits::{DeviceTrait, StreamTrait};
-use cpal::{Stream, StreamConfig};
-use koto_core::{KotoError, KotoResult, SamplePosition, SampleRate, Tempo, TimeSignature};
+use cpal::Stream;
+use koto_core::{
+    ChannelCount, KotoResult, MidiMessage, MonitorMode, PreRoll, SamplePosition, SampleRange,
+    SampleRate, SeekPolicy, StopBehavior, Tempo, TimeSignature, TrackId,
+};
 use parking_lot::Mutex;
 use rtrb::RingBuffer;
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 /// Ring buffer capacity for commands and events
 const COMMAND_BUFFER_SIZE: usize = 256;
@@ -27,6 +37,8 @@ pub struct AudioEngine {
     device_manager: AudioDeviceManager,
     /// Sample rate
     sample_rate: SampleRate,
+    /// Device and format tried first on start
+    preferences: DevicePreferences,
     /// Is engine running
     is_running: bool,
 }
@@ -47,33 +59,39 @@ impl AudioEngine {
             _input_stream: None,
             device_manager,
             sample_rate: SampleRate::default(),
+            preferences: DevicePreferences::default(),
             is_running: false,
         })
     }
 
-    /// Start the audio engine with default devices
+    /// Set the device and format to try on the next [`start`](Self::start)
+    pub fn set_device_preferences(&mut self, preferences: DevicePreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Start the audio engine with the preferred device and format,
+    /// falling back to the defaults for whatever isn't available
     pub fn start(&mut self) -> KotoResult<()> {
         if self.is_running {
             return Ok(());
         }
 
-        // Get default output device
-        let output_device = self
-            .device_manager
-            .default_output_device()
-            .ok_or(KotoError::AudioDevice("No output device".to_string()))?;
-
+        let output_device = self.preferred_output_device()?;
         let output_name = output_device.name().unwrap_or_default();
         info!("Using output device: {}", output_name);
 
-        // Get default config
-        let output_config = output_device
+        let default_config = output_device
             .default_output_config()
-            .map_err(|e| KotoError::AudioDevice(e.to_string()))?;
+            .map_err(default_config_error)?;
+        let supported = self
+            .device_manager
+            .supported_output_configs(&output_device)
+            .unwrap_or_default();
+        let (stream_config, buffer_size) =
+            preferred_config(&default_config, &supported, &self.preferences);
 
-        self.sample_rate = SampleRate(output_config.sample_rate().0);
-        let channels = output_config.channels() as usize;
-        let buffer_size = 512; // Default buffer size
+        self.sample_rate = SampleRate(stream_config.sample_rate.0);
+        let channels = stream_config.channels as usize;
 
         info!(
             "Output config: {}Hz, {} channels",
@@ -87,27 +105,39 @@ impl AudioEngine {
         self.command_tx = command_tx;
         self.event_rx = event_rx;
 
-        // Create audio callback
-        let callback = Arc::new(Mutex::new(AudioCallback::new(
-            command_rx,
-            event_tx,
-            self.sample_rate,
-            buffer_size,
-        )));
+        // Create audio callback, set up for the input before the stream runs
+        let mut callback = AudioCallback::new(command_rx, event_tx, self.sample_rate, buffer_size);
+        let input_channels = self
+            .device_manager
+            .default_input_device()
+            .and_then(|device| device.default_input_config().ok())
+            .map_or(ChannelCount::STEREO, |config| {
+                ChannelCount(config.channels())
+            });
+        callback.set_input_channels(input_channels);
+        // Input arrives a buffer late; output latency is refined from the
+        // stream's timestamps once it runs
+        callback.set_input_latency(buffer_size);
+        callback.set_output_latency(buffer_size);
+        let callback = Arc::new(Mutex::new(callback));
 
         // Create output stream
         let callback_clone = callback.clone();
-        let stream_config = StreamConfig {
-            channels: output_config.channels(),
-            sample_rate: output_config.sample_rate(),
-            buffer_size: cpal::BufferSize::Default,
-        };
+        let sample_rate = self.sample_rate;
 
         let output_stream = output_device
             .build_output_stream(
                 &stream_config,
-                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                move |data: &mut [f32], info: &cpal::OutputCallbackInfo| {
                     if let Some(mut cb) = callback_clone.try_lock() {
+                        // Time until this buffer is heard is the output latency
+                        let timestamp = info.timestamp();
+                        if let Some(latency) =
+                            timestamp.playback.duration_since(&timestamp.callback)
+                        {
+                            let samples = latency.as_secs_f64() * sample_rate.as_f64();
+                            cb.set_output_latency(samples.round() as usize);
+                        }
                         cb.process(data, None);
                     } else {
                         // If we can't get the lock, output silence
@@ -119,11 +149,16 @@ impl AudioEngine {
                 },
                 None,
             )
-            .map_err(|e| KotoError::AudioStream(e.to_string()))?;
+            .map_err(|e| {
+                build_stream_error(e, &stream_config, || {
+                    self.device_manager
+                        .supported_output_configs(&output_device)
+                        .map(|configs| describe_supported(&configs))
+                        .unwrap_or_default()
+                })
+            })?;
 
-        output_stream
-            .play()
-            .map_err(|e| KotoError::AudioStream(e.to_string()))?;
+        output_stream.play().map_err(play_stream_error)?;
 
         self._output_stream = Some(output_stream);
         self.is_running = true;
@@ -132,6 +167,20 @@ impl AudioEngine {
         Ok(())
     }
 
+    /// The preferred output device if it's connected, else the default
+    fn preferred_output_device(&self) -> KotoResult<cpal::Device> {
+        if let Some(name) = &self.preferences.output_device {
+            match self.device_manager.output_device_by_name(name) {
+                Ok(device) => return Ok(device),
+                Err(err) => warn!("Preferred output device unavailable ({err}), using the default"),
+            }
+        }
+        Ok(self
+            .device_manager
+            .default_output_device()
+            .ok_or(DeviceError::NoOutputDevice)?)
+    }
+
     /// Stop the audio engine
     pub fn stop(&mut self) {
         self._output_stream = None;
@@ -164,6 +213,86 @@ impl AudioEngine {
         self.send_command(AudioCommand::Stop);
     }
 
+    /// Start playback the configured pre-roll before the playhead
+    pub fn play_with_preroll(&mut self) {
+        self.send_command(AudioCommand::PlayWithPreRoll);
+    }
+
+    /// Set the pre-roll used by [`play_with_preroll`](Self::play_with_preroll)
+    pub fn set_pre_roll(&mut self, pre_roll: Option<PreRoll>) {
+        self.send_command(AudioCommand::SetPreRoll(pre_roll));
+    }
+
+    /// Pause playback, leaving the playhead in place
+    pub fn pause(&mut self) {
+        self.send_command(AudioCommand::Pause);
+    }
+
+    /// Set where the playhead goes when playback stops
+    pub fn set_stop_behavior(&mut self, behavior: StopBehavior) {
+        self.send_command(AudioCommand::SetStopBehavior(behavior));
+    }
+
+    /// Set the playback rate (0.25 to 4.0, 1.0 is normal speed)
+    pub fn set_playback_rate(&mut self, rate: f64) {
+        self.send_command(AudioCommand::SetPlaybackRate(rate));
+    }
+
+    /// Scrub to a position, suspending normal playback
+    pub fn scrub(&mut self, position: SamplePosition) {
+        self.send_command(AudioCommand::Scrub(position));
+    }
+
+    /// Release the scrub, resuming playback if it was running
+    pub fn end_scrub(&mut self) {
+        self.send_command(AudioCommand::EndScrub);
+    }
+
+    /// Set how seeks outside the loop behave while playing
+    pub fn set_seek_policy(&mut self, policy: SeekPolicy) {
+        self.send_command(AudioCommand::SetSeekPolicy(policy));
+    }
+
+    /// Refuse to start recording while `record_safe` is set
+    pub fn set_record_safe(&mut self, record_safe: bool) {
+        self.send_command(AudioCommand::SetRecordSafe(record_safe));
+    }
+
+    /// Set when live input is heard on the output
+    pub fn set_monitor_mode(&mut self, mode: MonitorMode) {
+        self.send_command(AudioCommand::SetMonitorMode(mode));
+    }
+
+    /// Mute or unmute a track
+    pub fn set_track_mute(&mut self, track: TrackId, mute: bool) {
+        self.send_command(AudioCommand::SetTrackMute(track, mute));
+    }
+
+    /// Solo or unsolo a track
+    pub fn set_track_solo(&mut self, track: TrackId, solo: bool) {
+        self.send_command(AudioCommand::SetTrackSolo(track, solo));
+    }
+
+    /// Enable or disable looping
+    pub fn set_loop_enabled(&mut self, enabled: bool) {
+        self.send_command(AudioCommand::SetLoopEnabled(enabled));
+    }
+
+    /// Set the loop region
+    pub fn set_loop_range(&mut self, range: SampleRange) {
+        self.send_command(AudioCommand::SetLoopRange(range));
+    }
+
+    /// Set the number of count-in bars before recording (0 disables)
+    pub fn set_count_in(&mut self, bars: u8) {
+        self.send_command(AudioCommand::SetCountIn(bars));
+    }
+
+    /// Send a MIDI message to the instruments
+    pub fn send_midi(&mut self, message: MidiMessage) {
+        self.send_command(AudioCommand::SendMidi(message));
+    }
+
     /// Seek to position
     pub fn seek(&mut self, position: SamplePosition) {
         self.send_command(AudioCommand::Seek(position));
@@ -179,9 +308,13 @@ impl AudioEngine {
         self.send_command(AudioCommand::SetTimeSignature(time_signature));
     }
 
-    /// Start recording
-    pub fn start_recording(&mut self) {
-        self.send_command(AudioCommand::StartRecording);
+    /// Start recording into a new take
+    ///
+    /// Drain the returned take on a disk-writer thread while recording.
+    pub fn start_recording(&mut self) -> RecordingTake {
+        let (input, take) = recording_take(self.sample_rate);
+        self.send_command(AudioCommand::StartRecording(input));
+        take
     }
 
     /// Stop recording
@@ -199,6 +332,11 @@ impl AudioEngine {
         self.send_command(AudioCommand::SetMetronomeEnabled(enabled));
     }
 
+    /// Enable/disable the master limiter
+    pub fn set_limiter_enabled(&mut self, enabled: bool) {
+        self.send_command(AudioCommand::SetLimiterEnabled(enabled));
+    }
+
     /// Get the sample rate
     pub fn sample_rate(&self) -> SampleRate {
         self.sample_rate
@@ -11,9 +11,15 @@ mod callback;
 mod command;
 mod device;
 mod engine;
+mod mix;
+mod recording;
+mod track_state;
 
 pub use buffer_pool::*;
 pub use callback::*;
 pub use command::*;
 pub use device::*;
 pub use engine::*;
+pub use mix::*;
+pub use recording::*;
+pub use track_state::*;
@@ -0,0 +1,21 @@
+//! Summing stage for track and bus buffers
+
+use koto_core::{AudioBuffer, SilenceFlags};
+
+/// Sum `inputs` into `output`, skipping buffers flagged as silent
+///
+/// `output` is cleared first. Returns true if every input was silent, in
+/// which case `output` is left as silence and downstream processing can
+/// be skipped.
+pub fn mix_inputs(output: &mut AudioBuffer, inputs: &[AudioBuffer], silence: SilenceFlags) -> bool {
+    output.clear();
+    let mut all_silent = true;
+    for (index, input) in inputs.iter().enumerate() {
+        if silence.is_silent(index) {
+            continue;
+        }
+        output.mix(input);
+        all_silent = false;
+    }
+    all_silent
+}
@@ -0,0 +1,88 @@
+//! Recorded input passed from the audio thread to a disk writer
+
+use koto_core::{ChannelMap, SampleRate};
+use rtrb::{Consumer, Producer, RingBuffer};
+use std::fmt;
+
+/// Seconds of stereo input a take holds before the writer has to drain it
+const TAKE_BUFFER_SECONDS: usize = 10;
+
+/// Create both ends of a new take for `sample_rate`
+///
+/// Allocates, so call it off the audio thread; the input end goes to the
+/// callback with [`AudioCommand::StartRecording`](crate::AudioCommand::StartRecording).
+pub fn recording_take(sample_rate: SampleRate) -> (TakeInput, RecordingTake) {
+    take_with_frames(sample_rate.0 as usize * TAKE_BUFFER_SECONDS)
+}
+
+/// A take that holds `frames` stereo frames between drains
+pub(crate) fn take_with_frames(frames: usize) -> (TakeInput, RecordingTake) {
+    let (producer, consumer) = RingBuffer::new(frames * 2);
+    (
+        TakeInput { samples: producer },
+        RecordingTake { samples: consumer },
+    )
+}
+
+/// Audio thread end of a take
+pub struct TakeInput {
+    samples: Producer<f32>,
+}
+
+impl TakeInput {
+    /// Write interleaved input as stereo, routed through `map`
+    ///
+    /// Returns how many frames didn't fit because the writer fell behind.
+    pub(crate) fn write(&mut self, input: &[f32], channels: usize, map: &ChannelMap) -> usize {
+        let frames = input.len() / channels;
+        let fits = frames.min(self.samples.slots() / 2);
+        if let Ok(chunk) = self.samples.write_chunk_uninit(fits * 2) {
+            chunk.fill_from_iter(input.chunks_exact(channels).flat_map(|frame| {
+                [0, 1].map(|channel| {
+                    map.source(channel)
+                        .and_then(|source| frame.get(source))
+                        .copied()
+                        .unwrap_or(0.0)
+                })
+            }));
+        }
+        frames - fits
+    }
+}
+
+impl fmt::Debug for TakeInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TakeInput")
+            .field("free_samples", &self.samples.slots())
+            .finish()
+    }
+}
+
+/// Writer end of a take, drained by a disk-writer thread
+///
+/// Keep it until [`is_finished`](Self::is_finished) so the audio thread
+/// never frees the buffer.
+pub struct RecordingTake {
+    samples: Consumer<f32>,
+}
+
+impl RecordingTake {
+    /// Move the interleaved stereo samples recorded so far to the end of
+    /// `out`; returns how many were moved
+    pub fn drain_into(&mut self, out: &mut Vec<f32>) -> usize {
+        let available = self.samples.slots();
+        if let Ok(chunk) = self.samples.read_chunk(available) {
+            let (first, second) = chunk.as_slices();
+            out.extend_from_slice(first);
+            out.extend_from_slice(second);
+            chunk.commit_all();
+        }
+        available
+    }
+
+    /// Whether recording into this take has stopped; nothing more arrives
+    /// once it's drained
+    pub fn is_finished(&self) -> bool {
+        self.samples.is_abandoned()
+    }
+}
@@ -0,0 +1,156 @@
+//! Mute/solo state of tracks, owned by the audio thread
+
+use koto_core::TrackId;
+
+/// Number of tracks whose mute/solo state the callback can hold
+pub const MAX_TRACK_STATES: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+struct TrackFlags {
+    id: TrackId,
+    mute: bool,
+    solo: bool,
+}
+
+/// Fixed-capacity mute/solo table with solo-in-place resolution
+///
+/// Only tracks that are muted or soloed take a slot, so the capacity limits
+/// how many can be muted or soloed at once. Never allocates.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackStates {
+    entries: [TrackFlags; MAX_TRACK_STATES],
+    len: usize,
+}
+
+impl TrackStates {
+    pub fn new() -> Self {
+        Self {
+            entries: [TrackFlags {
+                id: TrackId(0),
+                mute: false,
+                solo: false,
+            }; MAX_TRACK_STATES],
+            len: 0,
+        }
+    }
+
+    /// Returns false if the table is full and the change was dropped
+    pub fn set_mute(&mut self, track: TrackId, mute: bool) -> bool {
+        self.update(track, |flags| flags.mute = mute)
+    }
+
+    /// Returns false if the table is full and the change was dropped
+    pub fn set_solo(&mut self, track: TrackId, solo: bool) -> bool {
+        self.update(track, |flags| flags.solo = solo)
+    }
+
+    pub fn is_muted(&self, track: TrackId) -> bool {
+        self.get(track).is_some_and(|flags| flags.mute)
+    }
+
+    pub fn is_soloed(&self, track: TrackId) -> bool {
+        self.get(track).is_some_and(|flags| flags.solo)
+    }
+
+    /// Whether any track is soloed
+    pub fn any_solo(&self) -> bool {
+        self.active().iter().any(|flags| flags.solo)
+    }
+
+    /// Whether a track should be heard
+    ///
+    /// While any track is soloed, only soloed tracks are heard, even if they
+    /// are also muted; otherwise every unmuted track is. Knows nothing of
+    /// mixer channels: the engine doesn't sum them yet, and when it does it
+    /// should mute them by [`Mixer::effective_mute`](koto_mixer::Mixer::effective_mute)
+    /// instead, which keeps solo-safe channels and soloed signal paths.
+    pub fn is_audible(&self, track: TrackId) -> bool {
+        if self.any_solo() {
+            self.is_soloed(track)
+        } else {
+            !self.is_muted(track)
+        }
+    }
+
+    fn active(&self) -> &[TrackFlags] {
+        &self.entries[..self.len]
+    }
+
+    fn get(&self, track: TrackId) -> Option<&TrackFlags> {
+        self.active().iter().find(|flags| flags.id == track)
+    }
+
+    fn update(&mut self, track: TrackId, change: impl FnOnce(&mut TrackFlags)) -> bool {
+        let index = match self.active().iter().position(|flags| flags.id == track) {
+            Some(index) => index,
+            None if self.len < MAX_TRACK_STATES => {
+                self.entries[self.len] = TrackFlags {
+                    id: track,
+                    mute: false,
+                    solo: false,
+                };
+                self.len += 1;
+                self.len - 1
+            }
+            None => return false,
+        };
+        change(&mut self.entries[index]);
+
+        // Tracks back in their default state free their slot
+        let flags = self.entries[index];
+        if !flags.mute && !flags.solo {
+            self.len -= 1;
+            self.entries.swap(index, self.len);
+        }
+        true
+    }
+}
+
+impl Default for TrackStates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solo_wins_over_mute() {
+        let (a, b, c) = (TrackId(1), TrackId(2), TrackId(3));
+        let mut states = TrackStates::new();
+        states.set_mute(a, true);
+        assert!(!states.is_audible(a));
+        assert!(states.is_audible(b));
+
+        // Soloing a muted track makes it the only one heard
+        states.set_solo(a, true);
+        assert!(states.is_audible(a));
+        assert!(!states.is_audible(b));
+
+        states.set_solo(c, true);
+        assert!(states.is_audible(c));
+        assert!(!states.is_audible(b));
+
+        // Unsoloing everything brings the mute back
+        states.set_solo(a, false);
+        states.set_solo(c, false);
+        assert!(!states.is_audible(a));
+        assert!(states.is_audible(b));
+    }
+
+    #[test]
+    fn test_overflow_is_reported() {
+        let mut states = TrackStates::new();
+        for id in 0..MAX_TRACK_STATES as u64 {
+            assert!(states.set_mute(TrackId(id), true));
+        }
+        assert!(!states.set_solo(TrackId(9999), true));
+        assert!(!states.any_solo());
+
+        // Clearing a track frees its slot
+        states.set_mute(TrackId(0), false);
+        assert!(states.set_solo(TrackId(9999), true));
+    }
+}
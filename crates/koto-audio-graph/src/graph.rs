@@ -1,6 +1,7 @@
 //! Audio graph structure
 
-use std::collections::HashMap;
+use koto_core::SilenceFlags;
+use std::collections::{HashMap, HashSet};
 
 /// Unique identifier for a node in the graph
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -62,6 +63,39 @@ impl AudioGraph {
         self.nodes.get(&id).map(|n| n.as_ref())
     }
 
+    /// Compute which input ports of a node receive only silence
+    ///
+    /// A port is silent when every node connected to it is in `silent`
+    /// (unconnected ports are silent).
+    pub fn input_silence(&self, id: NodeId, silent: &HashSet<NodeId>) -> SilenceFlags {
+        let Some(node) = self.nodes.get(&id) else {
+            return SilenceFlags::NONE;
+        };
+        let mut flags = SilenceFlags::all(node.input_count());
+        for connection in self.connections.iter().filter(|c| c.target == id) {
+            if !silent.contains(&connection.source) {
+                flags.set(connection.target_port as usize, false);
+            }
+        }
+        flags
+    }
+
+    /// Check whether a node can skip processing because all its inputs are silent
+    ///
+    /// Source nodes (no inputs) and nodes that produce output from silence,
+    /// such as reverb tails, are never skipped. A skipped node's output is
+    /// itself silent and should be added to `silent` by the caller.
+    pub fn can_skip(&self, id: NodeId, silent: &HashSet<NodeId>) -> bool {
+        let Some(node) = self.nodes.get(&id) else {
+            return false;
+        };
+        node.input_count() > 0
+            && !node.process_when_silent()
+            && self
+                .input_silence(id, silent)
+                .all_silent(node.input_count())
+    }
+
     /// Get a mutable reference to a node by ID
     pub fn get_node_mut(&mut self, id: NodeId) -> Option<&mut dyn AudioNode> {
         self.nodes.get_mut(&id).map(|n| n.as_mut())
@@ -84,4 +118,12 @@ pub trait AudioNode: Send + 'static {
 
     /// Process audio (placeholder - actual implementation in audio-engine)
     fn name(&self) -> &str;
+
+    /// Whether the node must run even when all inputs are silent
+    ///
+    /// Override for nodes with tails or internal sources (reverbs, delays,
+    /// oscillators).
+    fn process_when_silent(&self) -> bool {
+        false
+    }
 }
@@ -1,6 +1,10 @@
 //! Audio node implementations
 
 use crate::AudioNode;
+use koto_core::{
+    AudioBuffer, AudioProcessor, ParameterHandler, ParameterInfo, ProcessContext, Sample,
+    StereoProcessor,
+};
 
 /// A simple pass-through node
 pub struct PassthroughNode {
@@ -31,7 +35,35 @@ impl AudioNode for PassthroughNode {
     }
 }
 
+impl AudioProcessor for PassthroughNode {
+    fn process(
+        &mut self,
+        inputs: &[AudioBuffer],
+        outputs: &mut [AudioBuffer],
+        _context: &mut ProcessContext,
+    ) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output.copy_from(input);
+        }
+    }
+
+    fn input_channels(&self) -> usize {
+        self.inputs
+    }
+
+    fn output_channels(&self) -> usize {
+        self.outputs
+    }
+}
+
+/// Lowest gain setting in dB; treated as silence
+const GAIN_MIN_DB: f32 = -60.0;
+const GAIN_MAX_DB: f32 = 12.0;
+
 /// A gain node that adjusts volume
+///
+/// Wrap it in a [`StereoAdapter`](koto_core::StereoAdapter) to process
+/// audio buffers.
 pub struct GainNode {
     gain: f32,
 }
@@ -64,6 +96,67 @@ impl AudioNode for GainNode {
     }
 }
 
+impl GainNode {
+    /// Parameter ID of the gain, in dB
+    pub const PARAM_GAIN: u32 = 0;
+}
+
+impl ParameterHandler for GainNode {
+    fn get_parameter(&self, id: u32) -> Option<f32> {
+        (id == Self::PARAM_GAIN).then(|| {
+            if self.gain <= 0.0 {
+                GAIN_MIN_DB
+            } else {
+                (20.0 * self.gain.log10()).clamp(GAIN_MIN_DB, GAIN_MAX_DB)
+            }
+        })
+    }
+
+    fn set_parameter(&mut self, id: u32, value: f32) {
+        if id == Self::PARAM_GAIN {
+            let db = value.clamp(GAIN_MIN_DB, GAIN_MAX_DB);
+            self.gain = if db <= GAIN_MIN_DB {
+                0.0
+            } else {
+                10.0f32.powf(db / 20.0)
+            };
+        }
+    }
+
+    fn parameter_count(&self) -> usize {
+        1
+    }
+
+    fn parameter_info(&self, id: u32) -> Option<ParameterInfo> {
+        (id == Self::PARAM_GAIN).then(|| {
+            ParameterInfo::new(Self::PARAM_GAIN, "Gain", GAIN_MIN_DB, GAIN_MAX_DB, 0.0)
+                .with_unit("dB")
+                .with_skew(2.0)
+        })
+    }
+
+    fn value_to_text(&self, _id: u32, value: f32) -> String {
+        if value <= GAIN_MIN_DB {
+            "-inf dB".to_string()
+        } else {
+            format!("{value:.1} dB")
+        }
+    }
+}
+
+impl StereoProcessor for GainNode {
+    fn process_stereo(
+        &mut self,
+        left: &mut [Sample],
+        right: &mut [Sample],
+        _context: &ProcessContext,
+    ) {
+        for sample in left.iter_mut().chain(right.iter_mut()) {
+            *sample *= self.gain;
+        }
+    }
+}
+
 /// Master output node
 pub struct MasterNode;
 
@@ -80,3 +173,72 @@ impl AudioNode for MasterNode {
         "Master"
     }
 }
+
+impl AudioProcessor for MasterNode {
+    /// The engine reads the master input directly, so there is nothing to do
+    fn process(
+        &mut self,
+        _inputs: &[AudioBuffer],
+        _outputs: &mut [AudioBuffer],
+        _context: &mut ProcessContext,
+    ) {
+    }
+
+    fn input_channels(&self) -> usize {
+        2
+    }
+
+    fn output_channels(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{
+        ChannelCount, SamplePosition, SampleRate, StereoAdapter, Tempo, TimeSignature,
+    };
+
+    #[test]
+    fn test_gain_node_processes_with_context() {
+        let mut node = StereoAdapter::new(GainNode::new(0.5));
+        node.prepare(SampleRate::DVD_QUALITY, 64);
+
+        let input = AudioBuffer::from_samples(vec![1.0; 128], ChannelCount::STEREO);
+        let mut outputs = [AudioBuffer::new(ChannelCount::STEREO, 64)];
+        let mut midi_out = Vec::new();
+        let mut context = ProcessContext {
+            sample_rate: SampleRate::DVD_QUALITY,
+            tempo: Tempo::DEFAULT,
+            time_signature: TimeSignature::COMMON_TIME,
+            playhead: SamplePosition::ZERO,
+            frames: 64,
+            midi_events: &[],
+            midi_out: &mut midi_out,
+            is_playing: true,
+            is_recording: false,
+            input_latency: 0,
+            output_latency: 0,
+        };
+        node.process(&[input], &mut outputs, &mut context);
+        assert!(outputs[0].samples().iter().all(|&s| s == 0.5));
+        assert!(midi_out.is_empty());
+    }
+
+    #[test]
+    fn test_gain_parameter_in_db() {
+        let mut node = GainNode::new(1.0);
+        let infos: Vec<_> = node.parameter_infos().collect();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].name, "Gain");
+        assert_eq!(infos[0].unit, "dB");
+
+        let value = node.text_to_value(GainNode::PARAM_GAIN, "-6 dB").unwrap();
+        node.set_parameter(GainNode::PARAM_GAIN, value);
+        assert!((node.gain() - 0.501).abs() < 1e-3);
+        assert_eq!(node.value_to_text(GainNode::PARAM_GAIN, -6.0), "-6.0 dB");
+        node.set_parameter(GainNode::PARAM_GAIN, -100.0);
+        assert_eq!(node.gain(), 0.0);
+    }
+}
@@ -0,0 +1,60 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use koto_core::{deinterleave, interleave, Sample};
+
+const FRAMES: usize = 512;
+
+fn naive_deinterleave(src: &[Sample], dsts: &mut [Vec<Sample>]) {
+    let channels = dsts.len();
+    for frame in 0..src.len() / channels {
+        for (channel, dst) in dsts.iter_mut().enumerate() {
+            dst[frame] = src[frame * channels + channel];
+        }
+    }
+}
+
+fn naive_interleave(srcs: &[Vec<Sample>], dst: &mut [Sample]) {
+    let channels = srcs.len();
+    for frame in 0..dst.len() / channels {
+        for (channel, src) in srcs.iter().enumerate() {
+            dst[frame * channels + channel] = src[frame];
+        }
+    }
+}
+
+fn bench_deinterleave(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deinterleave");
+    for channels in [2, 4, 6, 8] {
+        let src: Vec<Sample> = (0..FRAMES * channels).map(|i| i as Sample).collect();
+        let mut naive = vec![vec![0.0; FRAMES]; channels];
+        let mut planar = vec![vec![0.0; FRAMES]; channels];
+        let mut dsts: Vec<&mut [Sample]> = planar.iter_mut().map(|c| c.as_mut_slice()).collect();
+
+        group.bench_with_input(BenchmarkId::new("naive", channels), &channels, |b, _| {
+            b.iter(|| naive_deinterleave(black_box(&src), &mut naive))
+        });
+        group.bench_with_input(BenchmarkId::new("koto", channels), &channels, |b, _| {
+            b.iter(|| deinterleave(black_box(&src), &mut dsts))
+        });
+    }
+    group.finish();
+}
+
+fn bench_interleave(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interleave");
+    for channels in [2, 4, 6, 8] {
+        let planar = vec![vec![0.5; FRAMES]; channels];
+        let srcs: Vec<&[Sample]> = planar.iter().map(|c| c.as_slice()).collect();
+        let mut dst = vec![0.0; FRAMES * channels];
+
+        group.bench_with_input(BenchmarkId::new("naive", channels), &channels, |b, _| {
+            b.iter(|| naive_interleave(black_box(&planar), &mut dst))
+        });
+        group.bench_with_input(BenchmarkId::new("koto", channels), &channels, |b, _| {
+            b.iter(|| interleave(black_box(&srcs), &mut dst))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_deinterleave, bench_interleave);
+criterion_main!(benches);
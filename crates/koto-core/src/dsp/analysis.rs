@@ -0,0 +1,231 @@
+//! Level analysis (peak, RMS, true peak)
+
+use crate::types::{AudioBuffer, ChannelCount, Sample};
+
+/// Compute the peak level of each channel of interleaved samples
+///
+/// Writes one value per channel into `peaks`; extra entries are left untouched.
+/// Does not allocate, so it is safe to call from the audio thread.
+pub fn interleaved_peaks(samples: &[Sample], channels: usize, peaks: &mut [Sample]) {
+    let written = channels.min(peaks.len());
+    if written == 0 {
+        return;
+    }
+    peaks[..written].fill(0.0);
+    for frame in samples.chunks_exact(channels) {
+        for (peak, sample) in peaks.iter_mut().zip(frame) {
+            *peak = peak.max(sample.abs());
+        }
+    }
+}
+
+/// Compute the RMS level of each channel of interleaved samples
+///
+/// Writes one value per channel into `rms`; extra entries are left untouched.
+/// Does not allocate, so it is safe to call from the audio thread.
+pub fn interleaved_rms(samples: &[Sample], channels: usize, rms: &mut [Sample]) {
+    let written = channels.min(rms.len());
+    if written == 0 {
+        return;
+    }
+    rms[..written].fill(0.0);
+
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return;
+    }
+
+    for (channel, value) in rms[..written].iter_mut().enumerate() {
+        let sum: f64 = samples[channel..]
+            .iter()
+            .step_by(channels)
+            .take(frames)
+            .map(|s| (*s as f64) * (*s as f64))
+            .sum();
+        *value = (sum / frames as f64).sqrt() as Sample;
+    }
+}
+
+impl AudioBuffer {
+    /// Get the peak level of each channel
+    pub fn peak_per_channel(&self) -> Vec<Sample> {
+        let channels = self.channels().as_usize();
+        let mut peaks = vec![0.0; channels];
+        interleaved_peaks(self.samples(), channels, &mut peaks);
+        peaks
+    }
+
+    /// Get the RMS level of each channel
+    pub fn rms_per_channel(&self) -> Vec<Sample> {
+        let channels = self.channels().as_usize();
+        let mut rms = vec![0.0; channels];
+        interleaved_rms(self.samples(), channels, &mut rms);
+        rms
+    }
+}
+
+/// Oversampling factor used for true-peak estimation
+const OVERSAMPLING: usize = 4;
+
+/// Number of FIR taps per polyphase branch
+const TAPS_PER_PHASE: usize = 12;
+
+/// True-peak (inter-sample peak) detector
+///
+/// Estimates the peak of the reconstructed analog signal by 4x oversampling
+/// each channel with a 48-tap windowed-sinc interpolator, as described in
+/// ITU-R BS.1770. Peaks are held until [`TruePeakDetector::reset`] is called.
+pub struct TruePeakDetector {
+    /// Polyphase coefficients, one row per output phase
+    coefficients: [[f32; TAPS_PER_PHASE]; OVERSAMPLING],
+    /// Input history per channel, stored as a circular buffer
+    history: Vec<Sample>,
+    /// Next write position in each channel's history
+    position: usize,
+    /// Held peak per channel
+    peaks: Vec<Sample>,
+    channels: ChannelCount,
+}
+
+impl TruePeakDetector {
+    pub fn new(channels: ChannelCount) -> Self {
+        let total_taps = OVERSAMPLING * TAPS_PER_PHASE;
+        let center = (total_taps / 2) as f64;
+        let mut coefficients = [[0.0f32; TAPS_PER_PHASE]; OVERSAMPLING];
+
+        for (phase, row) in coefficients.iter_mut().enumerate() {
+            let mut sum = 0.0;
+            for (k, coefficient) in row.iter_mut().enumerate() {
+                let n = (k * OVERSAMPLING + phase) as f64;
+                let x = (n - center) / OVERSAMPLING as f64;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                // Hann window spanning total_taps + 1 points
+                let window = 0.5 - 0.5 * (std::f64::consts::TAU * n / total_taps as f64).cos();
+                let value = sinc * window;
+                *coefficient = value as f32;
+                sum += value;
+            }
+            // Normalize each phase to unity DC gain
+            for coefficient in row.iter_mut() {
+                *coefficient = (*coefficient as f64 / sum) as f32;
+            }
+        }
+
+        Self {
+            coefficients,
+            history: vec![0.0; channels.as_usize() * TAPS_PER_PHASE],
+            position: 0,
+            peaks: vec![0.0; channels.as_usize()],
+            channels,
+        }
+    }
+
+    /// Feed interleaved samples into the detector
+    pub fn process_interleaved(&mut self, samples: &[Sample]) {
+        let channels = self.channels.as_usize();
+        if channels == 0 {
+            return;
+        }
+
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let history = &mut self.history[channel * TAPS_PER_PHASE..][..TAPS_PER_PHASE];
+                history[self.position] = sample;
+
+                let mut peak = self.peaks[channel];
+                for row in &self.coefficients {
+                    let mut acc = 0.0;
+                    for (k, coefficient) in row.iter().enumerate() {
+                        let index = (self.position + TAPS_PER_PHASE - k) % TAPS_PER_PHASE;
+                        acc += history[index] * coefficient;
+                    }
+                    peak = peak.max(acc.abs());
+                }
+                self.peaks[channel] = peak;
+            }
+            self.position = (self.position + 1) % TAPS_PER_PHASE;
+        }
+    }
+
+    /// Feed an audio buffer into the detector
+    ///
+    /// The buffer must have the channel count the detector was created with.
+    pub fn process(&mut self, buffer: &AudioBuffer) {
+        debug_assert_eq!(buffer.channels(), self.channels);
+        self.process_interleaved(buffer.samples());
+    }
+
+    /// Get the held true peak of a channel
+    pub fn peak(&self, channel: usize) -> Sample {
+        self.peaks.get(channel).copied().unwrap_or(0.0)
+    }
+
+    /// Get the held true peaks of all channels
+    pub fn peaks(&self) -> &[Sample] {
+        &self.peaks
+    }
+
+    /// Get the maximum true peak across all channels
+    pub fn true_peak(&self) -> Sample {
+        self.peaks.iter().copied().fold(0.0, f32::max)
+    }
+
+    /// Clear held peaks and the interpolation history
+    pub fn reset(&mut self) {
+        self.history.fill(0.0);
+        self.peaks.fill(0.0);
+        self.position = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peak_and_rms_per_channel() {
+        let buffer = AudioBuffer::from_samples(vec![0.5, -1.0, -0.5, 0.0], ChannelCount::STEREO);
+        assert_eq!(buffer.peak_per_channel(), vec![0.5, 1.0]);
+        let rms = buffer.rms_per_channel();
+        assert!((rms[0] - 0.5).abs() < 1e-6);
+        assert!((rms[1] - 0.5f32.sqrt()).abs() < 1e-6);
+
+        // Fewer outputs than channels still steps by whole frames
+        let samples = [0.5, 0.25, 0.125, -1.0, 0.5, 0.0];
+        let mut first = [0.0; 2];
+        interleaved_peaks(&samples, 3, &mut first);
+        assert_eq!(first, [1.0, 0.5]);
+        interleaved_rms(&samples, 3, &mut first);
+        assert!((first[0] - 0.625f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_true_peak_catches_inter_sample_overshoot() {
+        // A quarter-rate sine with a 45 degree phase offset never samples its crest
+        let amplitude = 0.97f32;
+        let samples: Vec<Sample> = (0..4800)
+            .map(|i| {
+                let phase = std::f32::consts::FRAC_PI_2 * i as f32 + std::f32::consts::FRAC_PI_4;
+                amplitude * phase.sin()
+            })
+            .collect();
+        let buffer = AudioBuffer::from_samples(samples, ChannelCount::MONO);
+
+        let sample_peak = buffer.peak();
+        assert!(sample_peak < 0.7);
+
+        let mut detector = TruePeakDetector::new(ChannelCount::MONO);
+        detector.process(&buffer);
+        let true_peak = detector.true_peak();
+        assert!(
+            (true_peak - amplitude).abs() < 0.03,
+            "true peak {} should be close to {}",
+            true_peak,
+            amplitude
+        );
+    }
+}
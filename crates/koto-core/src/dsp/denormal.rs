@@ -0,0 +1,157 @@
+//! Denormal protection and sample sanitization
+
+use crate::types::{AudioBuffer, Sample};
+
+/// MXCSR flush-to-zero bit
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+const MXCSR_FTZ: u32 = 1 << 15;
+
+/// MXCSR denormals-are-zero bit
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+const MXCSR_DAZ: u32 = 1 << 6;
+
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+fn read_mxcsr() -> u32 {
+    let mut csr = 0u32;
+    // SAFETY: stmxcsr only stores the SSE control register to the given address
+    unsafe {
+        std::arch::asm!(
+            "stmxcsr [{}]",
+            in(reg) &mut csr,
+            options(nostack, preserves_flags)
+        );
+    }
+    csr
+}
+
+#[cfg(any(
+    target_arch = "x86_64",
+    all(target_arch = "x86", target_feature = "sse")
+))]
+fn write_mxcsr(csr: u32) {
+    // SAFETY: ldmxcsr loads the SSE control register; we only toggle the
+    // FTZ/DAZ bits of a value previously read from it
+    unsafe {
+        std::arch::asm!(
+            "ldmxcsr [{}]",
+            in(reg) &csr,
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+}
+
+/// RAII guard that flushes denormal floats to zero on the current thread
+///
+/// On x86 this sets the FTZ and DAZ bits of MXCSR and restores the previous
+/// value when dropped. On other architectures it does nothing. Create one at
+/// the top of the audio callback so filters and reverbs decaying towards
+/// silence don't hit the slow denormal path.
+pub struct DenormalGuard {
+    #[cfg(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse")
+    ))]
+    previous: u32,
+}
+
+impl DenormalGuard {
+    #[cfg(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse")
+    ))]
+    pub fn new() -> Self {
+        let previous = read_mxcsr();
+        write_mxcsr(previous | MXCSR_FTZ | MXCSR_DAZ);
+        Self { previous }
+    }
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse")
+    )))]
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for DenormalGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        #[cfg(any(
+            target_arch = "x86_64",
+            all(target_arch = "x86", target_feature = "sse")
+        ))]
+        write_mxcsr(self.previous);
+    }
+}
+
+/// Replace NaN and infinite samples with silence and flush subnormals to zero
+///
+/// Returns the number of samples that were changed. Does not allocate, so it
+/// is safe to call from the audio thread.
+pub fn sanitize_samples(samples: &mut [Sample]) -> usize {
+    let mut fixed = 0;
+    for sample in samples {
+        if !sample.is_finite() || sample.is_subnormal() {
+            *sample = 0.0;
+            fixed += 1;
+        }
+    }
+    fixed
+}
+
+impl AudioBuffer {
+    /// Replace NaN and infinite samples with silence and flush subnormals
+    ///
+    /// Returns the number of samples that were changed.
+    pub fn sanitize(&mut self) -> usize {
+        sanitize_samples(self.samples_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ChannelCount;
+
+    #[test]
+    fn test_sanitize_stops_nan_reaching_master() {
+        let mut track = AudioBuffer::from_samples(
+            vec![f32::NAN, f32::INFINITY, f32::MIN_POSITIVE / 2.0, 0.0],
+            ChannelCount::STEREO,
+        );
+        assert_eq!(track.sanitize(), 3);
+
+        let mut master = AudioBuffer::new(ChannelCount::STEREO, 2);
+        master.mix(&track);
+        master.apply_gain(0.5);
+        assert!(master.samples().iter().all(|&s| s == 0.0));
+    }
+
+    #[test]
+    fn test_guard_flushes_denormals_while_alive() {
+        let tiny = std::hint::black_box(f32::MIN_POSITIVE);
+        {
+            let _guard = DenormalGuard::new();
+            let result = std::hint::black_box(tiny) / 4.0;
+            if cfg!(target_arch = "x86_64") {
+                assert_eq!(result, 0.0);
+            }
+        }
+        assert!((std::hint::black_box(tiny) / 4.0).is_subnormal());
+    }
+}
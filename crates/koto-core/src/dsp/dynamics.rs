@@ -0,0 +1,325 @@
+//! Output protection: soft clipping and brickwall limiting
+
+use crate::traits::{AudioProcessor, ProcessContext};
+use crate::types::{AudioBuffer, ChannelCount, Sample, SampleRate};
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// Transfer curve used by [`SoftClipper`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ClipCurve {
+    /// Hyperbolic tangent; smooth but colours signals well below full scale
+    #[default]
+    Tanh,
+    /// Cubic polynomial; unity gain at low levels, reaches ±1.0 at ±1.5 input
+    Cubic,
+}
+
+impl ClipCurve {
+    /// Apply the curve to a single sample
+    #[inline]
+    pub fn apply(&self, x: Sample) -> Sample {
+        match self {
+            ClipCurve::Tanh => x.tanh(),
+            ClipCurve::Cubic => {
+                let x = x.clamp(-1.5, 1.5);
+                x - 4.0 / 27.0 * x * x * x
+            }
+        }
+    }
+}
+
+/// Memoryless soft clipper that keeps output within ±1.0
+pub struct SoftClipper {
+    channels: ChannelCount,
+    curve: ClipCurve,
+}
+
+impl SoftClipper {
+    pub fn new(channels: ChannelCount, curve: ClipCurve) -> Self {
+        Self { channels, curve }
+    }
+
+    pub fn curve(&self) -> ClipCurve {
+        self.curve
+    }
+
+    pub fn set_curve(&mut self, curve: ClipCurve) {
+        self.curve = curve;
+    }
+
+    /// Clip samples in place
+    pub fn process_interleaved(&self, samples: &mut [Sample]) {
+        for sample in samples {
+            *sample = self.curve.apply(*sample);
+        }
+    }
+}
+
+impl AudioProcessor for SoftClipper {
+    fn process(
+        &mut self,
+        inputs: &[AudioBuffer],
+        outputs: &mut [AudioBuffer],
+        _context: &mut ProcessContext,
+    ) {
+        for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+            output.copy_from(input);
+            self.process_interleaved(output.samples_mut());
+        }
+    }
+
+    fn input_channels(&self) -> usize {
+        self.channels.as_usize()
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels.as_usize()
+    }
+}
+
+/// Lookahead brickwall limiter
+///
+/// Audio is delayed by the lookahead time so gain reduction can ramp in
+/// before a peak arrives; the output never exceeds the ceiling. Gain recovers
+/// exponentially according to the release time. All buffers are allocated
+/// on construction, so processing is real-time safe.
+pub struct BrickwallLimiter {
+    sample_rate: SampleRate,
+    channels: usize,
+    lookahead_ms: f32,
+    release_ms: f32,
+    /// Ceiling as linear gain
+    ceiling: f32,
+    /// Lookahead window in frames
+    window: usize,
+    release_coef: f32,
+    /// Interleaved audio delay line of `window - 1` frames
+    delay: Vec<Sample>,
+    delay_pos: usize,
+    /// Required gain after release smoothing
+    smoothed: f32,
+    /// Recent smoothed gains, for the minimum over the lookahead window
+    hold: Vec<f32>,
+    /// Recent held minimums, averaged to ramp the gain into a peak
+    ramp: Vec<f32>,
+    ramp_sum: f64,
+    window_pos: usize,
+    /// Lowest gain applied during the last processed block
+    block_min_gain: f32,
+}
+
+impl BrickwallLimiter {
+    /// Create a limiter with 2 ms lookahead, 50 ms release and a -0.3 dBFS ceiling
+    pub fn new(sample_rate: SampleRate, channels: ChannelCount) -> Self {
+        let mut limiter = Self {
+            sample_rate,
+            channels: channels.as_usize(),
+            lookahead_ms: 2.0,
+            release_ms: 50.0,
+            ceiling: db_to_gain(-0.3),
+            window: 1,
+            release_coef: 0.0,
+            delay: Vec::new(),
+            delay_pos: 0,
+            smoothed: 1.0,
+            hold: Vec::new(),
+            ramp: Vec::new(),
+            ramp_sum: 0.0,
+            window_pos: 0,
+            block_min_gain: 1.0,
+        };
+        limiter.configure();
+        limiter
+    }
+
+    /// Recompute sizes and coefficients; allocates
+    fn configure(&mut self) {
+        let fs = self.sample_rate.as_f64() as f32;
+        self.window = ((self.lookahead_ms * 0.001 * fs).round() as usize).max(1);
+        self.release_coef = (-1.0 / (self.release_ms * 0.001 * fs)).exp();
+        self.delay = vec![0.0; (self.window - 1) * self.channels];
+        self.hold = vec![1.0; self.window];
+        self.ramp = vec![1.0; self.window];
+        self.reset();
+    }
+
+    /// Set the lookahead time, clamped to 1–5 ms
+    ///
+    /// Reallocates the delay line; do not call from the audio thread.
+    pub fn set_lookahead_ms(&mut self, lookahead_ms: f32) {
+        self.lookahead_ms = lookahead_ms.clamp(1.0, 5.0);
+        self.configure();
+    }
+
+    pub fn lookahead_ms(&self) -> f32 {
+        self.lookahead_ms
+    }
+
+    /// Set the release time in milliseconds
+    pub fn set_release_ms(&mut self, release_ms: f32) {
+        self.release_ms = release_ms.max(1.0);
+        let fs = self.sample_rate.as_f64() as f32;
+        self.release_coef = (-1.0 / (self.release_ms * 0.001 * fs)).exp();
+    }
+
+    pub fn release_ms(&self) -> f32 {
+        self.release_ms
+    }
+
+    /// Set the output ceiling in dBFS (at most 0.0)
+    pub fn set_ceiling_db(&mut self, ceiling_db: f32) {
+        self.ceiling = db_to_gain(ceiling_db.min(0.0));
+    }
+
+    pub fn ceiling_db(&self) -> f32 {
+        20.0 * self.ceiling.log10()
+    }
+
+    /// Gain reduction in dB (positive) at the deepest point of the last block
+    pub fn gain_reduction_db(&self) -> f32 {
+        -20.0 * self.block_min_gain.log10()
+    }
+
+    /// Limit interleaved samples in place
+    pub fn process_interleaved(&mut self, samples: &mut [Sample]) {
+        let channels = self.channels;
+        if channels == 0 {
+            return;
+        }
+        let delay_frames = self.window - 1;
+        self.block_min_gain = 1.0;
+
+        for frame in samples.chunks_exact_mut(channels) {
+            let peak = frame.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            let required = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+
+            // Instant attack, exponential release
+            self.smoothed = if required < self.smoothed {
+                required
+            } else {
+                required + (self.smoothed - required) * self.release_coef
+            };
+
+            // Hold the minimum over the window, then average it so the gain
+            // reaches the required value exactly when the delayed peak arrives
+            self.hold[self.window_pos] = self.smoothed;
+            let held = self.hold.iter().copied().fold(1.0f32, f32::min);
+            self.ramp_sum += (held - self.ramp[self.window_pos]) as f64;
+            self.ramp[self.window_pos] = held;
+            self.window_pos = (self.window_pos + 1) % self.window;
+            if self.window_pos == 0 {
+                // Avoid drift in the running sum
+                self.ramp_sum = self.ramp.iter().map(|&g| g as f64).sum();
+            }
+            let gain = (self.ramp_sum / self.window as f64) as f32;
+            self.block_min_gain = self.block_min_gain.min(gain);
+
+            if delay_frames > 0 {
+                let delayed = &mut self.delay[self.delay_pos * channels..][..channels];
+                for (sample, stored) in frame.iter_mut().zip(delayed) {
+                    std::mem::swap(sample, stored);
+                }
+                self.delay_pos = (self.delay_pos + 1) % delay_frames;
+            }
+
+            for sample in frame {
+                *sample = (*sample * gain).clamp(-self.ceiling, self.ceiling);
+            }
+        }
+    }
+}
+
+impl AudioProcessor for BrickwallLimiter {
+    fn process(
+        &mut self,
+        inputs: &[AudioBuffer],
+        outputs: &mut [AudioBuffer],
+        _context: &mut ProcessContext,
+    ) {
+        if let (Some(input), Some(output)) = (inputs.first(), outputs.first_mut()) {
+            output.copy_from(input);
+            self.process_interleaved(output.samples_mut());
+        }
+    }
+
+    fn input_channels(&self) -> usize {
+        self.channels
+    }
+
+    fn output_channels(&self) -> usize {
+        self.channels
+    }
+
+    fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+        self.configure();
+    }
+
+    fn reset(&mut self) {
+        self.delay.fill(0.0);
+        self.delay_pos = 0;
+        self.smoothed = 1.0;
+        self.hold.fill(1.0);
+        self.ramp.fill(1.0);
+        self.ramp_sum = self.window as f64;
+        self.window_pos = 0;
+        self.block_min_gain = 1.0;
+    }
+
+    fn latency(&self) -> usize {
+        self.window - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_output_never_exceeds_ceiling() {
+        let sample_rate = SampleRate::DVD_QUALITY;
+        let mut limiter = BrickwallLimiter::new(sample_rate, ChannelCount::STEREO);
+        limiter.set_ceiling_db(0.0);
+        assert_eq!(limiter.latency(), 95);
+
+        // Loud sine with an isolated spike, processed in small blocks
+        let mut samples: Vec<Sample> = (0..4800)
+            .flat_map(|i| {
+                let s = 1.8 * (std::f32::consts::TAU * 220.0 * i as f32 / 48000.0).sin();
+                [s, -s]
+            })
+            .collect();
+        samples[2001] = 6.0;
+        for block in samples.chunks_mut(2 * 64) {
+            limiter.process_interleaved(block);
+        }
+
+        assert!(samples.iter().all(|s| s.abs() <= 1.0));
+        assert!(limiter.gain_reduction_db() > 3.0);
+    }
+
+    #[test]
+    fn test_quiet_signal_passes_through_delayed() {
+        let mut limiter = BrickwallLimiter::new(SampleRate::DVD_QUALITY, ChannelCount::MONO);
+        let delay = limiter.latency();
+        let mut samples = vec![0.0; 256];
+        samples[0] = 0.5;
+        limiter.process_interleaved(&mut samples);
+        assert_eq!(samples[delay], 0.5);
+        assert_eq!(limiter.gain_reduction_db(), 0.0);
+
+        let clipper = SoftClipper::new(ChannelCount::MONO, ClipCurve::Cubic);
+        let mut loud = [4.0, -4.0, 0.01, 1.0];
+        clipper.process_interleaved(&mut loud);
+        assert_eq!(&loud[..2], &[1.0, -1.0]);
+        assert!((loud[2] - 0.01).abs() < 1e-6);
+        assert!(loud[3] > 0.85 && loud[3] < 1.0);
+    }
+}
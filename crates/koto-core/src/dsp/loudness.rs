@@ -0,0 +1,318 @@
+//! Loudness measurement (ITU-R BS.1770-4 / EBU R128)
+
+use crate::types::{AudioBuffer, ChannelCount, Sample, SampleRate};
+use std::collections::VecDeque;
+
+/// Loudness gating blocks advance in 100 ms steps
+const SUB_BLOCK_SECONDS: f64 = 0.1;
+
+/// Momentary loudness window: 400 ms (4 sub-blocks)
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+
+/// Short-term loudness window: 3 s (30 sub-blocks)
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+
+/// Absolute gating threshold in LUFS
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+
+/// Relative gating threshold in LU below the ungated loudness
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Convert a weighted mean square to LUFS
+fn energy_to_lufs(energy: f64) -> f64 {
+    if energy <= 0.0 {
+        f64::NEG_INFINITY
+    } else {
+        -0.691 + 10.0 * energy.log10()
+    }
+}
+
+/// Convert LUFS back to a weighted mean square
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10.0_f64.powf((lufs + 0.691) / 10.0)
+}
+
+/// Direct form I biquad used for K-weighting
+#[derive(Debug, Clone, Copy, Default)]
+struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    /// High-shelf stage modelling the acoustic effect of the head
+    fn pre_filter(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let vh = 10.0_f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b0: (vh + vb * k / q + k * k) / a0,
+            b1: 2.0 * (k * k - vh) / a0,
+            b2: (vh - vb * k / q + k * k) / a0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Default::default()
+        }
+    }
+
+    /// RLB high-pass stage
+    fn rlb_filter(sample_rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b0: 1.0,
+            b1: -2.0,
+            b2: 1.0,
+            a1: 2.0 * (k * k - 1.0) / a0,
+            a2: (1.0 - k / q + k * k) / a0,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+
+    fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.x2 = 0.0;
+        self.y1 = 0.0;
+        self.y2 = 0.0;
+    }
+}
+
+/// Loudness meter implementing ITU-R BS.1770-4
+///
+/// Audio is K-weighted per channel and analysed in 400 ms blocks with 75%
+/// overlap. Momentary (400 ms) and short-term (3 s) loudness are ungated;
+/// integrated loudness applies the -70 LUFS absolute and -10 LU relative
+/// gates over everything pushed since the last reset.
+///
+/// All loudness values are in LUFS and are `f64::NEG_INFINITY` until enough
+/// audio has been measured.
+pub struct LoudnessMeter {
+    channels: ChannelCount,
+    /// K-weighting filters (pre-filter, RLB) per channel
+    filters: Vec<[Biquad; 2]>,
+    /// Channel weights (1.0 for front channels, 1.41 for surrounds)
+    weights: Vec<f64>,
+    /// Samples per 100 ms sub-block
+    sub_block_len: usize,
+    /// Frames accumulated in the current sub-block
+    sub_block_pos: usize,
+    /// Sum of squared K-weighted samples per channel in the current sub-block
+    sub_block_sums: Vec<f64>,
+    /// Weighted mean square of the most recent sub-blocks (newest last)
+    recent: VecDeque<f64>,
+    /// Energies of all completed 400 ms gating blocks
+    blocks: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(sample_rate: SampleRate, channels: ChannelCount) -> Self {
+        let fs = sample_rate.as_f64();
+        let count = channels.as_usize();
+
+        Self {
+            channels,
+            filters: vec![[Biquad::pre_filter(fs), Biquad::rlb_filter(fs)]; count],
+            weights: vec![1.0; count],
+            sub_block_len: ((fs * SUB_BLOCK_SECONDS).round() as usize).max(1),
+            sub_block_pos: 0,
+            sub_block_sums: vec![0.0; count],
+            recent: VecDeque::with_capacity(SHORT_TERM_SUB_BLOCKS),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Set the weighting of a channel
+    ///
+    /// BS.1770 uses 1.0 for left, right and centre, 1.41 for surround
+    /// channels, and 0.0 to exclude the LFE channel.
+    pub fn set_channel_weight(&mut self, channel: usize, weight: f64) {
+        if let Some(w) = self.weights.get_mut(channel) {
+            *w = weight;
+        }
+    }
+
+    /// Push a block of audio
+    ///
+    /// The buffer must have the channel count the meter was created with.
+    pub fn push(&mut self, buffer: &AudioBuffer) {
+        debug_assert_eq!(buffer.channels(), self.channels);
+        self.push_interleaved(buffer.samples());
+    }
+
+    /// Push interleaved samples
+    pub fn push_interleaved(&mut self, samples: &[Sample]) {
+        let channels = self.channels.as_usize();
+        if channels == 0 {
+            return;
+        }
+
+        for frame in samples.chunks_exact(channels) {
+            for (channel, &sample) in frame.iter().enumerate() {
+                let [pre, rlb] = &mut self.filters[channel];
+                let weighted = rlb.process(pre.process(sample as f64));
+                self.sub_block_sums[channel] += weighted * weighted;
+            }
+
+            self.sub_block_pos += 1;
+            if self.sub_block_pos == self.sub_block_len {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        let len = self.sub_block_len as f64;
+        let energy: f64 = self
+            .sub_block_sums
+            .iter()
+            .zip(&self.weights)
+            .map(|(sum, weight)| weight * sum / len)
+            .sum();
+
+        self.sub_block_sums.fill(0.0);
+        self.sub_block_pos = 0;
+
+        if self.recent.len() == SHORT_TERM_SUB_BLOCKS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(energy);
+
+        if self.recent.len() >= MOMENTARY_SUB_BLOCKS {
+            self.blocks.push(self.window_energy(MOMENTARY_SUB_BLOCKS));
+        }
+    }
+
+    /// Mean energy of the newest `sub_blocks` sub-blocks
+    fn window_energy(&self, sub_blocks: usize) -> f64 {
+        self.recent.iter().rev().take(sub_blocks).sum::<f64>() / sub_blocks as f64
+    }
+
+    /// Momentary loudness over the last 400 ms
+    pub fn momentary_lufs(&self) -> f64 {
+        if self.recent.len() < MOMENTARY_SUB_BLOCKS {
+            return f64::NEG_INFINITY;
+        }
+        energy_to_lufs(self.window_energy(MOMENTARY_SUB_BLOCKS))
+    }
+
+    /// Short-term loudness over the last 3 s
+    pub fn short_term_lufs(&self) -> f64 {
+        if self.recent.len() < SHORT_TERM_SUB_BLOCKS {
+            return f64::NEG_INFINITY;
+        }
+        energy_to_lufs(self.window_energy(SHORT_TERM_SUB_BLOCKS))
+    }
+
+    /// Gated integrated loudness over everything pushed so far
+    pub fn integrated_lufs(&self) -> f64 {
+        let absolute_gate = lufs_to_energy(ABSOLUTE_GATE_LUFS);
+        let (sum, count) = self
+            .blocks
+            .iter()
+            .filter(|&&e| e > absolute_gate)
+            .fold((0.0, 0usize), |(sum, count), e| (sum + e, count + 1));
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+
+        let relative_gate = lufs_to_energy(energy_to_lufs(sum / count as f64) + RELATIVE_GATE_LU);
+        let gate = absolute_gate.max(relative_gate);
+        let (sum, count) = self
+            .blocks
+            .iter()
+            .filter(|&&e| e > gate)
+            .fold((0.0, 0usize), |(sum, count), e| (sum + e, count + 1));
+        if count == 0 {
+            return f64::NEG_INFINITY;
+        }
+
+        energy_to_lufs(sum / count as f64)
+    }
+
+    /// Clear all measurements and filter state
+    pub fn reset(&mut self) {
+        for [pre, rlb] in &mut self.filters {
+            pre.reset();
+            rlb.reset();
+        }
+        self.sub_block_pos = 0;
+        self.sub_block_sums.fill(0.0);
+        self.recent.clear();
+        self.blocks.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stereo_sine(freq: f64, dbfs: f64, seconds: f64, sample_rate: SampleRate) -> AudioBuffer {
+        let amplitude = 10.0_f64.powf(dbfs / 20.0);
+        let frames = (seconds * sample_rate.as_f64()) as usize;
+        let mut samples = Vec::with_capacity(frames * 2);
+        for i in 0..frames {
+            let t = i as f64 / sample_rate.as_f64();
+            let s = (amplitude * (std::f64::consts::TAU * freq * t).sin()) as Sample;
+            samples.push(s);
+            samples.push(s);
+        }
+        AudioBuffer::from_samples(samples, ChannelCount::STEREO)
+    }
+
+    #[test]
+    fn test_ebu_3341_stereo_sine_reads_minus_23_lufs() {
+        // EBU Tech 3341 case 1: 1 kHz stereo sine at -23 dBFS measures -23 LUFS
+        let sample_rate = SampleRate::DVD_QUALITY;
+        let mut meter = LoudnessMeter::new(sample_rate, ChannelCount::STEREO);
+        meter.push(&stereo_sine(1000.0, -23.0, 20.0, sample_rate));
+
+        assert!((meter.integrated_lufs() + 23.0).abs() < 0.1);
+        assert!((meter.momentary_lufs() + 23.0).abs() < 0.1);
+        assert!((meter.short_term_lufs() + 23.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_silence_is_gated_out() {
+        // EBU Tech 3341 case 3: quiet passages below the relative gate are ignored
+        let sample_rate = SampleRate::DVD_QUALITY;
+        let mut meter = LoudnessMeter::new(sample_rate, ChannelCount::STEREO);
+        meter.push(&stereo_sine(1000.0, -36.0, 10.0, sample_rate));
+        meter.push(&stereo_sine(1000.0, -23.0, 60.0, sample_rate));
+        meter.push(&stereo_sine(1000.0, -36.0, 10.0, sample_rate));
+
+        assert!((meter.integrated_lufs() + 23.0).abs() < 0.1);
+        assert_eq!(
+            LoudnessMeter::new(sample_rate, ChannelCount::STEREO).integrated_lufs(),
+            f64::NEG_INFINITY
+        );
+    }
+}
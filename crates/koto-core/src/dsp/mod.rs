@@ -0,0 +1,19 @@
+//! Signal processing utilities for Koto DAW
+
+mod analysis;
+mod denormal;
+mod dynamics;
+mod loudness;
+mod resample;
+mod smoothing;
+mod spectrum;
+mod stereo;
+
+pub use analysis::*;
+pub use denormal::*;
+pub use dynamics::*;
+pub use loudness::*;
+pub use resample::*;
+pub use smoothing::*;
+pub use spectrum::*;
+pub use stereo::*;
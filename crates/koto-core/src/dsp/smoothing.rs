@@ -0,0 +1,207 @@
+//! Parameter smoothing to avoid zipper noise
+
+use crate::types::SampleRate;
+use std::ops::{Add, Mul, Sub};
+
+/// Remaining distance to the target after the ramp time in exponential mode
+/// (-60 dB), at which point the value snaps to the target
+const EXPONENTIAL_RESIDUAL: f64 = 1e-3;
+
+/// Numeric types that can be smoothed
+pub trait Smoothable:
+    Copy + PartialEq + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    fn from_f64(value: f64) -> Self;
+}
+
+impl Smoothable for f32 {
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Smoothable for f64 {
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// How a [`SmoothedValue`] moves towards its target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmoothingMode {
+    /// Constant step per frame, arriving exactly after the ramp time
+    #[default]
+    Linear,
+    /// One-pole approach; covers 99.9% of the distance in the ramp time,
+    /// then snaps to the target
+    Exponential,
+}
+
+/// A value that glides to a new target over a fixed time
+///
+/// Call [`set_target`](Self::set_target) from parameter changes and
+/// [`next`](Self::next) once per frame in the audio callback. Neither
+/// allocates. The value never overshoots the target.
+#[derive(Debug, Clone)]
+pub struct SmoothedValue<T: Smoothable = f32> {
+    current: T,
+    target: T,
+    mode: SmoothingMode,
+    /// Ramp length in frames
+    ramp_frames: usize,
+    /// Frames left until the target is reached
+    remaining: usize,
+    /// Per-frame increment (linear mode)
+    step: T,
+    /// Fraction of the remaining distance kept each frame (exponential mode)
+    retain: T,
+}
+
+impl<T: Smoothable> SmoothedValue<T> {
+    /// Create a smoothed value starting at `initial`
+    pub fn new(initial: T, sample_rate: SampleRate, ramp_ms: f32, mode: SmoothingMode) -> Self {
+        let mut value = Self {
+            current: initial,
+            target: initial,
+            mode,
+            ramp_frames: 0,
+            remaining: 0,
+            step: T::from_f64(0.0),
+            retain: T::from_f64(0.0),
+        };
+        value.set_ramp(sample_rate, ramp_ms);
+        value
+    }
+
+    /// Change the ramp time; finishes any ramp in progress
+    pub fn set_ramp(&mut self, sample_rate: SampleRate, ramp_ms: f32) {
+        self.ramp_frames =
+            (sample_rate.as_f64() * ramp_ms.max(0.0) as f64 / 1000.0).round() as usize;
+        let frames = self.ramp_frames.max(1) as f64;
+        self.retain = T::from_f64(EXPONENTIAL_RESIDUAL.powf(1.0 / frames));
+        self.set_immediate(self.target);
+    }
+
+    pub fn mode(&self) -> SmoothingMode {
+        self.mode
+    }
+
+    /// Ramp length in frames
+    pub fn ramp_frames(&self) -> usize {
+        self.ramp_frames
+    }
+
+    /// Start gliding towards a new target
+    pub fn set_target(&mut self, target: T) {
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        if self.ramp_frames == 0 {
+            self.current = target;
+            self.remaining = 0;
+            return;
+        }
+        self.remaining = self.ramp_frames;
+        self.step = (target - self.current) * T::from_f64(1.0 / self.ramp_frames as f64);
+    }
+
+    /// Jump to a value without smoothing
+    pub fn set_immediate(&mut self, value: T) {
+        self.current = value;
+        self.target = value;
+        self.remaining = 0;
+    }
+
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    /// The value that the next call to [`next`](Self::next) will move from
+    pub fn current(&self) -> T {
+        self.current
+    }
+
+    /// Whether a ramp is in progress
+    pub fn is_smoothing(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Advance by one frame and return the new value
+    #[inline]
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> T {
+        if self.remaining == 0 {
+            return self.current;
+        }
+        self.remaining -= 1;
+        self.current = if self.remaining == 0 {
+            self.target
+        } else {
+            match self.mode {
+                SmoothingMode::Linear => self.current + self.step,
+                SmoothingMode::Exponential => {
+                    self.target + (self.current - self.target) * self.retain
+                }
+            }
+        };
+        self.current
+    }
+
+    /// Advance by `frames` frames and return the value at the end of the block
+    ///
+    /// Useful when a parameter only needs updating once per block.
+    pub fn next_block(&mut self, frames: usize) -> T {
+        if frames >= self.remaining {
+            self.remaining = 0;
+            self.current = self.target;
+            return self.current;
+        }
+        for _ in 0..frames {
+            self.next();
+        }
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reaches_target_in_ramp_time_without_overshoot() {
+        let sample_rate = SampleRate::DVD_QUALITY;
+        for mode in [SmoothingMode::Linear, SmoothingMode::Exponential] {
+            // 10 ms at 48 kHz = 480 frames
+            let mut value = SmoothedValue::new(0.0f32, sample_rate, 10.0, mode);
+            value.set_target(1.0);
+            assert!(value.is_smoothing());
+
+            let mut previous = 0.0;
+            for _ in 0..479 {
+                let v = value.next();
+                assert!(v >= previous && v < 1.0);
+                previous = v;
+            }
+            assert_eq!(value.next(), 1.0);
+            assert!(!value.is_smoothing());
+        }
+    }
+
+    #[test]
+    fn test_retarget_mid_ramp_settles_on_new_target() {
+        let mut value =
+            SmoothedValue::new(1.0f32, SampleRate::DVD_QUALITY, 5.0, SmoothingMode::Linear);
+        value.set_target(0.0);
+        value.next_block(100);
+        value.set_target(0.5);
+        let mut previous = value.current();
+        assert!(previous > 0.5);
+        while value.is_smoothing() {
+            let v = value.next();
+            assert!(v <= previous && v >= 0.5);
+            previous = v;
+        }
+        assert_eq!(value.current(), 0.5);
+    }
+}
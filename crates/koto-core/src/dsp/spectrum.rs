@@ -0,0 +1,197 @@
+//! Windowed FFT spectrum analysis
+
+use crate::types::{AudioBuffer, Sample, SampleRate};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+use std::sync::Arc;
+
+/// Lowest level reported by [`SpectrumAnalyzer::bins_db`]
+pub const SPECTRUM_FLOOR_DB: f32 = -120.0;
+
+/// Spectrum analyzer for display purposes
+///
+/// Accumulates incoming samples into frames of `fft_size`, applies a Hann
+/// window and transforms each frame with 50% overlap. Bin magnitudes are
+/// exponentially averaged across frames according to the smoothing factor.
+/// Multi-channel input is summed to mono before analysis.
+pub struct SpectrumAnalyzer {
+    fft: Arc<dyn Fft<f32>>,
+    fft_size: usize,
+    window: Vec<f32>,
+    /// Amplitude correction for the window's coherent gain
+    window_gain: f32,
+    /// Pending input samples
+    input: Vec<Sample>,
+    filled: usize,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// Smoothed linear magnitudes, one per bin
+    magnitudes: Vec<f32>,
+    /// Averaging factor (0.0 = no smoothing, close to 1.0 = heavy smoothing)
+    smoothing: f32,
+}
+
+impl SpectrumAnalyzer {
+    /// Create an analyzer
+    ///
+    /// `fft_size` is rounded up to the next power of two (minimum 2).
+    pub fn new(fft_size: usize) -> Self {
+        let fft_size = fft_size.max(2).next_power_of_two();
+        let fft = FftPlanner::new().plan_fft_forward(fft_size);
+        let window: Vec<f32> = (0..fft_size)
+            .map(|i| {
+                let phase = std::f32::consts::TAU * i as f32 / fft_size as f32;
+                0.5 - 0.5 * phase.cos()
+            })
+            .collect();
+        let window_gain = 2.0 / window.iter().sum::<f32>();
+        let scratch_len = fft.get_inplace_scratch_len();
+
+        Self {
+            fft,
+            fft_size,
+            window,
+            window_gain,
+            input: vec![0.0; fft_size],
+            filled: 0,
+            buffer: vec![Complex::default(); fft_size],
+            scratch: vec![Complex::default(); scratch_len],
+            magnitudes: vec![0.0; fft_size / 2 + 1],
+            smoothing: 0.5,
+        }
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.fft_size
+    }
+
+    /// Number of frequency bins (DC to Nyquist inclusive)
+    pub fn bin_count(&self) -> usize {
+        self.magnitudes.len()
+    }
+
+    /// Set the averaging factor between successive frames (clamped to 0.0..=0.99)
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.clamp(0.0, 0.99);
+    }
+
+    pub fn smoothing(&self) -> f32 {
+        self.smoothing
+    }
+
+    /// Push mono samples
+    pub fn push(&mut self, samples: &[Sample]) {
+        for &sample in samples {
+            self.push_sample(sample);
+        }
+    }
+
+    /// Push interleaved samples, summing channels to mono
+    pub fn push_interleaved(&mut self, samples: &[Sample], channels: usize) {
+        if channels == 0 {
+            return;
+        }
+        let scale = 1.0 / channels as f32;
+        for frame in samples.chunks_exact(channels) {
+            self.push_sample(frame.iter().sum::<f32>() * scale);
+        }
+    }
+
+    /// Push an audio buffer, summing channels to mono
+    pub fn push_buffer(&mut self, buffer: &AudioBuffer) {
+        self.push_interleaved(buffer.samples(), buffer.channels().as_usize());
+    }
+
+    fn push_sample(&mut self, sample: Sample) {
+        self.input[self.filled] = sample;
+        self.filled += 1;
+        if self.filled == self.fft_size {
+            self.analyze_frame();
+            // Keep the second half for 50% overlap with the next frame
+            let hop = self.fft_size / 2;
+            self.input.copy_within(hop.., 0);
+            self.filled = self.fft_size - hop;
+        }
+    }
+
+    fn analyze_frame(&mut self) {
+        for ((out, &sample), &w) in self.buffer.iter_mut().zip(&self.input).zip(&self.window) {
+            *out = Complex::new(sample * w, 0.0);
+        }
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        let smoothing = self.smoothing;
+        for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.buffer) {
+            let value = bin.norm() * self.window_gain;
+            *magnitude = smoothing * *magnitude + (1.0 - smoothing) * value;
+        }
+    }
+
+    /// Smoothed linear magnitude per bin (1.0 = full-scale sine)
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+
+    /// Smoothed magnitude per bin in dBFS, floored at [`SPECTRUM_FLOOR_DB`]
+    pub fn bins_db(&self) -> Vec<f32> {
+        self.magnitudes
+            .iter()
+            .map(|&m| {
+                if m > 0.0 {
+                    (20.0 * m.log10()).max(SPECTRUM_FLOOR_DB)
+                } else {
+                    SPECTRUM_FLOOR_DB
+                }
+            })
+            .collect()
+    }
+
+    /// Center frequency of a bin in Hz
+    pub fn bin_frequency(&self, bin: usize, sample_rate: SampleRate) -> f64 {
+        bin as f64 * sample_rate.as_f64() / self.fft_size as f64
+    }
+
+    /// Nearest bin for a frequency in Hz, clamped to the valid range
+    pub fn frequency_to_bin(&self, frequency: f64, sample_rate: SampleRate) -> usize {
+        let bin = (frequency * self.fft_size as f64 / sample_rate.as_f64()).round();
+        (bin.max(0.0) as usize).min(self.bin_count() - 1)
+    }
+
+    /// Clear accumulated input and averaged magnitudes
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.filled = 0;
+        self.magnitudes.fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sine_peak_lands_in_expected_bin() {
+        let sample_rate = SampleRate::DVD_QUALITY;
+        let mut analyzer = SpectrumAnalyzer::new(2048);
+
+        // Feed in blocks smaller than the FFT size
+        let samples: Vec<Sample> = (0..48000)
+            .map(|i| {
+                (std::f64::consts::TAU * 1000.0 * i as f64 / sample_rate.as_f64()).sin() as f32
+            })
+            .collect();
+        for block in samples.chunks(256) {
+            analyzer.push(block);
+        }
+
+        let bins = analyzer.bins_db();
+        let peak_bin = (0..bins.len())
+            .max_by(|&a, &b| bins[a].total_cmp(&bins[b]))
+            .unwrap();
+        let expected = analyzer.frequency_to_bin(1000.0, sample_rate);
+        assert!(peak_bin.abs_diff(expected) <= 1);
+        assert!((analyzer.bin_frequency(peak_bin, sample_rate) - 1000.0).abs() < 24.0);
+        assert!(bins[peak_bin] > -3.0);
+    }
+}
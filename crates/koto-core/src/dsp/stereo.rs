@@ -0,0 +1,117 @@
+//! Stereo width, mid/side conversion and phase correlation
+
+use crate::types::{AudioBuffer, ChannelCount, Sample, StereoFrame};
+
+impl StereoFrame {
+    /// Convert to mid/side, returned as `(mid, side)`
+    ///
+    /// `mid = (L + R) / 2` and `side = (L - R) / 2`, so converting back with
+    /// [`from_mid_side`](Self::from_mid_side) is lossless.
+    pub fn to_mid_side(&self) -> (Sample, Sample) {
+        (
+            (self.left + self.right) * 0.5,
+            (self.left - self.right) * 0.5,
+        )
+    }
+
+    /// Build a frame from mid/side components
+    pub fn from_mid_side(mid: Sample, side: Sample) -> Self {
+        Self {
+            left: mid + side,
+            right: mid - side,
+        }
+    }
+}
+
+/// Phase correlation of interleaved stereo samples
+///
+/// Returns a value from -1.0 (fully out of phase) through 0.0 (uncorrelated)
+/// to +1.0 (mono). Blocks where either channel is silent read 0.0.
+/// Does not allocate, so it is safe to call from the audio thread.
+pub fn stereo_correlation(samples: &[Sample]) -> f32 {
+    let mut lr = 0.0f64;
+    let mut ll = 0.0f64;
+    let mut rr = 0.0f64;
+    for frame in samples.chunks_exact(2) {
+        let (l, r) = (frame[0] as f64, frame[1] as f64);
+        lr += l * r;
+        ll += l * l;
+        rr += r * r;
+    }
+
+    let denominator = (ll * rr).sqrt();
+    if denominator <= f64::EPSILON {
+        0.0
+    } else {
+        (lr / denominator).clamp(-1.0, 1.0) as f32
+    }
+}
+
+impl AudioBuffer {
+    /// Scale the stereo image via mid/side
+    ///
+    /// 0.0 collapses to mono, 1.0 leaves the signal unchanged and values
+    /// above 1.0 widen it (up to 2.0 is typical). Does nothing unless the
+    /// buffer is stereo.
+    pub fn apply_stereo_width(&mut self, width: f32) {
+        if self.channels() != ChannelCount::STEREO {
+            return;
+        }
+        let width = width.max(0.0);
+        for frame in self.samples_mut().chunks_exact_mut(2) {
+            let (mid, side) = StereoFrame::new(frame[0], frame[1]).to_mid_side();
+            let widened = StereoFrame::from_mid_side(mid, side * width);
+            frame[0] = widened.left;
+            frame[1] = widened.right;
+        }
+    }
+
+    /// Phase correlation between left and right (stereo buffers only)
+    pub fn stereo_correlation(&self) -> Option<f32> {
+        (self.channels() == ChannelCount::STEREO).then(|| stereo_correlation(self.samples()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_signal() -> AudioBuffer {
+        let samples = (0..256)
+            .flat_map(|i| {
+                let t = i as f32 / 48.0;
+                [t.sin(), 0.5 * (t * 1.7).sin()]
+            })
+            .collect();
+        AudioBuffer::from_samples(samples, ChannelCount::STEREO)
+    }
+
+    #[test]
+    fn test_zero_width_is_mono() {
+        let mut buffer = test_signal();
+        buffer.apply_stereo_width(0.0);
+        for frame in buffer.samples().chunks_exact(2) {
+            assert_eq!(frame[0], frame[1]);
+        }
+        assert!((buffer.stereo_correlation().unwrap() - 1.0).abs() < 1e-6);
+
+        let original = test_signal();
+        let mut unchanged = test_signal();
+        unchanged.apply_stereo_width(1.0);
+        for (a, b) in unchanged.samples().iter().zip(original.samples()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_inverted_polarity_correlation() {
+        let samples: Vec<Sample> = (0..512)
+            .flat_map(|i| {
+                let s = (i as f32 * 0.05).sin();
+                [s, -s]
+            })
+            .collect();
+        assert!((stereo_correlation(&samples) + 1.0).abs() < 1e-6);
+        assert_eq!(stereo_correlation(&[0.0; 64]), 0.0);
+    }
+}
@@ -2,14 +2,33 @@
 
 use thiserror::Error;
 
+/// Boxed underlying error, kept as the [`source`](std::error::Error::source)
+/// of a [`KotoError`]
+pub type BoxedError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
 /// Main error type for Koto operations
 #[derive(Error, Debug)]
 pub enum KotoError {
-    #[error("Audio device error: {0}")]
-    AudioDevice(String),
+    #[error("Audio device not found: {name}")]
+    DeviceNotFound { name: String },
+
+    #[error("Audio device disconnected")]
+    DeviceDisconnected,
+
+    #[error("Unsupported audio configuration {requested} (supported: {supported})")]
+    UnsupportedConfig {
+        requested: String,
+        supported: String,
+    },
 
-    #[error("Audio stream error: {0}")]
-    AudioStream(String),
+    #[error("Failed to build audio stream: {0}")]
+    StreamBuildFailed(#[source] BoxedError),
+
+    #[error("Failed to start audio stream: {0}")]
+    StreamPlayFailed(#[source] BoxedError),
+
+    #[error("Audio backend error: {0}")]
+    AudioBackend(#[source] BoxedError),
 
     #[error("MIDI device error: {0}")]
     MidiDevice(String),
@@ -30,5 +49,34 @@ pub enum KotoError {
     Project(String),
 }
 
+impl KotoError {
+    /// Whether the error means the audio device went away, so the UI can
+    /// offer to pick another device rather than just report it
+    pub fn is_device_lost(&self) -> bool {
+        matches!(
+            self,
+            KotoError::DeviceDisconnected | KotoError::DeviceNotFound { .. }
+        )
+    }
+}
+
 /// Result type for Koto operations
 pub type KotoResult<T> = Result<T, KotoError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn test_source_chaining() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing.wav");
+        let err = KotoError::StreamBuildFailed(Box::new(io));
+        assert_eq!(err.source().unwrap().to_string(), "missing.wav");
+        assert!(!err.is_device_lost());
+
+        let err: KotoError = std::io::Error::other("disk full").into();
+        assert!(matches!(err, KotoError::FileIo(_)));
+        assert!(err.source().is_some());
+    }
+}
@@ -5,11 +5,16 @@
 //! - Time representation (samples, beats, ticks)
 //! - MIDI message types
 //! - Common traits for audio processing
+//! - Signal processing and analysis utilities
+//! - Sample format conversion
 
+pub mod dsp;
 pub mod error;
+pub mod sample_convert;
 pub mod traits;
 pub mod types;
 
+pub use dsp::*;
 pub use error::*;
 pub use traits::*;
 pub use types::*;
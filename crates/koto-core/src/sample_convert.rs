@@ -0,0 +1,269 @@
+//! Sample format conversion between f32 and integer PCM
+//!
+//! Float samples are clamped to -1.0..=1.0 and scaled by the positive
+//! maximum of the target format, so +1.0 and -1.0 map to symmetric integer
+//! values. Integer samples are scaled back by the magnitude of the negative
+//! minimum, so the full integer range decodes to -1.0..1.0.
+
+use crate::types::Sample;
+
+/// Largest positive 24-bit sample value
+pub const I24_MAX: i32 = 0x7F_FFFF;
+
+/// Smallest negative 24-bit sample value
+pub const I24_MIN: i32 = -0x80_0000;
+
+/// Triangular probability density function (TPDF) dither source
+///
+/// Produces noise in the range (-1.0, 1.0) LSB, the sum of two independent
+/// uniform values. Uses a small xorshift generator so it never allocates.
+#[derive(Debug, Clone)]
+pub struct TpdfDither {
+    state: u32,
+}
+
+impl TpdfDither {
+    pub fn new(seed: u32) -> Self {
+        // xorshift must never be seeded with zero
+        Self {
+            state: if seed == 0 { 0x9E37_79B9 } else { seed },
+        }
+    }
+
+    fn next_uniform(&mut self) -> f64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x as f64 / u32::MAX as f64 - 0.5
+    }
+
+    /// Get the next dither value in LSB units
+    pub fn sample(&mut self) -> f64 {
+        self.next_uniform() + self.next_uniform()
+    }
+}
+
+impl Default for TpdfDither {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+#[inline]
+fn quantize(
+    sample: Sample,
+    scale: f64,
+    min: f64,
+    max: f64,
+    dither: Option<&mut TpdfDither>,
+) -> f64 {
+    let value = (sample as f64).clamp(-1.0, 1.0) * scale;
+    let value = match dither {
+        Some(dither) => value + dither.sample(),
+        None => value,
+    };
+    // NaN clamps to NaN and then casts to zero
+    value.round().clamp(min, max)
+}
+
+/// Convert a float sample to 16-bit PCM
+pub fn f32_to_i16(sample: Sample) -> i16 {
+    quantize(
+        sample,
+        i16::MAX as f64,
+        i16::MIN as f64,
+        i16::MAX as f64,
+        None,
+    ) as i16
+}
+
+/// Convert a float sample to 16-bit PCM with TPDF dither
+pub fn f32_to_i16_dithered(sample: Sample, dither: &mut TpdfDither) -> i16 {
+    quantize(
+        sample,
+        i16::MAX as f64,
+        i16::MIN as f64,
+        i16::MAX as f64,
+        Some(dither),
+    ) as i16
+}
+
+/// Convert a float sample to 24-bit PCM (stored in the low bits of an i32)
+pub fn f32_to_i24(sample: Sample) -> i32 {
+    quantize(sample, I24_MAX as f64, I24_MIN as f64, I24_MAX as f64, None) as i32
+}
+
+/// Convert a float sample to 24-bit PCM with TPDF dither
+pub fn f32_to_i24_dithered(sample: Sample, dither: &mut TpdfDither) -> i32 {
+    quantize(
+        sample,
+        I24_MAX as f64,
+        I24_MIN as f64,
+        I24_MAX as f64,
+        Some(dither),
+    ) as i32
+}
+
+/// Convert a float sample to 32-bit PCM
+///
+/// No dithered variant is provided: f32 carries only 24 bits of precision,
+/// so there is no truncation error to decorrelate.
+pub fn f32_to_i32(sample: Sample) -> i32 {
+    quantize(
+        sample,
+        i32::MAX as f64,
+        i32::MIN as f64,
+        i32::MAX as f64,
+        None,
+    ) as i32
+}
+
+/// Convert a 16-bit PCM sample to float
+pub fn i16_to_f32(sample: i16) -> Sample {
+    sample as f32 / 32768.0
+}
+
+/// Convert a 24-bit PCM sample (low bits of an i32) to float
+pub fn i24_to_f32(sample: i32) -> Sample {
+    sample.clamp(I24_MIN, I24_MAX) as f32 / 8_388_608.0
+}
+
+/// Convert a 32-bit PCM sample to float
+pub fn i32_to_f32(sample: i32) -> Sample {
+    (sample as f64 / 2_147_483_648.0) as f32
+}
+
+/// Pack a 24-bit sample into 3 little-endian bytes (WAV layout)
+pub fn pack_i24(sample: i32) -> [u8; 3] {
+    let bytes = sample.clamp(I24_MIN, I24_MAX).to_le_bytes();
+    [bytes[0], bytes[1], bytes[2]]
+}
+
+/// Unpack 3 little-endian bytes into a sign-extended 24-bit sample
+pub fn unpack_i24(bytes: [u8; 3]) -> i32 {
+    // Place the bytes in the upper 24 bits and shift back to sign-extend
+    i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8
+}
+
+/// Convert interleaved float samples to 16-bit PCM
+///
+/// Converts `min(src.len(), dst.len())` samples.
+pub fn f32_slice_to_i16(src: &[Sample], dst: &mut [i16], mut dither: Option<&mut TpdfDither>) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = match dither.as_deref_mut() {
+            Some(dither) => f32_to_i16_dithered(sample, dither),
+            None => f32_to_i16(sample),
+        };
+    }
+}
+
+/// Convert interleaved float samples to 24-bit PCM
+///
+/// Converts `min(src.len(), dst.len())` samples.
+pub fn f32_slice_to_i24(src: &[Sample], dst: &mut [i32], mut dither: Option<&mut TpdfDither>) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = match dither.as_deref_mut() {
+            Some(dither) => f32_to_i24_dithered(sample, dither),
+            None => f32_to_i24(sample),
+        };
+    }
+}
+
+/// Convert interleaved float samples to packed 3-byte 24-bit PCM
+///
+/// Converts `min(src.len(), dst.len() / 3)` samples.
+pub fn f32_slice_to_i24_packed(
+    src: &[Sample],
+    dst: &mut [u8],
+    mut dither: Option<&mut TpdfDither>,
+) {
+    for (out, &sample) in dst.chunks_exact_mut(3).zip(src) {
+        let value = match dither.as_deref_mut() {
+            Some(dither) => f32_to_i24_dithered(sample, dither),
+            None => f32_to_i24(sample),
+        };
+        out.copy_from_slice(&pack_i24(value));
+    }
+}
+
+/// Convert interleaved float samples to 32-bit PCM
+pub fn f32_slice_to_i32(src: &[Sample], dst: &mut [i32]) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = f32_to_i32(sample);
+    }
+}
+
+/// Convert interleaved 16-bit PCM to float samples
+pub fn i16_slice_to_f32(src: &[i16], dst: &mut [Sample]) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = i16_to_f32(sample);
+    }
+}
+
+/// Convert interleaved 24-bit PCM to float samples
+pub fn i24_slice_to_f32(src: &[i32], dst: &mut [Sample]) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = i24_to_f32(sample);
+    }
+}
+
+/// Convert interleaved packed 3-byte 24-bit PCM to float samples
+pub fn i24_packed_slice_to_f32(src: &[u8], dst: &mut [Sample]) {
+    for (out, bytes) in dst.iter_mut().zip(src.chunks_exact(3)) {
+        *out = i24_to_f32(unpack_i24([bytes[0], bytes[1], bytes[2]]));
+    }
+}
+
+/// Convert interleaved 32-bit PCM to float samples
+pub fn i32_slice_to_f32(src: &[i32], dst: &mut [Sample]) {
+    for (out, &sample) in dst.iter_mut().zip(src) {
+        *out = i32_to_f32(sample);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipping_at_full_scale() {
+        assert_eq!(f32_to_i16(1.0), i16::MAX);
+        assert_eq!(f32_to_i16(-1.0), -i16::MAX);
+        assert_eq!(f32_to_i16(1.5), i16::MAX);
+        assert_eq!(f32_to_i16(-1.5), -i16::MAX);
+        assert_eq!(f32_to_i24(2.0), I24_MAX);
+        assert_eq!(f32_to_i24(-2.0), -I24_MAX);
+        assert_eq!(f32_to_i32(1.0), i32::MAX);
+        assert_eq!(f32_to_i16(f32::NAN), 0);
+    }
+
+    #[test]
+    fn test_i24_pack_round_trip() {
+        for value in [0, 1, -1, I24_MAX, I24_MIN, 123_456, -654_321] {
+            assert_eq!(unpack_i24(pack_i24(value)), value);
+        }
+        assert_eq!(pack_i24(-1), [0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_dither_symmetric_around_zero() {
+        let mut dither = TpdfDither::new(12345);
+        let mut sum = 0i64;
+        let mut positive = 0i32;
+        let mut negative = 0;
+        for _ in 0..100_000 {
+            let value = f32_to_i16_dithered(0.0, &mut dither);
+            assert!((-1..=1).contains(&value));
+            sum += value as i64;
+            match value.signum() {
+                1 => positive += 1,
+                -1 => negative += 1,
+                _ => {}
+            }
+        }
+        assert!(sum.abs() < 1000, "dither is biased: {}", sum);
+        assert!((positive - negative).abs() < 1000);
+    }
+}
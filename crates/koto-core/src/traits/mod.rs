@@ -1,5 +1,9 @@
 //! Core traits for Koto DAW
 
+mod parameter;
 mod processor;
+mod stereo;
 
+pub use parameter::*;
 pub use processor::*;
+pub use stereo::*;
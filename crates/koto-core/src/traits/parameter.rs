@@ -0,0 +1,190 @@
+//! Parameter access and descriptors
+
+/// Describes a parameter so a generic editor can display and edit it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterInfo {
+    /// Id used with [`ParameterHandler::get_parameter`]
+    pub id: u32,
+    /// Display name, e.g. "Gain"
+    pub name: String,
+    /// Display unit, e.g. "dB" (empty if unitless)
+    pub unit: String,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    /// Step between allowed values, for stepped (e.g. integer or
+    /// enumerated) parameters
+    pub step: Option<f32>,
+    /// Knob response: 1.0 is linear, below 1.0 gives more travel to the
+    /// low end of the range
+    pub skew: f32,
+}
+
+impl ParameterInfo {
+    /// A continuous, linear parameter
+    pub fn new(id: u32, name: impl Into<String>, min: f32, max: f32, default: f32) -> Self {
+        Self {
+            id,
+            name: name.into(),
+            unit: String::new(),
+            min,
+            max,
+            default,
+            step: None,
+            skew: 1.0,
+        }
+    }
+
+    pub fn with_unit(mut self, unit: impl Into<String>) -> Self {
+        self.unit = unit.into();
+        self
+    }
+
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = (step > 0.0).then_some(step);
+        self
+    }
+
+    pub fn with_skew(mut self, skew: f32) -> Self {
+        self.skew = skew.max(f32::EPSILON);
+        self
+    }
+
+    pub fn is_stepped(&self) -> bool {
+        self.step.is_some()
+    }
+
+    /// Clamp a value to the range and round it to the step
+    pub fn constrain(&self, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        match self.step {
+            Some(step) => {
+                (self.min + ((value - self.min) / step).round() * step).clamp(self.min, self.max)
+            }
+            None => value,
+        }
+    }
+
+    /// Map a value to a knob position (0.0 to 1.0), applying the skew
+    pub fn to_normalized(&self, value: f32) -> f32 {
+        let span = self.max - self.min;
+        if span <= 0.0 {
+            return 0.0;
+        }
+        ((value - self.min) / span).clamp(0.0, 1.0).powf(self.skew)
+    }
+
+    /// Map a knob position (0.0 to 1.0) to a value
+    pub fn from_normalized(&self, normalized: f32) -> f32 {
+        let linear = normalized.clamp(0.0, 1.0).powf(1.0 / self.skew);
+        self.constrain(self.min + linear * (self.max - self.min))
+    }
+}
+
+/// Trait for parameter handling
+pub trait ParameterHandler {
+    /// Get parameter value by ID
+    fn get_parameter(&self, id: u32) -> Option<f32>;
+
+    /// Set parameter value by ID
+    fn set_parameter(&mut self, id: u32, value: f32);
+
+    /// Get parameter count
+    fn parameter_count(&self) -> usize;
+
+    /// Get the descriptor of a parameter by ID
+    fn parameter_info(&self, id: u32) -> Option<ParameterInfo>;
+
+    /// Get the ID of the parameter at `index` (0 to count - 1)
+    ///
+    /// The default assumes IDs are numbered from 0.
+    fn parameter_id(&self, index: usize) -> Option<u32> {
+        (index < self.parameter_count()).then_some(index as u32)
+    }
+
+    /// Iterate over all parameter descriptors
+    fn parameter_infos(&self) -> ParameterInfos<'_, Self>
+    where
+        Self: Sized,
+    {
+        ParameterInfos::new(self)
+    }
+
+    /// Format a value for display, e.g. "-6.0 dB"
+    fn value_to_text(&self, id: u32, value: f32) -> String {
+        match self.parameter_info(id) {
+            Some(info) if info.unit.is_empty() => format!("{value:.1}"),
+            Some(info) => format!("{value:.1} {}", info.unit),
+            None => format!("{value}"),
+        }
+    }
+
+    /// Parse text typed by the user, ignoring a trailing unit
+    ///
+    /// Returns the value constrained to the parameter's range and step.
+    fn text_to_value(&self, id: u32, text: &str) -> Option<f32> {
+        let info = self.parameter_info(id)?;
+        let text = text.trim();
+        let number = text
+            .strip_suffix(info.unit.as_str())
+            .filter(|_| !info.unit.is_empty())
+            .unwrap_or(text);
+        let value: f32 = number.trim().parse().ok()?;
+        Some(info.constrain(value))
+    }
+}
+
+/// Iterator over the parameter descriptors of a [`ParameterHandler`]
+pub struct ParameterInfos<'a, H: ParameterHandler + ?Sized> {
+    handler: &'a H,
+    index: usize,
+}
+
+impl<'a, H: ParameterHandler + ?Sized> ParameterInfos<'a, H> {
+    /// Works for trait objects too, e.g. `ParameterInfos::new(&*boxed)`
+    pub fn new(handler: &'a H) -> Self {
+        Self { handler, index: 0 }
+    }
+}
+
+impl<H: ParameterHandler + ?Sized> Iterator for ParameterInfos<'_, H> {
+    type Item = ParameterInfo;
+
+    fn next(&mut self) -> Option<ParameterInfo> {
+        while self.index < self.handler.parameter_count() {
+            let index = self.index;
+            self.index += 1;
+            if let Some(info) = self
+                .handler
+                .parameter_id(index)
+                .and_then(|id| self.handler.parameter_info(id))
+            {
+                return Some(info);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew_and_step() {
+        let info = ParameterInfo::new(0, "Cutoff", 20.0, 20_000.0, 1000.0)
+            .with_unit("Hz")
+            .with_skew(0.5);
+        for value in [20.0, 440.0, 20_000.0] {
+            let round_trip = info.from_normalized(info.to_normalized(value));
+            assert!((round_trip - value).abs() < 0.05);
+        }
+        // Half-way on the knob is well below the linear midpoint
+        assert!(info.from_normalized(0.5) < 10_000.0);
+
+        let stepped = ParameterInfo::new(1, "Voices", 1.0, 8.0, 4.0).with_step(1.0);
+        assert!(stepped.is_stepped());
+        assert_eq!(stepped.constrain(3.4), 3.0);
+        assert_eq!(stepped.constrain(99.0), 8.0);
+    }
+}
@@ -16,25 +16,47 @@ pub struct ProcessContext<'a> {
     pub frames: usize,
     /// MIDI events for this buffer
     pub midi_events: &'a [MidiEvent],
+    /// Sink for MIDI generated by the processor (e.g. an arpeggiator)
+    ///
+    /// The host reserves capacity before processing; events are sent on
+    /// to the processor's MIDI destination after the block.
+    pub midi_out: &'a mut Vec<MidiEvent>,
     /// Is the transport playing?
     pub is_playing: bool,
     /// Is recording enabled?
     pub is_recording: bool,
+    /// Latency of the input device in samples
+    pub input_latency: usize,
+    /// Latency of the output device in samples
+    pub output_latency: usize,
 }
 
 /// Trait for audio processing nodes
+///
+/// The host calls [`prepare`](Self::prepare) before the stream starts and
+/// whenever the sample rate or maximum block size changes; allocation is
+/// allowed there. [`process`](Self::process) runs on the audio thread and
+/// must not allocate, lock or block.
 pub trait AudioProcessor: Send + 'static {
+    /// Prepare for processing blocks of up to `max_frames` frames
+    ///
+    /// Allocate delay lines and scratch buffers here. The default forwards
+    /// to [`set_sample_rate`](Self::set_sample_rate).
+    fn prepare(&mut self, sample_rate: SampleRate, _max_frames: usize) {
+        self.set_sample_rate(sample_rate);
+    }
+
     /// Process audio through this processor
     ///
     /// # Arguments
     /// * `inputs` - Input audio buffers
     /// * `outputs` - Output audio buffers (pre-allocated)
-    /// * `context` - Processing context
+    /// * `context` - Processing context, including the MIDI output sink
     fn process(
         &mut self,
         inputs: &[AudioBuffer],
         outputs: &mut [AudioBuffer],
-        context: &ProcessContext,
+        context: &mut ProcessContext,
     );
 
     /// Get the number of audio input channels
@@ -55,14 +77,29 @@ pub trait AudioProcessor: Send + 'static {
     }
 }
 
-/// Trait for parameter handling
-pub trait ParameterHandler {
-    /// Get parameter value by ID
-    fn get_parameter(&self, id: u32) -> Option<f32>;
+/// Trait for MIDI-only processing nodes (arpeggiators, transposers, filters)
+///
+/// The same real-time rules as [`AudioProcessor`] apply: allocate in
+/// [`prepare`](Self::prepare), never in
+/// [`process_midi`](Self::process_midi).
+pub trait MidiProcessor: Send + 'static {
+    /// Prepare for processing blocks of up to `max_frames` frames
+    fn prepare(&mut self, _sample_rate: SampleRate, _max_frames: usize) {}
 
-    /// Set parameter value by ID
-    fn set_parameter(&mut self, id: u32, value: f32);
+    /// Transform the events of one block
+    ///
+    /// `input` is sorted by sample offset; events appended to `output` must
+    /// have offsets within `context.frames` and be sorted too.
+    fn process_midi(
+        &mut self,
+        input: &[MidiEvent],
+        output: &mut Vec<MidiEvent>,
+        context: &ProcessContext,
+    );
 
-    /// Get parameter count
-    fn parameter_count(&self) -> usize;
+    /// Clear internal state (held notes, pattern position)
+    ///
+    /// Processors that were sounding notes release them at the start of the
+    /// next block so nothing is left hanging.
+    fn reset(&mut self) {}
 }
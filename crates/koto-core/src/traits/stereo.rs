@@ -0,0 +1,214 @@
+//! Stereo effects on separate left/right slices
+
+use super::{AudioProcessor, ProcessContext};
+use crate::types::{AudioBuffer, Sample, SampleRate};
+
+/// Scratch size used until [`AudioProcessor::prepare`] is called
+const DEFAULT_MAX_FRAMES: usize = 512;
+
+/// A stereo effect that works on one left/right pair
+///
+/// Wrap it in a [`StereoAdapter`] to use it as an [`AudioProcessor`].
+pub trait StereoProcessor: Send + 'static {
+    /// Prepare for blocks of up to `max_frames` frames (allocation allowed)
+    fn prepare(&mut self, _sample_rate: SampleRate, _max_frames: usize) {}
+
+    /// Process one block in place; `left` and `right` have the same length
+    fn process_stereo(
+        &mut self,
+        left: &mut [Sample],
+        right: &mut [Sample],
+        context: &ProcessContext,
+    );
+
+    fn reset(&mut self) {}
+
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// Runs a [`StereoProcessor`] as an [`AudioProcessor`]
+///
+/// Input 0 is split into left and right (a mono input feeds both), and the
+/// result is written to output 0 (a mono output gets the average). When the
+/// input and output lengths differ, the shorter wins and the rest of the
+/// output is silenced. Blocks longer than the prepared size are processed
+/// in several calls.
+pub struct StereoAdapter<T: StereoProcessor> {
+    processor: T,
+    left: Vec<Sample>,
+    right: Vec<Sample>,
+}
+
+impl<T: StereoProcessor> StereoAdapter<T> {
+    pub fn new(processor: T) -> Self {
+        Self {
+            processor,
+            left: vec![0.0; DEFAULT_MAX_FRAMES],
+            right: vec![0.0; DEFAULT_MAX_FRAMES],
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.processor
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.processor
+    }
+
+    pub fn into_inner(self) -> T {
+        self.processor
+    }
+}
+
+impl<T: StereoProcessor> AudioProcessor for StereoAdapter<T> {
+    fn prepare(&mut self, sample_rate: SampleRate, max_frames: usize) {
+        let frames = max_frames.max(1);
+        self.left.resize(frames, 0.0);
+        self.right.resize(frames, 0.0);
+        self.processor.prepare(sample_rate, max_frames);
+    }
+
+    fn process(
+        &mut self,
+        inputs: &[AudioBuffer],
+        outputs: &mut [AudioBuffer],
+        context: &mut ProcessContext,
+    ) {
+        let Some(output) = outputs.first_mut() else {
+            return;
+        };
+        let out_channels = output.channels().as_usize();
+        let Some(input) = inputs.first() else {
+            output.clear();
+            return;
+        };
+        let in_channels = input.channels().as_usize();
+        let frames = input.frames().min(output.frames());
+        let context = &*context;
+
+        let mut start = 0;
+        while start < frames {
+            let count = (frames - start).min(self.left.len());
+            let left = &mut self.left[..count];
+            let right = &mut self.right[..count];
+
+            let src = &input.samples()[start * in_channels..(start + count) * in_channels];
+            for ((l, r), frame) in left
+                .iter_mut()
+                .zip(right.iter_mut())
+                .zip(src.chunks_exact(in_channels.max(1)))
+            {
+                *l = frame[0];
+                *r = if in_channels > 1 { frame[1] } else { frame[0] };
+            }
+
+            self.processor.process_stereo(left, right, context);
+
+            let dst =
+                &mut output.samples_mut()[start * out_channels..(start + count) * out_channels];
+            for ((&l, &r), frame) in left
+                .iter()
+                .zip(right.iter())
+                .zip(dst.chunks_exact_mut(out_channels.max(1)))
+            {
+                if out_channels == 1 {
+                    frame[0] = (l + r) * 0.5;
+                } else {
+                    frame[0] = l;
+                    frame[1] = r;
+                    frame[2..].fill(0.0);
+                }
+            }
+            start += count;
+        }
+        output.samples_mut()[frames * out_channels..].fill(0.0);
+    }
+
+    fn input_channels(&self) -> usize {
+        2
+    }
+
+    fn output_channels(&self) -> usize {
+        2
+    }
+
+    fn reset(&mut self) {
+        self.processor.reset();
+    }
+
+    fn latency(&self) -> usize {
+        self.processor.latency()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ChannelCount, MidiEvent, SamplePosition, Tempo, TimeSignature};
+
+    /// Left doubled, right negated, so channel handling is visible
+    struct Marker;
+
+    impl StereoProcessor for Marker {
+        fn process_stereo(
+            &mut self,
+            left: &mut [Sample],
+            right: &mut [Sample],
+            _: &ProcessContext,
+        ) {
+            left.iter_mut().for_each(|s| *s *= 2.0);
+            right.iter_mut().for_each(|s| *s = -*s);
+        }
+    }
+
+    fn run(input: AudioBuffer, out_frames: usize, max_frames: usize) -> AudioBuffer {
+        let mut adapter = StereoAdapter::new(Marker);
+        adapter.prepare(SampleRate::DVD_QUALITY, max_frames);
+        let mut outputs = [AudioBuffer::new(ChannelCount::STEREO, out_frames)];
+        outputs[0].samples_mut().fill(9.0);
+        let mut midi_out: Vec<MidiEvent> = Vec::new();
+        let mut context = ProcessContext {
+            sample_rate: SampleRate::DVD_QUALITY,
+            tempo: Tempo::DEFAULT,
+            time_signature: TimeSignature::COMMON_TIME,
+            playhead: SamplePosition::ZERO,
+            frames: out_frames,
+            midi_events: &[],
+            midi_out: &mut midi_out,
+            is_playing: true,
+            is_recording: false,
+            input_latency: 0,
+            output_latency: 0,
+        };
+        adapter.process(&[input], &mut outputs, &mut context);
+        let [output] = outputs;
+        output
+    }
+
+    #[test]
+    fn test_mono_input_feeds_both_channels() {
+        let input = AudioBuffer::from_samples(vec![0.5; 8], ChannelCount::MONO);
+        let output = run(input, 8, 3);
+        for frame in output.samples().chunks_exact(2) {
+            assert_eq!(frame, [1.0, -0.5]);
+        }
+    }
+
+    #[test]
+    fn test_mismatched_frames_truncate() {
+        let input =
+            AudioBuffer::from_samples(vec![1.0, 1.0, 2.0, 2.0, 3.0, 3.0], ChannelCount::STEREO);
+        let output = run(input, 5, 64);
+        assert_eq!(
+            output.samples(),
+            [2.0, -1.0, 4.0, -2.0, 6.0, -3.0, 0.0, 0.0, 0.0, 0.0]
+        );
+
+        let input = AudioBuffer::from_samples(vec![1.0; 10], ChannelCount::STEREO);
+        let output = run(input, 2, 64);
+        assert_eq!(output.samples(), [2.0, -1.0, 2.0, -1.0]);
+    }
+}
@@ -62,7 +62,7 @@ impl Default for BufferSize {
 }
 
 /// An audio buffer containing interleaved samples for multiple channels
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct AudioBuffer {
     /// Interleaved sample data
     samples: Vec<Sample>,
@@ -103,6 +103,17 @@ impl AudioBuffer {
         self.frames
     }
 
+    /// Change the number of frames, keeping the samples that still fit and
+    /// filling new frames with silence
+    ///
+    /// Doesn't allocate while the buffer has held at least `frames` frames
+    /// before, so a buffer created at the largest block size can be reused
+    /// for smaller blocks.
+    pub fn set_frames(&mut self, frames: usize) {
+        self.samples.resize(frames * self.channels.as_usize(), 0.0);
+        self.frames = frames;
+    }
+
     /// Get a reference to the raw sample data
     pub fn samples(&self) -> &[Sample] {
         &self.samples
@@ -159,6 +170,174 @@ impl AudioBuffer {
     pub fn peak(&self) -> Sample {
         self.samples.iter().map(|s| s.abs()).fold(0.0, f32::max)
     }
+
+    /// Check whether every sample is within `threshold` of zero
+    ///
+    /// Stops at the first sample above the threshold, so non-silent buffers
+    /// are usually rejected quickly. A threshold of 0.0 requires exact zeros.
+    pub fn is_silent(&self, threshold: Sample) -> bool {
+        !self.samples.iter().any(|s| s.abs() > threshold)
+    }
+
+    /// Copy another buffer into this one, routing channels through a map
+    ///
+    /// Each destination channel takes the source channel listed in the map,
+    /// or silence if the map has no entry for it. Frames beyond the end of
+    /// the source are filled with silence.
+    pub fn copy_remapped(&mut self, other: &AudioBuffer, map: &ChannelMap) {
+        map.remap_interleaved(
+            &other.samples,
+            other.channels.as_usize(),
+            &mut self.samples,
+            self.channels.as_usize(),
+        );
+    }
+
+    /// Copy a mono buffer into both channels of this stereo buffer
+    pub fn upmix_mono_to_stereo(&mut self, mono: &AudioBuffer) {
+        debug_assert_eq!(mono.channels, ChannelCount::MONO);
+        debug_assert_eq!(self.channels, ChannelCount::STEREO);
+        self.copy_remapped(mono, &ChannelMap::mono_to_stereo());
+    }
+
+    /// Fold a stereo buffer down into this mono buffer
+    ///
+    /// Frames beyond the end of the source are filled with silence.
+    pub fn downmix_stereo_to_mono(&mut self, stereo: &AudioBuffer, mode: DownmixMode) {
+        debug_assert_eq!(stereo.channels, ChannelCount::STEREO);
+        debug_assert_eq!(self.channels, ChannelCount::MONO);
+        let gain = mode.gain();
+        let frames = self.frames.min(stereo.frames);
+        for (out, frame) in self.samples[..frames]
+            .iter_mut()
+            .zip(stereo.samples.chunks_exact(2))
+        {
+            *out = (frame[0] + frame[1]) * gain;
+        }
+        self.samples[frames..].fill(0.0);
+    }
+}
+
+/// When live input is passed through to the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum MonitorMode {
+    #[default]
+    Off,
+    /// Only while armed for recording
+    WhenArmed,
+    Always,
+}
+
+impl MonitorMode {
+    /// Whether input should be heard given the record-arm state
+    pub fn is_monitoring(&self, armed: bool) -> bool {
+        match self {
+            Self::Off => false,
+            Self::WhenArmed => armed,
+            Self::Always => true,
+        }
+    }
+}
+
+/// Gain applied when folding stereo down to mono
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DownmixMode {
+    /// Plain sum of left and right (+6 dB for correlated material)
+    Sum,
+    /// Sum attenuated by 3 dB (constant power for uncorrelated material)
+    #[default]
+    Minus3Db,
+    /// Sum attenuated by 6 dB (average of left and right)
+    Minus6Db,
+}
+
+impl DownmixMode {
+    /// Linear gain applied to the L+R sum
+    pub fn gain(&self) -> Sample {
+        match self {
+            DownmixMode::Sum => 1.0,
+            DownmixMode::Minus3Db => std::f32::consts::FRAC_1_SQRT_2,
+            DownmixMode::Minus6Db => 0.5,
+        }
+    }
+}
+
+/// Channel routing used when copying between buffers of different layouts
+///
+/// Entry `n` is the source channel feeding destination channel `n`;
+/// `None` (or a missing entry) produces silence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelMap(pub Vec<Option<usize>>);
+
+impl ChannelMap {
+    /// Map each channel to itself
+    pub fn identity(channels: ChannelCount) -> Self {
+        Self((0..channels.as_usize()).map(Some).collect())
+    }
+
+    /// Duplicate a mono source into left and right
+    pub fn mono_to_stereo() -> Self {
+        Self(vec![Some(0), Some(0)])
+    }
+
+    /// Default routing between two layouts
+    ///
+    /// Mono sources are duplicated into every destination channel; otherwise
+    /// channels map one-to-one and destination channels without a matching
+    /// source stay silent.
+    pub fn for_channels(source: ChannelCount, destination: ChannelCount) -> Self {
+        if source == ChannelCount::MONO {
+            Self(vec![Some(0); destination.as_usize()])
+        } else {
+            Self(
+                (0..destination.as_usize())
+                    .map(|ch| (ch < source.as_usize()).then_some(ch))
+                    .collect(),
+            )
+        }
+    }
+
+    /// Get the source channel for a destination channel
+    pub fn source(&self, destination: usize) -> Option<usize> {
+        self.0.get(destination).copied().flatten()
+    }
+
+    /// Remap interleaved samples between channel layouts
+    ///
+    /// Processes as many frames as fit in `dst`; frames beyond the end of
+    /// `src` and channels without a valid source are filled with silence.
+    /// Does not allocate, so it is safe to call from the audio thread.
+    pub fn remap_interleaved(
+        &self,
+        src: &[Sample],
+        src_channels: usize,
+        dst: &mut [Sample],
+        dst_channels: usize,
+    ) {
+        if dst_channels == 0 {
+            return;
+        }
+        if src_channels == 0 {
+            dst.fill(0.0);
+            return;
+        }
+
+        let mut src_frames = src.chunks_exact(src_channels);
+        for out in dst.chunks_exact_mut(dst_channels) {
+            match src_frames.next() {
+                Some(frame) => {
+                    for (ch, sample) in out.iter_mut().enumerate() {
+                        *sample = self
+                            .source(ch)
+                            .and_then(|src_ch| frame.get(src_ch))
+                            .copied()
+                            .unwrap_or(0.0);
+                    }
+                }
+                None => out.fill(0.0),
+            }
+        }
+    }
 }
 
 /// A stereo frame (left and right samples)
@@ -192,3 +371,81 @@ pub struct AudioFormat {
     pub channels: ChannelCount,
     pub buffer_size: BufferSize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SilenceFlags;
+
+    #[test]
+    fn test_upmix_and_downmix() {
+        let mono = AudioBuffer::from_samples(vec![0.5, -0.25], ChannelCount::MONO);
+        let mut stereo = AudioBuffer::new(ChannelCount::STEREO, 2);
+        stereo.upmix_mono_to_stereo(&mono);
+        assert_eq!(stereo.samples(), &[0.5, 0.5, -0.25, -0.25]);
+
+        let mut folded = AudioBuffer::new(ChannelCount::MONO, 2);
+        folded.downmix_stereo_to_mono(&stereo, DownmixMode::Sum);
+        assert_eq!(folded.samples(), &[1.0, -0.5]);
+        folded.downmix_stereo_to_mono(&stereo, DownmixMode::Minus6Db);
+        assert_eq!(folded.samples(), mono.samples());
+    }
+
+    #[test]
+    fn test_silence_detection_thresholds() {
+        let zeros = AudioBuffer::new(ChannelCount::STEREO, 64);
+        assert!(zeros.is_silent(0.0));
+        assert!(zeros.is_silent(1e-6));
+
+        // A single sample at -90 dBFS
+        let mut quiet = AudioBuffer::new(ChannelCount::STEREO, 64);
+        quiet.set(40, 1, -10.0_f32.powf(-90.0 / 20.0));
+        assert!(!quiet.is_silent(0.0));
+        assert!(!quiet.is_silent(10.0_f32.powf(-120.0 / 20.0)));
+        assert!(quiet.is_silent(10.0_f32.powf(-80.0 / 20.0)));
+
+        let flags = SilenceFlags::from_buffers(&[zeros, quiet], 1e-6);
+        assert!(flags.is_silent(0));
+        assert!(!flags.is_silent(1));
+        assert!(!flags.all_silent(2));
+        assert!(flags.all_silent(1));
+        let full = SilenceFlags::all(SilenceFlags::CAPACITY);
+        assert!(full.all_silent(SilenceFlags::CAPACITY));
+        assert!(!full.all_silent(SilenceFlags::CAPACITY + 1));
+    }
+
+    #[test]
+    fn test_set_frames_reuses_the_samples() {
+        let mut buffer = AudioBuffer::from_samples(vec![1.0; 8], ChannelCount::STEREO);
+        let samples = buffer.samples().as_ptr();
+        buffer.set_frames(2);
+        assert_eq!(buffer.frames(), 2);
+        assert_eq!(buffer.samples(), &[1.0; 4]);
+        buffer.set_frames(3);
+        assert_eq!(buffer.samples(), &[1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+        assert_eq!(buffer.samples().as_ptr(), samples);
+    }
+
+    #[test]
+    fn test_copy_remapped_identity_and_swap() {
+        let source = AudioBuffer::from_samples(vec![1.0, 2.0, 3.0, 4.0], ChannelCount::STEREO);
+        let mut dest = AudioBuffer::new(ChannelCount::STEREO, 2);
+        dest.copy_remapped(&source, &ChannelMap::identity(ChannelCount::STEREO));
+        assert_eq!(dest.samples(), source.samples());
+
+        dest.copy_remapped(&source, &ChannelMap(vec![Some(1), Some(0)]));
+        assert_eq!(dest.samples(), &[2.0, 1.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn test_copy_remapped_mismatched_frames() {
+        let source = AudioBuffer::from_samples(vec![1.0, 2.0], ChannelCount::MONO);
+        let mut longer = AudioBuffer::from_samples(vec![9.0; 8], ChannelCount::STEREO);
+        longer.copy_remapped(&source, &ChannelMap::mono_to_stereo());
+        assert_eq!(longer.samples(), &[1.0, 1.0, 2.0, 2.0, 0.0, 0.0, 0.0, 0.0]);
+
+        let mut shorter = AudioBuffer::new(ChannelCount::STEREO, 1);
+        shorter.copy_remapped(&source, &ChannelMap(vec![Some(0), Some(5)]));
+        assert_eq!(shorter.samples(), &[1.0, 0.0]);
+    }
+}
@@ -0,0 +1,151 @@
+//! Names and properties of MIDI control change numbers
+
+use super::ControlNumber;
+use std::fmt;
+
+/// General MIDI names of the controllers that aren't LSBs (32-63)
+fn base_name(control: u8) -> Option<&'static str> {
+    Some(match control {
+        0 => "Bank Select",
+        1 => "Modulation",
+        2 => "Breath Controller",
+        4 => "Foot Controller",
+        5 => "Portamento Time",
+        6 => "Data Entry",
+        7 => "Volume",
+        8 => "Balance",
+        10 => "Pan",
+        11 => "Expression",
+        12 => "Effect Control 1",
+        13 => "Effect Control 2",
+        16 => "General Purpose 1",
+        17 => "General Purpose 2",
+        18 => "General Purpose 3",
+        19 => "General Purpose 4",
+        64 => "Sustain",
+        65 => "Portamento",
+        66 => "Sostenuto",
+        67 => "Soft Pedal",
+        68 => "Legato Footswitch",
+        69 => "Hold 2",
+        70 => "Sound Variation",
+        71 => "Resonance",
+        72 => "Release Time",
+        73 => "Attack Time",
+        74 => "Brightness",
+        75 => "Decay Time",
+        76 => "Vibrato Rate",
+        77 => "Vibrato Depth",
+        78 => "Vibrato Delay",
+        79 => "Sound Controller 10",
+        80 => "General Purpose 5",
+        81 => "General Purpose 6",
+        82 => "General Purpose 7",
+        83 => "General Purpose 8",
+        84 => "Portamento Control",
+        88 => "High Resolution Velocity Prefix",
+        91 => "Reverb Send",
+        92 => "Tremolo Depth",
+        93 => "Chorus Send",
+        94 => "Detune Depth",
+        95 => "Phaser Depth",
+        96 => "Data Increment",
+        97 => "Data Decrement",
+        98 => "NRPN LSB",
+        99 => "NRPN MSB",
+        100 => "RPN LSB",
+        101 => "RPN MSB",
+        120 => "All Sound Off",
+        121 => "Reset All Controllers",
+        122 => "Local Control",
+        123 => "All Notes Off",
+        124 => "Omni Mode Off",
+        125 => "Omni Mode On",
+        126 => "Mono Mode On",
+        127 => "Poly Mode On",
+        _ => return None,
+    })
+}
+
+impl ControlNumber {
+    /// Whether the controller has a General MIDI assignment
+    pub fn is_defined(&self) -> bool {
+        match self.0 {
+            32..=63 => base_name(self.0 - 32).is_some(),
+            control => base_name(control).is_some(),
+        }
+    }
+
+    /// General MIDI name, e.g. "Brightness" for CC 74
+    ///
+    /// Controllers 32-63 are named after their MSB ("Volume LSB");
+    /// unassigned ones read "CC n (undefined)".
+    pub fn name(&self) -> String {
+        let name = match self.0 {
+            32..=63 => base_name(self.0 - 32).map(|msb| format!("{msb} LSB")),
+            control => base_name(control).map(str::to_string),
+        };
+        name.unwrap_or_else(|| format!("CC {} (undefined)", self.0))
+    }
+
+    /// Whether the controller is an on/off switch (value 64 and above is on)
+    pub fn is_switch(&self) -> bool {
+        (64..=69).contains(&self.0)
+    }
+
+    /// Whether the controller is the MSB of a 14-bit pair (0-31)
+    pub fn is_msb(&self) -> bool {
+        self.0 < 32
+    }
+
+    /// The LSB controller paired with this MSB (e.g. CC 39 for CC 7)
+    pub fn lsb_pair(&self) -> Option<ControlNumber> {
+        self.is_msb().then(|| ControlNumber(self.0 + 32))
+    }
+
+    /// All controllers with a General MIDI assignment, in order
+    pub fn defined() -> impl Iterator<Item = ControlNumber> {
+        (0..128)
+            .map(ControlNumber)
+            .filter(ControlNumber::is_defined)
+    }
+}
+
+impl fmt::Display for ControlNumber {
+    /// Formats as e.g. "CC74 Brightness", or "CC3" when undefined
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_defined() {
+            write!(f, "CC{} {}", self.0, self.name())
+        } else {
+            write!(f, "CC{}", self.0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_well_known_names() {
+        assert_eq!(ControlNumber(74).to_string(), "CC74 Brightness");
+        assert_eq!(ControlNumber::SUSTAIN.name(), "Sustain");
+        assert_eq!(ControlNumber(39).name(), "Volume LSB");
+        assert_eq!(ControlNumber(3).name(), "CC 3 (undefined)");
+        assert_eq!(ControlNumber(35).name(), "CC 35 (undefined)");
+        assert_eq!(ControlNumber::ALL_NOTES_OFF.name(), "All Notes Off");
+    }
+
+    #[test]
+    fn test_switches_pairs_and_iteration() {
+        assert!(ControlNumber::SUSTAIN.is_switch());
+        assert!(!ControlNumber::MODULATION.is_switch());
+        assert_eq!(ControlNumber::VOLUME.lsb_pair(), Some(ControlNumber(39)));
+        assert_eq!(ControlNumber(74).lsb_pair(), None);
+
+        let defined: Vec<_> = ControlNumber::defined().collect();
+        assert_eq!(defined.first(), Some(&ControlNumber(0)));
+        assert!(defined.contains(&ControlNumber(32)));
+        assert!(!defined.contains(&ControlNumber(3)));
+    }
+}
@@ -0,0 +1,120 @@
+//! Plugin delay compensation (PDC) for parallel signal paths
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// Padding delays that keep parallel paths through a graph aligned
+///
+/// Computed by [`PluginDelayCompensation::compute`]. Every path arriving at
+/// a node is delayed to match the slowest one, so each node's inputs line
+/// up in time.
+#[derive(Debug, Clone)]
+pub struct PluginDelayCompensation<N> {
+    /// Latency at each node's output, relative to the graph sources
+    output_latency: HashMap<N, usize>,
+    /// Delay to insert on each connection
+    connection_delay: HashMap<(N, N), usize>,
+}
+
+impl<N: Copy + Eq + Hash> PluginDelayCompensation<N> {
+    /// Compute compensation for a graph
+    ///
+    /// `latencies` gives each node's own latency (missing nodes count as 0)
+    /// and `connections` the `(from, to)` edges. Nodes on a cycle can't be
+    /// compensated and are left out of the result.
+    pub fn compute(latencies: &HashMap<N, usize>, connections: &[(N, N)]) -> Self {
+        let mut in_degree: HashMap<N, usize> = HashMap::new();
+        let mut outgoing: HashMap<N, Vec<N>> = HashMap::new();
+        for &(from, to) in connections {
+            in_degree.entry(from).or_insert(0);
+            *in_degree.entry(to).or_insert(0) += 1;
+            outgoing.entry(from).or_default().push(to);
+        }
+        for &node in latencies.keys() {
+            in_degree.entry(node).or_insert(0);
+        }
+
+        // Visit nodes in topological order, tracking the latest arrival at
+        // each node's input
+        let mut arrival: HashMap<N, usize> = HashMap::new();
+        let mut output_latency = HashMap::new();
+        let mut ready: VecDeque<N> = in_degree
+            .iter()
+            .filter(|(_, &degree)| degree == 0)
+            .map(|(&node, _)| node)
+            .collect();
+        while let Some(node) = ready.pop_front() {
+            let latency = arrival.get(&node).copied().unwrap_or(0)
+                + latencies.get(&node).copied().unwrap_or(0);
+            output_latency.insert(node, latency);
+            for &next in outgoing.get(&node).map(Vec::as_slice).unwrap_or(&[]) {
+                let latest = arrival.entry(next).or_insert(0);
+                *latest = (*latest).max(latency);
+                let degree = in_degree.get_mut(&next).expect("node registered above");
+                *degree -= 1;
+                if *degree == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+
+        let connection_delay = connections
+            .iter()
+            .filter_map(|&(from, to)| {
+                let sent = *output_latency.get(&from)?;
+                output_latency.get(&to)?;
+                let needed = arrival.get(&to).copied().unwrap_or(0);
+                Some(((from, to), needed - sent))
+            })
+            .collect();
+
+        Self {
+            output_latency,
+            connection_delay,
+        }
+    }
+
+    /// Delay in samples to insert on a connection
+    pub fn connection_delay(&self, from: N, to: N) -> usize {
+        self.connection_delay.get(&(from, to)).copied().unwrap_or(0)
+    }
+
+    /// Latency at a node's output, including everything upstream
+    pub fn output_latency(&self, node: N) -> Option<usize> {
+        self.output_latency.get(&node).copied()
+    }
+
+    /// Largest latency at any node's output
+    pub fn total_latency(&self) -> usize {
+        self.output_latency.values().copied().max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diamond_pads_the_fast_branch() {
+        // 0 -> 1 (256 samples) -> 3, 0 -> 2 (no latency) -> 3
+        let latencies = HashMap::from([(0, 0), (1, 256), (2, 0), (3, 0)]);
+        let connections = [(0, 1), (0, 2), (1, 3), (2, 3)];
+        let pdc = PluginDelayCompensation::compute(&latencies, &connections);
+
+        assert_eq!(pdc.connection_delay(2, 3), 256);
+        assert_eq!(pdc.connection_delay(1, 3), 0);
+        assert_eq!(pdc.connection_delay(0, 1), 0);
+        assert_eq!(pdc.connection_delay(0, 2), 0);
+        assert_eq!(pdc.output_latency(3), Some(256));
+        assert_eq!(pdc.total_latency(), 256);
+    }
+
+    #[test]
+    fn test_cycle_is_left_out() {
+        let latencies = HashMap::from([(0, 64)]);
+        let pdc = PluginDelayCompensation::compute(&latencies, &[(0, 1), (1, 2), (2, 1)]);
+        assert_eq!(pdc.output_latency(0), Some(64));
+        assert_eq!(pdc.output_latency(1), None);
+        assert_eq!(pdc.connection_delay(0, 1), 0);
+    }
+}
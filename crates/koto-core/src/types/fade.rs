@@ -0,0 +1,53 @@
+//! Fade curve shapes
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::FRAC_PI_2;
+
+/// Shape of a fade
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FadeCurve {
+    #[default]
+    Linear,
+    /// Sine/cosine pair; constant power when crossfading uncorrelated material
+    EqualPower,
+    /// Slow start, fast finish; sounds even on a decibel scale
+    Exponential,
+    /// Smoothstep, easing in and out
+    SCurve,
+}
+
+impl FadeCurve {
+    /// Gain of a fade-in at `t` (0.0 = start, 1.0 = end of the fade)
+    ///
+    /// A fade-out is the same curve read backwards: `gain(1.0 - t)`.
+    pub fn gain(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            FadeCurve::Linear => t,
+            FadeCurve::EqualPower => (t * FRAC_PI_2).sin(),
+            FadeCurve::Exponential => t * t * t,
+            FadeCurve::SCurve => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade_curve_endpoints() {
+        for curve in [
+            FadeCurve::Linear,
+            FadeCurve::EqualPower,
+            FadeCurve::Exponential,
+            FadeCurve::SCurve,
+        ] {
+            assert_eq!(curve.gain(0.0), 0.0);
+            assert!((curve.gain(1.0) - 1.0).abs() < 1e-6);
+            assert!(curve.gain(0.25) <= curve.gain(0.75));
+        }
+        let power = FadeCurve::EqualPower.gain(0.5).powi(2) * 2.0;
+        assert!((power - 1.0).abs() < 1e-6);
+    }
+}
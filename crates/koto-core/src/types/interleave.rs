@@ -0,0 +1,153 @@
+//! Conversion between interleaved and per-channel (planar) sample layouts
+//!
+//! Mismatched lengths are handled deterministically: data that doesn't fit
+//! in the destination is dropped, and destination samples with no source
+//! data are set to silence.
+
+use super::{AudioBuffer, Sample};
+
+/// Split interleaved samples into one slice per channel
+///
+/// The channel count is `dsts.len()`. Each destination receives as many
+/// frames as both it and `src` hold; the rest of it is zero-filled.
+pub fn deinterleave(src: &[Sample], dsts: &mut [&mut [Sample]]) {
+    let channels = dsts.len();
+    if channels == 0 {
+        return;
+    }
+    let frames = src.len() / channels;
+
+    for (channel, dst) in dsts.iter_mut().enumerate() {
+        let count = frames.min(dst.len());
+        let (head, tail) = dst.split_at_mut(count);
+        // A constant stride lets the compiler unroll and vectorize the gather
+        match channels {
+            1 => head.copy_from_slice(&src[..count]),
+            2 => deinterleave_channel::<2>(src, channel, head),
+            3 => deinterleave_channel::<3>(src, channel, head),
+            4 => deinterleave_channel::<4>(src, channel, head),
+            6 => deinterleave_channel::<6>(src, channel, head),
+            8 => deinterleave_channel::<8>(src, channel, head),
+            _ => {
+                for (out, frame) in head.iter_mut().zip(src.chunks_exact(channels)) {
+                    *out = frame[channel];
+                }
+            }
+        }
+        tail.fill(0.0);
+    }
+}
+
+#[inline]
+fn deinterleave_channel<const N: usize>(src: &[Sample], channel: usize, dst: &mut [Sample]) {
+    assert!(channel < N);
+    for (out, frame) in dst.iter_mut().zip(src.chunks_exact(N)) {
+        *out = frame[channel];
+    }
+}
+
+/// Merge per-channel slices into interleaved samples
+///
+/// The channel count is `srcs.len()`. `dst` holds `dst.len() / srcs.len()`
+/// frames; frames past the end of a shorter source are zero-filled, as is
+/// any partial frame at the end of `dst`.
+pub fn interleave(srcs: &[&[Sample]], dst: &mut [Sample]) {
+    let channels = srcs.len();
+    if channels == 0 {
+        dst.fill(0.0);
+        return;
+    }
+    let frames = dst.len() / channels;
+    let (body, remainder) = dst.split_at_mut(frames * channels);
+    remainder.fill(0.0);
+
+    for (channel, src) in srcs.iter().enumerate() {
+        let count = frames.min(src.len());
+        let (head, tail) = body.split_at_mut(count * channels);
+        match channels {
+            1 => head.copy_from_slice(&src[..count]),
+            2 => interleave_channel::<2>(src, channel, head),
+            3 => interleave_channel::<3>(src, channel, head),
+            4 => interleave_channel::<4>(src, channel, head),
+            6 => interleave_channel::<6>(src, channel, head),
+            8 => interleave_channel::<8>(src, channel, head),
+            _ => {
+                for (frame, &sample) in head.chunks_exact_mut(channels).zip(src.iter()) {
+                    frame[channel] = sample;
+                }
+            }
+        }
+        for frame in tail.chunks_exact_mut(channels) {
+            frame[channel] = 0.0;
+        }
+    }
+}
+
+#[inline]
+fn interleave_channel<const N: usize>(src: &[Sample], channel: usize, dst: &mut [Sample]) {
+    assert!(channel < N);
+    for (frame, &sample) in dst.chunks_exact_mut(N).zip(src.iter()) {
+        frame[channel] = sample;
+    }
+}
+
+impl AudioBuffer {
+    /// Fill the buffer from interleaved samples with the same channel count
+    ///
+    /// Extra source samples are ignored; if `src` is short, the rest of the
+    /// buffer is zero-filled.
+    pub fn fill_from_interleaved(&mut self, src: &[Sample]) {
+        let samples = self.samples_mut();
+        let count = samples.len().min(src.len());
+        samples[..count].copy_from_slice(&src[..count]);
+        samples[count..].fill(0.0);
+    }
+
+    /// Write the buffer's samples to an interleaved slice
+    ///
+    /// Only as much as fits is written; if `dst` is longer than the buffer,
+    /// the rest of it is zero-filled.
+    pub fn write_interleaved(&self, dst: &mut [Sample]) {
+        let samples = self.samples();
+        let count = samples.len().min(dst.len());
+        dst[..count].copy_from_slice(&samples[..count]);
+        dst[count..].fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_for_common_channel_counts() {
+        for channels in 1..=8 {
+            let frames = 37;
+            let src: Vec<Sample> = (0..frames * channels).map(|i| i as Sample).collect();
+            let mut planar = vec![vec![0.0; frames]; channels];
+            let mut dsts: Vec<&mut [Sample]> =
+                planar.iter_mut().map(|c| c.as_mut_slice()).collect();
+            deinterleave(&src, &mut dsts);
+            assert_eq!(planar[channels - 1][1], (2 * channels - 1) as Sample);
+
+            let srcs: Vec<&[Sample]> = planar.iter().map(|c| c.as_slice()).collect();
+            let mut out = vec![0.0; frames * channels];
+            interleave(&srcs, &mut out);
+            assert_eq!(out, src);
+        }
+    }
+
+    #[test]
+    fn test_mismatched_lengths_zero_fill() {
+        let src = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut left = [9.0; 4];
+        let mut right = [9.0; 1];
+        deinterleave(&src, &mut [&mut left, &mut right]);
+        assert_eq!(left, [1.0, 3.0, 0.0, 0.0]);
+        assert_eq!(right, [2.0]);
+
+        let mut out = [9.0; 7];
+        interleave(&[&[1.0, 2.0, 3.0], &[4.0]], &mut out);
+        assert_eq!(out, [1.0, 4.0, 2.0, 0.0, 3.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Named timeline markers
+
+use super::SamplePosition;
+use serde::{Deserialize, Serialize};
+
+/// Unique identifier for markers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct MarkerId(pub u64);
+
+/// A named position on the timeline
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Marker {
+    pub id: MarkerId,
+    pub position: SamplePosition,
+    pub name: String,
+    /// RGB color (0xRRGGBB)
+    pub color: u32,
+}
+
+/// Markers kept sorted by position
+///
+/// Ids are never reused, so they stay valid across undo/redo; removed markers
+/// can be put back with [`restore`](Self::restore). Markers at the same
+/// position are ordered by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkerList {
+    markers: Vec<Marker>,
+    next_id: u64,
+}
+
+impl MarkerList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a marker, returning its new id
+    pub fn add(
+        &mut self,
+        position: SamplePosition,
+        name: impl Into<String>,
+        color: u32,
+    ) -> MarkerId {
+        let id = MarkerId(self.next_id);
+        self.restore(Marker {
+            id,
+            position,
+            name: name.into(),
+            color,
+        });
+        id
+    }
+
+    /// Insert a marker with its existing id, e.g. when undoing a removal
+    ///
+    /// Replaces any marker with the same id.
+    pub fn restore(&mut self, marker: Marker) {
+        self.remove(marker.id);
+        self.next_id = self.next_id.max(marker.id.0 + 1);
+        let index = self.insertion_index(marker.position, marker.id);
+        self.markers.insert(index, marker);
+    }
+
+    pub fn remove(&mut self, id: MarkerId) -> Option<Marker> {
+        let index = self.markers.iter().position(|m| m.id == id)?;
+        Some(self.markers.remove(index))
+    }
+
+    /// Move a marker, returning its previous position
+    pub fn move_marker(
+        &mut self,
+        id: MarkerId,
+        position: SamplePosition,
+    ) -> Option<SamplePosition> {
+        let mut marker = self.remove(id)?;
+        let previous = marker.position;
+        marker.position = position;
+        let index = self.insertion_index(position, id);
+        self.markers.insert(index, marker);
+        Some(previous)
+    }
+
+    pub fn rename(&mut self, id: MarkerId, name: impl Into<String>) -> bool {
+        match self.markers.iter_mut().find(|m| m.id == id) {
+            Some(marker) => {
+                marker.name = name.into();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, id: MarkerId) -> Option<&Marker> {
+        self.markers.iter().find(|m| m.id == id)
+    }
+
+    /// Markers in position order
+    pub fn iter(&self) -> impl Iterator<Item = &Marker> {
+        self.markers.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.markers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.markers.is_empty()
+    }
+
+    /// First marker strictly after `position`
+    pub fn next_marker(&self, after: SamplePosition) -> Option<&Marker> {
+        let index = self.markers.partition_point(|m| m.position <= after);
+        self.markers.get(index)
+    }
+
+    /// Last marker strictly before `position`
+    pub fn previous_marker(&self, before: SamplePosition) -> Option<&Marker> {
+        let index = self.markers.partition_point(|m| m.position < before);
+        index.checked_sub(1).map(|i| &self.markers[i])
+    }
+
+    /// Marker closest to `position`, if within `tolerance` samples
+    pub fn marker_at(&self, position: SamplePosition, tolerance: i64) -> Option<&Marker> {
+        self.markers
+            .iter()
+            .map(|m| (m, (m.position.0 - position.0).abs()))
+            .filter(|&(_, distance)| distance <= tolerance)
+            .min_by_key(|&(_, distance)| distance)
+            .map(|(m, _)| m)
+    }
+
+    fn insertion_index(&self, position: SamplePosition, id: MarkerId) -> usize {
+        self.markers
+            .partition_point(|m| (m.position, m.id) < (position, id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stays_sorted_when_moved() {
+        let mut markers = MarkerList::new();
+        let verse = markers.add(SamplePosition(1000), "Verse 2", 0xFF0000);
+        let drop = markers.add(SamplePosition(5000), "Drop", 0x00FF00);
+        let intro = markers.add(SamplePosition(0), "Intro", 0x0000FF);
+
+        let order: Vec<MarkerId> = markers.iter().map(|m| m.id).collect();
+        assert_eq!(order, vec![intro, verse, drop]);
+
+        assert_eq!(
+            markers.move_marker(verse, SamplePosition(9000)),
+            Some(SamplePosition(1000))
+        );
+        let order: Vec<MarkerId> = markers.iter().map(|m| m.id).collect();
+        assert_eq!(order, vec![intro, drop, verse]);
+
+        // A restored marker keeps its id and new ones don't reuse it
+        let removed = markers.remove(drop).unwrap();
+        markers.restore(removed);
+        assert_eq!(markers.get(drop).unwrap().name, "Drop");
+        assert_ne!(markers.add(SamplePosition(1), "New", 0), drop);
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut markers = MarkerList::new();
+        let a = markers.add(SamplePosition(1000), "A", 0);
+        let b = markers.add(SamplePosition(2000), "B", 0);
+
+        assert_eq!(markers.next_marker(SamplePosition(0)).unwrap().id, a);
+        assert_eq!(markers.next_marker(SamplePosition(1000)).unwrap().id, b);
+        assert!(markers.next_marker(SamplePosition(2000)).is_none());
+        assert_eq!(markers.previous_marker(SamplePosition(2000)).unwrap().id, a);
+        assert!(markers.previous_marker(SamplePosition(1000)).is_none());
+
+        assert_eq!(markers.marker_at(SamplePosition(1980), 50).unwrap().id, b);
+        assert!(markers.marker_at(SamplePosition(1500), 50).is_none());
+    }
+}
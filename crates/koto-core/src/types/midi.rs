@@ -1,6 +1,7 @@
 //! MIDI message types
 
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 /// MIDI channel (0-15)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
@@ -38,6 +39,170 @@ impl NoteNumber {
     pub fn frequency(&self) -> f64 {
         440.0 * 2.0_f64.powf((self.0 as f64 - 69.0) / 12.0)
     }
+
+    /// Get frequency in Hz with pitch bend applied
+    ///
+    /// `range` is the bend range in semitones.
+    pub fn frequency_with_bend(&self, bend: PitchBend, range: f32) -> f64 {
+        let pitch = self.0 as f64 + bend.semitones(range) as f64;
+        440.0 * 2.0_f64.powf((pitch - 69.0) / 12.0)
+    }
+
+    /// Parse a note name such as "C4", "C#4", "Db3" or "A-1"
+    ///
+    /// Uses the same octave numbering as [`name`](Self::name) (C4 = 60).
+    /// Accidentals may be repeated ("C##4", "Bbb2").
+    pub fn from_name(name: &str) -> Result<Self, NoteNameError> {
+        let name = name.trim();
+        let mut chars = name.chars();
+        let letter = chars.next().ok_or(NoteNameError::Empty)?;
+        let pitch_class: i32 = match letter.to_ascii_uppercase() {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            other => return Err(NoteNameError::InvalidLetter(other)),
+        };
+
+        let rest = chars.as_str();
+        let octave_start = rest
+            .find(|c: char| c != '#' && c != 'b')
+            .unwrap_or(rest.len());
+        let (accidentals, octave) = rest.split_at(octave_start);
+        let offset: i32 = accidentals
+            .chars()
+            .map(|c| if c == '#' { 1 } else { -1 })
+            .sum();
+
+        if octave.is_empty() {
+            return Err(NoteNameError::MissingOctave);
+        }
+        let invalid_octave = || NoteNameError::InvalidOctave(octave.to_string());
+        let octave: i32 = octave.parse().map_err(|_| invalid_octave())?;
+
+        let number = octave
+            .checked_add(1)
+            .and_then(|o| o.checked_mul(12))
+            .and_then(|n| n.checked_add(pitch_class + offset))
+            .ok_or_else(invalid_octave)?;
+        if (0..=127).contains(&number) {
+            Ok(Self(number as u8))
+        } else {
+            Err(NoteNameError::OutOfRange(number))
+        }
+    }
+
+    /// Transpose by a number of semitones, or `None` if the result leaves 0..=127
+    pub fn transpose(&self, semitones: i8) -> Option<Self> {
+        let note = self.0 as i16 + semitones as i16;
+        (0..=127).contains(&note).then_some(Self(note as u8))
+    }
+
+    /// Signed interval in semitones from this note to `other`
+    pub fn semitones_to(&self, other: NoteNumber) -> i8 {
+        (other.0 as i16 - self.0 as i16) as i8
+    }
+
+    /// Octave number (C4 = middle C, so note 0 is octave -1)
+    pub fn octave(&self) -> i8 {
+        (self.0 / 12) as i8 - 1
+    }
+
+    /// Pitch class (0 = C, 1 = C#, ..., 11 = B)
+    pub fn pitch_class(&self) -> u8 {
+        self.0 % 12
+    }
+}
+
+impl std::str::FromStr for NoteNumber {
+    type Err = NoteNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_name(s)
+    }
+}
+
+/// Error parsing a note name
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NoteNameError {
+    #[error("Note name is empty")]
+    Empty,
+
+    #[error("Invalid note letter: {0}")]
+    InvalidLetter(char),
+
+    #[error("Note name has no octave")]
+    MissingOctave,
+
+    #[error("Invalid octave: {0}")]
+    InvalidOctave(String),
+
+    #[error("Note {0} is outside the MIDI range 0-127")]
+    OutOfRange(i32),
+}
+
+/// A musical scale as a set of pitch classes relative to a root
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Scale {
+    /// Root pitch class (0 = C)
+    pub root: u8,
+    /// Bit `n` set means the pitch class `root + n` is in the scale
+    pub mask: u16,
+}
+
+impl Scale {
+    const MAJOR_MASK: u16 = 0b1010_1011_0101;
+    const MINOR_MASK: u16 = 0b0101_1010_1101;
+    const CHROMATIC_MASK: u16 = 0b1111_1111_1111;
+
+    pub fn major(root: u8) -> Self {
+        Self::custom(root, Self::MAJOR_MASK)
+    }
+
+    /// Natural minor (Aeolian)
+    pub fn minor(root: u8) -> Self {
+        Self::custom(root, Self::MINOR_MASK)
+    }
+
+    pub fn chromatic() -> Self {
+        Self::custom(0, Self::CHROMATIC_MASK)
+    }
+
+    /// A scale from a 12-bit mask of intervals above the root
+    ///
+    /// The root itself is always included.
+    pub fn custom(root: u8, mask: u16) -> Self {
+        Self {
+            root: root % 12,
+            mask: (mask & Self::CHROMATIC_MASK) | 1,
+        }
+    }
+
+    /// Whether a note belongs to the scale
+    pub fn contains(&self, note: NoteNumber) -> bool {
+        let degree = (note.pitch_class() + 12 - self.root) % 12;
+        self.mask & (1 << degree) != 0
+    }
+
+    /// Move a note to the nearest note in the scale
+    ///
+    /// Ties resolve downwards. Stays within 0..=127.
+    pub fn snap_to_scale(&self, note: NoteNumber) -> NoteNumber {
+        for distance in 0..12i8 {
+            for candidate in [note.transpose(-distance), note.transpose(distance)]
+                .into_iter()
+                .flatten()
+            {
+                if self.contains(candidate) {
+                    return candidate;
+                }
+            }
+        }
+        note
+    }
 }
 
 /// MIDI velocity (0-127)
@@ -70,6 +235,58 @@ impl Default for Velocity {
     }
 }
 
+/// Pitch bend value (-8192 to 8191, 0 = center)
+///
+/// The raw range is asymmetric, so conversions scale upward bends by 8191
+/// and downward bends by 8192; the extremes map to exactly +/- the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct PitchBend(pub i16);
+
+impl PitchBend {
+    pub const MIN: Self = Self(-8192);
+    pub const CENTER: Self = Self(0);
+    pub const MAX: Self = Self(8191);
+    /// Bend range in semitones assumed by most instruments
+    pub const DEFAULT_RANGE: f32 = 2.0;
+
+    pub fn new(value: i16) -> Self {
+        Self(value.clamp(Self::MIN.0, Self::MAX.0))
+    }
+
+    /// Convert to normalized value (-1.0 to 1.0)
+    pub fn normalized(&self) -> f32 {
+        if self.0 >= 0 {
+            self.0 as f32 / Self::MAX.0 as f32
+        } else {
+            self.0 as f32 / -(Self::MIN.0 as f32)
+        }
+    }
+
+    /// Create from a normalized value (-1.0 to 1.0)
+    pub fn from_normalized(value: f32) -> Self {
+        let value = value.clamp(-1.0, 1.0);
+        let raw = if value >= 0.0 {
+            value * Self::MAX.0 as f32
+        } else {
+            value * -(Self::MIN.0 as f32)
+        };
+        Self(raw.round() as i16)
+    }
+
+    /// Bend in semitones for a bend range of `range` semitones
+    pub fn semitones(&self, range: f32) -> f32 {
+        self.normalized() * range
+    }
+
+    /// Create from a bend in semitones, clamped to `range`
+    pub fn from_semitones(semitones: f32, range: f32) -> Self {
+        if range <= 0.0 {
+            return Self::CENTER;
+        }
+        Self::from_normalized(semitones / range)
+    }
+}
+
 /// MIDI control change number
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ControlNumber(pub u8);
@@ -89,7 +306,9 @@ impl ControlNumber {
 }
 
 /// MIDI message types
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+///
+/// Serialized compactly as `[status, data1, data2]`; see `midi_serde`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MidiMessage {
     /// Note On event
     NoteOn {
@@ -201,10 +420,53 @@ impl MidiMessage {
             _ => None,
         }
     }
+
+    /// Encode as raw MIDI bytes
+    ///
+    /// Two-byte messages (program change, channel pressure) have a trailing
+    /// zero, which [`from_bytes`](Self::from_bytes) ignores. A note-on with
+    /// velocity 0 encodes as one, so it decodes as a note-off.
+    pub fn to_bytes(&self) -> [u8; 3] {
+        let status = |kind: u8, channel: &MidiChannel| kind | (channel.0 & 0x0F);
+        match self {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => [status(0x90, channel), note.0 & 0x7F, velocity.0 & 0x7F],
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => [status(0x80, channel), note.0 & 0x7F, velocity.0 & 0x7F],
+            MidiMessage::ControlChange {
+                channel,
+                control,
+                value,
+            } => [status(0xB0, channel), control.0 & 0x7F, value & 0x7F],
+            MidiMessage::ProgramChange { channel, program } => {
+                [status(0xC0, channel), program & 0x7F, 0]
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                let raw = (*value as i32 + 8192).clamp(0, 0x3FFF) as u16;
+                [status(0xE0, channel), (raw & 0x7F) as u8, (raw >> 7) as u8]
+            }
+            MidiMessage::ChannelPressure { channel, pressure } => {
+                [status(0xD0, channel), pressure & 0x7F, 0]
+            }
+            MidiMessage::PolyPressure {
+                channel,
+                note,
+                pressure,
+            } => [status(0xA0, channel), note.0 & 0x7F, pressure & 0x7F],
+        }
+    }
 }
 
 /// A MIDI event with timing information
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+///
+/// Serialized compactly as `[sample_offset, status, data1, data2]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MidiEvent {
     /// Sample offset within the current buffer
     pub sample_offset: usize,
@@ -219,4 +481,188 @@ impl MidiEvent {
             message,
         }
     }
+
+    /// Ordering key: by offset, with note-offs ahead of other events at the
+    /// same offset so a repeated pitch is released before it is retriggered
+    fn order_key(&self) -> (usize, bool) {
+        let is_note_off = matches!(self.message, MidiMessage::NoteOff { .. });
+        (self.sample_offset, !is_note_off)
+    }
+}
+
+/// Sort events by sample offset, putting note-offs first at equal offsets
+///
+/// The sort is stable, so other events at the same offset keep their order.
+pub fn sort_events(events: &mut [MidiEvent]) {
+    events.sort_by_key(MidiEvent::order_key);
+}
+
+/// Merge two event lists that are already in [`sort_events`] order
+///
+/// At equal keys, events from `a` come before events from `b`.
+pub fn merge_sorted(a: &[MidiEvent], b: &[MidiEvent]) -> Vec<MidiEvent> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if b[j].order_key() < a[i].order_key() {
+            merged.push(b[j]);
+            j += 1;
+        } else {
+            merged.push(a[i]);
+            i += 1;
+        }
+    }
+    merged.extend_from_slice(&a[i..]);
+    merged.extend_from_slice(&b[j..]);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_name_round_trip() {
+        for note in 0..=127 {
+            let note = NoteNumber(note);
+            assert_eq!(NoteNumber::from_name(&note.name()), Ok(note));
+        }
+        assert_eq!(NoteNumber::from_name("Db4"), Ok(NoteNumber(61)));
+        assert_eq!(NoteNumber::from_name("c-1"), Ok(NoteNumber(0)));
+        assert_eq!(
+            "Cb-1".parse::<NoteNumber>(),
+            Err(NoteNameError::OutOfRange(-1))
+        );
+        assert_eq!(
+            NoteNumber::from_name("H2"),
+            Err(NoteNameError::InvalidLetter('H'))
+        );
+        assert_eq!(
+            NoteNumber::from_name("C#"),
+            Err(NoteNameError::MissingOctave)
+        );
+
+        assert_eq!(NoteNumber(120).transpose(7), Some(NoteNumber(127)));
+        assert_eq!(NoteNumber(120).transpose(8), None);
+        assert_eq!(NoteNumber(60).semitones_to(NoteNumber(55)), -5);
+        assert_eq!(NoteNumber(59).octave(), 3);
+    }
+
+    #[test]
+    fn test_note_name_with_huge_octave_is_an_error() {
+        assert_eq!(
+            NoteNumber::from_name("C999999999"),
+            Err(NoteNameError::InvalidOctave("999999999".to_string()))
+        );
+        assert_eq!(
+            NoteNumber::from_name("Cb-999999999"),
+            Err(NoteNameError::InvalidOctave("-999999999".to_string()))
+        );
+        assert_eq!(
+            NoteNumber::from_name("C99"),
+            Err(NoteNameError::OutOfRange(1200))
+        );
+    }
+
+    fn note_on(offset: usize, note: u8) -> MidiEvent {
+        MidiEvent::new(
+            offset,
+            MidiMessage::NoteOn {
+                channel: MidiChannel(0),
+                note: NoteNumber(note),
+                velocity: Velocity::default(),
+            },
+        )
+    }
+
+    fn note_off(offset: usize, note: u8) -> MidiEvent {
+        MidiEvent::new(
+            offset,
+            MidiMessage::NoteOff {
+                channel: MidiChannel(0),
+                note: NoteNumber(note),
+                velocity: Velocity::OFF,
+            },
+        )
+    }
+
+    fn cc(offset: usize, value: u8) -> MidiEvent {
+        MidiEvent::new(
+            offset,
+            MidiMessage::ControlChange {
+                channel: MidiChannel(0),
+                control: ControlNumber::MODULATION,
+                value,
+            },
+        )
+    }
+
+    #[test]
+    fn test_sort_events_off_before_on_and_stable() {
+        let mut events = vec![
+            cc(10, 1),
+            note_on(10, 60),
+            cc(10, 2),
+            note_off(10, 60),
+            note_on(0, 64),
+            cc(10, 3),
+        ];
+        sort_events(&mut events);
+        assert_eq!(
+            events,
+            vec![
+                note_on(0, 64),
+                note_off(10, 60),
+                cc(10, 1),
+                note_on(10, 60),
+                cc(10, 2),
+                cc(10, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_sorted_releases_before_retrigger() {
+        let clip = vec![note_on(0, 60), note_on(32, 60)];
+        let live = vec![cc(0, 5), note_off(32, 60), cc(40, 6)];
+        let merged = merge_sorted(&clip, &live);
+        assert_eq!(
+            merged,
+            vec![
+                note_on(0, 60),
+                cc(0, 5),
+                note_off(32, 60),
+                note_on(32, 60),
+                cc(40, 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pitch_bend_range_extremes() {
+        let range = PitchBend::DEFAULT_RANGE;
+        assert_eq!(PitchBend::from_semitones(range, range), PitchBend::MAX);
+        assert_eq!(PitchBend::from_semitones(-range, range), PitchBend::MIN);
+        assert_eq!(PitchBend::from_semitones(5.0, range), PitchBend::MAX);
+        assert_eq!(PitchBend::MAX.semitones(12.0), 12.0);
+        assert_eq!(PitchBend::MIN.semitones(12.0), -12.0);
+        let half = PitchBend::from_semitones(1.0, range).semitones(range);
+        assert!((half - 1.0).abs() < 1e-3);
+
+        let a4 = NoteNumber(69);
+        let bent = a4.frequency_with_bend(PitchBend::MAX, range);
+        assert!((bent - NoteNumber(71).frequency()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_snap_to_scale() {
+        let d_minor = Scale::minor(2);
+        // C# is between C and D; ties go down
+        assert_eq!(d_minor.snap_to_scale(NoteNumber(61)), NoteNumber(60));
+        // F# snaps down to F, D# to D
+        assert_eq!(d_minor.snap_to_scale(NoteNumber(66)), NoteNumber(65));
+        assert_eq!(d_minor.snap_to_scale(NoteNumber(63)), NoteNumber(62));
+        assert!(Scale::major(0).contains(NoteNumber(71)));
+        assert!(!Scale::major(0).contains(NoteNumber(70)));
+    }
 }
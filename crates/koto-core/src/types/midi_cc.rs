@@ -0,0 +1,356 @@
+//! High-resolution control change and (N)RPN decoding
+
+use super::{ControlNumber, MidiChannel, MidiMessage};
+
+/// Registered parameter: pitch bend sensitivity (MSB semitones, LSB cents)
+pub const RPN_PITCH_BEND_SENSITIVITY: u16 = 0x0000;
+/// Registered parameter: channel fine tuning
+pub const RPN_FINE_TUNING: u16 = 0x0001;
+/// Registered parameter: channel coarse tuning
+pub const RPN_COARSE_TUNING: u16 = 0x0002;
+/// Registered parameter number that deselects the current parameter
+pub const RPN_NULL: u16 = 0x3FFF;
+
+const DATA_ENTRY_MSB: u8 = 6;
+const DATA_ENTRY_LSB: u8 = 38;
+const NRPN_LSB: u8 = 98;
+const NRPN_MSB: u8 = 99;
+const RPN_LSB: u8 = 100;
+const RPN_MSB: u8 = 101;
+
+/// Whether a parameter number is registered (RPN) or non-registered (NRPN)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParameterKind {
+    Registered,
+    NonRegistered,
+}
+
+/// Control events produced by [`CcResolver`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlEvent {
+    /// A plain 7-bit control change (no LSB arrived)
+    ControlChange {
+        channel: MidiChannel,
+        control: ControlNumber,
+        value: u8,
+    },
+    /// An MSB/LSB control pair (controls 0-31 with 32-63), 14-bit value
+    HighResControlChange {
+        channel: MidiChannel,
+        control: ControlNumber,
+        value: u16,
+    },
+    /// Data entry for a selected RPN or NRPN, 14-bit value
+    RpnChange {
+        channel: MidiChannel,
+        kind: ParameterKind,
+        parameter: u16,
+        value: u16,
+    },
+}
+
+/// An MSB waiting for its LSB
+#[derive(Debug, Clone, Copy)]
+struct Pending {
+    channel: u8,
+    control: u8,
+    msb: u8,
+    /// Parameter selected when a data entry MSB arrived
+    parameter: Option<(ParameterKind, u16)>,
+    /// Event count at which the MSB is emitted on its own
+    deadline: u64,
+}
+
+/// Parameter selection state of one channel
+#[derive(Debug, Clone, Copy, Default)]
+struct ChannelState {
+    kind: Option<ParameterKind>,
+    parameter_msb: u8,
+    parameter_lsb: u8,
+    /// Last MSB per control 0-31, for LSB-only updates
+    last_msb: [Option<u8>; 32],
+}
+
+impl ChannelState {
+    fn selected(&self) -> Option<(ParameterKind, u16)> {
+        let parameter = (self.parameter_msb as u16) << 7 | self.parameter_lsb as u16;
+        match self.kind {
+            Some(kind) if parameter != RPN_NULL => Some((kind, parameter)),
+            _ => None,
+        }
+    }
+}
+
+/// Combines MSB/LSB control pairs and decodes RPN/NRPN sequences
+///
+/// Feed every incoming message through [`process`](Self::process). When an
+/// MSB (controls 0-31) arrives, it is held until the matching LSB (controls
+/// 32-63) arrives; if the LSB hasn't arrived after `timeout_events` further
+/// messages, the MSB is emitted as a plain 7-bit control change. An LSB on
+/// its own refines the last MSB of that control. Parameter select
+/// controls (98-101) are consumed, and data entry (6/38) for a selected
+/// parameter produces [`ControlEvent::RpnChange`].
+#[derive(Debug, Clone)]
+pub struct CcResolver {
+    channels: [ChannelState; 16],
+    pending: Vec<Pending>,
+    timeout_events: u64,
+    event_count: u64,
+}
+
+impl CcResolver {
+    /// Create a resolver that waits up to `timeout_events` messages for an LSB
+    pub fn new(timeout_events: u32) -> Self {
+        Self {
+            channels: [ChannelState::default(); 16],
+            pending: Vec::with_capacity(16),
+            timeout_events: timeout_events as u64,
+            event_count: 0,
+        }
+    }
+
+    pub fn timeout_events(&self) -> u32 {
+        self.timeout_events as u32
+    }
+
+    /// Process a message, emitting any control events it completes
+    ///
+    /// Non-CC messages produce nothing but count towards the LSB timeout.
+    pub fn process(&mut self, message: &MidiMessage, mut emit: impl FnMut(ControlEvent)) {
+        self.event_count += 1;
+        self.expire(&mut emit);
+
+        if let MidiMessage::ControlChange {
+            channel,
+            control,
+            value,
+        } = *message
+        {
+            self.control_change(channel, control.0, value, &mut emit);
+        }
+    }
+
+    /// Emit all held MSBs as plain control changes
+    pub fn flush(&mut self, mut emit: impl FnMut(ControlEvent)) {
+        for pending in self.pending.drain(..) {
+            emit(Self::plain(pending));
+        }
+    }
+
+    /// Clear parameter selections and held MSBs without emitting them
+    pub fn reset(&mut self) {
+        self.channels = [ChannelState::default(); 16];
+        self.pending.clear();
+    }
+
+    fn expire(&mut self, emit: &mut impl FnMut(ControlEvent)) {
+        let now = self.event_count;
+        self.pending.retain(|pending| {
+            if pending.deadline < now {
+                emit(Self::plain(*pending));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Event for an MSB whose LSB never arrived
+    fn plain(pending: Pending) -> ControlEvent {
+        match pending.parameter {
+            Some((kind, parameter)) => ControlEvent::RpnChange {
+                channel: MidiChannel(pending.channel),
+                kind,
+                parameter,
+                value: (pending.msb as u16) << 7,
+            },
+            None => ControlEvent::ControlChange {
+                channel: MidiChannel(pending.channel),
+                control: ControlNumber(pending.control),
+                value: pending.msb,
+            },
+        }
+    }
+
+    fn take_pending(&mut self, channel: u8, control: u8) -> Option<Pending> {
+        let index = self
+            .pending
+            .iter()
+            .position(|p| p.channel == channel && p.control == control)?;
+        Some(self.pending.remove(index))
+    }
+
+    fn control_change(
+        &mut self,
+        channel: MidiChannel,
+        control: u8,
+        value: u8,
+        emit: &mut impl FnMut(ControlEvent),
+    ) {
+        let ch = channel.0.min(15);
+        let state = &mut self.channels[ch as usize];
+
+        match control {
+            RPN_MSB | RPN_LSB | NRPN_MSB | NRPN_LSB => {
+                let kind = if control >= RPN_LSB {
+                    ParameterKind::Registered
+                } else {
+                    ParameterKind::NonRegistered
+                };
+                if state.kind != Some(kind) {
+                    state.parameter_msb = 0;
+                    state.parameter_lsb = 0;
+                    state.kind = Some(kind);
+                }
+                if control == RPN_MSB || control == NRPN_MSB {
+                    state.parameter_msb = value;
+                } else {
+                    state.parameter_lsb = value;
+                }
+            }
+            0..=31 => {
+                // A new MSB replaces one that is still waiting
+                if let Some(previous) = self.take_pending(ch, control) {
+                    emit(Self::plain(previous));
+                }
+                let state = &mut self.channels[ch as usize];
+                state.last_msb[control as usize] = Some(value);
+                let parameter = if control == DATA_ENTRY_MSB {
+                    state.selected()
+                } else {
+                    None
+                };
+                let pending = Pending {
+                    channel: ch,
+                    control,
+                    msb: value,
+                    parameter,
+                    deadline: self.event_count + self.timeout_events,
+                };
+                if self.timeout_events == 0 {
+                    emit(Self::plain(pending));
+                } else {
+                    self.pending.push(pending);
+                }
+            }
+            32..=63 => {
+                let msb_control = control - 32;
+                let msb = self
+                    .take_pending(ch, msb_control)
+                    .map(|p| p.msb)
+                    .or(self.channels[ch as usize].last_msb[msb_control as usize]);
+                let state = &self.channels[ch as usize];
+                match msb {
+                    Some(msb) => {
+                        let value = (msb as u16) << 7 | value as u16;
+                        let event = match (control, state.selected()) {
+                            (DATA_ENTRY_LSB, Some((kind, parameter))) => ControlEvent::RpnChange {
+                                channel,
+                                kind,
+                                parameter,
+                                value,
+                            },
+                            _ => ControlEvent::HighResControlChange {
+                                channel,
+                                control: ControlNumber(msb_control),
+                                value,
+                            },
+                        };
+                        emit(event);
+                    }
+                    None => emit(ControlEvent::ControlChange {
+                        channel,
+                        control: ControlNumber(control),
+                        value,
+                    }),
+                }
+            }
+            _ => emit(ControlEvent::ControlChange {
+                channel,
+                control: ControlNumber(control),
+                value,
+            }),
+        }
+    }
+}
+
+impl Default for CcResolver {
+    /// Wait up to 4 messages for an LSB
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cc(channel: u8, control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange {
+            channel: MidiChannel(channel),
+            control: ControlNumber(control),
+            value,
+        }
+    }
+
+    fn run(resolver: &mut CcResolver, messages: &[MidiMessage]) -> Vec<ControlEvent> {
+        let mut events = Vec::new();
+        for message in messages {
+            resolver.process(message, |e| events.push(e));
+        }
+        events
+    }
+
+    #[test]
+    fn test_pitch_bend_range_rpn() {
+        // Select RPN 0, set 12 semitones + 50 cents, then deselect
+        let mut resolver = CcResolver::default();
+        let events = run(
+            &mut resolver,
+            &[
+                cc(3, 101, 0),
+                cc(3, 100, 0),
+                cc(3, 6, 12),
+                cc(3, 38, 50),
+                cc(3, 101, 127),
+                cc(3, 100, 127),
+            ],
+        );
+        assert_eq!(
+            events,
+            vec![ControlEvent::RpnChange {
+                channel: MidiChannel(3),
+                kind: ParameterKind::Registered,
+                parameter: RPN_PITCH_BEND_SENSITIVITY,
+                value: 12 << 7 | 50,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_high_res_pair_and_lsb_timeout() {
+        let mut resolver = CcResolver::new(2);
+        let events = run(&mut resolver, &[cc(0, 1, 64), cc(0, 33, 1)]);
+        assert_eq!(
+            events,
+            vec![ControlEvent::HighResControlChange {
+                channel: MidiChannel(0),
+                control: ControlNumber::MODULATION,
+                value: 64 << 7 | 1,
+            }]
+        );
+
+        // MSB alone is emitted as a 7-bit CC once the timeout passes
+        let events = run(&mut resolver, &[cc(0, 7, 100), cc(0, 64, 0), cc(0, 65, 0)]);
+        assert_eq!(events.len(), 2);
+        let events = run(&mut resolver, &[cc(0, 66, 127)]);
+        assert_eq!(
+            events[0],
+            ControlEvent::ControlChange {
+                channel: MidiChannel(0),
+                control: ControlNumber::VOLUME,
+                value: 100,
+            }
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Persistent MIDI notes and clips
+
+use super::{
+    sort_events, MidiChannel, MidiEvent, MidiMessage, NoteNumber, SamplePosition, TimeConverter,
+    Velocity,
+};
+use serde::{Deserialize, Serialize};
+
+/// A note with a position and duration, as stored in a MIDI region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MidiNote {
+    /// Start position in ticks, relative to the clip start
+    pub start: i64,
+    /// Length in ticks
+    pub length: i64,
+    pub note: NoteNumber,
+    pub velocity: Velocity,
+    pub channel: MidiChannel,
+}
+
+impl MidiNote {
+    pub fn new(start: i64, length: i64, note: NoteNumber, velocity: Velocity) -> Self {
+        Self {
+            start,
+            length: length.max(1),
+            note,
+            velocity,
+            channel: MidiChannel::default(),
+        }
+    }
+
+    /// End position in ticks (exclusive)
+    pub fn end(&self) -> i64 {
+        self.start + self.length
+    }
+
+    /// Check whether the note overlaps the tick range `start..end`
+    pub fn overlaps(&self, start: i64, end: i64) -> bool {
+        self.start < end && self.end() > start
+    }
+}
+
+/// A note whose note-on has been rendered but whose note-off has not
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HangingNote {
+    note: NoteNumber,
+    channel: MidiChannel,
+    end: SamplePosition,
+}
+
+/// A collection of notes, kept sorted by start position
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MidiClip {
+    notes: Vec<MidiNote>,
+    /// Notes started by `render` and still waiting for their note-off
+    #[serde(skip)]
+    hanging: Vec<HangingNote>,
+}
+
+impl MidiClip {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All notes, sorted by start position
+    pub fn notes(&self) -> &[MidiNote] {
+        &self.notes
+    }
+
+    pub fn len(&self) -> usize {
+        self.notes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    /// Add a note, returning its index
+    pub fn add(&mut self, note: MidiNote) -> usize {
+        let index = self.notes.partition_point(|n| n.start <= note.start);
+        self.notes.insert(index, note);
+        index
+    }
+
+    /// Remove the note at `index`
+    pub fn remove(&mut self, index: usize) -> Option<MidiNote> {
+        (index < self.notes.len()).then(|| self.notes.remove(index))
+    }
+
+    /// Notes overlapping the tick range `start..end`, with their indices
+    pub fn find_overlapping(
+        &self,
+        start: i64,
+        end: i64,
+    ) -> impl Iterator<Item = (usize, &MidiNote)> + '_ {
+        // Notes are sorted by start, so nothing at or after `end` can overlap
+        let limit = self.notes.partition_point(|n| n.start < end);
+        self.notes[..limit]
+            .iter()
+            .enumerate()
+            .filter(move |(_, n)| n.overlaps(start, end))
+    }
+
+    /// Render note events for the sample range `start..start + frames`
+    ///
+    /// Appends events with offsets relative to `start`, in time order with
+    /// note-offs before note-ons at the same offset. Notes are triggered when
+    /// their start falls in the range; their note-offs are remembered and
+    /// emitted in whichever later call reaches them, even if the clip is
+    /// edited or playback jumps in between.
+    pub fn render(
+        &mut self,
+        start: SamplePosition,
+        frames: usize,
+        converter: &TimeConverter,
+        out: &mut Vec<MidiEvent>,
+    ) {
+        let first = out.len();
+        let end = SamplePosition(start.0 + frames as i64);
+
+        let start_tick = converter.samples_to_ticks(start) - 1;
+        let end_tick = converter.samples_to_ticks(end) + 1;
+        for note in &self.notes {
+            if note.start > end_tick {
+                break;
+            }
+            if note.start < start_tick {
+                continue;
+            }
+            let on = converter.ticks_to_samples(note.start);
+            if on < start || on >= end {
+                continue;
+            }
+            out.push(MidiEvent::new(
+                (on - start).0 as usize,
+                MidiMessage::NoteOn {
+                    channel: note.channel,
+                    note: note.note,
+                    velocity: note.velocity,
+                },
+            ));
+            self.hanging.push(HangingNote {
+                note: note.note,
+                channel: note.channel,
+                end: converter.ticks_to_samples(note.end()),
+            });
+        }
+
+        self.hanging.retain(|hanging| {
+            if hanging.end >= end {
+                return true;
+            }
+            out.push(MidiEvent::new(
+                (hanging.end - start).0.max(0) as usize,
+                MidiMessage::NoteOff {
+                    channel: hanging.channel,
+                    note: hanging.note,
+                    velocity: Velocity::OFF,
+                },
+            ));
+            false
+        });
+
+        sort_events(&mut out[first..]);
+    }
+
+    /// Emit note-offs at offset 0 for every note still sounding (e.g. on stop)
+    pub fn release_all(&mut self, out: &mut Vec<MidiEvent>) {
+        for hanging in self.hanging.drain(..) {
+            out.push(MidiEvent::new(
+                0,
+                MidiMessage::NoteOff {
+                    channel: hanging.channel,
+                    note: hanging.note,
+                    velocity: Velocity::OFF,
+                },
+            ));
+        }
+    }
+
+    /// Number of notes currently sounding from previous renders
+    pub fn hanging_count(&self) -> usize {
+        self.hanging.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{SampleRate, Tempo, TimeSignature, TICKS_PER_QUARTER_NOTE};
+
+    #[test]
+    fn test_render_across_blocks_with_hanging_note() {
+        // 120 BPM at 48 kHz: one quarter note = 24000 samples
+        let converter = TimeConverter::new(
+            SampleRate::DVD_QUALITY,
+            Tempo::DEFAULT,
+            TimeSignature::COMMON_TIME,
+        );
+        let quarter = TICKS_PER_QUARTER_NOTE as i64;
+        let mut clip = MidiClip::new();
+        clip.add(MidiNote::new(
+            quarter,
+            quarter,
+            NoteNumber(64),
+            Velocity(90),
+        ));
+        clip.add(MidiNote::new(0, quarter, NoteNumber(60), Velocity(100)));
+        assert_eq!(clip.notes()[0].note, NoteNumber(60));
+        assert_eq!(clip.find_overlapping(quarter - 1, quarter).count(), 1);
+
+        let mut events = Vec::new();
+        clip.render(SamplePosition(0), 20000, &converter, &mut events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(clip.hanging_count(), 1);
+
+        // Second block contains the off of note 60 and the on of note 64
+        // at the same offset; the off comes first
+        events.clear();
+        clip.render(SamplePosition(20000), 20000, &converter, &mut events);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].sample_offset, 4000);
+        assert!(matches!(events[0].message, MidiMessage::NoteOff { note, .. } if note.0 == 60));
+        assert!(matches!(events[1].message, MidiMessage::NoteOn { note, .. } if note.0 == 64));
+
+        events.clear();
+        clip.release_all(&mut events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(clip.hanging_count(), 0);
+    }
+
+    #[test]
+    fn test_serde_skips_playback_state() {
+        let mut clip = MidiClip::new();
+        clip.add(MidiNote::new(
+            0,
+            480,
+            NoteNumber::MIDDLE_C,
+            Velocity::default(),
+        ));
+        let json = serde_json::to_string(&clip).unwrap();
+        let restored: MidiClip = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.notes(), clip.notes());
+    }
+}
@@ -0,0 +1,245 @@
+//! Channel, message kind and note range filtering of MIDI input
+
+use super::{MidiChannel, MidiMessage, NoteNumber, NoteTracker};
+
+/// Kinds of channel message, for filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MidiMessageKind {
+    /// Note on and note off
+    Note,
+    ControlChange,
+    ProgramChange,
+    PitchBend,
+    ChannelPressure,
+    PolyPressure,
+}
+
+impl MidiMessageKind {
+    /// Bit of this kind in [`MidiFilter::allowed_kinds`]
+    pub const fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl MidiMessage {
+    pub fn kind(&self) -> MidiMessageKind {
+        match self {
+            MidiMessage::NoteOn { .. } | MidiMessage::NoteOff { .. } => MidiMessageKind::Note,
+            MidiMessage::ControlChange { .. } => MidiMessageKind::ControlChange,
+            MidiMessage::ProgramChange { .. } => MidiMessageKind::ProgramChange,
+            MidiMessage::PitchBend { .. } => MidiMessageKind::PitchBend,
+            MidiMessage::ChannelPressure { .. } => MidiMessageKind::ChannelPressure,
+            MidiMessage::PolyPressure { .. } => MidiMessageKind::PolyPressure,
+        }
+    }
+
+    /// The same message on another channel
+    pub fn with_channel(self, channel: MidiChannel) -> Self {
+        let mut message = self;
+        match &mut message {
+            MidiMessage::NoteOn { channel: c, .. }
+            | MidiMessage::NoteOff { channel: c, .. }
+            | MidiMessage::ControlChange { channel: c, .. }
+            | MidiMessage::ProgramChange { channel: c, .. }
+            | MidiMessage::PitchBend { channel: c, .. }
+            | MidiMessage::ChannelPressure { channel: c, .. }
+            | MidiMessage::PolyPressure { channel: c, .. } => *c = channel,
+        }
+        message
+    }
+}
+
+/// Filters and optionally remaps the MIDI input of a route
+///
+/// Note-offs follow their note-on: a note-off passes only if the note-on it
+/// ends was passed, even if the filter settings changed in between, so
+/// notes are never blocked on and never left hanging.
+#[derive(Debug, Clone)]
+pub struct MidiFilter {
+    /// Allowed input channels, bit `n` for channel `n`
+    pub allowed_channels: u16,
+    /// Allowed message kinds, see [`MidiMessageKind::bit`]
+    pub allowed_kinds: u8,
+    /// Lowest note passed (notes and poly pressure)
+    pub note_low: NoteNumber,
+    /// Highest note passed (notes and poly pressure)
+    pub note_high: NoteNumber,
+    /// Channel that passed messages are moved to
+    pub remap_channel: Option<MidiChannel>,
+    /// Note-ons that were passed, by input channel
+    passed: NoteTracker,
+}
+
+impl MidiFilter {
+    pub const ALL_CHANNELS: u16 = u16::MAX;
+    pub const ALL_KINDS: u8 = u8::MAX;
+
+    /// A filter that passes everything unchanged
+    pub fn new() -> Self {
+        Self {
+            allowed_channels: Self::ALL_CHANNELS,
+            allowed_kinds: Self::ALL_KINDS,
+            note_low: NoteNumber(0),
+            note_high: NoteNumber(127),
+            remap_channel: None,
+            passed: NoteTracker::new(),
+        }
+    }
+
+    /// A filter that passes a single input channel
+    pub fn channel(channel: MidiChannel) -> Self {
+        Self {
+            allowed_channels: 1 << channel.0.min(15),
+            ..Self::new()
+        }
+    }
+
+    pub fn with_remap(mut self, channel: MidiChannel) -> Self {
+        self.remap_channel = Some(channel);
+        self
+    }
+
+    pub fn with_note_range(mut self, low: NoteNumber, high: NoteNumber) -> Self {
+        self.note_low = low;
+        self.note_high = high;
+        self
+    }
+
+    pub fn allows_channel(&self, channel: MidiChannel) -> bool {
+        self.allowed_channels & (1 << channel.0.min(15)) != 0
+    }
+
+    pub fn allows_kind(&self, kind: MidiMessageKind) -> bool {
+        self.allowed_kinds & kind.bit() != 0
+    }
+
+    fn allows_note(&self, note: NoteNumber) -> bool {
+        (self.note_low.0..=self.note_high.0).contains(&note.0)
+    }
+
+    /// Filter a message, returning it (possibly remapped) if it passes
+    pub fn process(&mut self, message: MidiMessage) -> Option<MidiMessage> {
+        let passes = match message {
+            MidiMessage::NoteOn { channel, note, .. }
+            | MidiMessage::NoteOff { channel, note, .. }
+                if !Self::starts_note(&message) =>
+            {
+                let passes = self.passed.is_note_held(channel, note);
+                if passes {
+                    self.passed.process(&message);
+                }
+                passes
+            }
+            _ => {
+                let passes = self.allows_channel(message.channel())
+                    && self.allows_kind(message.kind())
+                    && match message {
+                        MidiMessage::NoteOn { note, .. }
+                        | MidiMessage::PolyPressure { note, .. } => self.allows_note(note),
+                        _ => true,
+                    };
+                if passes && Self::starts_note(&message) {
+                    self.passed.process(&message);
+                }
+                passes
+            }
+        };
+
+        passes.then(|| match self.remap_channel {
+            Some(channel) => message.with_channel(channel),
+            None => message,
+        })
+    }
+
+    /// Forget passed notes (e.g. after sending all-notes-off)
+    pub fn reset(&mut self) {
+        self.passed.reset();
+    }
+
+    fn starts_note(message: &MidiMessage) -> bool {
+        matches!(message, MidiMessage::NoteOn { velocity, .. } if velocity.0 > 0)
+    }
+}
+
+impl Default for MidiFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ControlNumber, Velocity};
+
+    fn all_variants(channel: u8) -> Vec<MidiMessage> {
+        let channel = MidiChannel(channel);
+        let note = NoteNumber(61);
+        vec![
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity: Velocity(99),
+            },
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity: Velocity(17),
+            },
+            MidiMessage::ControlChange {
+                channel,
+                control: ControlNumber::PAN,
+                value: 33,
+            },
+            MidiMessage::ProgramChange {
+                channel,
+                program: 42,
+            },
+            MidiMessage::PitchBend {
+                channel,
+                value: -1234,
+            },
+            MidiMessage::ChannelPressure {
+                channel,
+                pressure: 77,
+            },
+            MidiMessage::PolyPressure {
+                channel,
+                note,
+                pressure: 55,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_remap_preserves_payload() {
+        let mut filter = MidiFilter::channel(MidiChannel(2)).with_remap(MidiChannel(9));
+        let output: Vec<_> = all_variants(2)
+            .into_iter()
+            .map(|m| filter.process(m))
+            .collect();
+        let expected: Vec<_> = all_variants(9).into_iter().map(Some).collect();
+        assert_eq!(output, expected);
+
+        assert!(all_variants(3)
+            .into_iter()
+            .all(|m| filter.process(m).is_none()));
+    }
+
+    #[test]
+    fn test_note_off_follows_note_on() {
+        let [note_on, note_off, ..] = all_variants(0)[..] else {
+            unreachable!()
+        };
+        let mut filter = MidiFilter::new().with_note_range(NoteNumber(0), NoteNumber(60));
+        // Blocked note-on: its note-off is blocked too
+        assert_eq!(filter.process(note_on), None);
+        assert_eq!(filter.process(note_off), None);
+
+        // Passed note-on: its note-off passes even after narrowing the filter
+        filter.note_high = NoteNumber(127);
+        assert_eq!(filter.process(note_on), Some(note_on));
+        filter.allowed_channels = 0;
+        assert_eq!(filter.process(note_off), Some(note_off));
+    }
+}
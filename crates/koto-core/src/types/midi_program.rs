@@ -0,0 +1,163 @@
+//! Bank select and program change handling
+
+use super::{MidiChannel, MidiMessage};
+
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+
+/// A program selection produced by [`ProgramSelector`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgramEvent {
+    /// Program change preceded by bank select
+    PatchSelect {
+        channel: MidiChannel,
+        /// 14-bit bank number (`MSB << 7 | LSB`)
+        bank: u16,
+        program: u8,
+    },
+    /// Program change without a (recent) bank select
+    ProgramChange { channel: MidiChannel, program: u8 },
+}
+
+/// Bank select received on a channel, waiting for its program change
+#[derive(Debug, Clone, Copy, Default)]
+struct PendingBank {
+    msb: Option<u8>,
+    lsb: Option<u8>,
+    /// Event count after which the bank select is discarded
+    deadline: u64,
+}
+
+/// Combines bank select (CC 0/32) with the following program change
+///
+/// A bank select only applies to a program change that arrives within
+/// `timeout_events` further messages; after that it is discarded and a
+/// later program change is reported without a bank. A missing LSB counts
+/// as 0, as does a missing MSB.
+#[derive(Debug, Clone)]
+pub struct ProgramSelector {
+    pending: [Option<PendingBank>; 16],
+    timeout_events: u64,
+    event_count: u64,
+}
+
+impl ProgramSelector {
+    /// Create a selector that waits up to `timeout_events` messages for a
+    /// program change after a bank select
+    pub fn new(timeout_events: u32) -> Self {
+        Self {
+            pending: [None; 16],
+            timeout_events: timeout_events as u64,
+            event_count: 0,
+        }
+    }
+
+    /// Process a message, returning a program event if it completes one
+    pub fn process(&mut self, message: &MidiMessage) -> Option<ProgramEvent> {
+        self.event_count += 1;
+        let now = self.event_count;
+        let timeout = self.timeout_events;
+        let slot = &mut self.pending[message.channel().0.min(15) as usize];
+        if slot.is_some_and(|pending| pending.deadline < now) {
+            *slot = None;
+        }
+
+        match *message {
+            MidiMessage::ControlChange { control, value, .. }
+                if control.0 == BANK_SELECT_MSB || control.0 == BANK_SELECT_LSB =>
+            {
+                let pending = slot.get_or_insert_with(PendingBank::default);
+                if control.0 == BANK_SELECT_MSB {
+                    pending.msb = Some(value);
+                } else {
+                    pending.lsb = Some(value);
+                }
+                pending.deadline = now + timeout;
+                None
+            }
+            MidiMessage::ProgramChange { channel, program } => Some(match slot.take() {
+                Some(bank) => ProgramEvent::PatchSelect {
+                    channel,
+                    bank: (bank.msb.unwrap_or(0) as u16) << 7 | bank.lsb.unwrap_or(0) as u16,
+                    program,
+                },
+                None => ProgramEvent::ProgramChange { channel, program },
+            }),
+            _ => None,
+        }
+    }
+
+    /// Discard pending bank selects
+    pub fn reset(&mut self) {
+        self.pending = [None; 16];
+    }
+}
+
+impl Default for ProgramSelector {
+    /// Wait up to 4 messages for a program change
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ControlNumber;
+
+    fn cc(control: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange {
+            channel: MidiChannel(1),
+            control: ControlNumber(control),
+            value,
+        }
+    }
+
+    fn program(program: u8) -> MidiMessage {
+        MidiMessage::ProgramChange {
+            channel: MidiChannel(1),
+            program,
+        }
+    }
+
+    #[test]
+    fn test_msb_only_and_full_bank() {
+        let mut selector = ProgramSelector::default();
+        assert_eq!(selector.process(&cc(0, 2)), None);
+        assert_eq!(
+            selector.process(&program(5)),
+            Some(ProgramEvent::PatchSelect {
+                channel: MidiChannel(1),
+                bank: 2 << 7,
+                program: 5,
+            })
+        );
+
+        selector.process(&cc(0, 1));
+        selector.process(&cc(32, 3));
+        assert_eq!(
+            selector.process(&program(7)),
+            Some(ProgramEvent::PatchSelect {
+                channel: MidiChannel(1),
+                bank: 1 << 7 | 3,
+                program: 7,
+            })
+        );
+    }
+
+    #[test]
+    fn test_bank_select_without_program_change_times_out() {
+        let mut selector = ProgramSelector::new(2);
+        selector.process(&cc(0, 4));
+        selector.process(&cc(7, 100));
+        selector.process(&cc(7, 90));
+        selector.process(&cc(7, 80));
+        assert_eq!(
+            selector.process(&program(9)),
+            Some(ProgramEvent::ProgramChange {
+                channel: MidiChannel(1),
+                program: 9,
+            })
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Compact serialization of MIDI messages and events
+//!
+//! Messages are written as raw MIDI bytes (`[status, data1, data2]`) and
+//! events as `[sample_offset, status, data1, data2]`, which is several times
+//! smaller than the field-by-field form for dense regions. The
+//! `readable-midi-serde` feature writes the field-by-field form instead.
+//! Both forms are accepted when reading, so files written before the
+//! compact format still load.
+
+use super::{ControlNumber, MidiChannel, MidiEvent, MidiMessage, NoteNumber, Velocity};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Version written by [`MidiEventList`]
+///
+/// Version 1 was a bare list of events in the field-by-field form.
+pub const MIDI_SERDE_VERSION: u32 = 2;
+
+/// Field-by-field form of [`MidiMessage`], as it was originally derived
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "MidiMessage")]
+enum ReadableMessage {
+    NoteOn {
+        channel: MidiChannel,
+        note: NoteNumber,
+        velocity: Velocity,
+    },
+    NoteOff {
+        channel: MidiChannel,
+        note: NoteNumber,
+        velocity: Velocity,
+    },
+    ControlChange {
+        channel: MidiChannel,
+        control: ControlNumber,
+        value: u8,
+    },
+    ProgramChange {
+        channel: MidiChannel,
+        program: u8,
+    },
+    PitchBend {
+        channel: MidiChannel,
+        value: i16,
+    },
+    ChannelPressure {
+        channel: MidiChannel,
+        pressure: u8,
+    },
+    PolyPressure {
+        channel: MidiChannel,
+        note: NoteNumber,
+        pressure: u8,
+    },
+}
+
+/// Field-by-field form of [`MidiEvent`]
+#[derive(Serialize, Deserialize)]
+struct ReadableEvent {
+    sample_offset: usize,
+    message: MidiMessage,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MessageRepr {
+    Compact([u8; 3]),
+    Readable(#[serde(with = "ReadableMessage")] MidiMessage),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EventRepr {
+    Compact(usize, u8, u8, u8),
+    Readable(ReadableEvent),
+}
+
+fn decode<E: serde::de::Error>(bytes: [u8; 3]) -> Result<MidiMessage, E> {
+    MidiMessage::from_bytes(&bytes)
+        .ok_or_else(|| E::custom(format!("invalid MIDI message bytes {bytes:02X?}")))
+}
+
+impl Serialize for MidiMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if cfg!(feature = "readable-midi-serde") {
+            ReadableMessage::serialize(self, serializer)
+        } else {
+            self.to_bytes().serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MidiMessage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match MessageRepr::deserialize(deserializer)? {
+            MessageRepr::Compact(bytes) => decode(bytes),
+            MessageRepr::Readable(message) => Ok(message),
+        }
+    }
+}
+
+impl Serialize for MidiEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if cfg!(feature = "readable-midi-serde") {
+            ReadableEvent {
+                sample_offset: self.sample_offset,
+                message: self.message,
+            }
+            .serialize(serializer)
+        } else {
+            let [status, data1, data2] = self.message.to_bytes();
+            (self.sample_offset, status, data1, data2).serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MidiEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match EventRepr::deserialize(deserializer)? {
+            EventRepr::Compact(sample_offset, status, data1, data2) => Ok(MidiEvent::new(
+                sample_offset,
+                decode([status, data1, data2])?,
+            )),
+            EventRepr::Readable(event) => Ok(MidiEvent::new(event.sample_offset, event.message)),
+        }
+    }
+}
+
+/// A list of MIDI events stored with a format version
+///
+/// Written as `{"version": 2, "events": [...]}`. A bare list (version 1) is
+/// also accepted when reading.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MidiEventList(pub Vec<MidiEvent>);
+
+#[derive(Serialize)]
+struct TaggedListRef<'a> {
+    version: u32,
+    events: &'a [MidiEvent],
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ListRepr {
+    Tagged {
+        version: u32,
+        events: Vec<MidiEvent>,
+    },
+    Legacy(Vec<MidiEvent>),
+}
+
+impl Serialize for MidiEventList {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TaggedListRef {
+            version: MIDI_SERDE_VERSION,
+            events: &self.0,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MidiEventList {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match ListRepr::deserialize(deserializer)? {
+            ListRepr::Tagged { version, events } if version <= MIDI_SERDE_VERSION => {
+                Ok(Self(events))
+            }
+            ListRepr::Tagged { version, .. } => Err(D::Error::custom(format!(
+                "unsupported MIDI event format version {version}"
+            ))),
+            ListRepr::Legacy(events) => Ok(Self(events)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_variants() -> Vec<MidiEvent> {
+        let channel = MidiChannel(5);
+        let note = NoteNumber(72);
+        [
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity: Velocity(100),
+            },
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity: Velocity(12),
+            },
+            MidiMessage::ControlChange {
+                channel,
+                control: ControlNumber::SUSTAIN,
+                value: 127,
+            },
+            MidiMessage::ProgramChange {
+                channel,
+                program: 19,
+            },
+            MidiMessage::PitchBend {
+                channel,
+                value: -8192,
+            },
+            MidiMessage::PitchBend {
+                channel,
+                value: 8191,
+            },
+            MidiMessage::ChannelPressure {
+                channel,
+                pressure: 64,
+            },
+            MidiMessage::PolyPressure {
+                channel,
+                note,
+                pressure: 3,
+            },
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, message)| MidiEvent::new(i * 100, message))
+        .collect()
+    }
+
+    /// Field-by-field JSON of a bare event list, regardless of features
+    fn readable_json(events: &[MidiEvent]) -> String {
+        let values: Vec<serde_json::Value> = events
+            .iter()
+            .map(|e| {
+                let message =
+                    ReadableMessage::serialize(&e.message, serde_json::value::Serializer).unwrap();
+                serde_json::json!({ "sample_offset": e.sample_offset, "message": message })
+            })
+            .collect();
+        serde_json::to_string(&values).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_and_legacy_files() {
+        let events = all_variants();
+        let list = MidiEventList(events.clone());
+        let json = serde_json::to_string(&list).unwrap();
+        assert!(json.starts_with(r#"{"version":2,"#));
+        let restored: MidiEventList = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, list);
+
+        // Version 1 files: a bare list in the field-by-field form
+        let legacy = readable_json(&events);
+        let restored: MidiEventList = serde_json::from_str(&legacy).unwrap();
+        assert_eq!(restored.0, events);
+
+        assert!(serde_json::from_str::<MidiEventList>(r#"{"version":99,"events":[]}"#).is_err());
+        assert!(serde_json::from_str::<MidiMessage>("[240, 0, 0]").is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "readable-midi-serde"))]
+    fn test_compact_size_for_dense_region() {
+        let events: Vec<MidiEvent> = (0..10_000)
+            .map(|i| {
+                let message = if i % 2 == 0 {
+                    MidiMessage::NoteOn {
+                        channel: MidiChannel(0),
+                        note: NoteNumber((i % 128) as u8),
+                        velocity: Velocity(100),
+                    }
+                } else {
+                    MidiMessage::NoteOff {
+                        channel: MidiChannel(0),
+                        note: NoteNumber((i % 128) as u8),
+                        velocity: Velocity::OFF,
+                    }
+                };
+                MidiEvent::new(i * 480, message)
+            })
+            .collect();
+
+        let compact = serde_json::to_string(&MidiEventList(events.clone())).unwrap();
+        let readable = readable_json(&events);
+        assert!(compact.len() < 10_000 * 24, "{} bytes", compact.len());
+        assert!(compact.len() * 4 < readable.len());
+    }
+}
@@ -1,9 +1,40 @@
 //! Core types for Koto DAW
 
 mod audio;
+mod control_names;
+mod delay_compensation;
+mod fade;
+mod interleave;
+mod marker;
 mod midi;
+mod midi_cc;
+mod midi_clip;
+mod midi_filter;
+mod midi_program;
+mod midi_serde;
+mod mpe;
+mod note_tracker;
+mod ring_buffer;
+mod silence;
 mod time;
+mod track;
+mod velocity;
 
 pub use audio::*;
+pub use delay_compensation::*;
+pub use fade::*;
+pub use interleave::*;
+pub use marker::*;
 pub use midi::*;
+pub use midi_cc::*;
+pub use midi_clip::*;
+pub use midi_filter::*;
+pub use midi_program::*;
+pub use midi_serde::*;
+pub use mpe::*;
+pub use note_tracker::*;
+pub use ring_buffer::*;
+pub use silence::*;
 pub use time::*;
+pub use track::*;
+pub use velocity::*;
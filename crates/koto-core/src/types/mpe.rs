@@ -0,0 +1,284 @@
+//! MIDI Polyphonic Expression (MPE) zones and per-note expression tracking
+
+use super::{ControlNumber, MidiChannel, MidiEvent, MidiMessage, NoteNumber, PitchBend};
+
+/// Controller carrying per-note timbre (third dimension) in MPE
+const TIMBRE: ControlNumber = ControlNumber(74);
+
+/// Which end of the channel range an MPE zone occupies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum MpeZone {
+    /// Master channel 1, member channels counting up from 2
+    #[default]
+    Lower,
+    /// Master channel 16, member channels counting down from 15
+    Upper,
+}
+
+/// Layout and bend ranges of an MPE zone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MpeZoneConfig {
+    pub zone: MpeZone,
+    /// Number of member channels (1-15)
+    pub member_channels: u8,
+    /// Bend range of member channels in semitones
+    pub note_bend_range: f32,
+    /// Bend range of the master channel in semitones
+    pub master_bend_range: f32,
+}
+
+impl MpeZoneConfig {
+    pub fn new(zone: MpeZone, member_channels: u8) -> Self {
+        Self {
+            zone,
+            member_channels: member_channels.clamp(1, 15),
+            ..Self::default()
+        }
+    }
+
+    pub fn master_channel(&self) -> MidiChannel {
+        match self.zone {
+            MpeZone::Lower => MidiChannel(0),
+            MpeZone::Upper => MidiChannel(15),
+        }
+    }
+
+    /// Whether a channel is one of the zone's member channels
+    pub fn is_member(&self, channel: MidiChannel) -> bool {
+        let count = self.member_channels.clamp(1, 15);
+        match self.zone {
+            MpeZone::Lower => (1..=count).contains(&channel.0),
+            MpeZone::Upper => (15 - count..=14).contains(&channel.0),
+        }
+    }
+}
+
+impl Default for MpeZoneConfig {
+    /// Lower zone over all 15 member channels with the MPE default ranges
+    fn default() -> Self {
+        Self {
+            zone: MpeZone::Lower,
+            member_channels: 15,
+            note_bend_range: 48.0,
+            master_bend_range: 2.0,
+        }
+    }
+}
+
+/// A per-note expression value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expression {
+    /// Per-note bend in semitones (master channel bend not included)
+    PitchBend(f32),
+    /// Pressure (0.0 to 1.0)
+    Pressure(f32),
+    /// Timbre, CC 74 (0.0 to 1.0)
+    Timbre(f32),
+}
+
+/// An expression change for one sounding note
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoteExpression {
+    /// Sample offset within the current buffer
+    pub sample_offset: usize,
+    /// Identifies the note from its note-on to its note-off
+    pub note_id: u32,
+    pub channel: MidiChannel,
+    pub note: NoteNumber,
+    pub expression: Expression,
+}
+
+/// Current expression values, per member channel and per note
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ExpressionState {
+    bend: f32,
+    pressure: f32,
+    timbre: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ActiveNote {
+    id: u32,
+    channel: MidiChannel,
+    note: NoteNumber,
+    state: ExpressionState,
+}
+
+/// Turns channel-wide messages on MPE member channels into per-note
+/// expression
+///
+/// Each note-on on a member channel starts a note with a new id, taking the
+/// bend, pressure and timbre last sent on its channel (MPE controllers send
+/// initial values just before the note-on). Later changes on that channel
+/// apply to the notes sounding on it, so notes on different channels keep
+/// independent values.
+#[derive(Debug, Clone)]
+pub struct MpeTracker {
+    config: MpeZoneConfig,
+    channels: [ExpressionState; 16],
+    notes: Vec<ActiveNote>,
+    next_id: u32,
+}
+
+impl MpeTracker {
+    pub fn new(config: MpeZoneConfig) -> Self {
+        Self {
+            config,
+            channels: [ExpressionState::default(); 16],
+            notes: Vec::with_capacity(16),
+            next_id: 0,
+        }
+    }
+
+    pub fn config(&self) -> &MpeZoneConfig {
+        &self.config
+    }
+
+    /// Change the zone layout; forgets sounding notes
+    pub fn set_config(&mut self, config: MpeZoneConfig) {
+        self.config = config;
+        self.reset();
+    }
+
+    /// Process an event, emitting expression changes for the notes it affects
+    pub fn process(&mut self, event: &MidiEvent, mut emit: impl FnMut(NoteExpression)) {
+        let channel = event.message.channel();
+        if !self.config.is_member(channel) {
+            return;
+        }
+        let index = channel.0.min(15) as usize;
+
+        let expression = match event.message {
+            MidiMessage::NoteOn { note, velocity, .. } if velocity.0 > 0 => {
+                self.notes.push(ActiveNote {
+                    id: self.next_id,
+                    channel,
+                    note,
+                    state: self.channels[index],
+                });
+                self.next_id = self.next_id.wrapping_add(1);
+                return;
+            }
+            MidiMessage::NoteOn { note, .. } | MidiMessage::NoteOff { note, .. } => {
+                self.notes
+                    .retain(|active| active.channel != channel || active.note != note);
+                return;
+            }
+            MidiMessage::PitchBend { value, .. } => {
+                Expression::PitchBend(PitchBend::new(value).semitones(self.config.note_bend_range))
+            }
+            MidiMessage::ChannelPressure { pressure, .. } => {
+                Expression::Pressure(pressure.min(127) as f32 / 127.0)
+            }
+            MidiMessage::ControlChange { control, value, .. } if control == TIMBRE => {
+                Expression::Timbre(value.min(127) as f32 / 127.0)
+            }
+            _ => return,
+        };
+
+        let channel_state = &mut self.channels[index];
+        Self::apply(channel_state, expression);
+        for active in self.notes.iter_mut().filter(|n| n.channel == channel) {
+            Self::apply(&mut active.state, expression);
+            emit(NoteExpression {
+                sample_offset: event.sample_offset,
+                note_id: active.id,
+                channel,
+                note: active.note,
+                expression,
+            });
+        }
+    }
+
+    /// Current per-note bend in semitones of a sounding note
+    pub fn note_bend(&self, note_id: u32) -> Option<f32> {
+        self.notes
+            .iter()
+            .find(|n| n.id == note_id)
+            .map(|n| n.state.bend)
+    }
+
+    /// Ids of sounding notes, oldest first
+    pub fn active_note_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.notes.iter().map(|n| n.id)
+    }
+
+    /// Forget sounding notes and channel expression
+    pub fn reset(&mut self) {
+        self.channels = [ExpressionState::default(); 16];
+        self.notes.clear();
+    }
+
+    fn apply(state: &mut ExpressionState, expression: Expression) {
+        match expression {
+            Expression::PitchBend(semitones) => state.bend = semitones,
+            Expression::Pressure(pressure) => state.pressure = pressure,
+            Expression::Timbre(timbre) => state.timbre = timbre,
+        }
+    }
+}
+
+impl Default for MpeTracker {
+    fn default() -> Self {
+        Self::new(MpeZoneConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Velocity;
+
+    fn event(message: MidiMessage) -> MidiEvent {
+        MidiEvent::new(0, message)
+    }
+
+    fn note_on(channel: u8, note: u8) -> MidiEvent {
+        event(MidiMessage::NoteOn {
+            channel: MidiChannel(channel),
+            note: NoteNumber(note),
+            velocity: Velocity::default(),
+        })
+    }
+
+    fn bend(channel: u8, value: i16) -> MidiEvent {
+        event(MidiMessage::PitchBend {
+            channel: MidiChannel(channel),
+            value,
+        })
+    }
+
+    #[test]
+    fn test_overlapping_notes_keep_independent_bend() {
+        let mut tracker = MpeTracker::default();
+        let mut expressions = Vec::new();
+        for e in [
+            bend(1, 4096),
+            note_on(1, 60),
+            note_on(2, 64),
+            bend(2, PitchBend::MIN.0),
+            bend(0, 8191),
+        ] {
+            tracker.process(&e, |x| expressions.push(x));
+        }
+
+        // Initial bend before the note-on carries over to the note
+        assert!((tracker.note_bend(0).unwrap() - 24.0).abs() < 0.01);
+        assert_eq!(tracker.note_bend(1), Some(-48.0));
+        // Only the note on channel 2 was affected; master channel ignored
+        assert_eq!(expressions.len(), 1);
+        assert_eq!(expressions[0].note_id, 1);
+        assert_eq!(expressions[0].note, NoteNumber(64));
+        assert_eq!(expressions[0].expression, Expression::PitchBend(-48.0));
+    }
+
+    #[test]
+    fn test_upper_zone_members() {
+        let config = MpeZoneConfig::new(MpeZone::Upper, 3);
+        assert_eq!(config.master_channel(), MidiChannel(15));
+        assert!(config.is_member(MidiChannel(12)));
+        assert!(config.is_member(MidiChannel(14)));
+        assert!(!config.is_member(MidiChannel(11)));
+        assert!(!config.is_member(MidiChannel(15)));
+    }
+}
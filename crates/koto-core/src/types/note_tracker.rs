@@ -0,0 +1,218 @@
+//! Tracking of sounding notes for panic and note chase
+
+use super::{ControlNumber, MidiChannel, MidiMessage, NoteNumber, Velocity};
+
+/// All Sound Off (CC 120) also silences held notes
+const ALL_SOUND_OFF: u8 = 120;
+
+/// Note and sustain pedal state of one channel
+#[derive(Debug, Clone, Copy)]
+struct ChannelNotes {
+    /// Outstanding note-ons per note (a pitch can be struck again before
+    /// it is released)
+    held: [u8; 128],
+    /// Notes released while the pedal was down, one bit per note
+    sustained: u128,
+    pedal_down: bool,
+}
+
+impl Default for ChannelNotes {
+    fn default() -> Self {
+        Self {
+            held: [0; 128],
+            sustained: 0,
+            pedal_down: false,
+        }
+    }
+}
+
+impl ChannelNotes {
+    fn is_sounding(&self, note: u8) -> bool {
+        self.held[note as usize] > 0 || self.sustained & (1 << note) != 0
+    }
+
+    fn release_all(&mut self) {
+        self.held = [0; 128];
+        self.sustained = 0;
+    }
+}
+
+/// Tracks which notes are sounding on each channel
+///
+/// Feed it every message sent to an instrument with
+/// [`process`](Self::process). A note counts as sounding while its key is
+/// held, or after its note-off while the sustain pedal (CC 64) is down.
+#[derive(Debug, Clone)]
+pub struct NoteTracker {
+    channels: Box<[ChannelNotes; 16]>,
+}
+
+impl NoteTracker {
+    pub fn new() -> Self {
+        Self {
+            channels: Box::new([ChannelNotes::default(); 16]),
+        }
+    }
+
+    /// Update the state from a message
+    pub fn process(&mut self, message: &MidiMessage) {
+        let state = &mut self.channels[message.channel().0.min(15) as usize];
+        match *message {
+            MidiMessage::NoteOn { note, velocity, .. } if velocity.0 > 0 => {
+                let count = &mut state.held[note.0.min(127) as usize];
+                *count = count.saturating_add(1);
+            }
+            MidiMessage::NoteOn { note, .. } | MidiMessage::NoteOff { note, .. } => {
+                let note = note.0.min(127);
+                let count = &mut state.held[note as usize];
+                *count = count.saturating_sub(1);
+                if *count == 0 && state.pedal_down {
+                    state.sustained |= 1 << note;
+                }
+            }
+            MidiMessage::ControlChange { control, value, .. } => match control {
+                ControlNumber::SUSTAIN => {
+                    state.pedal_down = value >= 64;
+                    if !state.pedal_down {
+                        state.sustained = 0;
+                    }
+                }
+                ControlNumber::ALL_NOTES_OFF | ControlNumber(ALL_SOUND_OFF) => {
+                    state.release_all();
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Whether a note is sounding (held or sustained by the pedal)
+    pub fn is_note_held(&self, channel: MidiChannel, note: NoteNumber) -> bool {
+        self.channels[channel.0.min(15) as usize].is_sounding(note.0.min(127))
+    }
+
+    /// Whether the sustain pedal is down on a channel
+    pub fn is_sustain_down(&self, channel: MidiChannel) -> bool {
+        self.channels[channel.0.min(15) as usize].pedal_down
+    }
+
+    /// All sounding notes, ordered by channel then note
+    pub fn active_notes(&self) -> Vec<(MidiChannel, NoteNumber)> {
+        let mut notes = Vec::new();
+        for (channel, state) in self.channels.iter().enumerate() {
+            for note in 0..128u8 {
+                if state.is_sounding(note) {
+                    notes.push((MidiChannel(channel as u8), NoteNumber(note)));
+                }
+            }
+        }
+        notes
+    }
+
+    /// Call `emit` with the messages needed to silence every sounding note
+    ///
+    /// A sustain-off comes first on channels with the pedal down, so the
+    /// receiver doesn't keep sustaining, followed by one note-off per
+    /// outstanding note-on. Does not allocate and does not change the
+    /// tracker's state.
+    pub fn for_each_note_off(&self, mut emit: impl FnMut(MidiMessage)) {
+        for (channel, state) in self.channels.iter().enumerate() {
+            let channel = MidiChannel(channel as u8);
+            if state.pedal_down {
+                emit(MidiMessage::ControlChange {
+                    channel,
+                    control: ControlNumber::SUSTAIN,
+                    value: 0,
+                });
+            }
+            for note in 0..128u8 {
+                let count = state.held[note as usize].max(state.is_sounding(note) as u8);
+                for _ in 0..count {
+                    emit(MidiMessage::NoteOff {
+                        channel,
+                        note: NoteNumber(note),
+                        velocity: Velocity::OFF,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Messages that silence every sounding note (see
+    /// [`for_each_note_off`](Self::for_each_note_off))
+    pub fn generate_note_offs(&self) -> Vec<MidiMessage> {
+        let mut messages = Vec::new();
+        self.for_each_note_off(|message| messages.push(message));
+        messages
+    }
+
+    /// Forget all notes and pedal state
+    pub fn reset(&mut self) {
+        *self.channels = [ChannelNotes::default(); 16];
+    }
+}
+
+impl Default for NoteTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(note: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            channel: MidiChannel(0),
+            note: NoteNumber(note),
+            velocity: Velocity::default(),
+        }
+    }
+
+    fn note_off(note: u8) -> MidiMessage {
+        MidiMessage::NoteOff {
+            channel: MidiChannel(0),
+            note: NoteNumber(note),
+            velocity: Velocity::OFF,
+        }
+    }
+
+    fn sustain(value: u8) -> MidiMessage {
+        MidiMessage::ControlChange {
+            channel: MidiChannel(0),
+            control: ControlNumber::SUSTAIN,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_sustain_pedal_holds_released_notes() {
+        let mut tracker = NoteTracker::new();
+        for message in [sustain(127), note_on(60), note_off(60)] {
+            tracker.process(&message);
+        }
+        assert!(tracker.is_note_held(MidiChannel(0), NoteNumber(60)));
+        assert_eq!(tracker.generate_note_offs(), vec![sustain(0), note_off(60)]);
+
+        tracker.process(&sustain(0));
+        assert!(tracker.active_notes().is_empty());
+        assert!(tracker.generate_note_offs().is_empty());
+    }
+
+    #[test]
+    fn test_double_note_on_needs_two_note_offs() {
+        let mut tracker = NoteTracker::new();
+        tracker.process(&note_on(64));
+        tracker.process(&note_on(64));
+        assert_eq!(
+            tracker.generate_note_offs(),
+            vec![note_off(64), note_off(64)]
+        );
+
+        tracker.process(&note_off(64));
+        assert!(tracker.is_note_held(MidiChannel(0), NoteNumber(64)));
+        tracker.process(&note_off(64));
+        assert!(!tracker.is_note_held(MidiChannel(0), NoteNumber(64)));
+    }
+}
@@ -0,0 +1,197 @@
+//! Fixed-capacity circular audio buffer
+
+use super::Sample;
+use thiserror::Error;
+
+/// What happens when a write does not fit in the remaining space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowMode {
+    /// Discard the oldest samples to make room
+    #[default]
+    OverwriteOldest,
+    /// Reject the whole write and leave the buffer untouched
+    Reject,
+}
+
+/// Error returned when a write is rejected in [`OverflowMode::Reject`]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("Ring buffer full: {requested} samples requested, {free} free")]
+pub struct RingBufferFull {
+    pub requested: usize,
+    pub free: usize,
+}
+
+/// A single-threaded circular buffer of samples
+///
+/// All storage is allocated up front, so reads and writes never allocate
+/// and are safe to use inside the audio callback.
+#[derive(Debug, Clone)]
+pub struct AudioRingBuffer {
+    data: Vec<Sample>,
+    /// Index of the oldest sample
+    read_pos: usize,
+    /// Number of samples currently stored
+    len: usize,
+    mode: OverflowMode,
+}
+
+impl AudioRingBuffer {
+    /// Create a ring buffer that overwrites the oldest samples when full
+    pub fn new(capacity: usize) -> Self {
+        Self::with_mode(capacity, OverflowMode::OverwriteOldest)
+    }
+
+    /// Create a ring buffer with the given overflow behavior
+    pub fn with_mode(capacity: usize, mode: OverflowMode) -> Self {
+        Self {
+            data: vec![0.0; capacity],
+            read_pos: 0,
+            len: 0,
+            mode,
+        }
+    }
+
+    /// Total number of samples the buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of samples available to read
+    pub fn available(&self) -> usize {
+        self.len
+    }
+
+    /// Number of samples that can be written without overflowing
+    pub fn free(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    pub fn mode(&self) -> OverflowMode {
+        self.mode
+    }
+
+    /// Write samples to the buffer
+    ///
+    /// In [`OverflowMode::OverwriteOldest`] this always succeeds; if more
+    /// samples are written than fit, only the newest `capacity()` are kept.
+    /// In [`OverflowMode::Reject`] a write larger than [`free`](Self::free)
+    /// fails and nothing is written.
+    pub fn write(&mut self, samples: &[Sample]) -> Result<(), RingBufferFull> {
+        let capacity = self.capacity();
+        if samples.len() > self.free() {
+            if self.mode == OverflowMode::Reject {
+                return Err(RingBufferFull {
+                    requested: samples.len(),
+                    free: self.free(),
+                });
+            }
+            if capacity == 0 {
+                return Ok(());
+            }
+        }
+
+        // Only the newest `capacity` samples can survive
+        let samples = &samples[samples.len().saturating_sub(capacity)..];
+        let overflow = (self.len + samples.len()).saturating_sub(capacity);
+        self.read_pos = (self.read_pos + overflow) % capacity.max(1);
+        self.len -= overflow;
+
+        let write_pos = (self.read_pos + self.len) % capacity.max(1);
+        let first = samples.len().min(capacity - write_pos);
+        self.data[write_pos..write_pos + first].copy_from_slice(&samples[..first]);
+        self.data[..samples.len() - first].copy_from_slice(&samples[first..]);
+        self.len += samples.len();
+
+        Ok(())
+    }
+
+    /// Read and remove the oldest samples
+    ///
+    /// Returns the number of samples read, which is less than `out.len()`
+    /// if fewer samples are available. The rest of `out` is left untouched.
+    pub fn read(&mut self, out: &mut [Sample]) -> usize {
+        let count = self.peek(out);
+        self.skip(count);
+        count
+    }
+
+    /// Copy the oldest samples without removing them
+    pub fn peek(&self, out: &mut [Sample]) -> usize {
+        let count = out.len().min(self.len);
+        let first = count.min(self.capacity() - self.read_pos);
+        out[..first].copy_from_slice(&self.data[self.read_pos..self.read_pos + first]);
+        out[first..count].copy_from_slice(&self.data[..count - first]);
+        count
+    }
+
+    /// Discard up to `count` of the oldest samples
+    pub fn skip(&mut self, count: usize) {
+        let count = count.min(self.len);
+        if count > 0 {
+            self.read_pos = (self.read_pos + count) % self.capacity();
+            self.len -= count;
+        }
+    }
+
+    /// Discard all stored samples
+    pub fn clear(&mut self) {
+        self.read_pos = 0;
+        self.len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_larger_than_capacity_keeps_newest() {
+        let mut ring = AudioRingBuffer::new(4);
+        ring.write(&[1.0, 2.0, 3.0]).unwrap();
+        ring.write(&[4.0, 5.0, 6.0, 7.0, 8.0, 9.0]).unwrap();
+        assert_eq!(ring.available(), 4);
+
+        let mut out = [0.0; 6];
+        assert_eq!(ring.read(&mut out), 4);
+        assert_eq!(&out[..4], &[6.0, 7.0, 8.0, 9.0]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_read_across_wrap_point() {
+        let mut ring = AudioRingBuffer::new(5);
+        ring.write(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+        let mut out = [0.0; 3];
+        ring.read(&mut out);
+        ring.write(&[5.0, 6.0, 7.0]).unwrap();
+
+        let mut out = [0.0; 4];
+        assert_eq!(ring.read(&mut out), 4);
+        assert_eq!(out, [4.0, 5.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn test_reject_mode_leaves_buffer_untouched() {
+        let mut ring = AudioRingBuffer::with_mode(4, OverflowMode::Reject);
+        ring.write(&[1.0, 2.0, 3.0]).unwrap();
+        let err = ring.write(&[4.0, 5.0]).unwrap_err();
+        assert_eq!(
+            err,
+            RingBufferFull {
+                requested: 2,
+                free: 1
+            }
+        );
+        assert_eq!(ring.available(), 3);
+        ring.write(&[4.0]).unwrap();
+        assert!(ring.is_full());
+    }
+}
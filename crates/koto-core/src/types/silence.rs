@@ -0,0 +1,88 @@
+//! Silence tracking for sparse processing
+
+use std::ops::{BitAnd, BitOr};
+
+/// Bitset marking which of up to 64 buffers (or input ports) are silent
+///
+/// Bit `n` set means buffer `n` is silent. The graph propagates these so a
+/// node whose inputs are all silent can skip processing, and mixing stages
+/// can skip silent sources entirely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct SilenceFlags(pub u64);
+
+impl SilenceFlags {
+    /// Maximum number of buffers that can be tracked
+    pub const CAPACITY: usize = 64;
+
+    /// No buffers marked silent
+    pub const NONE: Self = Self(0);
+
+    /// The first `count` buffers marked silent
+    pub fn all(count: usize) -> Self {
+        if count >= Self::CAPACITY {
+            Self(u64::MAX)
+        } else {
+            Self((1u64 << count) - 1)
+        }
+    }
+
+    /// Build flags by checking each buffer against a threshold
+    ///
+    /// Buffers past [`CAPACITY`](Self::CAPACITY) are treated as non-silent.
+    pub fn from_buffers(buffers: &[super::AudioBuffer], threshold: super::Sample) -> Self {
+        let mut flags = Self::NONE;
+        for (index, buffer) in buffers.iter().enumerate().take(Self::CAPACITY) {
+            flags.set(index, buffer.is_silent(threshold));
+        }
+        flags
+    }
+
+    /// Mark a buffer as silent or not; indices past the capacity are ignored
+    pub fn set(&mut self, index: usize, silent: bool) {
+        if index < Self::CAPACITY {
+            if silent {
+                self.0 |= 1 << index;
+            } else {
+                self.0 &= !(1 << index);
+            }
+        }
+    }
+
+    /// Whether a buffer is marked silent (indices past the capacity never are)
+    pub fn is_silent(&self, index: usize) -> bool {
+        index < Self::CAPACITY && self.0 & (1 << index) != 0
+    }
+
+    /// Whether all of the first `count` buffers are silent
+    ///
+    /// Always false past [`CAPACITY`](Self::CAPACITY), since those buffers
+    /// can't be marked silent.
+    pub fn all_silent(&self, count: usize) -> bool {
+        if count > Self::CAPACITY {
+            return false;
+        }
+        let mask = Self::all(count).0;
+        self.0 & mask == mask
+    }
+
+    /// Number of buffers marked silent
+    pub fn count(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+}
+
+impl BitAnd for SilenceFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for SilenceFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
@@ -6,6 +6,34 @@ use serde::{Deserialize, Serialize};
 /// Ticks per quarter note (PPQ) - standard MIDI resolution
 pub const TICKS_PER_QUARTER_NOTE: i32 = 960;
 
+/// Slowest supported playback rate
+pub const MIN_PLAYBACK_RATE: f64 = 0.25;
+/// Fastest supported playback rate
+pub const MAX_PLAYBACK_RATE: f64 = 4.0;
+
+/// Clamp a playback rate to the supported range (non-finite rates become 1.0)
+pub fn clamp_playback_rate(rate: f64) -> f64 {
+    if rate.is_finite() {
+        rate.clamp(MIN_PLAYBACK_RATE, MAX_PLAYBACK_RATE)
+    } else {
+        1.0
+    }
+}
+
+/// Timeline samples covered by `frames` output frames at `rate`
+///
+/// The fractional part is carried in `fraction` between calls so the
+/// playhead doesn't drift. A rate of exactly 1.0 returns `frames` unchanged.
+pub fn timeline_samples(frames: usize, rate: f64, fraction: &mut f64) -> usize {
+    if rate == 1.0 {
+        return frames;
+    }
+    let exact = frames as f64 * rate + *fraction;
+    let whole = exact.floor();
+    *fraction = exact - whole;
+    whole as usize
+}
+
 /// Position in samples (absolute)
 #[derive(
     Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
@@ -44,6 +72,140 @@ impl std::ops::Sub for SamplePosition {
     }
 }
 
+/// A half-open range of sample positions, `start..end`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SampleRange {
+    pub start: SamplePosition,
+    pub end: SamplePosition,
+}
+
+impl SampleRange {
+    pub fn new(start: SamplePosition, end: SamplePosition) -> Self {
+        Self { start, end }
+    }
+
+    /// Length in samples (negative if `end` is before `start`)
+    pub fn length(&self) -> i64 {
+        self.end.0 - self.start.0
+    }
+
+    /// Whether the range has no positive length
+    pub fn is_empty(&self) -> bool {
+        self.length() <= 0
+    }
+
+    pub fn contains(&self, position: SamplePosition) -> bool {
+        self.start <= position && position < self.end
+    }
+
+    /// Advance `position` by `frames`, wrapping back to `start` on reaching
+    /// `end`
+    ///
+    /// Wrapping only happens when `position` is before `end`, so a playhead
+    /// placed after the range plays on. The overshoot is carried past
+    /// `start`.
+    pub fn advance_looped(
+        &self,
+        position: SamplePosition,
+        frames: usize,
+    ) -> (SamplePosition, Option<LoopWrap>) {
+        let next = position.0 + frames as i64;
+        if self.is_empty() || position >= self.end || next < self.end.0 {
+            return (SamplePosition(next), None);
+        }
+        let overshoot = (next - self.end.0) % self.length();
+        let wrap = LoopWrap {
+            at_frame_offset: (self.end.0 - position.0) as usize,
+        };
+        (SamplePosition(self.start.0 + overshoot), Some(wrap))
+    }
+}
+
+/// Where in a block the playhead jumped back to the loop start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoopWrap {
+    /// Frame within the block at which playback continues from the loop start
+    pub at_frame_offset: usize,
+}
+
+/// Where the playhead goes when playback is stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum StopBehavior {
+    /// Leave the playhead where playback stopped
+    #[default]
+    StayAtPosition,
+    /// Jump back to the start of the project
+    ReturnToStart,
+    /// Jump back to where playback was last started
+    ReturnToLastPlayStart,
+}
+
+impl StopBehavior {
+    /// Playhead position after stopping at `position`
+    pub fn stop_position(
+        &self,
+        position: SamplePosition,
+        play_start: SamplePosition,
+    ) -> SamplePosition {
+        match self {
+            Self::StayAtPosition => position,
+            Self::ReturnToStart => SamplePosition::ZERO,
+            Self::ReturnToLastPlayStart => play_start,
+        }
+    }
+}
+
+/// What a seek outside the loop does while playing with looping enabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum SeekPolicy {
+    /// Go to the target and stop following the loop until the playhead
+    /// enters the loop range again
+    #[default]
+    FollowOnReentry,
+    /// Keep the playhead in the loop: targets before the range go to its
+    /// start, targets at or after its end wrap to the start
+    ClampToLoop,
+}
+
+impl SeekPolicy {
+    /// Where a seek to `target` lands while looping over `range`, and whether
+    /// the loop is followed from there
+    pub fn resolve(&self, target: SamplePosition, range: SampleRange) -> (SamplePosition, bool) {
+        if range.is_empty() || range.contains(target) {
+            return (target, true);
+        }
+        match self {
+            Self::FollowOnReentry => (target, false),
+            Self::ClampToLoop => (range.start, true),
+        }
+    }
+}
+
+/// How far before the playhead pre-roll playback starts
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PreRoll {
+    Bars(u8),
+    Seconds(f64),
+}
+
+impl PreRoll {
+    /// Position to start playing from so that `position` is reached after
+    /// the pre-roll, clamped at zero
+    pub fn start_position(
+        &self,
+        position: SamplePosition,
+        converter: &TimeConverter,
+    ) -> SamplePosition {
+        let length = match *self {
+            Self::Bars(bars) => {
+                converter.musical_to_samples(MusicalTime::new(bars as i32 + 1, 1, 0))
+            }
+            Self::Seconds(seconds) => converter.seconds_to_samples(seconds.max(0.0)),
+        };
+        SamplePosition((position.0 - length.0).max(0))
+    }
+}
+
 /// Musical time position (bars, beats, ticks)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct MusicalTime {
@@ -153,6 +315,18 @@ impl Default for Tempo {
     }
 }
 
+/// Video frame rate in frames per second
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameRate(pub f64);
+
+impl FrameRate {
+    pub const FPS_24: Self = Self(24.0);
+    pub const FPS_25: Self = Self(25.0);
+    /// NTSC rate, 30000/1001
+    pub const FPS_29_97: Self = Self(30000.0 / 1001.0);
+    pub const FPS_30: Self = Self(30.0);
+}
+
 /// Time converter for converting between different time representations
 pub struct TimeConverter {
     sample_rate: SampleRate,
@@ -169,6 +343,10 @@ impl TimeConverter {
         }
     }
 
+    pub fn time_signature(&self) -> TimeSignature {
+        self.time_signature
+    }
+
     pub fn samples_to_seconds(&self, samples: SamplePosition) -> f64 {
         samples.to_seconds(self.sample_rate)
     }
@@ -177,6 +355,10 @@ impl TimeConverter {
         SamplePosition::from_seconds(seconds, self.sample_rate)
     }
 
+    pub fn frames_to_samples(&self, frames: i64, rate: FrameRate) -> SamplePosition {
+        self.seconds_to_samples(frames as f64 / rate.0)
+    }
+
     pub fn samples_to_musical(&self, samples: SamplePosition) -> MusicalTime {
         let seconds = self.samples_to_seconds(samples);
         let beats = seconds * self.tempo.bpm() / 60.0;
@@ -184,6 +366,16 @@ impl TimeConverter {
         MusicalTime::from_ticks(total_ticks, self.time_signature.beats_per_bar())
     }
 
+    pub fn ticks_to_samples(&self, ticks: i64) -> SamplePosition {
+        SamplePosition(
+            (ticks as f64 * self.tempo.samples_per_tick(self.sample_rate)).round() as i64,
+        )
+    }
+
+    pub fn samples_to_ticks(&self, samples: SamplePosition) -> i64 {
+        (samples.0 as f64 / self.tempo.samples_per_tick(self.sample_rate)).round() as i64
+    }
+
     pub fn musical_to_samples(&self, time: MusicalTime) -> SamplePosition {
         let total_ticks = time.to_ticks(self.time_signature.beats_per_bar());
         let beats = total_ticks as f64 / TICKS_PER_QUARTER_NOTE as f64;
@@ -191,3 +383,68 @@ impl TimeConverter {
         self.seconds_to_samples(seconds)
     }
 }
+
+/// How positions are shown to the user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TimeDisplay {
+    /// `m:ss.mmm`
+    #[default]
+    MinutesSeconds,
+    /// `bar.beat.tick`, 1-based bars and beats
+    BarsBeats,
+    /// Raw sample count
+    Samples,
+}
+
+impl TimeDisplay {
+    pub fn format(&self, position: SamplePosition, converter: &TimeConverter) -> String {
+        let sign = if position.0 < 0 { "-" } else { "" };
+        let position = SamplePosition(position.0.abs());
+        match self {
+            Self::MinutesSeconds => {
+                let millis = (converter.samples_to_seconds(position) * 1000.0).round() as i64;
+                format!(
+                    "{sign}{}:{:02}.{:03}",
+                    millis / 60_000,
+                    millis / 1000 % 60,
+                    millis % 1000
+                )
+            }
+            Self::BarsBeats => {
+                let time = converter.samples_to_musical(position);
+                format!("{sign}{}.{}.{:03}", time.bar, time.beat, time.tick)
+            }
+            Self::Samples => format!("{sign}{}", position.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_display() {
+        let converter = TimeConverter::new(
+            SampleRate(48000),
+            Tempo::DEFAULT,
+            TimeSignature::COMMON_TIME,
+        );
+        // Bar 3, beat 2 at 120 BPM: 9 beats of half a second
+        let position = SamplePosition(216_000);
+        assert_eq!(
+            TimeDisplay::MinutesSeconds.format(position, &converter),
+            "0:04.500"
+        );
+        assert_eq!(
+            TimeDisplay::BarsBeats.format(position, &converter),
+            "3.2.000"
+        );
+        assert_eq!(TimeDisplay::Samples.format(position, &converter), "216000");
+        let long = converter.seconds_to_samples(754.25);
+        assert_eq!(
+            TimeDisplay::MinutesSeconds.format(long, &converter),
+            "12:34.250"
+        );
+    }
+}
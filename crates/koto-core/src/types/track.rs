@@ -0,0 +1,7 @@
+//! Track identifiers shared between the timeline and the engine
+
+use serde::{Deserialize, Serialize};
+
+/// Unique identifier for tracks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrackId(pub u64);
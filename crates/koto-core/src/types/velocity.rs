@@ -0,0 +1,169 @@
+//! Velocity curves for shaping incoming note velocities
+
+use super::{MidiMessage, Velocity};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Errors from validating a custom velocity curve
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum VelocityCurveError {
+    #[error("Velocity curve has no breakpoints")]
+    Empty,
+
+    #[error("Breakpoint ({0}, {1}) is outside 0-127")]
+    OutOfRange(u8, u8),
+
+    #[error("Breakpoint inputs must be strictly increasing (got {previous} then {next})")]
+    NotMonotonic { previous: u8, next: u8 },
+}
+
+/// Mapping from played velocity to output velocity
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum VelocityCurve {
+    /// Velocities pass through unchanged
+    #[default]
+    Linear,
+    /// Boosts light playing (square-root response)
+    Soft,
+    /// Requires harder playing for loud notes (square response)
+    Hard,
+    /// Breakpoints `(input, output)` with linear interpolation between them
+    ///
+    /// Inputs below the first or above the last breakpoint map to that
+    /// breakpoint's output. Build with [`VelocityCurve::custom`] to validate.
+    Custom(Vec<(u8, u8)>),
+}
+
+impl VelocityCurve {
+    /// Create a validated custom curve
+    pub fn custom(breakpoints: Vec<(u8, u8)>) -> Result<Self, VelocityCurveError> {
+        let curve = Self::Custom(breakpoints);
+        curve.validate()?;
+        Ok(curve)
+    }
+
+    /// Check that a custom table is non-empty, in range and has strictly
+    /// increasing inputs
+    pub fn validate(&self) -> Result<(), VelocityCurveError> {
+        let Self::Custom(points) = self else {
+            return Ok(());
+        };
+        if points.is_empty() {
+            return Err(VelocityCurveError::Empty);
+        }
+        if let Some(&(input, output)) = points.iter().find(|(i, o)| *i > 127 || *o > 127) {
+            return Err(VelocityCurveError::OutOfRange(input, output));
+        }
+        if let Some(pair) = points.windows(2).find(|pair| pair[1].0 <= pair[0].0) {
+            return Err(VelocityCurveError::NotMonotonic {
+                previous: pair[0].0,
+                next: pair[1].0,
+            });
+        }
+        Ok(())
+    }
+
+    /// Map a velocity through the curve
+    pub fn apply(&self, velocity: Velocity) -> Velocity {
+        let input = velocity.0.min(127);
+        let output = match self {
+            Self::Linear => return Velocity(input),
+            Self::Soft => 127.0 * (input as f32 / 127.0).sqrt(),
+            Self::Hard => 127.0 * (input as f32 / 127.0).powi(2),
+            Self::Custom(points) => Self::interpolate(points, input),
+        };
+        Velocity::new(output.round() as u8)
+    }
+
+    /// Apply the curve to note-on velocities, leaving other messages alone
+    ///
+    /// A note-on with velocity 0 is a note-off and is left untouched; any
+    /// other note-on stays at velocity 1 or above so it isn't turned into one.
+    pub fn apply_to_message(&self, message: MidiMessage) -> MidiMessage {
+        match message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity.0 > 0 => MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity: Velocity(self.apply(velocity).0.max(1)),
+            },
+            other => other,
+        }
+    }
+
+    fn interpolate(points: &[(u8, u8)], input: u8) -> f32 {
+        let (Some(&first), Some(&last)) = (points.first(), points.last()) else {
+            return input as f32;
+        };
+        if input <= first.0 {
+            return first.1 as f32;
+        }
+        if input >= last.0 {
+            return last.1 as f32;
+        }
+        points
+            .windows(2)
+            .find(|pair| input <= pair[1].0)
+            .map(|pair| {
+                let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+                let span = (x1 as f32 - x0 as f32).max(1.0);
+                let t = (input as f32 - x0 as f32) / span;
+                y0 as f32 + (y1 as f32 - y0 as f32) * t
+            })
+            .unwrap_or(last.1 as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{MidiChannel, NoteNumber};
+
+    #[test]
+    fn test_custom_curve_interpolation_and_validation() {
+        let curve = VelocityCurve::custom(vec![(10, 20), (64, 100), (127, 127)]).unwrap();
+        // At breakpoints
+        assert_eq!(curve.apply(Velocity(10)), Velocity(20));
+        assert_eq!(curve.apply(Velocity(64)), Velocity(100));
+        assert_eq!(curve.apply(Velocity(127)), Velocity(127));
+        // Between breakpoints and outside the table
+        assert_eq!(curve.apply(Velocity(37)), Velocity(60));
+        assert_eq!(curve.apply(Velocity(1)), Velocity(20));
+
+        assert_eq!(
+            VelocityCurve::custom(vec![(10, 20), (10, 30)]),
+            Err(VelocityCurveError::NotMonotonic {
+                previous: 10,
+                next: 10
+            })
+        );
+        assert_eq!(
+            VelocityCurve::custom(Vec::new()),
+            Err(VelocityCurveError::Empty)
+        );
+    }
+
+    #[test]
+    fn test_apply_to_message_only_touches_note_on() {
+        let note_on = |velocity| MidiMessage::NoteOn {
+            channel: MidiChannel(0),
+            note: NoteNumber::MIDDLE_C,
+            velocity: Velocity(velocity),
+        };
+        let curve = VelocityCurve::Hard;
+        assert_eq!(curve.apply_to_message(note_on(64)), note_on(32));
+        assert_eq!(curve.apply_to_message(note_on(0)), note_on(0));
+        assert_eq!(curve.apply_to_message(note_on(3)), note_on(1));
+
+        let note_off = MidiMessage::NoteOff {
+            channel: MidiChannel(0),
+            note: NoteNumber::MIDDLE_C,
+            velocity: Velocity(64),
+        };
+        assert_eq!(curve.apply_to_message(note_off), note_off);
+        assert!(VelocityCurve::Soft.apply(Velocity(32)).0 > 32);
+    }
+}
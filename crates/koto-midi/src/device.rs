@@ -1,7 +1,8 @@
 //! MIDI device management
 
-use koto_core::{KotoError, KotoResult};
-use midir::{MidiInput, MidiOutput};
+use crate::MidiParser;
+use koto_core::{KotoError, KotoResult, MidiMessage};
+use midir::{MidiInput, MidiInputConnection, MidiOutput};
 
 /// MIDI device info
 #[derive(Debug, Clone)]
@@ -68,6 +69,41 @@ impl MidiDeviceManager {
             })
             .unwrap_or_default()
     }
+
+    /// Open an input port and deliver parsed messages to `on_message`
+    ///
+    /// Raw bytes are fed through a [`MidiParser`], so running status and
+    /// split packets are handled. The callback receives the driver
+    /// timestamp in microseconds. Input stops when the returned connection
+    /// is dropped.
+    pub fn connect_input<F>(
+        &self,
+        port_number: usize,
+        mut on_message: F,
+    ) -> KotoResult<MidiInputConnection<MidiParser>>
+    where
+        F: FnMut(u64, MidiMessage) + Send + 'static,
+    {
+        // midir consumes the client on connect, so use a dedicated one
+        let midi_in =
+            MidiInput::new("Koto MIDI Input").map_err(|e| KotoError::MidiDevice(e.to_string()))?;
+        let port = midi_in
+            .ports()
+            .into_iter()
+            .nth(port_number)
+            .ok_or_else(|| KotoError::MidiDevice(format!("No input port {}", port_number)))?;
+
+        midi_in
+            .connect(
+                &port,
+                "koto-input",
+                move |timestamp, bytes, parser: &mut MidiParser| {
+                    parser.push_bytes(bytes, |message| on_message(timestamp, message));
+                },
+                MidiParser::new(),
+            )
+            .map_err(|e| KotoError::MidiDevice(e.to_string()))
+    }
 }
 
 impl Default for MidiDeviceManager {
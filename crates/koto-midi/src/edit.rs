@@ -0,0 +1,239 @@
+//! Quantize and humanize operations on recorded MIDI
+//!
+//! Both operate on events whose sample offsets are positions from the start
+//! of the recording. Only note-ons are moved; each note-off moves with its
+//! note-on so note lengths are preserved. Other events stay where they are.
+
+use koto_core::{sort_events, MidiEvent, MidiMessage, SamplePosition, TimeConverter, Velocity};
+use std::collections::{HashMap, VecDeque};
+
+/// For each note-on, the index of the note-off that ends it
+///
+/// Overlapping notes of the same pitch and channel are paired first in,
+/// first out. `events` must be sorted.
+fn pair_notes(events: &[MidiEvent]) -> Vec<Option<usize>> {
+    let mut pairs = vec![None; events.len()];
+    let mut open: HashMap<(u8, u8), VecDeque<usize>> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        match event.message {
+            MidiMessage::NoteOn {
+                channel,
+                note,
+                velocity,
+            } if velocity.0 > 0 => {
+                open.entry((channel.0, note.0))
+                    .or_default()
+                    .push_back(index);
+            }
+            MidiMessage::NoteOn { channel, note, .. }
+            | MidiMessage::NoteOff { channel, note, .. } => {
+                if let Some(on) = open
+                    .get_mut(&(channel.0, note.0))
+                    .and_then(|queue| queue.pop_front())
+                {
+                    pairs[on] = Some(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    pairs
+}
+
+fn is_note_start(event: &MidiEvent) -> bool {
+    matches!(event.message, MidiMessage::NoteOn { velocity, .. } if velocity.0 > 0)
+}
+
+fn shift(event: &mut MidiEvent, delta: i64) {
+    event.sample_offset = (event.sample_offset as i64 + delta).max(0) as usize;
+}
+
+/// Move note-ons (and their note-offs) towards a grid
+///
+/// `strength` is the fraction of the distance moved (0.0 leaves notes
+/// alone, 1.0 snaps them). `swing` (0.0 to 1.0) delays every other grid line
+/// by up to half a grid step. The events are left sorted.
+pub fn quantize_events(
+    events: &mut [MidiEvent],
+    grid_ticks: i64,
+    strength: f32,
+    swing: f32,
+    converter: &TimeConverter,
+) {
+    sort_events(events);
+    if grid_ticks <= 0 {
+        return;
+    }
+    let strength = strength.clamp(0.0, 1.0) as f64;
+    let swing_ticks = swing.clamp(0.0, 1.0) as f64 * grid_ticks as f64 / 2.0;
+    let grid_line = |index: i64| -> f64 {
+        let straight = (index * grid_ticks) as f64;
+        if index.rem_euclid(2) == 1 {
+            straight + swing_ticks
+        } else {
+            straight
+        }
+    };
+
+    let pairs = pair_notes(events);
+    for index in 0..events.len() {
+        if !is_note_start(&events[index]) {
+            continue;
+        }
+        let position = SamplePosition(events[index].sample_offset as i64);
+        let ticks = converter.samples_to_ticks(position) as f64;
+        let below = (ticks / grid_ticks as f64).floor() as i64;
+        // With swing the nearest line may be either neighbour of the
+        // straight grid cell, so compare the three candidates
+        let target = [below - 1, below, below + 1]
+            .into_iter()
+            .map(grid_line)
+            .min_by(|a, b| (a - ticks).abs().total_cmp(&(b - ticks).abs()))
+            .unwrap_or(ticks);
+
+        let target_samples = converter.ticks_to_samples(target.round() as i64).0;
+        let delta = ((target_samples - position.0) as f64 * strength).round() as i64;
+        shift(&mut events[index], delta);
+        if let Some(off) = pairs[index] {
+            shift(&mut events[off], delta);
+        }
+    }
+    sort_events(events);
+}
+
+/// Small xorshift generator so humanizing is repeatable from a seed
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn new(seed: u64) -> Self {
+        // xorshift must never be seeded with zero
+        Self(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    /// Uniform integer in `-range..=range`
+    fn next_in(&mut self, range: i64) -> i64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        if range <= 0 {
+            return 0;
+        }
+        (x % (2 * range as u64 + 1)) as i64 - range
+    }
+}
+
+/// Randomly offset note timing and velocity
+///
+/// Each note-on moves by up to `timing_jitter_ticks` either way (its
+/// note-off moves with it) and its velocity changes by up to
+/// `velocity_jitter`, staying within 1-127. The same seed always gives the
+/// same result. The events are left sorted.
+pub fn humanize_events(
+    events: &mut [MidiEvent],
+    timing_jitter_ticks: i64,
+    velocity_jitter: u8,
+    seed: u64,
+    converter: &TimeConverter,
+) {
+    sort_events(events);
+    let mut rng = Xorshift::new(seed);
+    let pairs = pair_notes(events);
+    for index in 0..events.len() {
+        if !is_note_start(&events[index]) {
+            continue;
+        }
+        let jitter = rng.next_in(timing_jitter_ticks);
+        let delta = converter.ticks_to_samples(jitter).0;
+        shift(&mut events[index], delta);
+        if let Some(off) = pairs[index] {
+            shift(&mut events[off], delta);
+        }
+
+        let change = rng.next_in(velocity_jitter as i64);
+        if let MidiMessage::NoteOn { velocity, .. } = &mut events[index].message {
+            *velocity = Velocity((velocity.0 as i64 + change).clamp(1, 127) as u8);
+        }
+    }
+    sort_events(events);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{MidiChannel, NoteNumber, SampleRate, Tempo, TimeSignature};
+
+    /// 48 kHz at 120 BPM: 25 samples per tick
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate::DVD_QUALITY,
+            Tempo::DEFAULT,
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    fn note(on_tick: usize, off_tick: usize, note: u8) -> [MidiEvent; 2] {
+        let channel = MidiChannel(0);
+        let note = NoteNumber(note);
+        [
+            MidiEvent::new(
+                on_tick * 25,
+                MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity: Velocity(100),
+                },
+            ),
+            MidiEvent::new(
+                off_tick * 25,
+                MidiMessage::NoteOff {
+                    channel,
+                    note,
+                    velocity: Velocity::OFF,
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_quantize_strength_moves_note_off_with_note_on() {
+        let mut events: Vec<MidiEvent> = [note(100, 300, 60), note(500, 600, 62)]
+            .into_iter()
+            .flatten()
+            .collect();
+        quantize_events(&mut events, 240, 0.5, 0.0, &converter());
+        let offsets: Vec<usize> = events.iter().map(|e| e.sample_offset / 25).collect();
+        // 100 -> halfway to 0, 500 -> halfway to 480; lengths kept
+        assert_eq!(offsets, vec![50, 250, 490, 590]);
+
+        let mut swung: Vec<MidiEvent> = note(300, 400, 60).into_iter().collect();
+        quantize_events(&mut swung, 240, 1.0, 0.5, &converter());
+        assert_eq!(swung[0].sample_offset / 25, 300);
+    }
+
+    #[test]
+    fn test_humanize_is_deterministic() {
+        let original: Vec<MidiEvent> = (0..8)
+            .flat_map(|i| note(i * 480, i * 480 + 240, 60))
+            .collect();
+        let mut a = original.clone();
+        let mut b = original.clone();
+        humanize_events(&mut a, 20, 10, 42, &converter());
+        humanize_events(&mut b, 20, 10, 42, &converter());
+        assert_eq!(a, b);
+        assert_ne!(a, original);
+
+        // Lengths are preserved and velocities stay in range
+        for pair in a.chunks(2) {
+            assert_eq!(pair[1].sample_offset - pair[0].sample_offset, 240 * 25);
+            if let MidiMessage::NoteOn { velocity, .. } = pair[0].message {
+                assert!((90..=110).contains(&velocity.0));
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
 //! MIDI engine
 
-use koto_core::MidiEvent;
+use koto_core::{
+    merge_sorted, sort_events, MidiEvent, MidiFilter, MpeTracker, MpeZoneConfig, NoteExpression,
+    ProgramEvent, ProgramSelector, VelocityCurve,
+};
 use std::collections::VecDeque;
 
 /// MIDI engine for processing and routing MIDI events
@@ -11,6 +14,18 @@ pub struct MidiEngine {
     recording: Vec<MidiEvent>,
     /// Is recording enabled
     is_recording: bool,
+    /// Curve applied to incoming note-on velocities
+    velocity_curve: VelocityCurve,
+    /// Combines bank select with program changes
+    program_selector: ProgramSelector,
+    /// Program selections waiting to be drained
+    program_events: Vec<ProgramEvent>,
+    /// Route input through the MPE tracker
+    mpe_enabled: bool,
+    /// Per-note expression from MPE member channels
+    mpe_tracker: MpeTracker,
+    /// Note expression waiting to be drained
+    note_expressions: Vec<NoteExpression>,
 }
 
 impl MidiEngine {
@@ -19,22 +34,98 @@ impl MidiEngine {
             pending_events: VecDeque::new(),
             recording: Vec::new(),
             is_recording: false,
+            velocity_curve: VelocityCurve::default(),
+            program_selector: ProgramSelector::default(),
+            program_events: Vec::new(),
+            mpe_enabled: false,
+            mpe_tracker: MpeTracker::default(),
+            note_expressions: Vec::new(),
         }
     }
 
     /// Add an event to be processed
-    pub fn push_event(&mut self, event: MidiEvent) {
+    ///
+    /// If the event's route has a filter, events it blocks are dropped and
+    /// passed events are remapped by it.
+    pub fn push_event(&mut self, mut event: MidiEvent, filter: Option<&mut MidiFilter>) {
+        if let Some(filter) = filter {
+            match filter.process(event.message) {
+                Some(message) => event.message = message,
+                None => return,
+            }
+        }
+        event.message = self.velocity_curve.apply_to_message(event.message);
+        if let Some(program_event) = self.program_selector.process(&event.message) {
+            self.program_events.push(program_event);
+        }
+        if self.mpe_enabled {
+            let expressions = &mut self.note_expressions;
+            self.mpe_tracker
+                .process(&event, |expression| expressions.push(expression));
+        }
         if self.is_recording {
             self.recording.push(event);
         }
         self.pending_events.push_back(event);
     }
 
+    /// Set the curve applied to incoming note-on velocities
+    pub fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        self.velocity_curve = curve;
+    }
+
+    pub fn velocity_curve(&self) -> &VelocityCurve {
+        &self.velocity_curve
+    }
+
     /// Get pending events for a buffer
     pub fn drain_events(&mut self) -> Vec<MidiEvent> {
         self.pending_events.drain(..).collect()
     }
 
+    /// Enable or disable MPE mode
+    ///
+    /// In MPE mode, input is also tracked per note and expression on member
+    /// channels is reported through [`drain_note_expressions`](Self::drain_note_expressions).
+    pub fn set_mpe_enabled(&mut self, enabled: bool) {
+        if enabled != self.mpe_enabled {
+            self.mpe_tracker.reset();
+        }
+        self.mpe_enabled = enabled;
+    }
+
+    pub fn is_mpe_enabled(&self) -> bool {
+        self.mpe_enabled
+    }
+
+    /// Set the MPE zone layout and bend ranges
+    pub fn set_mpe_config(&mut self, config: MpeZoneConfig) {
+        self.mpe_tracker.set_config(config);
+    }
+
+    /// Get per-note expression changes since the last call
+    pub fn drain_note_expressions(&mut self) -> Vec<NoteExpression> {
+        std::mem::take(&mut self.note_expressions)
+    }
+
+    /// Get program selections (bank select + program change) since the last call
+    pub fn drain_program_events(&mut self) -> Vec<ProgramEvent> {
+        std::mem::take(&mut self.program_events)
+    }
+
+    /// Drain pending live events and merge them with clip playback events
+    ///
+    /// `playback` must already be sorted (as produced by clip rendering).
+    /// Live events are sorted first, and at equal offsets playback events
+    /// come before live ones.
+    pub fn merge_with_playback(&mut self, playback: &[MidiEvent]) -> Vec<MidiEvent> {
+        let live = self.pending_events.make_contiguous();
+        sort_events(live);
+        let merged = merge_sorted(playback, live);
+        self.pending_events.clear();
+        merged
+    }
+
     /// Start recording
     pub fn start_recording(&mut self) {
         self.is_recording = true;
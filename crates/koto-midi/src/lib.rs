@@ -1,7 +1,13 @@
 //! Koto MIDI - MIDI processing and device handling
 
 pub mod device;
+pub mod edit;
 pub mod engine;
+pub mod parser;
+pub mod processors;
 
 pub use device::*;
+pub use edit::*;
 pub use engine::*;
+pub use parser::*;
+pub use processors::*;
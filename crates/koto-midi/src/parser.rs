@@ -0,0 +1,168 @@
+//! Incremental MIDI byte stream parsing
+
+use koto_core::MidiMessage;
+
+/// Streaming parser for raw MIDI bytes
+///
+/// Handles running status (consecutive messages of the same type sent
+/// without repeating the status byte), data bytes arriving one at a time,
+/// and real-time bytes (clock, start/stop) interleaved inside a message.
+/// System exclusive data is skipped. System and real-time messages are not
+/// reported, since [`MidiMessage`] only covers channel voice messages.
+#[derive(Debug, Clone, Default)]
+pub struct MidiParser {
+    /// Current running status, if any
+    status: Option<u8>,
+    /// Data bytes collected for the current message
+    data: [u8; 2],
+    /// Number of data bytes collected
+    data_len: usize,
+    /// Inside a system exclusive message
+    in_sysex: bool,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed one byte, returning a message if it completes one
+    pub fn push(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            // Real-time messages may appear anywhere and don't affect state
+            0xF8..=0xFF => None,
+            0xF0 => {
+                self.status = None;
+                self.data_len = 0;
+                self.in_sysex = true;
+                None
+            }
+            // End of exclusive and other system common messages cancel running status
+            0xF1..=0xF7 => {
+                self.status = None;
+                self.data_len = 0;
+                self.in_sysex = false;
+                None
+            }
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.data_len = 0;
+                self.in_sysex = false;
+                None
+            }
+            _ => {
+                if self.in_sysex {
+                    return None;
+                }
+                let status = self.status?;
+                self.data[self.data_len] = byte;
+                self.data_len += 1;
+                if self.data_len < Self::data_length(status) {
+                    return None;
+                }
+                self.data_len = 0;
+                MidiMessage::from_bytes(&[status, self.data[0], self.data[1]])
+            }
+        }
+    }
+
+    /// Feed a block of bytes, calling `on_message` for each complete message
+    pub fn push_bytes(&mut self, bytes: &[u8], mut on_message: impl FnMut(MidiMessage)) {
+        for &byte in bytes {
+            if let Some(message) = self.push(byte) {
+                on_message(message);
+            }
+        }
+    }
+
+    /// Forget running status and any partial message
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Number of data bytes following a channel status byte
+    fn data_length(status: u8) -> usize {
+        match status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{ControlNumber, MidiChannel, NoteNumber, Velocity};
+
+    /// Captured from a keyboard controller: a chord with running status
+    /// note-ons (the last as velocity-0 note-off), then CC sweeps with a
+    /// timing clock byte in the middle of a message
+    const CAPTURE: [u8; 16] = [
+        0x90, 60, 100, 64, 90, 67, 0, //
+        0xB1, 1, 10, 1, 0xF8, 20, //
+        0xC2, 5, 7,
+    ];
+
+    fn expected() -> Vec<MidiMessage> {
+        let ch0 = MidiChannel(0);
+        let ch1 = MidiChannel(1);
+        vec![
+            MidiMessage::NoteOn {
+                channel: ch0,
+                note: NoteNumber(60),
+                velocity: Velocity(100),
+            },
+            MidiMessage::NoteOn {
+                channel: ch0,
+                note: NoteNumber(64),
+                velocity: Velocity(90),
+            },
+            MidiMessage::NoteOff {
+                channel: ch0,
+                note: NoteNumber(67),
+                velocity: Velocity(0),
+            },
+            MidiMessage::ControlChange {
+                channel: ch1,
+                control: ControlNumber::MODULATION,
+                value: 10,
+            },
+            MidiMessage::ControlChange {
+                channel: ch1,
+                control: ControlNumber::MODULATION,
+                value: 20,
+            },
+            MidiMessage::ProgramChange {
+                channel: MidiChannel(2),
+                program: 5,
+            },
+            MidiMessage::ProgramChange {
+                channel: MidiChannel(2),
+                program: 7,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_running_status_stream() {
+        let mut parser = MidiParser::new();
+        let mut messages = Vec::new();
+        parser.push_bytes(&CAPTURE, |m| messages.push(m));
+        assert_eq!(messages, expected());
+
+        // Same stream split into single-byte packets
+        let mut parser = MidiParser::new();
+        let messages: Vec<_> = CAPTURE.iter().filter_map(|&b| parser.push(b)).collect();
+        assert_eq!(messages, expected());
+    }
+
+    #[test]
+    fn test_sysex_cancels_running_status() {
+        let mut parser = MidiParser::new();
+        let mut messages = Vec::new();
+        parser.push_bytes(&[0x90, 60, 100, 0xF0, 0x7E, 60, 100, 0xF7, 62, 100], |m| {
+            messages.push(m)
+        });
+        assert_eq!(messages.len(), 1);
+    }
+}
@@ -0,0 +1,351 @@
+//! Reference MIDI processors
+
+use koto_core::{
+    sort_events, MidiChannel, MidiEvent, MidiMessage, MidiProcessor, NoteNumber, ProcessContext,
+    Velocity,
+};
+
+/// Shifts notes by a number of semitones
+///
+/// Notes that would leave the MIDI range are dropped. A note-off is
+/// shifted by the amount its note-on was, so changing the interval while
+/// notes are held doesn't strand them.
+pub struct Transpose {
+    semitones: i8,
+    /// Output note of each sounding input note, per channel
+    sounding: Box<[[Option<u8>; 128]; 16]>,
+}
+
+impl Transpose {
+    pub fn new(semitones: i8) -> Self {
+        Self {
+            semitones,
+            sounding: Box::new([[None; 128]; 16]),
+        }
+    }
+
+    pub fn semitones(&self) -> i8 {
+        self.semitones
+    }
+
+    pub fn set_semitones(&mut self, semitones: i8) {
+        self.semitones = semitones;
+    }
+}
+
+impl MidiProcessor for Transpose {
+    fn process_midi(
+        &mut self,
+        input: &[MidiEvent],
+        output: &mut Vec<MidiEvent>,
+        _context: &ProcessContext,
+    ) {
+        for event in input {
+            let mut event = *event;
+            match &mut event.message {
+                MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } if velocity.0 > 0 => {
+                    let slot =
+                        &mut self.sounding[channel.0.min(15) as usize][note.0.min(127) as usize];
+                    match note.transpose(self.semitones) {
+                        Some(shifted) => {
+                            *slot = Some(shifted.0);
+                            *note = shifted;
+                        }
+                        None => continue,
+                    }
+                }
+                MidiMessage::NoteOn { channel, note, .. }
+                | MidiMessage::NoteOff { channel, note, .. } => {
+                    match self.sounding[channel.0.min(15) as usize][note.0.min(127) as usize].take()
+                    {
+                        Some(shifted) => *note = NoteNumber(shifted),
+                        None => continue,
+                    }
+                }
+                MidiMessage::PolyPressure { note, .. } => match note.transpose(self.semitones) {
+                    Some(shifted) => *note = shifted,
+                    None => continue,
+                },
+                _ => {}
+            }
+            output.push(event);
+        }
+    }
+
+    fn reset(&mut self) {
+        *self.sounding = [[None; 128]; 16];
+    }
+}
+
+/// Order in which an [`Arpeggiator`] plays the held notes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArpMode {
+    #[default]
+    Up,
+    Down,
+    /// Up then down, without repeating the top and bottom notes
+    UpDown,
+}
+
+/// Plays held notes one at a time on a tempo-synced grid
+///
+/// Steps fall on multiples of `rate_ticks` from the start of the timeline,
+/// so the pattern stays in time with the playhead. Steps only advance while
+/// the transport is playing. Incoming notes are consumed; other events pass
+/// through.
+pub struct Arpeggiator {
+    mode: ArpMode,
+    /// Step length in ticks (240 = sixteenth notes)
+    rate_ticks: u32,
+    /// Note length as a fraction of the step (0.0 to 1.0)
+    gate: f32,
+    /// Held notes, sorted by pitch
+    held: Vec<(MidiChannel, NoteNumber, Velocity)>,
+    /// Steps played since the pattern started
+    step: usize,
+    /// Note being played and the absolute sample position it ends at
+    sounding: Option<(MidiChannel, NoteNumber, f64)>,
+    /// Note left sounding by `reset`, released at the start of the next block
+    release_pending: Option<(MidiChannel, NoteNumber)>,
+}
+
+impl Arpeggiator {
+    pub fn new(mode: ArpMode, rate_ticks: u32, gate: f32) -> Self {
+        Self {
+            mode,
+            rate_ticks: rate_ticks.max(1),
+            gate: gate.clamp(0.0, 1.0),
+            held: Vec::with_capacity(16),
+            step: 0,
+            sounding: None,
+            release_pending: None,
+        }
+    }
+
+    pub fn set_mode(&mut self, mode: ArpMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_rate_ticks(&mut self, rate_ticks: u32) {
+        self.rate_ticks = rate_ticks.max(1);
+    }
+
+    pub fn set_gate(&mut self, gate: f32) {
+        self.gate = gate.clamp(0.0, 1.0);
+    }
+
+    /// Held note for the current step
+    fn current(&self) -> Option<(MidiChannel, NoteNumber, Velocity)> {
+        let len = self.held.len();
+        if len == 0 {
+            return None;
+        }
+        let index = match self.mode {
+            ArpMode::Up => self.step % len,
+            ArpMode::Down => len - 1 - self.step % len,
+            ArpMode::UpDown if len == 1 => 0,
+            ArpMode::UpDown => {
+                let position = self.step % (2 * len - 2);
+                if position < len {
+                    position
+                } else {
+                    2 * len - 2 - position
+                }
+            }
+        };
+        Some(self.held[index])
+    }
+
+    fn note_off(offset: usize, channel: MidiChannel, note: NoteNumber) -> MidiEvent {
+        MidiEvent::new(
+            offset,
+            MidiMessage::NoteOff {
+                channel,
+                note,
+                velocity: Velocity::OFF,
+            },
+        )
+    }
+}
+
+impl MidiProcessor for Arpeggiator {
+    fn process_midi(
+        &mut self,
+        input: &[MidiEvent],
+        output: &mut Vec<MidiEvent>,
+        context: &ProcessContext,
+    ) {
+        let first = output.len();
+        if let Some((channel, note)) = self.release_pending.take() {
+            output.push(Self::note_off(0, channel, note));
+        }
+
+        let block_start = context.playhead.0 as f64;
+        let block_end = block_start + context.frames as f64;
+        let last_offset = context.frames.saturating_sub(1);
+        let offset_of =
+            |position: f64| ((position - block_start).max(0.0) as usize).min(last_offset);
+
+        for event in input {
+            match event.message {
+                MidiMessage::NoteOn {
+                    channel,
+                    note,
+                    velocity,
+                } if velocity.0 > 0 => {
+                    if self.held.is_empty() {
+                        self.step = 0;
+                    }
+                    if let Err(index) = self.held.binary_search_by_key(&note.0, |h| h.1 .0) {
+                        self.held.insert(index, (channel, note, velocity));
+                    }
+                }
+                MidiMessage::NoteOn { note, .. } | MidiMessage::NoteOff { note, .. } => {
+                    self.held.retain(|h| h.1 != note);
+                    // Releasing every key stops the pattern straight away
+                    if self.held.is_empty() {
+                        if let Some((channel, note, _)) = self.sounding.take() {
+                            output.push(Self::note_off(event.sample_offset, channel, note));
+                        }
+                    }
+                }
+                _ => output.push(*event),
+            }
+        }
+
+        if context.is_playing {
+            let step_samples =
+                self.rate_ticks as f64 * context.tempo.samples_per_tick(context.sample_rate);
+            let mut index = (block_start / step_samples).ceil();
+            while index * step_samples < block_end {
+                let position = index * step_samples;
+                if let Some((channel, note, off)) = self.sounding.take() {
+                    output.push(Self::note_off(offset_of(off.min(position)), channel, note));
+                }
+                if let Some((channel, note, velocity)) = self.current() {
+                    output.push(MidiEvent::new(
+                        offset_of(position),
+                        MidiMessage::NoteOn {
+                            channel,
+                            note,
+                            velocity,
+                        },
+                    ));
+                    let off = position + self.gate as f64 * step_samples;
+                    self.sounding = Some((channel, note, off));
+                    self.step += 1;
+                }
+                index += 1.0;
+            }
+        }
+
+        if let Some((channel, note, off)) = self.sounding {
+            if off < block_end {
+                output.push(Self::note_off(offset_of(off), channel, note));
+                self.sounding = None;
+            }
+        }
+        sort_events(&mut output[first..]);
+    }
+
+    fn reset(&mut self) {
+        self.release_pending = self
+            .sounding
+            .take()
+            .map(|(channel, note, _)| (channel, note))
+            .or(self.release_pending);
+        self.held.clear();
+        self.step = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{SamplePosition, SampleRate, Tempo, TimeSignature};
+
+    fn context(midi_out: &mut Vec<MidiEvent>, playhead: i64, frames: usize) -> ProcessContext<'_> {
+        ProcessContext {
+            sample_rate: SampleRate::DVD_QUALITY,
+            tempo: Tempo::DEFAULT,
+            time_signature: TimeSignature::COMMON_TIME,
+            playhead: SamplePosition(playhead),
+            frames,
+            midi_events: &[],
+            midi_out,
+            is_playing: true,
+            is_recording: false,
+            input_latency: 0,
+            output_latency: 0,
+        }
+    }
+
+    fn note_on(note: u8) -> MidiEvent {
+        MidiEvent::new(
+            0,
+            MidiMessage::NoteOn {
+                channel: MidiChannel(0),
+                note: NoteNumber(note),
+                velocity: Velocity(100),
+            },
+        )
+    }
+
+    #[test]
+    fn test_transpose_releases_with_original_interval() {
+        let mut sink = Vec::new();
+        let ctx = context(&mut sink, 0, 64);
+        let mut transpose = Transpose::new(12);
+        let mut output = Vec::new();
+        transpose.process_midi(&[note_on(60), note_on(120)], &mut output, &ctx);
+        transpose.set_semitones(-5);
+        let off = MidiEvent::new(
+            10,
+            MidiMessage::NoteOff {
+                channel: MidiChannel(0),
+                note: NoteNumber(60),
+                velocity: Velocity::OFF,
+            },
+        );
+        transpose.process_midi(&[off], &mut output, &ctx);
+
+        // 120 + 12 is out of range and dropped; the note-off follows the +12
+        assert_eq!(output.len(), 2);
+        assert!(matches!(output[0].message, MidiMessage::NoteOn { note, .. } if note.0 == 72));
+        assert!(matches!(output[1].message, MidiMessage::NoteOff { note, .. } if note.0 == 72));
+    }
+
+    #[test]
+    fn test_arpeggiator_steps_on_subdivisions_and_resets_cleanly() {
+        // Sixteenths at 120 BPM / 48 kHz are 6000 samples apart
+        let mut arp = Arpeggiator::new(ArpMode::Up, 240, 1.0);
+        let mut sink = Vec::new();
+        let mut output = Vec::new();
+        let ctx = context(&mut sink, 0, 24_000);
+        arp.process_midi(&[note_on(67), note_on(60), note_on(64)], &mut output, &ctx);
+
+        let ons: Vec<(usize, u8)> = output
+            .iter()
+            .filter_map(|e| match e.message {
+                MidiMessage::NoteOn { note, .. } => Some((e.sample_offset, note.0)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ons, vec![(0, 60), (6000, 64), (12_000, 67), (18_000, 60)]);
+
+        // The last note is still sounding; reset releases it and stops
+        arp.reset();
+        output.clear();
+        let mut sink = Vec::new();
+        let ctx = context(&mut sink, 24_000, 24_000);
+        arp.process_midi(&[], &mut output, &ctx);
+        assert_eq!(
+            output,
+            vec![Arpeggiator::note_off(0, MidiChannel(0), NoteNumber(60))]
+        );
+    }
+}
@@ -1,22 +1,251 @@
 //! Koto Mixer - Mixer console
 
+mod meter;
+mod routing;
+mod send;
+mod solo;
+mod vca;
+
+pub use meter::*;
+pub use routing::*;
+pub use send::*;
+pub use solo::*;
+pub use vca::*;
+
+use koto_core::{AudioBuffer, ChannelCount, SampleRate, SmoothedValue, SmoothingMode, TrackId};
+use koto_timeline::{Timeline, TrackType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Ramp time for channel volume and pan changes
+const PARAMETER_RAMP_MS: f32 = 20.0;
+
+/// Errors changing the mixer's routing
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MixerError {
+    #[error("Channel not found: {0:?}")]
+    ChannelNotFound(ChannelId),
+    #[error("{0:?} can't send to itself")]
+    SendToSelf(ChannelId),
+    #[error("{0:?} is not an aux return")]
+    NotAReturn(ChannelId),
+    #[error("{from:?} already sends to {to:?}")]
+    SendExists { from: ChannelId, to: ChannelId },
+    #[error("{from:?} has no send to {to:?}")]
+    SendNotFound { from: ChannelId, to: ChannelId },
+    #[error("{0:?} is not a bus")]
+    NotABus(ChannelId),
+    #[error("Routing {from:?} to {to:?} would create a feedback loop")]
+    Cycle { from: ChannelId, to: ChannelId },
+    #[error("Mixer routing contains a feedback loop")]
+    RoutingCycle,
+    #[error("VCA group not found: {0:?}")]
+    VcaNotFound(VcaId),
+    #[error("Putting {vca:?} under {parent:?} would make the VCA control itself")]
+    VcaCycle { vca: VcaId, parent: VcaId },
+}
+
+/// Unique identifier for mixer channels, assigned by [`Mixer::add_channel`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ChannelId(pub u64);
+
+/// What a channel carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChannelKind {
+    /// A track or other source
+    #[default]
+    Audio,
+    /// Receives other channels' sends, e.g. for a shared reverb
+    AuxReturn,
+    /// Sums the channels routed to it; see [`Mixer::set_channel_output`]
+    Bus,
+}
+
+/// How a stereo channel's left and right inputs reach its fader
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChannelMode {
+    #[default]
+    Stereo,
+    /// Both sides summed, at the channel's [`MonoSumLevel`]
+    Mono,
+    /// The left input on both sides
+    Left,
+    /// The right input on both sides
+    Right,
+}
+
+/// Level of the left and right inputs when summed to mono
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MonoSumLevel {
+    /// Keeps the power of uncorrelated signals
+    #[default]
+    Minus3Db,
+    /// Keeps the level of identical signals
+    Minus6Db,
+}
+
+impl MonoSumLevel {
+    pub fn gain(&self) -> f32 {
+        match self {
+            MonoSumLevel::Minus3Db => std::f32::consts::FRAC_1_SQRT_2,
+            MonoSumLevel::Minus6Db => 0.5,
+        }
+    }
+}
+
 /// Mixer channel
+#[derive(Serialize, Deserialize)]
 pub struct MixerChannel {
+    pub id: ChannelId,
     pub name: String,
+    #[serde(default)]
+    pub kind: ChannelKind,
+    /// Timeline track the channel mirrors; see [`Mixer::sync_with_timeline`]
+    pub track_id: Option<TrackId>,
+    /// Gain applied before anything else, in dB
+    pub input_trim_db: f32,
+    pub phase_invert: bool,
+    pub channel_mode: ChannelMode,
+    pub mono_sum: MonoSumLevel,
     pub volume: f32,
     pub pan: f32,
     pub mute: bool,
     pub solo: bool,
+    /// Keep playing while other channels are soloed; see
+    /// [`Mixer::effective_mute`]
+    #[serde(default)]
+    pub solo_safe: bool,
+    /// Sends to aux returns; change them through [`Mixer::add_send`] and
+    /// friends, which check the routing
+    #[serde(default)]
+    pub sends: Vec<AuxSend>,
+    /// Where the channel's output goes; change it through
+    /// [`Mixer::set_channel_output`]
+    #[serde(default)]
+    pub output: OutputTarget,
+    /// Created by [`prepare`](Self::prepare) or the first
+    /// [`process`](Self::process)
+    #[serde(skip)]
+    processor: Option<MixerChannelProcessor>,
+    /// Levels of the channel's output, metered by [`process`](Self::process)
+    #[serde(skip)]
+    meter: ChannelMeter,
 }
 
 impl MixerChannel {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
+            id: ChannelId::default(),
             name: name.into(),
+            kind: ChannelKind::Audio,
+            track_id: None,
+            input_trim_db: 0.0,
+            phase_invert: false,
+            channel_mode: ChannelMode::Stereo,
+            mono_sum: MonoSumLevel::Minus3Db,
             volume: 1.0,
             pan: 0.0,
             mute: false,
             solo: false,
+            solo_safe: false,
+            sends: Vec::new(),
+            output: OutputTarget::Master,
+            processor: None,
+            meter: ChannelMeter::default(),
+        }
+    }
+
+    /// An aux return channel, solo-safe so soloed sources keep their
+    /// effects
+    pub fn aux_return(name: impl Into<String>) -> Self {
+        Self {
+            kind: ChannelKind::AuxReturn,
+            solo_safe: true,
+            ..Self::new(name)
+        }
+    }
+
+    /// A bus channel
+    pub fn bus(name: impl Into<String>) -> Self {
+        Self {
+            kind: ChannelKind::Bus,
+            ..Self::new(name)
+        }
+    }
+
+    /// Set up processing at `sample_rate`, jumping straight to the current
+    /// settings
+    pub fn prepare(&mut self, sample_rate: SampleRate) {
+        self.prepare_fader(sample_rate, self.fader());
+    }
+
+    /// [`prepare`](Self::prepare) with the fader starting at `fader`
+    pub(crate) fn prepare_fader(&mut self, sample_rate: SampleRate, fader: f32) {
+        self.processor = Some(MixerChannelProcessor::with_fader(self, sample_rate, fader));
+        self.meter = ChannelMeter::new(sample_rate);
+    }
+
+    /// Fader gain from the channel's own volume and mute
+    fn fader(&self) -> f32 {
+        if self.mute {
+            0.0
+        } else {
+            self.volume
+        }
+    }
+
+    pub fn meter(&self) -> &ChannelMeter {
+        &self.meter
+    }
+
+    /// Run a block through the channel strip: trim, phase, mode, fader and
+    /// pan, in that order, meter the output and write what each send picks
+    /// up into the caller's buffers
+    ///
+    /// `sends[i]` receives send `i` of [`sends`](Self::sends), or silence if
+    /// it is disabled; sends without a buffer are skipped. Pre-fader sends
+    /// tap the signal after trim, phase and mode, so they ignore the fader,
+    /// pan and mute. Post-fader sends tap the channel's output. Changes to
+    /// the settings since the last block are smoothed. Call
+    /// [`prepare`](Self::prepare) first; an unprepared channel prepares
+    /// itself at the default sample rate.
+    pub fn process(&mut self, buffer: &mut AudioBuffer, sends: &mut [AudioBuffer]) {
+        self.process_fader(buffer, sends, self.fader());
+    }
+
+    /// [`process`](Self::process) with the fader at `fader` in place of the
+    /// channel's own volume and mute, e.g. after its
+    /// [`Mixer::effective_mute`] and [`Mixer::effective_volume`]
+    pub(crate) fn process_fader(
+        &mut self,
+        buffer: &mut AudioBuffer,
+        sends: &mut [AudioBuffer],
+        fader: f32,
+    ) {
+        let mut processor = self.processor.take().unwrap_or_else(|| {
+            MixerChannelProcessor::with_fader(self, SampleRate::default(), fader)
+        });
+        processor.sync_fader(self, fader);
+        processor.process_input(buffer);
+        self.tap_sends(buffer, sends, true);
+        processor.process_output(buffer);
+        self.tap_sends(buffer, sends, false);
+        self.meter.update(buffer);
+        self.processor = Some(processor);
+    }
+
+    fn tap_sends(&self, buffer: &AudioBuffer, outputs: &mut [AudioBuffer], pre_fader: bool) {
+        for (send, output) in self.sends.iter().zip(outputs) {
+            if send.pre_fader != pre_fader {
+                continue;
+            }
+            if send.enabled {
+                output.copy_from(buffer);
+                output.apply_gain(db_to_gain(send.level_db));
+            } else {
+                output.clear();
+            }
         }
     }
 }
@@ -27,10 +256,146 @@ impl Default for MixerChannel {
     }
 }
 
+/// Audio-thread state for a mixer channel
+///
+/// Trim, volume and pan are smoothed so UI changes don't cause zipper
+/// noise. Call [`sync`](Self::sync) with the channel settings before each
+/// block.
+pub struct MixerChannelProcessor {
+    trim: SmoothedValue,
+    volume: SmoothedValue,
+    pan: SmoothedValue,
+    phase_invert: bool,
+    channel_mode: ChannelMode,
+    mono_gain: f32,
+}
+
+impl MixerChannelProcessor {
+    pub fn new(channel: &MixerChannel, sample_rate: SampleRate) -> Self {
+        Self::with_fader(channel, sample_rate, channel.fader())
+    }
+
+    fn with_fader(channel: &MixerChannel, sample_rate: SampleRate, fader: f32) -> Self {
+        Self {
+            trim: SmoothedValue::new(
+                db_to_gain(channel.input_trim_db),
+                sample_rate,
+                PARAMETER_RAMP_MS,
+                SmoothingMode::Linear,
+            ),
+            volume: SmoothedValue::new(
+                fader,
+                sample_rate,
+                PARAMETER_RAMP_MS,
+                SmoothingMode::Linear,
+            ),
+            pan: SmoothedValue::new(
+                channel.pan,
+                sample_rate,
+                PARAMETER_RAMP_MS,
+                SmoothingMode::Linear,
+            ),
+            phase_invert: channel.phase_invert,
+            channel_mode: channel.channel_mode,
+            mono_gain: channel.mono_sum.gain(),
+        }
+    }
+
+    /// Update the smoothing targets from the channel settings
+    pub fn sync(&mut self, channel: &MixerChannel) {
+        self.sync_fader(channel, channel.fader());
+    }
+
+    fn sync_fader(&mut self, channel: &MixerChannel, fader: f32) {
+        self.trim.set_target(db_to_gain(channel.input_trim_db));
+        self.volume.set_target(fader);
+        self.pan.set_target(channel.pan.clamp(-1.0, 1.0));
+        self.phase_invert = channel.phase_invert;
+        self.channel_mode = channel.channel_mode;
+        self.mono_gain = channel.mono_sum.gain();
+    }
+
+    /// Apply trim, phase, channel mode, volume and pan, in that order
+    ///
+    /// Channel mode and pan only apply to stereo buffers. Pan uses a
+    /// balance law: unity at center, attenuating the opposite side.
+    pub fn process(&mut self, buffer: &mut AudioBuffer) {
+        self.process_input(buffer);
+        self.process_output(buffer);
+    }
+
+    /// Trim, phase and channel mode: everything before the fader
+    fn process_input(&mut self, buffer: &mut AudioBuffer) {
+        let channels = buffer.channels();
+        let polarity = if self.phase_invert { -1.0 } else { 1.0 };
+        for frame in buffer
+            .samples_mut()
+            .chunks_exact_mut(channels.as_usize().max(1))
+        {
+            let trim = self.trim.next() * polarity;
+            if channels == ChannelCount::STEREO {
+                let (left, right) = (frame[0] * trim, frame[1] * trim);
+                (frame[0], frame[1]) = match self.channel_mode {
+                    ChannelMode::Stereo => (left, right),
+                    ChannelMode::Mono => {
+                        let sum = (left + right) * self.mono_gain;
+                        (sum, sum)
+                    }
+                    ChannelMode::Left => (left, left),
+                    ChannelMode::Right => (right, right),
+                };
+            } else {
+                for sample in frame {
+                    *sample *= trim;
+                }
+            }
+        }
+    }
+
+    /// Fader and pan
+    fn process_output(&mut self, buffer: &mut AudioBuffer) {
+        let channels = buffer.channels();
+        for frame in buffer
+            .samples_mut()
+            .chunks_exact_mut(channels.as_usize().max(1))
+        {
+            let volume = self.volume.next();
+            let pan = self.pan.next();
+            if channels == ChannelCount::STEREO {
+                frame[0] *= volume * (1.0 - pan).min(1.0);
+                frame[1] *= volume * (1.0 + pan).min(1.0);
+            } else {
+                for sample in frame {
+                    *sample *= volume;
+                }
+            }
+        }
+    }
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
 /// Mixer console
+#[derive(Serialize, Deserialize)]
 pub struct Mixer {
+    /// Add channels with [`add_channel`](Self::add_channel), which gives
+    /// them their IDs
     pub channels: Vec<MixerChannel>,
     pub master_volume: f32,
+    #[serde(default)]
+    pub solo_mode: SoloMode,
+    /// Add them with [`create_vca`](Self::create_vca), which gives them
+    /// their IDs
+    #[serde(default)]
+    pub vca_groups: Vec<VcaGroup>,
+    next_channel_id: u64,
+    #[serde(default)]
+    next_vca_id: u64,
+    /// Buffers and processing order for [`process_all`](Self::process_all)
+    #[serde(skip)]
+    plan: MixPlan,
 }
 
 impl Mixer {
@@ -38,21 +403,64 @@ impl Mixer {
         Self {
             channels: Vec::new(),
             master_volume: 1.0,
+            solo_mode: SoloMode::Additive,
+            vca_groups: Vec::new(),
+            next_channel_id: 1,
+            next_vca_id: 1,
+            plan: MixPlan::default(),
         }
     }
 
-    pub fn add_channel(&mut self, channel: MixerChannel) -> usize {
+    /// Add a channel at the end, giving it a new ID; returns its index
+    pub fn add_channel(&mut self, mut channel: MixerChannel) -> usize {
+        channel.id = self.new_channel_id();
         let index = self.channels.len();
         self.channels.push(channel);
+        self.update_plan(0);
         index
     }
 
+    fn new_channel_id(&mut self) -> ChannelId {
+        let id = ChannelId(self.next_channel_id);
+        self.next_channel_id += 1;
+        id
+    }
+
+    /// Remove a channel, any sends to it and its VCA memberships; channels
+    /// routed to it go to the master instead
     pub fn remove_channel(&mut self, index: usize) {
         if index < self.channels.len() {
             self.channels.remove(index);
+            self.drop_dangling_routes();
+            self.update_plan(0);
+        }
+    }
+
+    /// Set up every channel's processing at `sample_rate`, with the
+    /// faders at their VCA and solo levels, and the buffers for
+    /// [`process_all`](Self::process_all) for blocks of up to `max_frames`
+    /// frames
+    pub fn prepare(&mut self, sample_rate: SampleRate, max_frames: usize) {
+        self.update_plan(max_frames);
+        self.plan.update_mutes(&self.channels);
+        for index in 0..self.channels.len() {
+            let fader = self.fader(index, self.plan.is_muted(index));
+            self.channels[index].prepare_fader(sample_rate, fader);
         }
     }
 
+    pub fn channel_index(&self, id: ChannelId) -> Option<usize> {
+        self.channels.iter().position(|c| c.id == id)
+    }
+
+    pub fn channel(&self, id: ChannelId) -> Option<&MixerChannel> {
+        self.channels.iter().find(|c| c.id == id)
+    }
+
+    pub fn channel_mut(&mut self, id: ChannelId) -> Option<&mut MixerChannel> {
+        self.channels.iter_mut().find(|c| c.id == id)
+    }
+
     pub fn get_channel(&self, index: usize) -> Option<&MixerChannel> {
         self.channels.get(index)
     }
@@ -60,6 +468,60 @@ impl Mixer {
     pub fn get_channel_mut(&mut self, index: usize) -> Option<&mut MixerChannel> {
         self.channels.get_mut(index)
     }
+
+    /// Index of the channel mirroring `track`
+    pub fn channel_index_for_track(&self, track: TrackId) -> Option<usize> {
+        self.channels.iter().position(|c| c.track_id == Some(track))
+    }
+
+    pub fn channel_for_track(&self, track: TrackId) -> Option<&MixerChannel> {
+        self.channels.iter().find(|c| c.track_id == Some(track))
+    }
+
+    pub fn channel_for_track_mut(&mut self, track: TrackId) -> Option<&mut MixerChannel> {
+        self.channels.iter_mut().find(|c| c.track_id == Some(track))
+    }
+
+    /// Give every timeline track a channel, in track order
+    ///
+    /// Channels of tracks that still exist keep their settings and take
+    /// the track's current name; channels of removed tracks are dropped.
+    /// Master tracks get no channel, being the mixer's master section.
+    /// Channels not linked to a track stay, after the track channels.
+    /// Routing to dropped channels is removed as by
+    /// [`remove_channel`](Self::remove_channel).
+    pub fn sync_with_timeline(&mut self, timeline: &Timeline) {
+        let (linked, unlinked): (Vec<_>, Vec<_>) = std::mem::take(&mut self.channels)
+            .into_iter()
+            .partition(|c| c.track_id.is_some());
+        let mut linked: HashMap<TrackId, MixerChannel> = linked
+            .into_iter()
+            .filter_map(|c| Some((c.track_id?, c)))
+            .collect();
+        self.channels = timeline
+            .tracks
+            .iter()
+            .filter(|t| t.track_type != TrackType::Master)
+            .map(|track| {
+                let mut channel = linked.remove(&track.id).unwrap_or_else(|| {
+                    let id = ChannelId(self.next_channel_id);
+                    self.next_channel_id += 1;
+                    MixerChannel {
+                        id,
+                        track_id: Some(track.id),
+                        ..MixerChannel::default()
+                    }
+                });
+                channel.name.clone_from(&track.name);
+                channel
+            })
+            .chain(unlinked)
+            .collect();
+        if !linked.is_empty() {
+            self.drop_dangling_routes();
+        }
+        self.update_plan(0);
+    }
 }
 
 impl Default for Mixer {
@@ -67,3 +529,94 @@ impl Default for Mixer {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(mixer: &Mixer) -> Vec<&str> {
+        mixer.channels.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    fn stereo(frames: &[(f32, f32)]) -> AudioBuffer {
+        let samples = frames.iter().flat_map(|&(l, r)| [l, r]).collect();
+        AudioBuffer::from_samples(samples, ChannelCount::STEREO)
+    }
+
+    #[test]
+    fn test_phase_invert_nulls_against_original() {
+        let frames: Vec<(f32, f32)> = (0..64)
+            .map(|i| ((i as f32 * 0.1).sin(), (i as f32 * 0.23).cos()))
+            .collect();
+        let original = stereo(&frames);
+        let mut channel = MixerChannel::new("Kick In");
+        channel.phase_invert = true;
+        channel.prepare(SampleRate(48000));
+        let mut inverted = stereo(&frames);
+        channel.process(&mut inverted, &mut []);
+        for (a, b) in inverted.samples().iter().zip(original.samples()) {
+            assert!((a + b).abs() < 1e-6);
+        }
+
+        // Trim comes first, so it scales the inverted signal
+        channel.input_trim_db = -6.0;
+        channel.prepare(SampleRate(48000));
+        let mut trimmed = stereo(&frames);
+        channel.process(&mut trimmed, &mut []);
+        let gain = db_to_gain(-6.0);
+        for (a, b) in trimmed.samples().iter().zip(original.samples()) {
+            assert!((a + b * gain).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_mono_sum_level_and_side_modes() {
+        let process = |mode, mono_sum| {
+            let mut channel = MixerChannel::new("Keys");
+            channel.channel_mode = mode;
+            channel.mono_sum = mono_sum;
+            let mut buffer = stereo(&[(0.5, 0.5), (0.8, -0.2)]);
+            channel.process(&mut buffer, &mut []);
+            buffer.samples().to_vec()
+        };
+        let half = process(ChannelMode::Mono, MonoSumLevel::Minus6Db);
+        assert_eq!(half, [0.5, 0.5, 0.3, 0.3]);
+        let power = process(ChannelMode::Mono, MonoSumLevel::Minus3Db);
+        assert!((power[0] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert_eq!(power[0], power[1]);
+        assert_eq!(
+            process(ChannelMode::Left, MonoSumLevel::default()),
+            [0.5, 0.5, 0.8, 0.8]
+        );
+        assert_eq!(
+            process(ChannelMode::Right, MonoSumLevel::default()),
+            [0.5, 0.5, -0.2, -0.2]
+        );
+    }
+
+    #[test]
+    fn test_sync_follows_track_changes() {
+        let mut timeline = Timeline::new();
+        let drums = timeline.add_track("Drums", TrackType::Audio);
+        let bass = timeline.add_track("Bass", TrackType::Audio);
+        let vox = timeline.add_track("Vox", TrackType::Audio);
+        timeline.add_track("Master", TrackType::Master);
+        let mut mixer = Mixer::new();
+        mixer.add_channel(MixerChannel::new("Talkback"));
+        mixer.sync_with_timeline(&timeline);
+        assert_eq!(names(&mixer), ["Drums", "Bass", "Vox", "Talkback"]);
+
+        mixer.channel_for_track_mut(bass).unwrap().volume = 0.5;
+        mixer.channel_for_track_mut(bass).unwrap().pan = -0.25;
+        timeline.rename_track(bass, "Bass DI").unwrap();
+        timeline.remove_track(drums);
+        timeline.move_track(vox, 0).unwrap();
+        mixer.sync_with_timeline(&timeline);
+
+        assert_eq!(names(&mixer), ["Vox", "Bass DI", "Talkback"]);
+        assert!(mixer.channel_for_track(drums).is_none());
+        let channel = mixer.channel_for_track(bass).unwrap();
+        assert_eq!((channel.volume, channel.pan), (0.5, -0.25));
+        assert_eq!(mixer.channel_index_for_track(bass), Some(1));
+    }
+}
@@ -0,0 +1,199 @@
+//! Per-channel level meters
+
+use crate::{ChannelId, Mixer};
+use koto_core::{interleaved_peaks, interleaved_rms, AudioBuffer, SampleRate};
+use std::time::Duration;
+
+/// How long a peak is held before it starts to fall
+const PEAK_HOLD_TIME: Duration = Duration::from_millis(1500);
+/// How fast a held peak falls once the hold time is up
+const PEAK_HOLD_DECAY_DB_PER_SECOND: f32 = 20.0;
+
+/// Levels of a channel's output, updated by
+/// [`MixerChannel::process`](crate::MixerChannel::process)
+///
+/// Mono channels read the same on both sides. Never allocates.
+#[derive(Debug, Clone)]
+pub struct ChannelMeter {
+    sample_rate: SampleRate,
+    /// Of the last block, left and right
+    peak: [f32; 2],
+    rms: [f32; 2],
+    /// Highest recent peak, falling after [`PEAK_HOLD_TIME`]
+    peak_hold: [f32; 2],
+    /// Frames since each held peak was set
+    hold_frames: [u64; 2],
+    /// Metering time of the last sample at or over full scale, until reset
+    clipped_at: Option<Duration>,
+    /// Frames metered so far
+    frames: u64,
+}
+
+impl ChannelMeter {
+    pub fn new(sample_rate: SampleRate) -> Self {
+        Self {
+            sample_rate,
+            peak: [0.0; 2],
+            rms: [0.0; 2],
+            peak_hold: [0.0; 2],
+            hold_frames: [0; 2],
+            clipped_at: None,
+            frames: 0,
+        }
+    }
+
+    /// Meter one block
+    pub fn update(&mut self, buffer: &AudioBuffer) {
+        let channels = buffer.channels().as_usize().min(2);
+        if channels == 0 {
+            return;
+        }
+        interleaved_peaks(
+            buffer.samples(),
+            buffer.channels().as_usize(),
+            &mut self.peak,
+        );
+        interleaved_rms(
+            buffer.samples(),
+            buffer.channels().as_usize(),
+            &mut self.rms,
+        );
+        if channels == 1 {
+            self.peak[1] = self.peak[0];
+            self.rms[1] = self.rms[0];
+        }
+
+        let block = buffer.frames() as u64;
+        let rate = self.sample_rate.as_f64().max(1.0);
+        let hold_frames = (PEAK_HOLD_TIME.as_secs_f64() * rate) as u64;
+        let decay =
+            10.0_f32.powf(-PEAK_HOLD_DECAY_DB_PER_SECOND * (block as f64 / rate) as f32 / 20.0);
+        for side in 0..2 {
+            if self.peak[side] >= self.peak_hold[side] {
+                self.peak_hold[side] = self.peak[side];
+                self.hold_frames[side] = 0;
+            } else {
+                self.hold_frames[side] += block;
+                if self.hold_frames[side] > hold_frames {
+                    self.peak_hold[side] = (self.peak_hold[side] * decay).max(self.peak[side]);
+                }
+            }
+        }
+
+        if self.clipped_at.is_none() && self.peak.iter().any(|&peak| peak >= 1.0) {
+            self.clipped_at = Some(Duration::from_secs_f64(self.frames as f64 / rate));
+        }
+        self.frames += block;
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.clipped_at = None;
+    }
+
+    pub fn reading(&self, channel: ChannelId) -> MeterReading {
+        MeterReading {
+            channel,
+            peak: self.peak,
+            rms: self.rms,
+            peak_hold: self.peak_hold,
+            clipped_at: self.clipped_at,
+        }
+    }
+}
+
+impl Default for ChannelMeter {
+    fn default() -> Self {
+        Self::new(SampleRate::default())
+    }
+}
+
+/// A copy of a channel's meter to hand to the UI
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MeterReading {
+    pub channel: ChannelId,
+    /// Left and right, linear
+    pub peak: [f32; 2],
+    pub rms: [f32; 2],
+    pub peak_hold: [f32; 2],
+    /// When the channel clipped, in metering time, if it has since the
+    /// indicator was last reset
+    pub clipped_at: Option<Duration>,
+}
+
+impl MeterReading {
+    pub fn clipped(&self) -> bool {
+        self.clipped_at.is_some()
+    }
+}
+
+impl Mixer {
+    /// Every channel's meter, in channel order
+    pub fn meters(&self) -> Vec<MeterReading> {
+        self.channels
+            .iter()
+            .map(|c| c.meter.reading(c.id))
+            .collect()
+    }
+
+    /// Turn off every channel's clip indicator
+    pub fn reset_clip_indicators(&mut self) {
+        for channel in &mut self.channels {
+            channel.meter.reset_clip();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MixerChannel;
+    use koto_core::ChannelCount;
+
+    fn block(left: f32, right: f32, frames: usize) -> AudioBuffer {
+        let samples = (0..frames).flat_map(|_| [left, right]).collect();
+        AudioBuffer::from_samples(samples, ChannelCount::STEREO)
+    }
+
+    #[test]
+    fn test_peak_hold_decays_after_hold_time() {
+        let mut meter = ChannelMeter::new(SampleRate(1000));
+        meter.update(&block(0.5, 0.25, 100));
+        meter.update(&block(0.1, 0.1, 100));
+        let reading = meter.reading(ChannelId(1));
+        assert_eq!(reading.peak, [0.1, 0.1]);
+        assert_eq!(reading.peak_hold, [0.5, 0.25]);
+        assert!((reading.rms[0] - 0.1).abs() < 1e-6);
+
+        // 1.5 s of hold, then 20 dB per second
+        for _ in 0..14 {
+            meter.update(&block(0.0, 0.0, 100));
+        }
+        assert_eq!(meter.reading(ChannelId(1)).peak_hold[0], 0.5);
+        for _ in 0..10 {
+            meter.update(&block(0.0, 0.0, 100));
+        }
+        let held = meter.reading(ChannelId(1)).peak_hold[0];
+        assert!((held - 0.05).abs() < 1e-4, "{held}");
+    }
+
+    #[test]
+    fn test_clip_indicator_latches_until_reset() {
+        let mut mixer = Mixer::new();
+        mixer.add_channel(MixerChannel::new("Hot"));
+        mixer.add_channel(MixerChannel::new("Quiet"));
+        mixer.prepare(SampleRate(1000), 500);
+        mixer.channels[0].process(&mut block(0.2, 0.2, 500), &mut []);
+        mixer.channels[0].process(&mut block(1.2, 0.2, 10), &mut []);
+        mixer.channels[0].process(&mut block(0.2, 0.2, 10), &mut []);
+        mixer.channels[1].process(&mut block(0.3, 0.3, 10), &mut []);
+
+        let meters = mixer.meters();
+        assert_eq!(meters[0].clipped_at, Some(Duration::from_millis(500)));
+        assert!(!meters[1].clipped());
+        assert_eq!(meters[1].peak, [0.3, 0.3]);
+        assert_eq!(meters[1].channel, mixer.channels[1].id);
+
+        mixer.reset_clip_indicators();
+        assert!(mixer.meters().iter().all(|m| !m.clipped()));
+    }
+}
@@ -0,0 +1,546 @@
+//! Routing channels through buses to the master, and mixing a block in
+//! routing order
+
+use crate::{ChannelId, ChannelKind, Mixer, MixerChannel, MixerError};
+use koto_core::{AudioBuffer, ChannelCount, ChannelMap};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+/// Where a channel's output goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum OutputTarget {
+    #[default]
+    Master,
+    Bus(ChannelId),
+}
+
+impl Mixer {
+    /// Route a channel's output to the master or a bus
+    ///
+    /// Fails if the bus doesn't exist, isn't a bus, or already feeds the
+    /// channel.
+    pub fn set_channel_output(
+        &mut self,
+        channel: ChannelId,
+        target: OutputTarget,
+    ) -> Result<(), MixerError> {
+        self.channel(channel)
+            .ok_or(MixerError::ChannelNotFound(channel))?;
+        if let OutputTarget::Bus(bus) = target {
+            let destination = self.channel(bus).ok_or(MixerError::ChannelNotFound(bus))?;
+            if destination.kind != ChannelKind::Bus {
+                return Err(MixerError::NotABus(bus));
+            }
+            if self.feeds(bus, channel) {
+                return Err(MixerError::Cycle {
+                    from: channel,
+                    to: bus,
+                });
+            }
+        }
+        if let Some(channel) = self.channel_mut(channel) {
+            channel.output = target;
+        }
+        self.update_plan(0);
+        Ok(())
+    }
+
+    /// Channels in the order to process them: every channel comes after
+    /// all the channels sending or routed to it
+    ///
+    /// Uses the same topological sort as the audio graph's scheduler, but
+    /// keeps the mixer's order among independent channels. Routing set
+    /// through the mixer's methods can't loop; a loop made by editing
+    /// channels directly is an error.
+    pub fn processing_order(&self) -> Result<Vec<ChannelId>, MixerError> {
+        Ok(self
+            .order()?
+            .into_iter()
+            .map(|i| self.channels[i].id)
+            .collect())
+    }
+
+    /// [`processing_order`](Self::processing_order) as channel indices
+    fn order(&self) -> Result<Vec<usize>, MixerError> {
+        let index: HashMap<ChannelId, usize> = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id, i))
+            .collect();
+        let mut in_degree = vec![0usize; self.channels.len()];
+        let mut adj_list = vec![Vec::new(); self.channels.len()];
+        for (source, channel) in self.channels.iter().enumerate() {
+            for target in self.downstream(channel.id) {
+                if let Some(&target) = index.get(&target) {
+                    adj_list[source].push(target);
+                    in_degree[target] += 1;
+                }
+            }
+        }
+
+        // Kahn's algorithm, starting from channels nothing feeds
+        let mut queue: VecDeque<usize> = (0..self.channels.len())
+            .filter(|&i| in_degree[i] == 0)
+            .collect();
+        let mut result = Vec::with_capacity(self.channels.len());
+        while let Some(node) = queue.pop_front() {
+            result.push(node);
+            for &neighbor in &adj_list[node] {
+                in_degree[neighbor] -= 1;
+                if in_degree[neighbor] == 0 {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        if result.len() != self.channels.len() {
+            return Err(MixerError::RoutingCycle);
+        }
+        Ok(result)
+    }
+
+    /// Mix one block of `frames` frames and return the master output
+    ///
+    /// Each channel processes its entry in `inputs`, if any, plus whatever
+    /// was routed or sent to it, then passes its output on to its bus or
+    /// the master. Channels are muted by [`effective_mute`](Self::effective_mute),
+    /// worked out again only when a solo, mute or the routing changes, so
+    /// solo applies, and their faders are at their
+    /// [`effective_volume`](Self::effective_volume), so VCAs apply.
+    /// Channels and buses are stereo; inputs of other
+    /// layouts are mapped with [`ChannelMap::for_channels`].
+    ///
+    /// Mixes into the buffers and processing order set up by
+    /// [`prepare`](Self::prepare) and the routing methods, so it doesn't
+    /// allocate for blocks up to the prepared size. Routing changed by
+    /// editing channels directly, or a larger block, sets them up again
+    /// on the next block.
+    pub fn process_all(
+        &mut self,
+        inputs: &HashMap<ChannelId, AudioBuffer>,
+        frames: usize,
+    ) -> Result<&AudioBuffer, MixerError> {
+        self.update_plan(frames);
+        let mut plan = std::mem::take(&mut self.plan);
+        plan.update_mutes(&self.channels);
+        let mixed = self.mix(&mut plan, inputs, frames);
+        self.plan = plan;
+        mixed?;
+        Ok(self.plan.master())
+    }
+
+    fn mix(
+        &mut self,
+        plan: &mut MixPlan,
+        inputs: &HashMap<ChannelId, AudioBuffer>,
+        frames: usize,
+    ) -> Result<(), MixerError> {
+        let MixPlan {
+            order,
+            outputs,
+            send_targets,
+            received,
+            sends,
+            faders,
+            muted,
+            remapped,
+            remap,
+            ..
+        } = plan;
+        let order = order.as_ref().ok_or(MixerError::RoutingCycle)?;
+        for (index, fader) in faders.iter_mut().enumerate() {
+            *fader = self.fader(index, muted[index]);
+        }
+        for buffer in received.iter_mut() {
+            buffer.set_frames(frames);
+            buffer.clear();
+        }
+
+        for &index in order {
+            let mut buffer = std::mem::take(&mut received[index]);
+            let channel = &mut self.channels[index];
+            if let Some(input) = inputs.get(&channel.id) {
+                if input.channels() == ChannelCount::STEREO {
+                    buffer.mix(input);
+                } else {
+                    if remap.as_ref().map(|(layout, _)| *layout) != Some(input.channels()) {
+                        let map = ChannelMap::for_channels(input.channels(), ChannelCount::STEREO);
+                        *remap = Some((input.channels(), map));
+                    }
+                    if let Some((_, map)) = remap {
+                        remapped.set_frames(frames);
+                        remapped.copy_remapped(input, map);
+                        buffer.mix(remapped);
+                    }
+                }
+            }
+            let tapped = &mut sends[index];
+            for send in tapped.iter_mut() {
+                send.set_frames(frames);
+            }
+            channel.process_fader(&mut buffer, tapped, faders[index]);
+            for ((send, tapped), target) in channel
+                .sends
+                .iter()
+                .zip(tapped.iter())
+                .zip(&send_targets[index])
+            {
+                if let (true, Some(target)) = (send.enabled, target) {
+                    received[*target].mix(tapped);
+                }
+            }
+            if let Some(target) = outputs[index] {
+                received[target].mix(&buffer);
+            }
+            received[index] = buffer;
+        }
+        if let Some(master) = received.last_mut() {
+            master.apply_gain(self.master_volume);
+        }
+        Ok(())
+    }
+
+    /// Set up the plan [`process_all`](Self::process_all) mixes with again
+    /// if the routing changed, or if blocks of `frames` frames don't fit
+    ///
+    /// Allocates when it does, so the routing methods call it to keep the
+    /// work off the audio thread.
+    pub(crate) fn update_plan(&mut self, frames: usize) {
+        let routing = self.routing_key();
+        if self.plan.routing == Some(routing) && frames <= self.plan.max_frames {
+            return;
+        }
+        let max_frames = frames.max(self.plan.max_frames);
+        let order = self.order().ok();
+        let index: HashMap<ChannelId, usize> = self
+            .channels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.id, i))
+            .collect();
+        let master = self.channels.len();
+        let silence = || AudioBuffer::new(ChannelCount::STEREO, max_frames);
+        self.plan = MixPlan {
+            routing: Some(routing),
+            max_frames,
+            order,
+            outputs: self
+                .channels
+                .iter()
+                .map(|c| match c.output {
+                    OutputTarget::Master => Some(master),
+                    OutputTarget::Bus(bus) => index.get(&bus).copied(),
+                })
+                .collect(),
+            send_targets: self
+                .channels
+                .iter()
+                .map(|c| {
+                    c.sends
+                        .iter()
+                        .map(|s| index.get(&s.destination).copied())
+                        .collect()
+                })
+                .collect(),
+            received: (0..=master).map(|_| silence()).collect(),
+            sends: self
+                .channels
+                .iter()
+                .map(|c| vec![silence(); c.sends.len()])
+                .collect(),
+            faders: vec![0.0; self.channels.len()],
+            solo: None,
+            muted: vec![false; self.channels.len()],
+            downstream: vec![false; master + 1],
+            upstream: vec![false; master + 1],
+            remapped: silence(),
+            remap: None,
+        };
+    }
+
+    /// Fingerprint of the routing the plan depends on
+    fn routing_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for channel in &self.channels {
+            channel.id.hash(&mut hasher);
+            channel.output.hash(&mut hasher);
+            channel.sends.len().hash(&mut hasher);
+            for send in &channel.sends {
+                send.destination.hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Channels `channel` sends or outputs to
+    fn downstream(&self, channel: ChannelId) -> impl Iterator<Item = ChannelId> + '_ {
+        let channel = self.channel(channel);
+        let sends = channel
+            .into_iter()
+            .flat_map(|c| c.sends.iter().map(|s| s.destination));
+        let output = channel.and_then(|c| match c.output {
+            OutputTarget::Bus(bus) => Some(bus),
+            OutputTarget::Master => None,
+        });
+        sends.chain(output)
+    }
+
+    /// Whether signal from `from` reaches `to`, directly or through other
+    /// channels
+    pub(crate) fn feeds(&self, from: ChannelId, to: ChannelId) -> bool {
+        let mut pending = vec![from];
+        let mut visited = Vec::new();
+        while let Some(id) = pending.pop() {
+            if id == to {
+                return true;
+            }
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id);
+            pending.extend(self.downstream(id));
+        }
+        false
+    }
+
+    /// Fader gain of the channel at `index`, after solo, given whether
+    /// it's `muted`, and VCAs
+    pub(crate) fn fader(&self, index: usize, muted: bool) -> f32 {
+        let channel = &self.channels[index];
+        if muted {
+            0.0
+        } else {
+            self.effective_volume(channel.id).unwrap_or(channel.volume)
+        }
+    }
+
+    /// Remove sends to channels that are gone and their VCA memberships,
+    /// and send the output of channels routed to them to the master
+    pub(crate) fn drop_dangling_routes(&mut self) {
+        let ids: Vec<ChannelId> = self.channels.iter().map(|c| c.id).collect();
+        for group in &mut self.vca_groups {
+            group.members.retain(|member| ids.contains(member));
+        }
+        for channel in &mut self.channels {
+            channel.sends.retain(|s| ids.contains(&s.destination));
+            if let OutputTarget::Bus(bus) = channel.output {
+                if !ids.contains(&bus) {
+                    channel.output = OutputTarget::Master;
+                }
+            }
+        }
+    }
+}
+
+/// What [`Mixer::process_all`] mixes a block with, worked out ahead of
+/// time so a block only clears and reuses it
+#[derive(Default)]
+pub(crate) struct MixPlan {
+    /// Fingerprint of the routing the plan was made for
+    routing: Option<u64>,
+    /// Largest block the buffers hold without growing
+    max_frames: usize,
+    /// Channel indices in processing order; `None` if the routing loops
+    order: Option<Vec<usize>>,
+    /// Where each channel's output goes, as an index into `received`;
+    /// `None` for a bus that doesn't exist
+    outputs: Vec<Option<usize>>,
+    /// Where each of a channel's sends goes, as for `outputs`
+    send_targets: Vec<Vec<Option<usize>>>,
+    /// What each channel receives from the channels feeding it, followed
+    /// by the master
+    received: Vec<AudioBuffer>,
+    /// What each of a channel's sends picks up
+    sends: Vec<Vec<AudioBuffer>>,
+    faders: Vec<f32>,
+    /// Fingerprint of the solo, mute and solo-safe flags `muted` was
+    /// worked out for
+    solo: Option<u64>,
+    /// [`Mixer::effective_mute`] of each channel
+    muted: Vec<bool>,
+    /// Whether each channel is downstream or upstream of a soloed one,
+    /// while working out `muted`
+    downstream: Vec<bool>,
+    upstream: Vec<bool>,
+    /// A non-stereo input mapped to stereo, with the map for its layout
+    remapped: AudioBuffer,
+    remap: Option<(ChannelCount, ChannelMap)>,
+}
+
+impl MixPlan {
+    fn master(&self) -> &AudioBuffer {
+        self.received.last().expect("built with the master last")
+    }
+
+    pub(crate) fn is_muted(&self, index: usize) -> bool {
+        self.muted.get(index).copied().unwrap_or(false)
+    }
+
+    /// Work out [`Mixer::effective_mute`] of every channel again if a solo,
+    /// mute or solo-safe flag changed
+    ///
+    /// One pass each way through the processing order finds the channels
+    /// in a soloed channel's signal path, without allocating. Call it with
+    /// the channels the plan was made for.
+    pub(crate) fn update_mutes(&mut self, channels: &[MixerChannel]) {
+        let solo = solo_key(channels);
+        if self.solo == Some(solo) {
+            return;
+        }
+        self.solo = Some(solo);
+        let order = self.order.as_deref().unwrap_or_default();
+        let (outputs, send_targets) = (&self.outputs, &self.send_targets);
+        let targets = |index: usize| {
+            outputs[index]
+                .into_iter()
+                .chain(send_targets[index].iter().flatten().copied())
+        };
+        for (flag, channel) in self.downstream.iter_mut().zip(channels) {
+            *flag = channel.solo;
+        }
+        for &index in order {
+            if self.downstream[index] {
+                for target in targets(index) {
+                    self.downstream[target] = true;
+                }
+            }
+        }
+        for &index in order.iter().rev() {
+            self.upstream[index] =
+                channels[index].solo || targets(index).any(|target| self.upstream[target]);
+        }
+
+        let any_solo = channels.iter().any(|c| c.solo);
+        for (index, channel) in channels.iter().enumerate() {
+            let in_soloed_path = self.downstream[index] || self.upstream[index];
+            self.muted[index] = if !any_solo || channel.solo_safe || in_soloed_path {
+                channel.mute && !channel.solo
+            } else {
+                true
+            };
+        }
+    }
+}
+
+/// Fingerprint of the flags [`Mixer::effective_mute`] depends on, besides
+/// the routing
+fn solo_key(channels: &[MixerChannel]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for channel in channels {
+        (channel.solo, channel.mute, channel.solo_safe).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuxSend, MixerChannel};
+    use koto_core::SampleRate;
+
+    fn add(mixer: &mut Mixer, channel: MixerChannel) -> ChannelId {
+        let index = mixer.add_channel(channel);
+        mixer.channels[index].id
+    }
+
+    fn constant(value: f32) -> AudioBuffer {
+        AudioBuffer::from_samples(vec![value; 8], ChannelCount::STEREO)
+    }
+
+    #[test]
+    fn test_two_channels_through_bus_to_master() {
+        let mut mixer = Mixer::new();
+        // Added first, so the order has to move it after its sources
+        let drums = add(&mut mixer, MixerChannel::bus("Drums"));
+        let kick = add(&mut mixer, MixerChannel::new("Kick"));
+        let snare = add(&mut mixer, MixerChannel::new("Snare"));
+        let keys = add(&mut mixer, MixerChannel::new("Keys"));
+        mixer
+            .set_channel_output(kick, OutputTarget::Bus(drums))
+            .unwrap();
+        mixer
+            .set_channel_output(snare, OutputTarget::Bus(drums))
+            .unwrap();
+        assert_eq!(
+            mixer.set_channel_output(kick, OutputTarget::Bus(keys)),
+            Err(MixerError::NotABus(keys))
+        );
+
+        let order = mixer.processing_order().unwrap();
+        assert_eq!(order, [kick, snare, keys, drums]);
+
+        mixer.channel_mut(drums).unwrap().volume = 0.5;
+        mixer.master_volume = 0.5;
+        let inputs = HashMap::from([
+            (kick, constant(0.25)),
+            (snare, constant(0.5)),
+            (keys, constant(0.125)),
+        ]);
+        let master = mixer.process_all(&inputs, 4).unwrap();
+        // ((0.25 + 0.5) * 0.5 + 0.125) * 0.5
+        assert_eq!(master.samples(), [0.25; 8]);
+    }
+
+    #[test]
+    fn test_blocks_reuse_the_prepared_buffers() {
+        let mut mixer = Mixer::new();
+        let bus = add(&mut mixer, MixerChannel::bus("Bus"));
+        let fx = add(&mut mixer, MixerChannel::aux_return("FX"));
+        let source = add(&mut mixer, MixerChannel::new("Source"));
+        mixer.add_send(source, AuxSend::new(fx, 0.0)).unwrap();
+        mixer.channel_mut(bus).unwrap().volume = 0.0;
+        mixer.prepare(SampleRate(48000), 8);
+
+        let inputs = HashMap::from([(source, constant(0.25))]);
+        let master = mixer.process_all(&inputs, 4).unwrap();
+        // Straight to the master, and once more through the return
+        assert_eq!(master.samples(), [0.5; 8]);
+        let samples = master.samples().as_ptr();
+        let mono = AudioBuffer::from_samples(vec![0.25; 2], ChannelCount::MONO);
+        let master = mixer
+            .process_all(&HashMap::from([(source, mono)]), 2)
+            .unwrap();
+        assert_eq!(master.samples(), [0.5; 4]);
+        assert_eq!(master.samples().as_ptr(), samples);
+
+        // Routing changed behind the mixer's back is picked up
+        mixer.channel_mut(source).unwrap().output = OutputTarget::Bus(bus);
+        let master = mixer.process_all(&inputs, 4).unwrap();
+        assert_eq!(master.samples(), [0.25; 8]);
+    }
+
+    #[test]
+    fn test_routing_cycle_is_rejected() {
+        let mut mixer = Mixer::new();
+        let a = add(&mut mixer, MixerChannel::bus("A"));
+        let b = add(&mut mixer, MixerChannel::bus("B"));
+        let fx = add(&mut mixer, MixerChannel::aux_return("FX"));
+        mixer.set_channel_output(a, OutputTarget::Bus(b)).unwrap();
+        assert_eq!(
+            mixer.set_channel_output(b, OutputTarget::Bus(a)),
+            Err(MixerError::Cycle { from: b, to: a })
+        );
+        assert_eq!(
+            mixer.set_channel_output(a, OutputTarget::Bus(a)),
+            Err(MixerError::Cycle { from: a, to: a })
+        );
+        // A return feeding bus A can't be sent to from downstream of A
+        mixer.set_channel_output(fx, OutputTarget::Bus(a)).unwrap();
+        assert_eq!(
+            mixer.add_send(b, AuxSend::new(fx, 0.0)),
+            Err(MixerError::Cycle { from: b, to: fx })
+        );
+
+        // A loop made behind the mixer's back stops processing
+        mixer.channel_mut(b).unwrap().output = OutputTarget::Bus(a);
+        assert_eq!(mixer.processing_order(), Err(MixerError::RoutingCycle));
+        assert!(mixer.process_all(&HashMap::new(), 4).is_err());
+
+        mixer.remove_channel(mixer.channel_index(a).unwrap());
+        assert_eq!(mixer.channel(b).unwrap().output, OutputTarget::Master);
+        assert_eq!(mixer.processing_order().unwrap(), [b, fx]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Aux sends from channels to return channels
+
+use crate::{ChannelId, ChannelKind, Mixer, MixerError};
+use serde::{Deserialize, Serialize};
+
+/// A copy of a channel's signal sent to an aux return
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AuxSend {
+    pub destination: ChannelId,
+    pub level_db: f32,
+    /// Tap before the fader, so the send ignores the channel's fader, pan
+    /// and mute; see [`MixerChannel::process`](crate::MixerChannel::process)
+    pub pre_fader: bool,
+    pub enabled: bool,
+}
+
+impl AuxSend {
+    /// An enabled post-fader send
+    pub fn new(destination: ChannelId, level_db: f32) -> Self {
+        Self {
+            destination,
+            level_db,
+            pre_fader: false,
+            enabled: true,
+        }
+    }
+}
+
+impl Mixer {
+    /// Add a send from channel `from`
+    ///
+    /// The destination must be an aux return other than `from`, not already
+    /// sent to by `from` and not feeding back into `from`.
+    pub fn add_send(&mut self, from: ChannelId, send: AuxSend) -> Result<(), MixerError> {
+        let to = send.destination;
+        self.channel(from)
+            .ok_or(MixerError::ChannelNotFound(from))?;
+        let destination = self.channel(to).ok_or(MixerError::ChannelNotFound(to))?;
+        if to == from {
+            return Err(MixerError::SendToSelf(from));
+        }
+        if destination.kind != ChannelKind::AuxReturn {
+            return Err(MixerError::NotAReturn(to));
+        }
+        if self.find_send(from, to).is_ok() {
+            return Err(MixerError::SendExists { from, to });
+        }
+        if self.feeds(to, from) {
+            return Err(MixerError::Cycle { from, to });
+        }
+        if let Some(channel) = self.channel_mut(from) {
+            channel.sends.push(send);
+        }
+        self.update_plan(0);
+        Ok(())
+    }
+
+    pub fn remove_send(&mut self, from: ChannelId, to: ChannelId) -> Result<AuxSend, MixerError> {
+        let index = self.find_send(from, to)?;
+        let channel = self
+            .channel_mut(from)
+            .ok_or(MixerError::ChannelNotFound(from))?;
+        let send = channel.sends.remove(index);
+        self.update_plan(0);
+        Ok(send)
+    }
+
+    pub fn set_send_level(
+        &mut self,
+        from: ChannelId,
+        to: ChannelId,
+        level_db: f32,
+    ) -> Result<(), MixerError> {
+        let index = self.find_send(from, to)?;
+        if let Some(channel) = self.channel_mut(from) {
+            channel.sends[index].level_db = level_db;
+        }
+        Ok(())
+    }
+
+    /// Index of `from`'s send to `to`
+    fn find_send(&self, from: ChannelId, to: ChannelId) -> Result<usize, MixerError> {
+        self.channel(from)
+            .ok_or(MixerError::ChannelNotFound(from))?
+            .sends
+            .iter()
+            .position(|s| s.destination == to)
+            .ok_or(MixerError::SendNotFound { from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MixerChannel;
+    use koto_core::{AudioBuffer, ChannelCount};
+
+    fn add(mixer: &mut Mixer, channel: MixerChannel) -> ChannelId {
+        let index = mixer.add_channel(channel);
+        mixer.channels[index].id
+    }
+
+    #[test]
+    fn test_send_validation_rejects_self_and_cycles() {
+        let mut mixer = Mixer::new();
+        let vox = add(&mut mixer, MixerChannel::new("Vox"));
+        let delay = add(&mut mixer, MixerChannel::aux_return("Delay"));
+        let reverb = add(&mut mixer, MixerChannel::aux_return("Reverb"));
+        let bass = add(&mut mixer, MixerChannel::new("Bass"));
+
+        assert_eq!(
+            mixer.add_send(vox, AuxSend::new(bass, 0.0)),
+            Err(MixerError::NotAReturn(bass))
+        );
+        assert_eq!(
+            mixer.add_send(delay, AuxSend::new(delay, 0.0)),
+            Err(MixerError::SendToSelf(delay))
+        );
+        mixer.add_send(vox, AuxSend::new(delay, -6.0)).unwrap();
+        mixer.add_send(delay, AuxSend::new(reverb, -12.0)).unwrap();
+        assert_eq!(
+            mixer.add_send(vox, AuxSend::new(delay, 0.0)),
+            Err(MixerError::SendExists {
+                from: vox,
+                to: delay
+            })
+        );
+        // Delay feeds the reverb, so the reverb can't feed the delay
+        assert_eq!(
+            mixer.add_send(reverb, AuxSend::new(delay, 0.0)),
+            Err(MixerError::Cycle {
+                from: reverb,
+                to: delay
+            })
+        );
+
+        mixer.set_send_level(vox, delay, -3.0).unwrap();
+        let json = serde_json::to_string(&mixer).unwrap();
+        let restored: Mixer = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            restored.channel(vox).unwrap().sends,
+            [AuxSend::new(delay, -3.0)]
+        );
+
+        mixer.remove_send(delay, reverb).unwrap();
+        mixer.add_send(reverb, AuxSend::new(delay, 0.0)).unwrap();
+        mixer.remove_channel(mixer.channel_index(delay).unwrap());
+        assert!(mixer.channel(vox).unwrap().sends.is_empty());
+        assert!(mixer.channel(reverb).unwrap().sends.is_empty());
+    }
+
+    #[test]
+    fn test_pre_and_post_fader_tap_points() {
+        let mut channel = MixerChannel::new("Gtr");
+        channel.input_trim_db = 6.0;
+        channel.volume = 0.5;
+        channel.sends = vec![
+            AuxSend {
+                pre_fader: true,
+                ..AuxSend::new(ChannelId(7), 0.0)
+            },
+            AuxSend::new(ChannelId(8), -6.0),
+            AuxSend {
+                enabled: false,
+                ..AuxSend::new(ChannelId(9), 0.0)
+            },
+        ];
+        let mut buffer = AudioBuffer::from_samples(vec![0.25; 8], ChannelCount::STEREO);
+        let mut sends = vec![AudioBuffer::from_samples(vec![1.0; 8], ChannelCount::STEREO); 3];
+        channel.process(&mut buffer, &mut sends);
+
+        let trim = 10.0_f32.powf(6.0 / 20.0);
+        let level = |output: &AudioBuffer| output.samples()[0];
+        assert!((level(&sends[0]) - 0.25 * trim).abs() < 1e-6);
+        let post = 0.25 * trim * 0.5;
+        assert!((buffer.samples()[0] - post).abs() < 1e-6);
+        assert!((level(&sends[1]) - post * 10.0_f32.powf(-6.0 / 20.0)).abs() < 1e-6);
+        assert!(sends[2].samples().iter().all(|&s| s == 0.0));
+    }
+}
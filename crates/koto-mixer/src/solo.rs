@@ -0,0 +1,149 @@
+//! Solo-in-place: which channels are heard while others are soloed
+
+use crate::Mixer;
+use serde::{Deserialize, Serialize};
+
+/// What soloing a channel does to the other solos
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SoloMode {
+    /// Add the channel to the soloed ones
+    #[default]
+    Additive,
+    /// Solo only that channel
+    Exclusive,
+}
+
+impl Mixer {
+    /// Whether any channel is soloed
+    pub fn any_solo(&self) -> bool {
+        self.channels.iter().any(|c| c.solo)
+    }
+
+    /// Solo or unsolo a channel, following [`solo_mode`](Self::solo_mode)
+    ///
+    /// Solo never touches mute flags, so clearing it brings back the mutes
+    /// as they were.
+    pub fn set_solo(&mut self, index: usize, solo: bool) {
+        if index >= self.channels.len() {
+            return;
+        }
+        if solo && self.solo_mode == SoloMode::Exclusive {
+            for channel in &mut self.channels {
+                channel.solo = false;
+            }
+        }
+        self.channels[index].solo = solo;
+        self.plan.update_mutes(&self.channels);
+    }
+
+    /// Unsolo every channel
+    pub fn solo_clear(&mut self) {
+        for channel in &mut self.channels {
+            channel.solo = false;
+        }
+        self.plan.update_mutes(&self.channels);
+    }
+
+    /// Whether a channel is silenced, by its mute or by others' solo
+    ///
+    /// While any channel is soloed, soloed channels are heard even if
+    /// muted, as on the audio engine's tracks. So are channels in a soloed
+    /// channel's signal path, like the bus it feeds or the sources of a
+    /// soloed bus, and solo-safe channels, unless muted. Everything else is
+    /// silenced.
+    ///
+    /// Checks the routing on each call; [`process_all`](Self::process_all)
+    /// works out every channel's at once, only when they can change.
+    pub fn effective_mute(&self, index: usize) -> bool {
+        let Some(channel) = self.channels.get(index) else {
+            return false;
+        };
+        if !self.any_solo() {
+            return channel.mute;
+        }
+        if channel.solo {
+            return false;
+        }
+        let in_soloed_path =
+            self.channels.iter().filter(|c| c.solo).any(|soloed| {
+                self.feeds(channel.id, soloed.id) || self.feeds(soloed.id, channel.id)
+            });
+        if channel.solo_safe || in_soloed_path {
+            channel.mute
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuxSend, MixerChannel, OutputTarget};
+
+    /// Effective mutes, checking the ones the mixer mixes with agree
+    fn mutes(mixer: &mut Mixer) -> Vec<bool> {
+        let mutes: Vec<bool> = (0..mixer.channels.len())
+            .map(|i| mixer.effective_mute(i))
+            .collect();
+        mixer.plan.update_mutes(&mixer.channels);
+        let mixed: Vec<bool> = (0..mutes.len()).map(|i| mixer.plan.is_muted(i)).collect();
+        assert_eq!(mixed, mutes);
+        mutes
+    }
+
+    #[test]
+    fn test_solo_mutes_others_except_solo_safe() {
+        let mut mixer = Mixer::new();
+        for name in ["A", "B", "C"] {
+            mixer.add_channel(MixerChannel::new(name));
+        }
+        mixer.add_channel(MixerChannel::aux_return("Reverb"));
+        let (a, reverb) = (mixer.channels[0].id, mixer.channels[3].id);
+        mixer.add_send(a, AuxSend::new(reverb, -10.0)).unwrap();
+        mixer.channels[1].solo_safe = true;
+        mixer.channels[2].mute = true;
+        let before = mutes(&mut mixer);
+        assert_eq!(before, [false, false, true, false]);
+
+        mixer.set_solo(0, true);
+        assert_eq!(mutes(&mut mixer), [false, false, true, false]);
+        mixer.channels[1].solo_safe = false;
+        assert_eq!(mutes(&mut mixer), [false, true, true, false]);
+
+        // Additive by default; a soloed channel plays through its mute
+        mixer.set_solo(2, true);
+        assert_eq!(mutes(&mut mixer), [false, true, false, false]);
+        mixer.solo_mode = SoloMode::Exclusive;
+        mixer.set_solo(1, true);
+        assert_eq!(mutes(&mut mixer), [true, false, true, false]);
+
+        mixer.channels[1].solo_safe = true;
+        mixer.solo_clear();
+        assert!(!mixer.any_solo());
+        assert_eq!(mutes(&mut mixer), before);
+    }
+
+    #[test]
+    fn test_solo_follows_bus_routing() {
+        let mut mixer = Mixer::new();
+        mixer.add_channel(MixerChannel::new("Kick"));
+        mixer.add_channel(MixerChannel::new("Snare"));
+        mixer.add_channel(MixerChannel::bus("Drums"));
+        mixer.add_channel(MixerChannel::new("Bass"));
+        let ids: Vec<_> = mixer.channels.iter().map(|c| c.id).collect();
+        for source in &ids[..2] {
+            mixer
+                .set_channel_output(*source, OutputTarget::Bus(ids[2]))
+                .unwrap();
+        }
+
+        // A soloed source is heard through its bus
+        mixer.set_solo(0, true);
+        assert_eq!(mutes(&mut mixer), [false, true, false, true]);
+        // A soloed bus brings its sources
+        mixer.solo_clear();
+        mixer.set_solo(2, true);
+        assert_eq!(mutes(&mut mixer), [false, false, false, true]);
+    }
+}
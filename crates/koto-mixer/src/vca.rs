@@ -0,0 +1,223 @@
+//! VCA groups: faders that move the level of their member channels
+//! without carrying audio
+
+use crate::{db_to_gain, ChannelId, Mixer, MixerError};
+use serde::{Deserialize, Serialize};
+
+/// Unique identifier for VCA groups, assigned by [`Mixer::create_vca`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct VcaId(pub u64);
+
+/// A VCA fader and the channels it controls
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VcaGroup {
+    pub id: VcaId,
+    pub name: String,
+    /// Added to the fader level of every member, in dB
+    pub level_db: f32,
+    /// Change them through [`Mixer::assign_to_vca`] and
+    /// [`Mixer::unassign_from_vca`]
+    pub members: Vec<ChannelId>,
+    /// VCA controlling this one, whose level also applies to the members;
+    /// change it through [`Mixer::set_vca_parent`]
+    #[serde(default)]
+    pub parent: Option<VcaId>,
+}
+
+impl Mixer {
+    /// Add a VCA group at 0 dB with no members; returns its ID
+    pub fn create_vca(&mut self, name: impl Into<String>) -> VcaId {
+        let id = VcaId(self.next_vca_id);
+        self.next_vca_id += 1;
+        self.vca_groups.push(VcaGroup {
+            id,
+            name: name.into(),
+            level_db: 0.0,
+            members: Vec::new(),
+            parent: None,
+        });
+        id
+    }
+
+    /// Remove a VCA group; VCAs it controlled become top-level
+    pub fn remove_vca(&mut self, id: VcaId) -> Result<VcaGroup, MixerError> {
+        let index = self
+            .vca_groups
+            .iter()
+            .position(|g| g.id == id)
+            .ok_or(MixerError::VcaNotFound(id))?;
+        let group = self.vca_groups.remove(index);
+        for child in &mut self.vca_groups {
+            if child.parent == Some(id) {
+                child.parent = None;
+            }
+        }
+        Ok(group)
+    }
+
+    pub fn vca(&self, id: VcaId) -> Option<&VcaGroup> {
+        self.vca_groups.iter().find(|g| g.id == id)
+    }
+
+    pub fn vca_mut(&mut self, id: VcaId) -> Option<&mut VcaGroup> {
+        self.vca_groups.iter_mut().find(|g| g.id == id)
+    }
+
+    /// Put a channel under a VCA; a channel can be in several
+    pub fn assign_to_vca(&mut self, vca: VcaId, channel: ChannelId) -> Result<(), MixerError> {
+        self.channel(channel)
+            .ok_or(MixerError::ChannelNotFound(channel))?;
+        let group = self.vca_mut(vca).ok_or(MixerError::VcaNotFound(vca))?;
+        if !group.members.contains(&channel) {
+            group.members.push(channel);
+        }
+        Ok(())
+    }
+
+    pub fn unassign_from_vca(&mut self, vca: VcaId, channel: ChannelId) -> Result<(), MixerError> {
+        let group = self.vca_mut(vca).ok_or(MixerError::VcaNotFound(vca))?;
+        group.members.retain(|&member| member != channel);
+        Ok(())
+    }
+
+    /// Put a VCA under another one, or back at the top level with `None`
+    ///
+    /// Fails if `parent` is `vca` or already controlled by it.
+    pub fn set_vca_parent(&mut self, vca: VcaId, parent: Option<VcaId>) -> Result<(), MixerError> {
+        self.vca(vca).ok_or(MixerError::VcaNotFound(vca))?;
+        if let Some(parent) = parent {
+            self.vca(parent).ok_or(MixerError::VcaNotFound(parent))?;
+            if self.vca_chain(parent).any(|g| g.id == vca) {
+                return Err(MixerError::VcaCycle { vca, parent });
+            }
+        }
+        if let Some(group) = self.vca_mut(vca) {
+            group.parent = parent;
+        }
+        Ok(())
+    }
+
+    /// Total VCA level applied to a channel, in dB
+    ///
+    /// Sums the levels of the channel's VCAs and of the VCAs above them,
+    /// counting each VCA once even if it reaches the channel more than one
+    /// way.
+    pub fn vca_level_db(&self, channel: ChannelId) -> f32 {
+        let mut applied: Vec<VcaId> = Vec::new();
+        let mut level_db = 0.0;
+        for group in self
+            .vca_groups
+            .iter()
+            .filter(|g| g.members.contains(&channel))
+        {
+            for vca in self.vca_chain(group.id) {
+                if !applied.contains(&vca.id) {
+                    applied.push(vca.id);
+                    level_db += vca.level_db;
+                }
+            }
+        }
+        level_db
+    }
+
+    /// A channel's fader level with its VCAs applied, as a linear gain
+    pub fn effective_volume(&self, channel: ChannelId) -> Option<f32> {
+        let volume = self.channel(channel)?.volume;
+        Some(volume * db_to_gain(self.vca_level_db(channel)))
+    }
+
+    /// `vca` and the VCAs above it, nearest first
+    fn vca_chain(&self, vca: VcaId) -> impl Iterator<Item = &VcaGroup> + '_ {
+        let mut next = self.vca(vca);
+        // Bounded in case a loop was made by editing `parent` directly
+        std::iter::from_fn(move || {
+            let group = next?;
+            next = group.parent.and_then(|parent| self.vca(parent));
+            Some(group)
+        })
+        .take(self.vca_groups.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MixerChannel;
+    use koto_core::{AudioBuffer, ChannelCount};
+    use std::collections::HashMap;
+
+    fn add(mixer: &mut Mixer, channel: MixerChannel) -> ChannelId {
+        let index = mixer.add_channel(channel);
+        mixer.channels[index].id
+    }
+
+    #[test]
+    fn test_vca_levels_add_to_member_faders() {
+        let mut mixer = Mixer::new();
+        let kick = add(&mut mixer, MixerChannel::new("Kick"));
+        let snare = add(&mut mixer, MixerChannel::new("Snare"));
+        let drums = mixer.create_vca("Drums");
+        let all = mixer.create_vca("All");
+        mixer.channel_mut(kick).unwrap().volume = 0.5;
+        for channel in [kick, snare] {
+            mixer.assign_to_vca(drums, channel).unwrap();
+        }
+        mixer.assign_to_vca(all, kick).unwrap();
+        mixer.vca_mut(drums).unwrap().level_db = -6.0;
+        mixer.vca_mut(all).unwrap().level_db = -14.0;
+        assert_eq!(mixer.vca_level_db(kick), -20.0);
+        assert!((mixer.effective_volume(kick).unwrap() - 0.05).abs() < 1e-6);
+        assert_eq!(mixer.vca_level_db(snare), -6.0);
+
+        // The VCA gain reaches the audio
+        mixer.unassign_from_vca(all, kick).unwrap();
+        mixer.vca_mut(drums).unwrap().level_db = 20.0 * 0.5_f32.log10();
+        let inputs = HashMap::from([(
+            snare,
+            AudioBuffer::from_samples(vec![0.5; 8], ChannelCount::STEREO),
+        )]);
+        let master = mixer.process_all(&inputs, 4).unwrap();
+        assert!(master.samples().iter().all(|s| (s - 0.25).abs() < 1e-6));
+
+        let json = serde_json::to_string(&mixer).unwrap();
+        let restored: Mixer = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.vca_groups, mixer.vca_groups);
+        mixer.remove_channel(mixer.channel_index(kick).unwrap());
+        assert_eq!(mixer.vca(drums).unwrap().members, [snare]);
+    }
+
+    #[test]
+    fn test_nested_vcas_apply_once_and_reject_loops() {
+        let mut mixer = Mixer::new();
+        let vox = add(&mut mixer, MixerChannel::new("Vox"));
+        let leads = mixer.create_vca("Leads");
+        let music = mixer.create_vca("Music");
+        mixer.assign_to_vca(leads, vox).unwrap();
+        mixer.vca_mut(leads).unwrap().level_db = -3.0;
+        mixer.vca_mut(music).unwrap().level_db = -6.0;
+        mixer.set_vca_parent(leads, Some(music)).unwrap();
+        assert_eq!(mixer.vca_level_db(vox), -9.0);
+        // Also a direct member of Music: its level still applies once
+        mixer.assign_to_vca(music, vox).unwrap();
+        assert_eq!(mixer.vca_level_db(vox), -9.0);
+
+        assert_eq!(
+            mixer.set_vca_parent(music, Some(leads)),
+            Err(MixerError::VcaCycle {
+                vca: music,
+                parent: leads
+            })
+        );
+        assert_eq!(
+            mixer.set_vca_parent(music, Some(music)),
+            Err(MixerError::VcaCycle {
+                vca: music,
+                parent: music
+            })
+        );
+
+        mixer.remove_vca(music).unwrap();
+        assert_eq!(mixer.vca(leads).unwrap().parent, None);
+        assert_eq!(mixer.vca_level_db(vox), -3.0);
+    }
+}
@@ -0,0 +1,247 @@
+//! Periodic autosaves next to the project file
+
+use crate::{Project, ProjectError};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Path of the `n`th autosave of the project at `project_path`, 1 being
+/// the newest: `song.kproj` autosaves to `song.autosave-1.kproj`
+pub fn autosave_path(project_path: &Path, n: usize) -> PathBuf {
+    let stem = project_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    project_path.with_file_name(format!("{stem}.autosave-{n}.kproj"))
+}
+
+/// Write a file through a temporary next to it, renamed into place once
+/// complete, so a crash leaves either the old file or the new one
+pub(crate) fn write_atomic(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    let temp = path.with_file_name(name);
+    let result = File::create(&temp).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()
+    });
+    match result {
+        Ok(()) => {
+            std::fs::rename(&temp, path)?;
+            sync_dir(path)
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&temp);
+            Err(err)
+        }
+    }
+}
+
+/// Make a rename in `path`'s directory durable
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        Some(dir) => File::open(dir)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Saves rotating copies of a modified project every `interval`
+///
+/// Autosaves never touch the project file itself, nor its path or modified
+/// flag; the newest `keep` copies are kept.
+#[derive(Debug, Clone)]
+pub struct AutosaveManager {
+    pub interval: Duration,
+    pub keep: usize,
+    last_save: Option<Instant>,
+}
+
+impl AutosaveManager {
+    pub fn new(interval: Duration, keep: usize) -> Self {
+        Self {
+            interval,
+            keep: keep.max(1),
+            last_save: None,
+        }
+    }
+
+    /// Whether `interval` has passed since the last autosave
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.last_save
+            .is_none_or(|last| now.duration_since(last) >= self.interval)
+    }
+
+    /// Autosave if due; meant to be called regularly from the UI loop
+    pub fn tick(&mut self, project: &Project, now: Instant) -> std::io::Result<Option<PathBuf>> {
+        if !self.is_due(now) {
+            return Ok(None);
+        }
+        let saved = self.save_autosave(project)?;
+        self.last_save = Some(now);
+        Ok(saved)
+    }
+
+    /// Write an autosave now, shifting the older ones down
+    ///
+    /// Skipped, returning `None`, when the project has no unsaved changes or
+    /// has never been saved.
+    pub fn save_autosave(&mut self, project: &Project) -> std::io::Result<Option<PathBuf>> {
+        let Some(path) = project.path.as_deref().filter(|_| project.is_modified()) else {
+            return Ok(None);
+        };
+        let json = project.to_json(path)?;
+
+        // Stage as autosave 0 so a failed write leaves the others as they were
+        let staged = autosave_path(path, 0);
+        write_atomic(&staged, |file| file.write_all(json.as_bytes()))?;
+        let _ = std::fs::remove_file(autosave_path(path, self.keep));
+        for n in (1..self.keep).rev() {
+            let from = autosave_path(path, n);
+            if from.exists() {
+                std::fs::rename(&from, autosave_path(path, n + 1))?;
+            }
+        }
+        let newest = autosave_path(path, 1);
+        std::fs::rename(&staged, &newest)?;
+        Ok(Some(newest))
+    }
+}
+
+impl Project {
+    /// Newest autosave if it was written after the project file
+    pub fn has_newer_autosave(&self) -> Option<PathBuf> {
+        let path = self.path.as_deref()?;
+        let autosave = autosave_path(path, 1);
+        let autosaved = std::fs::metadata(&autosave)
+            .and_then(|m| m.modified())
+            .ok()?;
+        match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(saved) if saved >= autosaved => None,
+            _ => Some(autosave),
+        }
+    }
+
+    /// Replace the project by its newest autosave
+    ///
+    /// The project keeps its path, so the next save goes to the project
+    /// file, and is marked modified.
+    pub fn recover_from_autosave(&mut self) -> Result<(), ProjectError> {
+        let path = self.path.clone().ok_or(ProjectError::NoPath)?;
+        let mut recovered = Project::load(autosave_path(&path, 1))?;
+        recovered.path = Some(path);
+        recovered.mark_modified();
+        *self = recovered;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("koto-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn saved_name(path: &Path) -> String {
+        Project::load(path.to_path_buf()).unwrap().metadata.name
+    }
+
+    #[test]
+    fn test_autosaves_rotate_and_leave_project_file_alone() {
+        let dir = temp_dir("autosave");
+        let file = dir.join("song.kproj");
+        let mut project = Project::new("v0");
+        project.save_as(file.clone()).unwrap();
+
+        let mut autosave = AutosaveManager::new(Duration::from_secs(60), 2);
+        assert_eq!(autosave.save_autosave(&project).unwrap(), None);
+
+        for name in ["v1", "v2", "v3"] {
+            project.metadata.name = name.into();
+            project.mark_modified();
+            let written = autosave.save_autosave(&project).unwrap();
+            assert_eq!(written, Some(autosave_path(&file, 1)));
+        }
+        assert_eq!(saved_name(&autosave_path(&file, 1)), "v3");
+        assert_eq!(saved_name(&autosave_path(&file, 2)), "v2");
+        assert!(!autosave_path(&file, 3).exists());
+        assert!(!autosave_path(&file, 0).exists());
+        assert_eq!(saved_name(&file), "song");
+        assert!(project.is_modified());
+
+        let start = Instant::now();
+        assert!(autosave.tick(&project, start).unwrap().is_some());
+        assert_eq!(
+            autosave
+                .tick(&project, start + Duration::from_secs(30))
+                .unwrap(),
+            None
+        );
+        assert!(autosave.is_due(start + Duration::from_secs(60)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_write_keeps_previous_file() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("song.autosave-1.kproj");
+        std::fs::write(&path, "old").unwrap();
+
+        let result = write_atomic(&path, |file| {
+            file.write_all(b"half")?;
+            Err(std::io::Error::other("crash"))
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "old");
+        assert!(!dir.join("song.autosave-1.kproj.tmp").exists());
+
+        write_atomic(&path, |file| file.write_all(b"new")).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+        assert!(!dir.join("song.autosave-1.kproj.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_newer_autosave() {
+        let dir = temp_dir("recover");
+        let file = dir.join("song.kproj");
+        let mut project = Project::new("saved");
+        project.save_as(file.clone()).unwrap();
+        assert_eq!(project.has_newer_autosave(), None);
+
+        project.metadata.name = "unsaved".into();
+        project.mark_modified();
+        AutosaveManager::new(Duration::from_secs(60), 3)
+            .save_autosave(&project)
+            .unwrap();
+        // File times can be coarse; make the project file clearly older
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(10))
+            .unwrap();
+
+        let mut reopened = Project::load(file.clone()).unwrap();
+        assert_eq!(reopened.has_newer_autosave(), Some(autosave_path(&file, 1)));
+        reopened.recover_from_autosave().unwrap();
+        assert_eq!(reopened.metadata.name, "unsaved");
+        assert_eq!(reopened.path, Some(file.clone()));
+        assert!(reopened.is_modified());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+//! Collect and save: a self-contained copy of a project with its media
+
+use crate::{write_atomic, Project, ProjectError};
+use koto_timeline::{AudioSourceId, MEDIA_FOLDER};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const CHUNK: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollectOptions {
+    /// Leave out sources no region uses
+    pub exclude_unused: bool,
+}
+
+/// Progress passed to the callback of [`Project::collect_and_save`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollectProgress {
+    pub bytes_done: u64,
+    /// Size of every source found, duplicates included
+    pub bytes_total: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The file couldn't be found; the copy keeps pointing at its old path
+    Missing,
+    /// Same content as a file already copied for this source
+    Duplicate(AudioSourceId),
+    /// No region uses it and unused sources were excluded
+    Unused,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedMedia {
+    pub id: AudioSourceId,
+    pub path: PathBuf,
+    pub reason: SkipReason,
+}
+
+/// What [`Project::collect_and_save`] did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CollectReport {
+    pub project_file: PathBuf,
+    pub files_copied: usize,
+    pub bytes_copied: u64,
+    pub skipped: Vec<SkippedMedia>,
+}
+
+impl Project {
+    /// Save a self-contained copy of the project in `dest_dir`
+    ///
+    /// Every audio source is copied into the [`MEDIA_FOLDER`] under
+    /// `dest_dir`, files with identical content only once, and the copy's
+    /// sources point there. The project itself, its path and modified flag
+    /// are left alone.
+    ///
+    /// Copying can take a while: clone the project onto a worker thread to
+    /// run this. `progress` is called as data is copied; returning `false`
+    /// cancels with [`ProjectError::Cancelled`], before the project file is
+    /// written. Files copied by then are removed again.
+    pub fn collect_and_save(
+        &self,
+        dest_dir: &Path,
+        options: CollectOptions,
+        mut progress: impl FnMut(CollectProgress) -> bool,
+    ) -> Result<CollectReport, ProjectError> {
+        let media_dir = dest_dir.join(MEDIA_FOLDER);
+        std::fs::create_dir_all(&media_dir)?;
+        let mut copy = self.clone();
+        let mut report = CollectReport::default();
+
+        if options.exclude_unused {
+            for source in copy.timeline.remove_unused_sources() {
+                report.skipped.push(SkippedMedia {
+                    id: source.id,
+                    path: source.path,
+                    reason: SkipReason::Unused,
+                });
+            }
+        }
+
+        let sources: Vec<(AudioSourceId, PathBuf)> = copy
+            .timeline
+            .sources()
+            .map(|s| (s.id, s.path.clone()))
+            .collect();
+        let mut state = CollectProgress {
+            bytes_done: 0,
+            bytes_total: sources
+                .iter()
+                .filter_map(|(_, path)| std::fs::metadata(path).ok())
+                .map(|m| m.len())
+                .sum(),
+        };
+        // Copied files by (length, content hash)
+        let mut copied: HashMap<(u64, u64), Vec<(AudioSourceId, PathBuf)>> = HashMap::new();
+
+        for (id, path) in sources {
+            let Ok(length) = std::fs::metadata(&path).map(|m| m.len()) else {
+                report.skipped.push(SkippedMedia {
+                    id,
+                    path,
+                    reason: SkipReason::Missing,
+                });
+                continue;
+            };
+            let key = (length, hash_file(&path)?);
+            let mut duplicate = None;
+            for (original, dest) in copied.get(&key).into_iter().flatten() {
+                if same_content(&path, dest)? {
+                    duplicate = Some((*original, dest.clone()));
+                    break;
+                }
+            }
+
+            let dest = match duplicate {
+                Some((original, dest)) => {
+                    state.bytes_done += length;
+                    if !progress(state) {
+                        return Err(remove_copies(&copied));
+                    }
+                    report.skipped.push(SkippedMedia {
+                        id,
+                        path,
+                        reason: SkipReason::Duplicate(original),
+                    });
+                    dest
+                }
+                None => {
+                    let dest = unused_name(&media_dir, &path, &copied);
+                    copy_file(&path, &dest, &mut state, &mut progress).map_err(
+                        |err| match err {
+                            ProjectError::Cancelled => remove_copies(&copied),
+                            err => err,
+                        },
+                    )?;
+                    report.files_copied += 1;
+                    report.bytes_copied += length;
+                    copied.entry(key).or_default().push((id, dest.clone()));
+                    dest
+                }
+            };
+            copy.timeline.set_source_path(id, dest)?;
+        }
+
+        let name = match copy.metadata.name.trim() {
+            "" => "Untitled".to_string(),
+            name => name.to_string(),
+        };
+        report.project_file = dest_dir.join(format!("{name}.kproj"));
+        copy.metadata.stamp(SystemTime::now());
+        copy.write(&report.project_file)?;
+        Ok(report)
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buffer = vec![0; CHUNK];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+/// Byte-for-byte comparison, ruling out hash collisions
+fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; CHUNK], vec![0; CHUNK]);
+    loop {
+        let read = fill(&mut a, &mut chunk_a)?;
+        if read != fill(&mut b, &mut chunk_b)? || chunk_a[..read] != chunk_b[..read] {
+            return Ok(false);
+        }
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Read until `buffer` is full or the file ends; returns the bytes read
+fn fill(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// Delete the files copied before a cancel
+fn remove_copies(copied: &HashMap<(u64, u64), Vec<(AudioSourceId, PathBuf)>>) -> ProjectError {
+    for (_, dest) in copied.values().flatten() {
+        let _ = std::fs::remove_file(dest);
+    }
+    ProjectError::Cancelled
+}
+
+/// `media_dir` path for a copy of `source`, numbered if another file
+/// already took the name
+fn unused_name(
+    media_dir: &Path,
+    source: &Path,
+    copied: &HashMap<(u64, u64), Vec<(AudioSourceId, PathBuf)>>,
+) -> PathBuf {
+    let taken = |path: &Path| {
+        copied
+            .values()
+            .flatten()
+            .any(|(_, dest)| dest.as_path() == path)
+    };
+    let name = source.file_name().unwrap_or_default();
+    let dest = media_dir.join(name);
+    if !taken(&dest) {
+        return dest;
+    }
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = source
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| media_dir.join(format!("{stem}-{n}{extension}")))
+        .find(|dest| !taken(dest))
+        .unwrap()
+}
+
+fn copy_file(
+    from: &Path,
+    to: &Path,
+    state: &mut CollectProgress,
+    progress: &mut impl FnMut(CollectProgress) -> bool,
+) -> Result<(), ProjectError> {
+    let mut source = File::open(from)?;
+    let mut cancelled = false;
+    let mut buffer = vec![0; CHUNK];
+    let result = write_atomic(to, |dest| loop {
+        let read = source.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        dest.write_all(&buffer[..read])?;
+        state.bytes_done += read as u64;
+        if !progress(*state) {
+            cancelled = true;
+            return Err(std::io::Error::other("cancelled"));
+        }
+    });
+    match result {
+        Err(_) if cancelled => Err(ProjectError::Cancelled),
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{ChannelCount, SamplePosition, SampleRate};
+    use koto_timeline::{Region, RegionContent, TrackType};
+
+    fn add_source(project: &mut Project, path: PathBuf) -> AudioSourceId {
+        let id = project.timeline.add_source(
+            path,
+            SampleRate(48000),
+            ChannelCount(2),
+            SamplePosition(1000),
+        );
+        let track = project.timeline.tracks[0].id;
+        let region_id = project.timeline.new_region_id();
+        let mut region = Region::new(region_id, track, SamplePosition(0), SamplePosition(10));
+        region.content = RegionContent::Audio { source: id };
+        project.timeline.add_region(region).unwrap();
+        id
+    }
+
+    /// Sources: two identical takes in different folders, a different file
+    /// with a clashing name, a missing file and an unused one
+    fn setup(name: &str) -> (PathBuf, Project, Vec<AudioSourceId>) {
+        let dir = std::env::temp_dir().join(format!("koto-collect-{name}-{}", std::process::id()));
+        for folder in ["a", "b", "c"] {
+            std::fs::create_dir_all(dir.join("src").join(folder)).unwrap();
+        }
+        std::fs::write(dir.join("src/a/vox.wav"), b"take one").unwrap();
+        std::fs::write(dir.join("src/b/vox-copy.wav"), b"take one").unwrap();
+        std::fs::write(dir.join("src/c/vox.wav"), b"something else").unwrap();
+        std::fs::write(dir.join("src/unused.wav"), b"unused").unwrap();
+
+        let mut project = Project::new("Song");
+        project.timeline.add_track("Vox", TrackType::Audio);
+        let mut ids = Vec::new();
+        for path in [
+            "src/a/vox.wav",
+            "src/b/vox-copy.wav",
+            "src/c/vox.wav",
+            "src/gone.wav",
+        ] {
+            ids.push(add_source(&mut project, dir.join(path)));
+        }
+        ids.push(project.timeline.add_source(
+            dir.join("src/unused.wav"),
+            SampleRate(48000),
+            ChannelCount(2),
+            SamplePosition(1000),
+        ));
+        (dir, project, ids)
+    }
+
+    #[test]
+    fn test_collect_copies_and_deduplicates_media() {
+        let (dir, project, ids) = setup("copy");
+        let dest = dir.join("archive");
+        let mut calls = 0;
+        let report = project
+            .collect_and_save(
+                &dest,
+                CollectOptions {
+                    exclude_unused: true,
+                },
+                |_| {
+                    calls += 1;
+                    true
+                },
+            )
+            .unwrap();
+
+        assert_eq!(report.project_file, dest.join("Song.kproj"));
+        assert_eq!(report.files_copied, 2);
+        assert_eq!(report.bytes_copied, 8 + 14);
+        let reasons: Vec<_> = report.skipped.iter().map(|s| (s.id, s.reason)).collect();
+        assert_eq!(
+            reasons,
+            [
+                (ids[4], SkipReason::Unused),
+                (ids[1], SkipReason::Duplicate(ids[0])),
+                (ids[3], SkipReason::Missing),
+            ]
+        );
+        assert!(calls >= 3);
+        assert!(dest.join("Media/vox.wav").exists());
+        assert!(dest.join("Media/vox-2.wav").exists());
+        assert!(!dest.join("Media/unused.wav").exists());
+
+        let json = std::fs::read_to_string(&report.project_file).unwrap();
+        assert!(json.contains("\"Media/vox-2.wav\""));
+        let loaded = Project::load(report.project_file).unwrap();
+        let path = |id| loaded.timeline.get_source(id).unwrap().path.clone();
+        assert_eq!(path(ids[0]), dest.join("Media/vox.wav"));
+        assert_eq!(path(ids[1]), dest.join("Media/vox.wav"));
+        assert_eq!(std::fs::read(path(ids[2])).unwrap(), b"something else");
+        assert_eq!(loaded.missing_media.len(), 1);
+        // The original is untouched
+        assert_eq!(
+            project.timeline.get_source(ids[0]).unwrap().path,
+            dir.join("src/a/vox.wav")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_collect_can_be_cancelled_from_a_worker() {
+        let (dir, project, _) = setup("cancel");
+        let dest = dir.join("archive");
+        let worker = {
+            let (project, dest) = (project.clone(), dest.clone());
+            // Cancel at the duplicate, after the first file was copied
+            let mut calls = 0;
+            std::thread::spawn(move || {
+                project.collect_and_save(&dest, CollectOptions::default(), |_| {
+                    calls += 1;
+                    calls < 2
+                })
+            })
+        };
+        let result = worker.join().unwrap();
+        assert!(matches!(result, Err(ProjectError::Cancelled)));
+        assert!(!dest.join("Song.kproj").exists());
+        assert_eq!(
+            std::fs::read_dir(dest.join(MEDIA_FOLDER)).unwrap().count(),
+            0
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
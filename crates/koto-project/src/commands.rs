@@ -0,0 +1,179 @@
+//! Undoable project edits
+
+use crate::Project;
+use koto_core::{Marker, TrackId};
+use koto_timeline::{RippleChanges, RippleEdit};
+use koto_undo::{CommandRecord, SerializableCommand, UndoCommand};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// A ripple delete or insert, optionally moving markers too
+pub struct RippleCommand {
+    project: Arc<Mutex<Project>>,
+    state: RippleState,
+}
+
+/// Everything a [`RippleCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct RippleState {
+    edit: RippleEdit,
+    tracks: Option<Vec<TrackId>>,
+    ripple_markers: bool,
+    /// What the edit changed on the timeline, once it has run
+    changes: Option<RippleChanges>,
+    /// Markers the edit moved or removed, as they were before
+    markers: Vec<Marker>,
+}
+
+impl RippleCommand {
+    /// `tracks` of `None` ripples every track
+    pub fn new(
+        project: Arc<Mutex<Project>>,
+        edit: RippleEdit,
+        tracks: Option<Vec<TrackId>>,
+        ripple_markers: bool,
+    ) -> Self {
+        Self {
+            project,
+            state: RippleState {
+                edit,
+                tracks,
+                ripple_markers,
+                changes: None,
+                markers: Vec::new(),
+            },
+        }
+    }
+}
+
+impl UndoCommand for RippleCommand {
+    fn execute(&mut self) {
+        let state = &mut self.state;
+        let mut project = self.project.lock();
+        // Redo puts back exactly what the edit made, new region IDs included
+        if let Some(changes) = &state.changes {
+            project.timeline.redo_ripple(changes);
+        } else {
+            match project
+                .timeline
+                .ripple_with_changes(state.edit, state.tracks.as_deref())
+            {
+                Ok(changes) => state.changes = Some(changes),
+                Err(_) => return,
+            }
+        }
+        if state.ripple_markers {
+            state.markers = project.ripple_markers(state.edit);
+        }
+    }
+
+    fn undo(&mut self) {
+        let Some(changes) = &self.state.changes else {
+            return;
+        };
+        let mut project = self.project.lock();
+        project.timeline.undo_ripple(changes);
+        for marker in &self.state.markers {
+            project.markers.restore(marker.clone());
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self.state.edit {
+            RippleEdit::Delete(_) => "Ripple Delete",
+            RippleEdit::Insert { .. } => "Ripple Insert",
+        }
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Project>>> for RippleCommand {
+    const KIND: &'static str = "ripple";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        project: &Arc<Mutex<Project>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            project: project.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{SamplePosition, SampleRange};
+    use koto_timeline::{Region, TrackType};
+    use koto_undo::UndoHistory;
+
+    #[test]
+    fn test_ripple_moves_markers_and_undoes() {
+        let mut project = Project::new("Test");
+        let track = project.timeline.add_track("Audio 1", TrackType::Audio);
+        let id = project.timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(3000), SamplePosition(1000));
+        project.timeline.add_region(region).unwrap();
+        let inside = project.markers.add(SamplePosition(1500), "Gone", 0);
+        let after = project.markers.add(SamplePosition(2500), "Verse", 0);
+        let project = Arc::new(Mutex::new(project));
+
+        let edit = RippleEdit::Delete(SampleRange::new(SamplePosition(1000), SamplePosition(2000)));
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(RippleCommand::new(
+            project.clone(),
+            edit,
+            None,
+            true,
+        )));
+        {
+            let project = project.lock();
+            assert_eq!(
+                project.timeline.get_region(id).unwrap().start,
+                SamplePosition(2000)
+            );
+            assert!(project.markers.get(inside).is_none());
+            assert_eq!(
+                project.markers.get(after).unwrap().position,
+                SamplePosition(1500)
+            );
+        }
+
+        history.undo();
+        let project = project.lock();
+        assert_eq!(
+            project.timeline.get_region(id).unwrap().start,
+            SamplePosition(3000)
+        );
+        assert_eq!(project.markers.len(), 2);
+    }
+
+    #[test]
+    fn test_ripple_leaves_markers_without_flag() {
+        let mut project = Project::new("Test");
+        let marker = project.markers.add(SamplePosition(5000), "Chorus", 0);
+        let edit = RippleEdit::Insert {
+            position: SamplePosition(0),
+            length: SamplePosition(100),
+        };
+        project.ripple(edit, None, false).unwrap();
+        assert_eq!(
+            project.markers.get(marker).unwrap().position,
+            SamplePosition(5000)
+        );
+        project.ripple(edit, None, true).unwrap();
+        assert_eq!(
+            project.markers.get(marker).unwrap().position,
+            SamplePosition(5100)
+        );
+    }
+}
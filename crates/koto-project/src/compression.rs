@@ -0,0 +1,127 @@
+//! Optional gzip compression of project files
+
+use crate::{Project, ProjectError};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use std::io::Read;
+use std::path::Path;
+
+/// Extension of compressed project files
+pub const COMPRESSED_EXTENSION: &str = "kprojz";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// How a project file is written
+///
+/// Pretty JSON is the default since it diffs well; gzip-compressed compact
+/// JSON suits large projects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+}
+
+impl Compression {
+    /// Compression for a file at `path`: gzip for `.kprojz` files
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension == COMPRESSED_EXTENSION => Compression::Gzip,
+            _ => Compression::None,
+        }
+    }
+
+    pub(crate) fn encode(self, project: &Project) -> Result<Vec<u8>, ProjectError> {
+        match self {
+            Compression::None => Ok(serde_json::to_vec_pretty(project)?),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                serde_json::to_writer(&mut encoder, project)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+/// Project JSON from the contents of a file in either format, whatever its
+/// extension
+pub(crate) fn decode(bytes: Vec<u8>) -> Result<Vec<u8>, ProjectError> {
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+    let mut json = Vec::new();
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+    Ok(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::SamplePosition;
+    use koto_timeline::{Region, TrackType};
+
+    fn large_project() -> Project {
+        let mut project = Project::new("Large");
+        for t in 0..10 {
+            let track = project
+                .timeline
+                .add_track(format!("Track {t}"), TrackType::Audio);
+            for r in 0..500 {
+                let id = project.timeline.new_region_id();
+                let region =
+                    Region::new(id, track, SamplePosition(r * 48000), SamplePosition(24000));
+                project.timeline.add_region(region).unwrap();
+            }
+        }
+        project
+    }
+
+    #[test]
+    fn test_compressed_round_trip_and_size() {
+        let dir = std::env::temp_dir().join(format!("koto-gzip-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let project = large_project();
+        let plain = dir.join("large.kproj");
+        let compressed = dir.join(format!("large.{COMPRESSED_EXTENSION}"));
+        project.save_copy(&plain).unwrap();
+        project.save_copy(&compressed).unwrap();
+
+        let plain_size = std::fs::metadata(&plain).unwrap().len();
+        let compressed_size = std::fs::metadata(&compressed).unwrap().len();
+        assert!(
+            compressed_size * 20 < plain_size,
+            "{compressed_size} bytes compressed vs {plain_size}"
+        );
+        assert!(std::fs::read(&compressed).unwrap().starts_with(&GZIP_MAGIC));
+
+        // Loading sniffs the content, not the extension
+        let renamed = dir.join("renamed.kproj");
+        std::fs::rename(&compressed, &renamed).unwrap();
+        for path in [plain, renamed] {
+            let loaded = Project::load(path).unwrap();
+            assert_eq!(loaded.timeline.tracks.len(), 10);
+            assert_eq!(loaded.timeline.stats().region_count, 5000);
+            assert_eq!(
+                loaded.timeline.tracks[3].regions,
+                project.timeline.tracks[3].regions
+            );
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compression_for_path() {
+        assert_eq!(
+            Compression::for_path(Path::new("a/song.kprojz")),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::for_path(Path::new("a/song.kproj")),
+            Compression::None
+        );
+        assert_eq!(
+            Compression::for_path(Path::new("a/song")),
+            Compression::None
+        );
+    }
+}
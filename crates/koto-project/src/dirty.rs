@@ -0,0 +1,135 @@
+//! Unsaved-changes tracking
+//!
+//! Changes made through the [`UndoHistory`] are tracked by its saved
+//! marker, so undoing back to the saved state clears the modified flag.
+//! Everything else bumps the project's revision, which only a save catches
+//! up with.
+
+use crate::{Project, ProjectMetadata};
+use koto_undo::UndoHistory;
+
+impl Project {
+    /// Whether the project has changes since it was last saved or loaded,
+    /// shown as the `*` in the title bar
+    ///
+    /// Timeline edits count before their events are taken.
+    pub fn is_modified(&self) -> bool {
+        self.revision != self.saved_revision || self.history_modified || self.timeline.has_events()
+    }
+
+    /// Record a change made outside the undo history
+    pub fn mark_modified(&mut self) {
+        self.revision += 1;
+    }
+
+    /// Record that undo history commands ran: call after every
+    /// [`UndoHistory::execute`], [`undo`](UndoHistory::undo) and
+    /// [`redo`](UndoHistory::redo)
+    ///
+    /// Timeline events the commands caused don't count as changes of their
+    /// own; they are still returned by the next
+    /// [`take_timeline_events`](Self::take_timeline_events).
+    pub fn sync_undo(&mut self, history: &UndoHistory) {
+        let events = self.timeline.take_events();
+        self.history_events.extend(events);
+        self.history_modified = !history.is_at_saved();
+    }
+
+    /// Replace the name, author and notes
+    pub fn set_metadata(&mut self, metadata: ProjectMetadata) {
+        self.metadata = metadata;
+        self.mark_modified();
+    }
+
+    /// Edits queued before the save are saved: their events are kept for
+    /// the next [`take_timeline_events`](Self::take_timeline_events)
+    /// without marking the project
+    pub(crate) fn mark_saved(&mut self) {
+        let events = self.timeline.take_events();
+        self.history_events.extend(events);
+        self.saved_revision = self.revision;
+        self.history_modified = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RippleCommand;
+    use koto_core::SamplePosition;
+    use koto_timeline::{Region, RippleEdit, TimelineEvent, TrackType};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    fn ripple(project: &Arc<Mutex<Project>>, history: &mut UndoHistory) {
+        let edit = RippleEdit::Insert {
+            position: SamplePosition(0),
+            length: SamplePosition(100),
+        };
+        history.execute(Box::new(RippleCommand::new(
+            project.clone(),
+            edit,
+            None,
+            true,
+        )));
+        project.lock().sync_undo(history);
+    }
+
+    #[test]
+    fn test_undo_back_to_saved_clears_modified() {
+        let mut project = Project::new("Dirty");
+        let track = project.timeline.add_track("Audio 1", TrackType::Audio);
+        let id = project.timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(500), SamplePosition(100));
+        project.timeline.add_region(region).unwrap();
+        project.take_timeline_events();
+        project.mark_saved();
+        let project = Arc::new(Mutex::new(project));
+        let mut history = UndoHistory::default();
+
+        ripple(&project, &mut history);
+        assert!(project.lock().is_modified());
+        history.undo();
+        project.lock().sync_undo(&history);
+        assert!(!project.lock().is_modified());
+        // Undo's own events are passed on without marking the project
+        assert!(!project.lock().take_timeline_events().is_empty());
+        assert!(!project.lock().is_modified());
+
+        // A change outside the history stays until saved
+        project.lock().set_tempo(koto_core::Tempo::new(100.0));
+        ripple(&project, &mut history);
+        history.undo();
+        project.lock().sync_undo(&history);
+        assert!(project.lock().is_modified());
+    }
+
+    #[test]
+    fn test_undo_past_save_marks_modified() {
+        let dir = std::env::temp_dir().join(format!("koto-dirty-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Dirty");
+        let track = project.timeline.add_track("Audio 1", TrackType::Audio);
+        project.save_as(dir.join("dirty.kproj")).unwrap();
+        assert_eq!(
+            project.take_timeline_events(),
+            vec![TimelineEvent::TrackAdded(track)]
+        );
+        assert!(!project.is_modified());
+        let project = Arc::new(Mutex::new(project));
+        let mut history = UndoHistory::default();
+
+        ripple(&project, &mut history);
+        project.lock().save().unwrap();
+        history.mark_saved();
+        assert!(!project.lock().is_modified());
+        history.undo();
+        project.lock().sync_undo(&history);
+        assert!(project.lock().is_modified());
+        history.redo();
+        project.lock().sync_undo(&history);
+        assert!(!project.lock().is_modified());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+//! Editing time and the summary shown in the project info dialog
+
+use crate::{parse_rfc3339, Project};
+use koto_core::{SamplePosition, SampleRate, Tempo, TimeSignature, TICKS_PER_QUARTER_NOTE};
+use koto_timeline::TimelineStats;
+use std::time::{Instant, SystemTime};
+
+/// Summary of a project for an "about this project" dialog
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectInfo {
+    pub name: String,
+    /// `None` if unknown, e.g. for projects from older versions
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+    /// Editing time including the current session
+    pub total_edit_seconds: u64,
+    pub timeline: TimelineStats,
+    /// End of the last region
+    pub length: SamplePosition,
+    /// `length` in bars at the project tempo and time signature
+    pub length_bars: f64,
+    pub sample_rate: SampleRate,
+    pub tempo: Tempo,
+    pub time_signature: TimeSignature,
+    /// Size of the project file, if it has been saved
+    pub file_size: Option<u64>,
+}
+
+impl Project {
+    /// Start counting editing time; the app calls this when the project is
+    /// opened or its window gains focus
+    pub fn start_editing_session(&mut self, now: Instant) {
+        self.count_editing_time(now);
+        self.editing_since = Some(now);
+    }
+
+    /// Stop counting editing time; the app calls this when the project is
+    /// closed or its window loses focus
+    ///
+    /// The time is kept in the metadata with the next save. It doesn't
+    /// mark the project modified.
+    pub fn end_editing_session(&mut self, now: Instant) {
+        self.count_editing_time(now);
+        self.editing_since = None;
+    }
+
+    /// Add the current session's time so far to the metadata
+    pub(crate) fn count_editing_time(&mut self, now: Instant) {
+        if let Some(since) = self.editing_since.as_mut() {
+            let elapsed = now.saturating_duration_since(*since);
+            // Carry the sub-second remainder over to the next count
+            let seconds = elapsed.as_secs();
+            self.metadata.total_edit_seconds += seconds;
+            *since += std::time::Duration::from_secs(seconds);
+        }
+    }
+
+    /// Summary for the project info dialog, as of `now`
+    pub fn info(&self, now: Instant) -> ProjectInfo {
+        let session_seconds = self
+            .editing_since
+            .map_or(0, |since| now.saturating_duration_since(since).as_secs());
+        let length = self.timeline.length();
+        let converter = self.time_converter();
+        let ticks_per_bar =
+            i64::from(self.time_signature.beats_per_bar()) * i64::from(TICKS_PER_QUARTER_NOTE);
+        ProjectInfo {
+            name: self.metadata.name.clone(),
+            created: parse_rfc3339(&self.metadata.created),
+            modified: parse_rfc3339(&self.metadata.modified),
+            total_edit_seconds: self.metadata.total_edit_seconds + session_seconds,
+            timeline: self.timeline.stats(),
+            length,
+            length_bars: converter.samples_to_ticks(length) as f64 / ticks_per_bar as f64,
+            sample_rate: self.sample_rate,
+            tempo: self.tempo,
+            time_signature: self.time_signature,
+            file_size: self
+                .path
+                .as_deref()
+                .and_then(|path| std::fs::metadata(path).ok())
+                .map(|m| m.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_timeline::{Region, TrackType};
+    use std::time::Duration;
+
+    #[test]
+    fn test_info_and_editing_time() {
+        let dir = std::env::temp_dir().join(format!("koto-info-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Info");
+        assert!(parse_rfc3339(&project.metadata.created).is_some());
+        let track = project.timeline.add_track("Vox", TrackType::Audio);
+        project.timeline.add_track("Keys", TrackType::Midi);
+        // Two bars of 4/4 at 120 BPM
+        let length = project.time_converter().seconds_to_samples(4.0);
+        let id = project.timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition::ZERO, length);
+        project.timeline.add_region(region).unwrap();
+        project.mark_saved();
+
+        let start = Instant::now();
+        project.start_editing_session(start);
+        let info = project.info(start + Duration::from_millis(90_500));
+        assert_eq!(info.total_edit_seconds, 90);
+        assert_eq!(info.timeline.region_count, 1);
+        assert_eq!(info.timeline.tracks_by_type[&TrackType::Midi], 1);
+        assert_eq!(info.length_bars, 2.0);
+        assert_eq!(info.file_size, None);
+
+        project.end_editing_session(start + Duration::from_millis(90_500));
+        project.start_editing_session(start + Duration::from_secs(200));
+        project.end_editing_session(start + Duration::from_millis(209_600));
+        assert_eq!(project.metadata.total_edit_seconds, 99);
+        assert!(!project.is_modified());
+
+        let file = dir.join("info.kproj");
+        project.save_as(file.clone()).unwrap();
+        let loaded = Project::load(file.clone()).unwrap();
+        let info = loaded.info(Instant::now());
+        assert_eq!(info.total_edit_seconds, 99);
+        assert_eq!(
+            info.file_size,
+            Some(std::fs::metadata(&file).unwrap().len())
+        );
+        assert!(info.modified >= info.created);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_legacy_metadata_loads() {
+        let fixture =
+            std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/project-v0.json");
+        let loaded = Project::load(fixture).unwrap();
+        assert_eq!(loaded.metadata.total_edit_seconds, 0);
+        let info = loaded.info(Instant::now());
+        assert_eq!((info.created, info.modified), (None, None));
+    }
+}
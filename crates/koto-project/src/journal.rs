@@ -0,0 +1,510 @@
+//! Journal of edits since the last save, for recovering from a crash
+//! between autosaves
+
+use crate::{Project, ProjectError};
+use koto_core::{SamplePosition, Tempo, TimeSignature, TrackId};
+use koto_timeline::{Region, RegionEdge, RegionId, TimelineError, TrackType};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+
+/// Path of the journal of the project at `project_path`: `song.kproj`
+/// journals to `song.kproj.journal`, so `song.kprojz` doesn't share it
+pub fn journal_path(project_path: &Path) -> PathBuf {
+    let mut path = project_path.as_os_str().to_owned();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+/// An edit as written to the journal, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    AddTrack {
+        name: String,
+        track_type: TrackType,
+    },
+    RemoveTrack {
+        track: TrackId,
+    },
+    RenameTrack {
+        track: TrackId,
+        name: String,
+    },
+    AddRegion {
+        region: Region,
+    },
+    RemoveRegion {
+        region: RegionId,
+    },
+    MoveRegion {
+        region: RegionId,
+        track: TrackId,
+        start: SamplePosition,
+    },
+    TrimRegion {
+        region: RegionId,
+        edge: RegionEdge,
+        position: SamplePosition,
+    },
+    SplitRegion {
+        region: RegionId,
+        position: SamplePosition,
+    },
+    SetTempo {
+        tempo: Tempo,
+    },
+    SetTimeSignature {
+        time_signature: TimeSignature,
+    },
+}
+
+/// Appends edits to the project's journal as they happen
+///
+/// A handle to a writer thread: [`record`](Self::record) only queues the
+/// edit, and the thread appends and syncs the queued edits every
+/// `flush_interval`, so no caller waits on the disk. Dropping every handle
+/// writes what's queued and stops the thread. The project's journal, from
+/// [`Project::start_journal`], is truncated by every successful save.
+#[derive(Debug, Clone)]
+pub struct CommandJournal {
+    path: PathBuf,
+    writer: Sender<JournalMessage>,
+}
+
+#[derive(Debug)]
+enum JournalMessage {
+    Record(Vec<u8>),
+    Flush(Sender<std::io::Result<()>>),
+    /// Drop every edit and journal to the path from now on
+    Truncate(PathBuf, Sender<std::io::Result<()>>),
+}
+
+impl CommandJournal {
+    /// Start the writer thread for the journal of the project at
+    /// `project_path`
+    pub fn new(project_path: &Path, flush_interval: Duration) -> Self {
+        let path = journal_path(project_path);
+        let (writer, messages) = mpsc::channel();
+        let journal = JournalWriter::new(path.clone(), flush_interval);
+        std::thread::spawn(move || journal.run(messages));
+        Self { path, writer }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue an edit for the writer thread's next flush
+    pub fn record(&self, entry: &JournalEntry) -> Result<(), ProjectError> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.send(JournalMessage::Record(line))?;
+        Ok(())
+    }
+
+    /// Write the queued edits now, without waiting for the interval, and
+    /// wait until they're synced
+    pub fn flush(&self) -> std::io::Result<()> {
+        let (done, result) = mpsc::channel();
+        self.send(JournalMessage::Flush(done))?;
+        result.recv().map_err(|_| writer_stopped())?
+    }
+
+    /// Drop every edit, written or not, once the project has been saved
+    pub fn truncate(&mut self) -> std::io::Result<()> {
+        self.truncate_to(self.path.clone())
+    }
+
+    /// [`truncate`](Self::truncate), then journal to `path`
+    fn truncate_to(&mut self, path: PathBuf) -> std::io::Result<()> {
+        let (done, result) = mpsc::channel();
+        self.send(JournalMessage::Truncate(path.clone(), done))?;
+        result.recv().map_err(|_| writer_stopped())??;
+        self.path = path;
+        Ok(())
+    }
+
+    fn send(&self, message: JournalMessage) -> std::io::Result<()> {
+        self.writer.send(message).map_err(|_| writer_stopped())
+    }
+}
+
+fn writer_stopped() -> std::io::Error {
+    std::io::Error::other("journal writer thread stopped")
+}
+
+/// The writer thread's end of a [`CommandJournal`]
+#[derive(Debug)]
+struct JournalWriter {
+    flush_interval: Duration,
+    path: PathBuf,
+    buffer: Vec<u8>,
+    last_flush: Option<Instant>,
+}
+
+impl JournalWriter {
+    fn new(path: PathBuf, flush_interval: Duration) -> Self {
+        Self {
+            flush_interval,
+            path,
+            buffer: Vec::new(),
+            last_flush: None,
+        }
+    }
+
+    /// Handle messages until every [`CommandJournal`] is dropped, flushing
+    /// whenever edits are queued and due
+    fn run(mut self, messages: Receiver<JournalMessage>) {
+        loop {
+            let message = if self.has_pending() {
+                messages.recv_timeout(self.until_due(Instant::now()))
+            } else {
+                messages.recv().map_err(|_| RecvTimeoutError::Disconnected)
+            };
+            match message {
+                Ok(JournalMessage::Record(line)) => self.queue(&line),
+                Ok(JournalMessage::Flush(done)) => {
+                    let _ = done.send(self.flush());
+                }
+                Ok(JournalMessage::Truncate(path, done)) => {
+                    let _ = done.send(self.truncate(path));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    if let Err(err) = self.flush() {
+                        tracing::warn!("Failed to write journal {}: {err}", self.path.display());
+                    }
+                    return;
+                }
+            }
+            // Edits that fail to write stay queued for the next flush
+            if let Err(err) = self.tick(Instant::now()) {
+                tracing::warn!("Failed to write journal {}: {err}", self.path.display());
+            }
+        }
+    }
+
+    fn queue(&mut self, line: &[u8]) {
+        self.buffer.extend_from_slice(line);
+    }
+
+    /// Whether edits are waiting to be written
+    fn has_pending(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+
+    /// How long until `flush_interval` has passed since the last flush
+    fn until_due(&self, now: Instant) -> Duration {
+        self.last_flush.map_or(Duration::ZERO, |last| {
+            self.flush_interval.saturating_sub(now.duration_since(last))
+        })
+    }
+
+    /// Flush if due and there is anything to write; returns whether it did
+    fn tick(&mut self, now: Instant) -> std::io::Result<bool> {
+        if !self.has_pending() || !self.until_due(now).is_zero() {
+            return Ok(false);
+        }
+        self.flush()?;
+        self.last_flush = Some(now);
+        Ok(true)
+    }
+
+    /// Append the queued edits to the journal file and sync it
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&self.buffer)?;
+        file.sync_data()?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Drop every edit, written or not, and journal to `path` from now on
+    fn truncate(&mut self, path: PathBuf) -> std::io::Result<()> {
+        self.buffer.clear();
+        remove_if_present(&self.path)?;
+        self.path = path;
+        Ok(())
+    }
+}
+
+fn remove_if_present(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+impl Project {
+    /// Start journalling to the journal next to the project file, replacing
+    /// any journal already started
+    ///
+    /// Record edits through [`journal_mut`](Self::journal_mut).
+    pub fn start_journal(
+        &mut self,
+        flush_interval: Duration,
+    ) -> Result<&mut CommandJournal, ProjectError> {
+        let path = self.path.as_deref().ok_or(ProjectError::NoPath)?;
+        Ok(self
+            .journal
+            .insert(CommandJournal::new(path, flush_interval)))
+    }
+
+    pub fn journal_mut(&mut self) -> Option<&mut CommandJournal> {
+        self.journal.as_mut()
+    }
+
+    /// Drop the journalled edits once the project is saved to `path`,
+    /// moving the journal along with the project file
+    ///
+    /// A journal left at `path` by an earlier session goes too, so later
+    /// edits don't append to edits already in the file.
+    pub(crate) fn truncate_journal(&mut self, path: &Path) -> std::io::Result<()> {
+        let journal = journal_path(path);
+        if let Some(current) = &mut self.journal {
+            current.truncate_to(journal.clone())?;
+        }
+        remove_if_present(&journal)
+    }
+
+    /// The project's journal if it has edits newer than the project file,
+    /// left behind by a crash
+    pub fn has_newer_journal(&self) -> Option<PathBuf> {
+        let path = self.path.as_deref()?;
+        let journal = journal_path(path);
+        let written = std::fs::metadata(&journal)
+            .ok()
+            .filter(|m| m.len() > 0)?
+            .modified()
+            .ok()?;
+        match std::fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(saved) if saved > written => None,
+            _ => Some(journal),
+        }
+    }
+
+    /// Apply the edits in the project's journal; returns how many were
+    /// applied
+    ///
+    /// Replay stops at the first line that can't be read, normally one cut
+    /// short by the crash. The project is marked modified if anything was
+    /// applied.
+    pub fn replay_journal(&mut self) -> Result<usize, ProjectError> {
+        let path = self.path.as_deref().ok_or(ProjectError::NoPath)?;
+        let reader = BufReader::new(File::open(journal_path(path))?);
+        let mut applied = 0;
+        for line in reader.lines() {
+            let Ok(entry) = serde_json::from_str::<JournalEntry>(&line?) else {
+                break;
+            };
+            self.apply_journal_entry(&entry)?;
+            applied += 1;
+        }
+        if applied > 0 {
+            self.mark_modified();
+        }
+        Ok(applied)
+    }
+
+    /// Make one journalled edit
+    pub fn apply_journal_entry(&mut self, entry: &JournalEntry) -> Result<(), ProjectError> {
+        let timeline = &mut self.timeline;
+        match entry {
+            JournalEntry::AddTrack { name, track_type } => {
+                timeline.add_track(name.clone(), *track_type);
+            }
+            JournalEntry::RemoveTrack { track } => {
+                timeline
+                    .remove_track(*track)
+                    .ok_or(TimelineError::TrackNotFound(*track))?;
+            }
+            JournalEntry::RenameTrack { track, name } => {
+                timeline.rename_track(*track, name)?;
+            }
+            JournalEntry::AddRegion { region } => {
+                timeline.reserve_region_id(region.id);
+                timeline.add_region(region.clone())?;
+            }
+            JournalEntry::RemoveRegion { region } => {
+                timeline.remove_region(*region)?;
+            }
+            JournalEntry::MoveRegion {
+                region,
+                track,
+                start,
+            } => {
+                timeline.move_region(*region, *track, *start)?;
+            }
+            JournalEntry::TrimRegion {
+                region,
+                edge,
+                position,
+            } => {
+                timeline.trim_region(*region, *edge, *position)?;
+            }
+            JournalEntry::SplitRegion { region, position } => {
+                timeline.split_region(*region, *position)?;
+            }
+            JournalEntry::SetTempo { tempo } => self.set_tempo(*tempo),
+            JournalEntry::SetTimeSignature { time_signature } => {
+                self.set_time_signature(*time_signature)
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edits(project: &mut Project) -> Vec<JournalEntry> {
+        let mut entries = vec![JournalEntry::AddTrack {
+            name: "Vox".into(),
+            track_type: TrackType::Audio,
+        }];
+        project.apply_journal_entry(&entries[0]).unwrap();
+        let track = project.timeline.tracks.last().unwrap().id;
+        let id = project.timeline.new_region_id();
+        entries.extend([
+            JournalEntry::AddRegion {
+                region: Region::new(id, track, SamplePosition(1000), SamplePosition(4000)),
+            },
+            JournalEntry::SplitRegion {
+                region: id,
+                position: SamplePosition(3000),
+            },
+            JournalEntry::TrimRegion {
+                region: id,
+                edge: RegionEdge::Start,
+                position: SamplePosition(1500),
+            },
+            JournalEntry::RenameTrack {
+                track,
+                name: "Lead Vox".into(),
+            },
+            JournalEntry::SetTempo {
+                tempo: Tempo::new(96.0),
+            },
+        ]);
+        for entry in &entries[1..] {
+            project.apply_journal_entry(entry).unwrap();
+        }
+        entries
+    }
+
+    #[test]
+    fn test_replay_matches_direct_edits() {
+        let dir = std::env::temp_dir().join(format!("koto-journal-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        let mut base = Project::new("Song");
+        base.timeline.add_track("Keys", TrackType::Midi);
+        base.save_as(file.clone()).unwrap();
+        assert_eq!(base.has_newer_journal(), None);
+
+        let mut edited = base.clone();
+        let journal = CommandJournal::new(&file, Duration::from_secs(60));
+        for entry in edits(&mut edited) {
+            journal.record(&entry).unwrap();
+        }
+        journal.flush().unwrap();
+        // A line cut short by the crash is ignored
+        let mut file_handle = OpenOptions::new()
+            .append(true)
+            .open(journal.path())
+            .unwrap();
+        file_handle.write_all(b"{\"op\":\"remove_tr").unwrap();
+
+        let mut recovered = Project::load(file.clone()).unwrap();
+        assert_eq!(recovered.has_newer_journal(), Some(journal_path(&file)));
+        assert_eq!(recovered.replay_journal().unwrap(), 6);
+        assert!(recovered.is_modified());
+        assert_eq!(
+            serde_json::to_value(&recovered.timeline).unwrap(),
+            serde_json::to_value(&edited.timeline).unwrap()
+        );
+        assert_eq!(recovered.tempo, edited.tempo);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_flush_waits_for_interval_and_truncate_clears() {
+        let dir = std::env::temp_dir().join(format!("koto-journal-tick-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        assert_eq!(journal_path(&file), dir.join("song.kproj.journal"));
+        let mut writer = JournalWriter::new(journal_path(&file), Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(!writer.tick(start).unwrap());
+
+        let line = b"{\"op\":\"set_tempo\",\"tempo\":100.0}\n";
+        writer.queue(line);
+        assert!(writer.tick(start).unwrap());
+        writer.queue(line);
+        assert!(!writer.tick(start + Duration::from_secs(1)).unwrap());
+        assert!(writer.has_pending());
+        assert!(writer.tick(start + Duration::from_secs(2)).unwrap());
+        let written = std::fs::read_to_string(&writer.path).unwrap();
+        assert_eq!(written.lines().count(), 2);
+
+        writer.queue(line);
+        writer.truncate(journal_path(&file)).unwrap();
+        assert!(!writer.has_pending());
+        assert!(!journal_path(&file).exists());
+        writer.truncate(journal_path(&file)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_truncates_the_project_journal() {
+        let dir = std::env::temp_dir().join(format!("koto-journal-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        let mut project = Project::new("Song");
+        assert!(matches!(
+            project.start_journal(Duration::ZERO),
+            Err(ProjectError::NoPath)
+        ));
+        project.save_as(file.clone()).unwrap();
+
+        let entry = JournalEntry::SetTempo {
+            tempo: Tempo::new(100.0),
+        };
+        let journal = project.start_journal(Duration::from_secs(60)).unwrap();
+        journal.record(&entry).unwrap();
+        journal.flush().unwrap();
+        journal.record(&entry).unwrap();
+        project.apply_journal_entry(&entry).unwrap();
+        project.save().unwrap();
+        assert!(!journal_path(&file).exists());
+        // The edit still queued at the save is dropped too
+        project.journal_mut().unwrap().flush().unwrap();
+        assert!(!journal_path(&file).exists());
+
+        // Save As moves the journal to the new file
+        let moved = dir.join("take2.kproj");
+        std::fs::write(journal_path(&moved), "stale\n").unwrap();
+        let journal = project.journal_mut().unwrap();
+        journal.record(&entry).unwrap();
+        journal.flush().unwrap();
+        project.save_as(moved.clone()).unwrap();
+        assert!(!journal_path(&file).exists());
+        assert!(!journal_path(&moved).exists());
+        assert_eq!(
+            project.journal_mut().unwrap().path(),
+            journal_path(&moved).as_path()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,84 @@
 //! Koto Project - Project management
 
-use koto_core::{SampleRate, Tempo, TimeSignature};
-use koto_timeline::Timeline;
+mod autosave;
+mod collect;
+mod commands;
+mod compression;
+mod dirty;
+mod info;
+mod journal;
+mod load;
+mod migrate;
+mod recent;
+mod relink;
+mod session;
+mod settings;
+mod template;
+mod timestamp;
+mod track_sheet;
+mod undo_file;
+
+pub use autosave::*;
+pub use collect::*;
+pub use commands::*;
+pub use compression::*;
+pub use info::*;
+pub use journal::*;
+pub use load::*;
+pub use migrate::*;
+pub use recent::*;
+pub use relink::*;
+pub use session::*;
+pub use settings::*;
+pub use template::*;
+pub use timestamp::*;
+pub use track_sheet::*;
+pub use undo_file::*;
+
+use koto_core::{
+    Marker, MarkerList, SamplePosition, SampleRate, Tempo, TimeConverter, TimeSignature, TrackId,
+};
+use koto_timeline::{
+    AudioSourceId, MissingMedia, RippleEdit, SnapSettings, Timeline, TimelineError, TimelineEvent,
+    ValidationIssue,
+};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+use thiserror::Error;
+
+/// Errors loading and saving projects
+#[derive(Error, Debug)]
+pub enum ProjectError {
+    #[error("File I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid project file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid project file: {0}")]
+    InvalidFormat(String),
+    #[error("Project format version {found} is newer than the supported version {supported}")]
+    ProjectVersionMismatch { found: u32, supported: u32 },
+    #[error("No migration from project format version {0}")]
+    MissingMigration(u32),
+    #[error("Project has no file yet")]
+    NoPath,
+    #[error("{path:?} can't replace source {id:?}: {mismatch}")]
+    MediaMismatch {
+        id: AudioSourceId,
+        path: PathBuf,
+        mismatch: MediaMismatch,
+    },
+    #[error("Cancelled")]
+    Cancelled,
+    #[error(transparent)]
+    Timeline(#[from] TimelineError),
+}
 
 /// Project metadata
+///
+/// `created` and `modified` are RFC 3339 timestamps, set when the project
+/// is created and saved. Files from older versions may have empty ones.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectMetadata {
     pub name: String,
@@ -13,6 +86,19 @@ pub struct ProjectMetadata {
     pub description: String,
     pub created: String,
     pub modified: String,
+    /// Time spent with the project open, as of the last save
+    #[serde(default)]
+    pub total_edit_seconds: u64,
+}
+
+impl ProjectMetadata {
+    /// Mark as saved at `now`, and created then if it never was
+    fn stamp(&mut self, now: SystemTime) {
+        self.modified = rfc3339(now);
+        if self.created.is_empty() {
+            self.created = self.modified.clone();
+        }
+    }
 }
 
 impl Default for ProjectMetadata {
@@ -23,6 +109,7 @@ impl Default for ProjectMetadata {
             description: String::new(),
             created: String::new(),
             modified: String::new(),
+            total_edit_seconds: 0,
         }
     }
 }
@@ -30,49 +117,252 @@ impl Default for ProjectMetadata {
 /// Project file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
+    /// File format version; see [`MigrationRegistry`]
+    #[serde(default)]
+    pub format_version: u32,
     pub metadata: ProjectMetadata,
     pub sample_rate: SampleRate,
     pub tempo: Tempo,
     pub time_signature: TimeSignature,
     pub timeline: Timeline,
+    #[serde(default)]
+    pub markers: MarkerList,
+    /// Transport and view state from the last save
+    #[serde(default)]
+    pub session: SessionState,
     #[serde(skip)]
     pub path: Option<PathBuf>,
+    /// Bumped by every change not made through the undo history
+    #[serde(skip)]
+    revision: u64,
     #[serde(skip)]
-    pub modified: bool,
+    saved_revision: u64,
+    /// Whether the undo history has moved away from the saved state
+    #[serde(skip)]
+    history_modified: bool,
+    /// Timeline events from undo and redo, held for the next
+    /// [`take_timeline_events`](Self::take_timeline_events)
+    #[serde(skip)]
+    history_events: Vec<TimelineEvent>,
+    /// Start of the editing session not yet counted in the metadata
+    #[serde(skip)]
+    editing_since: Option<Instant>,
+    /// Edits since the last save, started by
+    /// [`start_journal`](Self::start_journal)
+    #[serde(skip)]
+    journal: Option<CommandJournal>,
+    /// Problems repaired when the file was loaded
+    #[serde(skip)]
+    pub load_issues: Vec<ValidationIssue>,
+    /// Audio files not found when the project was loaded
+    #[serde(skip)]
+    pub missing_media: Vec<MissingMedia>,
 }
 
 impl Project {
     pub fn new(name: impl Into<String>) -> Self {
         Self {
+            format_version: PROJECT_FORMAT_VERSION,
             metadata: ProjectMetadata {
                 name: name.into(),
+                created: rfc3339(SystemTime::now()),
                 ..Default::default()
             },
             sample_rate: SampleRate::default(),
             tempo: Tempo::DEFAULT,
             time_signature: TimeSignature::COMMON_TIME,
             timeline: Timeline::new(),
+            markers: MarkerList::new(),
+            session: SessionState::default(),
             path: None,
-            modified: false,
+            revision: 0,
+            saved_revision: 0,
+            history_modified: false,
+            history_events: Vec::new(),
+            editing_since: None,
+            journal: None,
+            load_issues: Vec::new(),
+            missing_media: Vec::new(),
+        }
+    }
+
+    /// Apply a ripple edit to the timeline, shifting markers along with it
+    /// if `ripple_markers` is set
+    ///
+    /// Markers inside a deleted range are removed.
+    pub fn ripple(
+        &mut self,
+        edit: RippleEdit,
+        tracks: Option<&[TrackId]>,
+        ripple_markers: bool,
+    ) -> Result<(), TimelineError> {
+        self.timeline.ripple(edit, tracks)?;
+        if ripple_markers {
+            self.ripple_markers(edit);
         }
+        Ok(())
+    }
+
+    /// Shift or remove markers as a ripple edit does; returns the markers
+    /// it changed, as they were before
+    pub(crate) fn ripple_markers(&mut self, edit: RippleEdit) -> Vec<Marker> {
+        let changed: Vec<Marker> = self
+            .markers
+            .iter()
+            .filter(|m| edit.map_position(m.position) != Some(m.position))
+            .cloned()
+            .collect();
+        for marker in &changed {
+            match edit.map_position(marker.position) {
+                Some(moved) => {
+                    self.markers.move_marker(marker.id, moved);
+                }
+                None => {
+                    self.markers.remove(marker.id);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Drain the timeline's change events, marking the project modified if
+    /// there were any besides those of undo and redo. Meant to be called
+    /// once per UI frame.
+    pub fn take_timeline_events(&mut self) -> Vec<TimelineEvent> {
+        let events = self.timeline.take_events();
+        if !events.is_empty() {
+            self.mark_modified();
+        }
+        let mut all = std::mem::take(&mut self.history_events);
+        all.extend(events);
+        all
+    }
+
+    /// Change the tempo, moving musical regions to stay on their beats
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        let old = self.time_converter();
+        self.tempo = tempo;
+        self.timeline.retime(&old, &self.time_converter());
+        self.mark_modified();
+    }
+
+    /// Change the time signature, moving musical regions along
+    pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
+        let old = self.time_converter();
+        self.time_signature = time_signature;
+        self.timeline.retime(&old, &self.time_converter());
+        self.mark_modified();
+    }
+
+    /// Relink a source to another file, taking it off the missing list
+    pub fn set_source_path(
+        &mut self,
+        id: AudioSourceId,
+        path: PathBuf,
+    ) -> Result<PathBuf, TimelineError> {
+        let old = self.timeline.set_source_path(id, path)?;
+        self.missing_media.retain(|m| m.id != id);
+        self.mark_modified();
+        Ok(old)
+    }
+
+    /// Converter for the project's sample rate, tempo and time signature
+    pub fn time_converter(&self) -> TimeConverter {
+        TimeConverter::new(self.sample_rate, self.tempo, self.time_signature)
+    }
+
+    /// Snap an edit position, with magnetic snapping to region edges on
+    /// `track` (or every track) and to the project markers
+    pub fn snap(
+        &self,
+        position: SamplePosition,
+        settings: &SnapSettings,
+        track: Option<TrackId>,
+    ) -> SamplePosition {
+        let markers: Vec<SamplePosition> = self.markers.iter().map(|m| m.position).collect();
+        self.timeline
+            .snap_with(position, settings, &self.time_converter(), track, &markers)
     }
 
-    /// Save project to file
-    pub fn save(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
-        std::fs::write(&path, json)?;
-        self.path = Some(path);
-        self.modified = false;
+    /// Save project to its file
+    ///
+    /// Audio source paths inside the project's folder are stored relative
+    /// to it, so the folder can be moved as a whole. The file is replaced
+    /// atomically, so a crash while saving keeps the previous version.
+    /// `.kprojz` files are gzip-compressed; see [`Compression`].
+    ///
+    /// Clears the modified flag; mark the undo history saved too with
+    /// [`UndoHistory::mark_saved`](koto_undo::UndoHistory::mark_saved), or
+    /// save with [`save_with_history`](Self::save_with_history) to keep it
+    /// for the next session.
+    pub fn save(&mut self) -> Result<(), ProjectError> {
+        let path = self.path.clone().ok_or(ProjectError::NoPath)?;
+        self.metadata.stamp(SystemTime::now());
+        self.count_editing_time(Instant::now());
+        self.write(&path)?;
+        self.mark_saved();
+        self.truncate_journal(&path)?;
         Ok(())
     }
 
+    /// Save to a new file, which becomes the project's file and gives the
+    /// project its name
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), ProjectError> {
+        let previous = (self.path.replace(path.clone()), self.metadata.name.clone());
+        if let Some(stem) = path.file_stem() {
+            self.metadata.name = stem.to_string_lossy().into_owned();
+        }
+        let result = self.save();
+        if result.is_err() {
+            (self.path, self.metadata.name) = previous;
+        }
+        result
+    }
+
+    /// Write a copy of the project to `path`, leaving its own file, path
+    /// and modified flag alone
+    pub fn save_copy(&self, path: &Path) -> Result<(), ProjectError> {
+        let mut copy = self.clone();
+        copy.metadata.stamp(SystemTime::now());
+        copy.write(path)
+    }
+
+    /// Write to `path`, compressed if it's a `.kprojz` file
+    fn write(&self, path: &Path) -> Result<(), ProjectError> {
+        let bytes = Compression::for_path(path).encode(&self.for_file(path))?;
+        write_atomic(path, |file| file.write_all(&bytes))?;
+        Ok(())
+    }
+
+    /// Serialize as pretty JSON for saving at `path`
+    fn to_json(&self, path: &Path) -> Result<String, std::io::Error> {
+        serde_json::to_string_pretty(&self.for_file(path)).map_err(std::io::Error::other)
+    }
+
+    /// Copy as it is stored in a file at `path`
+    fn for_file(&self, path: &Path) -> Project {
+        let mut saved = self.clone();
+        saved.format_version = PROJECT_FORMAT_VERSION;
+        if let Some(dir) = path.parent() {
+            saved.timeline.relativize_source_paths(dir);
+        }
+        saved
+    }
+
     /// Load project from file
-    pub fn load(path: PathBuf) -> Result<Self, std::io::Error> {
-        let json = std::fs::read_to_string(&path)?;
-        let mut project: Project = serde_json::from_str(&json).map_err(std::io::Error::other)?;
-        project.path = Some(path);
-        project.modified = false;
-        Ok(project)
+    ///
+    /// Compressed files are recognised by their content rather than their
+    /// extension. Files from older versions are upgraded with the default
+    /// [`MigrationRegistry`]; files from newer versions are refused. A
+    /// damaged or hand-edited timeline is repaired; what was wrong is kept
+    /// in `load_issues` and the project is marked modified. Audio files that
+    /// can't be found don't fail the load; they are listed in
+    /// `missing_media`.
+    ///
+    /// Large projects take a while; load them with
+    /// [`load_async`](Self::load_async) to keep the UI responsive.
+    pub fn load(path: PathBuf) -> Result<Self, ProjectError> {
+        Self::load_with_progress(path, |_| true)
     }
 }
 
@@ -81,3 +371,137 @@ impl Default for Project {
         Self::new("Untitled")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::ChannelCount;
+
+    #[test]
+    fn test_source_paths_saved_relative() {
+        let dir = std::env::temp_dir().join(format!("koto-project-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Paths");
+        let source = project.timeline.add_source(
+            dir.join("audio/vox.wav"),
+            SampleRate(48000),
+            ChannelCount(1),
+            SamplePosition(1000),
+        );
+
+        let file = dir.join("song.koto");
+        project.save_as(file.clone()).unwrap();
+        let json = std::fs::read_to_string(&file).unwrap();
+        assert!(json.contains("\"audio/vox.wav\""));
+        // The open project keeps absolute paths
+        assert_eq!(
+            project.timeline.get_source(source).unwrap().path,
+            dir.join("audio/vox.wav")
+        );
+
+        let mut loaded = Project::load(file).unwrap();
+        assert_eq!(
+            loaded.timeline.get_source(source).unwrap().path,
+            dir.join("audio/vox.wav")
+        );
+        // The file was never written, so it's reported rather than failing
+        assert_eq!(loaded.missing_media.len(), 1);
+        assert_eq!(loaded.missing_media[0].id, source);
+        loaded
+            .set_source_path(source, dir.join("vox-take2.wav"))
+            .unwrap();
+        assert!(loaded.missing_media.is_empty());
+        assert!(loaded.is_modified());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_timeline_events_mark_modified() {
+        let mut project = Project::new("Events");
+        assert!(project.take_timeline_events().is_empty());
+        assert!(!project.is_modified());
+
+        let track = project
+            .timeline
+            .add_track("Audio 1", koto_timeline::TrackType::Audio);
+        assert!(project.is_modified());
+        assert_eq!(
+            project.take_timeline_events(),
+            vec![TimelineEvent::TrackAdded(track)]
+        );
+        assert!(project.is_modified());
+    }
+
+    #[test]
+    fn test_load_repairs_corrupted_timeline() {
+        let dir = std::env::temp_dir().join(format!("koto-repair-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.koto");
+        let mut project = Project::new("Repair");
+        let track = project
+            .timeline
+            .add_track("Audio 1", koto_timeline::TrackType::Audio);
+        let id = project.timeline.new_region_id();
+        let region = koto_timeline::Region::new(id, track, SamplePosition(0), SamplePosition(100));
+        project.timeline.add_region(region).unwrap();
+        project.save_as(file.clone()).unwrap();
+        let clean = Project::load(file.clone()).unwrap();
+        assert!(clean.load_issues.is_empty() && !clean.is_modified());
+
+        let mut json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        json["timeline"]["tracks"][0]["regions"][0]["length"] = serde_json::json!(0);
+        std::fs::write(&file, json.to_string()).unwrap();
+
+        let loaded = Project::load(file).unwrap();
+        assert_eq!(loaded.load_issues, vec![ValidationIssue::InvalidLength(id)]);
+        assert!(loaded.is_modified());
+        assert!(loaded.timeline.validate().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_tempo_moves_musical_regions_only() {
+        let mut project = Project::new("Tempo");
+        let bar_3 = koto_core::MusicalTime::new(3, 1, 0);
+        let keys = project
+            .timeline
+            .add_track("Keys", koto_timeline::TrackType::Midi);
+        let start = project.time_converter().musical_to_samples(bar_3);
+        let midi = project
+            .timeline
+            .create_midi_region(keys, start, 1, &project.time_converter())
+            .unwrap();
+
+        project.set_tempo(Tempo::new(90.0));
+        let moved = project.timeline.get_region(midi).unwrap().start;
+        assert_eq!(moved, project.time_converter().musical_to_samples(bar_3));
+        assert_ne!(moved, start);
+        assert!(project.is_modified());
+    }
+
+    #[test]
+    fn test_save_as_and_save_copy() {
+        let dir = std::env::temp_dir().join(format!("koto-save-as-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Untitled");
+        assert!(matches!(project.save(), Err(ProjectError::NoPath)));
+
+        let file = dir.join("Demo Song.kproj");
+        project.save_as(file.clone()).unwrap();
+        assert_eq!(project.path, Some(file.clone()));
+        assert_eq!(project.metadata.name, "Demo Song");
+        let created = parse_rfc3339(&project.metadata.created).unwrap();
+        assert!(parse_rfc3339(&project.metadata.modified).unwrap() >= created);
+
+        project.mark_modified();
+        let copy = dir.join("backup.kproj");
+        project.save_copy(&copy).unwrap();
+        assert!(project.is_modified());
+        assert_eq!(project.path, Some(file));
+        let saved = Project::load(copy).unwrap();
+        assert_eq!(saved.metadata.name, "Demo Song");
+        assert!(saved.metadata.modified.ends_with('Z'));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
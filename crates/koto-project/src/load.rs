@@ -0,0 +1,262 @@
+//! Loading projects with progress, on a worker thread so the UI stays
+//! responsive
+
+use crate::{decode, MigrationRegistry, Project, ProjectError, PROJECT_FORMAT_VERSION};
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+const CHUNK: usize = 1 << 20;
+
+/// Steps of loading a project, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LoadStage {
+    ReadingFile,
+    Parsing,
+    /// Upgrading older files; see [`MigrationRegistry`]
+    Migrating,
+    /// Finding audio files and repairing the timeline
+    ResolvingMedia,
+}
+
+/// Progress passed to the callback of [`Project::load_with_progress`]
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct LoadProgress {
+    pub stage: LoadStage,
+    /// Share of the stage done, 0.0 to 1.0, for stages that can tell
+    pub fraction: Option<f32>,
+}
+
+impl LoadProgress {
+    fn new(stage: LoadStage, fraction: Option<f32>) -> Self {
+        Self { stage, fraction }
+    }
+}
+
+impl Project {
+    /// [`load`](Self::load), calling `progress` as each stage starts and
+    /// as the file is read; returning `false` cancels with
+    /// [`ProjectError::Cancelled`]
+    pub fn load_with_progress(
+        path: PathBuf,
+        mut progress: impl FnMut(LoadProgress) -> bool,
+    ) -> Result<Self, ProjectError> {
+        let mut report = |stage, fraction| {
+            if progress(LoadProgress::new(stage, fraction)) {
+                Ok(())
+            } else {
+                Err(ProjectError::Cancelled)
+            }
+        };
+
+        report(LoadStage::ReadingFile, Some(0.0))?;
+        let mut file = File::open(&path)?;
+        let total = file.metadata()?.len().max(1);
+        let mut bytes = Vec::with_capacity(total as usize);
+        let mut chunk = vec![0; CHUNK];
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&chunk[..read]);
+            let fraction = (bytes.len() as f64 / total as f64).min(1.0) as f32;
+            report(LoadStage::ReadingFile, Some(fraction))?;
+        }
+
+        report(LoadStage::Parsing, None)?;
+        let mut json: serde_json::Value = serde_json::from_slice(&decode(bytes)?)?;
+
+        report(LoadStage::Migrating, None)?;
+        MigrationRegistry::default().upgrade(&mut json, PROJECT_FORMAT_VERSION)?;
+        let mut project: Project = serde_json::from_value(json)?;
+
+        report(LoadStage::ResolvingMedia, None)?;
+        if let Some(dir) = path.parent() {
+            project.missing_media = project.timeline.resolve_source_paths(dir);
+        }
+        project.load_issues = project.timeline.repair();
+        project.path = Some(path);
+        if !project.load_issues.is_empty() {
+            project.mark_modified();
+        }
+        Ok(project)
+    }
+
+    /// Start loading a project on a worker thread
+    ///
+    /// Poll the returned handle from the UI for progress and the result.
+    pub fn load_async(path: PathBuf) -> ProjectLoad {
+        let shared = Arc::new(LoadShared {
+            progress: Mutex::new(LoadProgress::new(LoadStage::ReadingFile, Some(0.0))),
+            cancelled: AtomicBool::new(false),
+        });
+        let worker = {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                Project::load_with_progress(path, |progress| {
+                    *shared.progress.lock() = progress;
+                    !shared.cancelled.load(Ordering::Relaxed)
+                })
+            })
+        };
+        ProjectLoad {
+            shared,
+            worker: Some(worker),
+        }
+    }
+}
+
+struct LoadShared {
+    progress: Mutex<LoadProgress>,
+    cancelled: AtomicBool,
+}
+
+/// A project loading on a worker thread, from [`Project::load_async`]
+///
+/// Dropping the handle cancels the load.
+pub struct ProjectLoad {
+    shared: Arc<LoadShared>,
+    worker: Option<JoinHandle<Result<Project, ProjectError>>>,
+}
+
+impl ProjectLoad {
+    /// The stage reached so far
+    pub fn progress(&self) -> LoadProgress {
+        *self.shared.progress.lock()
+    }
+
+    /// Stop the load at its next step; the result is then
+    /// [`ProjectError::Cancelled`], unless it had already finished
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// The loaded project or error, once the worker is done
+    ///
+    /// Returns `None` while loading, and after the result was taken.
+    pub fn try_result(&mut self) -> Option<Result<Project, ProjectError>> {
+        if !self.worker.as_ref()?.is_finished() {
+            return None;
+        }
+        self.worker.take().map(join)
+    }
+
+    /// Block until the load finishes
+    pub fn wait(mut self) -> Result<Project, ProjectError> {
+        self.worker
+            .take()
+            .map(join)
+            .unwrap_or(Err(ProjectError::Cancelled))
+    }
+}
+
+impl Drop for ProjectLoad {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+fn join(worker: JoinHandle<Result<Project, ProjectError>>) -> Result<Project, ProjectError> {
+    worker
+        .join()
+        .unwrap_or_else(|_| Err(ProjectError::InvalidFormat("loader panicked".into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::SamplePosition;
+    use koto_timeline::{Region, TrackType};
+
+    /// A project file of several megabytes
+    fn large_project_file(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("koto-load-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Large");
+        for t in 0..20 {
+            let track = project
+                .timeline
+                .add_track(format!("Track {t}"), TrackType::Audio);
+            for r in 0..1000 {
+                let id = project.timeline.new_region_id();
+                let region =
+                    Region::new(id, track, SamplePosition(r * 48000), SamplePosition(24000));
+                project.timeline.add_region(region).unwrap();
+            }
+        }
+        let file = dir.join("large.kproj");
+        project.save_as(file.clone()).unwrap();
+        (dir, file)
+    }
+
+    #[test]
+    fn test_load_progress_is_monotonic() {
+        let (dir, file) = large_project_file("progress");
+        assert!(std::fs::metadata(&file).unwrap().len() > 2 * CHUNK as u64);
+        let mut seen = Vec::new();
+        let project = Project::load_with_progress(file.clone(), |progress| {
+            seen.push(progress);
+            true
+        })
+        .unwrap();
+        assert_eq!(project.timeline.tracks.len(), 20);
+
+        assert!(seen.windows(2).all(|pair| pair[0] <= pair[1]), "{seen:?}");
+        let stages: Vec<_> = seen.iter().map(|p| p.stage).collect();
+        for stage in [
+            LoadStage::ReadingFile,
+            LoadStage::Parsing,
+            LoadStage::Migrating,
+            LoadStage::ResolvingMedia,
+        ] {
+            assert!(stages.contains(&stage));
+        }
+        let reading = seen
+            .iter()
+            .filter(|p| p.stage == LoadStage::ReadingFile)
+            .count();
+        assert!(reading > 2);
+
+        let mut load = Project::load_async(file);
+        let mut last = load.progress();
+        let result = loop {
+            let progress = load.progress();
+            assert!(progress >= last);
+            last = progress;
+            if let Some(result) = load.try_result() {
+                break result;
+            }
+            std::thread::yield_now();
+        };
+        assert_eq!(result.unwrap().timeline.tracks.len(), 20);
+        assert!(load.try_result().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_load_returns_nothing() {
+        let (dir, file) = large_project_file("cancel");
+        let before = std::fs::read(&file).unwrap();
+
+        let load = Project::load_async(file.clone());
+        load.cancel();
+        assert!(matches!(load.wait(), Err(ProjectError::Cancelled)));
+
+        // Cancelling part-way through the file stops before parsing
+        let mut stages = Vec::new();
+        let result = Project::load_with_progress(file.clone(), |progress| {
+            stages.push(progress.stage);
+            progress.fraction.is_none_or(|fraction| fraction < 0.5)
+        });
+        assert!(matches!(result, Err(ProjectError::Cancelled)));
+        assert!(stages.iter().all(|&stage| stage == LoadStage::ReadingFile));
+        // Loading only reads the file
+        assert_eq!(std::fs::read(&file).unwrap(), before);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! Upgrading project files saved by older versions
+
+use crate::ProjectError;
+use serde_json::Value;
+
+/// Format version written by this build
+pub const PROJECT_FORMAT_VERSION: u32 = 1;
+
+/// One step upgrading project JSON from a format version to the next
+pub trait Migration {
+    /// Version this migration reads; it produces the one after
+    fn source_version(&self) -> u32;
+
+    fn migrate(&self, project: &mut Value) -> Result<(), ProjectError>;
+}
+
+/// Migrations applied in order to bring a project up to
+/// [`PROJECT_FORMAT_VERSION`]
+pub struct MigrationRegistry {
+    migrations: Vec<Box<dyn Migration>>,
+}
+
+impl MigrationRegistry {
+    /// Registry without any migrations
+    pub fn empty() -> Self {
+        Self {
+            migrations: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, migration: impl Migration + 'static) {
+        self.migrations.push(Box::new(migration));
+    }
+
+    /// Upgrade `project` step by step to `target`, returning the version
+    /// it was saved with
+    ///
+    /// Files without a version predate versioning and count as version 0.
+    pub fn upgrade(&self, project: &mut Value, target: u32) -> Result<u32, ProjectError> {
+        let found = match project.get("format_version") {
+            None => 0,
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| ProjectError::InvalidFormat("format_version".into()))?,
+        };
+        if found > target {
+            return Err(ProjectError::ProjectVersionMismatch {
+                found,
+                supported: target,
+            });
+        }
+
+        for version in found..target {
+            let migration = self
+                .migrations
+                .iter()
+                .find(|m| m.source_version() == version)
+                .ok_or(ProjectError::MissingMigration(version))?;
+            migration.migrate(project)?;
+            project["format_version"] = Value::from(version + 1);
+        }
+        Ok(found)
+    }
+}
+
+impl Default for MigrationRegistry {
+    /// Every migration of this build
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Unversioned);
+        registry
+    }
+}
+
+/// 0 → 1: files from before `format_version` existed
+///
+/// Fields added up to then all have defaults, so only the root needs
+/// checking before the version is stamped.
+struct Unversioned;
+
+impl Migration for Unversioned {
+    fn source_version(&self) -> u32 {
+        0
+    }
+
+    fn migrate(&self, project: &mut Value) -> Result<(), ProjectError> {
+        if !project.is_object() {
+            return Err(ProjectError::InvalidFormat(
+                "project is not an object".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Project;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn test_load_fixture_of_each_version() {
+        for name in ["project-v0.json", "project-v1.json"] {
+            let project = Project::load(fixture(name)).unwrap();
+            assert_eq!(project.format_version, PROJECT_FORMAT_VERSION, "{name}");
+            assert_eq!(project.metadata.name, "Fixture", "{name}");
+            assert_eq!(project.timeline.tracks.len(), 2, "{name}");
+            assert_eq!(project.timeline.tracks[0].regions.len(), 1, "{name}");
+            assert!(project.load_issues.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_newer_file_is_refused() {
+        let mut json: Value =
+            serde_json::from_str(&std::fs::read_to_string(fixture("project-v1.json")).unwrap())
+                .unwrap();
+        json["format_version"] = Value::from(PROJECT_FORMAT_VERSION + 1);
+        let result = MigrationRegistry::default().upgrade(&mut json, PROJECT_FORMAT_VERSION);
+        assert!(matches!(
+            result,
+            Err(ProjectError::ProjectVersionMismatch {
+                found: 2,
+                supported: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_migrations_run_in_order() {
+        struct Step(u32);
+        impl Migration for Step {
+            fn source_version(&self) -> u32 {
+                self.0
+            }
+            fn migrate(&self, project: &mut Value) -> Result<(), ProjectError> {
+                let steps = project["steps"].as_str().unwrap_or_default();
+                project["steps"] = Value::from(format!("{steps}{}", self.0));
+                Ok(())
+            }
+        }
+
+        let mut registry = MigrationRegistry::empty();
+        registry.register(Step(1));
+        registry.register(Step(0));
+        let mut json = serde_json::json!({});
+        assert_eq!(registry.upgrade(&mut json, 2).unwrap(), 0);
+        assert_eq!(json["steps"], "01");
+        assert_eq!(json["format_version"], 2);
+        assert!(matches!(
+            registry.upgrade(&mut json, 3),
+            Err(ProjectError::MissingMigration(2))
+        ));
+    }
+}
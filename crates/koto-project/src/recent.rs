@@ -0,0 +1,186 @@
+//! Recently opened projects, for the File menu
+
+use crate::{rfc3339, write_atomic, ProjectError};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Default number of entries kept
+pub const RECENT_PROJECTS_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentProject {
+    pub path: PathBuf,
+    /// RFC 3339 time the project was last opened
+    pub opened: String,
+    /// Pinned entries are listed first and never dropped for space
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// Recently opened project files, most recent first, saved to a JSON file
+/// after every change
+#[derive(Debug, Clone)]
+pub struct RecentProjects {
+    file: PathBuf,
+    capacity: usize,
+    entries: Vec<RecentProject>,
+}
+
+impl RecentProjects {
+    /// Read the list kept in `file`, starting empty if there is none yet
+    ///
+    /// Entries whose project no longer exists are dropped. Adding beyond
+    /// `capacity` entries drops the oldest unpinned one; the app uses
+    /// [`RECENT_PROJECTS_CAPACITY`].
+    pub fn load(file: PathBuf, capacity: usize) -> Result<Self, ProjectError> {
+        let mut entries: Vec<RecentProject> = match std::fs::read_to_string(&file) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let count = entries.len();
+        entries.retain(|e| e.path.exists());
+        let recent = Self {
+            file,
+            capacity,
+            entries,
+        };
+        if recent.entries.len() != count {
+            recent.save()?;
+        }
+        Ok(recent)
+    }
+
+    /// Entries to show: pinned ones first, each group most recent first
+    pub fn list(&self) -> Vec<&RecentProject> {
+        let (mut list, unpinned): (Vec<_>, Vec<_>) = self.entries.iter().partition(|e| e.pinned);
+        list.extend(unpinned);
+        list
+    }
+
+    /// Record that `path` was opened now, moving it to the top
+    pub fn add(&mut self, path: &Path) -> Result<(), ProjectError> {
+        let path = normalize(path);
+        let pinned = match self.entries.iter().position(|e| e.path == path) {
+            Some(index) => self.entries.remove(index).pinned,
+            None => false,
+        };
+        self.entries.insert(
+            0,
+            RecentProject {
+                path,
+                opened: rfc3339(SystemTime::now()),
+                pinned,
+            },
+        );
+        while self.entries.len() > self.capacity {
+            match self.entries.iter().rposition(|e| !e.pinned) {
+                Some(oldest) => self.entries.remove(oldest),
+                None => break,
+            };
+        }
+        self.save()
+    }
+
+    /// Pin or unpin an entry; returns whether it was in the list
+    pub fn pin(&mut self, path: &Path, pinned: bool) -> Result<bool, ProjectError> {
+        let path = normalize(path);
+        let Some(entry) = self.entries.iter_mut().find(|e| e.path == path) else {
+            return Ok(false);
+        };
+        entry.pinned = pinned;
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Drop an entry; returns whether it was in the list
+    pub fn remove(&mut self, path: &Path) -> Result<bool, ProjectError> {
+        let path = normalize(path);
+        let count = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        if self.entries.len() == count {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    fn save(&self) -> Result<(), ProjectError> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        write_atomic(&self.file, |file| file.write_all(json.as_bytes()))?;
+        Ok(())
+    }
+}
+
+/// Absolute path without `..` or links where possible, so the same file
+/// opened by different paths is one entry
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let dir = std::env::temp_dir().join(format!("koto-recent-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let projects = (0..4)
+            .map(|i| {
+                let path = dir.join(format!("song{i}.kproj"));
+                std::fs::write(&path, "{}").unwrap();
+                path
+            })
+            .collect();
+        (dir, projects)
+    }
+
+    fn paths(recent: &RecentProjects) -> Vec<PathBuf> {
+        recent.list().into_iter().map(|e| e.path.clone()).collect()
+    }
+
+    #[test]
+    fn test_reopening_moves_entry_to_top() {
+        let (dir, songs) = setup("dedup");
+        let config = dir.join("config/recent.json");
+        let mut recent = RecentProjects::load(config.clone(), 3).unwrap();
+        recent.add(&songs[0]).unwrap();
+        recent.add(&songs[1]).unwrap();
+        // Same file through a different path
+        recent.add(&dir.join("config/../song0.kproj")).unwrap();
+        assert_eq!(paths(&recent), [songs[0].clone(), songs[1].clone()]);
+
+        let reloaded = RecentProjects::load(config, 3).unwrap();
+        assert_eq!(paths(&reloaded), paths(&recent));
+        assert!(!reloaded.list()[0].opened.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pinning_capacity_and_pruning() {
+        let (dir, songs) = setup("pin");
+        let config = dir.join("recent.json");
+        let mut recent = RecentProjects::load(config.clone(), 2).unwrap();
+        recent.add(&songs[0]).unwrap();
+        assert!(recent.pin(&songs[0], true).unwrap());
+        for song in &songs[1..] {
+            recent.add(song).unwrap();
+        }
+        // The pinned entry survives the cap and is listed first
+        assert_eq!(paths(&recent), [songs[0].clone(), songs[3].clone()]);
+
+        assert!(recent.remove(&songs[3]).unwrap());
+        assert!(!recent.remove(&songs[3]).unwrap());
+        recent.add(&songs[2]).unwrap();
+        std::fs::remove_file(&songs[2]).unwrap();
+        let reloaded = RecentProjects::load(config, 2).unwrap();
+        assert_eq!(paths(&reloaded), [songs[0].clone()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,467 @@
+//! Finding moved audio files and relinking sources to them
+
+use crate::{Project, ProjectError};
+use koto_core::{SamplePosition, SampleRate};
+use koto_timeline::{AudioSource, AudioSourceId, MissingMedia};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// A source whose file can't be found, with what is known about it to
+/// check a replacement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingMediaEntry {
+    pub id: AudioSourceId,
+    /// Where the file was expected
+    pub path: PathBuf,
+    pub sample_rate: SampleRate,
+    pub expected_length: SamplePosition,
+    pub content_hash: Option<u64>,
+}
+
+/// How far a replacement file may differ from the source it replaces
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelinkTolerance {
+    /// Allowed length difference as a fraction of the expected length
+    pub length: f64,
+    /// Require the stored content hash, if any, to match
+    pub check_hash: bool,
+}
+
+impl Default for RelinkTolerance {
+    fn default() -> Self {
+        Self {
+            length: 0.001,
+            check_hash: false,
+        }
+    }
+}
+
+/// Why a file can't replace a source
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MediaMismatch {
+    #[error("sample rate is {found} Hz, expected {expected} Hz")]
+    SampleRate { expected: u32, found: u32 },
+    #[error("length is {found} samples, expected {expected}")]
+    Length { expected: i64, found: i64 },
+    #[error("content differs from the original file")]
+    Hash,
+}
+
+/// How a found file was matched to a missing source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Same content as the original, whatever its name
+    Exact,
+    /// Same file name and format, but the content couldn't be verified;
+    /// ask before relinking
+    NameOnly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelinkCandidate {
+    pub id: AudioSourceId,
+    pub path: PathBuf,
+    pub kind: MatchKind,
+}
+
+/// What [`Project::search_and_relink`] found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelinkReport {
+    /// Exact matches, already relinked
+    pub relinked: Vec<RelinkCandidate>,
+    /// Name-only matches left for the user to confirm with
+    /// [`Project::relink_media`]; a source may have several
+    pub to_confirm: Vec<RelinkCandidate>,
+    /// Sources nothing was found for
+    pub still_missing: Vec<AudioSourceId>,
+}
+
+/// Stable 64-bit FNV-1a hash of a file's bytes, as stored in
+/// [`AudioSource::content_hash`]
+pub fn media_hash(path: &Path) -> std::io::Result<u64> {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut hash = OFFSET;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hash);
+        }
+        for &byte in &buffer[..read] {
+            hash = (hash ^ u64::from(byte)).wrapping_mul(PRIME);
+        }
+    }
+}
+
+impl Project {
+    /// Check every source's file again, updating `missing_media`, and
+    /// describe the missing ones
+    pub fn scan_missing_media(&mut self) -> Vec<MissingMediaEntry> {
+        let missing: Vec<MissingMediaEntry> = self
+            .timeline
+            .sources()
+            .filter(|s| !s.path.exists())
+            .map(|s| MissingMediaEntry {
+                id: s.id,
+                path: s.path.clone(),
+                sample_rate: s.sample_rate,
+                expected_length: s.length,
+                content_hash: s.content_hash,
+            })
+            .collect();
+        self.missing_media = missing
+            .iter()
+            .map(|m| MissingMedia {
+                id: m.id,
+                path: m.path.clone(),
+            })
+            .collect();
+        missing
+    }
+
+    /// Point a source at `path` after checking the file can stand in for
+    /// the original
+    ///
+    /// WAV files must have the source's sample rate and a length within
+    /// `tolerance`; other formats aren't checked. With `check_hash`, a
+    /// stored content hash must match too.
+    pub fn relink_media(
+        &mut self,
+        id: AudioSourceId,
+        path: PathBuf,
+        tolerance: &RelinkTolerance,
+    ) -> Result<(), ProjectError> {
+        let source = self
+            .timeline
+            .get_source(id)
+            .ok_or(koto_timeline::TimelineError::SourceNotFound(id))?;
+        check_replacement(source, &path, tolerance)
+            .map_err(|err| err.into_project_error(id, &path))?;
+        if tolerance.check_hash {
+            if let Some(expected) = source.content_hash {
+                if media_hash(&path)? != expected {
+                    return Err(ProjectError::MediaMismatch {
+                        id,
+                        path,
+                        mismatch: MediaMismatch::Hash,
+                    });
+                }
+            }
+        }
+        self.set_source_path(id, path)?;
+        Ok(())
+    }
+
+    /// Look through `dirs` and their subfolders for missing sources
+    ///
+    /// Files whose content hash matches a source's stored hash are exact
+    /// matches, even if renamed, and are relinked right away. Files with a
+    /// source's name that pass the format checks are returned for
+    /// confirmation.
+    pub fn search_and_relink(
+        &mut self,
+        dirs: &[PathBuf],
+        tolerance: &RelinkTolerance,
+    ) -> Result<RelinkReport, ProjectError> {
+        let missing = self.scan_missing_media();
+        let mut files = Vec::new();
+        for dir in dirs {
+            collect_files(dir, &mut files)?;
+        }
+
+        // Each file is hashed at most once, the first time a source needs it
+        let mut hashes: HashMap<&Path, Option<u64>> = HashMap::new();
+        let mut report = RelinkReport::default();
+        for entry in missing {
+            let source = self.timeline.get_source(entry.id).cloned();
+            let Some(source) = source else { continue };
+            let name = entry.path.file_name();
+            let extension = entry.path.extension();
+            let mut exact = None;
+            let mut by_name = Vec::new();
+            for file in &files {
+                let same_name = name.is_some() && file.file_name() == name;
+                if let Some(expected) = entry.content_hash {
+                    // Renamed files are only worth hashing if the type matches
+                    if (same_name || file.extension() == extension)
+                        && *hashes.entry(file).or_insert_with(|| media_hash(file).ok())
+                            == Some(expected)
+                    {
+                        exact = Some(file.clone());
+                        break;
+                    }
+                }
+                if same_name && check_replacement(&source, file, tolerance).is_ok() {
+                    by_name.push(file.clone());
+                }
+            }
+
+            // A name match can't be trusted if the content is known to differ
+            let trust_names = !(tolerance.check_hash && entry.content_hash.is_some());
+            match exact {
+                Some(path) => {
+                    self.set_source_path(entry.id, path.clone())?;
+                    report.relinked.push(RelinkCandidate {
+                        id: entry.id,
+                        path,
+                        kind: MatchKind::Exact,
+                    });
+                }
+                None if trust_names && !by_name.is_empty() => {
+                    report
+                        .to_confirm
+                        .extend(by_name.into_iter().map(|path| RelinkCandidate {
+                            id: entry.id,
+                            path,
+                            kind: MatchKind::NameOnly,
+                        }));
+                }
+                None => report.still_missing.push(entry.id),
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Why [`check_replacement`] refused a file
+enum CheckError {
+    Io(std::io::Error),
+    Mismatch(MediaMismatch),
+}
+
+impl CheckError {
+    fn into_project_error(self, id: AudioSourceId, path: &Path) -> ProjectError {
+        match self {
+            Self::Io(err) => err.into(),
+            Self::Mismatch(mismatch) => ProjectError::MediaMismatch {
+                id,
+                path: path.to_path_buf(),
+                mismatch,
+            },
+        }
+    }
+}
+
+/// Check a WAV file's sample rate and length against `source`
+fn check_replacement(
+    source: &AudioSource,
+    path: &Path,
+    tolerance: &RelinkTolerance,
+) -> Result<(), CheckError> {
+    if !path.is_file() {
+        return Err(CheckError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} not found", path.display()),
+        )));
+    }
+    // Only WAV headers can be read here; other formats are taken as they are
+    let Ok(reader) = hound::WavReader::open(path) else {
+        return Ok(());
+    };
+    let found_rate = reader.spec().sample_rate;
+    if found_rate != source.sample_rate.0 {
+        return Err(CheckError::Mismatch(MediaMismatch::SampleRate {
+            expected: source.sample_rate.0,
+            found: found_rate,
+        }));
+    }
+    let found = i64::from(reader.duration());
+    let expected = source.length.0;
+    if (found - expected).abs() as f64 > expected as f64 * tolerance.length {
+        return Err(CheckError::Mismatch(MediaMismatch::Length {
+            expected,
+            found,
+        }));
+    }
+    Ok(())
+}
+
+/// Every file under `dir`, following no symlinked folders
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::ChannelCount;
+
+    fn write_wav(path: &Path, sample_rate: u32, frames: u32, seed: i16) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for i in 0..frames {
+            writer.write_sample(seed.wrapping_add(i as i16)).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    /// Add a source for a file at `path`, storing its hash if `hashed`,
+    /// then move the file to `moved_to` (or delete it)
+    fn add_moved(
+        project: &mut Project,
+        path: &Path,
+        seed: i16,
+        hashed: bool,
+        moved_to: Option<&Path>,
+    ) -> AudioSourceId {
+        write_wav(path, 48000, 1000, seed);
+        let id = project.timeline.add_source(
+            path,
+            SampleRate(48000),
+            ChannelCount(1),
+            SamplePosition(1000),
+        );
+        if hashed {
+            project.timeline.get_source_mut(id).unwrap().content_hash =
+                Some(media_hash(path).unwrap());
+        }
+        match moved_to {
+            Some(to) => {
+                std::fs::create_dir_all(to.parent().unwrap()).unwrap();
+                std::fs::rename(path, to).unwrap();
+            }
+            None => std::fs::remove_file(path).unwrap(),
+        }
+        id
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("koto-relink-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_search_and_relink_moved_files() {
+        let dir = temp_dir("search");
+        let moved = dir.join("moved");
+        let mut project = Project::new("Moved");
+        let vox = add_moved(
+            &mut project,
+            &dir.join("old/vox.wav"),
+            1,
+            true,
+            Some(&moved.join("takes/vox.wav")),
+        );
+        let bass = add_moved(
+            &mut project,
+            &dir.join("old/bass.wav"),
+            2,
+            true,
+            Some(&moved.join("bass-final.wav")),
+        );
+        let keys = add_moved(
+            &mut project,
+            &dir.join("old/keys.wav"),
+            3,
+            false,
+            Some(&moved.join("keys.wav")),
+        );
+        let gone = add_moved(&mut project, &dir.join("old/gone.wav"), 4, true, None);
+        // Same name, wrong format: not offered
+        write_wav(&moved.join("other/keys.wav"), 44100, 1000, 3);
+
+        let missing = project.scan_missing_media();
+        assert_eq!(missing.len(), 4);
+        assert_eq!(missing[0].expected_length, SamplePosition(1000));
+        assert_eq!(project.missing_media.len(), 4);
+
+        let report = project
+            .search_and_relink(std::slice::from_ref(&moved), &RelinkTolerance::default())
+            .unwrap();
+        let relinked: Vec<_> = report.relinked.iter().map(|c| (c.id, c.kind)).collect();
+        assert_eq!(
+            relinked,
+            [(vox, MatchKind::Exact), (bass, MatchKind::Exact)]
+        );
+        assert_eq!(
+            report.to_confirm,
+            [RelinkCandidate {
+                id: keys,
+                path: moved.join("keys.wav"),
+                kind: MatchKind::NameOnly,
+            }]
+        );
+        assert_eq!(report.still_missing, [gone]);
+        let path = |id| project.timeline.get_source(id).unwrap().path.clone();
+        assert_eq!(path(bass), moved.join("bass-final.wav"));
+        assert_eq!(path(keys), dir.join("old/keys.wav"));
+        assert_eq!(project.missing_media.len(), 2);
+        assert!(project.is_modified());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_relink_media_validates_replacement() {
+        let dir = temp_dir("validate");
+        let mut project = Project::new("Relink");
+        let id = add_moved(&mut project, &dir.join("take.wav"), 5, true, None);
+        let strict = RelinkTolerance {
+            check_hash: true,
+            ..Default::default()
+        };
+
+        write_wav(&dir.join("resampled.wav"), 44100, 1000, 5);
+        write_wav(&dir.join("short.wav"), 48000, 900, 5);
+        write_wav(&dir.join("other.wav"), 48000, 1000, 6);
+        let mismatch = |project: &mut Project, file: &str, tolerance| match project.relink_media(
+            id,
+            dir.join(file),
+            tolerance,
+        ) {
+            Err(ProjectError::MediaMismatch { mismatch, .. }) => mismatch,
+            other => panic!("unexpected {other:?}"),
+        };
+        assert_eq!(
+            mismatch(&mut project, "resampled.wav", &strict),
+            MediaMismatch::SampleRate {
+                expected: 48000,
+                found: 44100
+            }
+        );
+        assert!(matches!(
+            mismatch(&mut project, "short.wav", &strict),
+            MediaMismatch::Length { found: 900, .. }
+        ));
+        assert_eq!(
+            mismatch(&mut project, "other.wav", &strict),
+            MediaMismatch::Hash
+        );
+        assert!(matches!(
+            project.relink_media(id, dir.join("nowhere.wav"), &strict),
+            Err(ProjectError::Io(_))
+        ));
+
+        // Without the hash check the same-format file is accepted
+        project.scan_missing_media();
+        project
+            .relink_media(id, dir.join("other.wav"), &RelinkTolerance::default())
+            .unwrap();
+        assert_eq!(
+            project.timeline.get_source(id).unwrap().path,
+            dir.join("other.wav")
+        );
+        assert!(project.missing_media.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
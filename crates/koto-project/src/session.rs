@@ -0,0 +1,128 @@
+//! Transport and view state saved with the project
+
+use crate::Project;
+use koto_core::{SamplePosition, SampleRange, TrackId};
+use serde::{Deserialize, Serialize};
+
+/// Where the user left off: transport, mixer and view settings restored when
+/// the project is reopened
+///
+/// Every field has a default so files from before a field existed still
+/// load. Markers are part of the project itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    pub playhead: SamplePosition,
+    pub loop_range: SampleRange,
+    pub loop_enabled: bool,
+    pub metronome_enabled: bool,
+    pub master_volume: f32,
+    pub selected_track: Option<TrackId>,
+    /// Timeline zoom in pixels per second
+    pub timeline_zoom: f32,
+    /// First sample visible at the left of the timeline
+    pub timeline_scroll: SamplePosition,
+    /// Vertical scroll of the track list in pixels
+    pub track_scroll: f32,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            playhead: SamplePosition::ZERO,
+            loop_range: SampleRange::default(),
+            loop_enabled: false,
+            metronome_enabled: false,
+            master_volume: 1.0,
+            selected_track: None,
+            timeline_zoom: 100.0,
+            timeline_scroll: SamplePosition::ZERO,
+            track_scroll: 0.0,
+        }
+    }
+}
+
+impl Project {
+    /// Keep the app's current session state for the next save
+    ///
+    /// View state alone doesn't mark the project modified.
+    pub fn capture_session(&mut self, session: SessionState) {
+        self.session = session;
+    }
+
+    /// Session state for the app to restore, fitted to the project as it is
+    ///
+    /// A selected track that no longer exists is dropped, an empty loop is
+    /// disabled, and out-of-range values are clamped.
+    pub fn apply_session(&self) -> SessionState {
+        let mut session = self.session.clone();
+        session.playhead = session.playhead.max(SamplePosition::ZERO);
+        session.timeline_scroll = session.timeline_scroll.max(SamplePosition::ZERO);
+        session.track_scroll = session.track_scroll.max(0.0);
+        session.master_volume = session.master_volume.clamp(0.0, 1.0);
+        if !session.timeline_zoom.is_finite() || session.timeline_zoom <= 0.0 {
+            session.timeline_zoom = SessionState::default().timeline_zoom;
+        }
+        if session.loop_range.is_empty() {
+            session.loop_enabled = false;
+        }
+        session.selected_track = session
+            .selected_track
+            .filter(|&id| self.timeline.get_track(id).is_some());
+        session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_timeline::TrackType;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_session_round_trip() {
+        let dir = std::env::temp_dir().join(format!("koto-session-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut project = Project::new("Session");
+        let track = project.timeline.add_track("Vox", TrackType::Audio);
+        let session = SessionState {
+            playhead: SamplePosition(96000),
+            loop_range: SampleRange::new(SamplePosition(48000), SamplePosition(144000)),
+            loop_enabled: true,
+            metronome_enabled: true,
+            master_volume: 0.8,
+            selected_track: Some(track),
+            timeline_zoom: 250.0,
+            timeline_scroll: SamplePosition(24000),
+            track_scroll: 40.0,
+        };
+        project.save_as(dir.join("session.kproj")).unwrap();
+        project.capture_session(session.clone());
+        assert!(!project.is_modified());
+        project.save().unwrap();
+
+        let mut loaded = Project::load(dir.join("session.kproj")).unwrap();
+        assert_eq!(loaded.apply_session(), session);
+
+        loaded.timeline.remove_track(track);
+        loaded.session.loop_range = SampleRange::default();
+        loaded.session.master_volume = 3.0;
+        let applied = loaded.apply_session();
+        assert_eq!(applied.selected_track, None);
+        assert!(!applied.loop_enabled);
+        assert_eq!(applied.master_volume, 1.0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_without_session_loads_defaults() {
+        let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/project-v1.json");
+        let loaded = Project::load(fixture).unwrap();
+        assert_eq!(loaded.session, SessionState::default());
+
+        let json = serde_json::json!({ "playhead": 100, "future_field": true });
+        let partial: SessionState = serde_json::from_value(json).unwrap();
+        assert_eq!(partial.playhead, SamplePosition(100));
+        assert_eq!(partial.master_volume, 1.0);
+    }
+}
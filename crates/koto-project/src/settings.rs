@@ -0,0 +1,207 @@
+//! Application preferences, kept in the user's config directory rather
+//! than in any project
+
+use crate::{write_atomic, ProjectError, RECENT_PROJECTS_CAPACITY};
+use koto_core::SampleRate;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Audio devices and stream format to try before the system defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_rate: Option<SampleRate>,
+    /// Frames per buffer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buffer_size: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetronomeSound {
+    #[default]
+    Click,
+    Beep,
+    Woodblock,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetronomeSettings {
+    /// Linear gain, 0.0 to 1.0
+    pub level: f32,
+    /// Sound on the first beat of each bar
+    pub accent_sound: MetronomeSound,
+    /// Sound on the other beats
+    pub beat_sound: MetronomeSound,
+}
+
+impl Default for MetronomeSettings {
+    fn default() -> Self {
+        Self {
+            level: 0.7,
+            accent_sound: MetronomeSound::Click,
+            beat_sound: MetronomeSound::Click,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UiTheme {
+    #[default]
+    Dark,
+    Light,
+}
+
+/// Preferences saved as TOML in the user's config directory
+///
+/// Missing fields take their defaults, so settings files from older
+/// versions keep working.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    pub audio: AudioSettings,
+    pub metronome: MetronomeSettings,
+    pub autosave_interval_secs: u64,
+    /// Entries kept in the recent projects list
+    pub recent_projects: usize,
+    pub theme: UiTheme,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            audio: AudioSettings::default(),
+            metronome: MetronomeSettings::default(),
+            autosave_interval_secs: 120,
+            recent_projects: RECENT_PROJECTS_CAPACITY,
+            theme: UiTheme::Dark,
+        }
+    }
+}
+
+impl AppSettings {
+    /// `settings.toml` in Koto's folder of the user's config directory, if
+    /// the platform has one
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("koto").join("settings.toml"))
+    }
+
+    /// Read the settings at `path`
+    ///
+    /// A missing file gives the defaults. So does one that can't be read or
+    /// parsed, with a logged warning; it's replaced on the next save.
+    pub fn load_or_default(path: &Path) -> Self {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(err) => {
+                tracing::warn!(
+                    "Can't read settings {}: {err}; using defaults",
+                    path.display()
+                );
+                return Self::default();
+            }
+        };
+        match toml::from_str::<Self>(&text) {
+            Ok(settings) => settings.sanitized(),
+            Err(err) => {
+                tracing::warn!("Invalid settings {}: {err}; using defaults", path.display());
+                Self::default()
+            }
+        }
+    }
+
+    /// Write the settings to `path`, creating its folder if needed
+    pub fn save(&self, path: &Path) -> Result<(), ProjectError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text =
+            toml::to_string_pretty(self).map_err(|e| ProjectError::InvalidFormat(e.to_string()))?;
+        write_atomic(path, |file| file.write_all(text.as_bytes()))?;
+        Ok(())
+    }
+
+    pub fn autosave_interval(&self) -> Duration {
+        Duration::from_secs(self.autosave_interval_secs)
+    }
+
+    /// Bring hand-edited values back into range
+    fn sanitized(mut self) -> Self {
+        let level = self.metronome.level;
+        self.metronome.level = if level.is_finite() {
+            level.clamp(0.0, 1.0)
+        } else {
+            MetronomeSettings::default().level
+        };
+        self.audio.buffer_size = self.audio.buffer_size.filter(|&frames| frames > 0);
+        self.audio.sample_rate = self.audio.sample_rate.filter(|rate| rate.0 > 0);
+        self.autosave_interval_secs = self.autosave_interval_secs.max(10);
+        self.recent_projects = self.recent_projects.max(1);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("koto-settings-{name}-{}", std::process::id()))
+            .join("settings.toml")
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let path = temp_file("round-trip");
+        assert_eq!(AppSettings::load_or_default(&path), AppSettings::default());
+
+        let settings = AppSettings {
+            audio: AudioSettings {
+                output_device: Some("Studio Interface".into()),
+                input_device: None,
+                sample_rate: Some(SampleRate(96000)),
+                buffer_size: Some(256),
+            },
+            metronome: MetronomeSettings {
+                level: 0.5,
+                accent_sound: MetronomeSound::Woodblock,
+                beat_sound: MetronomeSound::Beep,
+            },
+            autosave_interval_secs: 300,
+            recent_projects: 20,
+            theme: UiTheme::Light,
+        };
+        settings.save(&path).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("[audio]"));
+        assert!(text.contains("accent_sound = \"woodblock\""));
+        assert_eq!(AppSettings::load_or_default(&path), settings);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_corrupt_or_partial_settings_fall_back() {
+        let path = temp_file("corrupt");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "theme = [not toml").unwrap();
+        assert_eq!(AppSettings::load_or_default(&path), AppSettings::default());
+
+        std::fs::write(&path, "theme = \"light\"\n[metronome]\nlevel = 4.0\n").unwrap();
+        let settings = AppSettings::load_or_default(&path);
+        assert_eq!(settings.theme, UiTheme::Light);
+        assert_eq!(settings.metronome.level, 1.0);
+        assert_eq!(settings.recent_projects, RECENT_PROJECTS_CAPACITY);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,36 @@
+//! Track templates stored as standalone files
+
+use koto_timeline::TrackTemplate;
+use std::path::Path;
+
+/// Save a track template as JSON
+pub fn save_track_template(template: &TrackTemplate, path: &Path) -> Result<(), std::io::Error> {
+    let json = serde_json::to_string_pretty(template).map_err(std::io::Error::other)?;
+    std::fs::write(path, json)
+}
+
+/// Load a track template saved with [`save_track_template`]
+pub fn load_track_template(path: &Path) -> Result<TrackTemplate, std::io::Error> {
+    let json = std::fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_timeline::TrackType;
+
+    #[test]
+    fn test_template_file_round_trip() {
+        let template = TrackTemplate {
+            name_pattern: "Vocal {n}".into(),
+            track_type: TrackType::Audio,
+            color: 0x123456,
+            height: 96,
+        };
+        let path = std::env::temp_dir().join(format!("koto-template-{}.json", std::process::id()));
+        save_track_template(&template, &path).unwrap();
+        assert_eq!(load_track_template(&path).unwrap(), template);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! RFC 3339 timestamps for project metadata
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format `time` as an RFC 3339 UTC timestamp with second precision,
+/// e.g. `2024-03-01T12:30:05Z`
+pub fn rfc3339(time: SystemTime) -> String {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    let (days, of_day) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        of_day / 3600,
+        of_day / 60 % 60,
+        of_day % 60
+    )
+}
+
+/// Parse an RFC 3339 timestamp such as those written by [`rfc3339`]
+///
+/// Fractional seconds are dropped. Returns `None` for anything else,
+/// including the empty strings of projects never saved by older versions.
+pub fn parse_rfc3339(text: &str) -> Option<SystemTime> {
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = text.get(range)?;
+        digits
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| digits.parse().ok())?
+    };
+    let bytes = text.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return None;
+    }
+    // Allow a leap second, as RFC 3339 does
+    if second > 60 {
+        return None;
+    }
+
+    let mut rest = &text[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && rest.as_bytes()[3] == b':' => {
+            let bytes = rest.as_bytes();
+            let sign = match bytes[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let two_digits = |at: usize| {
+                let pair = &bytes[at..at + 2];
+                pair.iter()
+                    .all(u8::is_ascii_digit)
+                    .then(|| i64::from(pair[0] - b'0') * 10 + i64::from(pair[1] - b'0'))
+            };
+            sign * (two_digits(1)? * 3600 + two_digits(4)? * 60)
+        }
+        _ => return None,
+    };
+
+    let seconds = days_from_civil(year, month as u32, day as u32) * 86_400
+        + hour * 3600
+        + minute * 60
+        + second
+        - offset;
+    match u64::try_from(seconds) {
+        Ok(after) => Some(UNIX_EPOCH + Duration::from_secs(after)),
+        Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(seconds.unsigned_abs())),
+    }
+}
+
+/// Calendar date of a day count since 1970-01-01, in the proleptic
+/// Gregorian calendar (Howard Hinnant's `civil_from_days`)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Day count since 1970-01-01 of a calendar date; the inverse of
+/// [`civil_from_days`]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let day_of_year = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        // Leap day
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_296_205);
+        assert_eq!(rfc3339(time), "2024-03-01T12:30:05Z");
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_208_000);
+        assert_eq!(rfc3339(time), "2024-02-29T12:00:00Z");
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        for seconds in [0, 951_782_400, 1_709_208_000, 4_107_542_399] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(parse_rfc3339(&rfc3339(time)), Some(time));
+        }
+        let time = UNIX_EPOCH + Duration::from_secs(1_709_296_205);
+        assert_eq!(parse_rfc3339("2024-03-01T21:30:05.250+09:00"), Some(time));
+        for invalid in [
+            "",
+            "2024-03-01",
+            "2024-13-01T00:00:00Z",
+            "2024-03-01T12:30:05",
+        ] {
+            assert_eq!(parse_rfc3339(invalid), None);
+        }
+    }
+}
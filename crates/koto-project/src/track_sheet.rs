@@ -0,0 +1,183 @@
+//! Printable track sheets for sharing a session with a studio
+
+use crate::Project;
+use koto_core::TimeDisplay;
+use koto_timeline::{Track, TrackType};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackSheetFormat {
+    /// Project details as `field,value` rows, a blank line, then the
+    /// track table
+    Csv,
+    /// A heading, a list of project details and a table
+    Markdown,
+}
+
+const COLUMNS: [&str; 9] = [
+    "#", "Name", "Type", "Regions", "First", "Last", "Mute", "Solo", "Arm",
+];
+
+impl Project {
+    /// Write a track sheet: the project's name, tempo, time signature,
+    /// sample rate and length, then a row per track
+    ///
+    /// Times are formatted with `display`. Write to a `Vec<u8>` for a string
+    /// to put on the clipboard.
+    pub fn export_track_sheet(
+        &self,
+        format: TrackSheetFormat,
+        display: TimeDisplay,
+        mut out: impl Write,
+    ) -> std::io::Result<()> {
+        let converter = self.time_converter();
+        let details = [
+            ("Project", self.metadata.name.clone()),
+            ("Tempo", format!("{} BPM", self.tempo.bpm())),
+            (
+                "Time signature",
+                format!(
+                    "{}/{}",
+                    self.time_signature.numerator, self.time_signature.denominator
+                ),
+            ),
+            ("Sample rate", format!("{} Hz", self.sample_rate.0)),
+            ("Length", display.format(self.timeline.length(), &converter)),
+        ];
+        let rows: Vec<[String; 9]> = self
+            .timeline
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                let time = |position| display.format(position, &converter);
+                let first = track.regions.first().map(|r| time(r.start));
+                let last = track.regions.iter().map(|r| r.end()).max().map(time);
+                [
+                    (index + 1).to_string(),
+                    track.name.clone(),
+                    type_name(track).to_string(),
+                    track.regions.len().to_string(),
+                    first.unwrap_or_default(),
+                    last.unwrap_or_default(),
+                    yes_no(track.mute),
+                    yes_no(track.solo),
+                    yes_no(track.armed),
+                ]
+            })
+            .collect();
+
+        match format {
+            TrackSheetFormat::Csv => {
+                for (field, value) in &details {
+                    writeln!(out, "{},{}", csv_field(field), csv_field(value))?;
+                }
+                writeln!(out)?;
+                writeln!(out, "{}", COLUMNS.map(csv_field).join(","))?;
+                for row in &rows {
+                    writeln!(out, "{}", row.each_ref().map(|f| csv_field(f)).join(","))?;
+                }
+            }
+            TrackSheetFormat::Markdown => {
+                writeln!(out, "# {}", markdown_cell(&self.metadata.name))?;
+                writeln!(out)?;
+                for (field, value) in &details[1..] {
+                    writeln!(out, "- {field}: {}", markdown_cell(value))?;
+                }
+                writeln!(out)?;
+                writeln!(out, "| {} |", COLUMNS.join(" | "))?;
+                writeln!(out, "|{}", "---|".repeat(COLUMNS.len()))?;
+                for row in &rows {
+                    let cells = row.each_ref().map(|f| markdown_cell(f));
+                    writeln!(out, "| {} |", cells.join(" | "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn type_name(track: &Track) -> &'static str {
+    match track.track_type {
+        TrackType::Audio => "Audio",
+        TrackType::Midi => "MIDI",
+        TrackType::Instrument => "Instrument",
+        TrackType::Bus => "Bus",
+        TrackType::Master => "Master",
+    }
+}
+
+fn yes_no(flag: bool) -> String {
+    if flag { "Yes" } else { "No" }.to_string()
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Keep a value from breaking out of its table cell
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{SamplePosition, SampleRate, Tempo, TimeSignature};
+    use koto_timeline::Region;
+
+    fn fixture() -> Project {
+        let mut project = Project::new("Demo, Take 2");
+        project.sample_rate = SampleRate(48000);
+        project.tempo = Tempo::new(120.0);
+        project.time_signature = TimeSignature::new(3, 4);
+        let vox = project.timeline.add_track("Lead | Vox", TrackType::Audio);
+        let keys = project
+            .timeline
+            .add_track("Keys \"Rhodes\"", TrackType::Midi);
+        project.timeline.add_track("Drums Bus", TrackType::Bus);
+        for (track, start, length) in [
+            (vox, 48_000, 96_000),
+            (vox, 240_000, 48_000),
+            (keys, 0, 432_000),
+        ] {
+            let id = project.timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+            project.timeline.add_region(region).unwrap();
+        }
+        let vox = project.timeline.get_track_mut(vox).unwrap();
+        vox.solo = true;
+        vox.armed = true;
+        project.timeline.get_track_mut(keys).unwrap().mute = true;
+        project
+    }
+
+    fn export(format: TrackSheetFormat, display: TimeDisplay) -> String {
+        let mut out = Vec::new();
+        fixture()
+            .export_track_sheet(format, display, &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_csv_track_sheet() {
+        assert_eq!(
+            export(TrackSheetFormat::Csv, TimeDisplay::MinutesSeconds),
+            include_str!("../fixtures/track-sheet.csv")
+        );
+    }
+
+    #[test]
+    fn test_markdown_track_sheet() {
+        assert_eq!(
+            export(TrackSheetFormat::Markdown, TimeDisplay::BarsBeats),
+            include_str!("../fixtures/track-sheet.md")
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Undo history kept next to the project file, so edits made before
+//! closing a project can still be undone after reopening it
+
+use crate::{write_atomic, Project, ProjectError, RippleCommand};
+use koto_timeline::{restore_timeline_command, Timeline};
+use koto_undo::{CommandRecord, SerializableCommand, UndoCommand, UndoHistory};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Version written to undo files; files of any other version are ignored
+pub const UNDO_FILE_VERSION: u32 = 1;
+
+/// Path of the undo history of the project at `project_path`: `song.kproj`
+/// keeps it in `song.kproj.undo`
+pub fn undo_path(project_path: &Path) -> PathBuf {
+    let mut name = project_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".undo");
+    project_path.with_file_name(name)
+}
+
+#[derive(Serialize, Deserialize)]
+struct UndoFile {
+    version: u32,
+    /// The project's `modified` stamp when the history was written, to
+    /// spot a project saved since without it
+    project_modified: String,
+    /// Oldest first
+    commands: Vec<CommandRecord>,
+}
+
+impl Project {
+    /// Save the project, then up to `depth` of the most recent commands in
+    /// `history` to its undo file
+    ///
+    /// Only commands that implement [`SerializableCommand`] against the
+    /// project are kept, back to the newest one that doesn't. Marks
+    /// `history` saved.
+    pub fn save_with_history(
+        &mut self,
+        history: &mut UndoHistory,
+        depth: usize,
+    ) -> Result<(), ProjectError> {
+        self.save()?;
+        history.mark_saved();
+        let path = undo_path(self.path.as_deref().ok_or(ProjectError::NoPath)?);
+        let commands = history.records(depth);
+        if commands.is_empty() {
+            return match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            };
+        }
+        let file = UndoFile {
+            version: UNDO_FILE_VERSION,
+            project_modified: self.metadata.modified.clone(),
+            commands,
+        };
+        write_atomic(&path, |out| {
+            serde_json::to_writer(&mut *out, &file).map_err(std::io::Error::other)
+        })?;
+        Ok(())
+    }
+
+    /// Load the history saved by [`save_with_history`](Self::save_with_history)
+    /// into `history`, replacing what it held; returns how many commands
+    /// were restored
+    ///
+    /// Project commands are restored against `project`, and the timeline's
+    /// own commands against `timeline`, the shared timeline they edit.
+    /// A missing file, one of another version or one written for an older
+    /// save of the project restores nothing. Restoring stops at the newest
+    /// command that can't be read, dropping the ones before it.
+    pub fn restore_history(
+        project: &Arc<Mutex<Project>>,
+        timeline: &Arc<Mutex<Timeline>>,
+        history: &mut UndoHistory,
+    ) -> Result<usize, ProjectError> {
+        let (path, modified) = {
+            let project = project.lock();
+            let path = project.path.as_deref().ok_or(ProjectError::NoPath)?;
+            (undo_path(path), project.metadata.modified.clone())
+        };
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err.into()),
+        };
+        let file: UndoFile = serde_json::from_slice(&bytes)?;
+        if file.version != UNDO_FILE_VERSION || file.project_modified != modified {
+            return Ok(0);
+        }
+
+        let mut commands: Vec<Box<dyn UndoCommand>> = file
+            .commands
+            .into_iter()
+            .rev()
+            .map_while(|record| restore_command(project, timeline, record))
+            .collect();
+        commands.reverse();
+        let restored = commands.len();
+        history.restore(commands);
+        Ok(restored)
+    }
+}
+
+/// Re-create a saved command, if its kind is known and its data readable
+fn restore_command(
+    project: &Arc<Mutex<Project>>,
+    timeline: &Arc<Mutex<Timeline>>,
+    record: CommandRecord,
+) -> Option<Box<dyn UndoCommand>> {
+    match record.kind.as_str() {
+        RippleCommand::KIND => RippleCommand::from_data(project, record.data)
+            .ok()
+            .map(|command| Box::new(command) as Box<dyn UndoCommand>),
+        _ => restore_timeline_command(timeline, record),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{SamplePosition, SampleRange};
+    use koto_timeline::{
+        DeleteSelectedCommand, MoveRegionCommand, NudgeSelectedCommand, Region, RegionId,
+        RenameCommand, RenameTarget, RippleEdit, SplitRegionCommand, TrackType,
+    };
+
+    fn project_with_region(path: &Path) -> (Arc<Mutex<Project>>, RegionId) {
+        let mut project = Project::new("Song");
+        let track = project.timeline.add_track("Audio 1", TrackType::Audio);
+        let id = project.timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(5000), SamplePosition(1000));
+        project.timeline.add_region(region).unwrap();
+        project.save_as(path.to_path_buf()).unwrap();
+        (Arc::new(Mutex::new(project)), id)
+    }
+
+    fn ripple(project: &Arc<Mutex<Project>>, start: i64) -> Box<RippleCommand> {
+        let range = SampleRange::new(SamplePosition(start), SamplePosition(start + 1000));
+        Box::new(RippleCommand::new(
+            project.clone(),
+            RippleEdit::Delete(range),
+            None,
+            false,
+        ))
+    }
+
+    /// A shared copy of the project's timeline for timeline commands
+    fn timeline_of(project: &Arc<Mutex<Project>>) -> Arc<Mutex<Timeline>> {
+        Arc::new(Mutex::new(project.lock().timeline.clone()))
+    }
+
+    struct Unsaved;
+
+    impl UndoCommand for Unsaved {
+        fn execute(&mut self) {}
+        fn undo(&mut self) {}
+        fn description(&self) -> &str {
+            "Unsaved"
+        }
+    }
+
+    #[test]
+    fn test_restored_command_undoes_after_reload() {
+        let dir = std::env::temp_dir().join(format!("koto-undo-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        let (project, id) = project_with_region(&file);
+
+        let mut history = UndoHistory::default();
+        history.execute(ripple(&project, 0));
+        history.execute(Box::new(Unsaved));
+        history.execute(ripple(&project, 1000));
+        history.execute(ripple(&project, 0));
+        project.lock().save_with_history(&mut history, 10).unwrap();
+        assert!(undo_path(&file).exists());
+        drop(history);
+
+        let reloaded = Arc::new(Mutex::new(Project::load(file.clone()).unwrap()));
+        let start =
+            |project: &Arc<Mutex<Project>>| project.lock().timeline.get_region(id).unwrap().start;
+        assert_eq!(start(&reloaded), SamplePosition(2000));
+        let mut history = UndoHistory::default();
+        // Only the commands after the unsaved one come back
+        assert_eq!(
+            Project::restore_history(&reloaded, &timeline_of(&reloaded), &mut history).unwrap(),
+            2
+        );
+        assert_eq!(history.undo_description(), Some("Ripple Delete"));
+        assert!(history.is_at_saved());
+
+        history.undo();
+        assert_eq!(start(&reloaded), SamplePosition(3000));
+        history.undo();
+        assert_eq!(start(&reloaded), SamplePosition(4000));
+        assert!(!history.can_undo());
+        history.redo();
+        assert_eq!(start(&reloaded), SamplePosition(3000));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_timeline_commands_restore_and_undo() {
+        let dir = std::env::temp_dir().join(format!("koto-undo-edits-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        let (project, id) = project_with_region(&file);
+        let track = project.lock().timeline.tracks[0].id;
+        {
+            let mut project = project.lock();
+            let filler = project.timeline.add_track("Filler", TrackType::Audio);
+            for i in 0..20 {
+                let id = project.timeline.new_region_id();
+                let mut region =
+                    Region::new(id, filler, SamplePosition(i * 100), SamplePosition(50));
+                region.name = "Filler region".into();
+                project.timeline.add_region(region).unwrap();
+            }
+        }
+        let timeline = Arc::new(Mutex::new(project.lock().timeline.clone()));
+        let original = timeline.lock().get_region(id).cloned().unwrap();
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(MoveRegionCommand::new(
+            timeline.clone(),
+            id,
+            track,
+            SamplePosition(8000),
+        )));
+        history.execute(Box::new(SplitRegionCommand::new(
+            timeline.clone(),
+            id,
+            SamplePosition(8400),
+        )));
+        timeline.lock().selection.add_region(id);
+        history.execute(Box::new(NudgeSelectedCommand::new(timeline.clone(), 100)));
+        history.execute(Box::new(RenameCommand::new(
+            timeline.clone(),
+            RenameTarget::Region(id),
+            "Left",
+        )));
+        let right = timeline.lock().tracks[0].regions[1].id;
+        timeline.lock().selection.clear();
+        timeline.lock().selection.add_region(right);
+        history.execute(Box::new(DeleteSelectedCommand::new(timeline.clone())));
+        let edited = timeline.lock().tracks[0].regions.clone();
+        project.lock().timeline = timeline.lock().clone();
+        project.lock().save_with_history(&mut history, 10).unwrap();
+        // Only what the edits changed is saved, not whole tracks
+        let saved = std::fs::read_to_string(undo_path(&file)).unwrap();
+        assert!(!saved.contains("Filler"));
+
+        let reloaded = Project::load(file.clone()).unwrap();
+        let timeline = Arc::new(Mutex::new(reloaded.timeline.clone()));
+        let reloaded = Arc::new(Mutex::new(reloaded));
+        let mut history = UndoHistory::default();
+        assert_eq!(
+            Project::restore_history(&reloaded, &timeline, &mut history).unwrap(),
+            5
+        );
+        while history.can_undo() {
+            history.undo();
+        }
+        assert_eq!(timeline.lock().get_region(id), Some(&original));
+        assert_eq!(timeline.lock().tracks[0].regions.len(), 1);
+        while history.can_redo() {
+            history.redo();
+        }
+        assert_eq!(timeline.lock().tracks[0].regions, edited);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stale_or_other_version_history_is_ignored() {
+        let dir = std::env::temp_dir().join(format!("koto-undo-stale-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("song.kproj");
+        let (project, _) = project_with_region(&file);
+        let mut history = UndoHistory::default();
+        history.execute(ripple(&project, 0));
+        project.lock().save_with_history(&mut history, 10).unwrap();
+
+        let text = std::fs::read_to_string(undo_path(&file)).unwrap();
+        let newer = text.replace("\"version\":1", "\"version\":2");
+        std::fs::write(undo_path(&file), newer).unwrap();
+        let mut restored = UndoHistory::default();
+        assert_eq!(
+            Project::restore_history(&project, &timeline_of(&project), &mut restored).unwrap(),
+            0
+        );
+
+        std::fs::write(undo_path(&file), text).unwrap();
+        project.lock().metadata.modified = "2000-01-01T00:00:00Z".into();
+        assert_eq!(
+            Project::restore_history(&project, &timeline_of(&project), &mut restored).unwrap(),
+            0
+        );
+        assert!(!restored.can_undo());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+//! Record arming
+
+use crate::{Timeline, TimelineError, TimelineEvent, TrackId, TrackType};
+
+/// How arming a track affects the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArmMode {
+    /// Disarm every other track of the same type
+    #[default]
+    Exclusive,
+    /// Leave other tracks armed
+    Additive,
+}
+
+impl TrackType {
+    /// Whether recorded input can land on tracks of this type
+    pub fn can_arm(&self) -> bool {
+        !matches!(self, TrackType::Bus | TrackType::Master)
+    }
+}
+
+impl Timeline {
+    /// Arm or disarm a track for recording
+    ///
+    /// Bus and master tracks can't be armed. `mode` only matters when
+    /// arming.
+    pub fn set_armed(
+        &mut self,
+        id: TrackId,
+        armed: bool,
+        mode: ArmMode,
+    ) -> Result<(), TimelineError> {
+        let track_type = self
+            .get_track(id)
+            .ok_or(TimelineError::TrackNotFound(id))?
+            .track_type;
+        if armed && !track_type.can_arm() {
+            return Err(TimelineError::NotArmable(id));
+        }
+
+        let mut events = Vec::new();
+        for track in &mut self.tracks {
+            let want = if track.id == id {
+                armed
+            } else if armed && mode == ArmMode::Exclusive && track.track_type == track_type {
+                false
+            } else {
+                continue;
+            };
+            if track.armed != want {
+                track.armed = want;
+                events.push(TimelineEvent::ArmChanged(track.id));
+            }
+        }
+        self.events.extend(events);
+        Ok(())
+    }
+
+    /// Tracks armed for recording, in display order
+    pub fn armed_tracks(&self) -> Vec<TrackId> {
+        self.tracks
+            .iter()
+            .filter(|t| t.armed)
+            .map(|t| t.id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exclusive_and_additive_arming() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let keys = timeline.add_track("Keys", TrackType::Instrument);
+        let bus = timeline.add_track("Bus", TrackType::Bus);
+        timeline.take_events();
+
+        timeline.set_armed(a, true, ArmMode::Additive).unwrap();
+        timeline.set_armed(keys, true, ArmMode::Exclusive).unwrap();
+        timeline.set_armed(b, true, ArmMode::Additive).unwrap();
+        assert_eq!(timeline.armed_tracks(), vec![a, b, keys]);
+
+        // Only tracks of the same type are disarmed
+        timeline.take_events();
+        timeline.set_armed(a, true, ArmMode::Exclusive).unwrap();
+        assert_eq!(timeline.armed_tracks(), vec![a, keys]);
+        assert_eq!(timeline.take_events(), vec![TimelineEvent::ArmChanged(b)]);
+
+        timeline.set_armed(a, false, ArmMode::Exclusive).unwrap();
+        timeline.set_armed(a, false, ArmMode::Exclusive).unwrap();
+        assert_eq!(timeline.armed_tracks(), vec![keys]);
+        assert_eq!(timeline.take_events(), vec![TimelineEvent::ArmChanged(a)]);
+
+        assert_eq!(
+            timeline.set_armed(bus, true, ArmMode::Additive),
+            Err(TimelineError::NotArmable(bus))
+        );
+        timeline.set_armed(bus, false, ArmMode::Additive).unwrap();
+        assert!(timeline.take_events().is_empty());
+    }
+}
@@ -0,0 +1,1410 @@
+//! Undoable timeline edits
+
+use crate::{
+    Crossfade, GroupEdit, GroupEditResult, NudgeAmount, NudgeReport, OverlapPolicy, OverlapReport,
+    Region, RegionId, SnapSettings, Timeline, TimelineError, Track, TrackId, TrackType,
+};
+use koto_core::{SamplePosition, SampleRange, TimeConverter};
+use koto_undo::{CommandRecord, SerializableCommand, UndoCommand};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Split a region at a position
+pub struct SplitRegionCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    state: SplitState,
+}
+
+/// Everything a [`SplitRegionCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct SplitState {
+    region: RegionId,
+    position: SamplePosition,
+    /// ID of the right half, kept so a redo recreates the same region
+    right: Option<RegionId>,
+    /// The region before the split, put back whole on undo
+    original: Option<Region>,
+    /// Crossfades of the region before the split, which the halves take
+    /// over or shorten
+    crossfades: Vec<Crossfade>,
+    /// Whether the last execute succeeded
+    applied: bool,
+}
+
+impl SplitRegionCommand {
+    pub fn new(timeline: Arc<Mutex<Timeline>>, region: RegionId, position: SamplePosition) -> Self {
+        Self {
+            timeline,
+            state: SplitState {
+                region,
+                position,
+                right: None,
+                original: None,
+                crossfades: Vec::new(),
+                applied: false,
+            },
+        }
+    }
+
+    /// The (left, right) region IDs once the split has been applied
+    pub fn result(&self) -> Option<(RegionId, RegionId)> {
+        let state = &self.state;
+        state
+            .right
+            .filter(|_| state.applied)
+            .map(|right| (state.region, right))
+    }
+}
+
+impl UndoCommand for SplitRegionCommand {
+    fn execute(&mut self) {
+        let state = &mut self.state;
+        let mut timeline = self.timeline.lock();
+        state.original = timeline.get_region(state.region).cloned();
+        state.crossfades = timeline
+            .tracks
+            .iter()
+            .flat_map(|t| &t.crossfades)
+            .filter(|x| x.involves(state.region))
+            .copied()
+            .collect();
+        let result = match state.right {
+            Some(right) => timeline.split_region_as(state.region, state.position, right),
+            None => timeline.split_region(state.region, state.position),
+        };
+        state.applied = match result {
+            Ok((_, right)) => {
+                state.right = Some(right);
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    fn undo(&mut self) {
+        let (Some((_, right)), Some(original)) = (self.result(), self.state.original.clone())
+        else {
+            return;
+        };
+        let mut timeline = self.timeline.lock();
+        self.state.applied = timeline.unsplit(original, right).is_err();
+        if !self.state.applied {
+            for crossfade in &self.state.crossfades {
+                timeline.restore_crossfade(*crossfade);
+            }
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Split Region"
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Timeline>>> for SplitRegionCommand {
+    const KIND: &'static str = "split_region";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        timeline: &Arc<Mutex<Timeline>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            timeline: timeline.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+/// Move a region to another position and/or track
+pub struct MoveRegionCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    state: MoveState,
+}
+
+/// Everything a [`MoveRegionCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct MoveState {
+    region: RegionId,
+    to: (TrackId, SamplePosition),
+    /// Where the region was before the move, once applied
+    from: Option<(TrackId, SamplePosition)>,
+    /// Regions changed at the destination to make room
+    overlaps: OverlapReport,
+    /// Crossfades on both tracks before the move
+    crossfades: Vec<Crossfade>,
+}
+
+impl MoveRegionCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        region: RegionId,
+        track: TrackId,
+        start: SamplePosition,
+    ) -> Self {
+        Self {
+            timeline,
+            state: MoveState {
+                region,
+                to: (track, start),
+                from: None,
+                overlaps: OverlapReport::default(),
+                crossfades: Vec::new(),
+            },
+        }
+    }
+}
+
+impl UndoCommand for MoveRegionCommand {
+    fn execute(&mut self) {
+        let state = &mut self.state;
+        let mut timeline = self.timeline.lock();
+        let Some(region) = timeline.get_region(state.region) else {
+            return;
+        };
+        let from = (region.track_id, region.start);
+        let (track, start) = state.to;
+        let crossfades = timeline.crossfades_on(&[from.0, track]);
+        if let Ok(overlaps) = timeline.move_region(state.region, track, start) {
+            state.from = Some(from);
+            state.overlaps = overlaps;
+            state.crossfades = crossfades;
+        }
+    }
+
+    fn undo(&mut self) {
+        let state = &mut self.state;
+        if let Some((track, start)) = state.from.take() {
+            let mut timeline = self.timeline.lock();
+            // Moving back onto the original track can't be incompatible,
+            // and the original spot was free when the move was made
+            let _ =
+                timeline.move_region_with(state.region, track, start, OverlapPolicy::AllowLayered);
+            timeline.revert_overlaps(&state.overlaps);
+            timeline.restore_crossfades(&state.crossfades);
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Move Region"
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Timeline>>> for MoveRegionCommand {
+    const KIND: &'static str = "move_region";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        timeline: &Arc<Mutex<Timeline>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            timeline: timeline.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+/// Duplicate a region
+pub struct DuplicateRegionCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    region: RegionId,
+    /// ID of the copy, kept so a redo recreates the same region
+    copy: Option<RegionId>,
+    /// Regions changed after the original to make room for the copy
+    overlaps: OverlapReport,
+    applied: bool,
+}
+
+impl DuplicateRegionCommand {
+    pub fn new(timeline: Arc<Mutex<Timeline>>, region: RegionId) -> Self {
+        Self {
+            timeline,
+            region,
+            copy: None,
+            overlaps: OverlapReport::default(),
+            applied: false,
+        }
+    }
+
+    /// ID of the copy once the duplicate has been applied
+    pub fn copy(&self) -> Option<RegionId> {
+        self.copy.filter(|_| self.applied)
+    }
+}
+
+impl UndoCommand for DuplicateRegionCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        self.applied = match timeline.duplicate_region_as(self.region, self.copy) {
+            Ok((copy, overlaps)) => {
+                self.copy = Some(copy);
+                self.overlaps = overlaps;
+                true
+            }
+            Err(_) => false,
+        };
+    }
+
+    fn undo(&mut self) {
+        if let Some(copy) = self.copy() {
+            let mut timeline = self.timeline.lock();
+            timeline.take_region(copy);
+            timeline.revert_overlaps(&self.overlaps);
+            self.applied = false;
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Duplicate Region"
+    }
+}
+
+/// Crossfade two regions
+pub struct CreateCrossfadeCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    left: RegionId,
+    right: RegionId,
+    length: SamplePosition,
+    /// Crossfade the pair had before, restored on undo
+    previous: Option<Crossfade>,
+    applied: bool,
+}
+
+impl CreateCrossfadeCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        left: RegionId,
+        right: RegionId,
+        length: SamplePosition,
+    ) -> Self {
+        Self {
+            timeline,
+            left,
+            right,
+            length,
+            previous: None,
+            applied: false,
+        }
+    }
+}
+
+impl UndoCommand for CreateCrossfadeCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        self.previous = timeline
+            .get_region(self.left)
+            .and_then(|r| timeline.get_track(r.track_id))
+            .and_then(|t| {
+                t.crossfades
+                    .iter()
+                    .find(|x| x.left == self.left && x.right == self.right)
+                    .copied()
+            });
+        self.applied = timeline
+            .create_crossfade(self.left, self.right, self.length)
+            .is_ok();
+    }
+
+    fn undo(&mut self) {
+        if !self.applied {
+            return;
+        }
+        let mut timeline = self.timeline.lock();
+        match self.previous {
+            Some(previous) => timeline.restore_crossfade(previous),
+            None => {
+                timeline.remove_crossfade(self.left, self.right);
+            }
+        }
+        self.applied = false;
+    }
+
+    fn description(&self) -> &str {
+        "Create Crossfade"
+    }
+}
+
+/// Move a track to another position in the track list
+pub struct MoveTrackCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    track: TrackId,
+    to: usize,
+    /// Index before the move, once applied
+    from: Option<usize>,
+}
+
+impl MoveTrackCommand {
+    pub fn new(timeline: Arc<Mutex<Timeline>>, track: TrackId, to: usize) -> Self {
+        Self {
+            timeline,
+            track,
+            to,
+            from: None,
+        }
+    }
+}
+
+impl UndoCommand for MoveTrackCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        self.from = timeline.track_index(self.track);
+        if self.from.is_some() {
+            let _ = timeline.move_track(self.track, self.to);
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some(from) = self.from.take() {
+            let _ = self.timeline.lock().move_track(self.track, from);
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Move Track"
+    }
+}
+
+/// Insert a new track at a position in the track list
+pub struct InsertTrackCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    index: usize,
+    name: String,
+    track_type: TrackType,
+    /// The inserted track, once applied
+    track: Option<TrackId>,
+    /// The track as it was when undone, so a redo brings back the same one
+    removed: Option<Track>,
+}
+
+impl InsertTrackCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        index: usize,
+        name: impl Into<String>,
+        track_type: TrackType,
+    ) -> Self {
+        Self {
+            timeline,
+            index,
+            name: name.into(),
+            track_type,
+            track: None,
+            removed: None,
+        }
+    }
+
+    /// ID of the inserted track once applied
+    pub fn track(&self) -> Option<TrackId> {
+        self.track
+    }
+}
+
+impl UndoCommand for InsertTrackCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        self.track = Some(match self.removed.take() {
+            Some(track) => {
+                let id = track.id;
+                timeline.insert_track(self.index, track);
+                id
+            }
+            None => timeline.insert_track_at(self.index, self.name.clone(), self.track_type),
+        });
+    }
+
+    fn undo(&mut self) {
+        let Some(id) = self.track.take() else {
+            return;
+        };
+        let mut timeline = self.timeline.lock();
+        self.removed = timeline.remove_track(id);
+    }
+
+    fn description(&self) -> &str {
+        "Insert Track"
+    }
+}
+
+/// Delete the selected regions as one step
+pub struct DeleteSelectedCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    state: DeleteState,
+}
+
+/// Everything a [`DeleteSelectedCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct DeleteState {
+    /// Regions selected when first executed, deleted again on redo
+    regions: Option<Vec<RegionId>>,
+    /// Deleted regions and the crossfades that went with them
+    removed: Vec<Region>,
+    crossfades: Vec<Crossfade>,
+}
+
+impl DeleteSelectedCommand {
+    pub fn new(timeline: Arc<Mutex<Timeline>>) -> Self {
+        Self {
+            timeline,
+            state: DeleteState {
+                regions: None,
+                removed: Vec::new(),
+                crossfades: Vec::new(),
+            },
+        }
+    }
+}
+
+impl UndoCommand for DeleteSelectedCommand {
+    fn execute(&mut self) {
+        let state = &mut self.state;
+        let mut timeline = self.timeline.lock();
+        let crossfades: Vec<Crossfade> = timeline
+            .tracks
+            .iter()
+            .flat_map(|t| t.crossfades.iter().copied())
+            .collect();
+        let ids = state
+            .regions
+            .get_or_insert_with(|| timeline.selected_region_ids());
+        if let Ok(removed) = timeline.delete_regions(ids) {
+            state.crossfades = crossfades
+                .into_iter()
+                .filter(|x| removed.iter().any(|r| x.involves(r.id)))
+                .collect();
+            state.removed = removed;
+        }
+    }
+
+    fn undo(&mut self) {
+        let mut timeline = self.timeline.lock();
+        for region in self.state.removed.drain(..) {
+            timeline.selection.add_region(region.id);
+            timeline.restore_region(region);
+        }
+        for crossfade in self.state.crossfades.drain(..) {
+            timeline.restore_crossfade(crossfade);
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Delete Regions"
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Timeline>>> for DeleteSelectedCommand {
+    const KIND: &'static str = "delete_selected";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        timeline: &Arc<Mutex<Timeline>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            timeline: timeline.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+/// Nudge the selected regions as one step, by a distance or to the
+/// playhead
+pub struct NudgeSelectedCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    state: NudgeState,
+}
+
+/// Everything a [`NudgeSelectedCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct NudgeState {
+    target: NudgeTarget,
+    /// Regions selected when first executed, nudged again on redo
+    regions: Option<Vec<RegionId>>,
+    /// What the last execute did
+    report: NudgeReport,
+    /// Crossfades on the nudged regions' tracks before the nudge
+    crossfades: Vec<Crossfade>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum NudgeTarget {
+    By(i64),
+    Playhead(SamplePosition),
+}
+
+impl NudgeSelectedCommand {
+    pub fn new(timeline: Arc<Mutex<Timeline>>, delta: i64) -> Self {
+        Self::with_target(timeline, NudgeTarget::By(delta))
+    }
+
+    /// Nudge by `amount`, converted to samples up front so a redo moves the
+    /// same distance
+    pub fn by_amount(
+        timeline: Arc<Mutex<Timeline>>,
+        amount: NudgeAmount,
+        settings: &SnapSettings,
+        converter: &TimeConverter,
+    ) -> Self {
+        Self::new(timeline, amount.to_samples(settings, converter))
+    }
+
+    /// Move the selected regions together so the earliest starts at
+    /// `playhead`
+    pub fn to_playhead(timeline: Arc<Mutex<Timeline>>, playhead: SamplePosition) -> Self {
+        Self::with_target(timeline, NudgeTarget::Playhead(playhead))
+    }
+
+    fn with_target(timeline: Arc<Mutex<Timeline>>, target: NudgeTarget) -> Self {
+        Self {
+            timeline,
+            state: NudgeState {
+                target,
+                regions: None,
+                report: NudgeReport::default(),
+                crossfades: Vec::new(),
+            },
+        }
+    }
+
+    /// Regions the last execute left in place
+    pub fn skipped(&self) -> &[RegionId] {
+        &self.state.report.skipped
+    }
+}
+
+impl UndoCommand for NudgeSelectedCommand {
+    fn execute(&mut self) {
+        let state = &mut self.state;
+        let mut timeline = self.timeline.lock();
+        let ids = state
+            .regions
+            .get_or_insert_with(|| timeline.selected_region_ids());
+        let tracks: Vec<TrackId> = ids
+            .iter()
+            .filter_map(|&id| timeline.get_region(id).map(|r| r.track_id))
+            .collect();
+        state.crossfades = timeline.crossfades_on(&tracks);
+        let result = match state.target {
+            NudgeTarget::By(delta) => timeline.nudge_all(ids, delta),
+            NudgeTarget::Playhead(playhead) => timeline.move_to_playhead(ids, playhead),
+        };
+        state.report = result.unwrap_or_default();
+    }
+
+    fn undo(&mut self) {
+        let mut timeline = self.timeline.lock();
+        for (id, start) in self.state.report.moved.drain(..) {
+            timeline.set_region_start(id, start);
+        }
+        timeline.regions_changed();
+        timeline.restore_crossfades(&self.state.crossfades);
+    }
+
+    fn description(&self) -> &str {
+        match self.state.target {
+            NudgeTarget::By(_) => "Nudge Regions",
+            NudgeTarget::Playhead(_) => "Move to Playhead",
+        }
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Timeline>>> for NudgeSelectedCommand {
+    const KIND: &'static str = "nudge_selected";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        timeline: &Arc<Mutex<Timeline>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            timeline: timeline.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+/// What a [`RenameCommand`] renames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenameTarget {
+    Track(TrackId),
+    Region(RegionId),
+}
+
+/// Rename a track or region
+pub struct RenameCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    state: RenameState,
+}
+
+/// Everything a [`RenameCommand`] saves with the undo history
+#[derive(Serialize, Deserialize)]
+struct RenameState {
+    target: RenameTarget,
+    name: String,
+    /// Name before the rename, once applied
+    previous: Option<String>,
+}
+
+impl RenameCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        target: RenameTarget,
+        name: impl Into<String>,
+    ) -> Self {
+        Self {
+            timeline,
+            state: RenameState {
+                target,
+                name: name.into(),
+                previous: None,
+            },
+        }
+    }
+
+    fn rename(&self, name: &str) -> Result<String, TimelineError> {
+        let mut timeline = self.timeline.lock();
+        match self.state.target {
+            RenameTarget::Track(id) => timeline.rename_track(id, name),
+            RenameTarget::Region(id) => timeline.rename_region(id, name),
+        }
+    }
+}
+
+impl UndoCommand for RenameCommand {
+    fn execute(&mut self) {
+        self.state.previous = self.rename(&self.state.name).ok();
+    }
+
+    fn undo(&mut self) {
+        if let Some(previous) = self.state.previous.take() {
+            let _ = self.rename(&previous);
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self.state.target {
+            RenameTarget::Track(_) => "Rename Track",
+            RenameTarget::Region(_) => "Rename Region",
+        }
+    }
+
+    fn record(&self) -> Option<CommandRecord> {
+        self.to_record()
+    }
+}
+
+impl SerializableCommand<Arc<Mutex<Timeline>>> for RenameCommand {
+    const KIND: &'static str = "rename";
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(&self.state)
+    }
+
+    fn from_data(
+        timeline: &Arc<Mutex<Timeline>>,
+        data: serde_json::Value,
+    ) -> serde_json::Result<Self> {
+        Ok(Self {
+            timeline: timeline.clone(),
+            state: serde_json::from_value(data)?,
+        })
+    }
+}
+
+/// Consolidate the regions in a range on one track
+pub struct ConsolidateCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    track: TrackId,
+    range: SampleRange,
+    converter: TimeConverter,
+    /// ID of the consolidated region, kept so a redo recreates it
+    region: Option<RegionId>,
+    /// Regions trimmed or removed to make room, once applied
+    overlaps: Option<OverlapReport>,
+}
+
+impl ConsolidateCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        track: TrackId,
+        range: SampleRange,
+        converter: TimeConverter,
+    ) -> Self {
+        Self {
+            timeline,
+            track,
+            range,
+            converter,
+            region: None,
+            overlaps: None,
+        }
+    }
+
+    /// ID of the consolidated region once applied
+    pub fn region(&self) -> Option<RegionId> {
+        self.region.filter(|_| self.overlaps.is_some())
+    }
+}
+
+impl UndoCommand for ConsolidateCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        let id = match self.region {
+            Some(id) => id,
+            None => timeline.new_region_id(),
+        };
+        self.overlaps = timeline
+            .consolidate_as(id, self.track, self.range, &self.converter)
+            .ok();
+        self.region = Some(id);
+    }
+
+    fn undo(&mut self) {
+        if let (Some(id), Some(overlaps)) = (self.region, self.overlaps.take()) {
+            let mut timeline = self.timeline.lock();
+            timeline.take_region(id);
+            timeline.revert_overlaps(&overlaps);
+        }
+    }
+
+    fn description(&self) -> &str {
+        "Consolidate"
+    }
+}
+
+/// Edit a region, and with `apply_to_group` its grouped regions, as one
+/// undo step
+pub struct GroupEditCommand {
+    timeline: Arc<Mutex<Timeline>>,
+    region: RegionId,
+    edit: GroupEdit,
+    apply_to_group: bool,
+    results: Vec<GroupEditResult>,
+    /// Affected tracks before and after the edit, once applied
+    tracks: Option<(Vec<Track>, Vec<Track>)>,
+}
+
+impl GroupEditCommand {
+    pub fn new(
+        timeline: Arc<Mutex<Timeline>>,
+        region: RegionId,
+        edit: GroupEdit,
+        apply_to_group: bool,
+    ) -> Self {
+        Self {
+            timeline,
+            region,
+            edit,
+            apply_to_group,
+            results: Vec::new(),
+            tracks: None,
+        }
+    }
+
+    /// Per-track results of the last execute; empty if it failed
+    pub fn results(&self) -> &[GroupEditResult] {
+        &self.results
+    }
+}
+
+impl UndoCommand for GroupEditCommand {
+    fn execute(&mut self) {
+        let mut timeline = self.timeline.lock();
+        if let Some((_, after)) = &self.tracks {
+            // Redo: restore the edited tracks so new regions keep their IDs
+            for track in after {
+                timeline.replace_track(track.clone());
+            }
+            return;
+        }
+        let before = timeline.tracks.clone();
+        let Ok(results) = timeline.edit_region(self.region, self.edit, self.apply_to_group) else {
+            return;
+        };
+        let touched = |t: &Track| results.iter().any(|r| r.track == t.id);
+        let before = before.into_iter().filter(|t| touched(t)).collect();
+        let after = timeline
+            .tracks
+            .iter()
+            .filter(|t| touched(t))
+            .cloned()
+            .collect();
+        self.tracks = Some((before, after));
+        self.results = results;
+    }
+
+    fn undo(&mut self) {
+        if let Some((before, _)) = &self.tracks {
+            let mut timeline = self.timeline.lock();
+            for track in before {
+                timeline.replace_track(track.clone());
+            }
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self.edit {
+            GroupEdit::Move { .. } => "Move Region",
+            GroupEdit::Trim { .. } => "Trim Region",
+            GroupEdit::Split { .. } => "Split Region",
+            GroupEdit::Delete => "Delete Region",
+        }
+    }
+}
+
+/// Re-create a timeline command saved with the undo history against
+/// `timeline`, if it's one of the kinds that can be saved
+pub fn restore_timeline_command(
+    timeline: &Arc<Mutex<Timeline>>,
+    record: CommandRecord,
+) -> Option<Box<dyn UndoCommand>> {
+    fn boxed(
+        command: serde_json::Result<impl UndoCommand + 'static>,
+    ) -> Option<Box<dyn UndoCommand>> {
+        Some(Box::new(command.ok()?))
+    }
+    let data = record.data;
+    match record.kind.as_str() {
+        SplitRegionCommand::KIND => boxed(SplitRegionCommand::from_data(timeline, data)),
+        MoveRegionCommand::KIND => boxed(MoveRegionCommand::from_data(timeline, data)),
+        DeleteSelectedCommand::KIND => boxed(DeleteSelectedCommand::from_data(timeline, data)),
+        NudgeSelectedCommand::KIND => boxed(NudgeSelectedCommand::from_data(timeline, data)),
+        RenameCommand::KIND => boxed(RenameCommand::from_data(timeline, data)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrackType;
+    use koto_undo::UndoHistory;
+
+    #[test]
+    fn test_split_undo_redo() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let mut region = Region::new(id, track, SamplePosition(0), SamplePosition(1000));
+        region.fade_in_length = SamplePosition(100);
+        region.fade_out_length = SamplePosition(200);
+        timeline
+            .get_track_mut(track)
+            .unwrap()
+            .add_region(region.clone());
+        let timeline = Arc::new(Mutex::new(timeline));
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(SplitRegionCommand::new(
+            timeline.clone(),
+            id,
+            SamplePosition(400),
+        )));
+        let split_ids: Vec<RegionId> = timeline.lock().tracks[0]
+            .regions
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(split_ids.len(), 2);
+
+        history.undo();
+        {
+            let timeline = timeline.lock();
+            assert_eq!(timeline.tracks[0].regions.len(), 1);
+            // Both fades survive, though each half lost one
+            assert_eq!(timeline.tracks[0].regions[0], region);
+        }
+
+        history.redo();
+        let redo_ids: Vec<RegionId> = timeline.lock().tracks[0]
+            .regions
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(redo_ids, split_ids);
+        assert_eq!(
+            timeline.lock().tracks[0].regions[1].source_offset,
+            SamplePosition(400)
+        );
+    }
+
+    #[test]
+    fn test_split_undo_restores_crossfades() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for start in [0, 1000, 2000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        timeline
+            .create_crossfade(ids[0], ids[1], SamplePosition(500))
+            .unwrap();
+        timeline
+            .create_crossfade(ids[1], ids[2], SamplePosition(300))
+            .unwrap();
+        let before = timeline.tracks[0].crossfades.clone();
+        let timeline = Arc::new(Mutex::new(timeline));
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(SplitRegionCommand::new(
+            timeline.clone(),
+            ids[1],
+            SamplePosition(1200),
+        )));
+        {
+            // The left half is too short for the first crossfade, and the
+            // right half took over the second
+            let timeline = timeline.lock();
+            let crossfades = &timeline.tracks[0].crossfades;
+            assert_eq!(crossfades[0].length, SamplePosition(200));
+            assert_eq!(crossfades[1].left, timeline.tracks[0].regions[2].id);
+        }
+
+        history.undo();
+        let mut after = timeline.lock().tracks[0].crossfades.clone();
+        after.sort_by_key(|x| x.left.0);
+        assert_eq!(after, before);
+        history.redo();
+        assert_eq!(timeline.lock().tracks[0].crossfades.len(), 2);
+    }
+
+    #[test]
+    fn test_move_and_duplicate_undo() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("Audio 1", TrackType::Audio);
+        let b = timeline.add_track("Audio 2", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, a, SamplePosition(100), SamplePosition(1000));
+        timeline.get_track_mut(a).unwrap().add_region(region);
+        let timeline = Arc::new(Mutex::new(timeline));
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(MoveRegionCommand::new(
+            timeline.clone(),
+            id,
+            b,
+            SamplePosition(500),
+        )));
+        assert_eq!(timeline.lock().get_region(id).unwrap().track_id, b);
+        history.undo();
+        {
+            let timeline = timeline.lock();
+            let region = timeline.get_region(id).unwrap();
+            assert_eq!((region.track_id, region.start), (a, SamplePosition(100)));
+        }
+
+        history.execute(Box::new(DuplicateRegionCommand::new(timeline.clone(), id)));
+        assert_eq!(timeline.lock().tracks[0].regions.len(), 2);
+        let copy = timeline.lock().tracks[0].regions[1].id;
+        history.undo();
+        assert_eq!(timeline.lock().tracks[0].regions.len(), 1);
+        history.redo();
+        assert_eq!(timeline.lock().tracks[0].regions[1].id, copy);
+    }
+
+    #[test]
+    fn test_duplicate_applies_overlap_policy() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for (start, length) in [(0, 1000), (1500, 1000)] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        timeline.overlap_policy = OverlapPolicy::TrimExisting;
+        let timeline = Arc::new(Mutex::new(timeline));
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(DuplicateRegionCommand::new(
+            timeline.clone(),
+            ids[0],
+        )));
+        {
+            let timeline = timeline.lock();
+            let after = timeline.get_region(ids[1]).unwrap();
+            assert_eq!(
+                (after.start, after.end()),
+                (SamplePosition(2000), SamplePosition(2500))
+            );
+        }
+        history.undo();
+        {
+            let timeline = timeline.lock();
+            assert_eq!(timeline.tracks[0].regions.len(), 2);
+            assert_eq!(
+                timeline.get_region(ids[1]).unwrap().start,
+                SamplePosition(1500)
+            );
+        }
+
+        // Nothing changes when a region in the way is locked
+        timeline.lock().set_region_locked(ids[1], true).unwrap();
+        assert_eq!(
+            timeline.lock().duplicate_region(ids[0]),
+            Err(TimelineError::RegionLocked(ids[1]))
+        );
+        assert_eq!(timeline.lock().tracks[0].regions.len(), 2);
+    }
+
+    #[test]
+    fn test_move_and_nudge_undo_restore_crossfades() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for start in [0, 1000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        let crossfade = timeline
+            .create_crossfade(ids[0], ids[1], SamplePosition(200))
+            .unwrap();
+        let timeline = Arc::new(Mutex::new(timeline));
+        let crossfades = || timeline.lock().tracks[0].crossfades.clone();
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(MoveRegionCommand::new(
+            timeline.clone(),
+            ids[1],
+            track,
+            SamplePosition(5000),
+        )));
+        assert!(crossfades().is_empty());
+        history.undo();
+        assert_eq!(crossfades(), [crossfade]);
+
+        timeline.lock().selection.add_region(ids[1]);
+        history.execute(Box::new(NudgeSelectedCommand::new(timeline.clone(), 500)));
+        assert!(crossfades().is_empty());
+        history.undo();
+        assert_eq!(crossfades(), [crossfade]);
+        history.redo();
+        assert!(crossfades().is_empty());
+    }
+
+    #[test]
+    fn test_crossfade_undo_restores_previous() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for start in [0, 1000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        timeline
+            .create_crossfade(ids[0], ids[1], SamplePosition(100))
+            .unwrap();
+        let timeline = Arc::new(Mutex::new(timeline));
+        let crossfade_length = || timeline.lock().tracks[0].crossfades[0].length;
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(CreateCrossfadeCommand::new(
+            timeline.clone(),
+            ids[0],
+            ids[1],
+            SamplePosition(300),
+        )));
+        assert_eq!(crossfade_length(), SamplePosition(300));
+        history.undo();
+        assert_eq!(crossfade_length(), SamplePosition(100));
+        assert_eq!(timeline.lock().tracks[0].crossfades.len(), 1);
+    }
+
+    #[test]
+    fn test_track_commands() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let timeline = Arc::new(Mutex::new(timeline));
+        let order = || -> Vec<TrackId> { timeline.lock().tracks.iter().map(|t| t.id).collect() };
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(MoveTrackCommand::new(timeline.clone(), a, 1)));
+        assert_eq!(order(), vec![b, a]);
+        history.undo();
+        assert_eq!(order(), vec![a, b]);
+
+        history.execute(Box::new(InsertTrackCommand::new(
+            timeline.clone(),
+            1,
+            "Below A",
+            TrackType::Midi,
+        )));
+        let inserted = order()[1];
+        history.undo();
+        assert_eq!(order(), vec![a, b]);
+        history.redo();
+        assert_eq!(order(), vec![a, inserted, b]);
+    }
+
+    #[test]
+    fn test_selection_commands_are_single_steps() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for start in [0, 1000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            timeline.selection.add_region(id);
+            ids.push(id);
+        }
+        timeline
+            .create_crossfade(ids[0], ids[1], SamplePosition(100))
+            .unwrap();
+        let timeline = Arc::new(Mutex::new(timeline));
+        let starts = || -> Vec<i64> {
+            timeline.lock().tracks[0]
+                .regions
+                .iter()
+                .map(|r| r.start.0)
+                .collect()
+        };
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(NudgeSelectedCommand::new(timeline.clone(), 500)));
+        assert_eq!(starts(), vec![500, 1500]);
+        history.undo();
+        assert_eq!(starts(), vec![0, 1000]);
+
+        history.execute(Box::new(DeleteSelectedCommand::new(timeline.clone())));
+        assert!(starts().is_empty());
+        history.undo();
+        assert!(!history.can_undo());
+        assert_eq!(starts(), vec![0, 1000]);
+        assert_eq!(timeline.lock().tracks[0].crossfades.len(), 1);
+    }
+
+    #[test]
+    fn test_selection_commands_redo_on_original_regions() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = Vec::new();
+        for start in [0, 2000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        timeline.selection.add_region(ids[0]);
+        let timeline = Arc::new(Mutex::new(timeline));
+        let start = |id| timeline.lock().get_region(id).map(|r| r.start.0);
+        let reselect = |id| {
+            let mut timeline = timeline.lock();
+            timeline.selection.clear();
+            timeline.selection.add_region(id);
+        };
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(NudgeSelectedCommand::new(timeline.clone(), 500)));
+        history.undo();
+        reselect(ids[1]);
+        history.redo();
+        assert_eq!((start(ids[0]), start(ids[1])), (Some(500), Some(2000)));
+
+        history.execute(Box::new(DeleteSelectedCommand::new(timeline.clone())));
+        assert_eq!(start(ids[1]), None);
+        history.undo();
+        reselect(ids[0]);
+        history.redo();
+        assert_eq!((start(ids[0]), start(ids[1])), (Some(500), None));
+    }
+
+    #[test]
+    fn test_rename_undo() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let timeline = Arc::new(Mutex::new(timeline));
+        let name = || timeline.lock().tracks[0].name.clone();
+        let mut history = UndoHistory::default();
+
+        history.execute(Box::new(RenameCommand::new(
+            timeline.clone(),
+            RenameTarget::Track(track),
+            " Kick ",
+        )));
+        assert_eq!(name(), "Kick");
+        history.undo();
+        assert_eq!(name(), "Audio 1");
+        history.redo();
+        assert_eq!(name(), "Kick");
+    }
+
+    #[test]
+    fn test_consolidate_undo_restores_regions() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        for (start, gain) in [(0, -3.0), (1500, 2.0)] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            region.gain_db = gain;
+            region.set_fade_out(SamplePosition(200));
+            timeline.add_region(region).unwrap();
+        }
+        let before = timeline.tracks[0].regions.clone();
+        let timeline = Arc::new(Mutex::new(timeline));
+        let converter = TimeConverter::new(
+            koto_core::SampleRate(48000),
+            koto_core::Tempo::new(120.0),
+            koto_core::TimeSignature::COMMON_TIME,
+        );
+        let range = SampleRange::new(SamplePosition(500), SamplePosition(3000));
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(ConsolidateCommand::new(
+            timeline.clone(),
+            track,
+            range,
+            converter,
+        )));
+        let after = timeline.lock().tracks[0].regions.clone();
+        assert_eq!(after.len(), 2);
+        assert_eq!(after[1].range(), range);
+
+        history.undo();
+        assert_eq!(timeline.lock().tracks[0].regions, before);
+        history.redo();
+        assert_eq!(timeline.lock().tracks[0].regions, after);
+    }
+
+    #[test]
+    fn test_nudge_regions_is_one_step() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let ids: Vec<_> = [0, 1000, 2000]
+            .into_iter()
+            .map(|start| {
+                let id = timeline.new_region_id();
+                let region = Region::new(id, track, SamplePosition(start), SamplePosition(500));
+                timeline.add_region(region).unwrap();
+                id
+            })
+            .collect();
+        let timeline = Arc::new(Mutex::new(timeline));
+        let converter = TimeConverter::new(
+            koto_core::SampleRate(48000),
+            koto_core::Tempo::new(120.0),
+            koto_core::TimeSignature::COMMON_TIME,
+        );
+        let starts = |timeline: &Arc<Mutex<Timeline>>| -> Vec<i64> {
+            timeline.lock().tracks[0]
+                .regions
+                .iter()
+                .map(|r| r.start.0)
+                .collect()
+        };
+
+        for id in ids {
+            timeline.lock().selection.add_region(id);
+        }
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(NudgeSelectedCommand::by_amount(
+            timeline.clone(),
+            NudgeAmount::Samples(100),
+            &SnapSettings::default(),
+            &converter,
+        )));
+        assert_eq!(starts(&timeline), vec![100, 1100, 2100]);
+        history.execute(Box::new(NudgeSelectedCommand::to_playhead(
+            timeline.clone(),
+            SamplePosition(5000),
+        )));
+        assert_eq!(starts(&timeline), vec![5000, 6000, 7000]);
+
+        history.undo();
+        assert_eq!(starts(&timeline), vec![100, 1100, 2100]);
+        history.undo();
+        assert_eq!(starts(&timeline), vec![0, 1000, 2000]);
+    }
+
+    #[test]
+    fn test_group_edit_is_one_undo_step() {
+        let mut timeline = Timeline::new();
+        let mut regions = Vec::new();
+        let mut tracks = Vec::new();
+        for name in ["Kick", "Snare"] {
+            let track = timeline.add_track(name, TrackType::Audio);
+            let id = timeline.new_region_id();
+            timeline
+                .add_region(Region::new(
+                    id,
+                    track,
+                    SamplePosition(0),
+                    SamplePosition(1000),
+                ))
+                .unwrap();
+            tracks.push(track);
+            regions.push(id);
+        }
+        timeline.add_edit_group(tracks.clone()).unwrap();
+        let timeline = Arc::new(Mutex::new(timeline));
+
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(GroupEditCommand::new(
+            timeline.clone(),
+            regions[0],
+            GroupEdit::Split {
+                position: SamplePosition(400),
+            },
+            true,
+        )));
+        let split = |timeline: &Timeline| {
+            tracks
+                .iter()
+                .map(|t| timeline.get_track(*t).unwrap().regions.len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(split(&timeline.lock()), [2, 2]);
+        let after = timeline.lock().tracks.clone();
+
+        history.undo();
+        assert_eq!(split(&timeline.lock()), [1, 1]);
+        assert_eq!(
+            timeline.lock().get_region(regions[1]).unwrap().length,
+            SamplePosition(1000)
+        );
+
+        history.redo();
+        let redone = &timeline.lock().tracks;
+        for (a, b) in after.iter().zip(redone) {
+            assert_eq!(a.regions, b.regions);
+        }
+    }
+}
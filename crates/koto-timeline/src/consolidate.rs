@@ -0,0 +1,227 @@
+//! Consolidating the regions in a range into one
+
+use crate::content::visible_notes;
+use crate::{
+    OverlapPolicy, OverlapReport, Region, RegionContent, RegionId, Timeline, TimelineError,
+    TimelineEvent, TrackId, TrackType,
+};
+use koto_core::{MidiClip, MidiNote, SamplePosition, SampleRange, TimeConverter};
+use serde::{Deserialize, Serialize};
+
+/// Audio regions joined into one, waiting to be rendered by the engine
+///
+/// Each part keeps its source, gain and fades; part starts are relative to
+/// the consolidated region's content start. Anything between parts is
+/// silence.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConsolidatedContent {
+    pub parts: Vec<Region>,
+}
+
+impl Timeline {
+    /// Replace the regions in `range` on a track by a single region
+    /// spanning the range
+    ///
+    /// Regions reaching outside the range keep their outside parts. On MIDI
+    /// tracks the visible notes are merged into one clip at the same
+    /// places; on audio tracks the regions become parts of
+    /// [`ConsolidatedContent`].
+    pub fn consolidate(
+        &mut self,
+        track: TrackId,
+        range: SampleRange,
+        converter: &TimeConverter,
+    ) -> Result<RegionId, TimelineError> {
+        let id = self.new_region_id();
+        self.consolidate_as(id, track, range, converter)?;
+        Ok(id)
+    }
+
+    pub(crate) fn consolidate_as(
+        &mut self,
+        id: RegionId,
+        track: TrackId,
+        range: SampleRange,
+        converter: &TimeConverter,
+    ) -> Result<OverlapReport, TimelineError> {
+        let t = self
+            .track_index(track)
+            .ok_or(TimelineError::TrackNotFound(track))?;
+        if range.is_empty() {
+            return Err(TimelineError::EmptyRange);
+        }
+        self.check_overlaps(t, range, id, OverlapPolicy::TrimExisting)?;
+
+        let parts: Vec<Region> = self.tracks[t]
+            .regions_in_range(range)
+            .map(|r| {
+                let mut part = r.clone();
+                if part.start < range.start {
+                    part.trim_start(range.start);
+                }
+                if part.end() > range.end {
+                    part.trim_end(range.end);
+                }
+                part
+            })
+            .collect();
+
+        let track = &self.tracks[t];
+        let mut region = Region::new(id, track.id, range.start, SamplePosition(range.length()));
+        region.color = track.color;
+        if let Some(first) = parts.first() {
+            region.name = first.name.clone();
+        }
+        region.content = if track.track_type.accepts_regions_from(TrackType::Midi) {
+            let mut clip = MidiClip::new();
+            for part in &parts {
+                let shift = converter.samples_to_ticks(part.start - range.start);
+                for note in visible_notes(part, converter) {
+                    clip.add(MidiNote {
+                        start: note.start + shift,
+                        ..note
+                    });
+                }
+            }
+            RegionContent::Midi(clip)
+        } else {
+            let parts = parts
+                .into_iter()
+                .map(|mut part| {
+                    part.start = part.start - range.start;
+                    part
+                })
+                .collect();
+            RegionContent::Consolidated(ConsolidatedContent { parts })
+        };
+
+        let report = self.clear_overlaps_with(t, range, id, OverlapPolicy::TrimExisting)?;
+        self.tracks[t].add_region(region);
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionAdded(id));
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AudioSourceId;
+    use koto_core::{FadeCurve, NoteNumber, SampleRate, Tempo, TimeSignature, Velocity};
+
+    /// 120 BPM at 48 kHz: 24000 samples per beat
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    fn range(start: i64, end: i64) -> SampleRange {
+        SampleRange::new(SamplePosition(start), SamplePosition(end))
+    }
+
+    #[test]
+    fn test_consolidate_audio_keeps_gains_and_fades() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Gtr", TrackType::Audio);
+        let mut ids = Vec::new();
+        for (start, gain) in [(0, -6.0), (1200, 3.0), (2000, 0.0)] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(1000));
+            region.content = RegionContent::Audio {
+                source: AudioSourceId(start as u64),
+            };
+            region.gain_db = gain;
+            region.set_fade_in(SamplePosition(200));
+            region.set_fade_out(SamplePosition(400));
+            region.fade_out_curve = FadeCurve::SCurve;
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+
+        let id = timeline
+            .consolidate(track, range(500, 2500), &converter())
+            .unwrap();
+        let spans: Vec<_> = timeline.tracks[0]
+            .regions
+            .iter()
+            .map(|r| (r.start.0, r.end().0))
+            .collect();
+        assert_eq!(spans, vec![(0, 500), (500, 2500), (2500, 3000)]);
+
+        let region = timeline.get_region(id).unwrap();
+        let RegionContent::Consolidated(content) = &region.content else {
+            panic!("expected consolidated content");
+        };
+        let parts: Vec<_> = content
+            .parts
+            .iter()
+            .map(|p| (p.start.0, p.end().0, p.source_offset.0, p.gain_db))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                (0, 500, 500, -6.0),
+                (700, 1700, 0, 3.0),
+                (1500, 2000, 0, 0.0)
+            ]
+        );
+        // Trimmed parts shorten their fade-out to fit
+        let fades: Vec<_> = content
+            .parts
+            .iter()
+            .map(|p| (p.fade_in_length.0, p.fade_out_length.0, p.fade_out_curve))
+            .collect();
+        assert_eq!(
+            fades,
+            vec![
+                (200, 300, FadeCurve::SCurve),
+                (200, 400, FadeCurve::SCurve),
+                (200, 300, FadeCurve::SCurve),
+            ]
+        );
+        assert_eq!(timeline.find_regions_using(AudioSourceId(1200)), vec![id]);
+    }
+
+    #[test]
+    fn test_consolidate_midi_merges_notes() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Keys", TrackType::Instrument);
+        let conv = converter();
+        let mut ids = Vec::new();
+        for start in [0, 96000] {
+            let id = timeline
+                .create_midi_region(track, SamplePosition(start), 1, &conv)
+                .unwrap();
+            ids.push(id);
+        }
+        for (i, region) in timeline.tracks[0].regions.iter_mut().enumerate() {
+            region.gain_db = i as f32 * -3.0;
+            let RegionContent::Midi(clip) = &mut region.content else {
+                unreachable!()
+            };
+            clip.add(MidiNote::new(
+                480,
+                480,
+                NoteNumber(60 + i as u8),
+                Velocity(100),
+            ));
+        }
+
+        // From half a beat in to the middle of bar 3, leaving a gap at the
+        // end; both notes sit half a beat into their regions
+        let id = timeline
+            .consolidate(track, range(12000, 240000), &conv)
+            .unwrap();
+        let region = timeline.get_region(id).unwrap();
+        assert_eq!(region.range(), range(12000, 240000));
+        let RegionContent::Midi(clip) = &region.content else {
+            panic!("expected MIDI content");
+        };
+        let notes: Vec<_> = clip.notes().iter().map(|n| (n.start, n.note.0)).collect();
+        assert_eq!(notes, vec![(0, 60), (3840, 61)]);
+        assert_eq!(timeline.tracks[0].regions.len(), 2);
+    }
+}
@@ -0,0 +1,330 @@
+//! What a region plays, and MIDI region editing
+
+use crate::{
+    AudioSourceId, ConsolidatedContent, Region, RegionId, TimeBase, Timeline, TimelineError,
+    TimelineEvent, TrackId, TrackType,
+};
+use koto_core::{MidiClip, MidiNote, SamplePosition, TimeConverter, TICKS_PER_QUARTER_NOTE};
+use serde::{Deserialize, Serialize};
+
+/// Content played by a region
+///
+/// The region's `source_offset` says where in the content it starts, for
+/// audio and MIDI alike.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum RegionContent {
+    #[default]
+    Empty,
+    Audio {
+        source: AudioSourceId,
+    },
+    /// Notes positioned in ticks from the start of the content
+    Midi(MidiClip),
+    /// Audio regions joined by [`Timeline::consolidate`]
+    Consolidated(ConsolidatedContent),
+}
+
+impl RegionContent {
+    pub fn is_midi(&self) -> bool {
+        matches!(self, RegionContent::Midi(_))
+    }
+
+    /// Audio sources played, including those of consolidated parts
+    pub fn source_ids(&self) -> Vec<AudioSourceId> {
+        match self {
+            RegionContent::Audio { source } => vec![*source],
+            RegionContent::Consolidated(content) => content
+                .parts
+                .iter()
+                .flat_map(|p| p.content.source_ids())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether this plays `source`, directly or through a consolidated part
+    pub fn uses_source(&self, source: AudioSourceId) -> bool {
+        match self {
+            RegionContent::Audio { source: s } => *s == source,
+            RegionContent::Consolidated(content) => {
+                content.parts.iter().any(|p| p.content.uses_source(source))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Notes of `clip` divided at `cut` ticks
+///
+/// Notes crossing the cut are truncated into the left part, or with
+/// `split_notes` also continued at the start of the right part. The right
+/// part is rebased to start at tick 0.
+fn split_clip(clip: &MidiClip, cut: i64, split_notes: bool) -> (MidiClip, MidiClip) {
+    let (mut left, mut right) = (MidiClip::new(), MidiClip::new());
+    for note in clip.notes() {
+        if note.start >= cut {
+            right.add(MidiNote {
+                start: note.start - cut,
+                ..*note
+            });
+            continue;
+        }
+        left.add(MidiNote {
+            length: note.length.min(cut - note.start),
+            ..*note
+        });
+        if split_notes && note.end() > cut {
+            right.add(MidiNote {
+                start: 0,
+                length: note.end() - cut,
+                ..*note
+            });
+        }
+    }
+    (left, right)
+}
+
+/// Notes of a region that start in its visible window, rebased so tick 0 is
+/// the region start
+pub(crate) fn visible_notes(region: &Region, converter: &TimeConverter) -> Vec<MidiNote> {
+    let RegionContent::Midi(clip) = &region.content else {
+        return Vec::new();
+    };
+    let from = converter.samples_to_ticks(region.source_offset);
+    let to = converter.samples_to_ticks(SamplePosition(region.source_offset.0 + region.length.0));
+    clip.notes()
+        .iter()
+        .filter(|n| n.start >= from && n.start < to)
+        .map(|n| MidiNote {
+            start: n.start - from,
+            ..*n
+        })
+        .collect()
+}
+
+impl Timeline {
+    /// Add an empty MIDI region `bars` long at `start`
+    pub fn create_midi_region(
+        &mut self,
+        track: TrackId,
+        start: SamplePosition,
+        bars: u32,
+        converter: &TimeConverter,
+    ) -> Result<RegionId, TimelineError> {
+        let (track_type, color) = self
+            .get_track(track)
+            .map(|t| (t.track_type, t.color))
+            .ok_or(TimelineError::TrackNotFound(track))?;
+        if !track_type.accepts_regions_from(TrackType::Midi) {
+            return Err(TimelineError::IncompatibleTrack {
+                from: TrackType::Midi,
+                to: track_type,
+            });
+        }
+
+        let beats = bars as i64 * converter.time_signature().beats_per_bar() as i64;
+        let length = converter.ticks_to_samples(beats * TICKS_PER_QUARTER_NOTE as i64);
+        let id = self.new_region_id();
+        let mut region = Region::new(id, track, start, length);
+        region.color = color;
+        region.content = RegionContent::Midi(MidiClip::new());
+        region.set_time_base(TimeBase::Musical, converter);
+        self.add_region(region)?;
+        Ok(id)
+    }
+
+    /// Split a MIDI region at `position`, dividing its notes between the
+    /// halves
+    ///
+    /// A note crossing the cut is truncated into the left half, or with
+    /// `split_notes` continued as a second note in the right half. Unlike
+    /// [`split_region`](Self::split_region), which leaves the notes in both
+    /// halves and hides them, the halves own separate notes afterwards.
+    pub fn split_midi_region(
+        &mut self,
+        id: RegionId,
+        position: SamplePosition,
+        converter: &TimeConverter,
+        split_notes: bool,
+    ) -> Result<(RegionId, RegionId), TimelineError> {
+        self.check_split(id, position)?;
+        let region = self
+            .get_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let RegionContent::Midi(clip) = &region.content else {
+            return Err(TimelineError::NotMidi(id));
+        };
+        let cut = converter.samples_to_ticks(SamplePosition(
+            position.0 - region.start.0 + region.source_offset.0,
+        ));
+        let (left_clip, right_clip) = split_clip(clip, cut, split_notes);
+
+        let (left, right) = self.split_region(id, position)?;
+        if let Some((t, r)) = self.locate_region(left) {
+            self.tracks[t].regions[r].content = RegionContent::Midi(left_clip);
+        }
+        if let Some((t, r)) = self.locate_region(right) {
+            let region = &mut self.tracks[t].regions[r];
+            region.content = RegionContent::Midi(right_clip);
+            region.source_offset = SamplePosition::ZERO;
+        }
+        self.emit(TimelineEvent::RegionChanged(left));
+        self.emit(TimelineEvent::RegionChanged(right));
+        Ok((left, right))
+    }
+
+    /// Join two MIDI regions on the same track into the left one, spanning
+    /// from the left start to the right end
+    ///
+    /// Only notes visible in each region are kept, at the same places on
+    /// the timeline. The right region is removed.
+    pub fn merge_midi_regions(
+        &mut self,
+        left: RegionId,
+        right: RegionId,
+        converter: &TimeConverter,
+    ) -> Result<RegionId, TimelineError> {
+        self.check_editable(left)?;
+        self.check_editable(right)?;
+        let (l, r) = match (self.get_region(left), self.get_region(right)) {
+            (Some(l), Some(r)) => (l, r),
+            (None, _) => return Err(TimelineError::RegionNotFound(left)),
+            (_, None) => return Err(TimelineError::RegionNotFound(right)),
+        };
+        for region in [l, r] {
+            if !region.content.is_midi() {
+                return Err(TimelineError::NotMidi(region.id));
+            }
+        }
+        if l.track_id != r.track_id || r.start < l.end() {
+            return Err(TimelineError::InvalidMerge { left, right });
+        }
+
+        let shift = converter.samples_to_ticks(SamplePosition(r.start.0 - l.start.0));
+        let mut clip = MidiClip::new();
+        for note in visible_notes(l, converter) {
+            clip.add(note);
+        }
+        for note in visible_notes(r, converter) {
+            clip.add(MidiNote {
+                start: note.start + shift,
+                ..note
+            });
+        }
+        let end = r.end();
+
+        self.take_region(right);
+        if let Some((t, i)) = self.locate_region(left) {
+            let region = &mut self.tracks[t].regions[i];
+            region.length = SamplePosition(end.0 - region.start.0);
+            region.source_offset = SamplePosition::ZERO;
+            region.content = RegionContent::Midi(clip);
+        }
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionResized(left));
+        self.emit(TimelineEvent::RegionChanged(left));
+        Ok(left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::{NoteNumber, SampleRate, Tempo, TimeSignature, Velocity};
+
+    /// 120 BPM at 48 kHz: 24000 samples per beat
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    fn note(start: i64, length: i64) -> MidiNote {
+        MidiNote::new(start, length, NoteNumber(60), Velocity(100))
+    }
+
+    fn notes(timeline: &Timeline, id: RegionId) -> Vec<(i64, i64)> {
+        match &timeline.get_region(id).unwrap().content {
+            RegionContent::Midi(clip) => clip.notes().iter().map(|n| (n.start, n.length)).collect(),
+            _ => panic!("not a MIDI region"),
+        }
+    }
+
+    /// A one-bar MIDI region at zero with notes on beats 1-3, the second
+    /// crossing beat 3
+    fn setup() -> (Timeline, RegionId) {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Keys", TrackType::Instrument);
+        let id = timeline
+            .create_midi_region(track, SamplePosition(0), 1, &converter())
+            .unwrap();
+        let region = &mut timeline.tracks[0].regions[0];
+        assert_eq!(region.length, SamplePosition(96000));
+        let RegionContent::Midi(clip) = &mut region.content else {
+            unreachable!()
+        };
+        clip.add(note(0, 480));
+        clip.add(note(960, 1440));
+        clip.add(note(2400, 480));
+        (timeline, id)
+    }
+
+    #[test]
+    fn test_split_midi_region() {
+        // Cut at beat 3 (tick 1920)
+        let (mut timeline, id) = setup();
+        let (left, right) = timeline
+            .split_midi_region(id, SamplePosition(48000), &converter(), false)
+            .unwrap();
+        assert_eq!(notes(&timeline, left), vec![(0, 480), (960, 960)]);
+        assert_eq!(notes(&timeline, right), vec![(480, 480)]);
+        assert_eq!(
+            timeline.get_region(right).unwrap().source_offset,
+            SamplePosition::ZERO
+        );
+
+        let (mut timeline, id) = setup();
+        let (_, right) = timeline
+            .split_midi_region(id, SamplePosition(48000), &converter(), true)
+            .unwrap();
+        assert_eq!(notes(&timeline, right), vec![(0, 480), (480, 480)]);
+    }
+
+    #[test]
+    fn test_merge_midi_regions() {
+        let (mut timeline, id) = setup();
+        let conv = converter();
+        let (left, right) = timeline
+            .split_midi_region(id, SamplePosition(48000), &conv, true)
+            .unwrap();
+        let merged = timeline.merge_midi_regions(left, right, &conv).unwrap();
+        assert_eq!(merged, left);
+        assert!(timeline.get_region(right).is_none());
+        assert_eq!(
+            timeline.get_region(left).unwrap().length,
+            SamplePosition(96000)
+        );
+        assert_eq!(
+            notes(&timeline, left),
+            vec![(0, 480), (960, 960), (1920, 480), (2400, 480)]
+        );
+
+        let audio = timeline.add_track("Audio", TrackType::Audio);
+        assert_eq!(
+            timeline.create_midi_region(audio, SamplePosition(0), 1, &conv),
+            Err(TimelineError::IncompatibleTrack {
+                from: TrackType::Midi,
+                to: TrackType::Audio
+            })
+        );
+    }
+
+    #[test]
+    fn test_region_without_content_loads() {
+        let json = r#"{"id":1,"name":"Old","start":0,"length":100,"track_id":0,"color":0}"#;
+        let region: Region = serde_json::from_str(json).unwrap();
+        assert_eq!(region.content, RegionContent::Empty);
+    }
+}
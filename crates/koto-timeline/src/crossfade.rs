@@ -0,0 +1,256 @@
+//! Crossfades between neighbouring audio regions
+
+use crate::{Region, RegionId, Timeline, TimelineError, TimelineEvent, TrackId, TrackType};
+use koto_core::{FadeCurve, SamplePosition};
+use serde::{Deserialize, Serialize};
+
+/// Crossfade between two regions on the same track
+///
+/// The fade is centred on the junction of the regions, or on the middle of
+/// their overlap if they overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crossfade {
+    pub left: RegionId,
+    pub right: RegionId,
+    pub length: SamplePosition,
+    pub curve: FadeCurve,
+}
+
+impl Crossfade {
+    /// Whether the crossfade involves `region`
+    pub fn involves(&self, region: RegionId) -> bool {
+        self.left == region || self.right == region
+    }
+}
+
+/// Whether two regions can be crossfaded, `left` first
+fn can_crossfade(left: &Region, right: &Region) -> bool {
+    left.start < right.start && left.end() >= right.start && left.end() < right.end()
+}
+
+/// Longest crossfade that fits within both regions
+fn max_crossfade(left: &Region, right: &Region) -> SamplePosition {
+    SamplePosition(left.length.0.min(right.length.0))
+}
+
+impl Timeline {
+    /// Crossfade two adjacent or overlapping audio regions on the same
+    /// track
+    ///
+    /// The length is clamped to fit both regions. Replaces any existing
+    /// crossfade between the pair and returns the new one.
+    pub fn create_crossfade(
+        &mut self,
+        left: RegionId,
+        right: RegionId,
+        length: SamplePosition,
+    ) -> Result<Crossfade, TimelineError> {
+        let (lt, l) = self
+            .locate_region(left)
+            .ok_or(TimelineError::RegionNotFound(left))?;
+        let (rt, r) = self
+            .locate_region(right)
+            .ok_or(TimelineError::RegionNotFound(right))?;
+        let invalid = TimelineError::InvalidCrossfade { left, right };
+        if lt != rt {
+            return Err(invalid);
+        }
+        let track = &mut self.tracks[lt];
+        let (left_region, right_region) = (&track.regions[l], &track.regions[r]);
+        if track.track_type != TrackType::Audio || !can_crossfade(left_region, right_region) {
+            return Err(invalid);
+        }
+
+        let crossfade = Crossfade {
+            left,
+            right,
+            length: length.clamp(SamplePosition(1), max_crossfade(left_region, right_region)),
+            curve: FadeCurve::EqualPower,
+        };
+        track
+            .crossfades
+            .retain(|x| !(x.left == left && x.right == right));
+        track.crossfades.push(crossfade);
+        let id = track.id;
+        self.emit(TimelineEvent::CrossfadesChanged(id));
+        Ok(crossfade)
+    }
+
+    /// Remove the crossfade between two regions, returning it
+    pub fn remove_crossfade(&mut self, left: RegionId, right: RegionId) -> Option<Crossfade> {
+        let (track, crossfade) = self.tracks.iter_mut().find_map(|track| {
+            let index = track
+                .crossfades
+                .iter()
+                .position(|x| x.left == left && x.right == right)?;
+            Some((track.id, track.crossfades.remove(index)))
+        })?;
+        self.emit(TimelineEvent::CrossfadesChanged(track));
+        Some(crossfade)
+    }
+
+    /// Put back a crossfade exactly as it was, e.g. when undoing
+    pub(crate) fn restore_crossfade(&mut self, crossfade: Crossfade) {
+        self.remove_crossfade(crossfade.left, crossfade.right);
+        if let Some((t, _)) = self.locate_region(crossfade.left) {
+            self.tracks[t].crossfades.push(crossfade);
+            let id = self.tracks[t].id;
+            self.emit(TimelineEvent::CrossfadesChanged(id));
+        }
+        self.regions_changed();
+    }
+
+    /// Crossfades on `tracks`, to put back with
+    /// [`restore_crossfades`](Self::restore_crossfades) when undoing an edit
+    /// that can drop or shorten them
+    pub(crate) fn crossfades_on(&self, tracks: &[TrackId]) -> Vec<Crossfade> {
+        self.tracks
+            .iter()
+            .filter(|t| tracks.contains(&t.id))
+            .flat_map(|t| t.crossfades.iter().copied())
+            .collect()
+    }
+
+    /// Put back crossfades from [`crossfades_on`](Self::crossfades_on)
+    /// that have since been dropped or changed
+    pub(crate) fn restore_crossfades(&mut self, crossfades: &[Crossfade]) {
+        for crossfade in crossfades {
+            let unchanged = self.tracks.iter().any(|t| t.crossfades.contains(crossfade));
+            if !unchanged {
+                self.restore_crossfade(*crossfade);
+            }
+        }
+    }
+
+    /// Crossfade a newly placed region with the regions it now touches,
+    /// if automatic crossfades are on
+    pub(crate) fn auto_crossfade(&mut self, id: RegionId) {
+        let Some(length) = self.auto_crossfade_length else {
+            return;
+        };
+        let Some((t, r)) = self.locate_region(id) else {
+            return;
+        };
+        let regions = &self.tracks[t].regions;
+        let region = &regions[r];
+        let previous = regions[..r]
+            .iter()
+            .rev()
+            .find(|other| can_crossfade(other, region))
+            .map(|other| other.id);
+        let next = regions[r + 1..]
+            .iter()
+            .find(|other| can_crossfade(region, other))
+            .map(|other| other.id);
+
+        // Not audio, or the neighbour doesn't fit: just don't crossfade
+        if let Some(previous) = previous {
+            let _ = self.create_crossfade(previous, id, length);
+        }
+        if let Some(next) = next {
+            let _ = self.create_crossfade(id, next, length);
+        }
+    }
+
+    /// Drop crossfades whose regions are gone or no longer touch, and
+    /// shorten those that no longer fit
+    pub(crate) fn sync_crossfades(&mut self) {
+        let mut changed = Vec::new();
+        for track in &mut self.tracks {
+            let regions = &track.regions;
+            let before = track.crossfades.clone();
+            track.crossfades.retain_mut(|crossfade| {
+                let find = |id| regions.iter().find(|r| r.id == id);
+                let (Some(left), Some(right)) = (find(crossfade.left), find(crossfade.right))
+                else {
+                    return false;
+                };
+                if !can_crossfade(left, right) {
+                    return false;
+                }
+                crossfade.length = crossfade.length.min(max_crossfade(left, right));
+                true
+            });
+            if track.crossfades != before {
+                changed.push(TimelineEvent::CrossfadesChanged(track.id));
+            }
+        }
+        self.events.extend(changed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OverlapPolicy, RegionEdge, TrackId};
+
+    fn setup() -> (Timeline, TrackId, RegionId, RegionId) {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let mut ids = [RegionId(0); 2];
+        for (i, start) in [0, 1000].into_iter().enumerate() {
+            ids[i] = timeline.new_region_id();
+            let region = Region::new(ids[i], track, SamplePosition(start), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+        }
+        (timeline, track, ids[0], ids[1])
+    }
+
+    #[test]
+    fn test_crossfade_follows_edits() {
+        let (mut timeline, track, a, b) = setup();
+        let crossfade = timeline
+            .create_crossfade(a, b, SamplePosition(5000))
+            .unwrap();
+        assert_eq!(crossfade.length, SamplePosition(1000));
+        assert_eq!(crossfade.curve, FadeCurve::EqualPower);
+        assert_eq!(
+            timeline.create_crossfade(b, a, SamplePosition(10)),
+            Err(TimelineError::InvalidCrossfade { left: b, right: a })
+        );
+
+        // Trimming shorter shrinks the crossfade
+        timeline
+            .trim_region(b, RegionEdge::End, SamplePosition(1400))
+            .unwrap();
+        let crossfades = &timeline.get_track(track).unwrap().crossfades;
+        assert_eq!(crossfades[0].length, SamplePosition(400));
+
+        // Splitting the left region hands the crossfade to its right half
+        let (_, tail) = timeline.split_region(a, SamplePosition(500)).unwrap();
+        let crossfades = &timeline.get_track(track).unwrap().crossfades;
+        assert_eq!((crossfades[0].left, crossfades[0].right), (tail, b));
+
+        // Moving apart removes it
+        timeline
+            .move_region(b, track, SamplePosition(3000))
+            .unwrap();
+        assert!(timeline.get_track(track).unwrap().crossfades.is_empty());
+    }
+
+    #[test]
+    fn test_auto_crossfade_and_delete() {
+        let mut timeline = Timeline::new();
+        timeline.overlap_policy = OverlapPolicy::TrimExisting;
+        timeline.auto_crossfade_length = Some(SamplePosition(64));
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let a = timeline.new_region_id();
+        let region = Region::new(a, track, SamplePosition(0), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+        let b = timeline.new_region_id();
+        let region = Region::new(b, track, SamplePosition(800), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+
+        let crossfades = timeline.get_track(track).unwrap().crossfades.clone();
+        assert_eq!(crossfades.len(), 1);
+        assert_eq!((crossfades[0].left, crossfades[0].right), (a, b));
+        assert_eq!(crossfades[0].length, SamplePosition(64));
+
+        let json = serde_json::to_string(&timeline).unwrap();
+        let loaded: Timeline = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get_track(track).unwrap().crossfades, crossfades);
+
+        timeline.remove_region(a).unwrap();
+        assert!(timeline.get_track(track).unwrap().crossfades.is_empty());
+    }
+}
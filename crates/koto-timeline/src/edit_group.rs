@@ -0,0 +1,347 @@
+//! Edit groups: tracks whose regions are edited together
+
+use crate::{Region, RegionEdge, RegionId, Timeline, TimelineError, TrackId};
+use koto_core::{SamplePosition, SampleRange};
+use serde::{Deserialize, Serialize};
+
+/// Unique identifier for edit groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EditGroupId(pub u64);
+
+/// Tracks edited together, like the kit pieces of a multitracked drum take
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EditGroup {
+    pub id: EditGroupId,
+    pub track_ids: Vec<TrackId>,
+    /// Disabled groups keep their tracks but edit them one at a time
+    pub enabled: bool,
+}
+
+/// Region edit that can be applied across an edit group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupEdit {
+    /// Move the region to `new_start`; grouped regions move by the same
+    /// amount
+    Move {
+        new_start: SamplePosition,
+    },
+    /// Move one edge to `position`
+    Trim {
+        edge: RegionEdge,
+        position: SamplePosition,
+    },
+    /// Split at `position`
+    Split {
+        position: SamplePosition,
+    },
+    Delete,
+}
+
+/// What happened to one region in a grouped edit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupEditOutcome {
+    Moved(SamplePosition),
+    /// Applied position of the trimmed edge
+    Trimmed(SamplePosition),
+    /// ID of the new right half
+    Split(RegionId),
+    Deleted,
+}
+
+/// Result of a grouped edit on one track
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GroupEditResult {
+    pub track: TrackId,
+    pub region: RegionId,
+    pub outcome: GroupEditOutcome,
+}
+
+impl Timeline {
+    pub fn edit_groups(&self) -> &[EditGroup] {
+        &self.edit_groups
+    }
+
+    pub fn get_edit_group(&self, id: EditGroupId) -> Option<&EditGroup> {
+        self.edit_groups.iter().find(|g| g.id == id)
+    }
+
+    /// Group tracks for editing; the group starts enabled
+    pub fn add_edit_group(
+        &mut self,
+        track_ids: Vec<TrackId>,
+    ) -> Result<EditGroupId, TimelineError> {
+        if let Some(&missing) = track_ids.iter().find(|&&t| self.track_index(t).is_none()) {
+            return Err(TimelineError::TrackNotFound(missing));
+        }
+        let id = EditGroupId(self.next_edit_group_id);
+        self.next_edit_group_id += 1;
+        self.edit_groups.push(EditGroup {
+            id,
+            track_ids,
+            enabled: true,
+        });
+        Ok(id)
+    }
+
+    pub fn remove_edit_group(&mut self, id: EditGroupId) -> Result<EditGroup, TimelineError> {
+        let index = self
+            .edit_groups
+            .iter()
+            .position(|g| g.id == id)
+            .ok_or(TimelineError::EditGroupNotFound(id))?;
+        Ok(self.edit_groups.remove(index))
+    }
+
+    pub fn set_edit_group_enabled(
+        &mut self,
+        id: EditGroupId,
+        enabled: bool,
+    ) -> Result<(), TimelineError> {
+        let group = self
+            .edit_groups
+            .iter_mut()
+            .find(|g| g.id == id)
+            .ok_or(TimelineError::EditGroupNotFound(id))?;
+        group.enabled = enabled;
+        Ok(())
+    }
+
+    /// Tracks sharing an enabled edit group with `track`, excluding itself
+    pub fn grouped_tracks(&self, track: TrackId) -> Vec<TrackId> {
+        let mut tracks = Vec::new();
+        for group in self
+            .edit_groups
+            .iter()
+            .filter(|g| g.enabled && g.track_ids.contains(&track))
+        {
+            for &other in &group.track_ids {
+                if other != track && !tracks.contains(&other) {
+                    tracks.push(other);
+                }
+            }
+        }
+        tracks
+    }
+
+    /// Apply an edit to a region and, with `apply_to_group`, to the
+    /// corresponding regions on its grouped tracks
+    ///
+    /// A grouped track's corresponding region is the one overlapping the
+    /// edited region the most; for a split it must contain the split point.
+    /// Tracks with no such region are left out. If the edit fails on any
+    /// region, everything is rolled back and that error returned. Results
+    /// come back in order, the edited region first.
+    pub fn edit_region(
+        &mut self,
+        region: RegionId,
+        edit: GroupEdit,
+        apply_to_group: bool,
+    ) -> Result<Vec<GroupEditResult>, TimelineError> {
+        let primary = self
+            .get_region(region)
+            .ok_or(TimelineError::RegionNotFound(region))?;
+        let (track, original) = (primary.track_id, primary.clone());
+        let match_range = match edit {
+            GroupEdit::Split { position } => {
+                SampleRange::new(position, SamplePosition(position.0 + 1))
+            }
+            _ => original.range(),
+        };
+
+        let mut targets = vec![(track, region)];
+        if apply_to_group {
+            for other in self.grouped_tracks(track) {
+                if let Some(id) = self.corresponding_region(other, match_range) {
+                    targets.push((other, id));
+                }
+            }
+        }
+
+        let tracks = self.tracks.clone();
+        let selection = self.selection.clone();
+        let next_region_id = self.next_region_id;
+        let events = self.events.len();
+        let mut results = Vec::with_capacity(targets.len());
+        for (track, id) in targets {
+            match self.apply_group_edit(id, &original, edit) {
+                Ok(outcome) => results.push(GroupEditResult {
+                    track,
+                    region: id,
+                    outcome,
+                }),
+                Err(err) => {
+                    self.tracks = tracks;
+                    self.selection = selection;
+                    self.next_region_id = next_region_id;
+                    self.events.truncate(events);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn corresponding_region(&self, track: TrackId, range: SampleRange) -> Option<RegionId> {
+        let overlap = |start: SamplePosition, end: SamplePosition| {
+            end.min(range.end).0 - start.max(range.start).0
+        };
+        self.get_track(track)?
+            .regions_in_range(range)
+            .max_by_key(|r| (overlap(r.start, r.end()), std::cmp::Reverse(r.start)))
+            .map(|r| r.id)
+    }
+
+    fn apply_group_edit(
+        &mut self,
+        id: RegionId,
+        original: &Region,
+        edit: GroupEdit,
+    ) -> Result<GroupEditOutcome, TimelineError> {
+        match edit {
+            GroupEdit::Move { new_start } => {
+                let region = self
+                    .get_region(id)
+                    .ok_or(TimelineError::RegionNotFound(id))?;
+                let (track, start) = (region.track_id, region.start);
+                let new_start = SamplePosition(start.0 + new_start.0 - original.start.0);
+                self.move_region(id, track, new_start)?;
+                let applied = self.get_region(id).map_or(new_start, |r| r.start);
+                Ok(GroupEditOutcome::Moved(applied))
+            }
+            GroupEdit::Trim { edge, position } => self
+                .trim_region(id, edge, position)
+                .map(GroupEditOutcome::Trimmed),
+            GroupEdit::Split { position } => self
+                .split_region(id, position)
+                .map(|(_, right)| GroupEditOutcome::Split(right)),
+            GroupEdit::Delete => self.remove_region(id).map(|_| GroupEditOutcome::Deleted),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrackType;
+
+    /// Kick, snare and overheads with a region each at 1000..3000
+    fn drums() -> (Timeline, Vec<TrackId>, Vec<RegionId>) {
+        let mut timeline = Timeline::new();
+        let mut tracks = Vec::new();
+        let mut regions = Vec::new();
+        for name in ["Kick", "Snare", "OH"] {
+            let track = timeline.add_track(name, TrackType::Audio);
+            let id = timeline.new_region_id();
+            timeline
+                .add_region(Region::new(
+                    id,
+                    track,
+                    SamplePosition(1000),
+                    SamplePosition(2000),
+                ))
+                .unwrap();
+            tracks.push(track);
+            regions.push(id);
+        }
+        timeline.add_edit_group(tracks.clone()).unwrap();
+        (timeline, tracks, regions)
+    }
+
+    #[test]
+    fn test_grouped_edits_follow_on_linked_tracks() {
+        let (mut timeline, tracks, regions) = drums();
+
+        let results = timeline
+            .edit_region(
+                regions[0],
+                GroupEdit::Move {
+                    new_start: SamplePosition(1500),
+                },
+                true,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1].track, tracks[1]);
+        for id in &regions {
+            assert_eq!(
+                timeline.get_region(*id).unwrap().start,
+                SamplePosition(1500)
+            );
+        }
+
+        let results = timeline
+            .edit_region(
+                regions[1],
+                GroupEdit::Split {
+                    position: SamplePosition(2500),
+                },
+                true,
+            )
+            .unwrap();
+        let split: Vec<_> = results.iter().map(|r| r.track).collect();
+        assert_eq!(split, [tracks[1], tracks[0], tracks[2]]);
+        for track in &tracks {
+            assert_eq!(timeline.get_track(*track).unwrap().regions.len(), 2);
+        }
+
+        // Without the flag, or with the group disabled, only one track changes
+        timeline
+            .edit_region(regions[0], GroupEdit::Delete, false)
+            .unwrap();
+        assert!(timeline.get_region(regions[1]).is_some());
+        let group = timeline.edit_groups()[0].id;
+        timeline.set_edit_group_enabled(group, false).unwrap();
+        let results = timeline
+            .edit_region(
+                regions[1],
+                GroupEdit::Trim {
+                    edge: RegionEdge::Start,
+                    position: SamplePosition(2000),
+                },
+                true,
+            )
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            timeline.get_region(regions[2]).unwrap().start,
+            SamplePosition(1500)
+        );
+    }
+
+    #[test]
+    fn test_grouped_edit_rolls_back_on_locked_member() {
+        let (mut timeline, _, regions) = drums();
+        timeline.set_region_locked(regions[2], true).unwrap();
+        timeline.take_events();
+
+        let result = timeline.edit_region(
+            regions[0],
+            GroupEdit::Trim {
+                edge: RegionEdge::End,
+                position: SamplePosition(2000),
+            },
+            true,
+        );
+        assert_eq!(result, Err(TimelineError::RegionLocked(regions[2])));
+        for id in &regions {
+            assert_eq!(
+                timeline.get_region(*id).unwrap().end(),
+                SamplePosition(3000)
+            );
+        }
+        assert!(timeline.take_events().is_empty());
+
+        // Halves selected by a split rolled back are deselected again
+        timeline.selection.add_region(regions[0]);
+        let before = timeline.selection.clone();
+        let result = timeline.edit_region(
+            regions[0],
+            GroupEdit::Split {
+                position: SamplePosition(2000),
+            },
+            true,
+        );
+        assert_eq!(result, Err(TimelineError::RegionLocked(regions[2])));
+        assert_eq!(timeline.selection, before);
+    }
+}
@@ -0,0 +1,361 @@
+//! Change notifications, so views only redo what an edit touched
+
+use crate::{Region, RegionId, Timeline, Track, TrackId};
+use std::collections::{HashMap, HashSet};
+
+/// Something about the timeline that changed
+///
+/// Edits made through `Timeline` methods queue events, which the UI drains
+/// once per frame with [`Timeline::take_events`]. Editing the public
+/// fields directly doesn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineEvent {
+    TrackAdded(TrackId),
+    TrackRemoved(TrackId),
+    /// The track's position in the track list changed
+    TrackMoved(TrackId),
+    /// Name, color, lock or freeze state
+    TrackChanged(TrackId),
+    /// Record arm turned on or off
+    ArmChanged(TrackId),
+    RegionAdded(RegionId),
+    RegionRemoved(RegionId),
+    /// Start or track changed, length unchanged
+    RegionMoved(RegionId),
+    /// An edge moved; the start may have too
+    RegionResized(RegionId),
+    /// Name, color, lock state or content
+    RegionChanged(RegionId),
+    CrossfadesChanged(TrackId),
+    SectionsChanged,
+    SourcesChanged,
+}
+
+impl Timeline {
+    /// Events queued since the last call, oldest first
+    pub fn take_events(&mut self) -> Vec<TimelineEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Whether events are queued for the next [`take_events`](Self::take_events)
+    pub fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    pub(crate) fn emit(&mut self, event: TimelineEvent) {
+        self.events.push(event);
+    }
+
+    /// Swap in a saved copy of a track with the same ID, e.g. when undoing,
+    /// and return the current one
+    pub fn replace_track(&mut self, track: Track) -> Option<Track> {
+        let t = self.track_index(track.id)?;
+        let crossfades_changed = self.tracks[t].crossfades != track.crossfades;
+        let old = std::mem::replace(&mut self.tracks[t], track);
+        self.emit_region_diff(t, &old.regions);
+        if crossfades_changed {
+            self.emit(TimelineEvent::CrossfadesChanged(old.id));
+        }
+        Some(old)
+    }
+
+    /// Queue events for how a track's regions differ from `before`
+    pub(crate) fn emit_region_diff(&mut self, track: usize, before: &[Region]) {
+        let old: HashMap<RegionId, &Region> = before.iter().map(|r| (r.id, r)).collect();
+        let mut events = Vec::new();
+        for region in &self.tracks[track].regions {
+            let event = match old.get(&region.id) {
+                None => TimelineEvent::RegionAdded(region.id),
+                Some(&old) if old == region => continue,
+                Some(old)
+                    if old.length != region.length || old.source_offset != region.source_offset =>
+                {
+                    TimelineEvent::RegionResized(region.id)
+                }
+                Some(old) if old.start != region.start => TimelineEvent::RegionMoved(region.id),
+                Some(_) => TimelineEvent::RegionChanged(region.id),
+            };
+            events.push(event);
+        }
+        let current: HashSet<RegionId> = self.tracks[track].regions.iter().map(|r| r.id).collect();
+        events.extend(
+            before
+                .iter()
+                .filter(|r| !current.contains(&r.id))
+                .map(|r| TimelineEvent::RegionRemoved(r.id)),
+        );
+        self.events.extend(events);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioSourceId, OverlapPolicy, RegionContent, RegionEdge, RippleEdit, TrackType};
+    use koto_core::{
+        ChannelCount, SamplePosition, SampleRange, SampleRate, Tempo, TimeConverter, TimeSignature,
+    };
+    use TimelineEvent::*;
+
+    fn setup() -> (Timeline, TrackId, TrackId, RegionId) {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, a, SamplePosition(1000), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![TrackAdded(a), TrackAdded(b), RegionAdded(id)]
+        );
+        (timeline, a, b, id)
+    }
+
+    fn range(start: i64, end: i64) -> SampleRange {
+        SampleRange::new(SamplePosition(start), SamplePosition(end))
+    }
+
+    /// Add a region on `track` and drop the event
+    fn add_region(timeline: &mut Timeline, track: TrackId, start: i64, length: i64) -> RegionId {
+        let id = timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+        timeline.add_region(region).unwrap();
+        timeline.take_events();
+        id
+    }
+
+    fn add_source(timeline: &mut Timeline, path: &str) -> AudioSourceId {
+        timeline.add_source(
+            path,
+            SampleRate(48000),
+            ChannelCount(2),
+            SamplePosition(48000),
+        )
+    }
+
+    #[test]
+    fn test_track_and_region_edits_emit_events() {
+        let (mut timeline, a, b, id) = setup();
+
+        timeline.move_track(b, 0).unwrap();
+        timeline.move_track(b, 0).unwrap();
+        timeline.rename_track(a, "Drums").unwrap();
+        timeline.set_track_locked(a, false).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![TrackMoved(b), TrackChanged(a), TrackChanged(a)]
+        );
+
+        timeline.move_region(id, b, SamplePosition(0)).unwrap();
+        assert_eq!(timeline.take_events(), vec![RegionMoved(id)]);
+        timeline
+            .trim_region(id, RegionEdge::End, SamplePosition(800))
+            .unwrap();
+        assert_eq!(timeline.take_events(), vec![RegionResized(id)]);
+        let (_, right) = timeline.split_region(id, SamplePosition(400)).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionResized(id), RegionAdded(right)]
+        );
+        let copy = timeline.duplicate_region(right).unwrap();
+        assert_eq!(timeline.take_events(), vec![RegionAdded(copy)]);
+        timeline.rename_region(copy, "Fill").unwrap();
+        timeline.set_region_locked(copy, true).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionChanged(copy), RegionChanged(copy)]
+        );
+        timeline.remove_region(right).unwrap();
+        assert_eq!(timeline.take_events(), vec![RegionRemoved(right)]);
+
+        timeline.remove_track(a);
+        assert_eq!(timeline.take_events(), vec![TrackRemoved(a)]);
+        // Failed edits change nothing and say nothing
+        assert!(timeline.remove_region(copy).is_err());
+        assert!(timeline.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_compound_edits_emit_events() {
+        let (mut timeline, a, _, id) = setup();
+        timeline.overlap_policy = OverlapPolicy::TrimExisting;
+        timeline.auto_crossfade_length = None;
+
+        // Placing over the end of the region trims it
+        let new = timeline.new_region_id();
+        let region = Region::new(new, a, SamplePosition(1500), SamplePosition(1000));
+        let report = timeline.add_region(region).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionResized(id), RegionAdded(new)]
+        );
+        timeline.revert_overlaps(&report);
+        timeline.take_region(new);
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionResized(id), RegionRemoved(new)]
+        );
+
+        // A ripple delete through the middle cuts the region in two
+        let range = SampleRange::new(SamplePosition(1200), SamplePosition(1400));
+        timeline.ripple(RippleEdit::Delete(range), None).unwrap();
+        let events = timeline.take_events();
+        let tail = timeline.tracks[0].regions[1].id;
+        assert_eq!(events, vec![RegionResized(id), RegionAdded(tail)]);
+        let insert = RippleEdit::Insert {
+            position: SamplePosition(0),
+            length: SamplePosition(100),
+        };
+        timeline.ripple(insert, None).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionMoved(id), RegionMoved(tail)]
+        );
+
+        let mut before = timeline.tracks[0].clone();
+        before.regions.retain(|r| r.id != tail);
+        let saved = timeline.replace_track(before).unwrap();
+        assert_eq!(timeline.take_events(), vec![RegionRemoved(tail)]);
+        timeline.replace_track(saved);
+        assert_eq!(timeline.take_events(), vec![RegionAdded(tail)]);
+    }
+
+    #[test]
+    fn test_section_edits_emit_events() {
+        let (mut timeline, _, _, id) = setup();
+
+        let verse = timeline.add_section("Verse", range(0, 3000), 0).unwrap();
+        assert_eq!(timeline.take_events(), vec![SectionsChanged]);
+        timeline.resize_section(verse, range(500, 2500)).unwrap();
+        assert_eq!(timeline.take_events(), vec![SectionsChanged]);
+        // The copy's regions arrive before the section itself
+        let copy = timeline.duplicate_section(verse).unwrap();
+        let copied = timeline.tracks[0].regions[1].id;
+        assert_ne!(copied, id);
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionAdded(copied), SectionsChanged]
+        );
+        timeline.remove_section(copy).unwrap();
+        assert_eq!(timeline.take_events(), vec![SectionsChanged]);
+
+        assert!(timeline.add_section("Chorus", range(0, 1000), 0).is_err());
+        assert!(timeline.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_source_edits_emit_events() {
+        let (mut timeline, _, _, id) = setup();
+
+        let source = add_source(&mut timeline, "/audio/kick.wav");
+        assert_eq!(timeline.take_events(), vec![SourcesChanged]);
+        // Adding a file already in the pool changes nothing
+        add_source(&mut timeline, "/audio/kick.wav");
+        assert!(timeline.take_events().is_empty());
+        timeline
+            .set_source_path(source, "/audio/kick2.wav")
+            .unwrap();
+        assert_eq!(timeline.take_events(), vec![SourcesChanged]);
+
+        timeline.tracks[0].regions[0].content = RegionContent::Audio { source };
+        assert!(timeline.remove_unused_sources().is_empty());
+        assert!(timeline.take_events().is_empty());
+        timeline.remove_source(source, true).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![RegionRemoved(id), SourcesChanged]
+        );
+    }
+
+    #[test]
+    fn test_crossfade_edits_emit_events() {
+        let (mut timeline, a, b, id) = setup();
+        timeline.auto_crossfade_length = None;
+        let right = add_region(&mut timeline, a, 2000, 1000);
+        let other = add_region(&mut timeline, b, 2000, 1000);
+
+        timeline
+            .create_crossfade(id, right, SamplePosition(100))
+            .unwrap();
+        assert_eq!(timeline.take_events(), vec![CrossfadesChanged(a)]);
+        // Replacing the crossfade says so once
+        timeline
+            .create_crossfade(id, right, SamplePosition(200))
+            .unwrap();
+        assert_eq!(timeline.take_events(), vec![CrossfadesChanged(a)]);
+        timeline.remove_crossfade(id, right).unwrap();
+        assert_eq!(timeline.take_events(), vec![CrossfadesChanged(a)]);
+
+        assert!(timeline.remove_crossfade(id, right).is_none());
+        assert!(timeline
+            .create_crossfade(id, other, SamplePosition(100))
+            .is_err());
+        assert!(timeline.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_freeze_emits_events() {
+        let (mut timeline, a, _, id) = setup();
+        let render = add_source(&mut timeline, "/renders/a.wav");
+        timeline.take_events();
+
+        timeline.freeze_track(a, render).unwrap();
+        assert_eq!(timeline.take_events(), vec![TrackChanged(a)]);
+        assert!(timeline.remove_region(id).is_err());
+        assert!(timeline.freeze_track(a, AudioSourceId(9)).is_err());
+        assert!(timeline.take_events().is_empty());
+
+        // The render leaves the pool before the track is unfrozen
+        timeline.unfreeze_track(a, true).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![SourcesChanged, TrackChanged(a)]
+        );
+    }
+
+    #[test]
+    fn test_track_color_emits_events() {
+        let (mut timeline, a, _, id) = setup();
+        add_region(&mut timeline, a, 3000, 1000);
+        timeline.tracks[0].regions[1].color = 0x123456;
+
+        // Only regions still wearing the track color follow it
+        timeline.set_track_color(a, 0xff0000, true).unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![TrackChanged(a), RegionChanged(id)]
+        );
+        timeline.set_track_color(a, 0x00ff00, false).unwrap();
+        assert_eq!(timeline.take_events(), vec![TrackChanged(a)]);
+
+        assert!(timeline.set_track_color(TrackId(9), 0, true).is_err());
+        assert!(timeline.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_consolidate_emits_events() {
+        let (mut timeline, a, _, id) = setup();
+        let covered = add_region(&mut timeline, a, 2500, 500);
+        let converter = TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        );
+
+        // Overlapped regions are trimmed or removed before the new one lands
+        let merged = timeline
+            .consolidate(a, range(1500, 3500), &converter)
+            .unwrap();
+        assert_eq!(
+            timeline.take_events(),
+            vec![
+                RegionResized(id),
+                RegionRemoved(covered),
+                RegionAdded(merged)
+            ]
+        );
+
+        assert!(timeline.consolidate(a, range(0, 0), &converter).is_err());
+        assert!(timeline.take_events().is_empty());
+    }
+}
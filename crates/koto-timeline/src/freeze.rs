@@ -0,0 +1,166 @@
+//! Track freezing: playing rendered audio in place of a track's regions
+
+use crate::{AudioSource, AudioSourceId, Timeline, TimelineError, TimelineEvent, TrackId};
+
+impl Timeline {
+    /// Freeze a track to play `source`, rendered from its regions
+    ///
+    /// While frozen, every edit to the track's regions fails with
+    /// [`TimelineError::TrackFrozen`]. Freezing again replaces the source.
+    pub fn freeze_track(
+        &mut self,
+        id: TrackId,
+        source: AudioSourceId,
+    ) -> Result<(), TimelineError> {
+        if self.get_source(source).is_none() {
+            return Err(TimelineError::SourceNotFound(source));
+        }
+        let track = self
+            .get_track_mut(id)
+            .ok_or(TimelineError::TrackNotFound(id))?;
+        track.frozen = true;
+        track.frozen_source = Some(source);
+        self.emit(TimelineEvent::TrackChanged(id));
+        Ok(())
+    }
+
+    /// Unfreeze a track, making its regions editable again
+    ///
+    /// With `delete_source` the rendered audio is also removed from the media
+    /// pool and returned; if regions still use it, the track stays frozen.
+    pub fn unfreeze_track(
+        &mut self,
+        id: TrackId,
+        delete_source: bool,
+    ) -> Result<Option<AudioSource>, TimelineError> {
+        let source = self
+            .get_track(id)
+            .ok_or(TimelineError::TrackNotFound(id))?
+            .frozen_source;
+        let removed = match source {
+            Some(source) if delete_source => Some(self.remove_source(source, false)?),
+            _ => None,
+        };
+        if let Some(track) = self.get_track_mut(id) {
+            track.frozen = false;
+            track.frozen_source = None;
+        }
+        self.emit(TimelineEvent::TrackChanged(id));
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, RegionContent, RegionEdge, RegionId, RippleEdit, TrackType};
+    use koto_core::{ChannelCount, SamplePosition, SampleRange, SampleRate, TimeConverter};
+
+    fn add_source(timeline: &mut Timeline, path: &str) -> AudioSourceId {
+        timeline.add_source(
+            path,
+            SampleRate(48000),
+            ChannelCount(2),
+            SamplePosition(48000),
+        )
+    }
+
+    /// A frozen track with one region, and an editable track beside it
+    fn setup() -> (Timeline, TrackId, TrackId, RegionId) {
+        let mut timeline = Timeline::new();
+        let frozen = timeline.add_track("Synth", TrackType::Audio);
+        let other = timeline.add_track("Other", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, frozen, SamplePosition(1000), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+        let render = add_source(&mut timeline, "/renders/synth.wav");
+        timeline.freeze_track(frozen, render).unwrap();
+        (timeline, frozen, other, id)
+    }
+
+    #[test]
+    fn test_frozen_track_refuses_edits() {
+        let (mut timeline, frozen, other, id) = setup();
+        let err = Err(TimelineError::TrackFrozen(frozen));
+
+        let new = timeline.new_region_id();
+        let region = Region::new(new, frozen, SamplePosition(5000), SamplePosition(100));
+        assert_eq!(timeline.add_region(region).map(|_| ()), err);
+        assert_eq!(timeline.remove_region(id).map(|_| ()), err);
+        assert_eq!(
+            timeline
+                .trim_region(id, RegionEdge::End, SamplePosition(1500))
+                .map(|_| ()),
+            err
+        );
+        assert_eq!(
+            timeline.split_region(id, SamplePosition(1500)).map(|_| ()),
+            err
+        );
+        assert_eq!(timeline.duplicate_region(id).map(|_| ()), err);
+        assert_eq!(
+            timeline
+                .move_region(id, other, SamplePosition(0))
+                .map(|_| ()),
+            err
+        );
+        let ripple = RippleEdit::Insert {
+            position: SamplePosition(0),
+            length: SamplePosition(100),
+        };
+        assert_eq!(timeline.ripple(ripple, None), err);
+        let range = SampleRange::new(SamplePosition(0), SamplePosition(3000));
+        let conv = TimeConverter::new(SampleRate(48000), Default::default(), Default::default());
+        assert_eq!(timeline.consolidate(frozen, range, &conv).map(|_| ()), err);
+
+        timeline.selection.add_region(id);
+        assert_eq!(timeline.nudge_selected(10).map(|_| ()), err);
+        assert_eq!(timeline.delete_selected().map(|_| ()), err);
+
+        // Moving a region from another track onto the frozen one
+        let incoming = timeline.new_region_id();
+        let region = Region::new(incoming, other, SamplePosition(0), SamplePosition(100));
+        timeline.add_region(region).unwrap();
+        assert_eq!(
+            timeline
+                .move_region(incoming, frozen, SamplePosition(0))
+                .map(|_| ()),
+            err
+        );
+
+        let region = timeline.get_region(id).unwrap();
+        assert_eq!(
+            region.range(),
+            SampleRange::new(SamplePosition(1000), SamplePosition(2000))
+        );
+        assert_eq!(timeline.tracks[0].regions.len(), 1);
+    }
+
+    #[test]
+    fn test_unfreeze() {
+        let (mut timeline, frozen, _, id) = setup();
+        let render = timeline.get_track(frozen).unwrap().frozen_source.unwrap();
+
+        // Still in use by a region, so the source can't go
+        timeline.get_track_mut(frozen).unwrap().regions[0].content =
+            RegionContent::Audio { source: render };
+        assert!(matches!(
+            timeline.unfreeze_track(frozen, true),
+            Err(TimelineError::SourceInUse { .. })
+        ));
+        assert!(timeline.get_track(frozen).unwrap().frozen);
+
+        assert_eq!(timeline.unfreeze_track(frozen, false), Ok(None));
+        assert!(timeline.get_source(render).is_some());
+        timeline
+            .trim_region(id, RegionEdge::End, SamplePosition(1500))
+            .unwrap();
+
+        timeline.get_track_mut(frozen).unwrap().regions[0].content = RegionContent::Empty;
+        timeline.freeze_track(frozen, render).unwrap();
+        let removed = timeline.unfreeze_track(frozen, true).unwrap();
+        assert_eq!(removed.map(|s| s.id), Some(render));
+        assert!(timeline.get_source(render).is_none());
+        assert_eq!(timeline.get_track(frozen).unwrap().frozen_source, None);
+    }
+}
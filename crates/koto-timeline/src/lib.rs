@@ -1,18 +1,106 @@
 //! Koto Timeline - Timeline and arrangement
 
-use koto_core::SamplePosition;
+mod arm;
+mod commands;
+mod consolidate;
+mod content;
+mod crossfade;
+mod edit_group;
+mod events;
+mod freeze;
+mod media;
+mod nudge;
+mod overlap;
+mod palette;
+mod ripple;
+mod search;
+mod section;
+mod selection;
+mod snap;
+mod stats;
+mod takes;
+mod template;
+mod time_base;
+mod validate;
+
+pub use arm::*;
+pub use commands::*;
+pub use consolidate::*;
+pub use content::*;
+pub use crossfade::*;
+pub use edit_group::*;
+pub use events::*;
+pub use media::*;
+pub use nudge::*;
+pub use overlap::*;
+pub use palette::*;
+pub use ripple::*;
+pub use search::*;
+pub use section::*;
+pub use selection::*;
+pub use snap::*;
+pub use stats::*;
+pub use takes::*;
+pub use template::*;
+pub use time_base::*;
+pub use validate::*;
+
+pub use koto_core::TrackId;
+use koto_core::{FadeCurve, SamplePosition, SampleRange};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-/// Unique identifier for tracks
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct TrackId(pub u64);
+/// Timeline errors
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TimelineError {
+    #[error("Region {0:?} not found")]
+    RegionNotFound(RegionId),
+    #[error("Cannot split region {region:?} at {position}: outside the region")]
+    SplitOutOfBounds { region: RegionId, position: i64 },
+    #[error("Track {0:?} not found")]
+    TrackNotFound(TrackId),
+    #[error("Cannot put a region from a {from:?} track on a {to:?} track")]
+    IncompatibleTrack { from: TrackType, to: TrackType },
+    #[error("Regions {left:?} and {right:?} are not neighbouring audio regions on one track")]
+    InvalidCrossfade { left: RegionId, right: RegionId },
+    #[error("Region {0:?} is locked")]
+    RegionLocked(RegionId),
+    #[error("Track {0:?} is locked")]
+    TrackLocked(TrackId),
+    #[error("Track {0:?} is frozen")]
+    TrackFrozen(TrackId),
+    #[error("Track {0:?} can't be armed for recording")]
+    NotArmable(TrackId),
+    #[error("Audio source {0:?} not found")]
+    SourceNotFound(AudioSourceId),
+    #[error("Audio source {id:?} is used by {regions} regions")]
+    SourceInUse { id: AudioSourceId, regions: usize },
+    #[error("Names can't be empty")]
+    EmptyName,
+    #[error("Region {0:?} has no MIDI content")]
+    NotMidi(RegionId),
+    #[error("Cannot merge {left:?} and {right:?}: they must be in order on one track")]
+    InvalidMerge { left: RegionId, right: RegionId },
+    #[error("Range is empty")]
+    EmptyRange,
+    #[error("Section {0:?} not found")]
+    SectionNotFound(SectionId),
+    #[error("Sections can't be empty")]
+    EmptySection,
+    #[error("Section would overlap {0:?}")]
+    SectionOverlap(SectionId),
+    #[error("Take {0:?} not found")]
+    TakeNotFound(TakeId),
+    #[error("Edit group {0:?} not found")]
+    EditGroupNotFound(EditGroupId),
+}
 
 /// Unique identifier for regions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct RegionId(pub u64);
 
 /// Track type
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TrackType {
     Audio,
     Midi,
@@ -21,8 +109,28 @@ pub enum TrackType {
     Master,
 }
 
+impl TrackType {
+    /// Whether regions from a `from` track can be placed on this track
+    pub fn accepts_regions_from(self, from: TrackType) -> bool {
+        match self {
+            TrackType::Audio => from == TrackType::Audio,
+            TrackType::Midi | TrackType::Instrument => {
+                matches!(from, TrackType::Midi | TrackType::Instrument)
+            }
+            TrackType::Bus | TrackType::Master => false,
+        }
+    }
+}
+
+/// Edge of a region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegionEdge {
+    Start,
+    End,
+}
+
 /// Audio/MIDI region
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Region {
     pub id: RegionId,
     pub name: String,
@@ -30,9 +138,50 @@ pub struct Region {
     pub length: SamplePosition,
     pub track_id: TrackId,
     pub color: u32,
+    /// What the region plays
+    #[serde(default)]
+    pub content: RegionContent,
+    /// Offset into the content where the region starts playing
+    #[serde(default)]
+    pub source_offset: SamplePosition,
+    /// Fade lengths; kept within the region by the setters and trims
+    #[serde(default)]
+    pub fade_in_length: SamplePosition,
+    #[serde(default)]
+    pub fade_out_length: SamplePosition,
+    #[serde(default)]
+    pub fade_in_curve: FadeCurve,
+    #[serde(default)]
+    pub fade_out_curve: FadeCurve,
+    #[serde(default)]
+    pub gain_db: f32,
+    /// Locked regions refuse edits
+    #[serde(default)]
+    pub locked: bool,
+    /// Repeat the first `content_length` samples of the content to fill
+    /// the region
+    #[serde(default)]
+    pub loop_enabled: bool,
+    /// Length of one repetition when looping
+    #[serde(default)]
+    pub content_length: SamplePosition,
+    /// Takes stacked by loop recording
+    #[serde(default)]
+    pub takes: Takes,
+    #[serde(default)]
+    pub time_base: TimeBase,
+    /// Start in ticks, kept for musical regions
+    #[serde(default)]
+    pub start_ticks: i64,
+    /// Length in ticks, kept for musical regions
+    #[serde(default)]
+    pub length_ticks: i64,
 }
 
 impl Region {
+    /// Create an empty region, colored like a new track with `track_id`
+    /// (see [`ColorIndex::for_track`]) until
+    /// [`Timeline::add_region`] gives it its track's color
     pub fn new(
         id: RegionId,
         track_id: TrackId,
@@ -45,13 +194,133 @@ impl Region {
             start,
             length,
             track_id,
-            color: 0x4A90D9,
+            color: ColorIndex::for_track(track_id).color(),
+            content: RegionContent::Empty,
+            source_offset: SamplePosition::ZERO,
+            fade_in_length: SamplePosition::ZERO,
+            fade_out_length: SamplePosition::ZERO,
+            fade_in_curve: FadeCurve::default(),
+            fade_out_curve: FadeCurve::default(),
+            gain_db: 0.0,
+            locked: false,
+            loop_enabled: false,
+            content_length: SamplePosition::ZERO,
+            takes: Takes::default(),
+            time_base: TimeBase::Absolute,
+            start_ticks: 0,
+            length_ticks: 0,
         }
     }
 
     pub fn end(&self) -> SamplePosition {
         SamplePosition(self.start.0 + self.length.0)
     }
+
+    pub fn range(&self) -> SampleRange {
+        SampleRange::new(self.start, self.end())
+    }
+
+    /// Whether any part of the region lies inside `range`
+    pub fn overlaps(&self, range: SampleRange) -> bool {
+        self.start < range.end && self.end() > range.start
+    }
+
+    /// Set the fade-in length, clamped to the room left by the fade-out.
+    /// Returns the applied length.
+    pub fn set_fade_in(&mut self, length: SamplePosition) -> SamplePosition {
+        let room = self.length.0 - self.fade_out_length.0;
+        self.fade_in_length = SamplePosition(length.0.clamp(0, room.max(0)));
+        self.fade_in_length
+    }
+
+    /// Set the fade-out length, clamped to the room left by the fade-in.
+    /// Returns the applied length.
+    pub fn set_fade_out(&mut self, length: SamplePosition) -> SamplePosition {
+        let room = self.length.0 - self.fade_in_length.0;
+        self.fade_out_length = SamplePosition(length.0.clamp(0, room.max(0)));
+        self.fade_out_length
+    }
+
+    /// Shorten the fades if the region no longer fits them
+    fn clamp_fades(&mut self) {
+        self.fade_in_length = SamplePosition(self.fade_in_length.0.min(self.length.0));
+        self.fade_out_length = SamplePosition(
+            self.fade_out_length
+                .0
+                .min(self.length.0 - self.fade_in_length.0),
+        );
+    }
+
+    /// Gain multiplier at `offset` samples into the region, combining the
+    /// fades and the region gain. Zero outside the region.
+    pub fn gain_at(&self, offset: SamplePosition) -> f32 {
+        let offset = offset.0;
+        if offset < 0 || offset >= self.length.0 {
+            return 0.0;
+        }
+
+        let mut gain = 10.0_f32.powf(self.gain_db / 20.0);
+        if offset < self.fade_in_length.0 {
+            gain *= self
+                .fade_in_curve
+                .gain(offset as f32 / self.fade_in_length.0 as f32);
+        }
+        let remaining = self.length.0 - offset;
+        if remaining < self.fade_out_length.0 {
+            gain *= self
+                .fade_out_curve
+                .gain(remaining as f32 / self.fade_out_length.0 as f32);
+        }
+        gain
+    }
+
+    fn loops(&self) -> bool {
+        self.loop_enabled && self.content_length.0 > 0
+    }
+
+    /// Position in the content played `region_offset` samples into the
+    /// region, wrapping around the repetition when looping
+    pub fn content_offset_at(&self, region_offset: SamplePosition) -> SamplePosition {
+        let offset = self.source_offset.0 + region_offset.0;
+        if self.loops() {
+            SamplePosition(offset.rem_euclid(self.content_length.0))
+        } else {
+            SamplePosition(offset)
+        }
+    }
+
+    /// Move the start edge, keeping the end and the content in place
+    ///
+    /// Clamped so the region stays at least one sample long and doesn't
+    /// reach before the start of the timeline, or of its source unless it
+    /// loops. Returns the applied start.
+    pub fn trim_start(&mut self, new_start: SamplePosition) -> SamplePosition {
+        let end = self.end().0;
+        let earliest = if self.loops() {
+            0
+        } else {
+            (self.start.0 - self.source_offset.0).max(0)
+        };
+        let new_start = new_start.0.clamp(earliest.min(end - 1), end - 1);
+        let delta = new_start - self.start.0;
+
+        self.source_offset = self.content_offset_at(SamplePosition(delta));
+        self.start = SamplePosition(new_start);
+        self.length = SamplePosition(end - new_start);
+        self.takes.retime(delta, self.length);
+        self.clamp_fades();
+        self.start
+    }
+
+    /// Move the end edge, clamped to at least one sample after the start.
+    /// Returns the applied end.
+    pub fn trim_end(&mut self, new_end: SamplePosition) -> SamplePosition {
+        let new_end = new_end.0.max(self.start.0 + 1);
+        self.length = SamplePosition(new_end - self.start.0);
+        self.takes.retime(0, self.length);
+        self.clamp_fades();
+        self.end()
+    }
 }
 
 /// Track in the timeline
@@ -60,12 +329,34 @@ pub struct Track {
     pub id: TrackId,
     pub name: String,
     pub track_type: TrackType,
+    /// Regions sorted by start position; add them with
+    /// [`add_region`](Self::add_region) to keep the order, and call
+    /// [`refresh_length`](Self::refresh_length) after editing them directly
     pub regions: Vec<Region>,
+    /// Crossfades between regions on this track
+    #[serde(default)]
+    pub crossfades: Vec<Crossfade>,
     pub mute: bool,
     pub solo: bool,
     pub armed: bool,
     pub height: u32,
     pub color: u32,
+    /// Locked tracks refuse edits to their regions
+    #[serde(default)]
+    pub locked: bool,
+    /// Frozen tracks play rendered audio and refuse edits to their regions;
+    /// see [`Timeline::freeze_track`]
+    #[serde(default)]
+    pub frozen: bool,
+    /// Rendered audio played while frozen
+    #[serde(default)]
+    pub frozen_source: Option<AudioSourceId>,
+    /// Folder track this track is nested under
+    #[serde(default)]
+    pub parent: Option<TrackId>,
+    /// End of the last region, kept up to date by the edit methods
+    #[serde(skip)]
+    cached_length: Option<SamplePosition>,
 }
 
 impl Track {
@@ -75,25 +366,90 @@ impl Track {
             name: name.into(),
             track_type,
             regions: Vec::new(),
+            crossfades: Vec::new(),
             mute: false,
             solo: false,
             armed: false,
             height: 80,
-            color: 0x4A90D9,
+            color: ColorIndex::for_track(id).color(),
+            locked: false,
+            frozen: false,
+            frozen_source: None,
+            parent: None,
+            cached_length: Some(SamplePosition::ZERO),
         }
     }
 
+    /// Fail if the track is locked or frozen
+    pub(crate) fn check_editable(&self) -> Result<(), TimelineError> {
+        if self.locked {
+            return Err(TimelineError::TrackLocked(self.id));
+        }
+        if self.frozen {
+            return Err(TimelineError::TrackFrozen(self.id));
+        }
+        Ok(())
+    }
+
+    /// Insert a region, keeping the regions sorted by start
     pub fn add_region(&mut self, region: Region) {
-        self.regions.push(region);
+        if let Some(length) = &mut self.cached_length {
+            *length = (*length).max(region.end());
+        }
+        let index = self.regions.partition_point(|r| r.start <= region.start);
+        self.regions.insert(index, region);
+    }
+
+    /// Regions intersecting `range`, in start order
+    ///
+    /// Regions starting at or after the range end are skipped by binary
+    /// search; earlier ones are checked for overlap, since a long region can
+    /// start well before the range.
+    pub fn regions_in_range(&self, range: SampleRange) -> impl Iterator<Item = &Region> {
+        let end = self.regions.partition_point(|r| r.start < range.end);
+        self.regions[..end]
+            .iter()
+            .filter(move |r| r.end() > range.start)
     }
 }
 
 /// The main timeline structure
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Timeline {
+    /// Tracks in display order, top to bottom. The order only changes
+    /// through the track methods (add, insert, move, remove), so iterating
+    /// is stable between edits.
     pub tracks: Vec<Track>,
+    /// How placing a region treats regions already on the track
+    #[serde(default)]
+    pub overlap_policy: OverlapPolicy,
+    /// Crossfade length applied when a trimming placement leaves regions
+    /// butted together; `None` disables automatic crossfades
+    #[serde(default)]
+    pub auto_crossfade_length: Option<SamplePosition>,
+    /// Current selection; not saved with the project
+    #[serde(skip)]
+    pub selection: Selection,
+    /// Media pool of audio files used by regions
+    #[serde(default)]
+    sources: Vec<AudioSource>,
     next_track_id: u64,
     next_region_id: u64,
+    #[serde(default)]
+    next_source_id: u64,
+    /// Arrangement sections, sorted by start
+    #[serde(default)]
+    sections: Vec<Section>,
+    #[serde(default)]
+    next_section_id: u64,
+    /// Tracks whose regions are edited together
+    #[serde(default)]
+    edit_groups: Vec<EditGroup>,
+    #[serde(default)]
+    next_edit_group_id: u64,
+    /// Changes not yet taken by [`take_events`](Self::take_events)
+    #[serde(skip)]
+    events: Vec<TimelineEvent>,
 }
 
 impl Timeline {
@@ -106,12 +462,75 @@ impl Timeline {
         let id = TrackId(self.next_track_id);
         self.next_track_id += 1;
         self.tracks.push(Track::new(id, name, track_type));
+        self.emit(TimelineEvent::TrackAdded(id));
         id
     }
 
-    /// Remove a track
-    pub fn remove_track(&mut self, id: TrackId) {
-        self.tracks.retain(|t| t.id != id);
+    /// Insert a new track at `index`, clamped to the end of the list
+    pub fn insert_track_at(
+        &mut self,
+        index: usize,
+        name: impl Into<String>,
+        track_type: TrackType,
+    ) -> TrackId {
+        let id = TrackId(self.next_track_id);
+        self.next_track_id += 1;
+        self.insert_track(index, Track::new(id, name, track_type));
+        id
+    }
+
+    /// Put an existing track back at `index`, e.g. when redoing
+    pub(crate) fn insert_track(&mut self, index: usize, track: Track) {
+        let index = index.min(self.tracks.len());
+        let id = track.id;
+        self.tracks.insert(index, track);
+        self.emit(TimelineEvent::TrackAdded(id));
+    }
+
+    /// Position of a track in display order
+    pub fn track_index(&self, id: TrackId) -> Option<usize> {
+        self.tracks.iter().position(|t| t.id == id)
+    }
+
+    /// Move a track to `new_index`, clamped to the list. Returns the index
+    /// it ended up at.
+    pub fn move_track(&mut self, id: TrackId, new_index: usize) -> Result<usize, TimelineError> {
+        let index = self
+            .track_index(id)
+            .ok_or(TimelineError::TrackNotFound(id))?;
+        let track = self.tracks.remove(index);
+        let new_index = new_index.min(self.tracks.len());
+        self.tracks.insert(new_index, track);
+        if new_index != index {
+            self.emit(TimelineEvent::TrackMoved(id));
+        }
+        Ok(new_index)
+    }
+
+    /// Remove a track, returning it
+    ///
+    /// Tracks nested under it move to the top level.
+    pub fn remove_track(&mut self, id: TrackId) -> Option<Track> {
+        let index = self.track_index(id)?;
+        let track = self.tracks.remove(index);
+        self.selection.remove_track(id);
+        let children: Vec<TrackId> = self
+            .tracks
+            .iter_mut()
+            .filter(|t| t.parent == Some(id))
+            .map(|t| {
+                t.parent = None;
+                t.id
+            })
+            .collect();
+        for child in children {
+            self.emit(TimelineEvent::TrackChanged(child));
+        }
+        for region in &track.regions {
+            self.selection.remove_region(region.id);
+        }
+        self.emit(TimelineEvent::TrackRemoved(id));
+        Some(track)
     }
 
     /// Get a track by ID
@@ -124,10 +543,683 @@ impl Timeline {
         self.tracks.iter_mut().find(|t| t.id == id)
     }
 
+    /// Regions on all tracks intersecting `range`
+    pub fn regions_in_range(&self, range: SampleRange) -> impl Iterator<Item = (TrackId, &Region)> {
+        self.tracks.iter().flat_map(move |track| {
+            track
+                .regions_in_range(range)
+                .map(move |region| (track.id, region))
+        })
+    }
+
+    /// Get a region by ID
+    pub fn get_region(&self, id: RegionId) -> Option<&Region> {
+        self.tracks
+            .iter()
+            .flat_map(|t| t.regions.iter())
+            .find(|r| r.id == id)
+    }
+
+    /// Track index and region index of a region
+    fn locate_region(&self, id: RegionId) -> Option<(usize, usize)> {
+        self.tracks.iter().enumerate().find_map(|(t, track)| {
+            track
+                .regions
+                .iter()
+                .position(|r| r.id == id)
+                .map(|r| (t, r))
+        })
+    }
+
+    /// Lock or unlock a region
+    pub fn set_region_locked(&mut self, id: RegionId, locked: bool) -> Result<(), TimelineError> {
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        self.tracks[t].regions[r].locked = locked;
+        self.emit(TimelineEvent::RegionChanged(id));
+        Ok(())
+    }
+
+    /// Lock or unlock several regions; nothing changes if any is missing
+    pub fn set_regions_locked(
+        &mut self,
+        ids: &[RegionId],
+        locked: bool,
+    ) -> Result<(), TimelineError> {
+        if let Some(&missing) = ids.iter().find(|&&id| self.get_region(id).is_none()) {
+            return Err(TimelineError::RegionNotFound(missing));
+        }
+        for &id in ids {
+            self.set_region_locked(id, locked)?;
+        }
+        Ok(())
+    }
+
+    /// Lock or unlock a track
+    pub fn set_track_locked(&mut self, id: TrackId, locked: bool) -> Result<(), TimelineError> {
+        self.get_track_mut(id)
+            .ok_or(TimelineError::TrackNotFound(id))?
+            .locked = locked;
+        self.emit(TimelineEvent::TrackChanged(id));
+        Ok(())
+    }
+
+    /// Fail if a region is locked, or its track is locked or frozen
+    pub(crate) fn check_editable(&self, id: RegionId) -> Result<(), TimelineError> {
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let track = &self.tracks[t];
+        track.check_editable()?;
+        if track.regions[r].locked {
+            return Err(TimelineError::RegionLocked(id));
+        }
+        Ok(())
+    }
+
+    /// Remove a region from whichever track holds it
+    pub fn remove_region(&mut self, id: RegionId) -> Result<Region, TimelineError> {
+        self.check_editable(id)?;
+        self.take_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))
+    }
+
+    /// Remove a region regardless of locks, for undoing
+    pub(crate) fn take_region(&mut self, id: RegionId) -> Option<Region> {
+        let region = self.detach_region(id)?;
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionRemoved(id));
+        Some(region)
+    }
+
+    /// Remove a region without syncing or events, to put it back changed
+    pub(crate) fn detach_region(&mut self, id: RegionId) -> Option<Region> {
+        let (t, r) = self.locate_region(id)?;
+        Some(self.tracks[t].regions.remove(r))
+    }
+
+    /// Put a removed region back on its track, e.g. when undoing
+    pub(crate) fn restore_region(&mut self, region: Region) {
+        let id = region.id;
+        if let Some(track) = self.get_track_mut(region.track_id) {
+            track.add_region(region);
+            self.emit(TimelineEvent::RegionAdded(id));
+        }
+    }
+
+    /// Move a region to `new_start` on `new_track`, keeping its ID
+    ///
+    /// Fails if the destination track can't hold the region's content.
+    /// Positions before zero are clamped. Regions already at the
+    /// destination are handled by the overlap policy.
+    pub fn move_region(
+        &mut self,
+        id: RegionId,
+        new_track: TrackId,
+        new_start: SamplePosition,
+    ) -> Result<OverlapReport, TimelineError> {
+        self.move_region_with(id, new_track, new_start, self.overlap_policy)
+    }
+
+    pub(crate) fn move_region_with(
+        &mut self,
+        id: RegionId,
+        new_track: TrackId,
+        new_start: SamplePosition,
+        policy: OverlapPolicy,
+    ) -> Result<OverlapReport, TimelineError> {
+        self.check_editable(id)?;
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let from = self.tracks[t].track_type;
+        let to_index = self
+            .track_index(new_track)
+            .ok_or(TimelineError::TrackNotFound(new_track))?;
+        let to = self.tracks[to_index].track_type;
+        if !to.accepts_regions_from(from) {
+            return Err(TimelineError::IncompatibleTrack { from, to });
+        }
+        let new_start = new_start.max(SamplePosition::ZERO);
+        let range = SampleRange::new(
+            new_start,
+            SamplePosition(new_start.0 + self.tracks[t].regions[r].length.0),
+        );
+        self.check_overlaps(to_index, range, id, policy)?;
+
+        let mut region = self.tracks[t].regions.remove(r);
+        region.track_id = new_track;
+        region.start = new_start;
+
+        let saved = std::mem::replace(&mut self.overlap_policy, policy);
+        let report = self.place_region(region);
+        self.overlap_policy = saved;
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionMoved(id));
+        report
+    }
+
+    /// Copy a region to just after the original on the same track,
+    /// applying the overlap policy
+    pub fn duplicate_region(&mut self, id: RegionId) -> Result<RegionId, TimelineError> {
+        self.duplicate_region_as(id, None).map(|(copy, _)| copy)
+    }
+
+    /// Duplicate, reusing `copy_id` if given so a redo recreates the same
+    /// region; returns the copy's ID and the regions changed to make room
+    pub(crate) fn duplicate_region_as(
+        &mut self,
+        id: RegionId,
+        copy_id: Option<RegionId>,
+    ) -> Result<(RegionId, OverlapReport), TimelineError> {
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let mut copy = self.tracks[t].regions[r].clone();
+        copy.start = copy.end();
+        self.check_overlaps(t, copy.range(), id, self.overlap_policy)?;
+        copy.id = copy_id.unwrap_or_else(|| self.new_region_id());
+        let copy_id = copy.id;
+        let report = self.place_region(copy)?;
+        self.emit(TimelineEvent::RegionAdded(copy_id));
+        Ok((copy_id, report))
+    }
+
+    /// Trim a region from either edge
+    ///
+    /// Trimming only changes the visible window onto the content; nothing
+    /// outside it is discarded. Returns the applied, possibly clamped,
+    /// position of the edge.
+    pub fn trim_region(
+        &mut self,
+        id: RegionId,
+        edge: RegionEdge,
+        new_position: SamplePosition,
+    ) -> Result<SamplePosition, TimelineError> {
+        self.check_editable(id)?;
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let track = &mut self.tracks[t];
+        let applied = match edge {
+            RegionEdge::Start => {
+                let mut region = track.regions.remove(r);
+                let applied = region.trim_start(new_position);
+                track.add_region(region);
+                applied
+            }
+            RegionEdge::End => track.regions[r].trim_end(new_position),
+        };
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionResized(id));
+        Ok(applied)
+    }
+
+    /// Cut a region in two at `position`
+    ///
+    /// The left half keeps the original ID; the right half gets a fresh one
+    /// and its source offset is advanced so the content stays in place.
+    /// Returns the (left, right) IDs.
+    pub fn split_region(
+        &mut self,
+        id: RegionId,
+        position: SamplePosition,
+    ) -> Result<(RegionId, RegionId), TimelineError> {
+        self.check_split(id, position)?;
+        let right_id = self.new_region_id();
+        self.split_region_as(id, position, right_id)
+    }
+
+    fn check_split(&self, id: RegionId, position: SamplePosition) -> Result<(), TimelineError> {
+        self.check_editable(id)?;
+        let region = self
+            .get_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        if position <= region.start || position >= region.end() {
+            return Err(TimelineError::SplitOutOfBounds {
+                region: id,
+                position: position.0,
+            });
+        }
+        Ok(())
+    }
+
+    /// Split with a given ID for the right half, so a redo recreates the
+    /// same region
+    pub(crate) fn split_region_as(
+        &mut self,
+        id: RegionId,
+        position: SamplePosition,
+        right_id: RegionId,
+    ) -> Result<(RegionId, RegionId), TimelineError> {
+        self.check_split(id, position)?;
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let track = &mut self.tracks[t];
+        let left = &mut track.regions[r];
+        let offset = position.0 - left.start.0;
+
+        let mut right = left.clone();
+        right.id = right_id;
+        right.start = position;
+        right.length = SamplePosition(left.length.0 - offset);
+        right.source_offset = left.content_offset_at(SamplePosition(offset));
+        right.fade_in_length = SamplePosition::ZERO;
+        right.takes.retime(offset, right.length);
+        left.length = SamplePosition(offset);
+        left.takes.retime(0, left.length);
+        left.fade_out_length = SamplePosition::ZERO;
+        left.clamp_fades();
+        right.clamp_fades();
+
+        track.add_region(right);
+        self.handoff_cut(id, right_id);
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionResized(id));
+        self.emit(TimelineEvent::RegionAdded(right_id));
+        Ok((id, right_id))
+    }
+
+    /// Undo a split: remove `right` and extend `left` back over it
+    pub(crate) fn join_split(
+        &mut self,
+        left: RegionId,
+        right: RegionId,
+    ) -> Result<(), TimelineError> {
+        let (t, r) = self
+            .locate_region(right)
+            .ok_or(TimelineError::RegionNotFound(right))?;
+        let (lt, l) = self
+            .locate_region(left)
+            .ok_or(TimelineError::RegionNotFound(left))?;
+        let right_region = self.tracks[t].regions.remove(r);
+        let left_region = &mut self.tracks[lt].regions[l];
+        left_region
+            .takes
+            .join(&right_region.takes, left_region.length);
+        left_region.length = SamplePosition(right_region.end().0 - left_region.start.0);
+        self.regions_changed();
+        self.emit(TimelineEvent::RegionRemoved(right));
+        self.emit(TimelineEvent::RegionResized(left));
+        Ok(())
+    }
+
+    /// Undo a split exactly: remove `right` and put back `original`, the
+    /// region as it was before the split, fades and all
+    pub(crate) fn unsplit(
+        &mut self,
+        original: Region,
+        right: RegionId,
+    ) -> Result<(), TimelineError> {
+        self.join_split(original.id, right)?;
+        if let Some((t, r)) = self.locate_region(original.id) {
+            self.tracks[t].regions[r] = original;
+        }
+        self.regions_changed();
+        Ok(())
+    }
+
     /// Create a new region ID
     pub fn new_region_id(&mut self) -> RegionId {
         let id = RegionId(self.next_region_id);
         self.next_region_id += 1;
         id
     }
+
+    /// Make sure [`new_region_id`](Self::new_region_id) never hands out
+    /// `id`, which was created elsewhere, e.g. by an edit being replayed
+    pub fn reserve_region_id(&mut self, id: RegionId) {
+        self.next_region_id = self.next_region_id.max(id.0 + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(timeline: &mut Timeline, track: TrackId, start: i64, length: i64) -> RegionId {
+        let id = timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+        timeline.get_track_mut(track).unwrap().add_region(region);
+        id
+    }
+
+    #[test]
+    fn test_regions_in_range() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let late = region(&mut timeline, track, 5000, 1000);
+        let long = region(&mut timeline, track, 0, 3000);
+        let before = region(&mut timeline, track, 100, 200);
+        let inside = region(&mut timeline, track, 2500, 100);
+
+        let starts: Vec<i64> = timeline.tracks[0]
+            .regions
+            .iter()
+            .map(|r| r.start.0)
+            .collect();
+        assert_eq!(starts, vec![0, 100, 2500, 5000]);
+
+        // The long region starts before the range but overlaps into it
+        let range = SampleRange::new(SamplePosition(2000), SamplePosition(5000));
+        let found: Vec<RegionId> = timeline
+            .regions_in_range(range)
+            .map(|(_, r)| r.id)
+            .collect();
+        assert_eq!(found, vec![long, inside]);
+        assert!(!found.contains(&before) && !found.contains(&late));
+
+        let range = SampleRange::new(SamplePosition(4000), SamplePosition(5001));
+        let found: Vec<(TrackId, RegionId)> = timeline
+            .regions_in_range(range)
+            .map(|(t, r)| (t, r.id))
+            .collect();
+        assert_eq!(found, vec![(track, late)]);
+    }
+
+    #[test]
+    fn test_trim_region_clamps() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let id = region(&mut timeline, track, 1000, 1000);
+        timeline.get_track_mut(track).unwrap().regions[0].source_offset = SamplePosition(300);
+
+        // Trimming the start keeps the content in place
+        let applied = timeline
+            .trim_region(id, RegionEdge::Start, SamplePosition(1200))
+            .unwrap();
+        assert_eq!(applied, SamplePosition(1200));
+        let r = timeline.get_region(id).unwrap();
+        assert_eq!((r.start.0, r.end().0, r.source_offset.0), (1200, 2000, 500));
+
+        // Can't extend before the source start
+        let applied = timeline
+            .trim_region(id, RegionEdge::Start, SamplePosition(0))
+            .unwrap();
+        assert_eq!(applied, SamplePosition(700));
+        assert_eq!(
+            timeline.get_region(id).unwrap().source_offset,
+            SamplePosition::ZERO
+        );
+
+        // Edges can't cross: at least one sample remains
+        let applied = timeline
+            .trim_region(id, RegionEdge::Start, SamplePosition(5000))
+            .unwrap();
+        assert_eq!(applied, SamplePosition(1999));
+        let applied = timeline
+            .trim_region(id, RegionEdge::End, SamplePosition(0))
+            .unwrap();
+        assert_eq!(applied, SamplePosition(2000));
+        assert_eq!(timeline.get_region(id).unwrap().length, SamplePosition(1));
+
+        let applied = timeline
+            .trim_region(id, RegionEdge::End, SamplePosition(3000))
+            .unwrap();
+        assert_eq!(applied, SamplePosition(3000));
+        assert_eq!(
+            timeline.trim_region(RegionId(99), RegionEdge::End, SamplePosition(0)),
+            Err(TimelineError::RegionNotFound(RegionId(99)))
+        );
+    }
+
+    #[test]
+    fn test_move_region_keeps_id() {
+        let mut timeline = Timeline::new();
+        let audio = timeline.add_track("Audio 1", TrackType::Audio);
+        let audio2 = timeline.add_track("Audio 2", TrackType::Audio);
+        let midi = timeline.add_track("MIDI 1", TrackType::Midi);
+        let id = region(&mut timeline, audio, 1000, 500);
+        let other = region(&mut timeline, audio2, 0, 500);
+        let selection = [id, other];
+
+        timeline
+            .move_region(id, audio2, SamplePosition(2000))
+            .unwrap();
+        assert!(timeline.get_track(audio).unwrap().regions.is_empty());
+        let ids: Vec<RegionId> = timeline
+            .get_track(audio2)
+            .unwrap()
+            .regions
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![other, id]);
+        for id in selection {
+            assert_eq!(timeline.get_region(id).unwrap().track_id, audio2);
+        }
+
+        assert_eq!(
+            timeline.move_region(id, midi, SamplePosition(0)),
+            Err(TimelineError::IncompatibleTrack {
+                from: TrackType::Audio,
+                to: TrackType::Midi
+            })
+        );
+        assert_eq!(timeline.get_region(id).unwrap().start, SamplePosition(2000));
+    }
+
+    #[test]
+    fn test_duplicate_region() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let id = region(&mut timeline, track, 1000, 500);
+        let copy = timeline.duplicate_region(id).unwrap();
+        assert_ne!(copy, id);
+        let copy = timeline.get_region(copy).unwrap();
+        assert_eq!((copy.start.0, copy.length.0), (1500, 500));
+    }
+
+    #[test]
+    fn test_region_fades_and_gain() {
+        let mut region = Region::new(
+            RegionId(0),
+            TrackId(0),
+            SamplePosition(0),
+            SamplePosition(1000),
+        );
+        assert_eq!(region.gain_at(SamplePosition(500)), 1.0);
+        assert_eq!(region.gain_at(SamplePosition(1000)), 0.0);
+
+        assert_eq!(region.set_fade_in(SamplePosition(400)), SamplePosition(400));
+        assert_eq!(
+            region.set_fade_out(SamplePosition(800)),
+            SamplePosition(600)
+        );
+        region.set_fade_out(SamplePosition(200));
+        region.gain_db = -6.0;
+
+        let flat = 10.0_f32.powf(-6.0 / 20.0);
+        assert_eq!(region.gain_at(SamplePosition(0)), 0.0);
+        assert!((region.gain_at(SamplePosition(200)) - flat * 0.5).abs() < 1e-6);
+        assert!((region.gain_at(SamplePosition(500)) - flat).abs() < 1e-6);
+        assert!((region.gain_at(SamplePosition(900)) - flat * 0.5).abs() < 1e-6);
+
+        // Trimming shorter pulls the fades in
+        region.trim_end(SamplePosition(300));
+        assert_eq!(region.fade_in_length, SamplePosition(300));
+        assert_eq!(region.fade_out_length, SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_region_loads_without_fade_fields() {
+        let json = r#"{"id":1,"name":"Old","start":0,"length":100,"track_id":0,"color":0}"#;
+        let region: Region = serde_json::from_str(json).unwrap();
+        assert_eq!(region.fade_in_length, SamplePosition::ZERO);
+        assert_eq!(region.gain_db, 0.0);
+        assert_eq!(region.gain_at(SamplePosition(50)), 1.0);
+    }
+
+    #[test]
+    fn test_move_track_keeps_regions() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let c = timeline.add_track("C", TrackType::Midi);
+        let id = region(&mut timeline, a, 0, 100);
+
+        assert_eq!(timeline.move_track(a, 99), Ok(2));
+        let order: Vec<TrackId> = timeline.tracks.iter().map(|t| t.id).collect();
+        assert_eq!(order, vec![b, c, a]);
+        assert_eq!(timeline.track_index(a), Some(2));
+        assert_eq!(timeline.get_region(id).unwrap().track_id, a);
+        assert_eq!(timeline.tracks[2].regions[0].id, id);
+
+        let d = timeline.insert_track_at(1, "D", TrackType::Audio);
+        assert_eq!(timeline.track_index(d), Some(1));
+        assert_eq!(
+            timeline.move_track(TrackId(99), 0),
+            Err(TimelineError::TrackNotFound(TrackId(99)))
+        );
+    }
+
+    #[test]
+    fn test_locked_regions_refuse_edits() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let a = region(&mut timeline, track, 0, 1000);
+        let b = region(&mut timeline, track, 2000, 1000);
+        timeline.set_regions_locked(&[a, b], true).unwrap();
+
+        let locked = Err(TimelineError::RegionLocked(a));
+        assert_eq!(
+            timeline
+                .move_region(a, track, SamplePosition(10))
+                .map(|_| ()),
+            locked
+        );
+        assert_eq!(
+            timeline
+                .trim_region(a, RegionEdge::End, SamplePosition(10))
+                .map(|_| ()),
+            locked
+        );
+        assert_eq!(
+            timeline.split_region(a, SamplePosition(10)).map(|_| ()),
+            locked
+        );
+        assert_eq!(timeline.remove_region(a).map(|_| ()), locked);
+
+        timeline.set_region_locked(a, false).unwrap();
+        timeline.set_track_locked(track, true).unwrap();
+        assert_eq!(
+            timeline.remove_region(a).map(|_| ()),
+            Err(TimelineError::TrackLocked(track))
+        );
+        timeline.set_track_locked(track, false).unwrap();
+        assert!(timeline.remove_region(a).is_ok());
+        assert_eq!(
+            timeline.set_regions_locked(&[b, a], false),
+            Err(TimelineError::RegionNotFound(a))
+        );
+        assert!(timeline.get_region(b).unwrap().locked);
+    }
+
+    #[test]
+    fn test_split_region() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let id = region(&mut timeline, track, 1000, 4000);
+        {
+            let region = &mut timeline.get_track_mut(track).unwrap().regions[0];
+            region.name = "Vox".into();
+            region.color = 0xFF0000;
+            region.source_offset = SamplePosition(200);
+        }
+
+        for position in [1000, 5000, 0] {
+            assert_eq!(
+                timeline.split_region(id, SamplePosition(position)),
+                Err(TimelineError::SplitOutOfBounds {
+                    region: id,
+                    position
+                })
+            );
+        }
+
+        let (left, right) = timeline.split_region(id, SamplePosition(2500)).unwrap();
+        assert_eq!(left, id);
+        assert_ne!(right, id);
+
+        let left = timeline.get_region(left).unwrap();
+        assert_eq!(
+            (left.start.0, left.length.0, left.source_offset.0),
+            (1000, 1500, 200)
+        );
+        let right = timeline.get_region(right).unwrap();
+        assert_eq!(
+            (right.start.0, right.length.0, right.source_offset.0),
+            (2500, 2500, 1700)
+        );
+        assert_eq!((right.name.as_str(), right.color), ("Vox", 0xFF0000));
+    }
+
+    fn looped_region() -> Region {
+        // A 1000-sample loop shown four times, starting 250 samples in
+        let mut region = Region::new(
+            RegionId(0),
+            TrackId(0),
+            SamplePosition(0),
+            SamplePosition(4000),
+        );
+        region.loop_enabled = true;
+        region.content_length = SamplePosition(1000);
+        region.source_offset = SamplePosition(250);
+        region
+    }
+
+    #[test]
+    fn test_content_offset_at_loop_boundaries() {
+        let mut region = looped_region();
+        let at = |region: &Region, offset| region.content_offset_at(SamplePosition(offset)).0;
+        assert_eq!(at(&region, 0), 250);
+        assert_eq!(at(&region, 749), 999);
+        assert_eq!(at(&region, 750), 0);
+        assert_eq!(at(&region, 1750), 0);
+        assert_eq!(at(&region, 1749), 999);
+        assert_eq!(at(&region, 3999), 249);
+
+        region.source_offset = SamplePosition::ZERO;
+        assert_eq!(at(&region, 1000), 0);
+        assert_eq!(at(&region, 3000), 0);
+        assert_eq!(at(&region, 999), 999);
+
+        region.loop_enabled = false;
+        assert_eq!(at(&region, 3000), 3000);
+    }
+
+    #[test]
+    fn test_split_looped_region() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Drums", TrackType::Audio);
+        let mut region = looped_region();
+        region.id = timeline.new_region_id();
+        region.track_id = track;
+        let id = region.id;
+        timeline.add_region(region).unwrap();
+
+        // Mid-repetition, exactly on a repetition boundary, and just after
+        let (_, a) = timeline.split_region(id, SamplePosition(1200)).unwrap();
+        let (_, b) = timeline.split_region(a, SamplePosition(1750)).unwrap();
+        let (_, c) = timeline.split_region(b, SamplePosition(2751)).unwrap();
+        let offsets: Vec<_> = [id, a, b, c]
+            .iter()
+            .map(|&id| timeline.get_region(id).unwrap().source_offset.0)
+            .collect();
+        assert_eq!(offsets, vec![250, 450, 0, 1]);
+
+        // Extending a looped region's start wraps back through the content
+        let mut extended = timeline.get_region(c).unwrap().clone();
+        assert_eq!(
+            extended.trim_start(SamplePosition(2000)),
+            SamplePosition(2000)
+        );
+        assert_eq!(extended.source_offset, SamplePosition(250));
+        assert_eq!(
+            extended.content_offset_at(SamplePosition(751)),
+            SamplePosition(1)
+        );
+    }
 }
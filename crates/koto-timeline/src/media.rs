@@ -0,0 +1,320 @@
+//! Audio sources referenced by regions: the project media pool
+
+use crate::{RegionId, Timeline, TimelineError, TimelineEvent};
+use koto_core::{ChannelCount, SamplePosition, SampleRate};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Unique identifier for audio sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AudioSourceId(pub u64);
+
+/// Min/max overview of a source for drawing waveforms
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeakCache {
+    /// Source frames summarised by each entry
+    pub frames_per_peak: u32,
+    /// (min, max) across channels for each block
+    pub peaks: Vec<(f32, f32)>,
+}
+
+/// An audio file used by regions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioSource {
+    pub id: AudioSourceId,
+    /// Relative paths are relative to the project file
+    pub path: PathBuf,
+    pub sample_rate: SampleRate,
+    pub channels: ChannelCount,
+    pub length: SamplePosition,
+    #[serde(default)]
+    pub peaks: Option<PeakCache>,
+    /// Hash of the file's bytes, for recognising it when relinking
+    #[serde(default)]
+    pub content_hash: Option<u64>,
+}
+
+/// Folder next to the project file searched for media that isn't where
+/// the project says
+pub const MEDIA_FOLDER: &str = "Media";
+
+/// Audio file that couldn't be found when resolving source paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingMedia {
+    pub id: AudioSourceId,
+    /// Where the file was expected
+    pub path: PathBuf,
+}
+
+impl Timeline {
+    /// Add an audio file to the media pool
+    ///
+    /// A file already in the pool keeps its existing ID.
+    pub fn add_source(
+        &mut self,
+        path: impl Into<PathBuf>,
+        sample_rate: SampleRate,
+        channels: ChannelCount,
+        length: SamplePosition,
+    ) -> AudioSourceId {
+        let path = path.into();
+        if let Some(existing) = self.sources.iter().find(|s| s.path == path) {
+            return existing.id;
+        }
+        let id = AudioSourceId(self.next_source_id);
+        self.next_source_id += 1;
+        self.sources.push(AudioSource {
+            id,
+            path,
+            sample_rate,
+            channels,
+            length,
+            peaks: None,
+            content_hash: None,
+        });
+        self.emit(TimelineEvent::SourcesChanged);
+        id
+    }
+
+    pub fn get_source(&self, id: AudioSourceId) -> Option<&AudioSource> {
+        self.sources.iter().find(|s| s.id == id)
+    }
+
+    pub fn get_source_mut(&mut self, id: AudioSourceId) -> Option<&mut AudioSource> {
+        self.sources.iter_mut().find(|s| s.id == id)
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &AudioSource> {
+        self.sources.iter()
+    }
+
+    /// Regions playing `source`, in track order then by start
+    pub fn find_regions_using(&self, source: AudioSourceId) -> Vec<RegionId> {
+        self.tracks
+            .iter()
+            .flat_map(|t| t.regions.iter())
+            .filter(|r| r.content.uses_source(source))
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// Remove a source from the pool
+    ///
+    /// If regions still use it, fails unless `cascade` is set, in which case
+    /// those regions are deleted too (all or none, if any is locked).
+    pub fn remove_source(
+        &mut self,
+        id: AudioSourceId,
+        cascade: bool,
+    ) -> Result<AudioSource, TimelineError> {
+        let index = self
+            .sources
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or(TimelineError::SourceNotFound(id))?;
+        let users = self.find_regions_using(id);
+        if !users.is_empty() {
+            if !cascade {
+                return Err(TimelineError::SourceInUse {
+                    id,
+                    regions: users.len(),
+                });
+            }
+            for &region in &users {
+                self.check_editable(region)?;
+            }
+            for region in users {
+                self.take_region(region);
+            }
+        }
+        self.emit(TimelineEvent::SourcesChanged);
+        Ok(self.sources.remove(index))
+    }
+
+    /// Remove every source no region uses, returning them
+    pub fn remove_unused_sources(&mut self) -> Vec<AudioSource> {
+        let (used, unused) = std::mem::take(&mut self.sources)
+            .into_iter()
+            .partition(|s| !self.find_regions_using(s.id).is_empty());
+        self.sources = used;
+        if !unused.is_empty() {
+            self.emit(TimelineEvent::SourcesChanged);
+        }
+        unused
+    }
+
+    /// Rewrite source paths under `base` as relative to it
+    pub fn relativize_source_paths(&mut self, base: &Path) {
+        for source in &mut self.sources {
+            if let Ok(relative) = source.path.strip_prefix(base) {
+                source.path = relative.to_path_buf();
+            }
+        }
+    }
+
+    /// Resolve relative source paths against `base`
+    ///
+    /// Files not found there are looked for by name in the
+    /// [`MEDIA_FOLDER`] under `base`. Those still missing keep their
+    /// expected path and are returned, to be relinked with
+    /// [`set_source_path`](Self::set_source_path).
+    pub fn resolve_source_paths(&mut self, base: &Path) -> Vec<MissingMedia> {
+        let mut missing = Vec::new();
+        for source in &mut self.sources {
+            if source.path.is_relative() {
+                source.path = base.join(&source.path);
+            }
+            if source.path.exists() {
+                continue;
+            }
+            let found = source
+                .path
+                .file_name()
+                .map(|name| base.join(MEDIA_FOLDER).join(name))
+                .filter(|candidate| candidate.exists());
+            match found {
+                Some(path) => source.path = path,
+                None => missing.push(MissingMedia {
+                    id: source.id,
+                    path: source.path.clone(),
+                }),
+            }
+        }
+        missing
+    }
+
+    /// Point a source at another file, returning its previous path
+    pub fn set_source_path(
+        &mut self,
+        id: AudioSourceId,
+        path: impl Into<PathBuf>,
+    ) -> Result<PathBuf, TimelineError> {
+        let source = self
+            .sources
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or(TimelineError::SourceNotFound(id))?;
+        let old = std::mem::replace(&mut source.path, path.into());
+        self.emit(TimelineEvent::SourcesChanged);
+        Ok(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, RegionContent, TrackType};
+
+    fn add_source(timeline: &mut Timeline, path: &str) -> AudioSourceId {
+        timeline.add_source(
+            path,
+            SampleRate(48000),
+            ChannelCount(2),
+            SamplePosition(48000),
+        )
+    }
+
+    #[test]
+    fn test_remove_source_in_use() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let kick = add_source(&mut timeline, "/audio/kick.wav");
+        let snare = add_source(&mut timeline, "/audio/snare.wav");
+        assert_eq!(add_source(&mut timeline, "/audio/kick.wav"), kick);
+
+        let mut users = Vec::new();
+        for start in [0, 96000] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(100));
+            region.content = RegionContent::Audio { source: kick };
+            timeline.add_region(region).unwrap();
+            users.push(id);
+        }
+        assert_eq!(timeline.find_regions_using(kick), users);
+
+        assert_eq!(
+            timeline.remove_source(kick, false),
+            Err(TimelineError::SourceInUse {
+                id: kick,
+                regions: 2
+            })
+        );
+        let removed = timeline.remove_unused_sources();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].id, snare);
+
+        timeline.set_region_locked(users[1], true).unwrap();
+        assert!(timeline.remove_source(kick, true).is_err());
+        timeline.set_region_locked(users[1], false).unwrap();
+        assert!(timeline.remove_source(kick, true).is_ok());
+        assert!(timeline.tracks[0].regions.is_empty());
+        assert_eq!(timeline.sources().count(), 0);
+    }
+
+    #[test]
+    fn test_relative_source_paths() {
+        let mut timeline = Timeline::new();
+        let inside = add_source(&mut timeline, "/songs/demo/audio/vox.wav");
+        let outside = add_source(&mut timeline, "/samples/hat.wav");
+
+        timeline.relativize_source_paths(Path::new("/songs/demo"));
+        assert_eq!(
+            timeline.get_source(inside).unwrap().path,
+            Path::new("audio/vox.wav")
+        );
+        assert_eq!(
+            timeline.get_source(outside).unwrap().path,
+            Path::new("/samples/hat.wav")
+        );
+
+        timeline.resolve_source_paths(Path::new("/elsewhere/demo"));
+        assert_eq!(
+            timeline.get_source(inside).unwrap().path,
+            Path::new("/elsewhere/demo/audio/vox.wav")
+        );
+    }
+
+    #[test]
+    fn test_resolve_falls_back_to_media_folder() {
+        let dir = std::env::temp_dir().join(format!("koto-media-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("audio")).unwrap();
+        std::fs::create_dir_all(dir.join(MEDIA_FOLDER)).unwrap();
+        std::fs::write(dir.join("audio/vox.wav"), b"").unwrap();
+        std::fs::write(dir.join(MEDIA_FOLDER).join("kick.wav"), b"").unwrap();
+
+        let mut timeline = Timeline::new();
+        let vox = add_source(&mut timeline, "audio/vox.wav");
+        let kick = add_source(&mut timeline, "/other/machine/kick.wav");
+        let bass = add_source(&mut timeline, "audio/bass.wav");
+
+        let missing = timeline.resolve_source_paths(&dir);
+        assert_eq!(
+            timeline.get_source(vox).unwrap().path,
+            dir.join("audio/vox.wav")
+        );
+        assert_eq!(
+            timeline.get_source(kick).unwrap().path,
+            dir.join(MEDIA_FOLDER).join("kick.wav")
+        );
+        assert_eq!(
+            missing,
+            [MissingMedia {
+                id: bass,
+                path: dir.join("audio/bass.wav"),
+            }]
+        );
+
+        timeline.take_events();
+        let old = timeline
+            .set_source_path(bass, dir.join("audio/vox.wav"))
+            .unwrap();
+        assert_eq!(old, dir.join("audio/bass.wav"));
+        assert_eq!(timeline.take_events(), [TimelineEvent::SourcesChanged]);
+        assert!(timeline.resolve_source_paths(&dir).is_empty());
+        assert_eq!(
+            timeline.set_source_path(AudioSourceId(9), "x.wav"),
+            Err(TimelineError::SourceNotFound(AudioSourceId(9)))
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
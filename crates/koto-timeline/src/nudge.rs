@@ -0,0 +1,167 @@
+//! Nudging regions by keyboard amounts
+
+use crate::{RegionId, SnapSettings, Timeline, TimelineError};
+use koto_core::{FrameRate, SamplePosition, TimeConverter};
+use serde::{Deserialize, Serialize};
+
+/// How far a nudge moves regions; negative amounts move earlier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NudgeAmount {
+    Samples(i64),
+    Milliseconds(f64),
+    /// Steps of the snap grid
+    Grid(i32),
+    Frames(i32, FrameRate),
+}
+
+impl NudgeAmount {
+    /// The amount in samples, using `settings` for the grid
+    pub fn to_samples(&self, settings: &SnapSettings, converter: &TimeConverter) -> i64 {
+        match *self {
+            NudgeAmount::Samples(samples) => samples,
+            NudgeAmount::Milliseconds(ms) => converter.seconds_to_samples(ms / 1000.0).0,
+            NudgeAmount::Grid(units) => settings.grid_to_samples(units as i64, converter),
+            NudgeAmount::Frames(frames, rate) => converter.frames_to_samples(frames as i64, rate).0,
+        }
+    }
+}
+
+/// Result of nudging a set of regions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NudgeReport {
+    /// Regions that moved, with their start before the nudge
+    pub moved: Vec<(RegionId, SamplePosition)>,
+    /// Regions left in place because they or their track are locked
+    pub skipped: Vec<RegionId>,
+}
+
+impl Timeline {
+    /// Shift regions together by `amount`; see
+    /// [`nudge_selected`](Self::nudge_selected)
+    pub fn nudge_regions(
+        &mut self,
+        ids: &[RegionId],
+        amount: NudgeAmount,
+        settings: &SnapSettings,
+        converter: &TimeConverter,
+    ) -> Result<NudgeReport, TimelineError> {
+        self.nudge_all(ids, amount.to_samples(settings, converter))
+    }
+
+    /// Move regions together so the earliest movable one starts at
+    /// `playhead`, keeping their relative offsets
+    pub fn move_to_playhead(
+        &mut self,
+        ids: &[RegionId],
+        playhead: SamplePosition,
+    ) -> Result<NudgeReport, TimelineError> {
+        let earliest = self
+            .movable(ids)?
+            .0
+            .iter()
+            .filter_map(|&id| self.get_region(id))
+            .map(|r| r.start)
+            .min();
+        match earliest {
+            Some(earliest) => self.nudge_all(ids, playhead.0 - earliest.0),
+            None => self.nudge_all(ids, 0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, SnapMode, TrackType};
+    use koto_core::{SampleRate, Tempo, TimeSignature};
+
+    /// 120 BPM at 48 kHz: 24000 samples per beat
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    fn setup() -> (Timeline, Vec<RegionId>) {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("A", TrackType::Audio);
+        let ids = [1000, 30000, 96000]
+            .into_iter()
+            .map(|start| {
+                let id = timeline.new_region_id();
+                let region = Region::new(id, track, SamplePosition(start), SamplePosition(500));
+                timeline.add_region(region).unwrap();
+                id
+            })
+            .collect();
+        (timeline, ids)
+    }
+
+    fn starts(timeline: &Timeline, ids: &[RegionId]) -> Vec<i64> {
+        ids.iter()
+            .map(|&id| timeline.get_region(id).unwrap().start.0)
+            .collect()
+    }
+
+    #[test]
+    fn test_nudge_amounts() {
+        let conv = converter();
+        let settings = SnapSettings {
+            mode: SnapMode::Division(8),
+            ..SnapSettings::default()
+        };
+        let samples = |amount: NudgeAmount| amount.to_samples(&settings, &conv);
+        assert_eq!(samples(NudgeAmount::Samples(-7)), -7);
+        assert_eq!(samples(NudgeAmount::Milliseconds(10.0)), 480);
+        assert_eq!(samples(NudgeAmount::Grid(-3)), -36000);
+        assert_eq!(samples(NudgeAmount::Frames(2, FrameRate::FPS_25)), 3840);
+        let bars = SnapSettings {
+            mode: SnapMode::Bar,
+            ..settings
+        };
+        assert_eq!(NudgeAmount::Grid(2).to_samples(&bars, &conv), 192000);
+    }
+
+    #[test]
+    fn test_nudge_clamps_and_skips_locked() {
+        let (mut timeline, ids) = setup();
+        timeline.set_region_locked(ids[1], true).unwrap();
+        let report = timeline
+            .nudge_regions(
+                &ids,
+                NudgeAmount::Milliseconds(-50.0),
+                &SnapSettings::default(),
+                &converter(),
+            )
+            .unwrap();
+        // Held back by the first region, so the spacing is kept
+        assert_eq!(starts(&timeline, &ids), vec![0, 30000, 95000]);
+        assert_eq!(report.skipped, vec![ids[1]]);
+        assert_eq!(
+            report.moved,
+            vec![
+                (ids[0], SamplePosition(1000)),
+                (ids[2], SamplePosition(96000))
+            ]
+        );
+
+        assert_eq!(
+            timeline.nudge_all(&[ids[0], RegionId(99)], 10),
+            Err(TimelineError::RegionNotFound(RegionId(99)))
+        );
+        assert_eq!(starts(&timeline, &ids), vec![0, 30000, 95000]);
+    }
+
+    #[test]
+    fn test_move_to_playhead_keeps_offsets() {
+        let (mut timeline, ids) = setup();
+        timeline.set_region_locked(ids[0], true).unwrap();
+        let report = timeline
+            .move_to_playhead(&ids, SamplePosition(50000))
+            .unwrap();
+        assert_eq!(starts(&timeline, &ids), vec![1000, 50000, 116000]);
+        assert_eq!(report.skipped, vec![ids[0]]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Resolving collisions between regions on a track
+
+use crate::{Region, RegionEdge, RegionId, Timeline, TimelineError, TimelineEvent};
+use koto_core::SampleRange;
+use serde::{Deserialize, Serialize};
+
+/// What happens to existing regions when a region is placed over them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OverlapPolicy {
+    /// Leave existing regions alone; regions stack on top of each other
+    #[default]
+    AllowLayered,
+    /// Shorten existing regions to make room, splitting them if the new
+    /// region lands in the middle
+    TrimExisting,
+    /// Remove every existing region the new one overlaps
+    ReplaceExisting,
+}
+
+/// Changes made to existing regions while resolving an overlap
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OverlapReport {
+    /// Regions that were shortened, as they were before the change
+    pub modified: Vec<Region>,
+    /// Regions that were removed
+    pub removed: Vec<Region>,
+    /// Regions created by splitting an existing one
+    pub created: Vec<RegionId>,
+}
+
+impl OverlapReport {
+    pub fn is_empty(&self) -> bool {
+        self.modified.is_empty() && self.removed.is_empty() && self.created.is_empty()
+    }
+}
+
+impl Timeline {
+    /// Add a region to its track, applying the overlap policy
+    ///
+    /// A region still colored by [`Region::new`] takes its track's color.
+    pub fn add_region(&mut self, mut region: Region) -> Result<OverlapReport, TimelineError> {
+        self.adopt_track_color(&mut region);
+        let id = region.id;
+        let report = self.place_region(region)?;
+        self.emit(TimelineEvent::RegionAdded(id));
+        Ok(report)
+    }
+
+    /// [`add_region`](Self::add_region) without announcing the region, for
+    /// moves
+    pub(crate) fn place_region(&mut self, region: Region) -> Result<OverlapReport, TimelineError> {
+        let track = self
+            .track_index(region.track_id)
+            .ok_or(TimelineError::TrackNotFound(region.track_id))?;
+        let id = region.id;
+        let report = self.clear_overlaps(track, region.range(), id)?;
+        self.tracks[track].add_region(region);
+        if self.overlap_policy == OverlapPolicy::TrimExisting {
+            self.auto_crossfade(id);
+        }
+        Ok(report)
+    }
+
+    /// Fail if placing a region over `range` would have to touch a locked
+    /// or frozen track, or a locked region
+    pub(crate) fn check_overlaps(
+        &self,
+        track: usize,
+        range: SampleRange,
+        placing: RegionId,
+        policy: OverlapPolicy,
+    ) -> Result<(), TimelineError> {
+        let track = &self.tracks[track];
+        track.check_editable()?;
+        if policy == OverlapPolicy::AllowLayered {
+            return Ok(());
+        }
+        match track
+            .regions_in_range(range)
+            .find(|r| r.id != placing && r.locked)
+        {
+            Some(locked) => Err(TimelineError::RegionLocked(locked.id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Make room for a region occupying `range` on a track, according to
+    /// the overlap policy. `placing` is skipped in case it's already there.
+    pub(crate) fn clear_overlaps(
+        &mut self,
+        track: usize,
+        range: SampleRange,
+        placing: RegionId,
+    ) -> Result<OverlapReport, TimelineError> {
+        self.clear_overlaps_with(track, range, placing, self.overlap_policy)
+    }
+
+    /// [`clear_overlaps`](Self::clear_overlaps) with a given policy
+    pub(crate) fn clear_overlaps_with(
+        &mut self,
+        track: usize,
+        range: SampleRange,
+        placing: RegionId,
+        policy: OverlapPolicy,
+    ) -> Result<OverlapReport, TimelineError> {
+        self.check_overlaps(track, range, placing, policy)?;
+        let mut report = OverlapReport::default();
+        if policy == OverlapPolicy::AllowLayered {
+            return Ok(report);
+        }
+
+        let colliding: Vec<Region> = self.tracks[track]
+            .regions_in_range(range)
+            .filter(|r| r.id != placing)
+            .cloned()
+            .collect();
+
+        for region in colliding {
+            let covered = region.start >= range.start && region.end() <= range.end;
+            if covered || policy == OverlapPolicy::ReplaceExisting {
+                self.take_region(region.id);
+                report.removed.push(region);
+                continue;
+            }
+
+            if region.start < range.start && region.end() > range.end {
+                // The new region lands inside this one: keep both ends
+                if let Ok((_, right)) = self.split_region(region.id, range.end) {
+                    report.created.push(right);
+                }
+                let _ = self.trim_region(region.id, RegionEdge::End, range.start);
+            } else if region.start < range.start {
+                let _ = self.trim_region(region.id, RegionEdge::End, range.start);
+            } else {
+                let _ = self.trim_region(region.id, RegionEdge::Start, range.end);
+            }
+            report.modified.push(region);
+        }
+        Ok(report)
+    }
+
+    /// Undo the changes in a report: drop regions created by splits and
+    /// restore modified and removed regions
+    pub fn revert_overlaps(&mut self, report: &OverlapReport) {
+        for &id in &report.created {
+            self.take_region(id);
+        }
+        for region in &report.modified {
+            self.detach_region(region.id);
+            if let Some(track) = self.get_track_mut(region.track_id) {
+                track.add_region(region.clone());
+            }
+            self.emit(TimelineEvent::RegionResized(region.id));
+        }
+        for region in &report.removed {
+            self.restore_region(region.clone());
+        }
+        self.regions_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrackType;
+    use koto_core::SamplePosition;
+
+    fn setup(policy: OverlapPolicy) -> (Timeline, crate::TrackId, RegionId) {
+        let mut timeline = Timeline::new();
+        timeline.overlap_policy = policy;
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(0), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+        (timeline, track, id)
+    }
+
+    fn place(
+        timeline: &mut Timeline,
+        track: crate::TrackId,
+        start: i64,
+        length: i64,
+    ) -> OverlapReport {
+        let id = timeline.new_region_id();
+        let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+        timeline.add_region(region).unwrap()
+    }
+
+    fn spans(timeline: &Timeline) -> Vec<(i64, i64)> {
+        timeline.tracks[0]
+            .regions
+            .iter()
+            .map(|r| (r.start.0, r.end().0))
+            .collect()
+    }
+
+    #[test]
+    fn test_trim_splits_around_inner_region() {
+        let (mut timeline, track, id) = setup(OverlapPolicy::TrimExisting);
+        let report = place(&mut timeline, track, 400, 200);
+
+        assert_eq!(spans(&timeline), vec![(0, 400), (400, 600), (600, 1000)]);
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].id, id);
+        assert_eq!(report.created.len(), 1);
+        let tail = timeline.get_region(report.created[0]).unwrap();
+        assert_eq!(tail.source_offset, SamplePosition(600));
+
+        timeline.revert_overlaps(&report);
+        timeline
+            .remove_region(timeline.tracks[0].regions[1].id)
+            .unwrap();
+        assert_eq!(spans(&timeline), vec![(0, 1000)]);
+    }
+
+    #[test]
+    fn test_trim_edges_and_replace() {
+        let (mut timeline, track, _) = setup(OverlapPolicy::TrimExisting);
+        place(&mut timeline, track, 800, 400);
+        place(&mut timeline, track, -100, 200);
+        assert_eq!(spans(&timeline), vec![(-100, 100), (100, 800), (800, 1200)]);
+
+        // A region covering another removes it under either policy
+        let report = place(&mut timeline, track, 50, 800);
+        assert_eq!(report.removed.len(), 1);
+        assert_eq!(spans(&timeline), vec![(-100, 50), (50, 850), (850, 1200)]);
+
+        let (mut timeline, track, id) = setup(OverlapPolicy::ReplaceExisting);
+        let report = place(&mut timeline, track, 900, 500);
+        assert_eq!(report.removed[0].id, id);
+        assert_eq!(spans(&timeline), vec![(900, 1400)]);
+
+        let (mut timeline, track, _) = setup(OverlapPolicy::AllowLayered);
+        assert!(place(&mut timeline, track, 500, 1000).is_empty());
+        assert_eq!(spans(&timeline), vec![(0, 1000), (500, 1500)]);
+    }
+
+    #[test]
+    fn test_trim_refuses_locked_region() {
+        let (mut timeline, track, id) = setup(OverlapPolicy::TrimExisting);
+        timeline.set_region_locked(id, true).unwrap();
+        let new = timeline.new_region_id();
+        let region = Region::new(new, track, SamplePosition(400), SamplePosition(200));
+        assert_eq!(
+            timeline.add_region(region),
+            Err(TimelineError::RegionLocked(id))
+        );
+        assert_eq!(spans(&timeline), vec![(0, 1000)]);
+
+        // A move that would trim it fails without losing the moved region
+        let other = timeline.add_track("Audio 2", TrackType::Audio);
+        let region = Region::new(new, other, SamplePosition(0), SamplePosition(200));
+        timeline.add_region(region).unwrap();
+        assert!(timeline
+            .move_region(new, track, SamplePosition(500))
+            .is_err());
+        assert_eq!(timeline.get_region(new).unwrap().track_id, other);
+    }
+}
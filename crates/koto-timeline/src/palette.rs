@@ -0,0 +1,130 @@
+//! Track and region colors
+
+use crate::{Region, Timeline, TimelineError, TimelineEvent, TrackId};
+use serde::{Deserialize, Serialize};
+
+/// Default track colors as packed `0xRRGGBB`
+pub const TRACK_PALETTE: [u32; 8] = [
+    0x4A90E2, // Blue
+    0x2ECC71, // Green
+    0x9B59B6, // Purple
+    0xF1C40F, // Yellow
+    0xE74C3C, // Red
+    0x1ABC9C, // Teal
+    0xE67E22, // Orange
+    0x34495E, // Dark blue
+];
+
+/// Index into [`TRACK_PALETTE`], wrapping around at the end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ColorIndex(pub usize);
+
+impl ColorIndex {
+    /// Palette slot for a track; ids are handed out in order, so new
+    /// tracks cycle through the palette
+    pub fn for_track(id: TrackId) -> Self {
+        Self(id.0 as usize % TRACK_PALETTE.len())
+    }
+
+    pub fn color(self) -> u32 {
+        TRACK_PALETTE[self.0 % TRACK_PALETTE.len()]
+    }
+}
+
+impl Timeline {
+    /// Change a track's color and return the previous one
+    ///
+    /// With `recolor_regions`, regions that still have the old track color
+    /// follow it; regions given their own color keep it.
+    pub fn set_track_color(
+        &mut self,
+        id: TrackId,
+        color: u32,
+        recolor_regions: bool,
+    ) -> Result<u32, TimelineError> {
+        let track = self
+            .get_track_mut(id)
+            .ok_or(TimelineError::TrackNotFound(id))?;
+        let old = std::mem::replace(&mut track.color, color);
+        let mut events = vec![TimelineEvent::TrackChanged(id)];
+        if recolor_regions {
+            for region in track.regions.iter_mut().filter(|r| r.color == old) {
+                region.color = color;
+                events.push(TimelineEvent::RegionChanged(region.id));
+            }
+        }
+        self.events.extend(events);
+        Ok(old)
+    }
+
+    /// Give a region still colored by [`Region::new`] its track's color
+    pub(crate) fn adopt_track_color(&self, region: &mut Region) {
+        if region.color == ColorIndex::for_track(region.track_id).color() {
+            if let Some(track) = self.get_track(region.track_id) {
+                region.color = track.color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RegionId, TrackType};
+    use koto_core::SamplePosition;
+
+    #[test]
+    fn test_tracks_cycle_through_palette() {
+        let mut timeline = Timeline::new();
+        let ids: Vec<_> = (0..=TRACK_PALETTE.len())
+            .map(|i| timeline.add_track(format!("T{i}"), TrackType::Audio))
+            .collect();
+        let colors: Vec<_> = ids
+            .iter()
+            .map(|&id| timeline.get_track(id).unwrap().color)
+            .collect();
+        assert_eq!(colors[..TRACK_PALETTE.len()], TRACK_PALETTE);
+        assert_eq!(colors[TRACK_PALETTE.len()], TRACK_PALETTE[0]);
+
+        let region = Region::new(RegionId(0), ids[2], SamplePosition(0), SamplePosition(10));
+        assert_eq!(region.color, TRACK_PALETTE[2]);
+
+        // Added regions follow a recolored track unless given their own color
+        timeline.set_track_color(ids[2], 0xABCDEF, false).unwrap();
+        timeline.add_region(region.clone()).unwrap();
+        let mut custom = Region::new(RegionId(1), ids[2], SamplePosition(20), SamplePosition(10));
+        custom.color = 0x00FF00;
+        timeline.add_region(custom).unwrap();
+        assert_eq!(timeline.get_region(RegionId(0)).unwrap().color, 0xABCDEF);
+        assert_eq!(timeline.get_region(RegionId(1)).unwrap().color, 0x00FF00);
+    }
+
+    #[test]
+    fn test_recolor_keeps_custom_region_colors() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Drums", TrackType::Audio);
+        let old = timeline.get_track(track).unwrap().color;
+        let mut ids = Vec::new();
+        for start in [0, 1000] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(100));
+            if start > 0 {
+                region.color = 0x00FF00;
+            }
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+
+        assert_eq!(timeline.set_track_color(track, 0xFF0000, false), Ok(old));
+        assert_eq!(timeline.get_region(ids[0]).unwrap().color, old);
+
+        // The track color no longer matches, so nothing follows
+        timeline.set_track_color(track, 0x123456, true).unwrap();
+        assert_eq!(timeline.get_region(ids[0]).unwrap().color, old);
+
+        timeline.set_track_color(track, old, false).unwrap();
+        timeline.set_track_color(track, 0x123456, true).unwrap();
+        assert_eq!(timeline.get_region(ids[0]).unwrap().color, 0x123456);
+        assert_eq!(timeline.get_region(ids[1]).unwrap().color, 0x00FF00);
+    }
+}
@@ -0,0 +1,458 @@
+//! Ripple edits: deleting or inserting time and shifting what follows
+
+use crate::{Crossfade, Region, RegionId, Timeline, TimelineError, TimelineEvent, TrackId};
+use koto_core::{SamplePosition, SampleRange};
+use serde::{Deserialize, Serialize};
+
+/// A span of time removed from, or inserted into, the timeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RippleEdit {
+    /// Remove the range and close the gap
+    Delete(SampleRange),
+    /// Open a gap of `length` at `position`
+    Insert {
+        position: SamplePosition,
+        length: SamplePosition,
+    },
+}
+
+impl RippleEdit {
+    /// Earliest position the edit affects
+    pub fn start(&self) -> SamplePosition {
+        match *self {
+            RippleEdit::Delete(range) => range.start,
+            RippleEdit::Insert { position, .. } => position,
+        }
+    }
+
+    /// Where a point in time ends up, or `None` if it was deleted
+    pub fn map_position(&self, position: SamplePosition) -> Option<SamplePosition> {
+        match *self {
+            RippleEdit::Delete(range) if position >= range.end => {
+                Some(SamplePosition(position.0 - range.length()))
+            }
+            RippleEdit::Delete(range) if position >= range.start => None,
+            RippleEdit::Insert {
+                position: at,
+                length,
+            } if position >= at => Some(SamplePosition(position.0 + length.0)),
+            _ => Some(position),
+        }
+    }
+
+    /// Apply the edit to one region
+    ///
+    /// Returns what remains of the region and, if the edit cut through it,
+    /// the part after the cut. The tail still carries the original ID.
+    fn apply(&self, mut region: Region) -> (Option<Region>, Option<Region>) {
+        let (start, end) = (region.start, region.end());
+        let (cut, resume) = match *self {
+            RippleEdit::Delete(range) => (range.start, range.end),
+            RippleEdit::Insert { position, length } => {
+                (position, SamplePosition(position.0 + length.0))
+            }
+        };
+        if end <= cut {
+            return (Some(region), None);
+        }
+        if start >= cut {
+            return match self.map_position(start) {
+                Some(new_start) => {
+                    region.start = new_start;
+                    (Some(region), None)
+                }
+                // Starts in the deleted range
+                None if end <= resume => (None, None),
+                None => {
+                    region.trim_start(resume);
+                    region.start = cut;
+                    (Some(region), None)
+                }
+            };
+        }
+
+        // Starts before the cut and runs into it
+        let mut tail = None;
+        let keeps_tail = match *self {
+            RippleEdit::Delete(range) => end > range.end,
+            RippleEdit::Insert { .. } => true,
+        };
+        if keeps_tail {
+            let mut rest = region.clone();
+            let content_start = match *self {
+                RippleEdit::Delete(range) => range.end,
+                RippleEdit::Insert { .. } => cut,
+            };
+            rest.trim_start(content_start);
+            rest.fade_in_length = SamplePosition::ZERO;
+            rest.start = match *self {
+                RippleEdit::Delete(_) => cut,
+                RippleEdit::Insert { .. } => resume,
+            };
+            tail = Some(rest);
+            region.fade_out_length = SamplePosition::ZERO;
+        }
+        region.trim_end(cut);
+        (Some(region), tail)
+    }
+}
+
+/// What a ripple edit changed, from
+/// [`ripple_with_changes`](Timeline::ripple_with_changes): only the
+/// regions from the edit's start on and the crossfades of the rippled
+/// tracks, before and after
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RippleChanges {
+    tracks: Vec<TrackId>,
+    before: Vec<Region>,
+    after: Vec<Region>,
+    crossfades_before: Vec<Crossfade>,
+    crossfades_after: Vec<Crossfade>,
+}
+
+impl Timeline {
+    /// Remove `range` from the given tracks (or all tracks) and pull later
+    /// regions earlier to close the gap
+    ///
+    /// Regions inside the range are deleted and those crossing its edges
+    /// trimmed; a region spanning the whole range is cut in two. Nothing
+    /// changes if an affected region or track is locked.
+    pub fn ripple_delete(
+        &mut self,
+        range: SampleRange,
+        tracks: Option<&[TrackId]>,
+    ) -> Result<(), TimelineError> {
+        self.ripple(RippleEdit::Delete(range), tracks)
+    }
+
+    /// Open a gap of `length` at `position` on the given tracks (or all
+    /// tracks), pushing later regions right. A region crossing `position`
+    /// is cut in two.
+    pub fn ripple_insert(
+        &mut self,
+        position: SamplePosition,
+        length: SamplePosition,
+        tracks: Option<&[TrackId]>,
+    ) -> Result<(), TimelineError> {
+        self.ripple(RippleEdit::Insert { position, length }, tracks)
+    }
+
+    /// Apply a ripple edit to the given tracks, or all tracks
+    pub fn ripple(
+        &mut self,
+        edit: RippleEdit,
+        tracks: Option<&[TrackId]>,
+    ) -> Result<(), TimelineError> {
+        let indices = self.ripple_tracks(tracks)?;
+        let from = edit.start();
+        for &t in &indices {
+            let track = &self.tracks[t];
+            let mut affected = track.regions.iter().filter(|r| r.end() > from);
+            if affected.clone().next().is_some() {
+                track.check_editable()?;
+            }
+            if let Some(locked) = affected.find(|r| r.locked) {
+                return Err(TimelineError::RegionLocked(locked.id));
+            }
+        }
+
+        for t in indices {
+            let old = std::mem::take(&mut self.tracks[t].regions);
+            let before = old.clone();
+            for region in old {
+                let id = region.id;
+                let (kept, tail) = edit.apply(region);
+                if let Some(kept) = kept {
+                    self.tracks[t].add_region(kept);
+                }
+                if let Some(mut tail) = tail {
+                    tail.id = self.new_region_id();
+                    self.handoff_cut(id, tail.id);
+                    self.tracks[t].add_region(tail);
+                }
+            }
+            self.emit_region_diff(t, &before);
+        }
+        self.regions_changed();
+        Ok(())
+    }
+
+    /// [`ripple`](Self::ripple), returning what changed so the edit can be
+    /// undone and redone with [`undo_ripple`](Self::undo_ripple) and
+    /// [`redo_ripple`](Self::redo_ripple)
+    pub fn ripple_with_changes(
+        &mut self,
+        edit: RippleEdit,
+        tracks: Option<&[TrackId]>,
+    ) -> Result<RippleChanges, TimelineError> {
+        let ids: Vec<TrackId> = self
+            .ripple_tracks(tracks)?
+            .into_iter()
+            .map(|t| self.tracks[t].id)
+            .collect();
+        // A region cut at the edit's start ends exactly there
+        let from = edit.start();
+        let touched = |timeline: &Timeline| -> Vec<Region> {
+            ids.iter()
+                .filter_map(|&id| timeline.get_track(id))
+                .flat_map(|t| t.regions.iter().filter(|r| r.end() >= from).cloned())
+                .collect()
+        };
+        let before = touched(self);
+        let crossfades_before = self.crossfades_on(&ids);
+        self.ripple(edit, tracks)?;
+        Ok(RippleChanges {
+            before,
+            after: touched(self),
+            crossfades_before,
+            crossfades_after: self.crossfades_on(&ids),
+            tracks: ids,
+        })
+    }
+
+    /// Put the regions and crossfades back as they were before a ripple
+    pub fn undo_ripple(&mut self, changes: &RippleChanges) {
+        self.swap_regions(
+            &changes.tracks,
+            &changes.after,
+            &changes.before,
+            &changes.crossfades_before,
+        );
+    }
+
+    /// Make a ripple undone by [`undo_ripple`](Self::undo_ripple) again,
+    /// with the same region IDs
+    pub fn redo_ripple(&mut self, changes: &RippleChanges) {
+        self.swap_regions(
+            &changes.tracks,
+            &changes.before,
+            &changes.after,
+            &changes.crossfades_after,
+        );
+    }
+
+    /// Replace the `from` regions on `tracks` with the `to` regions, then
+    /// put back `crossfades`
+    fn swap_regions(
+        &mut self,
+        tracks: &[TrackId],
+        from: &[Region],
+        to: &[Region],
+        crossfades: &[Crossfade],
+    ) {
+        for &id in tracks {
+            let Some(t) = self.track_index(id) else {
+                continue;
+            };
+            let before = self.tracks[t].regions.clone();
+            self.tracks[t]
+                .regions
+                .retain(|r| !from.iter().any(|old| old.id == r.id));
+            for region in to.iter().filter(|r| r.track_id == id) {
+                self.tracks[t].add_region(region.clone());
+            }
+            self.emit_region_diff(t, &before);
+        }
+        self.regions_changed();
+        self.restore_crossfades(crossfades);
+    }
+
+    /// Resolve which tracks a ripple applies to
+    fn ripple_tracks(&self, tracks: Option<&[TrackId]>) -> Result<Vec<usize>, TimelineError> {
+        match tracks {
+            Some(ids) => ids
+                .iter()
+                .map(|&id| self.track_index(id).ok_or(TimelineError::TrackNotFound(id)))
+                .collect(),
+            None => Ok((0..self.tracks.len()).collect()),
+        }
+    }
+
+    /// After cutting `left` in two, let `right` take over what followed
+    /// the original: crossfades to the next region and selection
+    pub(crate) fn handoff_cut(&mut self, left: RegionId, right: RegionId) {
+        let mut changed = Vec::new();
+        for track in &mut self.tracks {
+            for crossfade in &mut track.crossfades {
+                if crossfade.left == left {
+                    crossfade.left = right;
+                    changed.push(TimelineEvent::CrossfadesChanged(track.id));
+                }
+            }
+        }
+        self.events.extend(changed);
+        if self.selection.contains_region(left) {
+            self.selection.add_region(right);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RegionEdge, TrackType};
+
+    fn range(start: i64, end: i64) -> SampleRange {
+        SampleRange::new(SamplePosition(start), SamplePosition(end))
+    }
+
+    /// One track with regions at the given (start, end) spans
+    fn timeline_with(spans: &[(i64, i64)]) -> (Timeline, TrackId) {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        for &(start, end) in spans {
+            let id = timeline.new_region_id();
+            let region = Region::new(
+                id,
+                track,
+                SamplePosition(start),
+                SamplePosition(end - start),
+            );
+            timeline.add_region(region).unwrap();
+        }
+        (timeline, track)
+    }
+
+    fn spans(timeline: &Timeline) -> Vec<(i64, i64, i64)> {
+        timeline.tracks[0]
+            .regions
+            .iter()
+            .map(|r| (r.start.0, r.end().0, r.source_offset.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_ripple_delete_boundaries() {
+        // Ends at the range start, inside, starts at the range end
+        let (mut timeline, _) = timeline_with(&[(0, 1000), (1200, 1800), (2000, 2500)]);
+        timeline.ripple_delete(range(1000, 2000), None).unwrap();
+        assert_eq!(spans(&timeline), vec![(0, 1000, 0), (1000, 1500, 0)]);
+
+        // Straddling each edge
+        let (mut timeline, _) = timeline_with(&[(500, 1500), (1800, 3000)]);
+        timeline.ripple_delete(range(1000, 2000), None).unwrap();
+        assert_eq!(spans(&timeline), vec![(500, 1000, 0), (1000, 2000, 200)]);
+
+        // Exactly the range
+        let (mut timeline, _) = timeline_with(&[(1000, 2000)]);
+        timeline.ripple_delete(range(1000, 2000), None).unwrap();
+        assert!(spans(&timeline).is_empty());
+    }
+
+    #[test]
+    fn test_ripple_delete_spanning_region() {
+        let (mut timeline, _) = timeline_with(&[(0, 4000)]);
+        let original = timeline.tracks[0].regions[0].id;
+        timeline.selection.add_region(original);
+        timeline.ripple_delete(range(1000, 2000), None).unwrap();
+
+        // Both sides survive and the content after the range stays aligned
+        assert_eq!(spans(&timeline), vec![(0, 1000, 0), (1000, 3000, 2000)]);
+        let tail = timeline.tracks[0].regions[1].id;
+        assert_ne!(tail, original);
+        assert!(timeline.selection.contains_region(tail));
+    }
+
+    #[test]
+    fn test_ripple_changes_undo_and_redo() {
+        let (mut timeline, _) = timeline_with(&[(0, 500), (800, 4000), (5000, 6000)]);
+        let original = spans(&timeline);
+        let changes = timeline
+            .ripple_with_changes(RippleEdit::Delete(range(1000, 2000)), None)
+            .unwrap();
+        let rippled = timeline.tracks[0].regions.clone();
+        // Regions before the edit aren't kept
+        assert_eq!(changes.before.len(), 2);
+
+        timeline.undo_ripple(&changes);
+        assert_eq!(spans(&timeline), original);
+        assert_eq!(timeline.tracks[0].regions.len(), 3);
+        timeline.redo_ripple(&changes);
+        assert_eq!(timeline.tracks[0].regions, rippled);
+    }
+
+    #[test]
+    fn test_ripple_insert() {
+        let (mut timeline, _) = timeline_with(&[(0, 1000), (1000, 2000), (3000, 3500)]);
+        timeline
+            .ripple_insert(SamplePosition(500), SamplePosition(250), None)
+            .unwrap();
+        assert_eq!(
+            spans(&timeline),
+            vec![
+                (0, 500, 0),
+                (750, 1250, 500),
+                (1250, 2250, 0),
+                (3250, 3750, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ripple_scope_and_locks() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let mut ids = Vec::new();
+        for track in [a, b] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(3000), SamplePosition(1000));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+
+        timeline.ripple_delete(range(0, 1000), Some(&[b])).unwrap();
+        assert_eq!(
+            timeline.get_region(ids[0]).unwrap().start,
+            SamplePosition(3000)
+        );
+        assert_eq!(
+            timeline.get_region(ids[1]).unwrap().start,
+            SamplePosition(2000)
+        );
+
+        timeline.set_region_locked(ids[1], true).unwrap();
+        assert_eq!(
+            timeline.ripple_delete(range(0, 1000), None),
+            Err(TimelineError::RegionLocked(ids[1]))
+        );
+        assert_eq!(
+            timeline.get_region(ids[0]).unwrap().start,
+            SamplePosition(3000)
+        );
+
+        // Locked regions before the edit don't block it
+        timeline
+            .trim_region(ids[0], RegionEdge::Start, SamplePosition(3500))
+            .unwrap();
+        assert!(timeline
+            .ripple_insert(SamplePosition(2500), SamplePosition(10), Some(&[a]))
+            .is_ok());
+        assert_eq!(
+            timeline.ripple_delete(range(0, 10), Some(&[TrackId(9)])),
+            Err(TimelineError::TrackNotFound(TrackId(9)))
+        );
+    }
+
+    #[test]
+    fn test_map_position() {
+        let edit = RippleEdit::Delete(range(100, 200));
+        assert_eq!(
+            edit.map_position(SamplePosition(99)),
+            Some(SamplePosition(99))
+        );
+        assert_eq!(edit.map_position(SamplePosition(100)), None);
+        assert_eq!(
+            edit.map_position(SamplePosition(200)),
+            Some(SamplePosition(100))
+        );
+        let edit = RippleEdit::Insert {
+            position: SamplePosition(100),
+            length: SamplePosition(50),
+        };
+        assert_eq!(
+            edit.map_position(SamplePosition(100)),
+            Some(SamplePosition(150))
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Finding and renaming tracks and regions by name
+
+use crate::{RegionId, Timeline, TimelineError, TimelineEvent, TrackId};
+
+/// Case-insensitive name pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameQuery {
+    pattern: Vec<char>,
+    glob: bool,
+}
+
+impl NameQuery {
+    /// Match names containing `text`
+    pub fn substring(text: &str) -> Self {
+        Self {
+            pattern: text.to_lowercase().chars().collect(),
+            glob: false,
+        }
+    }
+
+    /// Match whole names against a pattern where `*` stands for any run of
+    /// characters and `?` for any one character, e.g. `Gtr*`
+    pub fn glob(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_lowercase().chars().collect(),
+            glob: true,
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.to_lowercase().chars().collect();
+        if self.glob {
+            glob_match(&self.pattern, &name)
+        } else {
+            self.pattern.is_empty() || name.windows(self.pattern.len()).any(|w| w == self.pattern)
+        }
+    }
+}
+
+impl From<&str> for NameQuery {
+    fn from(text: &str) -> Self {
+        Self::substring(text)
+    }
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    // Classic two-pointer match, backtracking to the last `*`
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Trimmed name, or an error if nothing is left
+fn clean_name(name: &str) -> Result<String, TimelineError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(TimelineError::EmptyName);
+    }
+    Ok(name.to_string())
+}
+
+impl Timeline {
+    /// Tracks whose names match, in track order
+    pub fn find_tracks(&self, query: impl Into<NameQuery>) -> Vec<TrackId> {
+        let query = query.into();
+        self.tracks
+            .iter()
+            .filter(|t| query.matches(&t.name))
+            .map(|t| t.id)
+            .collect()
+    }
+
+    /// Regions whose names match, in track order then by start
+    pub fn find_regions(&self, query: impl Into<NameQuery>) -> Vec<RegionId> {
+        let query = query.into();
+        self.tracks
+            .iter()
+            .flat_map(|t| t.regions.iter())
+            .filter(|r| query.matches(&r.name))
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// Rename a track, trimming whitespace. Returns the previous name.
+    pub fn rename_track(&mut self, id: TrackId, name: &str) -> Result<String, TimelineError> {
+        let name = clean_name(name)?;
+        let track = self
+            .get_track_mut(id)
+            .ok_or(TimelineError::TrackNotFound(id))?;
+        let old = std::mem::replace(&mut track.name, name);
+        self.emit(TimelineEvent::TrackChanged(id));
+        Ok(old)
+    }
+
+    /// Rename a region, trimming whitespace. Returns the previous name.
+    pub fn rename_region(&mut self, id: RegionId, name: &str) -> Result<String, TimelineError> {
+        let name = clean_name(name)?;
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let old = std::mem::replace(&mut self.tracks[t].regions[r].name, name);
+        self.emit(TimelineEvent::RegionChanged(id));
+        Ok(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, TrackType};
+    use koto_core::SamplePosition;
+
+    #[test]
+    fn test_find_by_name() {
+        let mut timeline = Timeline::new();
+        let names = [
+            "Gtr Left",
+            "Bass",
+            "GTR right",
+            "Vocals",
+            "Ärger",
+            "Überton",
+        ];
+        let ids: Vec<TrackId> = names
+            .iter()
+            .map(|name| timeline.add_track(*name, TrackType::Audio))
+            .collect();
+
+        assert_eq!(timeline.find_tracks("gtr"), vec![ids[0], ids[2]]);
+        assert_eq!(timeline.find_tracks("GTR"), vec![ids[0], ids[2]]);
+        assert_eq!(timeline.find_tracks("ärg"), vec![ids[4]]);
+        assert_eq!(timeline.find_tracks("ÜBER"), vec![ids[5]]);
+        assert_eq!(
+            timeline.find_tracks(NameQuery::glob("gtr*")),
+            vec![ids[0], ids[2]]
+        );
+        assert_eq!(
+            timeline.find_tracks(NameQuery::glob("*s")),
+            vec![ids[1], ids[3]]
+        );
+        assert_eq!(timeline.find_tracks(NameQuery::glob("b?ss")), vec![ids[1]]);
+        assert!(timeline.find_tracks(NameQuery::glob("gtr")).is_empty());
+
+        let mut regions = Vec::new();
+        for (track, start, name) in [
+            (ids[2], 0, "Riff B"),
+            (ids[0], 500, "Riff A"),
+            (ids[0], 0, "Intro riff"),
+        ] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(100));
+            region.name = name.into();
+            timeline.add_region(region).unwrap();
+            regions.push(id);
+        }
+        assert_eq!(
+            timeline.find_regions("riff"),
+            vec![regions[2], regions[1], regions[0]]
+        );
+    }
+
+    #[test]
+    fn test_rename_trims_and_rejects_empty() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Audio 1", TrackType::Audio);
+        assert_eq!(
+            timeline.rename_track(track, "  Drums "),
+            Ok("Audio 1".to_string())
+        );
+        assert_eq!(timeline.get_track(track).unwrap().name, "Drums");
+        assert_eq!(
+            timeline.rename_track(track, " \t"),
+            Err(TimelineError::EmptyName)
+        );
+        assert_eq!(timeline.get_track(track).unwrap().name, "Drums");
+        assert_eq!(
+            timeline.rename_region(RegionId(7), "Fill"),
+            Err(TimelineError::RegionNotFound(RegionId(7)))
+        );
+    }
+}
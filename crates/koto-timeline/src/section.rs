@@ -0,0 +1,279 @@
+//! Arrangement sections such as Intro, Verse and Chorus
+
+use crate::{Timeline, TimelineError, TimelineEvent};
+use koto_core::{SamplePosition, SampleRange};
+use serde::{Deserialize, Serialize};
+
+/// Unique identifier for arrangement sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SectionId(pub u64);
+
+/// A named span of the arrangement
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub id: SectionId,
+    pub name: String,
+    pub range: SampleRange,
+    pub color: u32,
+}
+
+impl Timeline {
+    /// Sections sorted by start; they never overlap
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn get_section(&self, id: SectionId) -> Option<&Section> {
+        self.sections.iter().find(|s| s.id == id)
+    }
+
+    /// Section containing `position`
+    pub fn section_at(&self, position: SamplePosition) -> Option<&Section> {
+        let index = self.sections.partition_point(|s| s.range.start <= position);
+        index
+            .checked_sub(1)
+            .map(|i| &self.sections[i])
+            .filter(|s| s.range.contains(position))
+    }
+
+    /// Add a section; it may not overlap an existing one
+    pub fn add_section(
+        &mut self,
+        name: impl Into<String>,
+        range: SampleRange,
+        color: u32,
+    ) -> Result<SectionId, TimelineError> {
+        self.check_section_range(None, range)?;
+        let id = SectionId(self.next_section_id);
+        self.next_section_id += 1;
+        self.insert_section(Section {
+            id,
+            name: name.into(),
+            range,
+            color,
+        });
+        self.emit(TimelineEvent::SectionsChanged);
+        Ok(id)
+    }
+
+    pub fn remove_section(&mut self, id: SectionId) -> Result<Section, TimelineError> {
+        let index = self.section_index(id)?;
+        self.emit(TimelineEvent::SectionsChanged);
+        Ok(self.sections.remove(index))
+    }
+
+    /// Change a section's range and return the previous one. Fails if it
+    /// would overlap another section.
+    pub fn resize_section(
+        &mut self,
+        id: SectionId,
+        range: SampleRange,
+    ) -> Result<SampleRange, TimelineError> {
+        self.check_section_range(Some(id), range)?;
+        let mut section = self.sections.remove(self.section_index(id)?);
+        let old = std::mem::replace(&mut section.range, range);
+        self.insert_section(section);
+        self.emit(TimelineEvent::SectionsChanged);
+        Ok(old)
+    }
+
+    /// Copy a section and its regions to the end of the arrangement
+    ///
+    /// The copy starts where the last region or section ends. Regions
+    /// reaching past either boundary are trimmed to the section in the copy;
+    /// the originals are untouched. Returns the new section.
+    pub fn duplicate_section(&mut self, id: SectionId) -> Result<SectionId, TimelineError> {
+        let section = self
+            .get_section(id)
+            .cloned()
+            .ok_or(TimelineError::SectionNotFound(id))?;
+        let range = section.range;
+        for track in &self.tracks {
+            if track.regions_in_range(range).next().is_some() {
+                track.check_editable()?;
+            }
+        }
+
+        let end = self
+            .sections
+            .iter()
+            .map(|s| s.range.end)
+            .fold(self.length(), SamplePosition::max);
+        let offset = end - range.start;
+
+        let copies: Vec<_> = self
+            .tracks
+            .iter()
+            .flat_map(|t| t.regions_in_range(range).cloned())
+            .collect();
+        for mut copy in copies {
+            copy.id = self.new_region_id();
+            if copy.start < range.start {
+                copy.trim_start(range.start);
+            }
+            if copy.end() > range.end {
+                copy.trim_end(range.end);
+            }
+            copy.start = copy.start + offset;
+            let t = self
+                .track_index(copy.track_id)
+                .ok_or(TimelineError::TrackNotFound(copy.track_id))?;
+            let id = copy.id;
+            self.tracks[t].add_region(copy);
+            self.emit(TimelineEvent::RegionAdded(id));
+        }
+
+        let new_range = SampleRange::new(end, range.end + offset);
+        self.add_section(section.name, new_range, section.color)
+    }
+
+    fn check_section_range(
+        &self,
+        id: Option<SectionId>,
+        range: SampleRange,
+    ) -> Result<(), TimelineError> {
+        if range.is_empty() {
+            return Err(TimelineError::EmptySection);
+        }
+        match self
+            .sections
+            .iter()
+            .find(|s| Some(s.id) != id && s.range.start < range.end && range.start < s.range.end)
+        {
+            Some(other) => Err(TimelineError::SectionOverlap(other.id)),
+            None => Ok(()),
+        }
+    }
+
+    fn section_index(&self, id: SectionId) -> Result<usize, TimelineError> {
+        self.sections
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or(TimelineError::SectionNotFound(id))
+    }
+
+    fn insert_section(&mut self, section: Section) {
+        let index = self
+            .sections
+            .partition_point(|s| s.range.start <= section.range.start);
+        self.sections.insert(index, section);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, RegionId, TrackId, TrackType};
+
+    fn range(start: i64, end: i64) -> SampleRange {
+        SampleRange::new(SamplePosition(start), SamplePosition(end))
+    }
+
+    fn place(timeline: &mut Timeline, track: TrackId, start: i64, length: i64) -> RegionId {
+        let id = timeline.new_region_id();
+        let mut region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+        region.name = format!("R{start}");
+        timeline.add_region(region).unwrap();
+        id
+    }
+
+    fn spans(timeline: &Timeline, track: TrackId) -> Vec<(String, i64, i64, i64)> {
+        timeline
+            .get_track(track)
+            .unwrap()
+            .regions
+            .iter()
+            .map(|r| (r.name.clone(), r.start.0, r.end().0, r.source_offset.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_sections_reject_overlap() {
+        let mut timeline = Timeline::new();
+        let verse = timeline.add_section("Verse", range(1000, 2000), 0).unwrap();
+        let intro = timeline.add_section("Intro", range(0, 1000), 0).unwrap();
+        assert_eq!(
+            timeline.add_section("Bridge", range(1500, 2500), 0),
+            Err(TimelineError::SectionOverlap(verse))
+        );
+        assert_eq!(
+            timeline.add_section("Gap", range(3000, 3000), 0),
+            Err(TimelineError::EmptySection)
+        );
+        assert_eq!(timeline.section_at(SamplePosition(999)).unwrap().id, intro);
+        assert_eq!(timeline.section_at(SamplePosition(1000)).unwrap().id, verse);
+        assert!(timeline.section_at(SamplePosition(2000)).is_none());
+
+        assert_eq!(
+            timeline.resize_section(intro, range(0, 1200)),
+            Err(TimelineError::SectionOverlap(verse))
+        );
+        assert_eq!(
+            timeline.resize_section(verse, range(1000, 1800)),
+            Ok(range(1000, 2000))
+        );
+        timeline.remove_section(intro).unwrap();
+        assert_eq!(
+            timeline.resize_section(verse, range(0, 1800)),
+            Ok(range(1000, 1800))
+        );
+        assert_eq!(timeline.sections().len(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_section_trims_straddling_regions() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        // Straddles the start, sits inside, straddles the end
+        place(&mut timeline, a, 500, 1000);
+        place(&mut timeline, a, 1600, 200);
+        place(&mut timeline, a, 1900, 600);
+        // Spans the whole section, and one entirely outside it
+        place(&mut timeline, b, 0, 4000);
+        place(&mut timeline, b, 2000, 100);
+        let chorus = timeline
+            .add_section("Chorus", range(1000, 2000), 7)
+            .unwrap();
+
+        let copy = timeline.duplicate_section(chorus).unwrap();
+        let section = timeline.get_section(copy).unwrap();
+        assert_eq!(
+            (section.name.as_str(), section.range),
+            ("Chorus", range(4000, 5000))
+        );
+        assert_eq!(section.color, 7);
+
+        assert_eq!(
+            spans(&timeline, a)[3..],
+            [
+                ("R500".into(), 4000, 4500, 500),
+                ("R1600".into(), 4600, 4800, 0),
+                ("R1900".into(), 4900, 5000, 0),
+            ]
+        );
+        assert_eq!(spans(&timeline, b)[2..], [("R0".into(), 4000, 5000, 1000)]);
+        // Originals untouched
+        assert_eq!(spans(&timeline, a)[0], ("R500".into(), 500, 1500, 0));
+        assert_eq!(timeline.length(), SamplePosition(5000));
+    }
+
+    #[test]
+    fn test_duplicate_section_after_later_sections() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("A", TrackType::Audio);
+        place(&mut timeline, track, 0, 100);
+        let intro = timeline.add_section("Intro", range(0, 1000), 0).unwrap();
+        timeline.add_section("Outro", range(1000, 3000), 0).unwrap();
+
+        let copy = timeline.duplicate_section(intro).unwrap();
+        assert_eq!(timeline.get_section(copy).unwrap().range, range(3000, 4000));
+        assert_eq!(spans(&timeline, track)[1], ("R0".into(), 3000, 3100, 0));
+
+        timeline.set_track_locked(track, true).unwrap();
+        assert_eq!(
+            timeline.duplicate_section(intro),
+            Err(TimelineError::TrackLocked(track))
+        );
+    }
+}
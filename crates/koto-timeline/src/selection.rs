@@ -0,0 +1,300 @@
+//! What the user has selected on the timeline
+
+use crate::{NudgeReport, Region, RegionId, Timeline, TimelineError, TimelineEvent, TrackId};
+use koto_core::{SamplePosition, SampleRange};
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+/// Selected regions, tracks and time range
+///
+/// IDs can outlive what they refer to; queries skip anything that no
+/// longer exists.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Selection {
+    regions: HashSet<RegionId>,
+    tracks: HashSet<TrackId>,
+    pub time_range: Option<SampleRange>,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_region(&mut self, id: RegionId) {
+        self.regions.insert(id);
+    }
+
+    pub fn remove_region(&mut self, id: RegionId) {
+        self.regions.remove(&id);
+    }
+
+    pub fn toggle_region(&mut self, id: RegionId) {
+        if !self.regions.remove(&id) {
+            self.regions.insert(id);
+        }
+    }
+
+    pub fn contains_region(&self, id: RegionId) -> bool {
+        self.regions.contains(&id)
+    }
+
+    pub fn add_track(&mut self, id: TrackId) {
+        self.tracks.insert(id);
+    }
+
+    pub fn remove_track(&mut self, id: TrackId) {
+        self.tracks.remove(&id);
+    }
+
+    pub fn toggle_track(&mut self, id: TrackId) {
+        if !self.tracks.remove(&id) {
+            self.tracks.insert(id);
+        }
+    }
+
+    pub fn contains_track(&self, id: TrackId) -> bool {
+        self.tracks.contains(&id)
+    }
+
+    pub fn region_ids(&self) -> impl Iterator<Item = RegionId> + '_ {
+        self.regions.iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty() && self.tracks.is_empty() && self.time_range.is_none()
+    }
+
+    /// Deselect everything
+    pub fn clear(&mut self) {
+        self.regions.clear();
+        self.tracks.clear();
+        self.time_range = None;
+    }
+
+    /// Selected regions that still exist, in track order then by start
+    pub fn selected_regions<'a>(&self, timeline: &'a Timeline) -> Vec<&'a Region> {
+        timeline
+            .tracks
+            .iter()
+            .flat_map(|t| t.regions.iter())
+            .filter(|r| self.regions.contains(&r.id))
+            .collect()
+    }
+
+    /// Selected tracks that still exist, in track order
+    pub fn selected_tracks(&self, timeline: &Timeline) -> Vec<TrackId> {
+        timeline
+            .tracks
+            .iter()
+            .map(|t| t.id)
+            .filter(|id| self.tracks.contains(id))
+            .collect()
+    }
+}
+
+impl Timeline {
+    /// Select the tracks at `tracks` (display indices) and every region on
+    /// them that intersects `time`, adding to the current selection
+    pub fn select_in_rect(&mut self, tracks: RangeInclusive<usize>, time: SampleRange) {
+        let end = (*tracks.end()).min(self.tracks.len().saturating_sub(1));
+        for track in self.tracks.get(*tracks.start()..=end).unwrap_or_default() {
+            self.selection.add_track(track.id);
+            for region in track.regions_in_range(time) {
+                self.selection.add_region(region.id);
+            }
+        }
+        self.selection.time_range = Some(time);
+    }
+
+    /// IDs of the selected regions that still exist
+    pub(crate) fn selected_region_ids(&self) -> Vec<RegionId> {
+        self.selection
+            .selected_regions(self)
+            .iter()
+            .map(|r| r.id)
+            .collect()
+    }
+
+    /// Delete all selected regions, or none if any of them is locked
+    ///
+    /// Returns the deleted regions.
+    pub fn delete_selected(&mut self) -> Result<Vec<Region>, TimelineError> {
+        let ids = self.selected_region_ids();
+        self.delete_regions(&ids)
+    }
+
+    /// [`delete_selected`](Self::delete_selected) for the regions `ids`,
+    /// e.g. the ones selected when an edit was first made
+    pub fn delete_regions(&mut self, ids: &[RegionId]) -> Result<Vec<Region>, TimelineError> {
+        for &id in ids {
+            self.check_editable(id)?;
+        }
+        let removed = ids.iter().filter_map(|&id| self.take_region(id)).collect();
+        for &id in ids {
+            self.selection.remove_region(id);
+        }
+        Ok(removed)
+    }
+
+    /// Shift all selected regions together by `delta` samples
+    ///
+    /// The nudge stops when the earliest region reaches zero, so the
+    /// regions keep their spacing. Locked regions, and regions on locked
+    /// tracks, stay put and are reported as skipped. The overlap policy
+    /// isn't applied, since the regions move together.
+    pub fn nudge_selected(&mut self, delta: i64) -> Result<NudgeReport, TimelineError> {
+        let ids = self.selected_region_ids();
+        self.nudge_all(&ids, delta)
+    }
+
+    /// [`nudge_selected`](Self::nudge_selected) for the regions `ids`,
+    /// e.g. the ones selected when an edit was first made
+    ///
+    /// Fails without changes if a region is missing or on a frozen track.
+    pub fn nudge_all(
+        &mut self,
+        ids: &[RegionId],
+        delta: i64,
+    ) -> Result<NudgeReport, TimelineError> {
+        let (movable, skipped) = self.movable(ids)?;
+        let delta = self.clamp_shift(&movable, delta);
+        let mut moved = Vec::with_capacity(movable.len());
+        for id in movable {
+            if let Some(start) = self.get_region(id).map(|r| r.start) {
+                moved.push((id, start));
+                self.set_region_start(id, SamplePosition(start.0 + delta));
+            }
+        }
+        // Only once all have moved, as neighbours move together
+        self.regions_changed();
+        Ok(NudgeReport { moved, skipped })
+    }
+
+    /// Split `ids` into editable regions and locked ones
+    pub(crate) fn movable(
+        &self,
+        ids: &[RegionId],
+    ) -> Result<(Vec<RegionId>, Vec<RegionId>), TimelineError> {
+        let mut movable = Vec::with_capacity(ids.len());
+        let mut skipped = Vec::new();
+        for &id in ids {
+            match self.check_editable(id) {
+                Ok(()) => movable.push(id),
+                Err(TimelineError::RegionLocked(_) | TimelineError::TrackLocked(_)) => {
+                    skipped.push(id)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok((movable, skipped))
+    }
+
+    /// `delta` limited so none of the regions `ids` would start before zero
+    fn clamp_shift(&self, ids: &[RegionId], delta: i64) -> i64 {
+        let earliest = ids
+            .iter()
+            .filter_map(|&id| self.get_region(id))
+            .map(|r| r.start.0)
+            .min();
+        earliest.map_or(delta, |earliest| delta.max(-earliest))
+    }
+
+    /// Move a region's start without any checks, keeping the track sorted.
+    /// Crossfades are left for the caller to sync.
+    pub(crate) fn set_region_start(&mut self, id: RegionId, start: SamplePosition) {
+        let Some((t, r)) = self.locate_region(id) else {
+            return;
+        };
+        let track = &mut self.tracks[t];
+        let mut region = track.regions.remove(r);
+        region.start = start;
+        track.add_region(region);
+        self.emit(TimelineEvent::RegionMoved(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TrackType;
+
+    fn setup() -> (Timeline, [TrackId; 2], [RegionId; 3]) {
+        let mut timeline = Timeline::new();
+        let tracks = [
+            timeline.add_track("A", TrackType::Audio),
+            timeline.add_track("B", TrackType::Audio),
+        ];
+        let mut regions = [RegionId(0); 3];
+        for (i, (track, start)) in [(0, 0), (0, 2000), (1, 500)].into_iter().enumerate() {
+            regions[i] = timeline.new_region_id();
+            let region = Region::new(
+                regions[i],
+                tracks[track],
+                SamplePosition(start),
+                SamplePosition(1000),
+            );
+            timeline.add_region(region).unwrap();
+        }
+        (timeline, tracks, regions)
+    }
+
+    #[test]
+    fn test_select_in_rect_and_updates() {
+        let (mut timeline, tracks, [a, b, c]) = setup();
+        let time = SampleRange::new(SamplePosition(600), SamplePosition(1500));
+        timeline.select_in_rect(0..=5, time);
+        let ids: Vec<RegionId> = timeline
+            .selection
+            .selected_regions(&timeline)
+            .iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, vec![a, c]);
+        assert!(!timeline.selection.contains_region(b));
+        assert_eq!(timeline.selection.time_range, Some(time));
+
+        // Splitting a selected region selects both halves
+        let (_, tail) = timeline.split_region(a, SamplePosition(300)).unwrap();
+        assert!(timeline.selection.contains_region(tail));
+
+        timeline.remove_track(tracks[1]);
+        assert!(!timeline.selection.contains_track(tracks[1]));
+        assert!(!timeline.selection.contains_region(c));
+        assert_eq!(timeline.selection.selected_regions(&timeline).len(), 2);
+
+        timeline.selection.toggle_region(a);
+        assert!(!timeline.selection.contains_region(a));
+        timeline.selection.clear();
+        assert!(timeline.selection.is_empty());
+    }
+
+    #[test]
+    fn test_delete_and_nudge_selected_are_atomic() {
+        let (mut timeline, _, [a, b, c]) = setup();
+        timeline.selection.add_region(a);
+        timeline.selection.add_region(c);
+
+        let previous = timeline.nudge_selected(-700).unwrap().moved;
+        assert_eq!(
+            previous,
+            vec![(a, SamplePosition(0)), (c, SamplePosition(500))]
+        );
+        assert_eq!(timeline.get_region(a).unwrap().start, SamplePosition(0));
+        // Held back by `a`, which is already at zero
+        assert_eq!(timeline.get_region(c).unwrap().start, SamplePosition(500));
+
+        timeline.set_region_locked(c, true).unwrap();
+        assert_eq!(
+            timeline.delete_selected(),
+            Err(TimelineError::RegionLocked(c))
+        );
+        assert!(timeline.get_region(a).is_some());
+
+        timeline.set_region_locked(c, false).unwrap();
+        let removed = timeline.delete_selected().unwrap();
+        assert_eq!(removed.len(), 2);
+        assert!(timeline.get_region(b).is_some());
+        assert!(timeline.selection.selected_regions(&timeline).is_empty());
+    }
+}
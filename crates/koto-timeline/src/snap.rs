@@ -0,0 +1,237 @@
+//! Grid and magnetic snapping for edit positions
+
+use crate::{Timeline, TrackId};
+use koto_core::{SamplePosition, TimeConverter, TICKS_PER_QUARTER_NOTE};
+use serde::{Deserialize, Serialize};
+
+/// Grid that positions snap to
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SnapMode {
+    Bar,
+    Beat,
+    /// Note division of a whole note, e.g. 8 for eighth notes
+    Division(u32),
+    Seconds(f64),
+    /// No grid; sample accurate
+    Samples,
+}
+
+/// Snapping configuration shared by edit operations and the UI
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SnapSettings {
+    pub enabled: bool,
+    pub mode: SnapMode,
+    /// Pull positions to nearby region edges and markers
+    pub magnetic: bool,
+    /// How close an edge or marker must be to pull, in samples
+    pub magnetic_tolerance: SamplePosition,
+}
+
+impl Default for SnapSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: SnapMode::Beat,
+            magnetic: false,
+            magnetic_tolerance: SamplePosition(1000),
+        }
+    }
+}
+
+impl SnapSettings {
+    /// Nearest grid line to `position`; ties go to the later line
+    pub fn snap_to_grid(
+        &self,
+        position: SamplePosition,
+        converter: &TimeConverter,
+    ) -> SamplePosition {
+        if let SnapMode::Seconds(seconds) = self.mode {
+            let step = converter.seconds_to_samples(seconds).0;
+            if step <= 0 {
+                return position;
+            }
+            let index = position.0.div_euclid(step);
+            return nearest_line(position, index, |k| SamplePosition(k * step));
+        }
+        let Some(grid_ticks) = self.grid_ticks(converter) else {
+            return position;
+        };
+        let index = converter.samples_to_ticks(position).div_euclid(grid_ticks);
+        nearest_line(position, index, |k| {
+            converter.ticks_to_samples(k * grid_ticks)
+        })
+    }
+
+    /// Distance of `units` grid steps in samples; one step is one sample
+    /// in [`SnapMode::Samples`]
+    pub fn grid_to_samples(&self, units: i64, converter: &TimeConverter) -> i64 {
+        if let SnapMode::Seconds(seconds) = self.mode {
+            return converter.seconds_to_samples(seconds * units as f64).0;
+        }
+        match self.grid_ticks(converter) {
+            Some(ticks) => converter.ticks_to_samples(units * ticks).0,
+            None => units,
+        }
+    }
+
+    /// Grid spacing in ticks for the musical modes
+    fn grid_ticks(&self, converter: &TimeConverter) -> Option<i64> {
+        let quarter = TICKS_PER_QUARTER_NOTE as i64;
+        match self.mode {
+            SnapMode::Bar => Some(converter.time_signature().beats_per_bar() as i64 * quarter),
+            SnapMode::Beat => Some(quarter),
+            SnapMode::Division(n) => Some((4 * quarter / n.max(1) as i64).max(1)),
+            SnapMode::Seconds(_) | SnapMode::Samples => None,
+        }
+    }
+}
+
+/// Nearest of the grid lines `line(k)`, starting the search from `index`
+fn nearest_line(
+    position: SamplePosition,
+    mut index: i64,
+    line: impl Fn(i64) -> SamplePosition,
+) -> SamplePosition {
+    // The estimate can be off by one where ticks round across a line
+    while line(index) > position {
+        index -= 1;
+    }
+    while line(index + 1) <= position {
+        index += 1;
+    }
+    let (before, after) = (line(index), line(index + 1));
+    if position.0 - before.0 < after.0 - position.0 {
+        before
+    } else {
+        after
+    }
+}
+
+impl Timeline {
+    /// Snap an edit position according to `settings`
+    ///
+    /// Magnetic snapping considers region edges on every track.
+    pub fn snap(
+        &self,
+        position: SamplePosition,
+        settings: &SnapSettings,
+        converter: &TimeConverter,
+    ) -> SamplePosition {
+        self.snap_with(position, settings, converter, None, &[])
+    }
+
+    /// Snap with magnetic snapping limited to region edges on `track` (or
+    /// every track) and also pulled to `markers`
+    ///
+    /// The nearest edge or marker within the tolerance wins over the grid.
+    pub fn snap_with(
+        &self,
+        position: SamplePosition,
+        settings: &SnapSettings,
+        converter: &TimeConverter,
+        track: Option<TrackId>,
+        markers: &[SamplePosition],
+    ) -> SamplePosition {
+        if !settings.enabled {
+            return position;
+        }
+        if settings.magnetic {
+            let edges = self
+                .tracks
+                .iter()
+                .filter(|t| track.is_none_or(|id| t.id == id))
+                .flat_map(|t| t.regions.iter())
+                .flat_map(|r| [r.start, r.end()]);
+            let nearest = edges
+                .chain(markers.iter().copied())
+                .map(|p| (p, (p.0 - position.0).abs()))
+                .filter(|&(_, distance)| distance <= settings.magnetic_tolerance.0)
+                .min_by_key(|&(p, distance)| (distance, p));
+            if let Some((edge, _)) = nearest {
+                return edge;
+            }
+        }
+        settings.snap_to_grid(position, converter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, TrackType};
+    use koto_core::{SampleRate, Tempo, TimeSignature};
+
+    /// 120 BPM at 48 kHz: 24000 samples per beat
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    fn settings(mode: SnapMode) -> SnapSettings {
+        SnapSettings {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snap_to_grid_modes_and_ties() {
+        let timeline = Timeline::new();
+        let conv = converter();
+        let snap = |p, mode| timeline.snap(SamplePosition(p), &settings(mode), &conv).0;
+
+        assert_eq!(snap(11999, SnapMode::Beat), 0);
+        assert_eq!(snap(12001, SnapMode::Beat), 24000);
+        // Exactly between two lines goes to the later one
+        assert_eq!(snap(12000, SnapMode::Beat), 24000);
+        assert_eq!(snap(47999, SnapMode::Bar), 0);
+        assert_eq!(snap(48001, SnapMode::Bar), 96000);
+        assert_eq!(snap(5000, SnapMode::Division(16)), 6000);
+        assert_eq!(snap(7000, SnapMode::Seconds(0.1)), 4800);
+        assert_eq!(snap(7200, SnapMode::Seconds(0.1)), 9600);
+        assert_eq!(snap(7001, SnapMode::Samples), 7001);
+        assert_eq!(snap(-13000, SnapMode::Beat), -24000);
+
+        let disabled = SnapSettings {
+            enabled: false,
+            ..settings(SnapMode::Bar)
+        };
+        assert_eq!(
+            timeline.snap(SamplePosition(12345), &disabled, &conv),
+            SamplePosition(12345)
+        );
+    }
+
+    #[test]
+    fn test_magnetic_snap_to_edges_and_markers() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, a, SamplePosition(30000), SamplePosition(1000));
+        timeline.add_region(region).unwrap();
+
+        let conv = converter();
+        let magnetic = SnapSettings {
+            magnetic: true,
+            magnetic_tolerance: SamplePosition(500),
+            ..settings(SnapMode::Beat)
+        };
+        let snap = |p, track, markers: &[SamplePosition]| {
+            timeline
+                .snap_with(SamplePosition(p), &magnetic, &conv, track, markers)
+                .0
+        };
+
+        assert_eq!(snap(30400, None, &[]), 30000);
+        assert_eq!(snap(30900, Some(a), &[]), 31000);
+        // Edges on other tracks are ignored when a track is given
+        assert_eq!(snap(30400, Some(b), &[]), 24000);
+        // Out of tolerance falls back to the grid
+        assert_eq!(snap(29000, None, &[]), 24000);
+        assert_eq!(snap(40100, Some(b), &[SamplePosition(40000)]), 40000);
+    }
+}
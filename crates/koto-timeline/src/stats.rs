@@ -0,0 +1,131 @@
+//! Song length and summary statistics
+
+use crate::{Timeline, Track, TrackId, TrackType};
+use koto_core::SamplePosition;
+use std::collections::HashMap;
+
+/// Summary of a timeline's contents
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TimelineStats {
+    pub tracks_by_type: HashMap<TrackType, usize>,
+    pub region_count: usize,
+    /// Sum of all region lengths
+    pub total_region_duration: SamplePosition,
+}
+
+impl Track {
+    /// End of the last region, or zero if the track is empty
+    pub fn length(&self) -> SamplePosition {
+        self.cached_length.unwrap_or_else(|| self.compute_length())
+    }
+
+    fn compute_length(&self) -> SamplePosition {
+        self.regions
+            .iter()
+            .map(|r| r.end())
+            .max()
+            .unwrap_or(SamplePosition::ZERO)
+            .max(SamplePosition::ZERO)
+    }
+
+    /// Recompute the cached length after regions changed
+    pub fn refresh_length(&mut self) {
+        self.cached_length = Some(self.compute_length());
+    }
+}
+
+impl Timeline {
+    /// End of the last region on any track, or zero if there are none
+    pub fn length(&self) -> SamplePosition {
+        self.tracks
+            .iter()
+            .map(|t| t.length())
+            .max()
+            .unwrap_or(SamplePosition::ZERO)
+    }
+
+    pub fn track_length(&self, id: TrackId) -> Option<SamplePosition> {
+        self.get_track(id).map(|t| t.length())
+    }
+
+    pub fn stats(&self) -> TimelineStats {
+        let mut stats = TimelineStats::default();
+        for track in &self.tracks {
+            *stats.tracks_by_type.entry(track.track_type).or_default() += 1;
+            stats.region_count += track.regions.len();
+            stats.total_region_duration.0 += track.regions.iter().map(|r| r.length.0).sum::<i64>();
+        }
+        stats
+    }
+
+    /// Bring derived state up to date after regions were edited
+    pub(crate) fn regions_changed(&mut self) {
+        self.sync_crossfades();
+        for track in &mut self.tracks {
+            track.refresh_length();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, RegionEdge};
+
+    #[test]
+    fn test_length_follows_edits() {
+        let mut timeline = Timeline::new();
+        assert_eq!(timeline.length(), SamplePosition::ZERO);
+        let a = timeline.add_track("A", TrackType::Audio);
+        let b = timeline.add_track("B", TrackType::Midi);
+        timeline.add_track("Bus", TrackType::Bus);
+
+        let mut ids = Vec::new();
+        for (track, start, length) in [(a, 0, 1000), (a, 5000, 1000), (b, 2000, 500)] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, track, SamplePosition(start), SamplePosition(length));
+            timeline.add_region(region).unwrap();
+            ids.push(id);
+        }
+        assert_eq!(timeline.length(), SamplePosition(6000));
+        assert_eq!(timeline.track_length(b), Some(SamplePosition(2500)));
+
+        timeline
+            .trim_region(ids[1], RegionEdge::End, SamplePosition(5500))
+            .unwrap();
+        assert_eq!(timeline.length(), SamplePosition(5500));
+
+        timeline.remove_region(ids[1]).unwrap();
+        assert_eq!(timeline.length(), SamplePosition(2500));
+        assert_eq!(timeline.track_length(a), Some(SamplePosition(1000)));
+
+        timeline.remove_region(ids[2]).unwrap();
+        timeline.remove_region(ids[0]).unwrap();
+        assert_eq!(timeline.length(), SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        timeline.add_track("B", TrackType::Audio);
+        timeline.add_track("Keys", TrackType::Instrument);
+        for start in [0, 1000] {
+            let id = timeline.new_region_id();
+            let region = Region::new(id, a, SamplePosition(start), SamplePosition(300));
+            timeline.add_region(region).unwrap();
+        }
+
+        let stats = timeline.stats();
+        assert_eq!(stats.tracks_by_type[&TrackType::Audio], 2);
+        assert_eq!(stats.tracks_by_type[&TrackType::Instrument], 1);
+        assert!(!stats.tracks_by_type.contains_key(&TrackType::Bus));
+        assert_eq!(stats.region_count, 2);
+        assert_eq!(stats.total_region_duration, SamplePosition(600));
+
+        // A loaded timeline has no cached lengths yet
+        let json = serde_json::to_string(&timeline).unwrap();
+        let loaded: Timeline = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.length(), SamplePosition(1300));
+    }
+}
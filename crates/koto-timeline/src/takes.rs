@@ -0,0 +1,548 @@
+//! Stacked takes from loop recording and comping between them
+
+use crate::{
+    ConsolidatedContent, Region, RegionContent, RegionId, Timeline, TimelineError, TimelineEvent,
+};
+use koto_core::{MidiClip, SamplePosition, SampleRange, TimeConverter};
+use serde::{Deserialize, Serialize};
+
+/// Identifier of a take within its region
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TakeId(pub u64);
+
+/// One recorded pass over a region
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Take {
+    pub id: TakeId,
+    pub name: String,
+    pub content: RegionContent,
+}
+
+/// Slice of a region played from one take
+///
+/// The range is in samples relative to the region start.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CompSegment {
+    pub range: SampleRange,
+    pub take_id: TakeId,
+}
+
+/// Takes recorded over a region and the comp choosing between them
+///
+/// Once a take exists, the comp tiles the whole region: segments are sorted,
+/// don't overlap, leave no gaps, and neighbours always use different takes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Takes {
+    takes: Vec<Take>,
+    comp: Vec<CompSegment>,
+    next_take_id: u64,
+}
+
+impl Takes {
+    pub fn takes(&self) -> &[Take] {
+        &self.takes
+    }
+
+    pub fn comp(&self) -> &[CompSegment] {
+        &self.comp
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.takes.is_empty()
+    }
+
+    pub fn get(&self, id: TakeId) -> Option<&Take> {
+        self.takes.iter().find(|t| t.id == id)
+    }
+
+    /// Take audible `offset` samples into the region
+    pub fn take_at(&self, offset: SamplePosition) -> Option<TakeId> {
+        self.comp
+            .iter()
+            .find(|s| s.range.start <= offset && offset < s.range.end)
+            .map(|s| s.take_id)
+    }
+
+    fn check_take(&self, id: TakeId) -> Result<(), TimelineError> {
+        match self.get(id) {
+            Some(_) => Ok(()),
+            None => Err(TimelineError::TakeNotFound(id)),
+        }
+    }
+
+    /// Move the comp `shift` samples earlier and fit it to `length`,
+    /// stretching the outer segments over any new room
+    pub(crate) fn retime(&mut self, shift: i64, length: SamplePosition) {
+        let bounds = SampleRange::new(SamplePosition::ZERO, length);
+        self.comp = self
+            .comp
+            .iter()
+            .map(|s| CompSegment {
+                range: clip(
+                    SampleRange::new(
+                        SamplePosition(s.range.start.0 - shift),
+                        SamplePosition(s.range.end.0 - shift),
+                    ),
+                    bounds,
+                ),
+                take_id: s.take_id,
+            })
+            .filter(|s| !s.range.is_empty())
+            .collect();
+        if self.comp.is_empty() {
+            return;
+        }
+        self.comp[0].range.start = SamplePosition::ZERO;
+        let last = self.comp.len() - 1;
+        self.comp[last].range.end = length;
+    }
+
+    /// Append `other`'s comp after `length` samples of this one
+    pub(crate) fn join(&mut self, other: &Takes, length: SamplePosition) {
+        self.comp.retain(|s| s.range.start < length);
+        if let Some(last) = self.comp.last_mut() {
+            last.range.end = length;
+        }
+        self.comp.extend(other.comp.iter().map(|s| CompSegment {
+            range: SampleRange::new(s.range.start + length, s.range.end + length),
+            take_id: s.take_id,
+        }));
+        self.merge_neighbours();
+    }
+
+    fn merge_neighbours(&mut self) {
+        self.comp.dedup_by(|next, prev| {
+            if prev.take_id == next.take_id && prev.range.end == next.range.start {
+                prev.range.end = next.range.end;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+fn clip(range: SampleRange, bounds: SampleRange) -> SampleRange {
+    SampleRange::new(range.start.max(bounds.start), range.end.min(bounds.end))
+}
+
+impl Region {
+    /// Stack a new take over the whole region and make it audible
+    pub fn add_take(&mut self, name: impl Into<String>, content: RegionContent) -> TakeId {
+        let id = TakeId(self.takes.next_take_id);
+        self.takes.next_take_id += 1;
+        self.takes.takes.push(Take {
+            id,
+            name: name.into(),
+            content: content.clone(),
+        });
+        self.takes.comp = vec![CompSegment {
+            range: SampleRange::new(SamplePosition::ZERO, self.length),
+            take_id: id,
+        }];
+        self.content = content;
+        id
+    }
+
+    /// Play one take over the whole region
+    pub fn set_active_take(&mut self, take: TakeId) -> Result<(), TimelineError> {
+        self.takes.check_take(take)?;
+        self.takes.comp = vec![CompSegment {
+            range: SampleRange::new(SamplePosition::ZERO, self.length),
+            take_id: take,
+        }];
+        self.content = self.takes.get(take).unwrap().content.clone();
+        Ok(())
+    }
+
+    /// Play `take` over `range` (relative to the region start), splitting
+    /// the segments it cuts into and merging neighbours using the same take
+    pub fn comp_from_selection(
+        &mut self,
+        range: SampleRange,
+        take: TakeId,
+    ) -> Result<(), TimelineError> {
+        self.takes.check_take(take)?;
+        let range = clip(range, SampleRange::new(SamplePosition::ZERO, self.length));
+        if range.is_empty() {
+            return Err(TimelineError::EmptyRange);
+        }
+
+        let mut comp = Vec::with_capacity(self.takes.comp.len() + 2);
+        for segment in &self.takes.comp {
+            if segment.range.end <= range.start || segment.range.start >= range.end {
+                comp.push(*segment);
+                continue;
+            }
+            if segment.range.start < range.start {
+                comp.push(CompSegment {
+                    range: SampleRange::new(segment.range.start, range.start),
+                    ..*segment
+                });
+            }
+            if segment.range.end > range.end {
+                comp.push(CompSegment {
+                    range: SampleRange::new(range.end, segment.range.end),
+                    ..*segment
+                });
+            }
+        }
+        let index = comp.partition_point(|s| s.range.start < range.start);
+        comp.insert(
+            index,
+            CompSegment {
+                range,
+                take_id: take,
+            },
+        );
+        self.takes.comp = comp;
+        self.takes.merge_neighbours();
+        if let [segment] = self.takes.comp.as_slice() {
+            self.content = self.takes.get(segment.take_id).unwrap().content.clone();
+        }
+        Ok(())
+    }
+
+    /// Single content playing the comp as it stands
+    ///
+    /// While one take covers the whole region that's simply the region's
+    /// content. Otherwise MIDI takes are merged into one clip keeping the
+    /// notes that start in each segment, and audio takes become
+    /// [`ConsolidatedContent`] parts.
+    pub fn flatten_comp(&self, converter: &TimeConverter) -> RegionContent {
+        if self.takes.comp.len() < 2 {
+            return self.content.clone();
+        }
+        let segments: Vec<(&CompSegment, &Take)> = self
+            .takes
+            .comp
+            .iter()
+            .filter_map(|s| self.takes.get(s.take_id).map(|t| (s, t)))
+            .collect();
+
+        if segments
+            .iter()
+            .all(|(_, t)| matches!(t.content, RegionContent::Midi(_)))
+        {
+            let mut clip = MidiClip::new();
+            for (segment, take) in segments {
+                let RegionContent::Midi(notes) = &take.content else {
+                    continue;
+                };
+                let from = converter.samples_to_ticks(self.source_offset + segment.range.start);
+                let to = converter.samples_to_ticks(self.source_offset + segment.range.end);
+                for note in notes
+                    .notes()
+                    .iter()
+                    .filter(|n| n.start >= from && n.start < to)
+                {
+                    clip.add(*note);
+                }
+            }
+            return RegionContent::Midi(clip);
+        }
+
+        let parts = segments
+            .into_iter()
+            .map(|(segment, take)| {
+                let offset = self.source_offset + segment.range.start;
+                let mut part = Region::new(
+                    self.id,
+                    self.track_id,
+                    offset,
+                    SamplePosition(segment.range.length()),
+                );
+                part.name = take.name.clone();
+                part.color = self.color;
+                part.content = take.content.clone();
+                part.source_offset = offset;
+                part
+            })
+            .collect();
+        RegionContent::Consolidated(ConsolidatedContent { parts })
+    }
+}
+
+impl Timeline {
+    /// Stack a new take on a region and make it audible
+    pub fn add_take(
+        &mut self,
+        region: RegionId,
+        name: impl Into<String>,
+        content: RegionContent,
+    ) -> Result<TakeId, TimelineError> {
+        let take = self.edit_takes(region, |r| Ok(r.add_take(name, content)))?;
+        Ok(take)
+    }
+
+    /// Play one take over a whole region
+    pub fn set_active_take(&mut self, region: RegionId, take: TakeId) -> Result<(), TimelineError> {
+        self.edit_takes(region, |r| r.set_active_take(take))
+    }
+
+    /// Play `take` over the part of a region inside `range` (in timeline
+    /// samples)
+    pub fn comp_from_selection(
+        &mut self,
+        region: RegionId,
+        range: SampleRange,
+        take: TakeId,
+    ) -> Result<(), TimelineError> {
+        self.edit_takes(region, |r| {
+            let relative = SampleRange::new(range.start - r.start, range.end - r.start);
+            r.comp_from_selection(relative, take)
+        })
+    }
+
+    /// Replace a region's takes by the content of its comp
+    pub fn flatten_comp(
+        &mut self,
+        region: RegionId,
+        converter: &TimeConverter,
+    ) -> Result<Takes, TimelineError> {
+        self.edit_takes(region, |r| {
+            r.content = r.flatten_comp(converter);
+            Ok(std::mem::take(&mut r.takes))
+        })
+    }
+
+    fn edit_takes<T>(
+        &mut self,
+        id: RegionId,
+        edit: impl FnOnce(&mut Region) -> Result<T, TimelineError>,
+    ) -> Result<T, TimelineError> {
+        self.check_editable(id)?;
+        let (t, r) = self
+            .locate_region(id)
+            .ok_or(TimelineError::RegionNotFound(id))?;
+        let value = edit(&mut self.tracks[t].regions[r])?;
+        self.emit(TimelineEvent::RegionChanged(id));
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AudioSourceId, RegionEdge, TrackId, TrackType};
+    use koto_core::{MidiNote, NoteNumber, SampleRate, Tempo, TimeSignature, Velocity};
+
+    fn range(start: i64, end: i64) -> SampleRange {
+        SampleRange::new(SamplePosition(start), SamplePosition(end))
+    }
+
+    fn audio(source: u64) -> RegionContent {
+        RegionContent::Audio {
+            source: AudioSourceId(source),
+        }
+    }
+
+    /// The comp must exactly cover the region, without gaps, overlaps or
+    /// neighbours on the same take
+    fn assert_tiles(region: &Region) {
+        let comp = region.takes.comp();
+        assert!(!comp.is_empty());
+        assert_eq!(comp[0].range.start, SamplePosition::ZERO);
+        assert_eq!(comp[comp.len() - 1].range.end, region.length);
+        for segment in comp {
+            assert!(!segment.range.is_empty());
+            assert!(region.takes.get(segment.take_id).is_some());
+        }
+        for pair in comp.windows(2) {
+            assert_eq!(pair[0].range.end, pair[1].range.start);
+            assert_ne!(pair[0].take_id, pair[1].take_id);
+        }
+    }
+
+    fn takes_of(region: &Region) -> Vec<(i64, i64, u64)> {
+        region
+            .takes
+            .comp()
+            .iter()
+            .map(|s| (s.range.start.0, s.range.end.0, s.take_id.0))
+            .collect()
+    }
+
+    fn region_with_takes(count: u64) -> Region {
+        let mut region = Region::new(
+            RegionId(0),
+            TrackId(0),
+            SamplePosition(1000),
+            SamplePosition(1000),
+        );
+        for i in 0..count {
+            region.add_take(format!("Take {}", i + 1), audio(i));
+        }
+        region
+    }
+
+    #[test]
+    fn test_comp_from_selection_splits_and_merges() {
+        let mut region = region_with_takes(3);
+        assert_eq!(takes_of(&region), [(0, 1000, 2)]);
+
+        region
+            .comp_from_selection(range(200, 400), TakeId(0))
+            .unwrap();
+        assert_tiles(&region);
+        assert_eq!(
+            takes_of(&region),
+            [(0, 200, 2), (200, 400, 0), (400, 1000, 2)]
+        );
+
+        region
+            .comp_from_selection(range(300, 700), TakeId(1))
+            .unwrap();
+        assert_tiles(&region);
+        assert_eq!(
+            takes_of(&region),
+            [(0, 200, 2), (200, 300, 0), (300, 700, 1), (700, 1000, 2)]
+        );
+
+        // Covering the middle with the outer take merges everything back
+        region
+            .comp_from_selection(range(150, 800), TakeId(2))
+            .unwrap();
+        assert_tiles(&region);
+        assert_eq!(takes_of(&region), [(0, 1000, 2)]);
+
+        // Selections are clamped to the region
+        region
+            .comp_from_selection(range(-500, 100), TakeId(1))
+            .unwrap();
+        region
+            .comp_from_selection(range(900, 5000), TakeId(1))
+            .unwrap();
+        assert_tiles(&region);
+        assert_eq!(
+            takes_of(&region),
+            [(0, 100, 1), (100, 900, 2), (900, 1000, 1)]
+        );
+
+        assert_eq!(
+            region.comp_from_selection(range(0, 10), TakeId(9)),
+            Err(TimelineError::TakeNotFound(TakeId(9)))
+        );
+        assert_eq!(
+            region.comp_from_selection(range(1000, 1200), TakeId(0)),
+            Err(TimelineError::EmptyRange)
+        );
+
+        region.set_active_take(TakeId(0)).unwrap();
+        assert_tiles(&region);
+        assert_eq!(region.content, audio(0));
+    }
+
+    #[test]
+    fn test_comp_follows_trims_and_splits() {
+        let mut timeline = Timeline::new();
+        let track = timeline.add_track("Vox", TrackType::Audio);
+        let id = timeline.new_region_id();
+        timeline
+            .add_region(Region::new(
+                id,
+                track,
+                SamplePosition(1000),
+                SamplePosition(1000),
+            ))
+            .unwrap();
+        timeline.add_take(id, "Take 1", audio(0)).unwrap();
+        let second = timeline.add_take(id, "Take 2", audio(1)).unwrap();
+        timeline
+            .comp_from_selection(id, range(1200, 1600), TakeId(0))
+            .unwrap();
+
+        timeline
+            .trim_region(id, RegionEdge::Start, SamplePosition(1300))
+            .unwrap();
+        let region = timeline.get_region(id).unwrap();
+        assert_tiles(region);
+        assert_eq!(takes_of(region), [(0, 300, 0), (300, 700, 1)]);
+
+        timeline
+            .trim_region(id, RegionEdge::End, SamplePosition(2500))
+            .unwrap();
+        assert_eq!(
+            takes_of(timeline.get_region(id).unwrap()),
+            [(0, 300, 0), (300, 1200, 1)]
+        );
+
+        let (left, right) = timeline.split_region(id, SamplePosition(1500)).unwrap();
+        for id in [left, right] {
+            assert_tiles(timeline.get_region(id).unwrap());
+        }
+        assert_eq!(takes_of(timeline.get_region(left).unwrap()), [(0, 200, 0)]);
+        assert_eq!(
+            takes_of(timeline.get_region(right).unwrap()),
+            [(0, 100, 0), (100, 1000, 1)]
+        );
+
+        timeline.join_split(left, right).unwrap();
+        assert_eq!(
+            takes_of(timeline.get_region(left).unwrap()),
+            [(0, 300, 0), (300, 1200, 1)]
+        );
+
+        timeline.take_events();
+        let takes = timeline.flatten_comp(left, &converter()).unwrap();
+        assert_eq!(takes.takes().len(), 2);
+        assert_eq!(timeline.take_events(), [TimelineEvent::RegionChanged(left)]);
+        let region = timeline.get_region(left).unwrap();
+        assert!(region.takes.is_empty());
+        let RegionContent::Consolidated(content) = &region.content else {
+            panic!("expected consolidated content, got {:?}", region.content);
+        };
+        let parts: Vec<_> = content
+            .parts
+            .iter()
+            .map(|p| (p.start.0, p.length.0, p.source_offset.0, p.content.clone()))
+            .collect();
+        assert_eq!(
+            parts,
+            [(300, 300, 300, audio(0)), (600, 900, 600, audio(second.0))]
+        );
+    }
+
+    fn converter() -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(120.0),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    #[test]
+    fn test_flatten_midi_comp_keeps_notes_per_segment() {
+        let conv = converter();
+        let takes = |pitch: u8| {
+            let mut clip = MidiClip::new();
+            for beat in 0..4 {
+                clip.add(MidiNote::new(
+                    conv.samples_to_ticks(SamplePosition(beat * 24000)),
+                    480,
+                    NoteNumber(pitch),
+                    Velocity(100),
+                ));
+            }
+            RegionContent::Midi(clip)
+        };
+        let mut region = Region::new(
+            RegionId(0),
+            TrackId(0),
+            SamplePosition(0),
+            SamplePosition(96000),
+        );
+        region.add_take("A", takes(60));
+        region.add_take("B", takes(72));
+        region
+            .comp_from_selection(range(24000, 72000), TakeId(0))
+            .unwrap();
+        assert_tiles(&region);
+
+        let RegionContent::Midi(flat) = region.flatten_comp(&conv) else {
+            panic!("expected MIDI");
+        };
+        let pitches: Vec<_> = flat.notes().iter().map(|n| n.note.0).collect();
+        assert_eq!(pitches, [72, 60, 60, 72]);
+    }
+}
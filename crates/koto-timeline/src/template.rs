@@ -0,0 +1,90 @@
+//! Reusable track setups
+
+use crate::{Timeline, Track, TrackId, TrackType};
+use serde::{Deserialize, Serialize};
+
+/// A saved track configuration, independent of any project
+///
+/// Regions are never part of a template.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackTemplate {
+    /// Name for new tracks; `{n}` is replaced by the lowest number not
+    /// already used, e.g. `Vocal {n}`
+    pub name_pattern: String,
+    pub track_type: TrackType,
+    pub color: u32,
+    pub height: u32,
+}
+
+impl TrackTemplate {
+    /// Capture a track's setup
+    pub fn from_track(track: &Track) -> Self {
+        Self {
+            name_pattern: track.name.clone(),
+            track_type: track.track_type,
+            color: track.color,
+            height: track.height,
+        }
+    }
+
+    /// Name for a new track, given the names already in use
+    fn name(&self, taken: &[&str]) -> String {
+        if !self.name_pattern.contains("{n}") {
+            return self.name_pattern.clone();
+        }
+        (1..)
+            .map(|n: u32| self.name_pattern.replace("{n}", &n.to_string()))
+            .find(|name| !taken.contains(&name.as_str()))
+            .unwrap_or_default()
+    }
+}
+
+impl Timeline {
+    /// Add a new, empty track set up from a template
+    pub fn create_track_from_template(&mut self, template: &TrackTemplate) -> TrackId {
+        let taken: Vec<&str> = self.tracks.iter().map(|t| t.name.as_str()).collect();
+        let name = template.name(&taken);
+        let id = self.add_track(name, template.track_type);
+        if let Some(track) = self.get_track_mut(id) {
+            track.color = template.color;
+            track.height = template.height;
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Region;
+    use koto_core::SamplePosition;
+
+    #[test]
+    fn test_template_instances_are_fresh() {
+        let mut timeline = Timeline::new();
+        let source = timeline.add_track("Lead Vox", TrackType::Audio);
+        let id = timeline.new_region_id();
+        let region = Region::new(id, source, SamplePosition(0), SamplePosition(100));
+        timeline.add_region(region).unwrap();
+        {
+            let track = timeline.get_track_mut(source).unwrap();
+            track.color = 0xFF00FF;
+            track.height = 120;
+        }
+
+        let mut template = TrackTemplate::from_track(timeline.get_track(source).unwrap());
+        template.name_pattern = "Vox {n}".into();
+        let first = timeline.create_track_from_template(&template);
+        let second = timeline.create_track_from_template(&template);
+        assert_ne!(first, second);
+        assert_ne!(first, source);
+
+        for (id, name) in [(first, "Vox 1"), (second, "Vox 2")] {
+            let track = timeline.get_track(id).unwrap();
+            assert_eq!(track.name, name);
+            assert_eq!((track.color, track.height), (0xFF00FF, 120));
+            assert_eq!(track.track_type, TrackType::Audio);
+            assert!(track.regions.is_empty());
+        }
+    }
+}
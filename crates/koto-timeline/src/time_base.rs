@@ -0,0 +1,176 @@
+//! Keeping musical regions on their beats when the tempo changes
+
+use crate::{Region, Timeline};
+use koto_core::{SamplePosition, TimeConverter};
+use serde::{Deserialize, Serialize};
+
+/// What a region's position is tied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeBase {
+    /// Stays at the same time in samples
+    #[default]
+    Absolute,
+    /// Stays on the same beats, moving when the tempo changes
+    Musical,
+}
+
+impl Region {
+    /// Tie the region to samples or to beats, taking its musical position
+    /// from `converter` when it becomes musical
+    pub fn set_time_base(&mut self, base: TimeBase, converter: &TimeConverter) {
+        if base == TimeBase::Musical {
+            (self.start_ticks, self.length_ticks) = self.musical_span(converter);
+        }
+        self.time_base = base;
+    }
+
+    /// Start and length in ticks under `converter`
+    ///
+    /// The stored ticks are preferred while they still land on the region's
+    /// samples, so repeated tempo changes don't accumulate rounding. Any
+    /// edit since moves the region off them and they are taken again.
+    fn musical_span(&self, converter: &TimeConverter) -> (i64, i64) {
+        let start = converter.ticks_to_samples(self.start_ticks);
+        let end = converter.ticks_to_samples(self.start_ticks + self.length_ticks);
+        if start == self.start && end == self.end() {
+            return (self.start_ticks, self.length_ticks);
+        }
+        let start = converter.samples_to_ticks(self.start);
+        (start, converter.samples_to_ticks(self.end()) - start)
+    }
+
+    /// Move a musical region from `old` to `new`, keeping its start, length
+    /// and content offset on the same beats
+    fn retime(&mut self, old: &TimeConverter, new: &TimeConverter) {
+        let (start_ticks, length_ticks) = self.musical_span(old);
+        let convert = |samples: SamplePosition| new.ticks_to_samples(old.samples_to_ticks(samples));
+
+        self.start = new.ticks_to_samples(start_ticks);
+        let end = new.ticks_to_samples(start_ticks + length_ticks);
+        self.length = SamplePosition((end.0 - self.start.0).max(1));
+        self.source_offset = convert(self.source_offset);
+        self.content_length = convert(self.content_length);
+        self.start_ticks = start_ticks;
+        self.length_ticks = length_ticks;
+        self.takes.retime(0, self.length);
+        self.clamp_fades();
+    }
+}
+
+impl Timeline {
+    /// Move the musical regions after a tempo or time signature change
+    ///
+    /// Positions are kept in quarter-note ticks, so after a time signature
+    /// change regions keep their beat count from the start rather than
+    /// their bar numbers. Absolute regions don't move.
+    pub fn retime(&mut self, old: &TimeConverter, new: &TimeConverter) {
+        let mut changed = Vec::new();
+        for (index, track) in self.tracks.iter_mut().enumerate() {
+            if !track
+                .regions
+                .iter()
+                .any(|r| r.time_base == TimeBase::Musical)
+            {
+                continue;
+            }
+            let before = track.regions.clone();
+            for region in &mut track.regions {
+                if region.time_base == TimeBase::Musical {
+                    region.retime(old, new);
+                }
+            }
+            track.regions.sort_by_key(|r| r.start);
+            changed.push((index, before));
+        }
+        if changed.is_empty() {
+            return;
+        }
+        self.regions_changed();
+        for (index, before) in changed {
+            self.emit_region_diff(index, &before);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RegionId, TimelineEvent, TrackId, TrackType};
+    use koto_core::{MusicalTime, SampleRate, Tempo, TimeSignature};
+
+    fn converter(bpm: f64) -> TimeConverter {
+        TimeConverter::new(
+            SampleRate(48000),
+            Tempo::new(bpm),
+            TimeSignature::COMMON_TIME,
+        )
+    }
+
+    #[test]
+    fn test_musical_region_stays_on_bar_across_tempo_change() {
+        let slow = converter(90.0);
+        let fast = converter(120.0);
+        let bar_3 = MusicalTime::new(3, 1, 0);
+
+        let mut timeline = Timeline::new();
+        let keys = timeline.add_track("Keys", TrackType::Midi);
+        let midi = timeline
+            .create_midi_region(keys, fast.musical_to_samples(bar_3), 2, &fast)
+            .unwrap();
+        let vox = timeline.add_track("Vox", TrackType::Audio);
+        let audio = timeline.new_region_id();
+        let region = Region::new(
+            audio,
+            vox,
+            fast.musical_to_samples(bar_3),
+            SamplePosition(48000),
+        );
+        timeline.add_region(region).unwrap();
+        timeline.take_events();
+
+        timeline.retime(&fast, &slow);
+        let region = timeline.get_region(midi).unwrap();
+        assert_eq!(region.time_base, TimeBase::Musical);
+        assert_eq!(region.start, slow.musical_to_samples(bar_3));
+        assert_eq!(slow.samples_to_musical(region.start), bar_3);
+        assert_eq!(
+            region.end(),
+            slow.musical_to_samples(MusicalTime::new(5, 1, 0))
+        );
+        assert_eq!(
+            timeline.get_region(audio).unwrap().start,
+            SamplePosition(192000)
+        );
+        assert_eq!(timeline.take_events(), [TimelineEvent::RegionResized(midi)]);
+
+        // And back again without drifting
+        timeline.retime(&slow, &fast);
+        assert_eq!(
+            timeline.get_region(midi).unwrap().start,
+            SamplePosition(192000)
+        );
+        assert_eq!(
+            timeline.get_region(midi).unwrap().length,
+            SamplePosition(192000)
+        );
+    }
+
+    #[test]
+    fn test_edited_musical_region_takes_new_position() {
+        let fast = converter(120.0);
+        let slow = converter(60.0);
+        let mut region = Region::new(
+            RegionId(0),
+            TrackId(0),
+            SamplePosition(0),
+            SamplePosition(24000),
+        );
+        region.set_time_base(TimeBase::Musical, &fast);
+
+        // Moved by hand since the ticks were taken
+        region.start = SamplePosition(48000);
+        region.retime(&fast, &slow);
+        assert_eq!(region.start, SamplePosition(96000));
+        assert_eq!(region.length, SamplePosition(48000));
+    }
+}
@@ -0,0 +1,433 @@
+//! Integrity checks for timelines loaded from disk
+
+use crate::{AudioSourceId, RegionContent, RegionId, Timeline, TrackId};
+use koto_core::SamplePosition;
+use std::collections::{HashMap, HashSet};
+
+/// A broken invariant found by [`Timeline::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    DuplicateTrackId(TrackId),
+    DuplicateRegionId(RegionId),
+    /// A region's `track_id` doesn't match the track holding it
+    RegionOnWrongTrack {
+        region: RegionId,
+        track: TrackId,
+    },
+    /// Length of zero or less
+    InvalidLength(RegionId),
+    NegativeStart(RegionId),
+    /// Fades are negative or longer than the region together
+    InvalidFades(RegionId),
+    MissingSource {
+        region: RegionId,
+        id: AudioSourceId,
+    },
+    UnsortedRegions(TrackId),
+    /// A frozen track's rendered audio isn't in the media pool
+    MissingFrozenSource {
+        track: TrackId,
+        id: AudioSourceId,
+    },
+    /// A crossfade names a region that doesn't exist
+    CrossfadeMissingRegion {
+        track: TrackId,
+        region: RegionId,
+    },
+    /// A crossfade names a region held by a different track
+    CrossfadeOnWrongTrack {
+        track: TrackId,
+        region: RegionId,
+    },
+    MissingParent {
+        track: TrackId,
+        parent: TrackId,
+    },
+    /// The track is nested, through its parents, under itself
+    ParentCycle(TrackId),
+    /// An ID counter would hand out an ID already in use
+    TrackIdCounterBehind,
+    RegionIdCounterBehind,
+    SourceIdCounterBehind,
+}
+
+impl Timeline {
+    /// Check the invariants the editing code relies on
+    ///
+    /// Timelines built through the API always pass; hand-edited or
+    /// corrupted project files may not.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut tracks = HashSet::new();
+        let mut regions = HashSet::new();
+        let sources: HashSet<AudioSourceId> = self.sources.iter().map(|s| s.id).collect();
+        let owners = self.region_owners();
+
+        for track in &self.tracks {
+            if !tracks.insert(track.id) {
+                issues.push(ValidationIssue::DuplicateTrackId(track.id));
+            }
+            if track.regions.windows(2).any(|w| w[0].start > w[1].start) {
+                issues.push(ValidationIssue::UnsortedRegions(track.id));
+            }
+            for region in &track.regions {
+                let id = region.id;
+                if !regions.insert(id) {
+                    issues.push(ValidationIssue::DuplicateRegionId(id));
+                }
+                if region.track_id != track.id {
+                    issues.push(ValidationIssue::RegionOnWrongTrack {
+                        region: id,
+                        track: track.id,
+                    });
+                }
+                if region.length.0 <= 0 {
+                    issues.push(ValidationIssue::InvalidLength(id));
+                }
+                if region.start.0 < 0 {
+                    issues.push(ValidationIssue::NegativeStart(id));
+                }
+                if !fades_valid(region.fade_in_length, region.fade_out_length, region.length) {
+                    issues.push(ValidationIssue::InvalidFades(id));
+                }
+                for source in region.content.source_ids() {
+                    if !sources.contains(&source) {
+                        issues.push(ValidationIssue::MissingSource {
+                            region: id,
+                            id: source,
+                        });
+                    }
+                }
+            }
+            if let Some(id) = track.frozen_source.filter(|id| !sources.contains(id)) {
+                issues.push(ValidationIssue::MissingFrozenSource {
+                    track: track.id,
+                    id,
+                });
+            }
+            for crossfade in &track.crossfades {
+                for region in [crossfade.left, crossfade.right] {
+                    match owners.get(&region) {
+                        None => issues.push(ValidationIssue::CrossfadeMissingRegion {
+                            track: track.id,
+                            region,
+                        }),
+                        Some(&owner) if owner != track.id => {
+                            issues.push(ValidationIssue::CrossfadeOnWrongTrack {
+                                track: track.id,
+                                region,
+                            })
+                        }
+                        Some(_) => {}
+                    }
+                }
+            }
+            match track.parent {
+                Some(parent) if self.get_track(parent).is_none() => {
+                    issues.push(ValidationIssue::MissingParent {
+                        track: track.id,
+                        parent,
+                    });
+                }
+                Some(_) if self.in_parent_cycle(track.id) => {
+                    issues.push(ValidationIssue::ParentCycle(track.id));
+                }
+                _ => {}
+            }
+        }
+
+        if tracks.iter().any(|id| id.0 >= self.next_track_id) {
+            issues.push(ValidationIssue::TrackIdCounterBehind);
+        }
+        if regions.iter().any(|id| id.0 >= self.next_region_id) {
+            issues.push(ValidationIssue::RegionIdCounterBehind);
+        }
+        if sources.iter().any(|id| id.0 >= self.next_source_id) {
+            issues.push(ValidationIssue::SourceIdCounterBehind);
+        }
+        issues
+    }
+
+    /// Fix whatever [`validate`](Self::validate) finds and return the issues
+    ///
+    /// Duplicate IDs after the first are renumbered, bad lengths, starts and
+    /// fades are clamped, regions are re-sorted and given their holding
+    /// track's ID, and content referring to missing sources is emptied.
+    /// Tracks frozen to missing audio are unfrozen, broken crossfades are
+    /// dropped, and missing or cyclic parents are cleared.
+    pub fn repair(&mut self) -> Vec<ValidationIssue> {
+        let issues = self.validate();
+        if issues.is_empty() {
+            return issues;
+        }
+
+        let max_track = self.tracks.iter().map(|t| t.id.0 + 1).max();
+        let max_region = self
+            .tracks
+            .iter()
+            .flat_map(|t| t.regions.iter())
+            .map(|r| r.id.0 + 1)
+            .max();
+        let max_source = self.sources.iter().map(|s| s.id.0 + 1).max();
+        self.next_track_id = self.next_track_id.max(max_track.unwrap_or(0));
+        self.next_region_id = self.next_region_id.max(max_region.unwrap_or(0));
+        self.next_source_id = self.next_source_id.max(max_source.unwrap_or(0));
+
+        let sources: HashSet<AudioSourceId> = self.sources.iter().map(|s| s.id).collect();
+        let mut tracks = HashSet::new();
+        let mut regions = HashSet::new();
+        for t in 0..self.tracks.len() {
+            if !tracks.insert(self.tracks[t].id) {
+                self.tracks[t].id = TrackId(self.next_track_id);
+                self.next_track_id += 1;
+            }
+            let track_id = self.tracks[t].id;
+            for r in 0..self.tracks[t].regions.len() {
+                if !regions.insert(self.tracks[t].regions[r].id) {
+                    let id = self.new_region_id();
+                    self.tracks[t].regions[r].id = id;
+                }
+                let region = &mut self.tracks[t].regions[r];
+                region.track_id = track_id;
+                region.length = region.length.max(SamplePosition(1));
+                region.start = region.start.max(SamplePosition::ZERO);
+                if !fades_valid(region.fade_in_length, region.fade_out_length, region.length) {
+                    region.fade_in_length = region.fade_in_length.max(SamplePosition::ZERO);
+                    region.fade_out_length = region.fade_out_length.max(SamplePosition::ZERO);
+                    region.clamp_fades();
+                }
+                if region
+                    .content
+                    .source_ids()
+                    .iter()
+                    .any(|s| !sources.contains(s))
+                {
+                    region.content = RegionContent::Empty;
+                }
+            }
+            self.tracks[t].regions.sort_by_key(|r| r.start);
+        }
+
+        let owners = self.region_owners();
+        for track in &mut self.tracks {
+            if track.frozen_source.is_some_and(|id| !sources.contains(&id)) {
+                track.frozen = false;
+                track.frozen_source = None;
+            }
+            let id = track.id;
+            track
+                .crossfades
+                .retain(|c| owners.get(&c.left) == Some(&id) && owners.get(&c.right) == Some(&id));
+        }
+        // Clearing one parent per cycle is enough to break it
+        for t in 0..self.tracks.len() {
+            let id = self.tracks[t].id;
+            let missing = self.tracks[t]
+                .parent
+                .is_some_and(|parent| self.get_track(parent).is_none());
+            if missing || self.in_parent_cycle(id) {
+                self.tracks[t].parent = None;
+            }
+        }
+        self.regions_changed();
+        issues
+    }
+}
+
+impl Timeline {
+    /// The track holding each region, the first one for duplicate IDs
+    fn region_owners(&self) -> HashMap<RegionId, TrackId> {
+        let mut owners = HashMap::new();
+        for track in &self.tracks {
+            for region in &track.regions {
+                owners.entry(region.id).or_insert(track.id);
+            }
+        }
+        owners
+    }
+
+    /// Whether following `id`'s parents leads back to it
+    fn in_parent_cycle(&self, id: TrackId) -> bool {
+        let mut next = self.get_track(id).and_then(|t| t.parent);
+        // Bounded in case the cycle doesn't pass through `id`
+        for _ in 0..self.tracks.len() {
+            match next {
+                Some(parent) if parent == id => return true,
+                Some(parent) => next = self.get_track(parent).and_then(|t| t.parent),
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+fn fades_valid(fade_in: SamplePosition, fade_out: SamplePosition, length: SamplePosition) -> bool {
+    fade_in.0 >= 0 && fade_out.0 >= 0 && fade_in.0 + fade_out.0 <= length.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Region, TrackType};
+    use koto_core::{ChannelCount, SampleRate};
+    use serde_json::{json, Value};
+
+    /// A valid timeline as JSON: two audio tracks, two regions on the first
+    fn fixture() -> Value {
+        let mut timeline = Timeline::new();
+        let a = timeline.add_track("A", TrackType::Audio);
+        timeline.add_track("B", TrackType::Audio);
+        let source = timeline.add_source(
+            "/audio/kick.wav",
+            SampleRate(48000),
+            ChannelCount(1),
+            SamplePosition(48000),
+        );
+        for start in [0, 2000] {
+            let id = timeline.new_region_id();
+            let mut region = Region::new(id, a, SamplePosition(start), SamplePosition(1000));
+            region.content = RegionContent::Audio { source };
+            timeline.add_region(region).unwrap();
+        }
+        assert!(timeline.validate().is_empty());
+        serde_json::to_value(&timeline).unwrap()
+    }
+
+    fn load(value: Value) -> Timeline {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_corrupted_fixture() {
+        let mut value = fixture();
+        value["tracks"][1]["id"] = json!(0);
+        let region = &mut value["tracks"][0]["regions"][1];
+        region["id"] = json!(0);
+        region["length"] = json!(-5);
+        region["fade_in_length"] = json!(-1);
+        region["content"] = json!({ "Audio": { "source": 9 } });
+        value["tracks"][0]["regions"][0]["start"] = json!(3000);
+        value["next_region_id"] = json!(0);
+
+        let timeline = load(value);
+        assert_eq!(
+            timeline.validate(),
+            vec![
+                ValidationIssue::UnsortedRegions(TrackId(0)),
+                ValidationIssue::DuplicateRegionId(RegionId(0)),
+                ValidationIssue::InvalidLength(RegionId(0)),
+                ValidationIssue::InvalidFades(RegionId(0)),
+                ValidationIssue::MissingSource {
+                    region: RegionId(0),
+                    id: AudioSourceId(9),
+                },
+                ValidationIssue::DuplicateTrackId(TrackId(0)),
+                ValidationIssue::RegionIdCounterBehind,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_missing_frozen_source() {
+        let mut value = fixture();
+        value["tracks"][1]["frozen"] = json!(true);
+        value["tracks"][1]["frozen_source"] = json!(9);
+
+        assert_eq!(
+            load(value).validate(),
+            vec![ValidationIssue::MissingFrozenSource {
+                track: TrackId(1),
+                id: AudioSourceId(9),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_broken_crossfades() {
+        let mut value = fixture();
+        let crossfade = |left: u64, right: u64| json!({ "left": left, "right": right, "length": 100, "curve": "Linear" });
+        value["tracks"][0]["crossfades"] = json!([crossfade(0, 5)]);
+        value["tracks"][1]["crossfades"] = json!([crossfade(0, 1)]);
+
+        assert_eq!(
+            load(value).validate(),
+            vec![
+                ValidationIssue::CrossfadeMissingRegion {
+                    track: TrackId(0),
+                    region: RegionId(5),
+                },
+                ValidationIssue::CrossfadeOnWrongTrack {
+                    track: TrackId(1),
+                    region: RegionId(0),
+                },
+                ValidationIssue::CrossfadeOnWrongTrack {
+                    track: TrackId(1),
+                    region: RegionId(1),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_parent_cycle() {
+        let mut value = fixture();
+        value["tracks"][0]["parent"] = json!(1);
+        value["tracks"][1]["parent"] = json!(0);
+
+        assert_eq!(
+            load(value).validate(),
+            vec![
+                ValidationIssue::ParentCycle(TrackId(0)),
+                ValidationIssue::ParentCycle(TrackId(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repair_references() {
+        let mut value = fixture();
+        value["tracks"][0]["frozen"] = json!(true);
+        value["tracks"][0]["frozen_source"] = json!(9);
+        value["tracks"][0]["crossfades"] =
+            json!([{ "left": 0, "right": 5, "length": 100, "curve": "Linear" }]);
+        value["tracks"][0]["parent"] = json!(1);
+        value["tracks"][1]["parent"] = json!(0);
+
+        let mut timeline = load(value);
+        assert_eq!(timeline.repair().len(), 4);
+        assert!(timeline.validate().is_empty());
+
+        let track = &timeline.tracks[0];
+        assert!(!track.frozen && track.crossfades.is_empty());
+        // Only the first parent is cleared, which breaks the cycle
+        assert_eq!(track.parent, None);
+        assert_eq!(timeline.tracks[1].parent, Some(TrackId(0)));
+    }
+
+    #[test]
+    fn test_repair_makes_timeline_valid() {
+        let mut value = fixture();
+        value["tracks"][1]["id"] = json!(0);
+        value["tracks"][0]["regions"][1]["id"] = json!(0);
+        value["tracks"][0]["regions"][1]["track_id"] = json!(7);
+        value["tracks"][0]["regions"][0]["start"] = json!(-400);
+        value["tracks"][0]["regions"][0]["fade_out_length"] = json!(5000);
+        value["next_track_id"] = json!(0);
+
+        let mut timeline = load(value);
+        assert_eq!(timeline.repair().len(), 6);
+        assert!(timeline.validate().is_empty());
+
+        let ids: Vec<_> = timeline.tracks.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![TrackId(0), TrackId(1)]);
+        let regions = &timeline.tracks[0].regions;
+        assert_eq!(regions[0].start, SamplePosition::ZERO);
+        assert_eq!(regions[0].fade_out_length, SamplePosition(1000));
+        assert_eq!(
+            (regions[1].id, regions[1].track_id),
+            (RegionId(2), TrackId(0))
+        );
+        // New IDs don't collide with repaired ones
+        assert_eq!(timeline.add_track("C", TrackType::Audio), TrackId(2));
+    }
+}
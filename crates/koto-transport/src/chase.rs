@@ -0,0 +1,123 @@
+//! Following an external clock
+
+use crate::Transport;
+use koto_core::{SamplePosition, Tempo};
+
+/// Position errors above this are fixed by jumping rather than varispeed
+pub const CHASE_JUMP_THRESHOLD_MS: f64 = 50.0;
+
+/// Time over which a small position error is corrected
+pub const CHASE_CORRECTION_MS: f64 = 200.0;
+
+/// Largest rate correction while chasing (±5%)
+pub const MAX_CHASE_CORRECTION: f64 = 0.05;
+
+/// What drives the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SyncSource {
+    /// The transport runs on its own clock
+    #[default]
+    Internal,
+    /// Slaved to incoming MIDI clock
+    MidiClock,
+    /// Slaved to another external clock (e.g. Ableton Link)
+    External,
+}
+
+impl SyncSource {
+    pub fn is_slaved(&self) -> bool {
+        *self != Self::Internal
+    }
+}
+
+impl Transport {
+    pub fn set_sync_source(&mut self, source: SyncSource) {
+        self.sync_source = source;
+        self.chase_correction = 1.0;
+    }
+
+    /// Rate multiplier currently applied to correct chase drift
+    pub fn chase_correction(&self) -> f64 {
+        self.chase_correction
+    }
+
+    /// Follow the external clock's position and play state
+    ///
+    /// Call once per block before [`advance`](Self::advance). Errors larger
+    /// than [`CHASE_JUMP_THRESHOLD_MS`] jump straight to the external
+    /// position; smaller ones adjust the rate so the error shrinks over
+    /// roughly [`CHASE_CORRECTION_MS`]. Does nothing while the sync source is
+    /// [`SyncSource::Internal`].
+    pub fn chase(&mut self, external_position: SamplePosition, external_playing: bool) {
+        if !self.sync_source.is_slaved() {
+            return;
+        }
+        if !external_playing {
+            self.pause();
+            self.chase_correction = 1.0;
+            if self.playhead != external_position {
+                self.set_playhead(external_position);
+            }
+            return;
+        }
+
+        let error = (external_position.0 - self.playhead.0) as f64;
+        let threshold = CHASE_JUMP_THRESHOLD_MS / 1000.0 * self.sample_rate.as_f64();
+        if !self.is_playing || error.abs() > threshold {
+            self.chase_correction = 1.0;
+            self.set_playhead(external_position);
+            self.start_playing();
+            return;
+        }
+
+        let window = CHASE_CORRECTION_MS / 1000.0 * self.sample_rate.as_f64();
+        self.chase_correction =
+            1.0 + (error / window).clamp(-MAX_CHASE_CORRECTION, MAX_CHASE_CORRECTION);
+    }
+
+    /// Set the tempo reported by the sync source, bypassing the slave lock
+    pub fn set_sync_tempo(&mut self, tempo: Tempo) {
+        let source = std::mem::replace(&mut self.sync_source, SyncSource::Internal);
+        self.set_tempo(tempo);
+        self.sync_source = source;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koto_core::SampleRate;
+
+    #[test]
+    fn test_chase_converges_on_drifting_clock() {
+        let mut transport = Transport::new(SampleRate::DVD_QUALITY);
+        transport.set_sync_source(SyncSource::MidiClock);
+
+        // External clock runs 0.1% fast
+        let mut external = 0.0f64;
+        transport.chase(SamplePosition(0), true);
+        external += 960.0; // 20 ms ahead, below the jump threshold
+        for _ in 0..2000 {
+            transport.chase(SamplePosition(external as i64), true);
+            transport.advance(512);
+            external += 512.0 * 1.001;
+        }
+        let error = external as i64 - transport.playhead.0;
+        assert!(error.abs() < 32, "error {error}");
+        assert!((transport.chase_correction() - 1.001).abs() < 1e-4);
+
+        // A large error jumps
+        transport.chase(SamplePosition(10_000_000), true);
+        assert_eq!(transport.playhead, SamplePosition(10_000_000));
+    }
+
+    #[test]
+    fn test_tempo_setter_ignored_when_slaved() {
+        let mut transport = Transport::default();
+        transport.set_sync_source(SyncSource::External);
+        transport.set_tempo(Tempo::new(90.0));
+        assert_eq!(transport.tempo, Tempo::DEFAULT);
+        transport.set_sync_tempo(Tempo::new(90.0));
+        assert_eq!(transport.tempo, Tempo::new(90.0));
+    }
+}
@@ -0,0 +1,104 @@
+//! Browser-style history of transport positions
+
+use koto_core::SamplePosition;
+use std::collections::VecDeque;
+
+/// Default number of positions kept
+pub const POSITION_HISTORY_CAPACITY: usize = 32;
+
+/// Bounded list of visited positions with back/forward navigation
+///
+/// Recording a position after going back discards the forward entries, as
+/// in a web browser. A position equal to the current entry isn't recorded
+/// again.
+#[derive(Debug, Clone)]
+pub struct PositionHistory {
+    entries: VecDeque<SamplePosition>,
+    /// Index of the current entry
+    cursor: usize,
+    capacity: usize,
+}
+
+impl PositionHistory {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            cursor: 0,
+            capacity,
+        }
+    }
+
+    pub fn record(&mut self, position: SamplePosition) {
+        if self.current() == Some(position) {
+            return;
+        }
+        self.entries.truncate(self.cursor + 1);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(position);
+        self.cursor = self.entries.len() - 1;
+    }
+
+    /// Step to the previous entry
+    pub fn back(&mut self) -> Option<SamplePosition> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        self.current()
+    }
+
+    /// Step to the next entry after going back
+    pub fn forward(&mut self) -> Option<SamplePosition> {
+        if self.cursor + 1 >= self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        self.current()
+    }
+
+    pub fn current(&self) -> Option<SamplePosition> {
+        self.entries.get(self.cursor).copied()
+    }
+
+    /// Entries from oldest to newest
+    pub fn entries(&self) -> impl Iterator<Item = SamplePosition> + '_ {
+        self.entries.iter().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.cursor = 0;
+    }
+}
+
+impl Default for PositionHistory {
+    fn default() -> Self {
+        Self::new(POSITION_HISTORY_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_forward_and_truncation() {
+        let mut history = PositionHistory::new(3);
+        for position in [10, 20, 20, 30, 40] {
+            history.record(SamplePosition(position));
+        }
+        let entries: Vec<i64> = history.entries().map(|p| p.0).collect();
+        assert_eq!(entries, vec![20, 30, 40]);
+
+        assert_eq!(history.back(), Some(SamplePosition(30)));
+        assert_eq!(history.back(), Some(SamplePosition(20)));
+        assert_eq!(history.back(), None);
+        assert_eq!(history.forward(), Some(SamplePosition(30)));
+
+        // Recording after going back drops the forward entries
+        history.record(SamplePosition(99));
+        assert_eq!(history.forward(), None);
+        let entries: Vec<i64> = history.entries().map(|p| p.0).collect();
+        assert_eq!(entries, vec![20, 30, 99]);
+    }
+}
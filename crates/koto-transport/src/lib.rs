@@ -1,6 +1,33 @@
 //! Koto Transport - Transport control
 
-use koto_core::{SamplePosition, SampleRate, Tempo, TimeSignature};
+use koto_core::{
+    clamp_playback_rate, timeline_samples, LoopWrap, MarkerId, MarkerList, MonitorMode,
+    MusicalTime, PreRoll, SamplePosition, SampleRange, SampleRate, SeekPolicy, StopBehavior, Tempo,
+    TimeConverter, TimeSignature,
+};
+use std::time::Instant;
+use thiserror::Error;
+
+mod chase;
+mod history;
+mod listener;
+mod tap_tempo;
+
+pub use chase::*;
+pub use history::*;
+pub use listener::*;
+pub use tap_tempo::*;
+
+use listener::{Listeners, TransportNotification};
+
+/// Transport errors
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TransportError {
+    #[error("Loop must have a positive length (start {start}, end {end})")]
+    InvalidLoop { start: i64, end: i64 },
+    #[error("Recording is disabled while record safe is on")]
+    RecordSafe,
+}
 
 /// Transport controller
 pub struct Transport {
@@ -13,6 +40,40 @@ pub struct Transport {
     pub loop_enabled: bool,
     pub loop_start: SamplePosition,
     pub loop_end: SamplePosition,
+    /// Bars of metronome played before recording starts
+    pub count_in_bars: u8,
+    /// When live input is heard on the output
+    pub input_monitoring: MonitorMode,
+    /// Refuse to start recording
+    pub record_safe: bool,
+    /// What stop does with the playhead
+    pub stop_behavior: StopBehavior,
+    /// Position the last play started from
+    pub play_start: SamplePosition,
+    /// Context played before the playhead by [`play_with_preroll`](Self::play_with_preroll)
+    pub pre_roll: Option<PreRoll>,
+    /// Tap tempo detector
+    pub tap: TapTempo,
+    pub markers: MarkerList,
+    /// Timeline samples played per output frame (0.25 to 4.0)
+    pub playback_rate: f64,
+    /// Sub-sample playhead position carried between advances
+    playhead_fraction: f64,
+    /// How seeks outside the loop behave while playing
+    pub seek_policy: SeekPolicy,
+    /// What drives the transport; tempo setters are ignored when slaved
+    pub sync_source: SyncSource,
+    /// Rate multiplier applied while chasing an external clock
+    chase_correction: f64,
+    /// Whether the playhead wraps at the loop end
+    following_loop: bool,
+    /// The playhead is being dragged and normal playback is suspended
+    pub is_scrubbing: bool,
+    /// Playback was running when the scrub started
+    resume_after_scrub: bool,
+    /// Play starts and explicit seek targets
+    history: PositionHistory,
+    listeners: Listeners,
 }
 
 impl Transport {
@@ -27,49 +88,358 @@ impl Transport {
             loop_enabled: false,
             loop_start: SamplePosition::ZERO,
             loop_end: SamplePosition::ZERO,
+            count_in_bars: 0,
+            input_monitoring: MonitorMode::default(),
+            record_safe: false,
+            stop_behavior: StopBehavior::default(),
+            play_start: SamplePosition::ZERO,
+            pre_roll: None,
+            tap: TapTempo::default(),
+            markers: MarkerList::new(),
+            playback_rate: 1.0,
+            playhead_fraction: 0.0,
+            seek_policy: SeekPolicy::default(),
+            sync_source: SyncSource::default(),
+            chase_correction: 1.0,
+            following_loop: true,
+            is_scrubbing: false,
+            resume_after_scrub: false,
+            history: PositionHistory::default(),
+            listeners: Listeners::default(),
         }
     }
 
     pub fn play(&mut self) {
-        self.is_playing = true;
+        if !self.is_playing {
+            self.history.record(self.playhead);
+            self.start_playing();
+        }
+    }
+
+    /// Start playing without recording the position in the history
+    fn start_playing(&mut self) {
+        if !self.is_playing {
+            self.play_start = self.playhead;
+            self.is_playing = true;
+            self.notify(TransportNotification::Play);
+        }
+    }
+
+    /// Start playing `pre_roll` before the playhead
+    ///
+    /// The original playhead is remembered as the play start, so
+    /// [`StopBehavior::ReturnToLastPlayStart`] returns to it rather than to
+    /// the pre-roll start.
+    pub fn play_with_preroll(&mut self) {
+        if self.is_playing {
+            return;
+        }
+        let original = self.playhead;
+        self.history.record(original);
+        if let Some(pre_roll) = self.pre_roll {
+            let position = pre_roll.start_position(original, &self.converter());
+            self.set_playhead(position);
+        }
+        self.start_playing();
+        self.play_start = original;
+    }
+
+    pub fn set_pre_roll(&mut self, pre_roll: Option<PreRoll>) {
+        self.pre_roll = pre_roll;
     }
 
+    /// Stop playback, moving the playhead according to the stop behavior
+    ///
+    /// Stopping while already stopped returns to the start.
     pub fn stop(&mut self) {
-        self.is_playing = false;
+        let position = if self.is_playing {
+            self.stop_behavior
+                .stop_position(self.playhead, self.play_start)
+        } else {
+            SamplePosition::ZERO
+        };
+        self.pause();
+        if position != self.playhead {
+            self.set_playhead(position);
+        }
     }
 
+    /// Stop playback without moving the playhead
     pub fn pause(&mut self) {
-        self.is_playing = false;
+        if self.is_playing {
+            self.is_playing = false;
+            self.notify(TransportNotification::Stop);
+        }
+    }
+
+    pub fn set_stop_behavior(&mut self, behavior: StopBehavior) {
+        self.stop_behavior = behavior;
     }
 
+    /// Move the playhead and record the target in the position history
+    ///
+    /// While playing with looping enabled, a target outside the loop is
+    /// handled according to the [`SeekPolicy`].
     pub fn seek(&mut self, position: SamplePosition) {
+        let position = self.set_playhead(position);
+        self.history.record(position);
+    }
+
+    /// Move the playhead without touching the history; returns where it
+    /// landed
+    fn set_playhead(&mut self, position: SamplePosition) -> SamplePosition {
+        let (position, following) = if self.is_playing && self.loop_enabled {
+            self.seek_policy.resolve(position, self.loop_range())
+        } else {
+            (position, true)
+        };
         self.playhead = position;
+        self.playhead_fraction = 0.0;
+        self.following_loop = following;
+        self.notify(TransportNotification::Seek(position));
+        position
+    }
+
+    /// Go back to the previous position in the history
+    pub fn back(&mut self) -> Option<SamplePosition> {
+        let position = self.history.back()?;
+        self.set_playhead(position);
+        Some(position)
+    }
+
+    /// Go forward again after [`back`](Self::back)
+    pub fn forward(&mut self) -> Option<SamplePosition> {
+        let position = self.history.forward()?;
+        self.set_playhead(position);
+        Some(position)
+    }
+
+    /// Recent play starts and seek targets, oldest first
+    pub fn history(&self) -> &PositionHistory {
+        &self.history
+    }
+
+    pub fn set_seek_policy(&mut self, policy: SeekPolicy) {
+        self.seek_policy = policy;
+    }
+
+    /// Whether playback wraps at the loop end; false after a seek out of the
+    /// loop until the playhead enters it again
+    pub fn is_following_loop(&self) -> bool {
+        self.loop_enabled && self.following_loop
     }
 
+    /// Return to zero; not recorded in the history
     pub fn rewind(&mut self) {
-        self.playhead = SamplePosition::ZERO;
+        self.set_playhead(SamplePosition::ZERO);
     }
 
+    /// Move the playhead by `position_delta` samples as part of a scrub
+    ///
+    /// The first call pauses playback; [`end_scrub`](Self::end_scrub)
+    /// resumes it. The playhead doesn't move before zero.
+    pub fn set_scrub(&mut self, position_delta: i64) {
+        if !self.is_scrubbing {
+            self.is_scrubbing = true;
+            self.resume_after_scrub = self.is_playing;
+            self.pause();
+        }
+        self.set_playhead(SamplePosition((self.playhead.0 + position_delta).max(0)));
+    }
+
+    /// Leave scrubbing, resuming playback if it was playing before
+    pub fn end_scrub(&mut self) {
+        if self.is_scrubbing {
+            self.is_scrubbing = false;
+            if std::mem::take(&mut self.resume_after_scrub) {
+                self.start_playing();
+            }
+        }
+    }
+
+    /// Set the playback rate, clamped to 0.25-4.0
+    ///
+    /// The playhead keeps its position, including the sub-sample part.
+    pub fn set_playback_rate(&mut self, rate: f64) {
+        self.playback_rate = clamp_playback_rate(rate);
+    }
+
+    /// Seek to the first marker after the playhead
+    pub fn goto_next_marker(&mut self) -> Option<MarkerId> {
+        let marker = self.markers.next_marker(self.playhead)?;
+        let (id, position) = (marker.id, marker.position);
+        self.seek(position);
+        Some(id)
+    }
+
+    /// Seek to the last marker before the playhead
+    pub fn goto_previous_marker(&mut self) -> Option<MarkerId> {
+        let marker = self.markers.previous_marker(self.playhead)?;
+        let (id, position) = (marker.id, marker.position);
+        self.seek(position);
+        Some(id)
+    }
+
+    /// Set the tempo; ignored while slaved to an external sync source
     pub fn set_tempo(&mut self, tempo: Tempo) {
+        if self.sync_source.is_slaved() {
+            return;
+        }
         self.tempo = tempo;
+        self.notify(TransportNotification::TempoChanged(tempo));
+    }
+
+    /// Register a tempo tap and adopt the detected tempo
+    ///
+    /// Returns `None` until the sequence has at least two taps, and while
+    /// slaved to an external sync source.
+    pub fn tap_tempo(&mut self, now: Instant) -> Option<Tempo> {
+        if self.sync_source.is_slaved() {
+            return None;
+        }
+        let tempo = self.tap.tap(now)?;
+        self.set_tempo(tempo);
+        Some(tempo)
+    }
+
+    /// Tempo, interval and confidence of the tap sequence in progress
+    pub fn tap_estimate(&self) -> Option<TapEstimate> {
+        self.tap.estimate()
     }
 
     pub fn set_time_signature(&mut self, time_signature: TimeSignature) {
         self.time_signature = time_signature;
     }
 
-    pub fn start_recording(&mut self) {
+    /// Start recording; fails while record safe is on
+    pub fn start_recording(&mut self) -> Result<(), TransportError> {
+        if self.record_safe {
+            return Err(TransportError::RecordSafe);
+        }
         self.is_recording = true;
+        Ok(())
+    }
+
+    pub fn set_record_safe(&mut self, record_safe: bool) {
+        self.record_safe = record_safe;
+    }
+
+    pub fn set_input_monitoring(&mut self, mode: MonitorMode) {
+        self.input_monitoring = mode;
+    }
+
+    /// Whether live input should currently be heard; recording counts as
+    /// armed
+    pub fn is_monitoring(&self) -> bool {
+        self.input_monitoring.is_monitoring(self.is_recording)
     }
 
     pub fn stop_recording(&mut self) {
         self.is_recording = false;
     }
 
+    pub fn set_count_in(&mut self, bars: u8) {
+        self.count_in_bars = bars;
+    }
+
+    /// Length of the count-in in samples at the current tempo
+    pub fn count_in_samples(&self) -> usize {
+        let beats = self.count_in_bars as f64 * self.time_signature.numerator as f64;
+        (beats * self.tempo.samples_per_beat(self.sample_rate)).round() as usize
+    }
+
+    /// Set the loop region; rejects loops without a positive length
+    pub fn set_loop(&mut self, range: SampleRange) -> Result<(), TransportError> {
+        if range.is_empty() {
+            return Err(TransportError::InvalidLoop {
+                start: range.start.0,
+                end: range.end.0,
+            });
+        }
+        self.loop_start = range.start;
+        self.loop_end = range.end;
+        self.notify_loop_changed();
+        Ok(())
+    }
+
+    pub fn set_loop_enabled(&mut self, enabled: bool) {
+        self.loop_enabled = enabled;
+        self.notify_loop_changed();
+    }
+
+    fn notify_loop_changed(&mut self) {
+        self.notify(TransportNotification::LoopChanged {
+            enabled: self.loop_enabled,
+            range: self.loop_range(),
+        });
+    }
+
+    pub fn loop_range(&self) -> SampleRange {
+        SampleRange::new(self.loop_start, self.loop_end)
+    }
+
+    /// Move the playhead forward by `frames` output frames
+    ///
+    /// The playhead moves `frames * playback_rate` samples. With looping
+    /// enabled, reaching the loop end wraps back to the loop start; the
+    /// returned [`LoopWrap`] tells the caller where to split processing (in
+    /// timeline samples).
+    /// Listeners see a wrap as a seek.
+    pub fn advance(&mut self, frames: usize) -> Option<LoopWrap> {
+        let rate = self.playback_rate * self.chase_correction;
+        let samples = timeline_samples(frames, rate, &mut self.playhead_fraction);
+        if !self.loop_enabled {
+            self.playhead.advance(samples);
+            return None;
+        }
+        if !self.following_loop {
+            self.playhead.advance(samples);
+            self.following_loop = self.loop_range().contains(self.playhead);
+            return None;
+        }
+        let (playhead, wrap) = self.loop_range().advance_looped(self.playhead, samples);
+        self.playhead = playhead;
+        if wrap.is_some() {
+            self.notify(TransportNotification::Seek(playhead));
+        }
+        wrap
+    }
+
     /// Get playhead position in seconds
     pub fn playhead_seconds(&self) -> f64 {
         self.playhead.to_seconds(self.sample_rate)
     }
+
+    /// Get playhead position in bars, beats and ticks
+    pub fn playhead_musical(&self) -> MusicalTime {
+        let ticks = self.converter().samples_to_ticks(self.playhead);
+        MusicalTime::from_ticks(ticks, self.time_signature.beats_per_bar())
+    }
+
+    /// Seek to a musical position
+    pub fn seek_musical(&mut self, time: MusicalTime) {
+        let ticks = time.to_ticks(self.time_signature.beats_per_bar());
+        let position = self.converter().ticks_to_samples(ticks.max(0));
+        self.seek(position);
+    }
+
+    /// Seek to the start of a bar (1-based)
+    pub fn seek_to_bar(&mut self, bar: i32) {
+        self.seek_musical(MusicalTime::new(bar.max(1), 1, 0));
+    }
+
+    /// Seek to the start of the bar `delta` bars from the current one
+    ///
+    /// Positions are computed from ticks, so nudging back and forth always
+    /// lands on the same samples.
+    pub fn seek_bars(&mut self, delta: i32) {
+        let bar = self.playhead_musical().bar;
+        self.seek_to_bar(bar.saturating_add(delta));
+    }
+
+    fn converter(&self) -> TimeConverter {
+        TimeConverter::new(self.sample_rate, self.tempo, self.time_signature)
+    }
 }
 
 impl Default for Transport {
@@ -77,3 +447,192 @@ impl Default for Transport {
         Self::new(SampleRate::default())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance_wraps_at_loop_end() {
+        let mut transport = Transport::default();
+        transport
+            .set_loop(SampleRange::new(SamplePosition(1000), SamplePosition(2000)))
+            .unwrap();
+        transport.set_loop_enabled(true);
+        transport.seek(SamplePosition(1900));
+
+        let wrap = transport.advance(256);
+        assert_eq!(
+            wrap,
+            Some(LoopWrap {
+                at_frame_offset: 100
+            })
+        );
+        assert_eq!(transport.playhead, SamplePosition(1156));
+
+        assert_eq!(transport.advance(256), None);
+        assert_eq!(transport.playhead, SamplePosition(1412));
+
+        // Past the loop end, playback just continues
+        transport.seek(SamplePosition(5000));
+        assert_eq!(transport.advance(256), None);
+        assert_eq!(transport.playhead, SamplePosition(5256));
+    }
+
+    #[test]
+    fn test_stop_behavior_and_double_stop() {
+        let mut transport = Transport::default();
+        transport.set_stop_behavior(StopBehavior::ReturnToLastPlayStart);
+        transport.seek(SamplePosition(1000));
+        transport.play();
+        transport.advance(500);
+        transport.pause();
+        assert_eq!(transport.playhead, SamplePosition(1500));
+
+        transport.play();
+        transport.advance(500);
+        transport.stop();
+        assert_eq!(transport.playhead, SamplePosition(1500));
+
+        transport.stop();
+        assert_eq!(transport.playhead, SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_seek_to_loop_end_while_playing() {
+        let range = SampleRange::new(SamplePosition(1000), SamplePosition(2000));
+        let mut transport = Transport::default();
+        transport.set_loop(range).unwrap();
+        transport.set_loop_enabled(true);
+        transport.play();
+
+        // The loop end is outside the half-open range: playback runs on
+        transport.seek(SamplePosition(2000));
+        assert!(!transport.is_following_loop());
+        assert_eq!(transport.advance(100), None);
+        assert_eq!(transport.playhead, SamplePosition(2100));
+
+        // Re-entering the loop naturally follows it again
+        transport.seek(SamplePosition(900));
+        transport.advance(200);
+        assert!(transport.is_following_loop());
+        assert!(transport.advance(1000).is_some());
+
+        transport.set_seek_policy(SeekPolicy::ClampToLoop);
+        transport.seek(SamplePosition(2000));
+        assert_eq!(transport.playhead, SamplePosition(1000));
+        assert!(transport.is_following_loop());
+        transport.seek(SamplePosition(1999));
+        assert_eq!(transport.playhead, SamplePosition(1999));
+        assert!(transport.advance(1).is_some());
+        assert_eq!(transport.playhead, SamplePosition(1000));
+    }
+
+    #[test]
+    fn test_bar_nudging_is_exact() {
+        let mut transport = Transport::new(SampleRate::CD_QUALITY);
+        transport.set_tempo(Tempo::new(133.0));
+        transport.set_time_signature(TimeSignature::new(7, 8));
+        transport.seek_to_bar(5);
+        let start = transport.playhead;
+        assert_eq!(transport.playhead_musical(), MusicalTime::new(5, 1, 0));
+
+        for _ in 0..10 {
+            transport.seek_bars(100);
+            assert_eq!(transport.playhead_musical(), MusicalTime::new(105, 1, 0));
+            transport.seek_bars(-100);
+            assert_eq!(transport.playhead, start);
+        }
+
+        transport.seek_bars(-100);
+        assert_eq!(transport.playhead, SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_pre_roll() {
+        // One bar of 4/4 at 120 BPM is 2 seconds
+        let mut transport = Transport::new(SampleRate::DVD_QUALITY);
+        transport.set_stop_behavior(StopBehavior::ReturnToLastPlayStart);
+        transport.set_pre_roll(Some(PreRoll::Bars(1)));
+        transport.seek(SamplePosition(200_000));
+        transport.play_with_preroll();
+        assert!(transport.is_playing);
+        assert_eq!(transport.playhead, SamplePosition(104_000));
+        transport.stop();
+        assert_eq!(transport.playhead, SamplePosition(200_000));
+
+        transport.set_pre_roll(Some(PreRoll::Seconds(10.0)));
+        transport.play_with_preroll();
+        assert_eq!(transport.playhead, SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_playback_rate() {
+        let mut transport = Transport::default();
+        transport.set_playback_rate(0.5);
+        for _ in 0..1000 {
+            transport.advance(333);
+        }
+        assert_eq!(transport.playhead, SamplePosition(166_500));
+
+        // Changing rate mid-way keeps the half sample already played
+        transport.advance(1);
+        transport.set_playback_rate(1.5);
+        transport.advance(1);
+        assert_eq!(transport.playhead, SamplePosition(166_502));
+
+        transport.set_playback_rate(100.0);
+        assert_eq!(transport.playback_rate, 4.0);
+    }
+
+    #[test]
+    fn test_record_safe_blocks_recording() {
+        let mut transport = Transport::default();
+        transport.set_input_monitoring(MonitorMode::WhenArmed);
+        transport.set_record_safe(true);
+        assert_eq!(transport.start_recording(), Err(TransportError::RecordSafe));
+        assert!(!transport.is_recording);
+        assert!(!transport.is_monitoring());
+
+        transport.set_record_safe(false);
+        assert!(transport.start_recording().is_ok());
+        assert!(transport.is_monitoring());
+    }
+
+    #[test]
+    fn test_history_ignores_rewind_and_loop_wraps() {
+        let mut transport = Transport::default();
+        transport
+            .set_loop(SampleRange::new(SamplePosition(0), SamplePosition(1000)))
+            .unwrap();
+        transport.set_loop_enabled(true);
+        transport.seek(SamplePosition(500));
+        transport.play();
+        transport.advance(800);
+        transport.pause();
+        transport.seek(SamplePosition(700));
+        transport.rewind();
+
+        let history: Vec<i64> = transport.history().entries().map(|p| p.0).collect();
+        assert_eq!(history, vec![500, 700]);
+        assert_eq!(transport.back(), Some(SamplePosition(500)));
+        assert_eq!(transport.playhead, SamplePosition(500));
+        assert_eq!(transport.forward(), Some(SamplePosition(700)));
+    }
+
+    #[test]
+    fn test_set_loop_rejects_empty_range() {
+        let mut transport = Transport::default();
+        let empty = SampleRange::new(SamplePosition(500), SamplePosition(500));
+        let backwards = SampleRange::new(SamplePosition(500), SamplePosition(100));
+        assert!(transport.set_loop(empty).is_err());
+        assert_eq!(
+            transport.set_loop(backwards),
+            Err(TransportError::InvalidLoop {
+                start: 500,
+                end: 100
+            })
+        );
+        assert_eq!(transport.loop_end, SamplePosition::ZERO);
+    }
+}
@@ -0,0 +1,194 @@
+//! Transport change notifications
+
+use crate::Transport;
+use koto_core::{SamplePosition, SampleRange, Tempo};
+use std::collections::VecDeque;
+
+/// Observer of transport changes
+///
+/// Every callback receives the transport, so a listener may change it; the
+/// resulting notifications are queued and delivered after the current one,
+/// never re-entrantly.
+pub trait TransportListener: Send {
+    fn on_play(&mut self, _transport: &mut Transport) {}
+    fn on_stop(&mut self, _transport: &mut Transport) {}
+    fn on_seek(&mut self, _transport: &mut Transport, _position: SamplePosition) {}
+    fn on_tempo_changed(&mut self, _transport: &mut Transport, _tempo: Tempo) {}
+    fn on_loop_changed(&mut self, _transport: &mut Transport, _enabled: bool, _range: SampleRange) {
+    }
+}
+
+/// Identifies a registered listener for removal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerHandle(u64);
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum TransportNotification {
+    Play,
+    Stop,
+    Seek(SamplePosition),
+    TempoChanged(Tempo),
+    LoopChanged { enabled: bool, range: SampleRange },
+}
+
+impl TransportNotification {
+    fn deliver(self, listener: &mut dyn TransportListener, transport: &mut Transport) {
+        match self {
+            Self::Play => listener.on_play(transport),
+            Self::Stop => listener.on_stop(transport),
+            Self::Seek(position) => listener.on_seek(transport, position),
+            Self::TempoChanged(tempo) => listener.on_tempo_changed(transport, tempo),
+            Self::LoopChanged { enabled, range } => {
+                listener.on_loop_changed(transport, enabled, range)
+            }
+        }
+    }
+}
+
+/// Registered listeners and notifications waiting to be delivered
+#[derive(Default)]
+pub(crate) struct Listeners {
+    active: Vec<(ListenerHandle, Box<dyn TransportListener>)>,
+    pending: VecDeque<TransportNotification>,
+    /// Removed while their notifications were being delivered
+    removed: Vec<ListenerHandle>,
+    dispatching: bool,
+    next_id: u64,
+}
+
+impl Transport {
+    /// Register a listener; the handle removes it again
+    pub fn add_listener(&mut self, listener: Box<dyn TransportListener>) -> ListenerHandle {
+        let handle = ListenerHandle(self.listeners.next_id);
+        self.listeners.next_id += 1;
+        self.listeners.active.push((handle, listener));
+        handle
+    }
+
+    /// Unregister a listener; returns whether it was registered
+    ///
+    /// Safe to call from inside a listener callback, in which case the
+    /// listener receives no further notifications.
+    pub fn remove_listener(&mut self, handle: ListenerHandle) -> bool {
+        if let Some(index) = self.listeners.active.iter().position(|(h, _)| *h == handle) {
+            self.listeners.active.remove(index);
+            return true;
+        }
+        let in_use = self.listeners.dispatching
+            && handle.0 < self.listeners.next_id
+            && !self.listeners.removed.contains(&handle);
+        if in_use {
+            self.listeners.removed.push(handle);
+        }
+        in_use
+    }
+
+    pub(crate) fn notify(&mut self, notification: TransportNotification) {
+        self.listeners.pending.push_back(notification);
+        if self.listeners.dispatching {
+            return;
+        }
+        self.listeners.dispatching = true;
+
+        // Listeners are taken out while they run so they can borrow the
+        // transport; anything added meanwhile lands in `active`
+        let mut listeners = std::mem::take(&mut self.listeners.active);
+        while let Some(notification) = self.listeners.pending.pop_front() {
+            for (handle, listener) in listeners.iter_mut() {
+                if !self.listeners.removed.contains(handle) {
+                    notification.deliver(listener.as_mut(), self);
+                }
+            }
+        }
+
+        let removed = std::mem::take(&mut self.listeners.removed);
+        listeners.retain(|(handle, _)| !removed.contains(handle));
+        listeners.append(&mut self.listeners.active);
+        self.listeners.active = listeners;
+        self.listeners.dispatching = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Logs every callback; seeks to zero on its first play if `rewind_on_play`
+    struct Recorder {
+        log: Arc<Mutex<Vec<String>>>,
+        rewind_on_play: bool,
+    }
+
+    impl TransportListener for Recorder {
+        fn on_play(&mut self, transport: &mut Transport) {
+            self.log.lock().unwrap().push("play".into());
+            if self.rewind_on_play {
+                self.rewind_on_play = false;
+                transport.seek(SamplePosition::ZERO);
+                // Delivered after this callback returns
+                self.log.lock().unwrap().push("play done".into());
+            }
+        }
+
+        fn on_stop(&mut self, _transport: &mut Transport) {
+            self.log.lock().unwrap().push("stop".into());
+        }
+
+        fn on_seek(&mut self, _transport: &mut Transport, position: SamplePosition) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("seek {}", position.0));
+        }
+
+        fn on_tempo_changed(&mut self, _transport: &mut Transport, tempo: Tempo) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("tempo {}", tempo.bpm()));
+        }
+    }
+
+    fn recorder(rewind_on_play: bool) -> (Box<Recorder>, Arc<Mutex<Vec<String>>>) {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            log: log.clone(),
+            rewind_on_play,
+        };
+        (Box::new(recorder), log)
+    }
+
+    #[test]
+    fn test_listener_mutating_transport_is_not_reentrant() {
+        let mut transport = Transport::default();
+        let (listener, log) = recorder(true);
+        transport.add_listener(listener);
+
+        transport.seek(SamplePosition(500));
+        transport.play();
+        transport.set_tempo(Tempo::new(90.0));
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec!["seek 500", "play", "play done", "seek 0", "tempo 90"]
+        );
+        assert_eq!(transport.playhead, SamplePosition::ZERO);
+    }
+
+    #[test]
+    fn test_removed_listener_is_not_called() {
+        let mut transport = Transport::default();
+        let (first, first_log) = recorder(false);
+        let (second, second_log) = recorder(false);
+        let handle = transport.add_listener(first);
+        transport.add_listener(second);
+
+        transport.play();
+        assert!(transport.remove_listener(handle));
+        assert!(!transport.remove_listener(handle));
+        transport.stop();
+
+        assert_eq!(*first_log.lock().unwrap(), vec!["play"]);
+        assert_eq!(*second_log.lock().unwrap(), vec!["play", "stop"]);
+    }
+}
@@ -0,0 +1,156 @@
+//! Tap tempo detection
+
+use koto_core::Tempo;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A pause longer than this starts a new tap sequence
+pub const TAP_RESET_GAP: Duration = Duration::from_secs(2);
+
+/// Tempo detected from the current tap sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TapEstimate {
+    pub tempo: Tempo,
+    /// Average of the accepted tap intervals
+    pub interval: Duration,
+    /// 0.0 to 1.0; grows with the number of consistent taps
+    pub confidence: f32,
+}
+
+/// Turns tap timestamps into a tempo
+///
+/// Averages the last `max_intervals` intervals, ignoring any that are more
+/// than twice or less than half the median. A gap longer than
+/// [`TAP_RESET_GAP`] starts over.
+#[derive(Debug, Clone)]
+pub struct TapTempo {
+    taps: VecDeque<Instant>,
+    max_intervals: usize,
+    estimate: Option<TapEstimate>,
+}
+
+impl TapTempo {
+    pub fn new(max_intervals: usize) -> Self {
+        let max_intervals = max_intervals.max(1);
+        Self {
+            taps: VecDeque::with_capacity(max_intervals + 1),
+            max_intervals,
+            estimate: None,
+        }
+    }
+
+    /// Record a tap; returns the tempo once there are at least two taps
+    pub fn tap(&mut self, now: Instant) -> Option<Tempo> {
+        if let Some(&last) = self.taps.back() {
+            if now.saturating_duration_since(last) > TAP_RESET_GAP {
+                self.reset();
+            }
+        }
+        self.taps.push_back(now);
+        while self.taps.len() > self.max_intervals + 1 {
+            self.taps.pop_front();
+        }
+        self.estimate = self.compute();
+        self.estimate.map(|estimate| estimate.tempo)
+    }
+
+    /// The current estimate, if a sequence is in progress
+    pub fn estimate(&self) -> Option<TapEstimate> {
+        self.estimate
+    }
+
+    /// Number of taps in the current sequence
+    pub fn tap_count(&self) -> usize {
+        self.taps.len()
+    }
+
+    pub fn reset(&mut self) {
+        self.taps.clear();
+        self.estimate = None;
+    }
+
+    fn compute(&self) -> Option<TapEstimate> {
+        let intervals: Vec<f64> = self
+            .taps
+            .iter()
+            .zip(self.taps.iter().skip(1))
+            .map(|(a, b)| b.saturating_duration_since(*a).as_secs_f64())
+            .filter(|&interval| interval > 0.0)
+            .collect();
+        if intervals.is_empty() {
+            return None;
+        }
+
+        let mut sorted = intervals.clone();
+        sorted.sort_by(f64::total_cmp);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) * 0.5
+        } else {
+            sorted[middle]
+        };
+
+        let accepted: Vec<f64> = intervals
+            .into_iter()
+            .filter(|&interval| interval <= median * 2.0 && interval >= median * 0.5)
+            .collect();
+        let mean = accepted.iter().sum::<f64>() / accepted.len() as f64;
+        let variance =
+            accepted.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / accepted.len() as f64;
+        let consistency = (1.0 - variance.sqrt() / mean).clamp(0.0, 1.0);
+        let coverage = accepted.len() as f64 / self.max_intervals as f64;
+
+        Some(TapEstimate {
+            tempo: Tempo::new(60.0 / mean),
+            interval: Duration::from_secs_f64(mean),
+            confidence: (coverage * consistency) as f32,
+        })
+    }
+}
+
+impl Default for TapTempo {
+    /// Average over the last 4 intervals
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap_at(tap: &mut TapTempo, start: Instant, seconds: &[f64]) -> Option<Tempo> {
+        seconds
+            .iter()
+            .map(|&s| tap.tap(start + Duration::from_secs_f64(s)))
+            .last()
+            .flatten()
+    }
+
+    #[test]
+    fn test_steady_taps() {
+        let start = Instant::now();
+        let mut tap = TapTempo::default();
+        let tempo = tap_at(&mut tap, start, &[0.0, 0.5, 1.0, 1.5, 2.0]).unwrap();
+        assert!((tempo.bpm() - 120.0).abs() < 1e-6);
+        assert!(tap.estimate().unwrap().confidence > 0.99);
+
+        // 87.5 BPM after a pause long enough to reset
+        let interval = 60.0 / 87.5;
+        let times: Vec<f64> = (0..6).map(|i| 10.0 + i as f64 * interval).collect();
+        let tempo = tap_at(&mut tap, start, &times).unwrap();
+        assert!((tempo.bpm() - 87.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_outlier_is_rejected() {
+        let start = Instant::now();
+        let mut tap = TapTempo::default();
+        // One missed beat makes a 1.8 s interval
+        let tempo = tap_at(&mut tap, start, &[0.0, 0.5, 1.0, 2.8, 3.3, 3.8]).unwrap();
+        assert!((tempo.bpm() - 120.0).abs() < 1e-6);
+        let estimate = tap.estimate().unwrap();
+        assert!(estimate.confidence < 1.0);
+        assert_eq!(estimate.interval.as_millis(), 500);
+    }
+}
@@ -2,8 +2,9 @@
 
 use crate::theme::KotoTheme;
 use egui::{CentralPanel, Context, TopBottomPanel};
-use koto_audio_engine::{AudioEngine, AudioEvent};
-use koto_core::{SamplePosition, Tempo};
+use koto_audio_engine::{AudioEngine, AudioEvent, DevicePreferences, RecordingTake};
+use koto_core::{SamplePosition, StopBehavior, Tempo};
+use koto_mixer::MeterReading;
 
 /// Main application state
 pub struct KotoApp {
@@ -19,23 +20,42 @@ pub struct KotoApp {
     pub is_playing: bool,
     /// Is recording
     pub is_recording: bool,
+    /// Beats left before recording starts, while counting in
+    pub count_in_remaining: Option<u32>,
+    /// Last take started, drained every frame
+    pub recording_take: Option<RecordingTake>,
+    /// Samples of the last take, until they're written to a file
+    pub recorded_samples: Vec<f32>,
     /// Peak meters (left, right)
     pub peak_meters: (f32, f32),
+    /// Master phase correlation (-1.0 to 1.0)
+    pub correlation: f32,
+    /// Mixer channel meters, in channel order
+    pub channel_meters: Vec<MeterReading>,
+    /// Master limiter gain reduction in dB
+    pub gain_reduction_db: f32,
     /// Master volume
     pub master_volume: f32,
+    /// Master limiter enabled
+    pub limiter_enabled: bool,
     /// Metronome enabled
     pub metronome_enabled: bool,
+    /// Where the stop button moves the playhead
+    pub stop_behavior: StopBehavior,
 }
 
 impl KotoApp {
-    /// Create a new application
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    /// Create a new application, starting audio with the preferred device
+    pub fn new(_cc: &eframe::CreationContext<'_>, preferences: DevicePreferences) -> Self {
         let mut audio_engine = AudioEngine::new().expect("Failed to create audio engine");
 
         // Start audio engine
+        audio_engine.set_device_preferences(preferences);
         if let Err(e) = audio_engine.start() {
             tracing::error!("Failed to start audio engine: {}", e);
         }
+        let stop_behavior = StopBehavior::ReturnToStart;
+        audio_engine.set_stop_behavior(stop_behavior);
 
         Self {
             audio_engine,
@@ -44,14 +64,25 @@ impl KotoApp {
             tempo: Tempo::DEFAULT,
             is_playing: false,
             is_recording: false,
+            count_in_remaining: None,
+            recording_take: None,
+            recorded_samples: Vec::new(),
             peak_meters: (0.0, 0.0),
+            correlation: 0.0,
+            channel_meters: Vec::new(),
+            gain_reduction_db: 0.0,
             master_volume: 1.0,
+            limiter_enabled: false,
             metronome_enabled: false,
+            stop_behavior,
         }
     }
 
     /// Process events from audio engine
     fn process_audio_events(&mut self) {
+        if let Some(take) = &mut self.recording_take {
+            take.drain_into(&mut self.recorded_samples);
+        }
         for event in self.audio_engine.receive_events() {
             match event {
                 AudioEvent::PlayheadMoved(pos) => {
@@ -60,9 +91,22 @@ impl KotoApp {
                 AudioEvent::MeterUpdate {
                     peak_left,
                     peak_right,
+                    gain_reduction_db,
+                    correlation,
                     ..
                 } => {
                     self.peak_meters = (peak_left, peak_right);
+                    self.gain_reduction_db = gain_reduction_db;
+                    self.correlation = correlation;
+                }
+                AudioEvent::ChannelMeters(chunk) => {
+                    self.channel_meters
+                        .resize(chunk.total, MeterReading::default());
+                    let end = (chunk.first + chunk.readings().len()).min(chunk.total);
+                    if chunk.first < end {
+                        self.channel_meters[chunk.first..end]
+                            .copy_from_slice(&chunk.readings()[..end - chunk.first]);
+                    }
                 }
                 AudioEvent::TransportStateChanged {
                     is_playing,
@@ -70,6 +114,20 @@ impl KotoApp {
                 } => {
                     self.is_playing = is_playing;
                     self.is_recording = is_recording;
+                    // Ticks for a new count-in follow this event
+                    self.count_in_remaining = None;
+                }
+                AudioEvent::CountInTick { beats_remaining } => {
+                    self.count_in_remaining = Some(beats_remaining);
+                }
+                AudioEvent::RecordingOverflow { dropped_frames } => {
+                    tracing::warn!("Recording fell behind; {} frames dropped", dropped_frames);
+                }
+                AudioEvent::TrackStateOverflow(track) => {
+                    tracing::warn!(
+                        "Too many muted/soloed tracks; change to {:?} dropped",
+                        track
+                    );
                 }
                 AudioEvent::DeviceError(err) => {
                     tracing::error!("Audio device error: {}", err);
@@ -82,6 +140,15 @@ impl KotoApp {
     }
 }
 
+/// Short label for the stop behavior selector
+fn stop_behavior_label(behavior: StopBehavior) -> &'static str {
+    match behavior {
+        StopBehavior::StayAtPosition => "Stay",
+        StopBehavior::ReturnToStart => "To start",
+        StopBehavior::ReturnToLastPlayStart => "To play start",
+    }
+}
+
 impl eframe::App for KotoApp {
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
         // Apply theme
@@ -99,7 +166,7 @@ impl eframe::App for KotoApp {
                 // Transport controls
                 if ui.button(if self.is_playing { "⏸" } else { "▶" }).clicked() {
                     if self.is_playing {
-                        self.audio_engine.stop_playback();
+                        self.audio_engine.pause();
                     } else {
                         self.audio_engine.play();
                     }
@@ -107,15 +174,40 @@ impl eframe::App for KotoApp {
 
                 if ui.button("⏹").clicked() {
                     self.audio_engine.stop_playback();
-                    self.audio_engine.seek(SamplePosition::ZERO);
                 }
 
-                let rec_button = ui.button(if self.is_recording { "⏺ REC" } else { "⏺" });
-                if rec_button.clicked() {
-                    if self.is_recording {
+                let previous_behavior = self.stop_behavior;
+                egui::ComboBox::from_id_salt("stop_behavior")
+                    .selected_text(stop_behavior_label(self.stop_behavior))
+                    .show_ui(ui, |ui| {
+                        for behavior in [
+                            StopBehavior::StayAtPosition,
+                            StopBehavior::ReturnToStart,
+                            StopBehavior::ReturnToLastPlayStart,
+                        ] {
+                            ui.selectable_value(
+                                &mut self.stop_behavior,
+                                behavior,
+                                stop_behavior_label(behavior),
+                            );
+                        }
+                    });
+                if self.stop_behavior != previous_behavior {
+                    self.audio_engine.set_stop_behavior(self.stop_behavior);
+                }
+
+                let rec_label = match self.count_in_remaining {
+                    Some(beats) => format!("⏺ {beats}"),
+                    None if self.is_recording => "⏺ REC".to_string(),
+                    None => "⏺".to_string(),
+                };
+                if ui.button(rec_label).clicked() {
+                    if self.is_recording || self.count_in_remaining.is_some() {
+                        self.count_in_remaining = None;
                         self.audio_engine.stop_recording();
                     } else {
-                        self.audio_engine.start_recording();
+                        self.recorded_samples.clear();
+                        self.recording_take = Some(self.audio_engine.start_recording());
                     }
                 }
 
@@ -197,6 +289,14 @@ impl eframe::App for KotoApp {
                 };
                 ui.painter().rect_filled(filled_rect, 2.0, color);
 
+                // Phase correlation, highlighted when out of phase
+                let correlation_color = if self.correlation < 0.0 {
+                    self.theme.error
+                } else {
+                    self.theme.text_dim
+                };
+                ui.colored_label(correlation_color, format!("{:+.2}", self.correlation));
+
                 ui.separator();
 
                 // Master volume
@@ -208,6 +308,14 @@ impl eframe::App for KotoApp {
                     self.audio_engine.set_master_volume(self.master_volume);
                 }
 
+                // Master limiter
+                if ui.checkbox(&mut self.limiter_enabled, "Limiter").changed() {
+                    self.audio_engine.set_limiter_enabled(self.limiter_enabled);
+                }
+                if self.limiter_enabled {
+                    ui.label(format!("GR {:.1} dB", self.gain_reduction_db));
+                }
+
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     ui.label(format!("{}Hz", self.audio_engine.sample_rate().0));
                 });
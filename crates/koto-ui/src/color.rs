@@ -0,0 +1,14 @@
+//! Conversion between the timeline's packed colors and egui colors
+
+use egui::Color32;
+
+/// Convert a packed `0xRRGGBB` color; the top byte is ignored
+pub fn packed_to_color32(packed: u32) -> Color32 {
+    let [_, r, g, b] = packed.to_be_bytes();
+    Color32::from_rgb(r, g, b)
+}
+
+/// Pack a color as `0xRRGGBB`, dropping alpha
+pub fn color32_to_packed(color: Color32) -> u32 {
+    u32::from_be_bytes([0, color.r(), color.g(), color.b()])
+}
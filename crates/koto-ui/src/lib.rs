@@ -1,10 +1,12 @@
 //! Koto UI - User interface using egui and eframe
 
 pub mod app;
+pub mod color;
 pub mod theme;
 pub mod views;
 pub mod widgets;
 
 pub use app::*;
+pub use color::*;
 pub use eframe;
 pub use theme::*;
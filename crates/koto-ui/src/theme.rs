@@ -1,6 +1,8 @@
 //! UI theming
 
+use crate::color::packed_to_color32;
 use egui::{Color32, Rounding, Stroke, Style, Visuals};
+use koto_timeline::TRACK_PALETTE;
 
 /// Koto dark theme colors
 pub struct KotoTheme {
@@ -37,16 +39,10 @@ impl KotoTheme {
             success: Color32::from_rgb(46, 204, 113),
             warning: Color32::from_rgb(241, 196, 15),
             error: Color32::from_rgb(231, 76, 60),
-            track_colors: vec![
-                Color32::from_rgb(74, 144, 226), // Blue
-                Color32::from_rgb(46, 204, 113), // Green
-                Color32::from_rgb(155, 89, 182), // Purple
-                Color32::from_rgb(241, 196, 15), // Yellow
-                Color32::from_rgb(231, 76, 60),  // Red
-                Color32::from_rgb(26, 188, 156), // Teal
-                Color32::from_rgb(230, 126, 34), // Orange
-                Color32::from_rgb(52, 73, 94),   // Dark blue
-            ],
+            track_colors: TRACK_PALETTE
+                .iter()
+                .map(|&c| packed_to_color32(c))
+                .collect(),
         }
     }
 
@@ -1,6 +1,9 @@
 //! Mixer view
 
+use crate::widgets::mute_solo_buttons;
 use egui::Ui;
+use koto_audio_engine::AudioEngine;
+use koto_timeline::Timeline;
 
 /// Mixer console view
 pub struct MixerView {
@@ -19,9 +22,15 @@ impl MixerView {
         Self::default()
     }
 
-    pub fn ui(&mut self, ui: &mut Ui) {
+    pub fn ui(&mut self, ui: &mut Ui, timeline: &mut Timeline, engine: &mut AudioEngine) {
         ui.horizontal(|ui| {
-            ui.label("Mixer - Coming soon");
+            for track in &mut timeline.tracks {
+                ui.vertical(|ui| {
+                    ui.label(&track.name);
+                    ui.horizontal(|ui| mute_solo_buttons(ui, track, engine));
+                });
+                ui.separator();
+            }
         });
     }
 }
@@ -1,6 +1,21 @@
 //! Timeline view
 
+use crate::widgets::mute_solo_buttons;
 use egui::{Color32, Pos2, Rect, Ui, Vec2};
+use koto_audio_engine::AudioEngine;
+use koto_timeline::Timeline;
+
+/// Height of the time ruler in pixels
+const RULER_HEIGHT: f32 = 24.0;
+
+/// Scrub gesture on the time ruler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RulerScrub {
+    /// The ruler is being dragged at this time (seconds)
+    Move(f64),
+    /// The drag ended
+    Release,
+}
 
 /// Timeline view for arranging audio and MIDI regions
 pub struct TimelineView {
@@ -10,6 +25,8 @@ pub struct TimelineView {
     pub scroll: f32,
     /// Track height in pixels
     pub track_height: f32,
+    /// The current drag started in the ruler
+    pub scrubbing: bool,
 }
 
 impl Default for TimelineView {
@@ -18,6 +35,7 @@ impl Default for TimelineView {
             zoom: 50.0,
             scroll: 0.0,
             track_height: 80.0,
+            scrubbing: false,
         }
     }
 }
@@ -38,7 +56,9 @@ impl TimelineView {
     }
 
     /// Render the timeline
-    pub fn ui(&mut self, ui: &mut Ui) {
+    ///
+    /// Dragging in the ruler scrubs; dragging elsewhere scrolls.
+    pub fn ui(&mut self, ui: &mut Ui) -> Option<RulerScrub> {
         let available_size = ui.available_size();
         let (response, painter) =
             ui.allocate_painter(available_size, egui::Sense::click_and_drag());
@@ -53,8 +73,25 @@ impl TimelineView {
         // Draw grid lines
         self.draw_grid(&painter, rect);
 
+        // Handle scrub
+        let mut scrub = None;
+        if response.drag_started() {
+            self.scrubbing = response
+                .interact_pointer_pos()
+                .is_some_and(|pos| pos.y < rect.top() + RULER_HEIGHT);
+        }
+        if self.scrubbing {
+            if response.drag_stopped() {
+                self.scrubbing = false;
+                scrub = Some(RulerScrub::Release);
+            } else if let Some(pos) = response.interact_pointer_pos() {
+                let time = self.x_to_time(pos.x, rect.left()).max(0.0);
+                scrub = Some(RulerScrub::Move(time));
+            }
+        }
+
         // Handle scroll
-        if response.dragged() {
+        if response.dragged() && !self.scrubbing {
             let delta = response.drag_delta();
             self.scroll -= delta.x / self.zoom;
             self.scroll = self.scroll.max(0.0);
@@ -72,11 +109,26 @@ impl TimelineView {
                 self.scroll = self.scroll.max(0.0);
             }
         }
+
+        scrub
+    }
+
+    /// Render the track header column (name, mute, solo)
+    pub fn track_headers(&self, ui: &mut Ui, timeline: &mut Timeline, engine: &mut AudioEngine) {
+        ui.add_space(RULER_HEIGHT);
+        for track in &mut timeline.tracks {
+            let size = Vec2::new(ui.available_width(), self.track_height);
+            ui.allocate_ui(size, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(&track.name);
+                    mute_solo_buttons(ui, track, engine);
+                });
+            });
+        }
     }
 
     fn draw_ruler(&self, painter: &egui::Painter, rect: Rect) {
-        let ruler_height = 24.0;
-        let ruler_rect = Rect::from_min_size(rect.min, Vec2::new(rect.width(), ruler_height));
+        let ruler_rect = Rect::from_min_size(rect.min, Vec2::new(rect.width(), RULER_HEIGHT));
         painter.rect_filled(ruler_rect, 0.0, Color32::from_rgb(40, 40, 45));
 
         // Draw time markers
@@ -118,7 +170,10 @@ impl TimelineView {
             let x = self.time_to_x(t as f64, rect.left());
             if x >= rect.left() && x <= rect.right() {
                 painter.line_segment(
-                    [Pos2::new(x, rect.top() + 24.0), Pos2::new(x, rect.bottom())],
+                    [
+                        Pos2::new(x, rect.top() + RULER_HEIGHT),
+                        Pos2::new(x, rect.bottom()),
+                    ],
                     (1.0, Color32::from_rgb(45, 45, 50)),
                 );
             }
@@ -2,8 +2,10 @@
 
 pub mod knob;
 pub mod meter;
+pub mod track_buttons;
 pub mod waveform;
 
 pub use knob::*;
 pub use meter::*;
+pub use track_buttons::*;
 pub use waveform::*;
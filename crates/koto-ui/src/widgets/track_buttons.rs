@@ -0,0 +1,28 @@
+//! Mute and solo toggle buttons
+
+use egui::{Color32, Ui};
+use koto_audio_engine::AudioEngine;
+use koto_timeline::Track;
+
+/// Draw mute/solo toggles for a track and forward changes to the engine
+pub fn mute_solo_buttons(ui: &mut Ui, track: &mut Track, engine: &mut AudioEngine) {
+    let mute = egui::Button::new("M").fill(if track.mute {
+        Color32::from_rgb(200, 120, 40)
+    } else {
+        Color32::from_rgb(50, 50, 56)
+    });
+    if ui.add(mute).clicked() {
+        track.mute = !track.mute;
+        engine.set_track_mute(track.id, track.mute);
+    }
+
+    let solo = egui::Button::new("S").fill(if track.solo {
+        Color32::from_rgb(210, 190, 40)
+    } else {
+        Color32::from_rgb(50, 50, 56)
+    });
+    if ui.add(solo).clicked() {
+        track.solo = !track.solo;
+        engine.set_track_solo(track.id, track.solo);
+    }
+}
@@ -1,5 +1,6 @@
 //! Koto Undo - Undo/redo system
 
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
 /// A command that can be undone and redone
@@ -10,16 +11,60 @@ pub trait UndoCommand: Send {
     fn undo(&mut self);
     /// Get a description of the command
     fn description(&self) -> &str;
+    /// The command as data, if it can be saved with the history
+    ///
+    /// Commands implementing [`SerializableCommand`] return
+    /// [`to_record`](SerializableCommand::to_record) here.
+    fn record(&self) -> Option<CommandRecord> {
+        None
+    }
+}
+
+/// A command saved as data
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommandRecord {
+    /// [`SerializableCommand::KIND`] of the command's type
+    pub kind: String,
+    pub data: serde_json::Value,
+}
+
+/// A command that can describe itself as data and be re-created, already
+/// executed, against a `Target`
+pub trait SerializableCommand<Target>: UndoCommand + Sized {
+    /// Name the command is saved under; keep it stable across versions
+    const KIND: &'static str;
+
+    fn to_data(&self) -> serde_json::Result<serde_json::Value>;
+
+    /// Re-create a command saved with [`to_data`](Self::to_data), in the
+    /// state it was in after executing
+    fn from_data(target: &Target, data: serde_json::Value) -> serde_json::Result<Self>;
+
+    fn to_record(&self) -> Option<CommandRecord> {
+        Some(CommandRecord {
+            kind: Self::KIND.to_string(),
+            data: self.to_data().ok()?,
+        })
+    }
 }
 
 /// Undo/redo history
+///
+/// Each executed command gets a revision number, so the history can tell
+/// when undo or redo lands back on the state marked with
+/// [`mark_saved`](Self::mark_saved).
 pub struct UndoHistory {
-    /// Commands that can be undone
-    undo_stack: VecDeque<Box<dyn UndoCommand>>,
-    /// Commands that can be redone
-    redo_stack: VecDeque<Box<dyn UndoCommand>>,
+    /// Commands that can be undone, with their revisions
+    undo_stack: VecDeque<(u64, Box<dyn UndoCommand>)>,
+    /// Commands that can be redone, with their revisions
+    redo_stack: VecDeque<(u64, Box<dyn UndoCommand>)>,
     /// Maximum history size
     max_size: usize,
+    /// Revision with nothing left to undo
+    base_revision: u64,
+    next_revision: u64,
+    /// Revision marked as saved, if still reachable
+    saved_revision: Option<u64>,
 }
 
 impl UndoHistory {
@@ -28,27 +73,33 @@ impl UndoHistory {
             undo_stack: VecDeque::new(),
             redo_stack: VecDeque::new(),
             max_size,
+            base_revision: 0,
+            next_revision: 1,
+            saved_revision: Some(0),
         }
     }
 
     /// Execute a command and add it to the history
     pub fn execute(&mut self, mut command: Box<dyn UndoCommand>) {
         command.execute();
-        self.undo_stack.push_back(command);
+        self.undo_stack.push_back((self.next_revision, command));
+        self.next_revision += 1;
         self.redo_stack.clear();
 
         // Limit history size
         while self.undo_stack.len() > self.max_size {
-            self.undo_stack.pop_front();
+            if let Some((revision, _)) = self.undo_stack.pop_front() {
+                self.base_revision = revision;
+            }
         }
     }
 
     /// Undo the last command
     pub fn undo(&mut self) -> Option<&str> {
-        if let Some(mut command) = self.undo_stack.pop_back() {
+        if let Some((revision, mut command)) = self.undo_stack.pop_back() {
             command.undo();
             let desc = command.description().to_string();
-            self.redo_stack.push_back(command);
+            self.redo_stack.push_back((revision, command));
             Some(Box::leak(desc.into_boxed_str()))
         } else {
             None
@@ -57,10 +108,10 @@ impl UndoHistory {
 
     /// Redo the last undone command
     pub fn redo(&mut self) -> Option<&str> {
-        if let Some(mut command) = self.redo_stack.pop_back() {
+        if let Some((revision, mut command)) = self.redo_stack.pop_back() {
             command.execute();
             let desc = command.description().to_string();
-            self.undo_stack.push_back(command);
+            self.undo_stack.push_back((revision, command));
             Some(Box::leak(desc.into_boxed_str()))
         } else {
             None
@@ -79,19 +130,75 @@ impl UndoHistory {
 
     /// Get the description of the next undo action
     pub fn undo_description(&self) -> Option<&str> {
-        self.undo_stack.back().map(|c| c.description())
+        self.undo_stack.back().map(|(_, c)| c.description())
     }
 
     /// Get the description of the next redo action
     pub fn redo_description(&self) -> Option<&str> {
-        self.redo_stack.back().map(|c| c.description())
+        self.redo_stack.back().map(|(_, c)| c.description())
     }
 
     /// Clear all history
     pub fn clear(&mut self) {
+        self.base_revision = self.revision();
         self.undo_stack.clear();
         self.redo_stack.clear();
     }
+
+    /// Revision of the current state: that of the last command not undone
+    pub fn revision(&self) -> u64 {
+        self.undo_stack
+            .back()
+            .map_or(self.base_revision, |(revision, _)| *revision)
+    }
+
+    /// Mark the current state as saved
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = Some(self.revision());
+    }
+
+    /// Whether undo and redo have brought the history back to the state
+    /// marked as saved
+    ///
+    /// A new history starts out saved. Once the saved state can no longer
+    /// be reached, e.g. a new command replaced it on the redo stack, this
+    /// stays `false` until the next [`mark_saved`](Self::mark_saved).
+    pub fn is_at_saved(&self) -> bool {
+        self.saved_revision == Some(self.revision())
+    }
+
+    /// Records of up to `limit` of the most recent undoable commands,
+    /// oldest first
+    ///
+    /// Stops at the newest command without a record: the ones before it
+    /// can't be undone without it.
+    pub fn records(&self, limit: usize) -> Vec<CommandRecord> {
+        let mut records: Vec<CommandRecord> = self
+            .undo_stack
+            .iter()
+            .rev()
+            .take(limit)
+            .map_while(|(_, command)| command.record())
+            .collect();
+        records.reverse();
+        records
+    }
+
+    /// Replace the history with commands that have already been executed,
+    /// oldest first, and mark the result saved
+    pub fn restore(&mut self, commands: Vec<Box<dyn UndoCommand>>) {
+        self.clear();
+        for command in commands {
+            self.undo_stack.push_back((self.next_revision, command));
+            self.next_revision += 1;
+        }
+        while self.undo_stack.len() > self.max_size {
+            if let Some((revision, _)) = self.undo_stack.pop_front() {
+                self.base_revision = revision;
+            }
+        }
+        self.mark_saved();
+    }
 }
 
 impl Default for UndoHistory {
@@ -99,3 +206,115 @@ impl Default for UndoHistory {
         Self::new(100)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Noop;
+
+    impl UndoCommand for Noop {
+        fn execute(&mut self) {}
+        fn undo(&mut self) {}
+        fn description(&self) -> &str {
+            "Noop"
+        }
+    }
+
+    /// A command that saves as its number
+    struct Numbered(u64);
+
+    impl UndoCommand for Numbered {
+        fn execute(&mut self) {}
+        fn undo(&mut self) {}
+        fn description(&self) -> &str {
+            "Numbered"
+        }
+        fn record(&self) -> Option<CommandRecord> {
+            self.to_record()
+        }
+    }
+
+    impl SerializableCommand<()> for Numbered {
+        const KIND: &'static str = "numbered";
+
+        fn to_data(&self) -> serde_json::Result<serde_json::Value> {
+            serde_json::to_value(self.0)
+        }
+
+        fn from_data(_: &(), data: serde_json::Value) -> serde_json::Result<Self> {
+            serde_json::from_value(data).map(Self)
+        }
+    }
+
+    #[test]
+    fn test_saved_marker_follows_undo_and_redo() {
+        let mut history = UndoHistory::default();
+        assert!(history.is_at_saved());
+        history.execute(Box::new(Noop));
+        assert!(!history.is_at_saved());
+        history.undo();
+        assert!(history.is_at_saved());
+
+        history.redo();
+        history.mark_saved();
+        history.undo();
+        assert!(!history.is_at_saved());
+        history.redo();
+        assert!(history.is_at_saved());
+
+        // A new command discards the saved state on the redo stack
+        history.undo();
+        history.execute(Box::new(Noop));
+        history.undo();
+        assert!(!history.is_at_saved());
+    }
+
+    #[test]
+    fn test_saved_marker_survives_trimming() {
+        let mut history = UndoHistory::new(2);
+        history.mark_saved();
+        for _ in 0..3 {
+            history.execute(Box::new(Noop));
+        }
+        while history.undo().is_some() {}
+        // The oldest command was dropped, so this isn't the saved state
+        assert!(!history.is_at_saved());
+        history.mark_saved();
+        history.clear();
+        assert!(history.is_at_saved());
+    }
+
+    #[test]
+    fn test_records_stop_at_unserializable_command() {
+        let mut history = UndoHistory::default();
+        history.execute(Box::new(Numbered(1)));
+        history.execute(Box::new(Noop));
+        for n in 2..5 {
+            history.execute(Box::new(Numbered(n)));
+        }
+        let numbers = |records: Vec<CommandRecord>| -> Vec<u64> {
+            records
+                .into_iter()
+                .map(|r| Numbered::from_data(&(), r.data).unwrap().0)
+                .collect()
+        };
+        assert_eq!(numbers(history.records(10)), [2, 3, 4]);
+        assert_eq!(numbers(history.records(2)), [3, 4]);
+        assert_eq!(history.records(10)[0].kind, "numbered");
+
+        let mut restored = UndoHistory::new(2);
+        restored.execute(Box::new(Noop));
+        restored.restore(vec![
+            Box::new(Numbered(2)),
+            Box::new(Numbered(3)),
+            Box::new(Numbered(4)),
+        ]);
+        assert!(restored.is_at_saved());
+        assert!(!restored.can_redo());
+        assert_eq!(numbers(restored.records(10)), [3, 4]);
+        restored.undo();
+        restored.undo();
+        assert!(!restored.can_undo());
+    }
+}